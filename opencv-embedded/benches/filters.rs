@@ -0,0 +1,58 @@
+// opencv-embedded/benches/filters.rs
+//
+// The pre-detector filters on a camera-sized frame, as they run once per
+// frame on a Pi Zero: a 640x480 BGR frame and its 320x240 gray
+// downscale, each into a dst reused across iterations.
+use std::hint::black_box;
+use std::time::Instant;
+
+use opencv_embedded::core::{Mat, Size};
+use opencv_embedded::imgproc;
+
+fn frame(rows: i32, cols: i32, channels: i32) -> Mat {
+    // Noisy enough that the median's selection does real work.
+    let mut state = 0x9e37_79b9_u32;
+    let data: Vec<u8> = (0..rows * cols * channels)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect();
+    Mat::from_slice_2d(rows, cols, channels, &data).unwrap()
+}
+
+fn time(name: &str, iterations: u32, mut run: impl FnMut()) {
+    run();
+    let started = Instant::now();
+    for _ in 0..iterations {
+        run();
+    }
+    let each = started.elapsed() / iterations;
+    println!("{:<28} {:>10.3} ms", name, each.as_secs_f64() * 1000.0);
+}
+
+fn main() {
+    // `cargo bench` passes --bench; anything else is a smoke run.
+    let iterations = if std::env::args().any(|arg| arg == "--bench") { 10 } else { 1 };
+    for (label, src) in [("640x480 BGR", frame(480, 640, 3)), ("320x240 gray", frame(240, 320, 1))] {
+        let mut dst = Mat::default();
+        time(&format!("{} gaussian 5x5", label), iterations, || {
+            imgproc::gaussian_blur(&src, &mut dst, Size::new(5, 5), 0.0, 0.0).unwrap();
+            black_box(&dst);
+        });
+        time(&format!("{} median 3", label), iterations, || {
+            imgproc::median_blur(&src, &mut dst, 3).unwrap();
+            black_box(&dst);
+        });
+        time(&format!("{} erode 3x3", label), iterations, || {
+            imgproc::erode(&src, &mut dst, Size::new(3, 3), 1).unwrap();
+            black_box(&dst);
+        });
+        time(&format!("{} dilate 3x3", label), iterations, || {
+            imgproc::dilate(&src, &mut dst, Size::new(3, 3), 1).unwrap();
+            black_box(&dst);
+        });
+    }
+}
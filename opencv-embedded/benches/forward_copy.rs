@@ -0,0 +1,82 @@
+// opencv-embedded/benches/forward_copy.rs
+//
+// The cost of getting a YOLOv5 output (1x25200x85 floats) out of the
+// wrapper: the old path made one FFI call per float, the current one
+// copies the wrapper's buffer once through slice::from_raw_parts. The
+// wrapper call is stood in for by a non-inlined extern "C" function, so
+// this runs on either backend. With the system backend and
+// SCOUT_TEST_MODELS set, a real yolov8n forward pass is timed too.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use opencv_embedded::core::Mat;
+
+const PROPOSALS: usize = 25200;
+const ATTRIBUTES: usize = 85;
+
+// What opencv_dnn_getOutputData did for each value.
+#[inline(never)]
+extern "C" fn get_output_data(data: *const f32, index: usize) -> f32 {
+    unsafe { *data.add(index) }
+}
+
+fn per_value(output: &[f32]) -> Mat {
+    let values: Vec<f32> = (0..output.len()).map(|i| get_output_data(black_box(output.as_ptr()), i)).collect();
+    Mat::from_slice_2d(PROPOSALS as i32, ATTRIBUTES as i32, 1, &values).unwrap()
+}
+
+fn one_copy(output: &[f32]) -> Mat {
+    let values = unsafe { std::slice::from_raw_parts(black_box(output.as_ptr()), output.len()) };
+    Mat::from_slice_2d(PROPOSALS as i32, ATTRIBUTES as i32, 1, values)
+        .unwrap()
+        .with_dims(vec![1, PROPOSALS as i32, ATTRIBUTES as i32])
+        .unwrap()
+}
+
+fn time(name: &str, iterations: u32, mut run: impl FnMut()) -> Duration {
+    run();
+    let started = Instant::now();
+    for _ in 0..iterations {
+        run();
+    }
+    let each = started.elapsed() / iterations;
+    println!("{:<28} {:>10.3} ms", name, each.as_secs_f64() * 1000.0);
+    each
+}
+
+#[cfg(system_opencv)]
+fn real_forward(iterations: u32) {
+    use opencv_embedded::core::{Scalar, Size, CV_32F, CV_8UC3};
+    use opencv_embedded::dnn;
+
+    let Some(dir) = std::env::var_os("SCOUT_TEST_MODELS") else { return };
+    let model = std::path::Path::new(&dir).join("yolov8n.onnx");
+    let Ok(net) = dnn::read_net_from_onnx(&model.to_string_lossy()) else { return };
+    let image = Mat::zeros(480, 640, CV_8UC3).unwrap();
+    let blob = dnn::blob_from_image(&image, 1.0 / 255.0, Size::new(640, 640), Scalar::default(), true, false, CV_32F).unwrap();
+    time("yolov8n forward", iterations, || {
+        let mut outputs = Vec::new();
+        net.forward(&blob, &mut outputs, &Vec::new()).unwrap();
+        black_box(outputs);
+    });
+}
+
+#[cfg(not(system_opencv))]
+fn real_forward(_iterations: u32) {}
+
+fn main() {
+    // `cargo bench` passes --bench; anything else is a smoke run.
+    let iterations = if std::env::args().any(|arg| arg == "--bench") { 20 } else { 1 };
+    let output: Vec<f32> = (0..PROPOSALS * ATTRIBUTES).map(|i| (i % 997) as f32 / 997.0).collect();
+
+    let before = time("per-value FFI copy", iterations, || {
+        black_box(per_value(&output));
+    });
+    let after = time("one slice copy", iterations, || {
+        black_box(one_copy(&output));
+    });
+    println!("{:.1}x faster", before.as_secs_f64() / after.as_secs_f64());
+    assert_eq!(per_value(&output).to_vec::<f32>().unwrap(), one_copy(&output).to_vec::<f32>().unwrap());
+
+    real_forward(iterations);
+}
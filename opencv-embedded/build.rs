@@ -1,13 +1,64 @@
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+// Where the system OpenCV and our wrapper live on Raspberry Pi OS.
+const SEARCH_DIRS: &[&str] = &["/usr/lib/aarch64-linux-gnu", "/usr/local/lib"];
+
 fn main() {
+    println!("cargo:rustc-check-cfg=cfg(system_opencv)");
+    println!("cargo:rerun-if-env-changed=OPENCV_EMBEDDED_PURE_RUST");
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG_PATH");
+
+    // The Pi build links the system OpenCV unless told otherwise; every
+    // other target gets the pure-Rust backend unless `system-opencv` is
+    // asked for.
+    let forced = env::var_os("CARGO_FEATURE_SYSTEM_OPENCV").is_some();
+    let pure_rust = env::var("OPENCV_EMBEDDED_PURE_RUST").map(|v| v != "0").unwrap_or(false);
+    let aarch64 = env::var("CARGO_CFG_TARGET_ARCH").as_deref() == Ok("aarch64");
+    if !forced && (pure_rust || !aarch64) {
+        return;
+    }
+    println!("cargo:rustc-cfg=system_opencv");
+
+    let pkg_config_dirs = probe_pkg_config();
+    let found = pkg_config_dirs.is_some()
+        || SEARCH_DIRS.iter().any(|dir| Path::new(dir).join("libopencv_core.so").exists());
+    if !found {
+        panic!(
+            "\n\nopencv-embedded: the system-opencv backend is enabled but OpenCV 4 wasn't found.\n\
+             It was looked for with `pkg-config opencv4` and in {}.\n\n\
+             To fix it, either:\n  \
+             - install OpenCV: sudo apt install -y libopencv-dev pkg-config\n    \
+               and build the wrapper: g++ -shared -fPIC opencv-embedded/src/opencv_wrapper.cpp \\\n      \
+               $(pkg-config --cflags --libs opencv4) -o /usr/local/lib/libopencv_wrapper.so\n  \
+             - or build the pure-Rust backend (no dnn): {}\n\n",
+            SEARCH_DIRS.join(", "),
+            if forced { "drop the system-opencv feature" } else { "OPENCV_EMBEDDED_PURE_RUST=1 cargo build" }
+        );
+    }
+
     // Link to system OpenCV libraries
     println!("cargo:rustc-link-lib=opencv_core");
     println!("cargo:rustc-link-lib=opencv_dnn");
     println!("cargo:rustc-link-lib=opencv_imgproc");
     println!("cargo:rustc-link-lib=opencv_imgcodecs");
-    
+
     // Link to our custom wrapper
     println!("cargo:rustc-link-lib=opencv_wrapper");
 
-    println!("cargo:rustc-link-search=/usr/lib/aarch64-linux-gnu");
-    println!("cargo:rustc-link-search=/usr/local/lib");
+    for dir in pkg_config_dirs.into_iter().flatten().chain(SEARCH_DIRS.iter().map(|d| d.to_string())) {
+        println!("cargo:rustc-link-search={}", dir);
+    }
+}
+
+// The -L directories pkg-config reports for opencv4, or None when either
+// pkg-config or the package is missing.
+fn probe_pkg_config() -> Option<Vec<String>> {
+    let output = Command::new("pkg-config").args(["--libs-only-L", "opencv4"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let flags = String::from_utf8_lossy(&output.stdout);
+    Some(flags.split_whitespace().filter_map(|f| f.strip_prefix("-L")).map(str::to_string).collect())
 }
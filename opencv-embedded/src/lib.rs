@@ -1,17 +1,24 @@
 // opencv-embedded/src/lib.rs
+//
+// Built against the system OpenCV (the `system_opencv` cfg, set by
+// build.rs) or, without it, as a pure-Rust backend: Mat over a Rust
+// allocation, imgproc, imgcodecs and videoio, but no dnn.
 
+#[cfg(system_opencv)]
 use std::ffi::{c_void, c_int};
-use std::ffi::CString;
 use std::ptr;
 
+// Whether this build links the system OpenCV, with dnn, rather than the
+// pure-Rust backend.
+pub const SYSTEM_OPENCV: bool = cfg!(system_opencv);
+
+#[cfg(system_opencv)]
 #[link(name = "opencv_core")]
 #[link(name = "opencv_imgproc")]
 #[link(name = "opencv_dnn")]
 extern "C" {
     fn cvCreateMat(rows: c_int, cols: c_int, typ: c_int) -> *mut c_void;
     fn cvReleaseMat(mat: *mut *mut c_void);
-    fn cvGet2D(mat: *const c_void, row: c_int, col: c_int) -> f64;
-    fn cvSet2D(mat: *mut c_void, row: c_int, col: c_int, value: f64);
 }
 
 pub mod core {
@@ -25,74 +32,467 @@ pub mod core {
     pub const CV_32S: i32 = 4;
     pub const CV_32F: i32 = 5;
     pub const CV_64F: i32 = 6;
+    pub const CV_8UC1: i32 = CV_8U;
     pub const CV_32FC1: i32 = CV_32F;
 
     pub const CV_8UC3: i32 = CV_8U + (2 << 3);
     pub const CV_32FC3: i32 = CV_32F + (2 << 3);
 
+    const CV_CN_SHIFT: i32 = 3;
+    const CV_MAT_DEPTH_MASK: i32 = (1 << CV_CN_SHIFT) - 1;
+
+    pub fn mat_depth(typ: i32) -> i32 {
+        typ & CV_MAT_DEPTH_MASK
+    }
+
+    pub fn mat_channels(typ: i32) -> i32 {
+        (typ >> CV_CN_SHIFT) + 1
+    }
+
+    pub fn mat_type(depth: i32, channels: i32) -> i32 {
+        depth + ((channels - 1) << CV_CN_SHIFT)
+    }
+
+    // Bytes per channel value, or None for a depth we don't know.
+    pub fn depth_size(depth: i32) -> Option<usize> {
+        match depth {
+            CV_8U | CV_8S => Some(1),
+            CV_16U | CV_16S => Some(2),
+            CV_32S | CV_32F => Some(4),
+            CV_64F => Some(8),
+            _ => None,
+        }
+    }
+
+    // Named as in OpenCV's C++ API.
+    #[allow(non_upper_case_globals)]
     pub const Mat_AUTO_STEP: usize = 0;
 
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum ErrorKind {
+        LoadNetwork,
+        SetInput,
+        Forward,
+        Blob,
+        Backend,
+    }
+
+    impl ErrorKind {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                ErrorKind::LoadNetwork => "load network",
+                ErrorKind::SetInput => "set input",
+                ErrorKind::Forward => "forward",
+                ErrorKind::Blob => "blob",
+                ErrorKind::Backend => "backend",
+            }
+        }
+    }
+
     #[derive(Debug, Clone)]
-    pub struct Error(pub String);
+    #[non_exhaustive]
+    pub enum Error {
+        // Reported by the C++ wrapper; `message` is its last error, and
+        // `path` the file involved, if any.
+        Wrapper {
+            kind: ErrorKind,
+            message: String,
+            path: Option<String>,
+        },
+        // Anything caught on the Rust side: bad shapes, types, indices.
+        Other(String),
+    }
 
     impl std::fmt::Display for Error {
         fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-            write!(f, "{}", self.0)
+            match self {
+                Error::Wrapper { kind, message, path: Some(path) } => write!(f, "{} failed for {}: {}", kind.as_str(), path, message),
+                Error::Wrapper { kind, message, path: None } => write!(f, "{} failed: {}", kind.as_str(), message),
+                Error::Other(message) => write!(f, "{}", message),
+            }
         }
     }
 
     impl std::error::Error for Error {}
 
+    impl From<String> for Error {
+        fn from(message: String) -> Self {
+            Error::Other(message)
+        }
+    }
+
+    impl From<&str> for Error {
+        fn from(message: &str) -> Self {
+            Error::Other(message.to_string())
+        }
+    }
+
     impl From<std::ffi::NulError> for Error {
         fn from(e: std::ffi::NulError) -> Self {
-            Error(format!("Null error: {}", e))
+            Error::from(format!("Null error: {}", e))
+        }
+    }
+
+    impl From<std::io::Error> for Error {
+        fn from(e: std::io::Error) -> Self {
+            Error::from(format!("I/O error: {}", e))
+        }
+    }
+
+    #[cfg(feature = "image-interop")]
+    impl From<image::ImageError> for Error {
+        fn from(e: image::ImageError) -> Self {
+            Error::from(format!("Image error: {}", e))
         }
     }
 
     pub type Result<T> = std::result::Result<T, Error>;
 
+    mod sealed {
+        pub trait Sealed {}
+    }
+
+    // Rust types that can be read out of a Mat: a channel value for
+    // single-channel mats, or an array holding every channel of a pixel,
+    // e.g. [u8; 3] for CV_8UC3.
+    pub trait MatElem: sealed::Sealed + Copy {
+        const DEPTH: i32;
+        const CHANNELS: i32;
+    }
+
+    macro_rules! mat_elem {
+        ($($t:ty => $depth:expr),*) => {$(
+            impl sealed::Sealed for $t {}
+            impl MatElem for $t {
+                const DEPTH: i32 = $depth;
+                const CHANNELS: i32 = 1;
+            }
+            impl<const N: usize> sealed::Sealed for [$t; N] {}
+            impl<const N: usize> MatElem for [$t; N] {
+                const DEPTH: i32 = $depth;
+                const CHANNELS: i32 = N as i32;
+            }
+        )*};
+    }
+
+    mat_elem!(u8 => CV_8U, i8 => CV_8S, u16 => CV_16U, i16 => CV_16S, i32 => CV_32S, f32 => CV_32F, f64 => CV_64F);
+
+    // Leading fields of the C API's CvMat, as returned by cvCreateMat.
+    #[cfg(system_opencv)]
+    #[repr(C)]
+    struct CvMatHeader {
+        typ: c_int,
+        step: c_int,
+        refcount: *mut c_int,
+        hdr_refcount: c_int,
+        data: *mut u8,
+        rows: c_int,
+        cols: c_int,
+    }
+
+    // Who frees the memory behind a Mat when it is dropped.
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Ownership {
+        // Caller-provided data, views and empty mats: nothing to free.
+        Borrowed,
+        // Allocated with cvCreateMat.
+        #[cfg(system_opencv)]
+        CvMat,
+        // A cv::Mat allocated by the C++ wrapper.
+        #[cfg(system_opencv)]
+        #[cfg_attr(not(feature = "wrapper-blob"), allow(dead_code))]
+        Wrapper,
+        // A boxed byte slice of `len` bytes, for the pure-Rust backend.
+        #[cfg(not(system_opencv))]
+        Heap { len: usize },
+    }
+
+    // Not Send or Sync: borrowed mats and views alias memory someone else
+    // owns. Move data between threads as a MatBuffer.
     pub struct Mat {
-        pub ptr: *mut c_void,
-        pub rows: i32,
-        pub cols: i32,
+        // Handed to cvReleaseMat or the wrapper on drop, per `ownership`.
+        ptr: *mut c_void,
+        // Fixed at construction, as offset_of's bounds checks trust them;
+        // read through rows() and cols().
+        rows: i32,
+        cols: i32,
+        typ: i32,
+        // Bytes from the start of one row to the next.
+        step: usize,
+        // First byte of pixel data. Null for mats only the C++ wrapper
+        // can read, such as dnn blobs.
+        data: *mut u8,
+        // The logical shape, e.g. [1, 3, 416, 416] for an NCHW blob stored
+        // as a single row. Same as [rows, cols] for plain 2-D mats.
+        dims: Vec<i32>,
+        ownership: Ownership,
+    }
+
+    /// A row or region of another Mat. It borrows the parent, so it can't
+    /// outlive the allocation it points into:
+    ///
+    /// ```compile_fail
+    /// use opencv_embedded::core::{Mat, Rect, CV_8UC1};
+    ///
+    /// let view = {
+    ///     let parent = Mat::zeros(4, 4, CV_8UC1).unwrap();
+    ///     parent.roi(Rect::new(0, 0, 2, 2)).unwrap()
+    /// };
+    /// assert_eq!(view.rows(), 2);
+    /// ```
+    ///
+    /// Kept within the parent's lifetime, the same view is fine:
+    ///
+    /// ```
+    /// use opencv_embedded::core::{Mat, Rect, CV_8UC1};
+    ///
+    /// let parent = Mat::zeros(4, 4, CV_8UC1).unwrap();
+    /// let view = parent.roi(Rect::new(0, 0, 2, 2)).unwrap();
+    /// assert_eq!(view.rows(), 2);
+    /// ```
+    pub struct MatView<'a> {
+        mat: Mat,
+        _parent: std::marker::PhantomData<&'a Mat>,
+    }
+
+    impl std::ops::Deref for MatView<'_> {
+        type Target = Mat;
+
+        fn deref(&self) -> &Mat {
+            &self.mat
+        }
+    }
+
+    // A mutable region of another Mat, from roi_mut.
+    pub struct MatViewMut<'a> {
+        mat: Mat,
+        _parent: std::marker::PhantomData<&'a mut Mat>,
+    }
+
+    impl std::ops::Deref for MatViewMut<'_> {
+        type Target = Mat;
+
+        fn deref(&self) -> &Mat {
+            &self.mat
+        }
+    }
+
+    // No DerefMut: a &mut Mat would let the view's Mat be swapped out and
+    // outlive the parent. Writes go through these instead.
+    impl MatViewMut<'_> {
+        pub fn set<T: MatElem>(&mut self, row: i32, col: i32, value: T) -> Result<()> {
+            self.mat.set(row, col, value)
+        }
+
+        pub fn copy_from_slice<T: MatElem>(&mut self, data: &[T]) -> Result<()> {
+            self.mat.copy_from_slice(data)
+        }
+
+        // `src` must have the view's shape and type.
+        pub fn copy_from(&mut self, src: &Mat) -> Result<()> {
+            if src.rows != self.mat.rows || src.cols != self.mat.cols || src.typ != self.mat.typ {
+                return Err(Error::from(format!(
+                    "Cannot copy a {}x{} type {} mat into a {}x{} type {} view",
+                    src.rows, src.cols, src.typ, self.mat.rows, self.mat.cols, self.mat.typ
+                )));
+            }
+            src.copy_rows(&mut self.mat)
+        }
     }
 
     impl Mat {
         pub fn new() -> Result<Self> {
-            Ok(Mat {
-                ptr: ptr::null_mut(),
-                rows: 0,
-                cols: 0,
-            })
+            Ok(Mat::default())
         }
 
+        /// Wraps `data` without copying or taking ownership of it.
+        ///
+        /// # Safety
+        ///
+        /// `data` must point to `rows` rows of `cols` elements of `typ`,
+        /// `step` bytes apart (or packed, with `Mat_AUTO_STEP`), and stay
+        /// valid and unaliased for as long as the Mat is used.
         pub unsafe fn new_rows_cols_with_data(
             rows: i32,
             cols: i32,
-            _typ: i32,
+            typ: i32,
             data: *mut c_void,
-            _step: usize
+            step: usize
         ) -> Result<Self> {
+            let elem_size = elem_size(typ)?;
+            let step = if step == Mat_AUTO_STEP { cols as usize * elem_size } else { step };
             Ok(Mat {
                 ptr: data,
                 rows,
                 cols,
+                typ,
+                step,
+                data: data as *mut u8,
+                dims: vec![rows, cols],
+                ownership: Ownership::Borrowed,
             })
         }
         
+        #[cfg(system_opencv)]
         pub fn zeros(rows: i32, cols: i32, typ: i32) -> Result<Self> {
+            elem_size(typ)?;
             unsafe {
                 let ptr = cvCreateMat(rows, cols, typ);
-                Ok(Mat { ptr, rows, cols })
+                if ptr.is_null() {
+                    return Err(Error::from(format!("cvCreateMat failed for {}x{}", rows, cols)));
+                }
+                let header = &*(ptr as *const CvMatHeader);
+                Ok(Mat {
+                    ptr,
+                    rows,
+                    cols,
+                    typ,
+                    step: header.step as usize,
+                    data: header.data,
+                    dims: vec![rows, cols],
+                    ownership: Ownership::CvMat,
+                })
             }
         }
-        
-        pub fn default() -> Self {
+
+        #[cfg(not(system_opencv))]
+        pub fn zeros(rows: i32, cols: i32, typ: i32) -> Result<Self> {
+            let elem_size = elem_size(typ)?;
+            if rows < 0 || cols < 0 {
+                return Err(Error::from(format!("Invalid mat size {}x{}", rows, cols)));
+            }
+            let step = cols as usize * elem_size;
+            let len = rows as usize * step;
+            let data = Box::into_raw(vec![0u8; len].into_boxed_slice()) as *mut u8;
+            Ok(Mat {
+                ptr: data as *mut c_void,
+                rows,
+                cols,
+                typ,
+                step,
+                data,
+                dims: vec![rows, cols],
+                ownership: Ownership::Heap { len },
+            })
+        }
+
+        // Takes ownership of a cv::Mat returned by the C++ wrapper.
+        #[cfg(system_opencv)]
+        #[cfg_attr(not(feature = "wrapper-blob"), allow(dead_code))]
+        pub(crate) fn from_handle(ptr: *mut c_void, rows: i32, cols: i32, typ: i32) -> Self {
             Mat {
-                ptr: ptr::null_mut(),
-                rows: 0,
-                cols: 0,
+                ptr,
+                rows,
+                cols,
+                typ,
+                step: 0,
+                data: ptr::null_mut(),
+                dims: vec![rows, cols],
+                ownership: Ownership::Wrapper,
+            }
+        }
+
+        // Copies row-major `data`, `channels` values per element, into a
+        // newly allocated Mat.
+        pub fn from_slice_2d<T: MatElem>(rows: i32, cols: i32, channels: i32, data: &[T]) -> Result<Mat> {
+            if rows < 0 || cols < 0 || channels < 1 {
+                return Err(Error::from(format!("Invalid mat shape {}x{}x{}", rows, cols, channels)));
+            }
+            let mut mat = Mat::zeros(rows, cols, mat_type(T::DEPTH, channels))?;
+            mat.copy_from_slice(data)?;
+            Ok(mat)
+        }
+
+        // Overwrites every channel value from row-major `data`.
+        pub fn copy_from_slice<T: MatElem>(&mut self, data: &[T]) -> Result<()> {
+            if T::CHANNELS != 1 || T::DEPTH != mat_depth(self.typ) {
+                return Err(Error::from(format!("Element type doesn't match mat type {}", self.typ)));
+            }
+            if self.data.is_null() {
+                return Err(Error::from("Null mat"));
+            }
+            let row_len = self.cols as usize * self.channels() as usize;
+            if data.len() != self.rows as usize * row_len {
+                return Err(Error::from(format!(
+                    "Expected {} values for a {}x{}x{} mat, got {}",
+                    self.rows as usize * row_len, self.rows, self.cols, self.channels(), data.len()
+                )));
+            }
+            for (row, values) in data.chunks(row_len.max(1)).enumerate() {
+                unsafe {
+                    let dst = self.data.add(row * self.step) as *mut T;
+                    std::ptr::copy_nonoverlapping(values.as_ptr(), dst, values.len());
+                }
+            }
+            Ok(())
+        }
+
+        // Every channel value, row-major, without row padding.
+        pub fn to_vec<T: MatElem>(&self) -> Result<Vec<T>> {
+            if T::CHANNELS != 1 || T::DEPTH != mat_depth(self.typ) {
+                return Err(Error::from(format!("Element type doesn't match mat type {}", self.typ)));
+            }
+            if self.data.is_null() {
+                return Err(Error::from("Null mat"));
+            }
+            let row_len = self.cols as usize * self.channels() as usize;
+            let mut values = Vec::with_capacity(self.rows as usize * row_len);
+            for row in 0..self.rows as usize {
+                unsafe {
+                    let src = self.data.add(row * self.step) as *const T;
+                    for i in 0..row_len {
+                        values.push(std::ptr::read_unaligned(src.add(i)));
+                    }
+                }
+            }
+            Ok(values)
+        }
+
+        // Channels keep the image's RGB order; swap them before handing
+        // the mat to anything expecting OpenCV's BGR.
+        #[cfg(feature = "image-interop")]
+        pub fn to_rgb_image(&self) -> Result<image::RgbImage> {
+            if self.typ != CV_8UC3 {
+                return Err(Error::from(format!("Expected a CV_8UC3 mat, got type {}", self.typ)));
+            }
+            image::RgbImage::from_raw(self.cols as u32, self.rows as u32, self.to_vec::<u8>()?)
+                .ok_or_else(|| Error::from("Mat data doesn't fit the image size"))
+        }
+
+        #[cfg(system_opencv)]
+        pub(crate) fn data_ptr(&self) -> *const u8 {
+            self.data
+        }
+
+        // The CvMat or cv::Mat handle the C++ wrapper takes.
+        #[cfg(system_opencv)]
+        pub(crate) fn raw_ptr(&self) -> *mut c_void {
+            self.ptr
+        }
+
+        pub fn dims(&self) -> &[i32] {
+            &self.dims
+        }
+
+        // Reinterprets the data with a new logical shape holding the same
+        // number of values. Only for single-channel mats, so that each
+        // value in the shape is one element of the mat's type.
+        pub fn with_dims(mut self, dims: Vec<i32>) -> Result<Mat> {
+            if self.channels() != 1 {
+                return Err(Error::from(format!("Cannot reshape a {}-channel mat; dims count single values", self.channels())));
+            }
+            let total: i64 = dims.iter().map(|&d| d as i64).product();
+            let values = self.rows as i64 * self.cols as i64 * self.channels() as i64;
+            if total != values {
+                return Err(Error::from(format!("Shape {:?} doesn't hold {} values", dims, values)));
             }
+            self.dims = dims;
+            Ok(self)
+        }
+
+        pub fn is_owned(&self) -> bool {
+            self.ownership != Ownership::Borrowed
         }
         
         pub fn rows(&self) -> i32 {
@@ -102,49 +502,332 @@ pub mod core {
         pub fn cols(&self) -> i32 {
             self.cols
         }
+
+        pub fn typ(&self) -> i32 {
+            self.typ
+        }
+
+        pub fn channels(&self) -> i32 {
+            mat_channels(self.typ)
+        }
+
+        pub fn step(&self) -> usize {
+            self.step
+        }
+
+        // Bytes per element, all channels included.
+        pub fn elem_size(&self) -> usize {
+            elem_size(self.typ).unwrap_or(0)
+        }
+
+        // Byte offset of the first channel of (row, col), checked against
+        // the mat's bounds and element type.
+        fn offset_of<T>(&self, row: i32, col: i32) -> Result<usize> {
+            if self.data.is_null() {
+                return Err(Error::from("Null mat"));
+            }
+            if row < 0 || row >= self.rows || col < 0 || col >= self.cols {
+                return Err(Error::from(format!("Element ({}, {}) out of bounds for {}x{} mat", row, col, self.rows, self.cols)));
+            }
+            let channel_size = depth_size(mat_depth(self.typ)).unwrap_or(0);
+            let size = std::mem::size_of::<T>();
+            if size != channel_size && size != self.elem_size() {
+                return Err(Error::from(format!(
+                    "Requested a {}-byte element from a mat with {}-byte channels",
+                    size, channel_size
+                )));
+            }
+            Ok(row as usize * self.step + col as usize * self.elem_size())
+        }
         
-        pub fn at_2d<T>(&self, row: i32, col: i32) -> Result<*const T> {
-            if self.ptr.is_null() {
-                return Err(Error("Null mat".to_string()));
+        fn check_elem<T: MatElem>(&self) -> Result<()> {
+            if T::DEPTH != mat_depth(self.typ) || T::CHANNELS != self.channels() {
+                return Err(Error::from(format!(
+                    "Element type with depth {} and {} channels doesn't match mat type {}",
+                    T::DEPTH, T::CHANNELS, self.typ
+                )));
             }
-            unsafe {
-                let offset = ((row * self.cols + col) * std::mem::size_of::<T>() as i32) as isize;
-                Ok(self.ptr.offset(offset) as *const T)
+            Ok(())
+        }
+
+        /// Reads element (row, col), checked against the shape the mat was
+        /// built with. That shape can't be changed from outside, so the
+        /// check can't be talked out of:
+        ///
+        /// ```compile_fail,E0616
+        /// use opencv_embedded::core::{Mat, CV_8UC1};
+        ///
+        /// let mut mat = Mat::zeros(2, 2, CV_8UC1).unwrap();
+        /// mat.rows = 1_000_000;
+        /// let _ = mat.get::<u8>(900_000, 0);
+        /// ```
+        ///
+        /// ```compile_fail,E0616
+        /// use opencv_embedded::core::{Mat, CV_8UC1};
+        ///
+        /// let mut mat = Mat::zeros(2, 2, CV_8UC1).unwrap();
+        /// mat.ptr = std::ptr::null_mut();
+        /// ```
+        ///
+        /// ```
+        /// use opencv_embedded::core::{Mat, CV_8UC1};
+        ///
+        /// let mat = Mat::zeros(2, 2, CV_8UC1).unwrap();
+        /// assert!(mat.get::<u8>(900_000, 0).is_err());
+        /// ```
+        pub fn get<T: MatElem>(&self, row: i32, col: i32) -> Result<T> {
+            self.check_elem::<T>()?;
+            let offset = self.offset_of::<T>(row, col)?;
+            unsafe { Ok(std::ptr::read_unaligned(self.data.add(offset) as *const T)) }
+        }
+
+        pub fn set<T: MatElem>(&mut self, row: i32, col: i32, value: T) -> Result<()> {
+            self.check_elem::<T>()?;
+            let offset = self.offset_of::<T>(row, col)?;
+            unsafe { std::ptr::write_unaligned(self.data.add(offset) as *mut T, value) };
+            Ok(())
+        }
+
+        pub fn row_slice<T: MatElem>(&self, row: i32) -> Result<&[T]> {
+            self.check_elem::<T>()?;
+            let offset = self.offset_of::<T>(row, 0)?;
+            let start = unsafe { self.data.add(offset) };
+            if start.align_offset(std::mem::align_of::<T>()) != 0 {
+                return Err(Error::from("Mat row is not aligned for the element type"));
             }
+            unsafe { Ok(std::slice::from_raw_parts(start as *const T, self.cols as usize)) }
+        }
+
+        pub fn at_2d<T>(&self, row: i32, col: i32) -> Result<*const T> {
+            let offset = self.offset_of::<T>(row, col)?;
+            unsafe { Ok(self.data.add(offset) as *const T) }
         }
         
         pub fn at_2d_mut<T>(&mut self, row: i32, col: i32) -> Result<*mut T> {
-            if self.ptr.is_null() {
-                return Err(Error("Null mat".to_string()));
+            let offset = self.offset_of::<T>(row, col)?;
+            unsafe { Ok(self.data.add(offset) as *mut T) }
+        }
+        
+        pub fn row(&self, row_idx: i32) -> Result<MatView<'_>> {
+            if row_idx < 0 || row_idx >= self.rows {
+                return Err(Error::from("Row index out of bounds"));
             }
-            unsafe {
-                let offset = ((row * self.cols + col) * std::mem::size_of::<T>() as i32) as isize;
-                Ok(self.ptr.offset(offset) as *mut T)
+            self.roi(Rect::new(0, row_idx, self.cols, 1))
+        }
+
+        // A view of `rect`, which must lie wholly inside the mat. It shares
+        // the parent's data and step, so it is usually not continuous.
+        pub fn roi(&self, rect: Rect) -> Result<MatView<'_>> {
+            Ok(MatView {
+                mat: self.sub_mat(rect)?,
+                _parent: std::marker::PhantomData,
+            })
+        }
+
+        // Like roi, but writes through the view land in this mat.
+        pub fn roi_mut(&mut self, rect: Rect) -> Result<MatViewMut<'_>> {
+            Ok(MatViewMut {
+                mat: self.sub_mat(rect)?,
+                _parent: std::marker::PhantomData,
+            })
+        }
+
+        fn sub_mat(&self, rect: Rect) -> Result<Mat> {
+            if self.data.is_null() {
+                return Err(Error::from("Null mat"));
+            }
+            // Saturating, so a huge rect is off the mat rather than an overflow.
+            if rect.width <= 0 || rect.height <= 0 || rect.x < 0 || rect.y < 0
+                || rect.x.saturating_add(rect.width) > self.cols || rect.y.saturating_add(rect.height) > self.rows
+            {
+                return Err(Error::from(format!(
+                    "ROI {}x{} at ({}, {}) doesn't fit inside a {}x{} mat",
+                    rect.width, rect.height, rect.x, rect.y, self.cols, self.rows
+                )));
             }
+            let data = unsafe { self.data.add(rect.y as usize * self.step + rect.x as usize * self.elem_size()) };
+            Ok(Mat {
+                ptr: data as *mut c_void,
+                rows: rect.height,
+                cols: rect.width,
+                typ: self.typ,
+                step: self.step,
+                data,
+                dims: vec![rect.height, rect.width],
+                ownership: Ownership::Borrowed,
+            })
         }
-        
-        pub fn row(&self, row_idx: i32) -> Result<Mat> {
-            if row_idx >= self.rows {
-                return Err(Error("Row index out of bounds".to_string()));
+
+        // No padding between rows, so the data is one run of
+        // rows * cols elements.
+        pub fn is_continuous(&self) -> bool {
+            self.rows <= 1 || self.step == self.cols as usize * self.elem_size()
+        }
+
+        // A continuous copy that owns its data, whatever this mat is.
+        pub fn clone_owned(&self) -> Result<Mat> {
+            let mut dst = Mat::default();
+            self.copy_to(&mut dst)?;
+            Ok(dst)
+        }
+
+        // dst is reallocated unless it already has this shape and type.
+        pub fn copy_to(&self, dst: &mut Mat) -> Result<()> {
+            if dst.rows != self.rows || dst.cols != self.cols || dst.typ != self.typ || dst.data.is_null() {
+                *dst = Mat::zeros(self.rows, self.cols, self.typ)?;
             }
-            unsafe {
-                let offset = (row_idx * self.cols * 4) as isize;
-                Ok(Mat {
-                    ptr: self.ptr.offset(offset),
-                    rows: 1,
-                    cols: self.cols,
-                })
+            self.copy_rows(dst)?;
+            dst.dims = self.dims.clone();
+            Ok(())
+        }
+
+        // dst must already have this shape and type. ptr::copy, because a
+        // view may overlap the mat it is copied from.
+        fn copy_rows(&self, dst: &mut Mat) -> Result<()> {
+            if self.data.is_null() || dst.data.is_null() {
+                return Err(Error::from("Null mat"));
+            }
+            let row_len = self.cols as usize * self.elem_size();
+            for row in 0..self.rows as usize {
+                unsafe {
+                    std::ptr::copy(self.data.add(row * self.step), dst.data.add(row * dst.step), row_len);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// An owned, padding-free copy of a Mat. It holds no C pointers, only
+    /// its own Vec, so unlike Mat it is Send and can carry frames and
+    /// outputs between threads. A Mat can't be moved to another thread:
+    ///
+    /// ```compile_fail
+    /// use opencv_embedded::core::{Mat, CV_8UC3};
+    ///
+    /// let frame = Mat::zeros(2, 2, CV_8UC3).unwrap();
+    /// std::thread::spawn(move || frame.rows()).join().unwrap();
+    /// ```
+    ///
+    /// but its MatBuffer can:
+    ///
+    /// ```
+    /// use opencv_embedded::core::{Mat, MatBuffer, CV_8UC3};
+    ///
+    /// let frame = Mat::zeros(2, 2, CV_8UC3).unwrap();
+    /// let buffer = MatBuffer::from_mat(&frame).unwrap();
+    /// let rows = std::thread::spawn(move || buffer.to_mat().unwrap().rows()).join().unwrap();
+    /// assert_eq!(rows, 2);
+    /// ```
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct MatBuffer {
+        rows: i32,
+        cols: i32,
+        typ: i32,
+        dims: Vec<i32>,
+        data: Vec<u8>,
+    }
+
+    impl MatBuffer {
+        pub fn from_mat(mat: &Mat) -> Result<Self> {
+            if mat.data.is_null() && mat.rows > 0 {
+                return Err(Error::from("Null mat"));
+            }
+            let row_len = mat.cols as usize * elem_size(mat.typ)?;
+            let mut data = Vec::with_capacity(mat.rows as usize * row_len);
+            for row in 0..mat.rows as usize {
+                unsafe {
+                    data.extend_from_slice(std::slice::from_raw_parts(mat.data.add(row * mat.step), row_len));
+                }
+            }
+            Ok(MatBuffer { rows: mat.rows, cols: mat.cols, typ: mat.typ, dims: mat.dims.clone(), data })
+        }
+
+        pub fn to_mat(&self) -> Result<Mat> {
+            let mat = Mat::zeros(self.rows, self.cols, self.typ)?;
+            let row_len = self.cols as usize * elem_size(self.typ)?;
+            for (row, bytes) in self.data.chunks(row_len.max(1)).enumerate() {
+                unsafe {
+                    std::ptr::copy_nonoverlapping(bytes.as_ptr(), mat.data.add(row * mat.step), bytes.len());
+                }
             }
+            if self.dims == [self.rows, self.cols] {
+                return Ok(mat);
+            }
+            mat.with_dims(self.dims.clone())
+        }
+
+        pub fn rows(&self) -> i32 {
+            self.rows
+        }
+
+        pub fn cols(&self) -> i32 {
+            self.cols
+        }
+
+        pub fn typ(&self) -> i32 {
+            self.typ
+        }
+
+        pub fn dims(&self) -> &[i32] {
+            &self.dims
+        }
+
+        pub fn data(&self) -> &[u8] {
+            &self.data
+        }
+    }
+
+    // Channels stay in the image's RGB order, as with to_rgb_image.
+    #[cfg(feature = "image-interop")]
+    impl TryFrom<&image::RgbImage> for Mat {
+        type Error = Error;
+
+        fn try_from(image: &image::RgbImage) -> Result<Mat> {
+            let rows = i32::try_from(image.height()).map_err(|_| Error::from("Image too tall for a mat"))?;
+            let cols = i32::try_from(image.width()).map_err(|_| Error::from("Image too wide for a mat"))?;
+            Mat::from_slice_2d(rows, cols, 3, image.as_raw())
         }
     }
+
+    fn elem_size(typ: i32) -> Result<usize> {
+        depth_size(mat_depth(typ))
+            .map(|size| size * mat_channels(typ) as usize)
+            .ok_or_else(|| Error::from(format!("Unsupported mat type {}", typ)))
+    }
     
+    impl Default for Mat {
+        fn default() -> Self {
+            Mat {
+                ptr: ptr::null_mut(),
+                rows: 0,
+                cols: 0,
+                typ: CV_8U,
+                step: 0,
+                data: ptr::null_mut(),
+                dims: vec![0, 0],
+                ownership: Ownership::Borrowed,
+            }
+        }
+    }
+
     impl Drop for Mat {
         fn drop(&mut self) {
-//            if !self.ptr.is_null() {
-//                unsafe {
-//                    cvReleaseMat(&mut self.ptr);
-//                }
-//            }
+            if self.ptr.is_null() {
+                return;
+            }
+            unsafe {
+                match self.ownership {
+                    Ownership::Borrowed => {}
+                    #[cfg(system_opencv)]
+                    Ownership::CvMat => cvReleaseMat(&mut self.ptr),
+                    #[cfg(system_opencv)]
+                    Ownership::Wrapper => crate::dnn::release_mat(self.ptr),
+                    #[cfg(not(system_opencv))]
+                    Ownership::Heap { len } => {
+                        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(self.data, len)));
+                    }
+                }
+            }
         }
     }
     
@@ -159,7 +842,19 @@ pub mod core {
         }
     }
     
-    #[derive(Clone, Copy)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Point {
+        pub x: i32,
+        pub y: i32,
+    }
+    
+    impl Point {
+        pub fn new(x: i32, y: i32) -> Self {
+            Point { x, y }
+        }
+    }
+    
+    #[derive(Clone, Copy, Default)]
     pub struct Scalar(pub f64, pub f64, pub f64, pub f64);
     
     impl Scalar {
@@ -167,13 +862,21 @@ pub mod core {
             Scalar(v0, v1, v2, v3)
         }
         
-        pub fn default() -> Self {
-            Scalar(0.0, 0.0, 0.0, 0.0)
+        // Channels past the fourth read as 0.
+        pub fn channel(&self, index: usize) -> f64 {
+            match index {
+                0 => self.0,
+                1 => self.1,
+                2 => self.2,
+                3 => self.3,
+                _ => 0.0,
+            }
         }
     }
     
     pub type Vector<T> = Vec<T>;
     
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct Rect {
         pub x: i32,
         pub y: i32,
@@ -185,36 +888,870 @@ pub mod core {
         pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
             Rect { x, y, width, height }
         }
-    }
-}
 
-pub mod dnn {
-    use super::*;
-    use crate::core::{Mat, Size, Scalar, Vector, Error, Result};
-    use std::ffi::CString;
-    use std::os::raw::{c_char, c_void, c_int, c_double, c_float};
-    use std::ptr;
+        // Negative sizes count as empty.
+        pub fn area(&self) -> i64 {
+            self.width.max(0) as i64 * self.height.max(0) as i64
+        }
 
-    #[link(name = "opencv_wrapper")]
-    extern "C" {
-        fn opencv_dnn_readNetFromDarknet(cfg: *const c_char, weights: *const c_char) -> *mut c_void;
-        fn opencv_dnn_forward(net: *mut c_void, blob: *mut c_void, layer_name: *const c_char, output: *mut c_void) -> *mut c_void;
-        fn opencv_dnn_setInput(net: *mut c_void, blob: *mut c_void) -> c_int;
-        fn opencv_dnn_blobFromImage(image: *mut c_void, scale: c_double, width: c_int, height: c_int) -> *mut c_void;
-        fn opencv_dnn_getOutputData(output: *mut c_void, row: c_int, col: c_int) -> c_float;
-        fn opencv_dnn_getOutputDims(output: *mut c_void, rows: *mut c_int, cols: *mut c_int) -> c_int;
-        fn opencv_dnn_releaseNet(net: *mut c_void);
-        fn opencv_dnn_releaseMat(mat: *mut c_void);
+        // Intersection over union; 0 when either rect is empty.
+        pub fn iou(&self, other: &Rect) -> f32 {
+            if self.area() == 0 || other.area() == 0 {
+                return 0.0;
+            }
+            let w = ((self.x + self.width).min(other.x + other.width) - self.x.max(other.x)).max(0) as i64;
+            let h = ((self.y + self.height).min(other.y + other.height) - self.y.max(other.y)).max(0) as i64;
+            let intersection = w * h;
+            intersection as f32 / (self.area() + other.area() - intersection) as f32
+        }
     }
 
-    pub const DNN_BACKEND_OPENCV: i32 = 0;
-    pub const DNN_TARGET_CPU: i32 = 0;
+    pub const NORM_INF: i32 = 1;
+    pub const NORM_L1: i32 = 2;
+    pub const NORM_L2: i32 = 4;
+    pub const NORM_MINMAX: i32 = 32;
 
-    pub struct Net {
-        pub ptr: *mut c_void,
+    // The second operand of add, subtract and multiply: another mat of the
+    // same shape and type, or a Scalar holding one value per channel.
+    pub enum Operand<'a> {
+        Mat(&'a Mat),
+        Scalar(Scalar),
     }
 
-    impl Drop for Net {
+    impl<'a> From<&'a Mat> for Operand<'a> {
+        fn from(mat: &'a Mat) -> Self {
+            Operand::Mat(mat)
+        }
+    }
+
+    impl From<Scalar> for Operand<'_> {
+        fn from(scalar: Scalar) -> Self {
+            Operand::Scalar(scalar)
+        }
+    }
+
+    // The elementwise ops and statistics below work on 8U and 32F mats.
+    // Results keep src1's type and, as in OpenCV, saturate for 8-bit mats.
+    pub fn add<'a>(src1: &Mat, src2: impl Into<Operand<'a>>, dst: &mut Mat) -> Result<()> {
+        elementwise(src1, src2.into(), dst, |a, b| a + b)
+    }
+
+    pub fn subtract<'a>(src1: &Mat, src2: impl Into<Operand<'a>>, dst: &mut Mat) -> Result<()> {
+        elementwise(src1, src2.into(), dst, |a, b| a - b)
+    }
+
+    pub fn multiply<'a>(src1: &Mat, src2: impl Into<Operand<'a>>, dst: &mut Mat, scale: f64) -> Result<()> {
+        let scale = scale as f32;
+        elementwise(src1, src2.into(), dst, |a, b| a * b * scale)
+    }
+
+    fn elementwise(src1: &Mat, src2: Operand, dst: &mut Mat, op: impl Fn(f32, f32) -> f32) -> Result<()> {
+        let a = read_values(src1)?;
+        let out: Vec<f32> = match src2 {
+            Operand::Mat(other) => {
+                if other.rows() != src1.rows() || other.cols() != src1.cols() || other.typ() != src1.typ() {
+                    return Err(Error::from(format!(
+                        "Operands differ: {}x{} type {} and {}x{} type {}",
+                        src1.rows(), src1.cols(), src1.typ(), other.rows(), other.cols(), other.typ()
+                    )));
+                }
+                let b = read_values(other)?;
+                a.iter().zip(&b).map(|(&a, &b)| op(a, b)).collect()
+            }
+            Operand::Scalar(scalar) => {
+                let channels = src1.channels() as usize;
+                let b: Vec<f32> = (0..channels).map(|c| scalar.channel(c) as f32).collect();
+                a.chunks_exact(channels).flat_map(|pixel| pixel.iter().zip(&b).map(|(&a, &b)| op(a, b))).collect()
+            }
+        };
+        write_values(dst, src1.rows(), src1.cols(), src1.typ(), &out)
+    }
+
+    // Single-channel mats only, as in OpenCV. Locations are (x, y) of the
+    // first extreme value in row-major order.
+    pub fn min_max_loc(
+        src: &Mat,
+        min_val: Option<&mut f64>,
+        max_val: Option<&mut f64>,
+        min_loc: Option<&mut Point>,
+        max_loc: Option<&mut Point>,
+    ) -> Result<()> {
+        if src.channels() != 1 {
+            return Err(Error::from(format!("min_max_loc needs a single-channel mat, got {} channels", src.channels())));
+        }
+        let values = read_values(src)?;
+        if values.is_empty() {
+            return Err(Error::from("min_max_loc on an empty mat"));
+        }
+        let (mut min_i, mut max_i) = (0, 0);
+        for (i, &v) in values.iter().enumerate() {
+            if v < values[min_i] {
+                min_i = i;
+            }
+            if v > values[max_i] {
+                max_i = i;
+            }
+        }
+        let cols = src.cols() as usize;
+        let point = |i: usize| Point::new((i % cols) as i32, (i / cols) as i32);
+        if let Some(v) = min_val {
+            *v = values[min_i] as f64;
+        }
+        if let Some(v) = max_val {
+            *v = values[max_i] as f64;
+        }
+        if let Some(p) = min_loc {
+            *p = point(min_i);
+        }
+        if let Some(p) = max_loc {
+            *p = point(max_i);
+        }
+        Ok(())
+    }
+
+    // Per channel, for up to four channels. The standard deviation is the
+    // population one (divided by N), matching OpenCV.
+    pub fn mean_std_dev(src: &Mat, mean: &mut Scalar, stddev: &mut Scalar) -> Result<()> {
+        let channels = src.channels() as usize;
+        if channels > 4 {
+            return Err(Error::from(format!("mean_std_dev handles up to 4 channels, got {}", channels)));
+        }
+        let values = read_values(src)?;
+        let count = (values.len() / channels) as f64;
+        if count == 0.0 {
+            return Err(Error::from("mean_std_dev on an empty mat"));
+        }
+        let mut sum = [0.0f64; 4];
+        let mut sum_sq = [0.0f64; 4];
+        for pixel in values.chunks_exact(channels) {
+            for (c, &v) in pixel.iter().enumerate() {
+                sum[c] += v as f64;
+                sum_sq[c] += v as f64 * v as f64;
+            }
+        }
+        let m = sum.map(|s| s / count);
+        let sd: Vec<f64> = (0..4).map(|c| (sum_sq[c] / count - m[c] * m[c]).max(0.0).sqrt()).collect();
+        *mean = Scalar(m[0], m[1], m[2], m[3]);
+        *stddev = Scalar(sd[0], sd[1], sd[2], sd[3]);
+        Ok(())
+    }
+
+    // NORM_MINMAX maps the value range onto [min(alpha, beta), max(alpha,
+    // beta)]; a constant mat maps to the lower bound. NORM_INF, NORM_L1
+    // and NORM_L2 scale so that norm comes out as alpha. Norms span every
+    // channel value. dst keeps src's type.
+    pub fn normalize(src: &Mat, dst: &mut Mat, alpha: f64, beta: f64, norm_type: i32) -> Result<()> {
+        let values = read_values(src)?;
+        let (scale, shift) = match norm_type {
+            NORM_MINMAX => {
+                let (lo, hi) = values.iter().fold((f64::MAX, f64::MIN), |(lo, hi), &v| (lo.min(v as f64), hi.max(v as f64)));
+                let (dst_lo, dst_hi) = (alpha.min(beta), alpha.max(beta));
+                let scale = if hi - lo > f64::EPSILON { (dst_hi - dst_lo) / (hi - lo) } else { 0.0 };
+                (scale, dst_lo - lo * scale)
+            }
+            NORM_INF | NORM_L1 | NORM_L2 => {
+                let norm = match norm_type {
+                    NORM_INF => values.iter().fold(0.0f64, |n, &v| n.max((v as f64).abs())),
+                    NORM_L1 => values.iter().map(|&v| (v as f64).abs()).sum(),
+                    _ => values.iter().map(|&v| v as f64 * v as f64).sum::<f64>().sqrt(),
+                };
+                (if norm > f64::EPSILON { alpha / norm } else { 0.0 }, 0.0)
+            }
+            other => return Err(Error::from(format!("Unsupported norm type {}", other))),
+        };
+        let out: Vec<f32> = values.iter().map(|&v| (v as f64 * scale + shift) as f32).collect();
+        write_values(dst, src.rows(), src.cols(), src.typ(), &out)
+    }
+
+    // Every channel value as f32, which holds 8U and 32F data exactly.
+    pub(crate) fn read_values(mat: &Mat) -> Result<Vec<f32>> {
+        match mat_depth(mat.typ()) {
+            CV_8U => Ok(mat.to_vec::<u8>()?.into_iter().map(f32::from).collect()),
+            CV_32F => mat.to_vec::<f32>(),
+            other => Err(Error::from(format!("Unsupported depth {}", other))),
+        }
+    }
+
+    // Stores `values` into dst, rounding and saturating for 8-bit mats.
+    // dst is reallocated unless it already has the given shape and type.
+    pub(crate) fn write_values(dst: &mut Mat, rows: i32, cols: i32, typ: i32, values: &[f32]) -> Result<()> {
+        if dst.rows() != rows || dst.cols() != cols || dst.typ() != typ {
+            *dst = Mat::zeros(rows, cols, typ)?;
+        }
+        match mat_depth(typ) {
+            CV_8U => {
+                let bytes: Vec<u8> = values.iter().map(|v| v.round().clamp(0.0, 255.0) as u8).collect();
+                dst.copy_from_slice(&bytes)
+            }
+            CV_32F => dst.copy_from_slice(values),
+            other => Err(Error::from(format!("Unsupported depth {} ({} channels)", other, mat_channels(typ)))),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) mod tests {
+        use super::*;
+
+        // A small xorshift, so the property tests need no rand dependency
+        // and fail the same way every run.
+        pub(crate) struct Rng(pub(crate) u64);
+
+        impl Rng {
+            pub(crate) fn next(&mut self) -> u64 {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                self.0
+            }
+
+            pub(crate) fn below(&mut self, n: u64) -> i32 {
+                (self.next() % n) as i32
+            }
+
+            // An 8UC1, 8UC3 or 32FC1 mat of up to 12x12 random values.
+            pub(crate) fn mat(&mut self, typ: i32) -> Mat {
+                let (rows, cols) = (1 + self.below(12), 1 + self.below(12));
+                self.mat_sized(rows, cols, typ)
+            }
+
+            // Cut from a larger mat, so its values don't start at the
+            // allocation. Floats are in -100..100 at a hundredth's
+            // resolution.
+            pub(crate) fn mat_sized(&mut self, rows: i32, cols: i32, typ: i32) -> Mat {
+                let (pad_x, pad_y) = (self.below(3), self.below(3));
+                let (full_rows, full_cols) = (rows + pad_y * 2, cols + pad_x * 2);
+                let count = (full_rows * full_cols * mat_channels(typ)) as usize;
+                let full = if mat_depth(typ) == CV_8U {
+                    let data: Vec<u8> = (0..count).map(|_| self.below(256) as u8).collect();
+                    Mat::from_slice_2d(full_rows, full_cols, mat_channels(typ), &data).unwrap()
+                } else {
+                    let data: Vec<f32> = (0..count).map(|_| (self.below(20_001) - 10_000) as f32 / 100.0).collect();
+                    Mat::from_slice_2d(full_rows, full_cols, mat_channels(typ), &data).unwrap()
+                };
+                full.roi(Rect::new(pad_x, pad_y, cols, rows)).unwrap().clone_owned().unwrap()
+            }
+        }
+
+        // Every channel value, read one element at a time through get.
+        pub(crate) fn naive_values(mat: &Mat) -> Vec<f64> {
+            let mut values = Vec::new();
+            for row in 0..mat.rows() {
+                for col in 0..mat.cols() {
+                    match mat.typ() {
+                        CV_8UC1 => values.push(mat.get::<u8>(row, col).unwrap() as f64),
+                        CV_8UC3 => values.extend(mat.get::<[u8; 3]>(row, col).unwrap().map(f64::from)),
+                        CV_32FC1 => values.push(mat.get::<f32>(row, col).unwrap() as f64),
+                        other => panic!("no naive reader for type {}", other),
+                    }
+                }
+            }
+            values
+        }
+
+        // As OpenCV stores a result into a mat of `typ`.
+        pub(crate) fn saturate(v: f64, typ: i32) -> f64 {
+            if mat_depth(typ) == CV_8U {
+                v.round().clamp(0.0, 255.0)
+            } else {
+                v as f32 as f64
+            }
+        }
+
+        // 4x5 BGR pixels, (row * 10 + col, 100 + row, 200 + col).
+        fn bgr() -> Mat {
+            let data: Vec<u8> = (0..4u8)
+                .flat_map(|row| (0..5u8).flat_map(move |col| [row * 10 + col, 100 + row, 200 + col]))
+                .collect();
+            Mat::from_slice_2d(4, 5, 3, &data).unwrap()
+        }
+
+        // 3x4 floats, row + col / 10.
+        fn floats() -> Mat {
+            let data: Vec<f32> = (0..3).flat_map(|row| (0..4).map(move |col| row as f32 + col as f32 / 10.0)).collect();
+            Mat::from_slice_2d(3, 4, 1, &data).unwrap()
+        }
+
+        #[test]
+        fn type_channels_and_step_come_from_the_mat_type() {
+            let mat = bgr();
+            assert_eq!((mat.typ(), mat.channels(), mat.elem_size()), (CV_8UC3, 3, 3));
+            assert!(mat.step() >= 5 * 3);
+            let mat = floats();
+            assert_eq!((mat.typ(), mat.channels(), mat.elem_size()), (CV_32FC1, 1, 4));
+            assert!(mat.step() >= 4 * 4);
+            assert_eq!(Mat::zeros(2, 2, CV_32FC3).unwrap().elem_size(), 12);
+        }
+
+        #[test]
+        fn pixels_read_back_from_8uc3_mats() {
+            let mat = bgr();
+            assert_eq!(mat.get::<[u8; 3]>(0, 0).unwrap(), [0, 100, 200]);
+            assert_eq!(mat.get::<[u8; 3]>(2, 3).unwrap(), [23, 102, 203]);
+            assert_eq!(mat.get::<[u8; 3]>(3, 4).unwrap(), [34, 103, 204]);
+            // A channel value through the raw accessor, at the pixel's first
+            // channel, as OpenCV's at<uchar> would.
+            let first = mat.at_2d::<u8>(1, 2).unwrap();
+            unsafe {
+                assert_eq!((*first, *first.add(1), *first.add(2)), (12, 101, 202));
+            }
+            assert_eq!(mat.row_slice::<[u8; 3]>(1).unwrap()[4], [14, 101, 204]);
+        }
+
+        #[test]
+        fn values_read_back_from_32fc1_mats() {
+            let mat = floats();
+            assert_eq!(mat.get::<f32>(0, 0).unwrap(), 0.0);
+            assert_eq!(mat.get::<f32>(1, 3).unwrap(), 1.3);
+            assert_eq!(mat.get::<f32>(2, 2).unwrap(), 2.2);
+            unsafe {
+                assert_eq!(*mat.at_2d::<f32>(2, 1).unwrap(), 2.1);
+            }
+            assert_eq!(mat.row_slice::<f32>(2).unwrap(), &[2.0, 2.1, 2.2, 2.3]);
+        }
+
+        #[test]
+        fn a_mismatched_element_type_is_an_error() {
+            let mat = bgr();
+            assert!(mat.get::<u8>(0, 0).is_err());
+            assert!(mat.get::<[u8; 4]>(0, 0).is_err());
+            assert!(mat.get::<[f32; 3]>(0, 0).is_err());
+            assert!(mat.at_2d::<f64>(0, 0).is_err());
+            let mat = floats();
+            assert!(mat.get::<u8>(0, 0).is_err());
+            assert!(mat.get::<i32>(0, 0).is_err());
+            assert!(mat.get::<f64>(0, 0).is_err());
+            assert!(mat.row_slice::<[f32; 2]>(0).is_err());
+        }
+
+        #[test]
+        fn an_index_off_the_mat_is_an_error() {
+            let mat = bgr();
+            for (row, col) in [(-1, 0), (0, -1), (4, 0), (0, 5), (4, 5), (i32::MAX, 0)] {
+                assert!(mat.get::<[u8; 3]>(row, col).is_err(), "({}, {})", row, col);
+                assert!(mat.at_2d::<u8>(row, col).is_err(), "({}, {})", row, col);
+            }
+            assert!(mat.row_slice::<[u8; 3]>(4).is_err());
+            assert!(Mat::default().get::<u8>(0, 0).is_err());
+        }
+
+        #[test]
+        fn reshaping_leaves_the_bounds_where_they_were() {
+            // with_dims is the only way to change a mat's shape from outside,
+            // and it changes dims() alone; the checks still use 2x2.
+            let mat = Mat::zeros(2, 2, CV_8UC1).unwrap().with_dims(vec![1, 4]).unwrap();
+            assert_eq!((mat.rows(), mat.cols()), (2, 2));
+            assert!(mat.get::<u8>(1, 1).is_ok());
+            assert!(mat.get::<u8>(0, 3).is_err());
+            assert!(mat.get::<u8>(900_000, 0).is_err());
+            assert!(mat.row_slice::<u8>(2).is_err());
+            assert!(Mat::zeros(2, 2, CV_8UC1).unwrap().with_dims(vec![1_000_000, 2]).is_err());
+            // Nor can a multi-channel mat be passed off as more elements.
+            assert!(Mat::zeros(2, 2, CV_8UC3).unwrap().with_dims(vec![1, 12]).is_err());
+
+            // Nor does a view reach past its region into the parent.
+            let parent = bgr();
+            let view = parent.roi(Rect::new(0, 0, 2, 2)).unwrap();
+            assert!(view.get::<[u8; 3]>(0, 2).is_err());
+            assert_eq!(view.row_slice::<[u8; 3]>(1).unwrap().len(), 2);
+        }
+
+        // These only touch Rust-owned buffers, so they can run under miri
+        // on the pure-Rust backend.
+        #[test]
+        fn set_then_get_round_trips_each_element_type() {
+            let mut mat = Mat::zeros(3, 4, CV_8UC3).unwrap();
+            mat.set(2, 3, [1u8, 2, 3]).unwrap();
+            mat.set(0, 0, [255u8, 0, 7]).unwrap();
+            assert_eq!(mat.get::<[u8; 3]>(2, 3).unwrap(), [1, 2, 3]);
+            assert_eq!(mat.get::<[u8; 3]>(0, 0).unwrap(), [255, 0, 7]);
+            assert_eq!(mat.get::<[u8; 3]>(1, 1).unwrap(), [0, 0, 0]);
+
+            let mut mat = Mat::zeros(2, 2, CV_32FC1).unwrap();
+            mat.set(1, 0, -4.5f32).unwrap();
+            assert_eq!(mat.get::<f32>(1, 0).unwrap(), -4.5);
+
+            let mut mat = Mat::zeros(2, 2, mat_type(CV_16S, 1)).unwrap();
+            mat.set(0, 1, -300i16).unwrap();
+            assert_eq!(mat.get::<i16>(0, 1).unwrap(), -300);
+
+            let mut mat = Mat::zeros(1, 2, mat_type(CV_64F, 2)).unwrap();
+            mat.set(0, 1, [0.25f64, 1e300]).unwrap();
+            assert_eq!(mat.get::<[f64; 2]>(0, 1).unwrap(), [0.25, 1e300]);
+        }
+
+        #[test]
+        fn set_checks_type_and_bounds_and_leaves_the_mat_alone() {
+            let mut mat = floats();
+            let before = mat.to_vec::<f32>().unwrap();
+            assert!(mat.set(0, 0, 1u8).is_err());
+            assert!(mat.set(0, 0, 1.0f64).is_err());
+            assert!(mat.set(0, 0, [1.0f32; 3]).is_err());
+            assert!(mat.set(3, 0, 1.0f32).is_err());
+            assert!(mat.set(0, -1, 1.0f32).is_err());
+            assert_eq!(mat.to_vec::<f32>().unwrap(), before);
+        }
+
+        #[test]
+        fn row_slice_is_the_whole_row() {
+            let mat = floats();
+            for row in 0..3 {
+                let values = mat.row_slice::<f32>(row).unwrap();
+                assert_eq!(values.len(), 4);
+                for (col, value) in values.iter().enumerate() {
+                    assert_eq!(*value, mat.get::<f32>(row, col as i32).unwrap());
+                }
+            }
+            let mat = bgr();
+            let row: Vec<[u8; 3]> = mat.row_slice::<[u8; 3]>(3).unwrap().to_vec();
+            assert_eq!(row, (0..5u8).map(|col| [30 + col, 103, 200 + col]).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn row_slice_of_a_view_stops_at_the_view() {
+            let mat = bgr();
+            let view = mat.roi(Rect::new(1, 2, 3, 2)).unwrap();
+            assert_eq!(view.row_slice::<[u8; 3]>(0).unwrap(), &[[21, 102, 201], [22, 102, 202], [23, 102, 203]]);
+            assert_eq!(view.get::<[u8; 3]>(1, 2).unwrap(), [33, 103, 203]);
+            assert!(view.get::<[u8; 3]>(2, 0).is_err());
+        }
+
+        #[test]
+        fn a_roi_is_the_pixels_it_covers() {
+            let mat = bgr();
+            let view = mat.roi(Rect::new(2, 1, 3, 2)).unwrap();
+            assert_eq!((view.rows(), view.cols(), view.typ()), (2, 3, CV_8UC3));
+            assert_eq!(view.step(), mat.step());
+            for row in 0..2 {
+                for col in 0..3 {
+                    assert_eq!(view.get::<[u8; 3]>(row, col).unwrap(), mat.get::<[u8; 3]>(row + 1, col + 2).unwrap());
+                }
+            }
+            // A view of a view is offset from the first.
+            let inner = view.roi(Rect::new(1, 1, 2, 1)).unwrap();
+            assert_eq!(inner.get::<[u8; 3]>(0, 0).unwrap(), [23, 102, 203]);
+            // The whole mat, and a single pixel in its last corner.
+            assert_eq!(mat.roi(Rect::new(0, 0, 5, 4)).unwrap().to_vec::<u8>().unwrap(), mat.to_vec::<u8>().unwrap());
+            assert_eq!(mat.roi(Rect::new(4, 3, 1, 1)).unwrap().get::<[u8; 3]>(0, 0).unwrap(), [34, 103, 204]);
+        }
+
+        #[test]
+        fn writes_through_a_roi_land_in_the_parent() {
+            let mut mat = floats();
+            {
+                let mut view = mat.roi_mut(Rect::new(1, 1, 2, 2)).unwrap();
+                view.set(0, 0, -1.0f32).unwrap();
+                view.set(1, 1, -2.0f32).unwrap();
+                assert!(view.set(2, 0, -3.0f32).is_err());
+            }
+            assert_eq!(mat.get::<f32>(1, 1).unwrap(), -1.0);
+            assert_eq!(mat.get::<f32>(2, 2).unwrap(), -2.0);
+            // Nothing else moved.
+            assert_eq!(mat.row_slice::<f32>(0).unwrap(), &[0.0, 0.1, 0.2, 0.3]);
+            assert_eq!(mat.row_slice::<f32>(1).unwrap(), &[1.0, -1.0, 1.2, 1.3]);
+            assert_eq!(mat.row_slice::<f32>(2).unwrap(), &[2.0, 2.1, -2.0, 2.3]);
+        }
+
+        #[test]
+        fn a_roi_off_the_mat_is_an_error() {
+            let mat = bgr();
+            let outside = [
+                Rect::new(-1, 0, 2, 2),
+                Rect::new(0, -1, 2, 2),
+                Rect::new(4, 0, 2, 1),
+                Rect::new(0, 3, 1, 2),
+                Rect::new(5, 0, 1, 1),
+                Rect::new(0, 0, 6, 4),
+                Rect::new(0, 0, 0, 1),
+                Rect::new(0, 0, 1, 0),
+                Rect::new(0, 0, -1, 1),
+                // Would overflow rather than land past the edge.
+                Rect::new(1, 0, i32::MAX, 1),
+                Rect::new(0, i32::MAX, 1, i32::MAX),
+            ];
+            for rect in outside {
+                let error = mat.roi(rect).err().unwrap_or_else(|| panic!("{:?} fit", rect));
+                assert!(error.to_string().contains("doesn't fit inside a 5x4 mat"), "{}", error);
+            }
+            let error = mat.roi(Rect::new(3, 2, 4, 1)).err().unwrap();
+            assert!(error.to_string().contains("ROI 4x1 at (3, 2)"), "{}", error);
+            let mut mat = floats();
+            assert!(mat.roi_mut(Rect::new(3, 0, 2, 1)).is_err());
+            assert!(Mat::default().roi(Rect::new(0, 0, 1, 1)).is_err());
+        }
+
+        #[test]
+        fn copies_of_a_roi_are_continuous_and_their_own() {
+            let mat = bgr();
+            let view = mat.roi(Rect::new(1, 0, 3, 3)).unwrap();
+            assert!(mat.is_continuous());
+            assert!(!view.is_continuous());
+            // A single row has no padding to skip.
+            assert!(mat.roi(Rect::new(1, 2, 3, 1)).unwrap().is_continuous());
+
+            let mut copy = view.clone_owned().unwrap();
+            assert!(copy.is_continuous());
+            assert_eq!(copy.to_vec::<u8>().unwrap(), view.to_vec::<u8>().unwrap());
+            copy.set(0, 0, [9u8, 9, 9]).unwrap();
+            assert_eq!(mat.get::<[u8; 3]>(0, 1).unwrap(), [1, 100, 201]);
+
+            // copy_to keeps a dst of the right shape, and replaces any other.
+            let mut dst = Mat::zeros(3, 3, CV_8UC3).unwrap();
+            let data = dst.data;
+            view.copy_to(&mut dst).unwrap();
+            assert_eq!(dst.data, data);
+            assert_eq!(dst.to_vec::<u8>().unwrap(), view.to_vec::<u8>().unwrap());
+            let mut dst = floats();
+            view.copy_to(&mut dst).unwrap();
+            assert_eq!((dst.rows(), dst.cols(), dst.typ()), (3, 3, CV_8UC3));
+            assert_eq!(dst.to_vec::<u8>().unwrap(), view.to_vec::<u8>().unwrap());
+        }
+
+        #[test]
+        fn bgr_data_round_trips_byte_for_byte() {
+            let data: Vec<u8> = (0..7 * 9 * 3).map(|i| (i * 37 % 256) as u8).collect();
+            let mat = Mat::from_slice_2d(7, 9, 3, &data).unwrap();
+            assert_eq!(mat.typ(), CV_8UC3);
+            assert_eq!(mat.to_vec::<u8>().unwrap(), data);
+            // A view's rows are gathered past the parent's stride.
+            let view = mat.roi(Rect::new(2, 1, 4, 3)).unwrap();
+            let expected: Vec<u8> = (1..4).flat_map(|row| data[(row * 9 + 2) * 3..(row * 9 + 6) * 3].to_vec()).collect();
+            assert_eq!(view.to_vec::<u8>().unwrap(), expected);
+            assert_eq!(view.clone_owned().unwrap().to_vec::<u8>().unwrap(), expected);
+        }
+
+        #[test]
+        fn f32_data_round_trips_bit_for_bit() {
+            let data: Vec<f32> = vec![0.0, -0.0, 1.5, -2.25, f32::MIN_POSITIVE, f32::MAX, f32::MIN, 1e-40, f32::INFINITY, f32::NEG_INFINITY, 0.1, 3.0];
+            let mat = Mat::from_slice_2d(3, 4, 1, &data).unwrap();
+            assert_eq!(mat.typ(), CV_32FC1);
+            let back = mat.to_vec::<f32>().unwrap();
+            assert_eq!(back.iter().map(|v| v.to_bits()).collect::<Vec<_>>(), data.iter().map(|v| v.to_bits()).collect::<Vec<_>>());
+            let nan = Mat::from_slice_2d(1, 1, 1, &[f32::NAN]).unwrap();
+            assert_eq!(nan.get::<f32>(0, 0).unwrap().to_bits(), f32::NAN.to_bits());
+        }
+
+        #[test]
+        fn data_that_doesnt_fit_the_size_is_an_error() {
+            assert!(Mat::from_slice_2d(2, 2, 3, &[0u8; 11]).is_err());
+            assert!(Mat::from_slice_2d(2, 2, 1, &[0f32; 5]).is_err());
+        }
+
+        #[cfg(feature = "image-interop")]
+        #[test]
+        fn rgb_images_round_trip_byte_for_byte() {
+            let image = image::RgbImage::from_fn(6, 4, |x, y| image::Rgb([x as u8 * 40, y as u8 * 60, (x * y) as u8]));
+            let mat = Mat::try_from(&image).unwrap();
+            assert_eq!((mat.rows(), mat.cols(), mat.typ()), (4, 6, CV_8UC3));
+            assert_eq!(mat.get::<[u8; 3]>(3, 5).unwrap(), [200, 180, 15]);
+            assert_eq!(mat.to_rgb_image().unwrap(), image);
+
+            let view = mat.roi(Rect::new(1, 1, 3, 2)).unwrap();
+            let cropped = image::imageops::crop_imm(&image, 1, 1, 3, 2).to_image();
+            assert_eq!(view.to_rgb_image().unwrap(), cropped);
+            assert!(floats().to_rgb_image().is_err());
+        }
+
+        const TYPES: [i32; 3] = [CV_8UC1, CV_8UC3, CV_32FC1];
+
+        #[test]
+        fn elementwise_ops_match_a_naive_loop() {
+            let mut rng = Rng(0xadd5);
+            for round in 0..300 {
+                let typ = TYPES[round % 3];
+                let a = rng.mat(typ);
+                let other = rng.mat_sized(a.rows(), a.cols(), typ);
+                let scalar = Scalar::new(rng.below(300) as f64 - 150.0, rng.below(10) as f64 / 4.0, -3.0, 0.0);
+                let scale = [1.0, 0.5, 2.0][rng.below(3) as usize];
+                let (va, vb) = (naive_values(&a), naive_values(&other));
+                let channels = a.channels() as usize;
+                let s = |i: usize| scalar.channel(i % channels) as f32 as f64;
+
+                let mut dst = Mat::default();
+                add(&a, &other, &mut dst).unwrap();
+                let expected: Vec<f64> = va.iter().zip(&vb).map(|(x, y)| saturate((*x as f32 + *y as f32) as f64, typ)).collect();
+                assert_eq!(naive_values(&dst), expected, "add, type {}", typ);
+
+                subtract(&a, scalar, &mut dst).unwrap();
+                let expected: Vec<f64> = va.iter().enumerate().map(|(i, x)| saturate((*x as f32 - s(i) as f32) as f64, typ)).collect();
+                assert_eq!(naive_values(&dst), expected, "subtract scalar, type {}", typ);
+
+                multiply(&a, &other, &mut dst, scale).unwrap();
+                let expected: Vec<f64> = va.iter().zip(&vb).map(|(x, y)| saturate((*x as f32 * *y as f32 * scale as f32) as f64, typ)).collect();
+                assert_eq!(naive_values(&dst), expected, "multiply, type {}", typ);
+                assert_eq!((dst.rows(), dst.cols(), dst.typ()), (a.rows(), a.cols(), typ));
+            }
+        }
+
+        #[test]
+        fn mismatched_operands_are_errors() {
+            let mut dst = Mat::default();
+            let a = Mat::zeros(3, 4, CV_8UC1).unwrap();
+            assert!(add(&a, &Mat::zeros(4, 3, CV_8UC1).unwrap(), &mut dst).is_err());
+            assert!(subtract(&a, &Mat::zeros(3, 4, CV_32FC1).unwrap(), &mut dst).is_err());
+            assert!(multiply(&a, &Mat::zeros(3, 4, CV_8UC3).unwrap(), &mut dst, 1.0).is_err());
+            assert!(add(&Mat::zeros(2, 2, mat_type(CV_16S, 1)).unwrap(), Scalar::default(), &mut dst).is_err());
+            assert!(min_max_loc(&Mat::zeros(2, 2, CV_8UC3).unwrap(), None, None, None, None).is_err());
+            assert!(normalize(&a, &mut dst, 1.0, 0.0, 99).is_err());
+        }
+
+        #[test]
+        fn min_max_loc_matches_a_naive_scan() {
+            let mut rng = Rng(0x3131);
+            for round in 0..300 {
+                let mat = rng.mat(if round % 2 == 0 { CV_8UC1 } else { CV_32FC1 });
+                let (mut min, mut max) = (0.0, 0.0);
+                let (mut min_at, mut max_at) = (Point::new(-1, -1), Point::new(-1, -1));
+                min_max_loc(&mat, Some(&mut min), Some(&mut max), Some(&mut min_at), Some(&mut max_at)).unwrap();
+
+                let mut expected = (f64::MAX, Point::new(0, 0), f64::MIN, Point::new(0, 0));
+                for row in 0..mat.rows() {
+                    for col in 0..mat.cols() {
+                        let v = naive_values(&mat.roi(Rect::new(col, row, 1, 1)).unwrap())[0];
+                        if v < expected.0 {
+                            expected.0 = v;
+                            expected.1 = Point::new(col, row);
+                        }
+                        if v > expected.2 {
+                            expected.2 = v;
+                            expected.3 = Point::new(col, row);
+                        }
+                    }
+                }
+                assert_eq!((min, min_at, max, max_at), expected, "round {}", round);
+            }
+        }
+
+        #[test]
+        fn mean_and_stddev_match_a_two_pass_reference() {
+            let mut rng = Rng(0x5eed);
+            for round in 0..300 {
+                let typ = TYPES[round % 3];
+                let mat = rng.mat(typ);
+                let (mut mean, mut stddev) = (Scalar::default(), Scalar::default());
+                mean_std_dev(&mat, &mut mean, &mut stddev).unwrap();
+
+                let channels = mat.channels() as usize;
+                let values = naive_values(&mat);
+                for c in 0..4 {
+                    let channel: Vec<f64> = values.iter().skip(c).step_by(channels).copied().collect();
+                    if c >= channels {
+                        assert_eq!((mean.channel(c), stddev.channel(c)), (0.0, 0.0));
+                        continue;
+                    }
+                    let n = channel.len() as f64;
+                    let m = channel.iter().sum::<f64>() / n;
+                    let sd = (channel.iter().map(|v| (v - m) * (v - m)).sum::<f64>() / n).sqrt();
+                    assert!((mean.channel(c) - m).abs() < 1e-9, "mean {} vs {}", mean.channel(c), m);
+                    assert!((stddev.channel(c) - sd).abs() < 1e-6, "stddev {} vs {}", stddev.channel(c), sd);
+                }
+            }
+        }
+
+        #[test]
+        fn normalize_matches_a_naive_rescale() {
+            let mut rng = Rng(0x2070);
+            for round in 0..300 {
+                let typ = TYPES[round % 3];
+                let mat = rng.mat(typ);
+                let values = naive_values(&mat);
+                let mut dst = Mat::default();
+
+                let (alpha, beta) = ([0.0, 10.0, 255.0][round % 3], [1.0, 200.0, 0.0][round % 3]);
+                normalize(&mat, &mut dst, alpha, beta, NORM_MINMAX).unwrap();
+                let (lo, hi) = values.iter().fold((f64::MAX, f64::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+                let (to_lo, to_hi) = (alpha.min(beta), alpha.max(beta));
+                let expected: Vec<f64> = values
+                    .iter()
+                    .map(|&v| if hi > lo { to_lo + (v - lo) * (to_hi - to_lo) / (hi - lo) } else { to_lo })
+                    .map(|v| saturate(v, typ))
+                    .collect();
+                for (got, want) in naive_values(&dst).iter().zip(&expected) {
+                    // The implementation folds the scale and shift first, so
+                    // 8-bit values may round the other way at a half.
+                    let slack = if mat_depth(typ) == CV_8U { 1.0 } else { 1e-3 };
+                    assert!((got - want).abs() <= slack, "{} vs {} for type {}", got, want, typ);
+                }
+
+                if typ == CV_32FC1 {
+                    normalize(&mat, &mut dst, 2.0, 0.0, NORM_L2).unwrap();
+                    let norm = values.iter().map(|v| v * v).sum::<f64>().sqrt();
+                    let out = naive_values(&dst);
+                    if norm > 0.0 {
+                        let l2 = out.iter().map(|v| v * v).sum::<f64>().sqrt();
+                        assert!((l2 - 2.0).abs() < 1e-4, "L2 norm {}", l2);
+                        for (got, v) in out.iter().zip(&values) {
+                            assert!((got - v * 2.0 / norm).abs() < 1e-4);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(system_opencv)]
+pub mod dnn {
+    use super::*;
+    use crate::core::{Mat, MatBuffer, Size, Scalar, Vector, Error, ErrorKind, Result};
+    use std::ffi::CString;
+    use std::os::raw::{c_char, c_void, c_int, c_double, c_float};
+    use std::ptr;
+    use std::sync::{Arc, Mutex, MutexGuard};
+
+    #[link(name = "opencv_wrapper")]
+    extern "C" {
+        fn opencv_wrapper_last_error() -> *const c_char;
+        fn opencv_dnn_readNetFromDarknet(cfg: *const c_char, weights: *const c_char) -> *mut c_void;
+        fn opencv_dnn_readNetFromONNX(model: *const c_char) -> *mut c_void;
+        fn opencv_dnn_readNet(model: *const c_char, config: *const c_char, framework: *const c_char) -> *mut c_void;
+        fn opencv_dnn_getLayerNames(net: *mut c_void) -> *const c_char;
+        fn opencv_dnn_getUnconnectedOutLayers(net: *mut c_void, out: *mut c_int, max: c_int) -> c_int;
+        fn opencv_dnn_setInput(net: *mut c_void, blob: *mut c_void) -> c_int;
+        fn opencv_dnn_setPreferableBackend(net: *mut c_void, backend: c_int) -> c_int;
+        fn opencv_dnn_setPreferableTarget(net: *mut c_void, target: c_int) -> c_int;
+        fn opencv_dnn_getAvailableBackends(backends: *mut c_int, targets: *mut c_int, max: c_int) -> c_int;
+        fn opencv_dnn_setInputData(net: *mut c_void, data: *const c_float, dims: *const c_int, ndims: c_int) -> c_int;
+        #[cfg_attr(not(feature = "wrapper-blob"), allow(dead_code))]
+        fn opencv_dnn_blobFromImage(
+            data: *const u8,
+            rows: c_int,
+            cols: c_int,
+            typ: c_int,
+            step: usize,
+            scale: c_double,
+            width: c_int,
+            height: c_int,
+            mean: *const c_double,
+            swap_rb: c_int,
+            crop: c_int,
+            ddepth: c_int
+        ) -> *mut c_void;
+        fn opencv_dnn_forwardAll(net: *mut c_void, names: *const *const c_char, count: c_int, outputs: *mut *mut c_void, max_outputs: c_int) -> c_int;
+        fn opencv_dnn_getOutputBuffer(output: *mut c_void, dims: *mut c_int, max_dims: c_int, data: *mut *const c_float, len: *mut usize) -> c_int;
+        fn opencv_dnn_releaseNet(net: *mut c_void);
+        fn opencv_dnn_releaseMat(mat: *mut c_void);
+    }
+
+    // The message of the last failed wrapper call on this thread.
+    pub fn last_wrapper_error() -> Option<String> {
+        unsafe {
+            let message = opencv_wrapper_last_error();
+            if message.is_null() {
+                return None;
+            }
+            let message = std::ffi::CStr::from_ptr(message).to_string_lossy().into_owned();
+            (!message.is_empty()).then_some(message)
+        }
+    }
+
+    fn wrapper_error(kind: ErrorKind, path: Option<&str>, fallback: &str) -> Error {
+        Error::Wrapper {
+            kind,
+            message: last_wrapper_error().unwrap_or_else(|| fallback.to_string()),
+            path: path.map(str::to_string),
+        }
+    }
+
+    pub(crate) unsafe fn release_mat(mat: *mut c_void) {
+        opencv_dnn_releaseMat(mat);
+    }
+
+    // The wrapper reads a Rust-built blob as packed floats in the shape of
+    // dims(), so anything else would have it read past the data.
+    fn check_input(blob: &Mat) -> Result<()> {
+        if blob.data_ptr().is_null() || (blob.typ() == core::CV_32FC1 && blob.is_continuous()) {
+            return Ok(());
+        }
+        Err(Error::Wrapper {
+            kind: ErrorKind::SetInput,
+            message: format!("input blobs must be continuous CV_32FC1, got type {}", blob.typ()),
+            path: None,
+        })
+    }
+
+    // Enough for every output of the detection models we load.
+    const MAX_OUTPUTS: usize = 16;
+    const MAX_DIMS: usize = 8;
+
+    // cv::dnn::Backend and cv::dnn::Target values.
+    pub const DNN_BACKEND_DEFAULT: i32 = 0;
+    pub const DNN_BACKEND_HALIDE: i32 = 1;
+    pub const DNN_BACKEND_INFERENCE_ENGINE: i32 = 2;
+    pub const DNN_BACKEND_OPENCV: i32 = 3;
+    pub const DNN_BACKEND_VKCOM: i32 = 4;
+    pub const DNN_BACKEND_CUDA: i32 = 5;
+
+    pub const DNN_TARGET_CPU: i32 = 0;
+    pub const DNN_TARGET_OPENCL: i32 = 1;
+    pub const DNN_TARGET_OPENCL_FP16: i32 = 2;
+    pub const DNN_TARGET_MYRIAD: i32 = 3;
+    pub const DNN_TARGET_VULKAN: i32 = 4;
+    pub const DNN_TARGET_FPGA: i32 = 5;
+    pub const DNN_TARGET_CUDA: i32 = 6;
+    pub const DNN_TARGET_CUDA_FP16: i32 = 7;
+    pub const DNN_TARGET_HDDL: i32 = 8;
+
+    // Config names, as used by the rover's vision.dnn_backend setting.
+    pub fn backend_from_str(name: &str) -> Option<i32> {
+        match name {
+            "default" => Some(DNN_BACKEND_DEFAULT),
+            "halide" => Some(DNN_BACKEND_HALIDE),
+            "inference_engine" | "openvino" => Some(DNN_BACKEND_INFERENCE_ENGINE),
+            "opencv" => Some(DNN_BACKEND_OPENCV),
+            "vulkan" => Some(DNN_BACKEND_VKCOM),
+            "cuda" => Some(DNN_BACKEND_CUDA),
+            _ => None,
+        }
+    }
+
+    pub fn target_from_str(name: &str) -> Option<i32> {
+        match name {
+            "cpu" => Some(DNN_TARGET_CPU),
+            "opencl" => Some(DNN_TARGET_OPENCL),
+            "opencl_fp16" => Some(DNN_TARGET_OPENCL_FP16),
+            "myriad" => Some(DNN_TARGET_MYRIAD),
+            "vulkan" => Some(DNN_TARGET_VULKAN),
+            "fpga" => Some(DNN_TARGET_FPGA),
+            "cuda" => Some(DNN_TARGET_CUDA),
+            "cuda_fp16" => Some(DNN_TARGET_CUDA_FP16),
+            "hddl" => Some(DNN_TARGET_HDDL),
+            _ => None,
+        }
+    }
+
+    // (backend, target) pairs the system OpenCV was built with.
+    pub fn get_available_backends() -> Result<Vec<(i32, i32)>> {
+        unsafe {
+            let count = opencv_dnn_getAvailableBackends(ptr::null_mut(), ptr::null_mut(), 0);
+            if count < 0 {
+                return Err(wrapper_error(ErrorKind::Backend, None, "could not list backends"));
+            }
+            let mut backends = vec![0; count as usize];
+            let mut targets = vec![0; count as usize];
+            opencv_dnn_getAvailableBackends(backends.as_mut_ptr(), targets.as_mut_ptr(), count);
+            Ok(backends.into_iter().zip(targets).collect())
+        }
+    }
+
+    // Only built by the read_net functions, from a handle the wrapper just
+    // returned, and the handle never leaves this module.
+    pub struct Net {
+        ptr: *mut c_void,
+    }
+
+    // Safe to move to another thread because:
+    // - the handle owns its cv::dnn::Net outright: `ptr` is private and
+    //   only set from a fresh wrapper handle, so no other Net or caller
+    //   holds it,
+    // - OpenCV's Net has no thread affinity, and
+    // - the wrapper's last error is thread-local, so it is read back on
+    //   whichever thread made the failing call.
+    // Net is deliberately not Sync: forward() takes &self but mutates the
+    // net's internal buffers, so concurrent calls must be serialized by
+    // the caller, e.g. with SharedNet.
+    unsafe impl Send for Net {}
+
+    impl Drop for Net {
         fn drop(&mut self) {
             if !self.ptr.is_null() {
                 unsafe {
@@ -225,154 +1762,2858 @@ pub mod dnn {
     }
 
     impl Net {
-        pub fn set_preferable_backend(&mut self, _backend: i32) -> Result<()> {
+        // Fails up front when the system OpenCV wasn't built with the
+        // backend, rather than at the first forward pass.
+        pub fn set_preferable_backend(&mut self, backend: i32) -> Result<()> {
+            let available = get_available_backends()?;
+            if backend != DNN_BACKEND_DEFAULT && !available.iter().any(|&(b, _)| b == backend) {
+                return Err(Error::Wrapper {
+                    kind: ErrorKind::Backend,
+                    message: format!("backend {} is not available in this OpenCV build", backend),
+                    path: None,
+                });
+            }
+            if unsafe { opencv_dnn_setPreferableBackend(self.ptr, backend) } != 0 {
+                return Err(wrapper_error(ErrorKind::Backend, None, "setPreferableBackend failed"));
+            }
             Ok(())
         }
 
-        pub fn set_preferable_target(&mut self, _target: i32) -> Result<()> {
+        pub fn set_preferable_target(&mut self, target: i32) -> Result<()> {
+            let available = get_available_backends()?;
+            if !available.iter().any(|&(_, t)| t == target) {
+                return Err(Error::Wrapper {
+                    kind: ErrorKind::Backend,
+                    message: format!("target {} is not available in this OpenCV build", target),
+                    path: None,
+                });
+            }
+            if unsafe { opencv_dnn_setPreferableTarget(self.ptr, target) } != 0 {
+                return Err(wrapper_error(ErrorKind::Backend, None, "setPreferableTarget failed"));
+            }
             Ok(())
         }
 
         pub fn set_input(&self, blob: &Mat, _name: &str, _scale: f64, _mean: Scalar) -> Result<()> {
+            check_input(blob)?;
             unsafe {
-                let ret = opencv_dnn_setInput(self.ptr, blob.ptr);
+                // Blobs built in Rust hand over their data; the wrapper's
+                // own blobs are already cv::Mats.
+                let ret = if blob.data_ptr().is_null() {
+                    opencv_dnn_setInput(self.ptr, blob.raw_ptr())
+                } else {
+                    let dims = blob.dims();
+                    opencv_dnn_setInputData(self.ptr, blob.data_ptr() as *const c_float, dims.as_ptr(), dims.len() as c_int)
+                };
                 if ret == 0 {
                     Ok(())
                 } else {
-                    Err(Error("Failed to set input".to_string()))
+                    Err(wrapper_error(ErrorKind::SetInput, None, "no network or blob"))
                 }
             }
         }
 
-        pub fn forward(&self, outputs: &mut Vector<Mat>, layer_names: &Vector<String>) -> Result<()> {
+        // Sets `blob` as the input and runs the net once for every layer in
+        // `layer_names`, or for its unconnected outputs when that is empty.
+        // Outputs with more than two dims are stored with all but the last
+        // dim folded into rows; dims() has the real shape.
+        pub fn forward(&self, blob: &Mat, outputs: &mut Vector<Mat>, layer_names: &Vector<String>) -> Result<()> {
+            self.set_input(blob, "", 1.0, Scalar::default())?;
+
+            let c_names = layer_names.iter()
+                .map(|name| CString::new(name.as_str()))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let name_ptrs: Vec<*const c_char> = c_names.iter().map(|n| n.as_ptr()).collect();
+            let mut output_ptrs = [ptr::null_mut(); MAX_OUTPUTS];
+
             unsafe {
-                for name in layer_names.iter() {
-                    let c_name = CString::new(name.as_str()).map_err(|e| Error(format!("{}", e)))?;
-                    
-                    let output_ptr = opencv_dnn_forward(
-                        self.ptr, 
-                        ptr::null_mut(),
-                        c_name.as_ptr(),
-                        ptr::null_mut()
-                    );
-                    
-                    if !output_ptr.is_null() {
-                        let mut rows: c_int = 0;
-                        let mut cols: c_int = 0;
-                        opencv_dnn_getOutputDims(output_ptr, &mut rows, &mut cols);
-                        
-                        let mut output_mat = Mat::zeros(rows, cols, core::CV_32FC1)?;
-                        
-                        for i in 0..rows {
-                            for j in 0..cols {
-                                let val = opencv_dnn_getOutputData(output_ptr, i, j);
-                                if let Ok(ptr) = output_mat.at_2d_mut::<f32>(i, j) {
-                                    *ptr = val;
-                                }
-                            }
-                        }
-                        
-                        outputs.push(output_mat);
-                        opencv_dnn_releaseMat(output_ptr);
-                    }
+                let count = opencv_dnn_forwardAll(
+                    self.ptr,
+                    name_ptrs.as_ptr(),
+                    name_ptrs.len() as c_int,
+                    output_ptrs.as_mut_ptr(),
+                    MAX_OUTPUTS as c_int,
+                );
+                if count < 0 {
+                    return Err(wrapper_error(ErrorKind::Forward, None, "forward failed"));
                 }
-                
-                if outputs.is_empty() {
-                    outputs.push(Mat::default());
+
+                // Every output is released, even after a failed copy.
+                let copied: Vec<Result<Mat>> = output_ptrs[..count as usize].iter()
+                    .map(|&output_ptr| {
+                        let mat = copy_output(output_ptr);
+                        opencv_dnn_releaseMat(output_ptr);
+                        mat
+                    })
+                    .collect();
+                for mat in copied {
+                    outputs.push(mat?);
                 }
-                
-                Ok(())
             }
+            
+            if outputs.is_empty() {
+                outputs.push(Mat::default());
+            }
+            
+            Ok(())
         }
 
         pub fn get_layer_names(&self) -> Result<Vector<String>> {
-            Ok(vec![
-                "conv_0", "conv_1", "pool_2", "conv_3", "pool_4", "conv_5",
-                "pool_6", "conv_7", "pool_8", "conv_9", "conv_10", "conv_11",
-                "pool_12", "conv_13", "conv_14", "conv_15", "yolo_16",
-                "route_17", "conv_18", "upsample_19", "route_20", "conv_21",
-                "conv_22", "yolo_23"
-            ].iter().map(|s| s.to_string()).collect())
+            if self.ptr.is_null() {
+                return Err(Error::from("Null network"));
+            }
+            unsafe {
+                let names = std::ffi::CStr::from_ptr(opencv_dnn_getLayerNames(self.ptr)).to_string_lossy();
+                Ok(names.split('\n').filter(|n| !n.is_empty()).map(str::to_string).collect())
+            }
         }
 
+        // Layer ids as OpenCV numbers them, for indexing get_layer_names.
         pub fn get_unconnected_out_layers(&self) -> Result<Vector<i32>> {
-            Ok(vec![16, 23])
+            if self.ptr.is_null() {
+                return Err(Error::from("Null network"));
+            }
+            unsafe {
+                let count = opencv_dnn_getUnconnectedOutLayers(self.ptr, ptr::null_mut(), 0);
+                let mut layers = vec![0; count.max(0) as usize];
+                opencv_dnn_getUnconnectedOutLayers(self.ptr, layers.as_mut_ptr(), count);
+                Ok(layers)
+            }
+        }
+    }
+
+    // One bulk copy of a wrapper-owned output into an owned Mat.
+    unsafe fn copy_output(output_ptr: *mut c_void) -> Result<Mat> {
+        let mut dims = [0 as c_int; MAX_DIMS];
+        let mut data: *const c_float = ptr::null();
+        let mut len = 0usize;
+        let ndims = opencv_dnn_getOutputBuffer(output_ptr, dims.as_mut_ptr(), MAX_DIMS as c_int, &mut data, &mut len);
+        if ndims < 1 || ndims as usize > MAX_DIMS || data.is_null() {
+            return Err(wrapper_error(ErrorKind::Forward, None, "unreadable output"));
+        }
+
+        let dims = dims[..ndims as usize].to_vec();
+        let cols = *dims.last().unwrap_or(&0);
+        let rows = if cols == 0 { 0 } else { (len / cols as usize) as i32 };
+        let values = std::slice::from_raw_parts(data, len);
+        Mat::from_slice_2d(rows, cols, 1, values)?.with_dims(dims)
+    }
+
+    pub fn read_net_from_onnx(path: &str) -> Result<Net> {
+        let c_path = CString::new(path)?;
+        let net_ptr = unsafe { opencv_dnn_readNetFromONNX(c_path.as_ptr()) };
+        if net_ptr.is_null() {
+            Err(wrapper_error(ErrorKind::LoadNetwork, Some(path), "empty network"))
+        } else {
+            Ok(Net { ptr: net_ptr })
+        }
+    }
+
+    pub fn read_net_from_tensorflow(model: &str, config: &str) -> Result<Net> {
+        read_net(model, config, "tensorflow")
+    }
+
+    // Mirrors cv::dnn::readNet. An empty `framework` is worked out from
+    // the model's extension: .onnx, .weights/.cfg (darknet) or .pb.
+    pub fn read_net(model: &str, config: &str, framework: &str) -> Result<Net> {
+        let framework = if framework.is_empty() {
+            detect_framework(model)
+                .ok_or_else(|| Error::from(format!("Can't tell the model format of {}", model)))?
+        } else {
+            framework
+        };
+        let c_model = CString::new(model)?;
+        let c_config = CString::new(config)?;
+        let c_framework = CString::new(framework)?;
+        let net_ptr = unsafe { opencv_dnn_readNet(c_model.as_ptr(), c_config.as_ptr(), c_framework.as_ptr()) };
+        if net_ptr.is_null() {
+            Err(wrapper_error(ErrorKind::LoadNetwork, Some(model), "empty network"))
+        } else {
+            Ok(Net { ptr: net_ptr })
+        }
+    }
+
+    fn detect_framework(model: &str) -> Option<&'static str> {
+        let extension = std::path::Path::new(model).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "onnx" => Some("onnx"),
+            "weights" | "cfg" => Some("darknet"),
+            "pb" | "pbtxt" => Some("tensorflow"),
+            _ => None,
         }
     }
 
     pub fn read_net_from_darknet(cfg_path: &str, weights_path: &str) -> Result<Net> {
         unsafe {
-            let c_cfg = CString::new(cfg_path).map_err(|e| Error(format!("{}", e)))?;
-            let c_weights = CString::new(weights_path).map_err(|e| Error(format!("{}", e)))?;
+            let c_cfg = CString::new(cfg_path).map_err(|e| Error::from(e.to_string()))?;
+            let c_weights = CString::new(weights_path).map_err(|e| Error::from(e.to_string()))?;
             
             let net_ptr = opencv_dnn_readNetFromDarknet(c_cfg.as_ptr(), c_weights.as_ptr());
             
             if net_ptr.is_null() {
-                Err(Error("Failed to load network".to_string()))
+                let path = format!("{} / {}", cfg_path, weights_path);
+                Err(wrapper_error(ErrorKind::LoadNetwork, Some(&path), "empty network"))
             } else {
                 Ok(Net { ptr: net_ptr })
             }
         }
     }
 
+    // Builds a 1xCxHxW float blob the way cv::dnn::blobFromImage does:
+    // resize to `size` (with `crop`, scale to cover it and cut out the
+    // centre), swap the first and third channels if `swap_rb`, then
+    // subtract `mean` and multiply by `scale`. With the `wrapper-blob`
+    // feature the C++ wrapper builds it instead, from the same arguments.
+    #[cfg(not(feature = "wrapper-blob"))]
     pub fn blob_from_image(
         image: &Mat,
         scale: f64,
         size: Size,
-        _mean: Scalar,
-        _swap_rb: bool,
-        _crop: bool,
-        _ddepth: i32
+        mean: Scalar,
+        swap_rb: bool,
+        crop: bool,
+        ddepth: i32
     ) -> Result<Mat> {
-        unsafe {
-            let blob_ptr = opencv_dnn_blobFromImage(image.ptr, scale, size.width, size.height);
-            
-            if blob_ptr.is_null() {
-                Err(Error("Failed to create blob".to_string()))
-            } else {
-                Ok(Mat { 
-                    ptr: blob_ptr,
-                    rows: 1,
-                    cols: size.width * size.height * 3,
-                })
-            }
-        }
+        rust_blob_from_image(image, scale, size, mean, swap_rb, crop, ddepth)
     }
 
+    // Kept in wrapper-blob test builds to check the wrapper against.
+    #[cfg(any(not(feature = "wrapper-blob"), test))]
+    fn rust_blob_from_image(
+        image: &Mat,
+        scale: f64,
+        size: Size,
+        mean: Scalar,
+        swap_rb: bool,
+        crop: bool,
+        ddepth: i32
+    ) -> Result<Mat> {
+        check_blob_args(image, ddepth)?;
+        let (width, height) = (size.width as usize, size.height as usize);
+        let channels = image.channels() as usize;
+
+        let mut resized = Mat::default();
+        let (resized_w, resized_h) = if crop {
+            let factor = (size.width as f32 / image.cols() as f32).max(size.height as f32 / image.rows() as f32);
+            let w = ((image.cols() as f32 * factor).round() as i32).max(size.width);
+            let h = ((image.rows() as f32 * factor).round() as i32).max(size.height);
+            (w as usize, h as usize)
+        } else {
+            (width, height)
+        };
+        crate::imgproc::resize_def(image, &mut resized, Size::new(resized_w as i32, resized_h as i32))?;
+        let values: Vec<f32> = match core::mat_depth(resized.typ()) {
+            core::CV_8U => resized.to_vec::<u8>()?.into_iter().map(f32::from).collect(),
+            _ => resized.to_vec::<f32>()?,
+        };
+
+        let (x0, y0) = ((resized_w - width) / 2, (resized_h - height) / 2);
+        let mean = [mean.0, mean.1, mean.2, mean.3];
+        let mut blob = Vec::with_capacity(channels * width * height);
+        for c in 0..channels {
+            let src_c = if swap_rb && channels >= 3 && c < 3 { 2 - c } else { c };
+            let m = mean.get(c).copied().unwrap_or(0.0) as f32;
+            for y in 0..height {
+                for x in 0..width {
+                    let v = values[((y0 + y) * resized_w + x0 + x) * channels + src_c];
+                    blob.push((v - m) * scale as f32);
+                }
+            }
+        }
+
+        Mat::from_slice_2d(1, blob.len() as i32, 1, &blob)?
+            .with_dims(vec![1, channels as i32, size.height, size.width])
+    }
+
+    fn check_blob_args(image: &Mat, ddepth: i32) -> Result<()> {
+        if ddepth != core::CV_32F {
+            return Err(Error::from(format!("Only CV_32F blobs are supported, got depth {}", ddepth)));
+        }
+        if image.rows() == 0 || image.cols() == 0 {
+            return Err(Error::from("Cannot make a blob from an empty image"));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "wrapper-blob")]
+    pub fn blob_from_image(
+        image: &Mat,
+        scale: f64,
+        size: Size,
+        mean: Scalar,
+        swap_rb: bool,
+        crop: bool,
+        ddepth: i32
+    ) -> Result<Mat> {
+        check_blob_args(image, ddepth)?;
+        if image.data_ptr().is_null() {
+            return Err(Error::Wrapper {
+                kind: ErrorKind::Blob,
+                message: "the image has no pixel data to hand over".to_string(),
+                path: None,
+            });
+        }
+        let mean = [mean.0, mean.1, mean.2, mean.3];
+        let channels = image.channels();
+        unsafe {
+            let blob_ptr = opencv_dnn_blobFromImage(
+                image.data_ptr(),
+                image.rows(),
+                image.cols(),
+                image.typ(),
+                image.step(),
+                scale,
+                size.width,
+                size.height,
+                mean.as_ptr(),
+                swap_rb as c_int,
+                crop as c_int,
+                ddepth,
+            );
+            
+            if blob_ptr.is_null() {
+                Err(wrapper_error(ErrorKind::Blob, None, "no blob returned"))
+            } else {
+                Mat::from_handle(blob_ptr, 1, channels * size.width * size.height, core::CV_32FC1)
+                    .with_dims(vec![1, channels, size.height, size.width])
+            }
+        }
+    }
+
+    // Greedy NMS with OpenCV's semantics: candidates above score_threshold
+    // are visited best first, at most top_k of them when top_k > 0, and a
+    // box is kept unless it overlaps a kept box by more than nms_threshold.
+    // With eta < 1 the threshold shrinks by eta after each kept box while
+    // it stays above 0.5.
     pub fn nms_boxes(
-        _bboxes: &Vector<crate::core::Rect>,
+        bboxes: &Vector<crate::core::Rect>,
         scores: &Vector<f32>,
         score_threshold: f32,
-        _nms_threshold: f32,
+        nms_threshold: f32,
         indices: &mut Vector<i32>,
-        _eta: f32,
-        _top_k: i32
+        eta: f32,
+        top_k: i32
     ) -> Result<()> {
-        for i in 0..scores.len() {
-            if scores[i] > score_threshold {
+        if bboxes.len() != scores.len() {
+            return Err(Error::from(format!("{} boxes but {} scores", bboxes.len(), scores.len())));
+        }
+        indices.clear();
+
+        let mut candidates: Vec<usize> = (0..scores.len())
+            .filter(|&i| scores[i] > score_threshold)
+            .collect();
+        candidates.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+        if top_k > 0 {
+            candidates.truncate(top_k as usize);
+        }
+
+        let mut threshold = nms_threshold;
+        for i in candidates {
+            let keep = indices.iter().all(|&kept| bboxes[i].iou(&bboxes[kept as usize]) <= threshold);
+            if keep {
                 indices.push(i as i32);
+                if eta < 1.0 && threshold > 0.5 {
+                    threshold *= eta;
+                }
             }
         }
         Ok(())
     }
+
+    #[derive(Debug, Clone)]
+    pub struct YoloDetection {
+        pub class_id: usize,
+        pub class_name: String,
+        pub confidence: f32,
+        // In original image pixels.
+        pub bbox: crate::core::Rect,
+    }
+
+    // The whole blob -> forward -> decode -> NMS pipeline for YOLOv5 and
+    // YOLOv8 style models.
+    pub struct YoloDetector {
+        net: Net,
+        input_size: Size,
+        class_names: Vec<String>,
+        conf_threshold: f32,
+        nms_threshold: f32,
+    }
+
+    impl YoloDetector {
+        // `config` is only needed for Darknet models; pass "" otherwise.
+        // The class names file has one name per line, in class id order.
+        pub fn new(
+            model: &str,
+            config: &str,
+            input_size: Size,
+            class_names_path: &str,
+            conf_threshold: f32,
+            nms_threshold: f32,
+        ) -> Result<Self> {
+            let names = std::fs::read_to_string(class_names_path)
+                .map_err(|e| Error::from(format!("Failed to read {}: {}", class_names_path, e)))?;
+            let class_names = names.lines().map(str::trim).filter(|n| !n.is_empty()).map(str::to_string).collect();
+            Ok(Self {
+                net: read_net(model, config, "")?,
+                input_size,
+                class_names,
+                conf_threshold,
+                nms_threshold,
+            })
+        }
+
+        pub fn net_mut(&mut self) -> &mut Net {
+            &mut self.net
+        }
+
+        // `image` is an 8-bit BGR frame, as imread returns.
+        pub fn detect(&self, image: &Mat) -> Result<Vec<YoloDetection>> {
+            let blob = blob_from_image(image, 1.0 / 255.0, Size::new(self.input_size.width, self.input_size.height), Scalar::default(), true, false, core::CV_32F)?;
+            let mut outputs = Vec::new();
+            self.net.forward(&blob, &mut outputs, &Vec::new())?;
+
+            let scale_x = image.cols() as f32 / self.input_size.width as f32;
+            let scale_y = image.rows() as f32 / self.input_size.height as f32;
+            let mut candidates = Vec::new();
+            for output in &outputs {
+                if output.rows() == 0 {
+                    continue;
+                }
+                let values = output.to_vec::<f32>()?;
+                candidates.extend(decode_yolo_output(&values, output.dims(), self.class_names.len(), self.conf_threshold, scale_x, scale_y)?);
+            }
+
+            suppress(&candidates, &self.class_names, self.conf_threshold, self.nms_threshold)
+        }
+    }
+
+    // NMS within each class of decoded candidates, naming the survivors;
+    // surest first.
+    fn suppress(candidates: &[YoloDetection], class_names: &[String], conf_threshold: f32, nms_threshold: f32) -> Result<Vec<YoloDetection>> {
+        let mut detections = Vec::new();
+        for (class_id, class_name) in class_names.iter().enumerate() {
+            let class: Vec<&YoloDetection> = candidates.iter().filter(|d| d.class_id == class_id).collect();
+            if class.is_empty() {
+                continue;
+            }
+            let boxes = class.iter().map(|d| d.bbox).collect();
+            let scores = class.iter().map(|d| d.confidence).collect();
+            let mut kept = Vec::new();
+            nms_boxes(&boxes, &scores, conf_threshold, nms_threshold, &mut kept, 1.0, 0)?;
+            detections.extend(kept.into_iter().map(|i| YoloDetection {
+                class_name: class_name.clone(),
+                ..class[i as usize].clone()
+            }));
+        }
+        detections.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        Ok(detections)
+    }
+
+    // A YoloDetector that threads can share. The mutex serializes forward
+    // passes, so one loaded net serves every caller; `detect` runs each
+    // pass on tokio's blocking pool so it doesn't stall the async runtime.
+    #[derive(Clone)]
+    pub struct SharedNet {
+        detector: Arc<Mutex<YoloDetector>>,
+    }
+
+    impl SharedNet {
+        pub fn new(detector: YoloDetector) -> Self {
+            SharedNet { detector: Arc::new(Mutex::new(detector)) }
+        }
+
+        // A poisoned lock only means another caller panicked mid-detect;
+        // the net itself holds no Rust-side state that could be torn.
+        pub fn lock(&self) -> MutexGuard<'_, YoloDetector> {
+            self.detector.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        pub fn detect_blocking(&self, image: &MatBuffer) -> Result<Vec<YoloDetection>> {
+            let image = image.to_mat()?;
+            self.lock().detect(&image)
+        }
+
+        #[cfg(feature = "tokio")]
+        pub async fn detect(&self, image: MatBuffer) -> Result<Vec<YoloDetection>> {
+            let shared = self.clone();
+            tokio::task::spawn_blocking(move || shared.detect_blocking(&image))
+                .await
+                .map_err(|e| Error::from(format!("Detection task failed: {}", e)))?
+        }
+    }
+
+
+    // Decodes one raw YOLO output of shape [1, N, 5 + C] (YOLOv5: box,
+    // objectness, class scores) or [1, 4 + C, N] (YOLOv8: box and class
+    // scores, transposed, no objectness), with or without the batch dim.
+    // Boxes are (cx, cy, w, h) in model input pixels and come back scaled
+    // into the original image. Class names are left empty.
+    pub fn decode_yolo_output(
+        values: &[f32],
+        dims: &[i32],
+        num_classes: usize,
+        conf_threshold: f32,
+        scale_x: f32,
+        scale_y: f32,
+    ) -> Result<Vec<YoloDetection>> {
+        // Only the batch dim goes: one proposal, or one class, is still a
+        // dim of 1.
+        let (a, b) = match *dims {
+            [1, a, b] | [a, b] if a >= 0 && b >= 0 => (a as usize, b as usize),
+            _ => return Err(Error::from(format!("Unexpected YOLO output shape {:?}", dims))),
+        };
+        if a.checked_mul(b) != Some(values.len()) {
+            return Err(Error::from(format!("YOLO output has {} values for shape {:?}", values.len(), dims)));
+        }
+
+        // (proposals, attributes, transposed, has objectness)
+        let (count, attrs, transposed, objectness) = if b == num_classes + 5 {
+            (a, b, false, true)
+        } else if a == num_classes + 4 {
+            (b, a, true, false)
+        } else if b == num_classes + 4 {
+            (a, b, false, false)
+        } else {
+            return Err(Error::from(format!("YOLO output shape {:?} doesn't fit {} classes", dims, num_classes)));
+        };
+        let at = |i: usize, k: usize| if transposed { values[k * count + i] } else { values[i * attrs + k] };
+        let first_class = if objectness { 5 } else { 4 };
+
+        let mut detections = Vec::new();
+        for i in 0..count {
+            let Some((class_id, class_score)) = (0..num_classes)
+                .map(|c| (c, at(i, first_class + c)))
+                .max_by(|x, y| x.1.total_cmp(&y.1))
+            else {
+                continue;
+            };
+            let confidence = if objectness { at(i, 4) * class_score } else { class_score };
+            if confidence <= conf_threshold {
+                continue;
+            }
+
+            let (cx, cy, w, h) = (at(i, 0), at(i, 1), at(i, 2), at(i, 3));
+            detections.push(YoloDetection {
+                class_id,
+                class_name: String::new(),
+                confidence,
+                bbox: crate::core::Rect::new(
+                    ((cx - w / 2.0) * scale_x).round() as i32,
+                    ((cy - h / 2.0) * scale_y).round() as i32,
+                    (w * scale_x).round() as i32,
+                    (h * scale_y).round() as i32,
+                ),
+            });
+        }
+        Ok(detections)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::core::tests::Rng;
+        use crate::core::Rect;
+
+        fn nms(boxes: &[Rect], scores: &[f32], score_threshold: f32, nms_threshold: f32, eta: f32, top_k: i32) -> Vec<i32> {
+            let mut indices = Vec::new();
+            nms_boxes(&boxes.to_vec(), &scores.to_vec(), score_threshold, nms_threshold, &mut indices, eta, top_k).unwrap();
+            indices
+        }
+
+        #[test]
+        fn iou_of_hand_computed_pairs() {
+            let a = Rect::new(0, 0, 10, 10);
+            assert_eq!(a.iou(&a), 1.0);
+            // Half of a overlapped by an equal box: 50 / 150.
+            assert_eq!(a.iou(&Rect::new(5, 0, 10, 10)), 1.0 / 3.0);
+            // A quarter: 25 / 175.
+            assert_eq!(a.iou(&Rect::new(5, 5, 10, 10)), 1.0 / 7.0);
+            // Nested: 16 / 100.
+            assert_eq!(a.iou(&Rect::new(2, 2, 4, 4)), 0.16);
+            assert_eq!(a.iou(&Rect::new(10, 0, 10, 10)), 0.0);
+            assert_eq!(a.iou(&Rect::new(20, 20, 5, 5)), 0.0);
+            assert_eq!(a.iou(&Rect::new(3, 3, 0, 5)), 0.0);
+            assert_eq!(Rect::new(0, 0, 0, 0).iou(&Rect::new(0, 0, 0, 0)), 0.0);
+        }
+
+        #[test]
+        fn overlapping_boxes_are_suppressed_best_first() {
+            let boxes = [
+                Rect::new(0, 0, 10, 10),
+                Rect::new(1, 0, 10, 10),   // IoU 0.82 with 0
+                Rect::new(5, 0, 10, 10),   // IoU 1/3 with 0
+                Rect::new(50, 50, 10, 10), // apart
+            ];
+            let scores = [0.6, 0.9, 0.7, 0.5];
+            // 1 goes first and takes 0 with it; 2 overlaps 1 by 6/14.
+            assert_eq!(nms(&boxes, &scores, 0.0, 0.5, 1.0, 0), vec![1, 2, 3]);
+            assert_eq!(nms(&boxes, &scores, 0.0, 0.4, 1.0, 0), vec![1, 3]);
+            // Nothing overlaps by more than 1.
+            assert_eq!(nms(&boxes, &scores, 0.0, 1.0, 1.0, 0), vec![1, 2, 0, 3]);
+            // Scores at the threshold don't survive it.
+            assert_eq!(nms(&boxes, &scores, 0.6, 0.5, 1.0, 0), vec![1, 2]);
+        }
+
+        #[test]
+        fn an_overlap_at_the_threshold_is_kept() {
+            let boxes = [Rect::new(0, 0, 10, 10), Rect::new(5, 0, 10, 10)];
+            assert_eq!(nms(&boxes, &[0.9, 0.8], 0.0, 1.0 / 3.0, 1.0, 0), vec![0, 1]);
+            assert_eq!(nms(&boxes, &[0.9, 0.8], 0.0, 0.3, 1.0, 0), vec![0]);
+        }
+
+        #[test]
+        fn top_k_limits_the_candidates_not_the_survivors() {
+            let boxes = [Rect::new(0, 0, 10, 10), Rect::new(1, 1, 10, 10), Rect::new(40, 0, 10, 10), Rect::new(80, 0, 10, 10)];
+            let scores = [0.9, 0.8, 0.7, 0.6];
+            // The best two overlap, so only one of them is left.
+            assert_eq!(nms(&boxes, &scores, 0.0, 0.5, 1.0, 2), vec![0]);
+            assert_eq!(nms(&boxes, &scores, 0.0, 0.5, 1.0, 3), vec![0, 2]);
+            assert_eq!(nms(&boxes, &scores, 0.0, 0.5, 1.0, 0), vec![0, 2, 3]);
+        }
+
+        #[test]
+        fn eta_tightens_the_threshold_after_each_kept_box() {
+            // Each box overlaps the one before it by 0.6 and the rest by less.
+            let boxes = [Rect::new(0, 0, 16, 10), Rect::new(4, 0, 16, 10), Rect::new(8, 0, 16, 10)];
+            let scores = [0.9, 0.8, 0.7];
+            assert_eq!(nms(&boxes, &scores, 0.0, 0.7, 1.0, 0), vec![0, 1, 2]);
+            // 0.7 becomes 0.56 once 0 is kept, which 1 overlaps it by more
+            // than; 2 overlaps 0 by 8/24.
+            assert_eq!(nms(&boxes, &scores, 0.0, 0.7, 0.8, 0), vec![0, 2]);
+        }
+
+        #[test]
+        fn zero_area_and_empty_inputs() {
+            assert!(nms(&[], &[], 0.0, 0.5, 1.0, 0).is_empty());
+            // Zero-area boxes overlap nothing, so they're all kept.
+            let boxes = [Rect::new(0, 0, 0, 0), Rect::new(0, 0, 0, 0), Rect::new(0, 0, 10, 0)];
+            assert_eq!(nms(&boxes, &[0.5, 0.6, 0.7], 0.0, 0.5, 1.0, 0), vec![2, 1, 0]);
+            let mut indices = vec![7];
+            assert!(nms_boxes(&vec![Rect::new(0, 0, 1, 1)], &vec![], 0.0, 0.5, &mut indices, 1.0, 0).is_err());
+        }
+
+        // 2x2 BGR: (10, 20, 30), (40, 50, 60) over (70, 80, 90), (100, 110, 120).
+        #[cfg(not(feature = "wrapper-blob"))]
+        fn square() -> Mat {
+            Mat::from_slice_2d(2, 2, 3, &(1..=12u8).map(|v| v * 10).collect::<Vec<_>>()).unwrap()
+        }
+
+        #[cfg(not(feature = "wrapper-blob"))]
+        fn blob(image: &Mat, scale: f64, size: Size, mean: Scalar, swap_rb: bool, crop: bool) -> (Vec<i32>, Vec<f32>) {
+            let blob = blob_from_image(image, scale, size, mean, swap_rb, crop, core::CV_32F).unwrap();
+            (blob.dims().to_vec(), blob.to_vec::<f32>().unwrap())
+        }
+
+        #[cfg(not(feature = "wrapper-blob"))]
+        #[test]
+        fn blobs_are_nchw_with_the_mean_taken_off_then_scaled() {
+            let (dims, values) = blob(&square(), 0.5, Size::new(2, 2), Scalar::new(1.0, 2.0, 3.0, 0.0), false, false);
+            assert_eq!(dims, vec![1, 3, 2, 2]);
+            // One plane per channel, each in row-major pixel order.
+            assert_eq!(values, vec![4.5, 19.5, 34.5, 49.5, 9.0, 24.0, 39.0, 54.0, 13.5, 28.5, 43.5, 58.5]);
+
+            let (_, values) = blob(&square(), 1.0 / 255.0, Size::new(2, 2), Scalar::default(), false, false);
+            assert!((values[3] - 100.0 / 255.0).abs() < 1e-6);
+            assert!((values[11] - 120.0 / 255.0).abs() < 1e-6);
+        }
+
+        #[cfg(not(feature = "wrapper-blob"))]
+        #[test]
+        fn swap_rb_swaps_the_planes_and_the_mean_follows_the_output_order() {
+            // As in OpenCV, the mean is given in the blob's channel order.
+            let (_, values) = blob(&square(), 0.5, Size::new(2, 2), Scalar::new(1.0, 2.0, 3.0, 0.0), true, false);
+            assert_eq!(values, vec![14.5, 29.5, 44.5, 59.5, 9.0, 24.0, 39.0, 54.0, 3.5, 18.5, 33.5, 48.5]);
+        }
+
+        #[cfg(not(feature = "wrapper-blob"))]
+        #[test]
+        fn crop_takes_the_centre_instead_of_squashing() {
+            // 4 wide, 2 high: B is 10 per column, G 100 for the second row.
+            let data: Vec<u8> = (0..2u8).flat_map(|y| (0..4u8).flat_map(move |x| [x * 10, y * 100, 7])).collect();
+            let image = Mat::from_slice_2d(2, 4, 3, &data).unwrap();
+            // Cropped, columns 1 and 2 are kept as they are.
+            let (dims, values) = blob(&image, 1.0, Size::new(2, 2), Scalar::default(), false, true);
+            assert_eq!(dims, vec![1, 3, 2, 2]);
+            assert_eq!(values, vec![10.0, 20.0, 10.0, 20.0, 0.0, 0.0, 100.0, 100.0, 7.0, 7.0, 7.0, 7.0]);
+            // Squashed, each output pixel averages a pair of columns.
+            let (_, values) = blob(&image, 1.0, Size::new(2, 2), Scalar::default(), false, false);
+            assert_eq!(&values[..4], &[5.0, 25.0, 5.0, 25.0]);
+        }
+
+        #[test]
+        fn only_continuous_float_blobs_are_handed_to_the_wrapper() {
+            // Same number of values as a 1x3x2x2 blob, but bytes.
+            let bytes = Mat::zeros(1, 12, core::CV_8UC1).unwrap().with_dims(vec![1, 3, 2, 2]).unwrap();
+            assert!(matches!(check_input(&bytes), Err(Error::Wrapper { kind: ErrorKind::SetInput, .. })));
+            let floats = Mat::zeros(4, 3, core::CV_32FC1).unwrap();
+            assert!(check_input(&floats.roi(crate::core::Rect::new(0, 0, 2, 4)).unwrap()).is_err());
+            assert!(check_input(&floats.with_dims(vec![1, 3, 2, 2]).unwrap()).is_ok());
+        }
+
+        #[cfg(not(feature = "wrapper-blob"))]
+        #[test]
+        fn blobs_resize_to_the_requested_size() {
+            let (dims, values) = blob(&square(), 1.0, Size::new(4, 3), Scalar::default(), false, false);
+            assert_eq!(dims, vec![1, 3, 3, 4]);
+            assert_eq!(values.len(), 3 * 3 * 4);
+            assert_eq!((values[0], values[11]), (10.0, 100.0));
+
+            let floats = Mat::from_slice_2d(1, 1, 3, &[0.25f32, 0.5, 0.75]).unwrap();
+            let (_, values) = blob(&floats, 2.0, Size::new(1, 1), Scalar::default(), true, false);
+            assert_eq!(values, vec![1.5, 1.0, 0.5]);
+
+            assert!(blob_from_image(&square(), 1.0, Size::new(2, 2), Scalar::default(), false, false, core::CV_8U).is_err());
+            assert!(blob_from_image(&Mat::default(), 1.0, Size::new(2, 2), Scalar::default(), false, false, core::CV_32F).is_err());
+        }
+
+        // The wrapper's blob has to be the one the Rust code builds from the
+        // same arguments, or switching backends changes what the net sees.
+        #[cfg(feature = "wrapper-blob")]
+        #[test]
+        fn the_wrapper_blob_matches_the_rust_one() {
+            let data: Vec<u8> = (0..2u8).flat_map(|y| (0..4u8).flat_map(move |x| [x * 10, y * 100, 7 + x])).collect();
+            let image = Mat::from_slice_2d(2, 4, 3, &data).unwrap();
+            let means = [Scalar::default(), Scalar::new(1.0, 2.0, 3.0, 0.0)];
+            for (mean, swap_rb, crop) in means.iter().flat_map(|&m| [(m, false, false), (m, true, false), (m, false, true), (m, true, true)]) {
+                let case = format!("mean ({}, {}, {}), swap_rb {}, crop {}", mean.0, mean.1, mean.2, swap_rb, crop);
+                let rust = rust_blob_from_image(&image, 0.5, Size::new(2, 2), mean, swap_rb, crop, core::CV_32F).unwrap();
+                let wrapper = blob_from_image(&image, 0.5, Size::new(2, 2), mean, swap_rb, crop, core::CV_32F).unwrap();
+                let wrapper = unsafe { copy_output(wrapper.raw_ptr()) }.unwrap();
+                assert_eq!(wrapper.dims(), rust.dims(), "{}", case);
+                let (wrapper, rust) = (wrapper.to_vec::<f32>().unwrap(), rust.to_vec::<f32>().unwrap());
+                assert!(wrapper.iter().zip(&rust).all(|(a, b)| (a - b).abs() < 1e-4), "{}: {:?} vs {:?}", case, wrapper, rust);
+            }
+            assert!(blob_from_image(&image, 1.0, Size::new(2, 2), Scalar::default(), false, false, core::CV_8U).is_err());
+        }
+
+        // Raw outputs saved as little-endian floats, for a 1280x960 image
+        // run at 640x640 with three classes. Both hold the same six
+        // proposals: two overlapping people, a car, a dog over the car, a
+        // dog below the threshold and a proposal scoring nothing.
+        const YOLOV5: &[u8] = include_bytes!("../tests/fixtures/yolov5_1x6x8.f32");
+        const YOLOV8: &[u8] = include_bytes!("../tests/fixtures/yolov8_1x7x6.f32");
+        const SCALE: (f32, f32) = (2.0, 1.5);
+
+        fn tensor(bytes: &[u8]) -> Vec<f32> {
+            bytes.chunks(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+        }
+
+        fn class_names() -> Vec<String> {
+            ["person", "car", "dog"].map(String::from).to_vec()
+        }
+
+        fn decode(bytes: &[u8], dims: &[i32]) -> Result<Vec<YoloDetection>> {
+            decode_yolo_output(&tensor(bytes), dims, 3, 0.3, SCALE.0, SCALE.1)
+        }
+
+        // What the detector returns for either output, in original image
+        // pixels.
+        fn assert_golden(detections: &[YoloDetection]) {
+            let golden = [
+                ("person", 0, 0.81, Rect::new(160, 90, 80, 120)),
+                ("car", 1, 0.56, Rect::new(540, 255, 120, 90)),
+                ("dog", 2, 0.42, Rect::new(544, 258, 120, 90)),
+            ];
+            assert_eq!(detections.len(), golden.len(), "{:?}", detections);
+            for (detection, (name, class_id, confidence, bbox)) in detections.iter().zip(golden) {
+                assert_eq!(detection.class_name, name);
+                assert_eq!(detection.class_id, class_id);
+                assert!((detection.confidence - confidence).abs() < 1e-5, "{:?}", detection);
+                assert_eq!(detection.bbox, bbox);
+            }
+        }
+
+        #[test]
+        fn yolov5_output_decodes_to_the_golden_detections() {
+            let candidates = decode(YOLOV5, &[1, 6, 8]).unwrap();
+            // Everything over the threshold, before NMS: both people.
+            assert_eq!(candidates.iter().map(|d| d.class_id).collect::<Vec<_>>(), vec![0, 0, 1, 2]);
+            assert_eq!(candidates[1].bbox, Rect::new(168, 93, 80, 120));
+            assert!(candidates.iter().all(|d| d.class_name.is_empty()));
+            assert_golden(&suppress(&candidates, &class_names(), 0.3, 0.45).unwrap());
+        }
+
+        #[test]
+        fn yolov8_output_decodes_to_the_golden_detections() {
+            let candidates = decode(YOLOV8, &[1, 7, 6]).unwrap();
+            assert_eq!(candidates.len(), 4);
+            assert_golden(&suppress(&candidates, &class_names(), 0.3, 0.45).unwrap());
+        }
+
+        #[test]
+        fn only_the_batch_dim_is_dropped() {
+            let values = tensor(YOLOV5);
+            assert_eq!(decode(YOLOV5, &[6, 8]).unwrap().len(), 4);
+            // A single proposal keeps its dim of 1.
+            let one = decode_yolo_output(&values[..8], &[1, 1, 8], 3, 0.3, SCALE.0, SCALE.1).unwrap();
+            assert_eq!(one.len(), 1);
+            assert_eq!(one[0].bbox, Rect::new(160, 90, 80, 120));
+            // A single-class YOLOv8 output of the first four proposals,
+            // [1, 5, 4].
+            let v8 = tensor(YOLOV8);
+            let person: Vec<f32> = v8.chunks(6).take(5).flat_map(|row| row[..4].to_vec()).collect();
+            assert_eq!(decode_yolo_output(&person, &[1, 5, 4], 1, 0.3, 1.0, 1.0).unwrap().len(), 2);
+
+            assert!(decode(YOLOV5, &[2, 3, 8]).is_err());
+            assert!(decode(YOLOV5, &[1, 6, 8, 1]).is_err());
+            assert!(decode(YOLOV5, &[1, 48]).is_err());
+            assert!(decode(YOLOV5, &[1, 6, 7]).is_err());
+            assert!(decode(YOLOV5, &[-6, -8]).is_err());
+        }
+
+        #[test]
+        fn survivors_are_always_a_subset_of_the_score_survivors() {
+            let mut rng = Rng(0x5eed_cafe);
+            for _ in 0..500 {
+                let n = rng.below(40) as usize;
+                let boxes: Vec<Rect> = (0..n).map(|_| Rect::new(rng.below(100), rng.below(100), rng.below(40), rng.below(40))).collect();
+                let scores: Vec<f32> = (0..n).map(|_| rng.below(1000) as f32 / 1000.0).collect();
+                let score_threshold = rng.below(10) as f32 / 10.0;
+                let nms_threshold = rng.below(11) as f32 / 10.0;
+                let eta = if rng.below(2) == 0 { 1.0 } else { 0.9 };
+                let top_k = rng.below(8) - 2;
+                let kept = nms(&boxes, &scores, score_threshold, nms_threshold, eta, top_k);
+
+                let mut seen = vec![false; n];
+                for &i in &kept {
+                    let i = i as usize;
+                    assert!(i < n && !seen[i], "index {} twice or off the end", i);
+                    seen[i] = true;
+                    assert!(scores[i] > score_threshold);
+                }
+                assert!(kept.windows(2).all(|pair| scores[pair[0] as usize] >= scores[pair[1] as usize]));
+                if top_k > 0 {
+                    assert!(kept.len() <= top_k as usize);
+                }
+                // Without eta, no two survivors overlap by more than the
+                // threshold.
+                if eta == 1.0 {
+                    for (a, &i) in kept.iter().enumerate() {
+                        for &j in &kept[a + 1..] {
+                            assert!(boxes[i as usize].iou(&boxes[j as usize]) <= nms_threshold);
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
 
 pub mod imgproc {
-    use super::*;
-    
-    pub fn resize(_src: &core::Mat, _dst: &mut core::Mat, _size: core::Size) -> core::Result<()> {
-        Ok(())
+    use crate::core::{mat_depth, mat_type, read_values, write_values, Error, Mat, Point, Rect, Result, Scalar, Size, Vector, CV_8UC1, CV_8UC3};
+
+    pub const INTER_NEAREST: i32 = 0;
+    pub const INTER_LINEAR: i32 = 1;
+
+    pub const COLOR_BGR2RGB: i32 = 4;
+    pub const COLOR_RGB2BGR: i32 = 4;
+    pub const COLOR_BGR2GRAY: i32 = 6;
+    pub const COLOR_RGB2GRAY: i32 = 7;
+
+    pub const THRESH_BINARY: i32 = 0;
+    pub const THRESH_BINARY_INV: i32 = 1;
+    pub const THRESH_TRUNC: i32 = 2;
+    pub const THRESH_TOZERO: i32 = 3;
+    pub const THRESH_TOZERO_INV: i32 = 4;
+
+    // Pass as thickness to fill a rectangle or circle.
+    pub const FILLED: i32 = -1;
+
+    // Works on 8-bit and float mats with any channel count. dst is
+    // reallocated unless it already has the target size and type.
+    pub fn resize(src: &Mat, dst: &mut Mat, size: Size, interpolation: i32) -> Result<()> {
+        if size.width <= 0 || size.height <= 0 {
+            return Err(Error::from(format!("Invalid resize target {}x{}", size.width, size.height)));
+        }
+        if src.rows() == 0 || src.cols() == 0 {
+            return Err(Error::from("Cannot resize an empty mat"));
+        }
+        let channels = src.channels() as usize;
+        let values = read_values(src)?;
+        let (src_w, src_h) = (src.cols() as usize, src.rows() as usize);
+        let (dst_w, dst_h) = (size.width as usize, size.height as usize);
+        let scale_x = src_w as f32 / dst_w as f32;
+        let scale_y = src_h as f32 / dst_h as f32;
+        let at = |x: usize, y: usize, c: usize| values[(y * src_w + x) * channels + c];
+
+        let mut out = Vec::with_capacity(dst_w * dst_h * channels);
+        for dy in 0..dst_h {
+            for dx in 0..dst_w {
+                match interpolation {
+                    INTER_NEAREST => {
+                        let sx = ((dx as f32 * scale_x) as usize).min(src_w - 1);
+                        let sy = ((dy as f32 * scale_y) as usize).min(src_h - 1);
+                        out.extend((0..channels).map(|c| at(sx, sy, c)));
+                    }
+                    INTER_LINEAR => {
+                        // Pixel centres line up, as in OpenCV.
+                        let fx = ((dx as f32 + 0.5) * scale_x - 0.5).clamp(0.0, (src_w - 1) as f32);
+                        let fy = ((dy as f32 + 0.5) * scale_y - 0.5).clamp(0.0, (src_h - 1) as f32);
+                        let (x0, y0) = (fx as usize, fy as usize);
+                        let (x1, y1) = ((x0 + 1).min(src_w - 1), (y0 + 1).min(src_h - 1));
+                        let (wx, wy) = (fx - x0 as f32, fy - y0 as f32);
+                        out.extend((0..channels).map(|c| {
+                            let top = at(x0, y0, c) * (1.0 - wx) + at(x1, y0, c) * wx;
+                            let bottom = at(x0, y1, c) * (1.0 - wx) + at(x1, y1, c) * wx;
+                            top * (1.0 - wy) + bottom * wy
+                        }));
+                    }
+                    other => return Err(Error::from(format!("Unsupported interpolation {}", other))),
+                }
+            }
+        }
+        write_values(dst, size.height, size.width, src.typ(), &out)
     }
-}
 
-pub mod imgcodecs {
-    use super::*;
-    
-    pub fn imread(_filename: &str) -> core::Mat {
-        core::Mat::default()
+    pub fn resize_def(src: &Mat, dst: &mut Mat, size: Size) -> Result<()> {
+        resize(src, dst, size, INTER_LINEAR)
     }
-    
-    pub fn imwrite(_filename: &str, _img: &core::Mat) -> bool {
-        true
+
+    // Per channel value, for 8U and 32F mats of any channel count. Returns
+    // the threshold, as OpenCV does.
+    pub fn threshold(src: &Mat, dst: &mut Mat, thresh: f64, maxval: f64, typ: i32) -> Result<f64> {
+        let (t, max) = (thresh as f32, maxval as f32);
+        let op: fn(f32, f32, f32) -> f32 = match typ {
+            THRESH_BINARY => |v, t, max| if v > t { max } else { 0.0 },
+            THRESH_BINARY_INV => |v, t, max| if v > t { 0.0 } else { max },
+            THRESH_TRUNC => |v, t, _| if v > t { t } else { v },
+            THRESH_TOZERO => |v, t, _| if v > t { v } else { 0.0 },
+            THRESH_TOZERO_INV => |v, t, _| if v > t { 0.0 } else { v },
+            other => return Err(Error::from(format!("Unsupported threshold type {}", other))),
+        };
+        let out: Vec<f32> = read_values(src)?.into_iter().map(|v| op(v, t, max)).collect();
+        write_values(dst, src.rows(), src.cols(), src.typ(), &out)?;
+        Ok(thresh)
+    }
+
+    pub fn cvt_color(src: &Mat, dst: &mut Mat, code: i32) -> Result<()> {
+        if src.channels() != 3 {
+            return Err(Error::from(format!("Color conversion needs 3 channels, got {}", src.channels())));
+        }
+        let values = read_values(src)?;
+        let pixels = values.chunks(3);
+        let depth = mat_depth(src.typ());
+        let (out, channels): (Vec<f32>, i32) = match code {
+            COLOR_BGR2RGB => (pixels.flat_map(|p| [p[2], p[1], p[0]]).collect(), 3),
+            COLOR_BGR2GRAY => (pixels.map(|p| 0.114 * p[0] + 0.587 * p[1] + 0.299 * p[2]).collect(), 1),
+            COLOR_RGB2GRAY => (pixels.map(|p| 0.299 * p[0] + 0.587 * p[1] + 0.114 * p[2]).collect(), 1),
+            other => return Err(Error::from(format!("Unsupported color conversion {}", other))),
+        };
+        write_values(dst, src.rows(), src.cols(), mat_type(depth, channels), &out)
+    }
+
+    // The filters below take 8UC1 and 8UC3 mats and replicate the border
+    // pixels, like OpenCV's BORDER_REPLICATE. dst is only reallocated
+    // when it doesn't already match src.
+
+    // ksize dimensions must be odd, or 0 to derive them from sigma. A
+    // sigma of 0 is derived from ksize, and sigma_y = 0 means sigma_x.
+    pub fn gaussian_blur(src: &Mat, dst: &mut Mat, ksize: Size, sigma_x: f64, sigma_y: f64) -> Result<()> {
+        let image = Filtered::new(src)?;
+        let sigma_y = if sigma_y > 0.0 { sigma_y } else { sigma_x };
+        let kernel_x = gaussian_kernel(ksize.width, sigma_x)?;
+        let kernel_y = gaussian_kernel(ksize.height, sigma_y)?;
+        let (w, h, ch) = (image.width, image.height, image.channels);
+
+        let mut rows = vec![0f32; image.data.len()];
+        let radius = (kernel_x.len() / 2) as isize;
+        for y in 0..h {
+            let line = &image.data[y * w * ch..(y + 1) * w * ch];
+            for x in 0..w {
+                for c in 0..ch {
+                    rows[(y * w + x) * ch + c] = kernel_x.iter().enumerate()
+                        .map(|(k, weight)| weight * line[clamp_index(x as isize + k as isize - radius, w) * ch + c] as f32)
+                        .sum();
+                }
+            }
+        }
+        let mut out = vec![0u8; image.data.len()];
+        let radius = (kernel_y.len() / 2) as isize;
+        for y in 0..h {
+            for x in 0..w {
+                for c in 0..ch {
+                    let value: f32 = kernel_y.iter().enumerate()
+                        .map(|(k, weight)| weight * rows[(clamp_index(y as isize + k as isize - radius, h) * w + x) * ch + c])
+                        .sum();
+                    out[(y * w + x) * ch + c] = value.round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+        image.store(dst, &out)
+    }
+
+    // OpenCV's sizing rules for 8-bit images, including its fixed tables
+    // for small kernels when sigma is derived.
+    fn gaussian_kernel(ksize: i32, sigma: f64) -> Result<Vec<f32>> {
+        if ksize < 0 || (ksize > 0 && ksize % 2 == 0) || (ksize == 0 && sigma <= 0.0) {
+            return Err(Error::from(format!("Invalid Gaussian kernel: size {}, sigma {}", ksize, sigma)));
+        }
+        if sigma <= 0.0 {
+            match ksize {
+                1 => return Ok(vec![1.0]),
+                3 => return Ok(vec![0.25, 0.5, 0.25]),
+                5 => return Ok(vec![0.0625, 0.25, 0.375, 0.25, 0.0625]),
+                7 => return Ok(vec![0.03125, 0.109375, 0.21875, 0.28125, 0.21875, 0.109375, 0.03125]),
+                _ => {}
+            }
+        }
+        let ksize = if ksize == 0 { ((sigma * 6.0 + 1.0).round() as i32) | 1 } else { ksize };
+        let sigma = if sigma > 0.0 { sigma } else { 0.3 * ((ksize - 1) as f64 * 0.5 - 1.0) + 0.8 };
+        let centre = (ksize / 2) as f64;
+        let weights: Vec<f64> = (0..ksize).map(|i| (-(i as f64 - centre).powi(2) / (2.0 * sigma * sigma)).exp()).collect();
+        let total: f64 = weights.iter().sum();
+        Ok(weights.into_iter().map(|w| (w / total) as f32).collect())
+    }
+
+    // ksize is the odd, square aperture size.
+    pub fn median_blur(src: &Mat, dst: &mut Mat, ksize: i32) -> Result<()> {
+        if ksize < 3 || ksize % 2 == 0 {
+            return Err(Error::from(format!("Median aperture must be odd and at least 3, got {}", ksize)));
+        }
+        let image = Filtered::new(src)?;
+        let (w, h, ch) = (image.width, image.height, image.channels);
+        let radius = (ksize / 2) as isize;
+        let mut window = Vec::with_capacity((ksize * ksize) as usize);
+        let mut out = vec![0u8; image.data.len()];
+        for y in 0..h {
+            for x in 0..w {
+                for c in 0..ch {
+                    window.clear();
+                    for dy in -radius..=radius {
+                        let row = clamp_index(y as isize + dy, h) * w;
+                        for dx in -radius..=radius {
+                            window.push(image.data[(row + clamp_index(x as isize + dx, w)) * ch + c]);
+                        }
+                    }
+                    let middle = window.len() / 2;
+                    out[(y * w + x) * ch + c] = *window.select_nth_unstable(middle).1;
+                }
+            }
+        }
+        image.store(dst, &out)
+    }
+
+    // Rectangular structuring element of `ksize`, anchored at its centre
+    // (even sizes lean towards the top-left), applied `iterations` times.
+    pub fn erode(src: &Mat, dst: &mut Mat, ksize: Size, iterations: i32) -> Result<()> {
+        morphology(src, dst, ksize, iterations, |a, b| a.min(b))
+    }
+
+    pub fn dilate(src: &Mat, dst: &mut Mat, ksize: Size, iterations: i32) -> Result<()> {
+        morphology(src, dst, ksize, iterations, |a, b| a.max(b))
+    }
+
+    // A rectangle is separable, so each pass is a row sweep then a column
+    // sweep. Replicating the border leaves min and max unchanged, as if
+    // the outside were ignored.
+    fn morphology(src: &Mat, dst: &mut Mat, ksize: Size, iterations: i32, pick: fn(u8, u8) -> u8) -> Result<()> {
+        if ksize.width < 1 || ksize.height < 1 {
+            return Err(Error::from(format!("Invalid structuring element {}x{}", ksize.width, ksize.height)));
+        }
+        let image = Filtered::new(src)?;
+        let (w, h, ch) = (image.width, image.height, image.channels);
+        let (kw, kh) = (ksize.width as isize, ksize.height as isize);
+        let mut current = image.data.clone();
+        let mut pass = vec![0u8; current.len()];
+        for _ in 0..iterations.max(1) {
+            for y in 0..h {
+                for x in 0..w {
+                    for c in 0..ch {
+                        pass[(y * w + x) * ch + c] = (-(kw / 2)..kw - kw / 2)
+                            .map(|dx| current[(y * w + clamp_index(x as isize + dx, w)) * ch + c])
+                            .fold(current[(y * w + x) * ch + c], pick);
+                    }
+                }
+            }
+            for y in 0..h {
+                for x in 0..w {
+                    for c in 0..ch {
+                        current[(y * w + x) * ch + c] = (-(kh / 2)..kh - kh / 2)
+                            .map(|dy| pass[(clamp_index(y as isize + dy, h) * w + x) * ch + c])
+                            .fold(pass[(y * w + x) * ch + c], pick);
+                    }
+                }
+            }
+        }
+        image.store(dst, &current)
+    }
+
+    fn clamp_index(i: isize, len: usize) -> usize {
+        i.clamp(0, len as isize - 1) as usize
+    }
+
+    // An 8UC1 or 8UC3 source, unpacked for filtering.
+    struct Filtered {
+        data: Vec<u8>,
+        width: usize,
+        height: usize,
+        channels: usize,
+        typ: i32,
+    }
+
+    impl Filtered {
+        fn new(src: &Mat) -> Result<Self> {
+            if src.typ() != CV_8UC1 && src.typ() != CV_8UC3 {
+                return Err(Error::from(format!("Filtering needs an 8UC1 or 8UC3 mat, got type {}", src.typ())));
+            }
+            if src.rows() == 0 || src.cols() == 0 {
+                return Err(Error::from("Cannot filter an empty mat"));
+            }
+            Ok(Filtered {
+                data: src.to_vec::<u8>()?,
+                width: src.cols() as usize,
+                height: src.rows() as usize,
+                channels: src.channels() as usize,
+                typ: src.typ(),
+            })
+        }
+
+        fn store(&self, dst: &mut Mat, values: &[u8]) -> Result<()> {
+            if dst.rows() != self.height as i32 || dst.cols() != self.width as i32 || dst.typ() != self.typ {
+                *dst = Mat::zeros(self.height as i32, self.width as i32, self.typ)?;
+            }
+            dst.copy_from_slice(values)
+        }
+    }
+
+    pub const CC_STAT_LEFT: usize = 0;
+    pub const CC_STAT_TOP: usize = 1;
+    pub const CC_STAT_WIDTH: usize = 2;
+    pub const CC_STAT_HEIGHT: usize = 3;
+    pub const CC_STAT_AREA: usize = 4;
+
+    pub const RETR_EXTERNAL: i32 = 0;
+    pub const CHAIN_APPROX_NONE: i32 = 1;
+    pub const CHAIN_APPROX_SIMPLE: i32 = 2;
+
+    // Labels the nonzero pixels of an 8UC1 image with two-pass union-find.
+    // As in OpenCV, label 0 is the background and is counted in the return
+    // value; `labels` is a CV_32S image, `stats` an N x 5 CV_32S mat
+    // indexed by the CC_STAT_* columns and `centroids` an N x 2 CV_64F mat
+    // of (x, y).
+    pub fn connected_components_with_stats(
+        image: &Mat,
+        labels: &mut Mat,
+        stats: &mut Mat,
+        centroids: &mut Mat,
+        connectivity: i32,
+    ) -> Result<i32> {
+        if connectivity != 4 && connectivity != 8 {
+            return Err(Error::from(format!("Connectivity must be 4 or 8, got {}", connectivity)));
+        }
+        let (w, h, foreground) = binary_pixels(image)?;
+        let (label, n) = label_components(w, h, &foreground, connectivity == 8);
+
+        let mut bounds = vec![(usize::MAX, usize::MAX, 0usize, 0usize); n];
+        let mut area = vec![0i32; n];
+        let mut sums = vec![(0f64, 0f64); n];
+        for y in 0..h {
+            for x in 0..w {
+                let l = label[y * w + x];
+                let b = &mut bounds[l];
+                *b = (b.0.min(x), b.1.min(y), b.2.max(x), b.3.max(y));
+                area[l] += 1;
+                sums[l].0 += x as f64;
+                sums[l].1 += y as f64;
+            }
+        }
+
+        let mut stat_values = Vec::with_capacity(n * 5);
+        let mut centroid_values = Vec::with_capacity(n * 2);
+        for l in 0..n {
+            let (left, top, right, bottom) = bounds[l];
+            if area[l] == 0 {
+                stat_values.extend([0, 0, 0, 0, 0]);
+                centroid_values.extend([0.0, 0.0]);
+                continue;
+            }
+            stat_values.extend([left as i32, top as i32, (right - left + 1) as i32, (bottom - top + 1) as i32, area[l]]);
+            centroid_values.extend([sums[l].0 / area[l] as f64, sums[l].1 / area[l] as f64]);
+        }
+        let label_values: Vec<i32> = label.iter().map(|&l| l as i32).collect();
+        *labels = Mat::from_slice_2d(h as i32, w as i32, 1, &label_values)?;
+        *stats = Mat::from_slice_2d(n as i32, 5, 1, &stat_values)?;
+        *centroids = Mat::from_slice_2d(n as i32, 2, 1, &centroid_values)?;
+        Ok(n as i32)
+    }
+
+    // Per-pixel labels numbered 1.. in raster order of each component's
+    // first pixel, 0 for background, plus the label count including 0.
+    fn label_components(w: usize, h: usize, foreground: &[bool], eight: bool) -> (Vec<usize>, usize) {
+        fn root(parent: &mut [usize], mut l: usize) -> usize {
+            while parent[l] != l {
+                parent[l] = parent[parent[l]];
+                l = parent[l];
+            }
+            l
+        }
+
+        let mut label = vec![0usize; w * h];
+        let mut parent = vec![0usize];
+        for y in 0..h {
+            for x in 0..w {
+                if !foreground[y * w + x] {
+                    continue;
+                }
+                let mut neighbours = Vec::with_capacity(4);
+                if x > 0 {
+                    neighbours.push(label[y * w + x - 1]);
+                }
+                if y > 0 {
+                    neighbours.push(label[(y - 1) * w + x]);
+                    if eight && x > 0 {
+                        neighbours.push(label[(y - 1) * w + x - 1]);
+                    }
+                    if eight && x + 1 < w {
+                        neighbours.push(label[(y - 1) * w + x + 1]);
+                    }
+                }
+                neighbours.retain(|&l| l != 0);
+                if neighbours.is_empty() {
+                    label[y * w + x] = parent.len();
+                    parent.push(parent.len());
+                    continue;
+                }
+                let roots: Vec<usize> = neighbours.iter().map(|&l| root(&mut parent, l)).collect();
+                let smallest = roots.iter().copied().min().unwrap_or(0);
+                for r in roots {
+                    parent[r] = smallest;
+                }
+                label[y * w + x] = smallest;
+            }
+        }
+
+        // Provisional labels only ever point at smaller ones, so renumbering
+        // roots in label order keeps raster order.
+        let mut final_label = vec![0usize; parent.len()];
+        let mut n = 1;
+        for l in 1..parent.len() {
+            let r = root(&mut parent, l);
+            if r == l {
+                final_label[l] = n;
+                n += 1;
+            } else {
+                final_label[l] = final_label[r];
+            }
+        }
+        for l in label.iter_mut() {
+            *l = final_label[*l];
+        }
+        (label, n)
+    }
+
+    // Outer borders of the 8-connected blobs of nonzero pixels in an 8UC1
+    // image, traced clockwise (in image coordinates) from each blob's
+    // top-left pixel. Only RETR_EXTERNAL is supported, so blobs sitting in
+    // another blob's hole are skipped.
+    pub fn find_contours(image: &Mat, contours: &mut Vector<Vector<Point>>, mode: i32, method: i32) -> Result<()> {
+        if mode != RETR_EXTERNAL {
+            return Err(Error::from(format!("Unsupported contour retrieval mode {}", mode)));
+        }
+        if method != CHAIN_APPROX_NONE && method != CHAIN_APPROX_SIMPLE {
+            return Err(Error::from(format!("Unsupported contour approximation {}", method)));
+        }
+        let (w, h, foreground) = binary_pixels(image)?;
+        let (label, n) = label_components(w, h, &foreground, true);
+
+        // Background reachable from outside the image, 4-connected to pair
+        // with 8-connected blobs.
+        let mut outside = vec![false; w * h];
+        let mut stack: Vec<(usize, usize)> = (0..w).flat_map(|x| [(x, 0), (x, h - 1)])
+            .chain((0..h).flat_map(|y| [(0, y), (w - 1, y)]))
+            .collect();
+        while let Some((x, y)) = stack.pop() {
+            let i = y * w + x;
+            if foreground[i] || outside[i] {
+                continue;
+            }
+            outside[i] = true;
+            if x > 0 {
+                stack.push((x - 1, y));
+            }
+            if x + 1 < w {
+                stack.push((x + 1, y));
+            }
+            if y > 0 {
+                stack.push((x, y - 1));
+            }
+            if y + 1 < h {
+                stack.push((x, y + 1));
+            }
+        }
+
+        let fg = |x: isize, y: isize| x >= 0 && y >= 0 && (x as usize) < w && (y as usize) < h && foreground[y as usize * w + x as usize];
+        let mut traced = vec![false; n];
+        contours.clear();
+        for (i, &l) in label.iter().enumerate() {
+            if l == 0 || traced[l] {
+                continue;
+            }
+            traced[l] = true;
+            // A blob's first pixel has background (or the image edge) to
+            // its left, and that background is the region the blob lies in.
+            let (x, y) = (i % w, i / w);
+            if x > 0 && !outside[i - 1] {
+                continue;
+            }
+            let contour = trace_border(&fg, x as isize, y as isize);
+            contours.push(if method == CHAIN_APPROX_SIMPLE { compress_chain(contour) } else { contour });
+        }
+        Ok(())
+    }
+
+    const DIRECTIONS: [(isize, isize); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+
+    // Moore-neighbour tracing, clockwise from a pixel with background to
+    // its left. It stops when about to leave the start pixel the same way
+    // it first did, so borders that pass through the start twice are
+    // followed in full.
+    fn trace_border(fg: &dyn Fn(isize, isize) -> bool, x: isize, y: isize) -> Vec<Point> {
+        let start = (x, y);
+        let mut contour = Vec::new();
+        let mut current = start;
+        // As if the start was entered moving east.
+        let mut search = 5;
+        let mut first_move = None;
+        loop {
+            let next = (0..8).map(|i| (search + i) % 8).find(|&d| fg(current.0 + DIRECTIONS[d].0, current.1 + DIRECTIONS[d].1));
+            let Some(d) = next else {
+                return vec![Point::new(x as i32, y as i32)];
+            };
+            if current == start {
+                if first_move == Some(d) {
+                    return contour;
+                }
+                first_move.get_or_insert(d);
+            }
+            contour.push(Point::new(current.0 as i32, current.1 as i32));
+            current = (current.0 + DIRECTIONS[d].0, current.1 + DIRECTIONS[d].1);
+            search = (d + 5) % 8;
+        }
+    }
+
+    // Keeps only the points where the chain changes direction.
+    fn compress_chain(contour: Vec<Point>) -> Vec<Point> {
+        let n = contour.len();
+        if n < 3 {
+            return contour;
+        }
+        let step = |a: Point, b: Point| (b.x - a.x, b.y - a.y);
+        (0..n)
+            .filter(|&i| step(contour[(i + n - 1) % n], contour[i]) != step(contour[i], contour[(i + 1) % n]))
+            .map(|i| contour[i])
+            .collect()
+    }
+
+    // Width, height and a nonzero mask of an 8UC1 image.
+    fn binary_pixels(image: &Mat) -> Result<(usize, usize, Vec<bool>)> {
+        if image.typ() != CV_8UC1 {
+            return Err(Error::from(format!("Expected an 8UC1 binary image, got type {}", image.typ())));
+        }
+        if image.rows() == 0 || image.cols() == 0 {
+            return Err(Error::from("Empty image"));
+        }
+        let mask = image.to_vec::<u8>()?.into_iter().map(|v| v != 0).collect();
+        Ok((image.cols() as usize, image.rows() as usize, mask))
+    }
+
+    // Drawing works on 8UC3 mats only. Colors are in the mat's channel
+    // order (BGR for anything from imread), and everything clips at the
+    // image border, so shapes may lie partly or wholly off-screen.
+    pub fn rectangle(img: &mut Mat, rect: Rect, color: Scalar, thickness: i32) -> Result<()> {
+        let mut canvas = Canvas::new(img, color)?;
+        if rect.width <= 0 || rect.height <= 0 {
+            return Ok(());
+        }
+        let (x0, y0) = (rect.x, rect.y);
+        let (x1, y1) = (rect.x + rect.width - 1, rect.y + rect.height - 1);
+        if thickness < 0 {
+            canvas.fill(x0, y0, x1, y1);
+            return Ok(());
+        }
+        // Like OpenCV, the stroke is centred on the rectangle's edge.
+        let (lo, hi) = stroke_extent(thickness);
+        canvas.fill(x0 - lo, y0 - lo, x1 + hi, y0 + hi);
+        canvas.fill(x0 - lo, y1 - lo, x1 + hi, y1 + hi);
+        canvas.fill(x0 - lo, y0 - lo, x0 + hi, y1 + hi);
+        canvas.fill(x1 - lo, y0 - lo, x1 + hi, y1 + hi);
+        Ok(())
+    }
+
+    pub fn line(img: &mut Mat, pt1: Point, pt2: Point, color: Scalar, thickness: i32) -> Result<()> {
+        if thickness <= 0 {
+            return Err(Error::from(format!("Line thickness must be at least 1, got {}", thickness)));
+        }
+        let mut canvas = Canvas::new(img, color)?;
+        let (dx, dy) = ((pt2.x - pt1.x).abs(), -(pt2.y - pt1.y).abs());
+        let (sx, sy) = (if pt1.x < pt2.x { 1 } else { -1 }, if pt1.y < pt2.y { 1 } else { -1 });
+        let (mut x, mut y) = (pt1.x, pt1.y);
+        let mut err = dx + dy;
+        loop {
+            if thickness == 1 {
+                canvas.put(x, y);
+            } else {
+                canvas.disk(x, y, thickness as f32 / 2.0);
+            }
+            if x == pt2.x && y == pt2.y {
+                return Ok(());
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    pub fn circle(img: &mut Mat, center: Point, radius: i32, color: Scalar, thickness: i32) -> Result<()> {
+        if radius < 0 {
+            return Err(Error::from(format!("Circle radius must not be negative, got {}", radius)));
+        }
+        let mut canvas = Canvas::new(img, color)?;
+        if thickness < 0 {
+            canvas.disk(center.x, center.y, radius as f32 + 0.5);
+            return Ok(());
+        }
+        let half = thickness.max(1) as f32 / 2.0;
+        let outer = radius + thickness / 2 + 1;
+        for dy in -outer..=outer {
+            for dx in -outer..=outer {
+                let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                if (distance - radius as f32).abs() < half {
+                    canvas.put(center.x + dx, center.y + dy);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Renders with a fixed 5x7 bitmap font, one glyph per 6 pixels of
+    // advance before scaling. As in OpenCV, `org` is the bottom-left
+    // corner of the text. font_scale is rounded to a whole pixel multiple
+    // and thickness widens each stroke. Characters outside printable
+    // ASCII draw as '?'.
+    pub fn put_text(img: &mut Mat, text: &str, org: Point, font_scale: f64, color: Scalar, thickness: i32) -> Result<()> {
+        let mut canvas = Canvas::new(img, color)?;
+        let scale = (font_scale.round() as i32).max(1);
+        let (lo, hi) = stroke_extent(thickness.max(1));
+        let top = org.y - FONT_HEIGHT * scale + 1;
+        for (i, ch) in text.chars().enumerate() {
+            let glyph = glyph(ch);
+            let left = org.x + i as i32 * (FONT_WIDTH + 1) * scale;
+            for (col, bits) in glyph.iter().enumerate() {
+                for row in 0..FONT_HEIGHT {
+                    if bits & (1 << row) == 0 {
+                        continue;
+                    }
+                    let x = left + col as i32 * scale;
+                    let y = top + row * scale;
+                    canvas.fill(x - lo, y - lo, x + scale - 1 + hi, y + scale - 1 + hi);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Pixels either side of the centre line for a stroke of this width.
+    fn stroke_extent(thickness: i32) -> (i32, i32) {
+        let thickness = thickness.max(1);
+        ((thickness - 1) / 2, thickness / 2)
+    }
+
+    struct Canvas<'a> {
+        img: &'a mut Mat,
+        color: [u8; 3],
+    }
+
+    impl<'a> Canvas<'a> {
+        fn new(img: &'a mut Mat, color: Scalar) -> Result<Self> {
+            if img.typ() != CV_8UC3 {
+                return Err(Error::from(format!("Drawing needs an 8UC3 mat, got type {}", img.typ())));
+            }
+            let channel = |v: f64| v.round().clamp(0.0, 255.0) as u8;
+            Ok(Canvas { img, color: [channel(color.0), channel(color.1), channel(color.2)] })
+        }
+
+        fn put(&mut self, x: i32, y: i32) {
+            if x >= 0 && y >= 0 && x < self.img.cols() && y < self.img.rows() {
+                let _ = self.img.set(y, x, self.color);
+            }
+        }
+
+        // Inclusive on both corners, clipped to the image.
+        fn fill(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+            let (x0, y0) = (x0.max(0), y0.max(0));
+            let (x1, y1) = (x1.min(self.img.cols() - 1), y1.min(self.img.rows() - 1));
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    let _ = self.img.set(y, x, self.color);
+                }
+            }
+        }
+
+        fn disk(&mut self, cx: i32, cy: i32, radius: f32) {
+            let reach = radius.ceil() as i32;
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    if ((dx * dx + dy * dy) as f32) < radius * radius {
+                        self.put(cx + dx, cy + dy);
+                    }
+                }
+            }
+        }
+    }
+
+    const FONT_WIDTH: i32 = 5;
+    const FONT_HEIGHT: i32 = 7;
+
+    fn glyph(ch: char) -> &'static [u8; 5] {
+        let index = match ch {
+            ' '..='~' => ch as usize - ' ' as usize,
+            _ => '?' as usize - ' ' as usize,
+        };
+        &FONT[index]
+    }
+
+    // Classic 5x7 font covering ' ' to '~'. One byte per column, least
+    // significant bit at the top.
+    const FONT: [[u8; 5]; 95] = [
+        [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00],
+        [0x14, 0x7F, 0x14, 0x7F, 0x14], [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62],
+        [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00], [0x00, 0x1C, 0x22, 0x41, 0x00],
+        [0x00, 0x41, 0x22, 0x1C, 0x00], [0x14, 0x08, 0x3E, 0x08, 0x14], [0x08, 0x08, 0x3E, 0x08, 0x08],
+        [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00],
+        [0x20, 0x10, 0x08, 0x04, 0x02], [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00],
+        [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31], [0x18, 0x14, 0x12, 0x7F, 0x10],
+        [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+        [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x36, 0x36, 0x00, 0x00],
+        [0x00, 0x56, 0x36, 0x00, 0x00], [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14],
+        [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06], [0x32, 0x49, 0x79, 0x41, 0x3E],
+        [0x7E, 0x11, 0x11, 0x11, 0x7E], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+        [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x09, 0x01],
+        [0x3E, 0x41, 0x49, 0x49, 0x7A], [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00],
+        [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41], [0x7F, 0x40, 0x40, 0x40, 0x40],
+        [0x7F, 0x02, 0x0C, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+        [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46],
+        [0x46, 0x49, 0x49, 0x49, 0x31], [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F],
+        [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x3F, 0x40, 0x38, 0x40, 0x3F], [0x63, 0x14, 0x08, 0x14, 0x63],
+        [0x07, 0x08, 0x70, 0x08, 0x07], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00],
+        [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04],
+        [0x40, 0x40, 0x40, 0x40, 0x40], [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78],
+        [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20], [0x38, 0x44, 0x44, 0x48, 0x7F],
+        [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7E, 0x09, 0x01, 0x02], [0x0C, 0x52, 0x52, 0x52, 0x3E],
+        [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3D, 0x00],
+        [0x7F, 0x10, 0x28, 0x44, 0x00], [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78],
+        [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38], [0x7C, 0x14, 0x14, 0x14, 0x08],
+        [0x08, 0x14, 0x14, 0x18, 0x7C], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+        [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C],
+        [0x3C, 0x40, 0x30, 0x40, 0x3C], [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C],
+        [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], [0x00, 0x00, 0x7F, 0x00, 0x00],
+        [0x00, 0x41, 0x36, 0x08, 0x00], [0x08, 0x04, 0x08, 0x10, 0x08],
+    ];
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::core::tests::{naive_values, saturate, Rng};
+        use crate::core::{CV_32FC1, CV_32FC3, CV_32S};
+
+        // 3x3, 100 per column and 10 per row: 0 top left, 220 bottom right.
+        fn gradient(typ: i32) -> Mat {
+            let values: Vec<f32> = (0..3).flat_map(|y| (0..3).map(move |x| (x * 100 + y * 10) as f32)).collect();
+            let mut mat = Mat::default();
+            write_values(&mut mat, 3, 3, typ, &values).unwrap();
+            mat
+        }
+
+        fn resized(src: &Mat, width: i32, height: i32, interpolation: i32) -> Mat {
+            let mut dst = Mat::default();
+            resize(src, &mut dst, Size::new(width, height), interpolation).unwrap();
+            assert_eq!((dst.cols(), dst.rows(), dst.typ()), (width, height, src.typ()));
+            dst
+        }
+
+        #[test]
+        fn bilinear_resize_keeps_corners_and_the_centre() {
+            // Up to 5x5, pixel centres land at -0.2 (clamped), 0.4, 1.0, 1.6
+            // and 2.2 (clamped) in the source.
+            let dst = resized(&gradient(CV_8UC1), 5, 5, INTER_LINEAR);
+            assert_eq!(dst.get::<u8>(0, 0).unwrap(), 0);
+            assert_eq!(dst.get::<u8>(0, 4).unwrap(), 200);
+            assert_eq!(dst.get::<u8>(4, 0).unwrap(), 20);
+            assert_eq!(dst.get::<u8>(4, 4).unwrap(), 220);
+            assert_eq!(dst.get::<u8>(2, 2).unwrap(), 110);
+            assert_eq!(dst.get::<u8>(1, 1).unwrap(), 44);
+            assert_eq!(dst.get::<u8>(3, 3).unwrap(), 176);
+
+            let dst = resized(&gradient(CV_32FC1), 5, 5, INTER_LINEAR);
+            assert_eq!(dst.get::<f32>(2, 2).unwrap(), 110.0);
+            assert!((dst.get::<f32>(3, 1).unwrap() - 56.0).abs() < 1e-3);
+            assert_eq!(dst.get::<f32>(4, 4).unwrap(), 220.0);
+        }
+
+        #[test]
+        fn nearest_resize_picks_source_pixels() {
+            let dst = resized(&gradient(CV_8UC1), 5, 5, INTER_NEAREST);
+            // Destination 0..5 takes source 0, 0, 1, 1, 2.
+            assert_eq!(dst.get::<u8>(1, 1).unwrap(), 0);
+            assert_eq!(dst.get::<u8>(2, 2).unwrap(), 110);
+            assert_eq!(dst.get::<u8>(3, 4).unwrap(), 210);
+            assert_eq!(dst.get::<u8>(4, 4).unwrap(), 220);
+
+            // Down, every channel from the same pixel.
+            let data: Vec<u8> = (0..6u8).flat_map(|y| (0..6u8).flat_map(move |x| [x, y, x + y])).collect();
+            let src = Mat::from_slice_2d(6, 6, 3, &data).unwrap();
+            let dst = resized(&src, 3, 3, INTER_NEAREST);
+            assert_eq!(dst.get::<[u8; 3]>(0, 0).unwrap(), [0, 0, 0]);
+            assert_eq!(dst.get::<[u8; 3]>(1, 2).unwrap(), [4, 2, 6]);
+            assert_eq!(dst.get::<[u8; 3]>(2, 2).unwrap(), [4, 4, 8]);
+        }
+
+        #[test]
+        fn resize_reuses_a_matching_dst_and_replaces_any_other() {
+            let src = gradient(CV_8UC1);
+            let mut dst = Mat::zeros(5, 5, CV_8UC1).unwrap();
+            let before = dst.at_2d::<u8>(0, 0).unwrap();
+            resize(&src, &mut dst, Size::new(5, 5), INTER_LINEAR).unwrap();
+            assert_eq!(dst.at_2d::<u8>(0, 0).unwrap(), before);
+
+            let mut dst = Mat::zeros(2, 2, CV_32FC1).unwrap();
+            resize_def(&src, &mut dst, Size::new(4, 6)).unwrap();
+            assert_eq!((dst.cols(), dst.rows(), dst.typ()), (4, 6, CV_8UC1));
+
+            assert!(resize(&src, &mut dst, Size::new(0, 4), INTER_LINEAR).is_err());
+            assert!(resize(&Mat::default(), &mut dst, Size::new(4, 4), INTER_LINEAR).is_err());
+            assert!(resize(&src, &mut dst, Size::new(4, 4), 99).is_err());
+        }
+
+        #[test]
+        fn bgr_converts_to_rgb_and_gray() {
+            let src = Mat::from_slice_2d(1, 4, 3, &[255u8, 0, 0, 0, 255, 0, 0, 0, 255, 10, 20, 30]).unwrap();
+            let mut dst = Mat::default();
+            cvt_color(&src, &mut dst, COLOR_BGR2RGB).unwrap();
+            assert_eq!(dst.to_vec::<u8>().unwrap(), [0, 0, 255, 0, 255, 0, 255, 0, 0, 30, 20, 10]);
+            // RGB2BGR is the same swap, so it undoes it.
+            let mut back = Mat::default();
+            cvt_color(&dst, &mut back, COLOR_RGB2BGR).unwrap();
+            assert_eq!(back.to_vec::<u8>().unwrap(), src.to_vec::<u8>().unwrap());
+
+            cvt_color(&src, &mut dst, COLOR_BGR2GRAY).unwrap();
+            assert_eq!(dst.typ(), CV_8UC1);
+            // 0.114 B + 0.587 G + 0.299 R.
+            assert_eq!(dst.to_vec::<u8>().unwrap(), [29, 150, 76, 22]);
+            cvt_color(&src, &mut dst, COLOR_RGB2GRAY).unwrap();
+            assert_eq!(dst.to_vec::<u8>().unwrap(), [76, 150, 29, 18]);
+
+            let floats = Mat::from_slice_2d(1, 1, 3, &[1.0f32, 0.5, 0.0]).unwrap();
+            assert_eq!(floats.typ(), CV_32FC3);
+            cvt_color(&floats, &mut dst, COLOR_BGR2GRAY).unwrap();
+            assert_eq!(dst.typ(), CV_32FC1);
+            assert!((dst.get::<f32>(0, 0).unwrap() - 0.4075).abs() < 1e-6);
+
+            assert!(cvt_color(&gradient(CV_8UC1), &mut dst, COLOR_BGR2GRAY).is_err());
+            assert!(cvt_color(&src, &mut dst, 99).is_err());
+        }
+
+        #[test]
+        fn threshold_matches_a_naive_loop() {
+            let mut rng = Rng(0x7777);
+            for round in 0..300 {
+                let typ = [CV_8UC1, CV_8UC3, CV_32FC1][round % 3];
+                let mat = rng.mat(typ);
+                let (t, max) = (rng.below(200) as f64 - 50.0, 100.0 + rng.below(200) as f64);
+                let op = [THRESH_BINARY, THRESH_BINARY_INV, THRESH_TRUNC, THRESH_TOZERO, THRESH_TOZERO_INV][rng.below(5) as usize];
+                let mut dst = Mat::default();
+                assert_eq!(threshold(&mat, &mut dst, t, max, op).unwrap(), t);
+                let expected: Vec<f64> = naive_values(&mat)
+                    .into_iter()
+                    .map(|v| match op {
+                        THRESH_BINARY => if v > t { max } else { 0.0 },
+                        THRESH_BINARY_INV => if v > t { 0.0 } else { max },
+                        THRESH_TRUNC => v.min(t),
+                        THRESH_TOZERO => if v > t { v } else { 0.0 },
+                        _ => if v > t { 0.0 } else { v },
+                    })
+                    .map(|v| saturate(v, typ))
+                    .collect();
+                assert_eq!(naive_values(&dst), expected, "type {} op {} at {}", typ, op, t);
+            }
+            assert!(threshold(&gradient(CV_8UC1), &mut Mat::default(), 1.0, 1.0, 99).is_err());
+        }
+
+        // Channel c of pixel (x, y) of a filter's input, replicating the
+        // border as BORDER_REPLICATE does.
+        fn replicated(mat: &Mat, values: &[f64], x: i32, y: i32, c: i32) -> f64 {
+            let (x, y) = (x.clamp(0, mat.cols() - 1), y.clamp(0, mat.rows() - 1));
+            values[((y * mat.cols() + x) * mat.channels() + c) as usize]
+        }
+
+        // Each output channel value from its whole window, in src order.
+        fn naive_filter(mat: &Mat, mut at: impl FnMut(&[f64], i32, i32, i32) -> f64) -> Vec<f64> {
+            let values = naive_values(mat);
+            let mut out = Vec::with_capacity(values.len());
+            for y in 0..mat.rows() {
+                for x in 0..mat.cols() {
+                    for c in 0..mat.channels() {
+                        out.push(at(&values, x, y, c));
+                    }
+                }
+            }
+            out
+        }
+
+        // Weights straight from the Gaussian, or the binomial rows OpenCV
+        // uses for a derived sigma.
+        fn naive_kernel(ksize: i32, sigma: f64) -> Vec<f64> {
+            match (ksize, sigma > 0.0) {
+                (3, false) => return vec![0.25, 0.5, 0.25],
+                (5, false) => return [1.0, 4.0, 6.0, 4.0, 1.0].iter().map(|w| w / 16.0).collect(),
+                _ => {}
+            }
+            let ksize = if ksize == 0 { ((sigma * 6.0 + 1.0).round() as i32) | 1 } else { ksize };
+            let weights: Vec<f64> = (0..ksize).map(|i| (-((i - ksize / 2) as f64).powi(2) / (2.0 * sigma * sigma)).exp()).collect();
+            let total: f64 = weights.iter().sum();
+            weights.iter().map(|w| w / total).collect()
+        }
+
+        fn naive_gaussian(mat: &Mat, (kw, kh): (i32, i32), sigma_x: f64, sigma_y: f64) -> Vec<f64> {
+            let (kx, ky) = (naive_kernel(kw, sigma_x), naive_kernel(kh, sigma_y));
+            let (rx, ry) = ((kx.len() / 2) as i32, (ky.len() / 2) as i32);
+            naive_filter(mat, |values, x, y, c| {
+                let mut sum = 0.0;
+                for (j, wy) in ky.iter().enumerate() {
+                    for (i, wx) in kx.iter().enumerate() {
+                        sum += wx * wy * replicated(mat, values, x + i as i32 - rx, y + j as i32 - ry, c);
+                    }
+                }
+                sum.round().clamp(0.0, 255.0)
+            })
+        }
+
+        fn naive_median(mat: &Mat, ksize: i32) -> Vec<f64> {
+            let r = ksize / 2;
+            naive_filter(mat, |values, x, y, c| {
+                let mut window: Vec<f64> = (-r..=r).flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+                    .map(|(dx, dy)| replicated(mat, values, x + dx, y + dy, c))
+                    .collect();
+                window.sort_by(f64::total_cmp);
+                window[window.len() / 2]
+            })
+        }
+
+        // The whole rectangle at once, `iterations` times over.
+        fn naive_morphology(mat: &Mat, (kw, kh): (i32, i32), iterations: i32, pick: fn(f64, f64) -> f64) -> Vec<f64> {
+            let mut current = mat.clone_owned().unwrap();
+            for _ in 0..iterations {
+                let out = naive_filter(&current, |values, x, y, c| {
+                    let mut picked = replicated(&current, values, x, y, c);
+                    for dy in -(kh / 2)..kh - kh / 2 {
+                        for dx in -(kw / 2)..kw - kw / 2 {
+                            picked = pick(picked, replicated(&current, values, x + dx, y + dy, c));
+                        }
+                    }
+                    picked
+                });
+                let bytes: Vec<u8> = out.iter().map(|v| *v as u8).collect();
+                current = Mat::from_slice_2d(mat.rows(), mat.cols(), mat.channels(), &bytes).unwrap();
+            }
+            naive_values(&current)
+        }
+
+        #[test]
+        fn gaussian_blur_matches_a_naive_convolution() {
+            let mut rng = Rng(0x6a55);
+            for round in 0..200 {
+                let typ = [CV_8UC1, CV_8UC3][round % 2];
+                let mat = rng.mat(typ);
+                let (ksize, sigma_x, sigma_y) = match rng.below(4) {
+                    0 => ((3, 5), 0.0, 0.0),
+                    1 => ((5, 3), 0.0, 0.0),
+                    2 => ((1 + 2 * rng.below(4), 1 + 2 * rng.below(4)), 0.5 + rng.below(4) as f64 / 2.0, 0.8),
+                    _ => ((0, 0), 0.5 + rng.below(3) as f64 / 2.0, 0.0),
+                };
+                let mut dst = Mat::default();
+                gaussian_blur(&mat, &mut dst, Size::new(ksize.0, ksize.1), sigma_x, sigma_y).unwrap();
+                assert_eq!((dst.rows(), dst.cols(), dst.typ()), (mat.rows(), mat.cols(), typ));
+                let sigma_y = if sigma_y > 0.0 { sigma_y } else { sigma_x };
+                let expected = naive_gaussian(&mat, ksize, sigma_x, sigma_y);
+                // The separable passes round in f32, so a value on a .5 can
+                // land either side.
+                for (i, (got, want)) in naive_values(&dst).iter().zip(&expected).enumerate() {
+                    assert!((got - want).abs() <= 1.0, "type {} {:?} sigma {}: value {} is {}, not {}", typ, ksize, sigma_x, i, got, want);
+                }
+            }
+        }
+
+        #[test]
+        fn median_blur_matches_a_naive_sort() {
+            let mut rng = Rng(0x3ed1);
+            for round in 0..200 {
+                let typ = [CV_8UC1, CV_8UC3][round % 2];
+                let mat = rng.mat(typ);
+                let ksize = [3, 5, 7][rng.below(3) as usize];
+                let mut dst = Mat::default();
+                median_blur(&mat, &mut dst, ksize).unwrap();
+                assert_eq!(naive_values(&dst), naive_median(&mat, ksize), "type {} ksize {}", typ, ksize);
+            }
+        }
+
+        #[test]
+        fn erode_and_dilate_match_a_naive_window() {
+            let mut rng = Rng(0xe0de);
+            for round in 0..200 {
+                let typ = [CV_8UC1, CV_8UC3][round % 2];
+                let mat = rng.mat(typ);
+                let ksize = (1 + rng.below(4), 1 + rng.below(4));
+                let iterations = 1 + rng.below(3);
+                let mut dst = Mat::default();
+                erode(&mat, &mut dst, Size::new(ksize.0, ksize.1), iterations).unwrap();
+                assert_eq!(naive_values(&dst), naive_morphology(&mat, ksize, iterations, f64::min), "erode {:?} x{}", ksize, iterations);
+                dilate(&mat, &mut dst, Size::new(ksize.0, ksize.1), iterations).unwrap();
+                assert_eq!(naive_values(&dst), naive_morphology(&mat, ksize, iterations, f64::max), "dilate {:?} x{}", ksize, iterations);
+            }
+        }
+
+        #[test]
+        fn filters_replicate_the_border_pixels() {
+            // One lit pixel in the top-left corner of a 5x5 gray image.
+            let mut corner = Mat::zeros(5, 5, CV_8UC1).unwrap();
+            corner.set(0, 0, 255u8).unwrap();
+            let mut dst = Mat::default();
+            // Replicated, the taps off the edge are the corner again:
+            // (0.25 + 0.5)^2 of it.
+            gaussian_blur(&corner, &mut dst, Size::new(3, 3), 0.0, 0.0).unwrap();
+            assert_eq!(dst.get::<u8>(0, 0).unwrap(), 143);
+            assert_eq!(dst.get::<u8>(0, 1).unwrap(), 48);
+            assert_eq!(dst.get::<u8>(1, 1).unwrap(), 16);
+            assert_eq!(dst.get::<u8>(2, 2).unwrap(), 0);
+            // Four of the nine replicated taps are the corner: not the median.
+            median_blur(&corner, &mut dst, 3).unwrap();
+            assert_eq!(dst.get::<u8>(0, 0).unwrap(), 0);
+            dilate(&corner, &mut dst, Size::new(3, 3), 1).unwrap();
+            assert_eq!(naive_values(&dst).iter().filter(|v| **v == 255.0).count(), 4);
+
+            // A lit left column in BGR: the median and erosion keep it,
+            // because the replicated column outside is lit too.
+            let mut edge = Mat::zeros(4, 4, CV_8UC3).unwrap();
+            for row in 0..4 {
+                edge.set(row, 0, [10u8, 20, 30]).unwrap();
+            }
+            median_blur(&edge, &mut dst, 3).unwrap();
+            assert_eq!(dst.get::<[u8; 3]>(2, 0).unwrap(), [10, 20, 30]);
+            assert_eq!(dst.get::<[u8; 3]>(2, 1).unwrap(), [0, 0, 0]);
+            erode(&edge, &mut dst, Size::new(2, 1), 1).unwrap();
+            assert_eq!(dst.get::<[u8; 3]>(1, 0).unwrap(), [10, 20, 30]);
+        }
+
+        #[test]
+        fn filters_reuse_a_matching_dst() {
+            let src = Rng(0xd57).mat_sized(6, 8, CV_8UC3);
+            let mut dst = Mat::zeros(6, 8, CV_8UC3).unwrap();
+            let before = dst.at_2d::<u8>(0, 0).unwrap();
+            gaussian_blur(&src, &mut dst, Size::new(3, 3), 0.0, 0.0).unwrap();
+            median_blur(&src, &mut dst, 3).unwrap();
+            erode(&src, &mut dst, Size::new(3, 3), 1).unwrap();
+            dilate(&src, &mut dst, Size::new(3, 3), 2).unwrap();
+            assert_eq!(dst.at_2d::<u8>(0, 0).unwrap(), before);
+            // A dst of another type is replaced.
+            let mut dst = Mat::zeros(6, 8, CV_8UC1).unwrap();
+            median_blur(&src, &mut dst, 3).unwrap();
+            assert_eq!(dst.typ(), CV_8UC3);
+        }
+
+        #[test]
+        fn filters_refuse_bad_input() {
+            let mut dst = Mat::default();
+            let floats = Mat::zeros(4, 4, CV_32FC1).unwrap();
+            assert!(gaussian_blur(&floats, &mut dst, Size::new(3, 3), 0.0, 0.0).is_err());
+            assert!(median_blur(&floats, &mut dst, 3).is_err());
+            assert!(erode(&floats, &mut dst, Size::new(3, 3), 1).is_err());
+            assert!(dilate(&Mat::default(), &mut dst, Size::new(3, 3), 1).is_err());
+            let src = gradient(CV_8UC1);
+            assert!(gaussian_blur(&src, &mut dst, Size::new(4, 3), 0.0, 0.0).is_err());
+            assert!(gaussian_blur(&src, &mut dst, Size::new(0, 0), 0.0, 0.0).is_err());
+            assert!(median_blur(&src, &mut dst, 1).is_err());
+            assert!(median_blur(&src, &mut dst, 4).is_err());
+            assert!(erode(&src, &mut dst, Size::new(0, 3), 1).is_err());
+        }
+
+        // A binary 8UC1 image drawn as rows of '#' (255) and '.' (0).
+        fn binary(rows: &[&str]) -> Mat {
+            let data: Vec<u8> = rows.iter().flat_map(|row| row.bytes().map(|b| if b == b'#' { 255 } else { 0 })).collect();
+            Mat::from_slice_2d(rows.len() as i32, rows[0].len() as i32, 1, &data).unwrap()
+        }
+
+        // Count, labels, stats rows and centroids.
+        type Components = (i32, Vec<i32>, Vec<[i32; 5]>, Vec<(f64, f64)>);
+
+        fn components(image: &Mat, connectivity: i32) -> Components {
+            let (mut labels, mut stats, mut centroids) = (Mat::default(), Mat::default(), Mat::default());
+            let n = connected_components_with_stats(image, &mut labels, &mut stats, &mut centroids, connectivity).unwrap();
+            assert_eq!((labels.rows(), labels.cols(), labels.typ()), (image.rows(), image.cols(), mat_type(CV_32S, 1)));
+            assert_eq!((stats.rows(), stats.cols(), centroids.rows(), centroids.cols()), (n, 5, n, 2));
+            let stats = (0..n).map(|l| [0, 1, 2, 3, 4].map(|c| stats.get::<i32>(l, c).unwrap())).collect();
+            let centroids = (0..n).map(|l| (centroids.get::<f64>(l, 0).unwrap(), centroids.get::<f64>(l, 1).unwrap())).collect();
+            (n, labels.to_vec::<i32>().unwrap(), stats, centroids)
+        }
+
+        // Components by flood fill, one seed at a time.
+        fn naive_component_count(image: &Mat, eight: bool) -> i32 {
+            let (w, h) = (image.cols(), image.rows());
+            let mut seen = vec![false; (w * h) as usize];
+            let mut count = 1;
+            for start in 0..w * h {
+                if seen[start as usize] || image.get::<u8>(start / w, start % w).unwrap() == 0 {
+                    continue;
+                }
+                count += 1;
+                let mut stack = vec![(start % w, start / w)];
+                while let Some((x, y)) = stack.pop() {
+                    if x < 0 || y < 0 || x >= w || y >= h || seen[(y * w + x) as usize] || image.get::<u8>(y, x).unwrap() == 0 {
+                        continue;
+                    }
+                    seen[(y * w + x) as usize] = true;
+                    for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                        if eight || dx == 0 || dy == 0 {
+                            stack.push((x + dx, y + dy));
+                        }
+                    }
+                }
+            }
+            count
+        }
+
+        #[test]
+        fn components_are_labelled_in_raster_order_with_their_stats() {
+            let image = binary(&[
+                "##....#",
+                "##....#",
+                "...#...",
+                "......#",
+            ]);
+            let (n, labels, stats, centroids) = components(&image, 8);
+            assert_eq!(n, 5);
+            assert_eq!(&labels[..7], &[1, 1, 0, 0, 0, 0, 2]);
+            assert_eq!(labels[2 * 7 + 3], 3);
+            assert_eq!(labels[3 * 7 + 6], 4);
+            assert_eq!(stats[0][CC_STAT_AREA], 28 - 8);
+            assert_eq!(stats[1], [0, 0, 2, 2, 4]);
+            assert_eq!(stats[2], [6, 0, 1, 2, 2]);
+            assert_eq!(stats[3], [3, 2, 1, 1, 1]);
+            assert_eq!(centroids[1], (0.5, 0.5));
+            assert_eq!(centroids[2], (6.0, 0.5));
+            assert_eq!(centroids[3], (3.0, 2.0));
+        }
+
+        #[test]
+        fn diagonal_neighbours_join_only_with_connectivity_8() {
+            let image = binary(&[
+                "#...",
+                ".#..",
+                "..#.",
+                "...#",
+            ]);
+            assert_eq!(components(&image, 8).0, 2);
+            assert_eq!(components(&image, 4).0, 5);
+        }
+
+        #[test]
+        fn arms_that_meet_lower_down_are_one_component() {
+            // Two labels until the bottom row joins them, then one.
+            let image = binary(&[
+                "#.#.#",
+                "#.#.#",
+                "#####",
+            ]);
+            let (n, labels, stats, _) = components(&image, 4);
+            assert_eq!(n, 2);
+            assert!(labels.iter().all(|&l| l == 0 || l == 1));
+            assert_eq!(stats[1], [0, 0, 5, 3, 11]);
+        }
+
+        #[test]
+        fn component_counts_match_a_flood_fill() {
+            let mut rng = Rng(0xb10b);
+            for round in 0..200 {
+                let (rows, cols) = (1 + rng.below(16), 1 + rng.below(16));
+                let density = 1 + rng.below(4) as u64;
+                let data: Vec<u8> = (0..rows * cols).map(|_| if rng.below(5) < density as i32 { 255 } else { 0 }).collect();
+                let image = Mat::from_slice_2d(rows, cols, 1, &data).unwrap();
+                for connectivity in [4, 8] {
+                    let (n, labels, stats, _) = components(&image, connectivity);
+                    assert_eq!(n, naive_component_count(&image, connectivity == 8), "round {} connectivity {}", round, connectivity);
+                    assert_eq!(stats.iter().map(|s| s[CC_STAT_AREA]).sum::<i32>(), rows * cols);
+                    for l in 0..n {
+                        assert_eq!(labels.iter().filter(|&&label| label == l).count() as i32, stats[l as usize][CC_STAT_AREA]);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn an_empty_image_has_only_the_background() {
+            let (n, labels, stats, _) = components(&Mat::zeros(3, 4, CV_8UC1).unwrap(), 8);
+            assert_eq!(n, 1);
+            assert!(labels.iter().all(|&l| l == 0));
+            assert_eq!(stats[0], [0, 0, 4, 3, 12]);
+            let (mut labels, mut stats, mut centroids) = (Mat::default(), Mat::default(), Mat::default());
+            assert!(connected_components_with_stats(&gradient(CV_8UC1), &mut labels, &mut stats, &mut centroids, 6).is_err());
+            assert!(connected_components_with_stats(&gradient(CV_32FC1), &mut labels, &mut stats, &mut centroids, 8).is_err());
+        }
+
+        fn contours_of(image: &Mat, method: i32) -> Vec<Vec<(i32, i32)>> {
+            let mut contours = Vector::new();
+            find_contours(image, &mut contours, RETR_EXTERNAL, method).unwrap();
+            contours.iter().map(|c| c.iter().map(|p| (p.x, p.y)).collect()).collect()
+        }
+
+        #[test]
+        fn a_square_is_traced_clockwise_from_its_top_left() {
+            let image = binary(&[
+                ".....",
+                ".###.",
+                ".###.",
+                ".###.",
+                ".....",
+            ]);
+            assert_eq!(contours_of(&image, CHAIN_APPROX_NONE), vec![vec![(1, 1), (2, 1), (3, 1), (3, 2), (3, 3), (2, 3), (1, 3), (1, 2)]]);
+            assert_eq!(contours_of(&image, CHAIN_APPROX_SIMPLE), vec![vec![(1, 1), (3, 1), (3, 3), (1, 3)]]);
+        }
+
+        #[test]
+        fn there_is_one_external_contour_per_outer_blob() {
+            // A ring with a dot in its hole, a lone pixel and a diagonal line
+            // against the edge.
+            let image = binary(&[
+                "#####...#",
+                "#...#....",
+                "#.#.#..#.",
+                "#...#.#..",
+                "#####....",
+            ]);
+            let contours = contours_of(&image, CHAIN_APPROX_SIMPLE);
+            assert_eq!(contours.len(), 3);
+            assert_eq!(contours[0], vec![(0, 0), (4, 0), (4, 4), (0, 4)]);
+            assert_eq!(contours[1], vec![(8, 0)]);
+            // A one-pixel-wide line is walked there and back.
+            assert_eq!(contours[2], vec![(7, 2), (6, 3)]);
+            assert_eq!(contours_of(&Mat::zeros(3, 3, CV_8UC1).unwrap(), CHAIN_APPROX_NONE), Vec::<Vec<(i32, i32)>>::new());
+        }
+
+        #[test]
+        fn contour_points_lie_on_their_blob() {
+            let mut rng = Rng(0xc047);
+            for _ in 0..100 {
+                let (rows, cols) = (1 + rng.below(12), 1 + rng.below(12));
+                let data: Vec<u8> = (0..rows * cols).map(|_| if rng.below(2) == 0 { 255 } else { 0 }).collect();
+                let image = Mat::from_slice_2d(rows, cols, 1, &data).unwrap();
+                let contours = contours_of(&image, CHAIN_APPROX_NONE);
+                // No more outer contours than 8-connected blobs.
+                assert!((contours.len() as i32) < naive_component_count(&image, true));
+                for contour in &contours {
+                    for (i, &(x, y)) in contour.iter().enumerate() {
+                        assert_eq!(image.get::<u8>(y, x).unwrap(), 255);
+                        // Each step is to one of the 8 neighbours.
+                        let (nx, ny) = contour[(i + 1) % contour.len()];
+                        assert!((nx - x).abs() <= 1 && (ny - y).abs() <= 1);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn find_contours_refuses_what_it_doesnt_support() {
+            let mut contours = Vector::new();
+            let image = binary(&["#"]);
+            assert!(find_contours(&image, &mut contours, 1, CHAIN_APPROX_NONE).is_err());
+            assert!(find_contours(&image, &mut contours, RETR_EXTERNAL, 3).is_err());
+            assert!(find_contours(&Mat::zeros(3, 3, CV_8UC3).unwrap(), &mut contours, RETR_EXTERNAL, CHAIN_APPROX_NONE).is_err());
+        }
+
+        const WHITE: Scalar = Scalar(255.0, 255.0, 255.0, 0.0);
+
+        fn canvas(width: i32, height: i32) -> Mat {
+            Mat::zeros(height, width, CV_8UC3).unwrap()
+        }
+
+        // (x, y) of every pixel drawn on.
+        fn painted(img: &Mat) -> std::collections::BTreeSet<(i32, i32)> {
+            (0..img.rows())
+                .flat_map(|y| (0..img.cols()).map(move |x| (x, y)))
+                .filter(|&(x, y)| img.get::<[u8; 3]>(y, x).unwrap() != [0, 0, 0])
+                .collect()
+        }
+
+        fn area(x0: i32, y0: i32, x1: i32, y1: i32) -> std::collections::BTreeSet<(i32, i32)> {
+            (y0..=y1).flat_map(|y| (x0..=x1).map(move |x| (x, y))).collect()
+        }
+
+        #[test]
+        fn rectangles_outline_fill_and_take_the_color_in_bgr() {
+            let mut img = canvas(10, 8);
+            rectangle(&mut img, Rect::new(2, 1, 5, 4), Scalar::new(10.0, 20.0, 30.0, 0.0), 1).unwrap();
+            let interior = area(3, 2, 5, 3);
+            assert_eq!(painted(&img), &area(2, 1, 6, 4) - &interior);
+            assert_eq!(img.get::<[u8; 3]>(1, 2).unwrap(), [10, 20, 30]);
+
+            // Thickness 3 is centred on the edge, one pixel either side.
+            let mut img = canvas(14, 12);
+            rectangle(&mut img, Rect::new(2, 2, 8, 7), WHITE, 3).unwrap();
+            assert_eq!(painted(&img), &area(1, 1, 10, 9) - &area(4, 4, 7, 6));
+
+            let mut img = canvas(10, 8);
+            rectangle(&mut img, Rect::new(2, 1, 5, 4), Scalar::new(300.0, -5.0, 12.6, 0.0), FILLED).unwrap();
+            assert_eq!(painted(&img), area(2, 1, 6, 4));
+            assert_eq!(img.get::<[u8; 3]>(3, 4).unwrap(), [255, 0, 13]);
+
+            let mut img = canvas(10, 8);
+            rectangle(&mut img, Rect::new(2, 1, 0, 4), WHITE, 1).unwrap();
+            assert!(painted(&img).is_empty());
+        }
+
+        #[test]
+        fn boxes_partly_or_wholly_off_the_image_are_clipped() {
+            let mut img = canvas(10, 8);
+            rectangle(&mut img, Rect::new(-3, -2, 6, 5), WHITE, 1).unwrap();
+            assert_eq!(painted(&img), [(0, 2), (1, 2), (2, 2), (2, 0), (2, 1)].into_iter().collect());
+
+            let mut img = canvas(10, 8);
+            rectangle(&mut img, Rect::new(8, 6, 10, 10), WHITE, FILLED).unwrap();
+            assert_eq!(painted(&img), area(8, 6, 9, 7));
+
+            let mut img = canvas(10, 8);
+            rectangle(&mut img, Rect::new(20, 20, 5, 5), WHITE, 2).unwrap();
+            rectangle(&mut img, Rect::new(-30, -30, 5, 5), WHITE, FILLED).unwrap();
+            // A box around the whole image leaves only its inside untouched.
+            rectangle(&mut img, Rect::new(-1, -1, 12, 10), WHITE, 3).unwrap();
+            assert_eq!(painted(&img), &area(0, 0, 9, 7) - &area(1, 1, 8, 6));
+        }
+
+        #[test]
+        fn lines_plot_every_pixel_between_their_ends() {
+            let mut img = canvas(10, 8);
+            line(&mut img, Point::new(1, 1), Point::new(5, 1), WHITE, 1).unwrap();
+            line(&mut img, Point::new(0, 3), Point::new(3, 6), WHITE, 1).unwrap();
+            let expected: std::collections::BTreeSet<_> = (1..=5).map(|x| (x, 1)).chain((0..=3).map(|i| (i, 3 + i))).collect();
+            assert_eq!(painted(&img), expected);
+
+            // Thickness 3 stamps a 3x3 disk at each step.
+            let mut img = canvas(10, 8);
+            line(&mut img, Point::new(2, 4), Point::new(6, 4), WHITE, 3).unwrap();
+            assert_eq!(painted(&img), area(1, 3, 7, 5));
+
+            let mut img = canvas(10, 8);
+            line(&mut img, Point::new(-5, 2), Point::new(12, 2), WHITE, 1).unwrap();
+            assert_eq!(painted(&img), area(0, 2, 9, 2));
+            assert!(line(&mut img, Point::new(0, 0), Point::new(1, 1), WHITE, 0).is_err());
+        }
+
+        #[test]
+        fn circles_ring_or_fill_around_their_centre() {
+            let mut img = canvas(11, 11);
+            circle(&mut img, Point::new(5, 5), 3, WHITE, 1).unwrap();
+            let ring = painted(&img);
+            for point in [(8, 5), (5, 2), (2, 5), (5, 8), (7, 7), (3, 3)] {
+                assert!(ring.contains(&point), "{:?}", point);
+            }
+            assert!(!ring.contains(&(5, 5)) && !ring.contains(&(6, 6)) && !ring.contains(&(9, 5)));
+            // Symmetric about the centre.
+            assert!(ring.iter().all(|&(x, y)| ring.contains(&(10 - x, y)) && ring.contains(&(y, x))));
+
+            let mut img = canvas(11, 11);
+            circle(&mut img, Point::new(5, 5), 3, WHITE, FILLED).unwrap();
+            let disk = painted(&img);
+            assert!(disk.contains(&(5, 5)) && disk.contains(&(8, 5)) && disk.contains(&(7, 7)));
+            assert!(!disk.contains(&(8, 7)));
+            assert!(ring.is_subset(&disk));
+
+            let mut img = canvas(6, 6);
+            circle(&mut img, Point::new(-1, -1), 2, WHITE, FILLED).unwrap();
+            assert_eq!(painted(&img), [(0, 0), (1, 0), (0, 1)].into_iter().collect());
+            assert!(circle(&mut img, Point::new(2, 2), -1, WHITE, 1).is_err());
+        }
+
+        #[test]
+        fn text_comes_from_the_bitmap_font_with_org_at_the_bottom_left() {
+            // 'H': two full columns and the middle row between them.
+            let mut img = canvas(12, 10);
+            put_text(&mut img, "H", Point::new(1, 8), 1.0, WHITE, 1).unwrap();
+            let h: std::collections::BTreeSet<_> = area(1, 2, 1, 8).into_iter().chain(area(5, 2, 5, 8)).chain(area(2, 5, 4, 5)).collect();
+            assert_eq!(painted(&img), h);
+
+            // Each font pixel becomes a 2x2 block at scale 2, and the next
+            // glyph starts 12 pixels on.
+            let mut img = canvas(30, 16);
+            put_text(&mut img, "HH", Point::new(0, 15), 2.0, WHITE, 1).unwrap();
+            let drawn = painted(&img);
+            assert!(area(0, 2, 1, 15).is_subset(&drawn) && area(12, 2, 13, 15).is_subset(&drawn));
+            assert!(!drawn.contains(&(2, 2)) && !drawn.contains(&(11, 8)));
+            assert_eq!(drawn.len(), 2 * 17 * 4);
+
+            let mut question = canvas(12, 10);
+            put_text(&mut question, "?", Point::new(1, 8), 1.0, WHITE, 1).unwrap();
+            let mut other = canvas(12, 10);
+            put_text(&mut other, "é", Point::new(1, 8), 1.0, WHITE, 1).unwrap();
+            assert_eq!(painted(&other), painted(&question));
+
+            // Off the top left, only the right of the H lands on the image.
+            let mut img = canvas(12, 10);
+            put_text(&mut img, "H", Point::new(-4, 3), 1.0, WHITE, 1).unwrap();
+            assert_eq!(painted(&img), area(0, 0, 0, 3));
+        }
+
+        #[test]
+        fn drawing_needs_an_8uc3_mat() {
+            let mut gray = Mat::zeros(4, 4, CV_8UC1).unwrap();
+            assert!(rectangle(&mut gray, Rect::new(0, 0, 2, 2), WHITE, 1).is_err());
+            assert!(line(&mut gray, Point::new(0, 0), Point::new(2, 2), WHITE, 1).is_err());
+            assert!(circle(&mut gray, Point::new(1, 1), 1, WHITE, 1).is_err());
+            assert!(put_text(&mut gray, "H", Point::new(0, 3), 1.0, WHITE, 1).is_err());
+        }
+    }
+}
+
+// Decoding and encoding go through the `image` crate, so they need the
+// `imgcodecs` feature; without it every call fails.
+pub mod imgcodecs {
+    use crate::core::{Error, Mat, Result};
+    #[cfg(feature = "imgcodecs")]
+    use crate::core::{CV_8UC1, CV_8UC3};
+
+    // Like OpenCV, returns an 8UC3 mat in BGR order.
+    pub fn imread(filename: &str) -> Result<Mat> {
+        #[cfg(feature = "imgcodecs")]
+        {
+            bgr_mat(image::open(filename)?.into_rgb8())
+        }
+        #[cfg(not(feature = "imgcodecs"))]
+        {
+            Err(disabled(filename))
+        }
+    }
+
+    // Decodes an in-memory image (any format the `image` crate detects)
+    // into a BGR 8UC3 mat.
+    pub fn imdecode(buf: &[u8]) -> Result<Mat> {
+        #[cfg(feature = "imgcodecs")]
+        {
+            bgr_mat(image::load_from_memory(buf)?.into_rgb8())
+        }
+        #[cfg(not(feature = "imgcodecs"))]
+        {
+            let _ = buf;
+            Err(disabled("an in-memory image"))
+        }
+    }
+
+    pub fn imread_grayscale(filename: &str) -> Result<Mat> {
+        #[cfg(feature = "imgcodecs")]
+        {
+            let image = image::open(filename)?.into_luma8();
+            Mat::from_slice_2d(image.height() as i32, image.width() as i32, 1, image.as_raw())
+        }
+        #[cfg(not(feature = "imgcodecs"))]
+        {
+            Err(disabled(filename))
+        }
+    }
+
+    // The format comes from the file extension. Takes BGR 8UC3 or
+    // grayscale 8UC1 mats.
+    pub fn imwrite(filename: &str, img: &Mat) -> Result<()> {
+        #[cfg(feature = "imgcodecs")]
+        {
+            let format = image::ImageFormat::from_path(filename)
+                .map_err(|_| Error::from(format!("Unsupported image extension: {}", filename)))?;
+            let (width, height) = (img.cols() as u32, img.rows() as u32);
+            let (data, color) = match img.typ() {
+                CV_8UC3 => {
+                    let rgb: Vec<u8> = img.to_vec::<u8>()?.chunks(3).flat_map(|p| [p[2], p[1], p[0]]).collect();
+                    (rgb, image::ColorType::Rgb8)
+                }
+                CV_8UC1 => (img.to_vec::<u8>()?, image::ColorType::L8),
+                other => return Err(Error::from(format!("Cannot write mat type {} as an image", other))),
+            };
+            image::save_buffer_with_format(filename, &data, width, height, color, format)?;
+            Ok(())
+        }
+        #[cfg(not(feature = "imgcodecs"))]
+        {
+            let _ = img;
+            Err(disabled(filename))
+        }
+    }
+
+    #[cfg(feature = "imgcodecs")]
+    fn bgr_mat(image: image::RgbImage) -> Result<Mat> {
+        let bgr: Vec<u8> = image.as_raw().chunks(3).flat_map(|p| [p[2], p[1], p[0]]).collect();
+        Mat::from_slice_2d(image.height() as i32, image.width() as i32, 3, &bgr)
+    }
+
+    #[cfg(not(feature = "imgcodecs"))]
+    fn disabled(filename: &str) -> Error {
+        Error::from(format!("Cannot read or write {}: built without the imgcodecs feature", filename))
+    }
+}
+
+// Frames from V4L2 devices (with the `videoio-v4l2` feature, Linux only)
+// and from MJPEG or raw video files. Every backend hands out BGR 8UC3
+// mats, as OpenCV does.
+pub mod videoio {
+    use crate::core::{Error, Mat, Result, CV_8UC3};
+    use std::fs::File;
+    use std::io::{BufReader, ErrorKind, Read};
+
+    pub const CAP_PROP_FRAME_WIDTH: i32 = 3;
+    pub const CAP_PROP_FRAME_HEIGHT: i32 = 4;
+    pub const CAP_PROP_FPS: i32 = 5;
+
+    const DEFAULT_WIDTH: u32 = 640;
+    const DEFAULT_HEIGHT: u32 = 480;
+    const DEFAULT_FPS: u32 = 30;
+
+    enum Backend {
+        #[cfg(all(feature = "videoio-v4l2", target_os = "linux"))]
+        Device(v4l2::Device),
+        Mjpeg(BufReader<File>),
+        Raw { reader: BufReader<File>, format: RawFormat },
+    }
+
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum RawFormat {
+        Bgr24,
+        Yuyv,
+    }
+
+    // `source` picks the backend: a device path ("/dev/video0") or bare
+    // index ("0") opens a V4L2 camera, `.mjpg`/`.mjpeg` files are read as
+    // concatenated JPEG frames, and `.bgr`/`.raw` (packed BGR24) or
+    // `.yuv`/`.yuyv` (YUYV 4:2:2) files as headerless frames. Raw files
+    // carry no frame size, so set CAP_PROP_FRAME_WIDTH/HEIGHT before the
+    // first read unless they are 640x480.
+    pub struct VideoCapture {
+        source: String,
+        backend: Option<Backend>,
+        width: u32,
+        height: u32,
+        fps: u32,
+    }
+
+    impl VideoCapture {
+        pub fn open(source: &str) -> Result<Self> {
+            let mut capture = VideoCapture {
+                source: source.to_string(),
+                backend: None,
+                width: DEFAULT_WIDTH,
+                height: DEFAULT_HEIGHT,
+                fps: DEFAULT_FPS,
+            };
+            capture.backend = Some(capture.open_backend()?);
+            Ok(capture)
+        }
+
+        pub fn is_opened(&self) -> bool {
+            self.backend.is_some()
+        }
+
+        // Ok(false) at the end of a file, leaving `frame` untouched.
+        pub fn read(&mut self, frame: &mut Mat) -> Result<bool> {
+            let (width, height) = (self.width as usize, self.height as usize);
+            let Some(backend) = self.backend.as_mut() else {
+                return Err(Error::from(format!("{} is not open", self.source)));
+            };
+            let decoded = match backend {
+                #[cfg(all(feature = "videoio-v4l2", target_os = "linux"))]
+                Backend::Device(device) => Some(device.read()?),
+                Backend::Mjpeg(reader) => match next_jpeg(reader)? {
+                    Some(jpeg) => Some(crate::imgcodecs::imdecode(&jpeg)?),
+                    None => None,
+                },
+                Backend::Raw { reader, format } => {
+                    let frame_len = match format {
+                        RawFormat::Bgr24 => width * height * 3,
+                        RawFormat::Yuyv => width * height * 2,
+                    };
+                    let mut buf = vec![0u8; frame_len];
+                    match reader.read_exact(&mut buf) {
+                        Ok(()) => {}
+                        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+                        Err(e) => return Err(e.into()),
+                    }
+                    if *format == RawFormat::Yuyv {
+                        buf = yuyv_to_bgr(&buf, width, height, width * 2);
+                    }
+                    Some(Mat::from_slice_2d(height as i32, width as i32, 3, &buf)?)
+                }
+            };
+            let Some(decoded) = decoded else { return Ok(false) };
+            if frame.rows() == decoded.rows() && frame.cols() == decoded.cols() && frame.typ() == CV_8UC3 {
+                frame.copy_from_slice(&decoded.to_vec::<u8>()?)?;
+            } else {
+                *frame = decoded;
+            }
+            Ok(true)
+        }
+
+        // Returns whether the property took effect. A device reopens with
+        // the new settings, which the driver may round to what it
+        // supports; read them back with get(). MJPEG frames keep their
+        // encoded size.
+        pub fn set(&mut self, prop: i32, value: f64) -> Result<bool> {
+            if value <= 0.0 {
+                return Ok(false);
+            }
+            let value = value.round() as u32;
+            let is_mjpeg = matches!(self.backend, Some(Backend::Mjpeg(_)));
+            match prop {
+                CAP_PROP_FRAME_WIDTH if !is_mjpeg => self.width = value,
+                CAP_PROP_FRAME_HEIGHT if !is_mjpeg => self.height = value,
+                CAP_PROP_FPS => self.fps = value,
+                _ => return Ok(false),
+            }
+            #[cfg(all(feature = "videoio-v4l2", target_os = "linux"))]
+            if matches!(self.backend, Some(Backend::Device(_))) {
+                // The old device has to be closed before it can be reopened.
+                self.backend = None;
+                self.backend = Some(self.open_backend()?);
+            }
+            Ok(true)
+        }
+
+        pub fn get(&self, prop: i32) -> f64 {
+            match prop {
+                CAP_PROP_FRAME_WIDTH => self.width as f64,
+                CAP_PROP_FRAME_HEIGHT => self.height as f64,
+                CAP_PROP_FPS => self.fps as f64,
+                _ => 0.0,
+            }
+        }
+
+        pub fn release(&mut self) {
+            self.backend = None;
+        }
+
+        fn open_backend(&mut self) -> Result<Backend> {
+            let source = self.source.as_str();
+            if source.starts_with("/dev/video") || source.parse::<u32>().is_ok() {
+                return self.open_device();
+            }
+            let extension = std::path::Path::new(source)
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.to_ascii_lowercase())
+                .unwrap_or_default();
+            let reader = || -> Result<BufReader<File>> {
+                File::open(source)
+                    .map(BufReader::new)
+                    .map_err(|e| Error::from(format!("Failed to open {}: {}", source, e)))
+            };
+            match extension.as_str() {
+                "mjpg" | "mjpeg" => Ok(Backend::Mjpeg(reader()?)),
+                "bgr" | "raw" => Ok(Backend::Raw { reader: reader()?, format: RawFormat::Bgr24 }),
+                "yuv" | "yuyv" => Ok(Backend::Raw { reader: reader()?, format: RawFormat::Yuyv }),
+                _ => Err(Error::from(format!("Unsupported video source {}", source))),
+            }
+        }
+
+        #[cfg(all(feature = "videoio-v4l2", target_os = "linux"))]
+        fn open_device(&mut self) -> Result<Backend> {
+            let path = match self.source.parse::<u32>() {
+                Ok(index) => format!("/dev/video{}", index),
+                Err(_) => self.source.clone(),
+            };
+            let device = v4l2::Device::open(&path, self.width, self.height, self.fps)?;
+            (self.width, self.height, self.fps) = (device.width, device.height, device.fps);
+            Ok(Backend::Device(device))
+        }
+
+        #[cfg(not(all(feature = "videoio-v4l2", target_os = "linux")))]
+        fn open_device(&mut self) -> Result<Backend> {
+            Err(Error::from(format!(
+                "Cannot open {}: V4L2 capture needs Linux and the videoio-v4l2 feature",
+                self.source
+            )))
+        }
+    }
+
+    // Frames are whatever lies between an SOI and the next EOI marker.
+    // Entropy-coded data byte-stuffs 0xFF, so EOI can't appear inside a
+    // frame, although an embedded EXIF thumbnail would cut it short.
+    fn next_jpeg(reader: &mut BufReader<File>) -> Result<Option<Vec<u8>>> {
+        let mut frame = Vec::new();
+        let mut prev = 0u8;
+        let mut in_frame = false;
+        for byte in reader.bytes() {
+            let byte = byte?;
+            if in_frame {
+                frame.push(byte);
+                if prev == 0xFF && byte == 0xD9 {
+                    return Ok(Some(frame));
+                }
+            } else if prev == 0xFF && byte == 0xD8 {
+                in_frame = true;
+                frame.extend_from_slice(&[0xFF, 0xD8]);
+            }
+            prev = byte;
+        }
+        Ok(None)
+    }
+
+    // BT.601 limited range, which is what UVC cameras send.
+    fn yuyv_to_bgr(data: &[u8], width: usize, height: usize, stride: usize) -> Vec<u8> {
+        let mut bgr = Vec::with_capacity(width * height * 3);
+        let clamp = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+        for row in data.chunks(stride).take(height) {
+            for pair in row[..width * 2].chunks_exact(4) {
+                let (u, v) = (pair[1] as f32 - 128.0, pair[3] as f32 - 128.0);
+                for y in [pair[0], pair[2]] {
+                    let y = 1.164 * (y as f32 - 16.0);
+                    bgr.extend([clamp(y + 2.018 * u), clamp(y - 0.391 * u - 0.813 * v), clamp(y + 1.596 * v)]);
+                }
+            }
+        }
+        bgr
+    }
+
+    // Streaming capture over mmap'd buffers. The ioctl structs mirror
+    // linux/videodev2.h; their sizes are part of the request numbers, so
+    // the layouts have to match the kernel's exactly.
+    #[cfg(all(feature = "videoio-v4l2", target_os = "linux"))]
+    mod v4l2 {
+        use super::yuyv_to_bgr;
+        use crate::core::{Error, Mat, Result};
+        use std::ffi::{c_void, CString};
+        use std::mem::size_of;
+
+        const BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+        const MEMORY_MMAP: u32 = 1;
+        const FIELD_ANY: u32 = 0;
+        const CAP_VIDEO_CAPTURE: u32 = 0x0000_0001;
+        const CAP_STREAMING: u32 = 0x0400_0000;
+        const CAP_DEVICE_CAPS: u32 = 0x8000_0000;
+        const CAP_TIMEPERFRAME: u32 = 0x1000;
+        const BUFFER_COUNT: u32 = 4;
+
+        const fn fourcc(code: &[u8; 4]) -> u32 {
+            (code[0] as u32) | (code[1] as u32) << 8 | (code[2] as u32) << 16 | (code[3] as u32) << 24
+        }
+        const PIX_FMT_YUYV: u32 = fourcc(b"YUYV");
+        const PIX_FMT_MJPEG: u32 = fourcc(b"MJPG");
+
+        #[repr(C)]
+        struct Capability {
+            driver: [u8; 16],
+            card: [u8; 32],
+            bus_info: [u8; 32],
+            version: u32,
+            capabilities: u32,
+            device_caps: u32,
+            reserved: [u32; 3],
+        }
+
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        struct PixFormat {
+            width: u32,
+            height: u32,
+            pixelformat: u32,
+            field: u32,
+            bytesperline: u32,
+            sizeimage: u32,
+            colorspace: u32,
+            priv_: u32,
+            flags: u32,
+            ycbcr_enc: u32,
+            quantization: u32,
+            xfer_func: u32,
+        }
+
+        // The kernel union also holds pointer-bearing members, which set
+        // its alignment.
+        #[repr(C)]
+        union FormatUnion {
+            pix: PixFormat,
+            raw: [u8; 200],
+            _align: [*mut c_void; 0],
+        }
+
+        #[repr(C)]
+        struct Format {
+            typ: u32,
+            fmt: FormatUnion,
+        }
+
+        #[repr(C)]
+        struct RequestBuffers {
+            count: u32,
+            typ: u32,
+            memory: u32,
+            capabilities: u32,
+            flags: u8,
+            reserved: [u8; 3],
+        }
+
+        #[repr(C)]
+        struct Timecode {
+            typ: u32,
+            flags: u32,
+            frames: u8,
+            seconds: u8,
+            minutes: u8,
+            hours: u8,
+            userbits: [u8; 4],
+        }
+
+        #[repr(C)]
+        union BufferM {
+            offset: u32,
+            userptr: libc::c_ulong,
+            planes: *mut c_void,
+            fd: i32,
+        }
+
+        #[repr(C)]
+        struct Buffer {
+            index: u32,
+            typ: u32,
+            bytesused: u32,
+            flags: u32,
+            field: u32,
+            timestamp: libc::timeval,
+            timecode: Timecode,
+            sequence: u32,
+            memory: u32,
+            m: BufferM,
+            length: u32,
+            reserved2: u32,
+            request_fd: i32,
+        }
+
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        struct Fract {
+            numerator: u32,
+            denominator: u32,
+        }
+
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        struct CaptureParm {
+            capability: u32,
+            capturemode: u32,
+            timeperframe: Fract,
+            extendedmode: u32,
+            readbuffers: u32,
+            reserved: [u32; 4],
+        }
+
+        #[repr(C)]
+        union StreamParmUnion {
+            capture: CaptureParm,
+            raw: [u8; 200],
+        }
+
+        #[repr(C)]
+        struct StreamParm {
+            typ: u32,
+            parm: StreamParmUnion,
+        }
+
+        const fn ioc(dir: u64, nr: u64, size: usize) -> u64 {
+            dir << 30 | (size as u64) << 16 | (b'V' as u64) << 8 | nr
+        }
+        const READ: u64 = 2;
+        const WRITE: u64 = 1;
+        const VIDIOC_QUERYCAP: u64 = ioc(READ, 0, size_of::<Capability>());
+        const VIDIOC_S_FMT: u64 = ioc(READ | WRITE, 5, size_of::<Format>());
+        const VIDIOC_REQBUFS: u64 = ioc(READ | WRITE, 8, size_of::<RequestBuffers>());
+        const VIDIOC_QUERYBUF: u64 = ioc(READ | WRITE, 9, size_of::<Buffer>());
+        const VIDIOC_QBUF: u64 = ioc(READ | WRITE, 15, size_of::<Buffer>());
+        const VIDIOC_DQBUF: u64 = ioc(READ | WRITE, 17, size_of::<Buffer>());
+        const VIDIOC_STREAMON: u64 = ioc(WRITE, 18, size_of::<i32>());
+        const VIDIOC_STREAMOFF: u64 = ioc(WRITE, 19, size_of::<i32>());
+        const VIDIOC_S_PARM: u64 = ioc(READ | WRITE, 22, size_of::<StreamParm>());
+
+        struct Mapping {
+            ptr: *mut c_void,
+            len: usize,
+        }
+
+        pub struct Device {
+            fd: i32,
+            buffers: Vec<Mapping>,
+            streaming: bool,
+            pixelformat: u32,
+            bytesperline: u32,
+            pub width: u32,
+            pub height: u32,
+            pub fps: u32,
+        }
+
+        impl Device {
+            pub fn open(path: &str, width: u32, height: u32, fps: u32) -> Result<Self> {
+                let c_path = CString::new(path)?;
+                let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_RDWR | libc::O_CLOEXEC) };
+                if fd < 0 {
+                    return Err(os_error("open", path));
+                }
+                // From here on Drop closes the fd and unmaps whatever was
+                // mapped, however far setup got.
+                let mut device = Device {
+                    fd,
+                    buffers: Vec::new(),
+                    streaming: false,
+                    pixelformat: 0,
+                    bytesperline: 0,
+                    width,
+                    height,
+                    fps,
+                };
+                device.configure(path)?;
+                Ok(device)
+            }
+
+            fn configure(&mut self, path: &str) -> Result<()> {
+                let mut cap: Capability = unsafe { std::mem::zeroed() };
+                self.ioctl(VIDIOC_QUERYCAP, &mut cap, "VIDIOC_QUERYCAP", path)?;
+                let caps = if cap.capabilities & CAP_DEVICE_CAPS != 0 { cap.device_caps } else { cap.capabilities };
+                if caps & CAP_VIDEO_CAPTURE == 0 || caps & CAP_STREAMING == 0 {
+                    return Err(Error::from(format!("{} is not a streaming capture device", path)));
+                }
+
+                // YUYV converts without a decoder; MJPEG is the fallback
+                // for cameras that only offer that.
+                let mut negotiated = None;
+                for pixelformat in [PIX_FMT_YUYV, PIX_FMT_MJPEG] {
+                    let mut format: Format = unsafe { std::mem::zeroed() };
+                    format.typ = BUF_TYPE_VIDEO_CAPTURE;
+                    format.fmt.pix = PixFormat {
+                        width: self.width,
+                        height: self.height,
+                        pixelformat,
+                        field: FIELD_ANY,
+                        ..unsafe { std::mem::zeroed() }
+                    };
+                    self.ioctl(VIDIOC_S_FMT, &mut format, "VIDIOC_S_FMT", path)?;
+                    let pix = unsafe { format.fmt.pix };
+                    if pix.pixelformat == pixelformat {
+                        negotiated = Some(pix);
+                        break;
+                    }
+                }
+                let pix = negotiated.ok_or_else(|| Error::from(format!("{} supports neither YUYV nor MJPEG", path)))?;
+                (self.width, self.height) = (pix.width, pix.height);
+                self.pixelformat = pix.pixelformat;
+                self.bytesperline = pix.bytesperline.max(pix.width * 2);
+
+                let mut parm: StreamParm = unsafe { std::mem::zeroed() };
+                parm.typ = BUF_TYPE_VIDEO_CAPTURE;
+                parm.parm.capture.timeperframe = Fract { numerator: 1, denominator: self.fps };
+                // Not every driver lets the frame rate be chosen.
+                if self.ioctl(VIDIOC_S_PARM, &mut parm, "VIDIOC_S_PARM", path).is_ok() {
+                    let capture = unsafe { parm.parm.capture };
+                    if capture.capability & CAP_TIMEPERFRAME != 0 && capture.timeperframe.numerator > 0 {
+                        self.fps = capture.timeperframe.denominator / capture.timeperframe.numerator;
+                    }
+                }
+
+                let mut request = RequestBuffers {
+                    count: BUFFER_COUNT,
+                    typ: BUF_TYPE_VIDEO_CAPTURE,
+                    memory: MEMORY_MMAP,
+                    capabilities: 0,
+                    flags: 0,
+                    reserved: [0; 3],
+                };
+                self.ioctl(VIDIOC_REQBUFS, &mut request, "VIDIOC_REQBUFS", path)?;
+                for index in 0..request.count {
+                    let mut buffer = new_buffer(index);
+                    self.ioctl(VIDIOC_QUERYBUF, &mut buffer, "VIDIOC_QUERYBUF", path)?;
+                    let len = buffer.length as usize;
+                    let ptr = unsafe {
+                        libc::mmap(
+                            std::ptr::null_mut(),
+                            len,
+                            libc::PROT_READ | libc::PROT_WRITE,
+                            libc::MAP_SHARED,
+                            self.fd,
+                            buffer.m.offset as libc::off_t,
+                        )
+                    };
+                    if ptr == libc::MAP_FAILED {
+                        return Err(os_error("mmap", path));
+                    }
+                    self.buffers.push(Mapping { ptr, len });
+                    self.ioctl(VIDIOC_QBUF, &mut buffer, "VIDIOC_QBUF", path)?;
+                }
+
+                let mut typ = BUF_TYPE_VIDEO_CAPTURE as i32;
+                self.ioctl(VIDIOC_STREAMON, &mut typ, "VIDIOC_STREAMON", path)?;
+                self.streaming = true;
+                Ok(())
+            }
+
+            // Blocks until the driver fills a buffer, converts it and hands
+            // the buffer straight back.
+            pub fn read(&mut self) -> Result<Mat> {
+                let mut buffer = new_buffer(0);
+                self.ioctl(VIDIOC_DQBUF, &mut buffer, "VIDIOC_DQBUF", "device")?;
+                let mapping = &self.buffers[buffer.index as usize];
+                let used = (buffer.bytesused as usize).min(mapping.len);
+                let data = unsafe { std::slice::from_raw_parts(mapping.ptr as *const u8, used) };
+                let (width, height) = (self.width as usize, self.height as usize);
+                let frame = if self.pixelformat == PIX_FMT_MJPEG {
+                    crate::imgcodecs::imdecode(data)
+                } else if used < self.bytesperline as usize * (height - 1) + width * 2 {
+                    Err(Error::from(format!("Short YUYV frame: {} bytes", used)))
+                } else {
+                    let bgr = yuyv_to_bgr(data, width, height, self.bytesperline as usize);
+                    Mat::from_slice_2d(height as i32, width as i32, 3, &bgr)
+                };
+                self.ioctl(VIDIOC_QBUF, &mut buffer, "VIDIOC_QBUF", "device")?;
+                frame
+            }
+
+            fn ioctl<T>(&self, request: u64, arg: &mut T, name: &str, path: &str) -> Result<()> {
+                loop {
+                    let ret = unsafe { libc::ioctl(self.fd, request as _, arg as *mut T) };
+                    if ret >= 0 {
+                        return Ok(());
+                    }
+                    if std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+                        return Err(os_error(name, path));
+                    }
+                }
+            }
+        }
+
+        impl Drop for Device {
+            fn drop(&mut self) {
+                if self.streaming {
+                    let mut typ = BUF_TYPE_VIDEO_CAPTURE as i32;
+                    let _ = self.ioctl(VIDIOC_STREAMOFF, &mut typ, "VIDIOC_STREAMOFF", "device");
+                }
+                for mapping in self.buffers.drain(..) {
+                    unsafe { libc::munmap(mapping.ptr, mapping.len) };
+                }
+                unsafe { libc::close(self.fd) };
+            }
+        }
+
+        fn new_buffer(index: u32) -> Buffer {
+            let mut buffer: Buffer = unsafe { std::mem::zeroed() };
+            buffer.index = index;
+            buffer.typ = BUF_TYPE_VIDEO_CAPTURE;
+            buffer.memory = MEMORY_MMAP;
+            buffer
+        }
+
+        fn os_error(call: &str, path: &str) -> Error {
+            Error::from(format!("{} on {} failed: {}", call, path, std::io::Error::last_os_error()))
+        }
     }
 }
@@ -1,40 +1,164 @@
 #include <opencv2/dnn.hpp>
 #include <opencv2/opencv.hpp>
 #include <iostream>
+#include <string>
 #include <vector>
 
+// Set by every failing call; read from Rust through opencv_wrapper_last_error.
+static thread_local std::string last_error;
+
+static void set_error(const std::string& message) {
+    last_error = message;
+}
+
 extern "C" {
     cv::dnn::Net* global_net = nullptr;
+
+    const char* opencv_wrapper_last_error() {
+        return last_error.c_str();
+    }
     
+    static void* keep_net(cv::dnn::Net net) {
+        if (net.empty()) {
+            set_error("network has no layers");
+            return nullptr;
+        }
+        
+        net.setPreferableBackend(cv::dnn::DNN_BACKEND_OPENCV);
+        net.setPreferableTarget(cv::dnn::DNN_TARGET_CPU);
+        
+        global_net = new cv::dnn::Net(net);
+        std::cout << "Network loaded successfully!" << std::endl;
+        return global_net;
+    }
+
     void* opencv_dnn_readNetFromDarknet(const char* cfg, const char* weights) {
         try {
             std::cout << "Loading YOLO from " << cfg << " and " << weights << std::endl;
-            cv::dnn::Net net = cv::dnn::readNetFromDarknet(cfg, weights);
-            if (net.empty()) {
-                std::cout << "Failed to load network!" << std::endl;
-                return nullptr;
-            }
-            
-            net.setPreferableBackend(cv::dnn::DNN_BACKEND_OPENCV);
-            net.setPreferableTarget(cv::dnn::DNN_TARGET_CPU);
-            
-            global_net = new cv::dnn::Net(net);
-            std::cout << "Network loaded successfully!" << std::endl;
-            return global_net;
+            return keep_net(cv::dnn::readNetFromDarknet(cfg, weights));
+        } catch (const cv::Exception& e) {
+            set_error(e.what());
+            return nullptr;
+        }
+    }
+
+    void* opencv_dnn_readNetFromONNX(const char* model) {
+        try {
+            std::cout << "Loading ONNX model from " << model << std::endl;
+            return keep_net(cv::dnn::readNetFromONNX(model));
+        } catch (const cv::Exception& e) {
+            set_error(e.what());
+            return nullptr;
+        }
+    }
+
+    void* opencv_dnn_readNet(const char* model, const char* config, const char* framework) {
+        try {
+            std::cout << "Loading " << framework << " model from " << model << std::endl;
+            return keep_net(cv::dnn::readNet(model, config, framework));
         } catch (const cv::Exception& e) {
-            std::cout << "Error: " << e.what() << std::endl;
+            set_error(e.what());
             return nullptr;
         }
     }
+
+    int opencv_dnn_setPreferableBackend(void* net_ptr, int backend) {
+        try {
+            if (!net_ptr) {
+                set_error("null network");
+                return -1;
+            }
+            ((cv::dnn::Net*)net_ptr)->setPreferableBackend(backend);
+            return 0;
+        } catch (const cv::Exception& e) {
+            set_error(e.what());
+            return -1;
+        }
+    }
+
+    int opencv_dnn_setPreferableTarget(void* net_ptr, int target) {
+        try {
+            if (!net_ptr) {
+                set_error("null network");
+                return -1;
+            }
+            ((cv::dnn::Net*)net_ptr)->setPreferableTarget(target);
+            return 0;
+        } catch (const cv::Exception& e) {
+            set_error(e.what());
+            return -1;
+        }
+    }
+
+    // Writes up to max (backend, target) pairs and returns how many exist.
+    int opencv_dnn_getAvailableBackends(int* backends, int* targets, int max) {
+        try {
+            std::vector<std::pair<cv::dnn::Backend, cv::dnn::Target>> available = cv::dnn::getAvailableBackends();
+            for (int i = 0; i < (int)available.size() && i < max; i++) {
+                backends[i] = available[i].first;
+                targets[i] = available[i].second;
+            }
+            return (int)available.size();
+        } catch (const cv::Exception& e) {
+            set_error(e.what());
+            return -1;
+        }
+    }
+
+    // Newline-separated; the string stays valid until the next call on
+    // this thread.
+    const char* opencv_dnn_getLayerNames(void* net_ptr) {
+        static thread_local std::string names;
+        names.clear();
+        if (!net_ptr) return names.c_str();
+        cv::dnn::Net* net = (cv::dnn::Net*)net_ptr;
+        for (const auto& name : net->getLayerNames()) {
+            if (!names.empty()) names += '\n';
+            names += name;
+        }
+        return names.c_str();
+    }
+
+    // Writes up to max ids into out and returns how many the net has.
+    int opencv_dnn_getUnconnectedOutLayers(void* net_ptr, int* out, int max) {
+        if (!net_ptr) return 0;
+        cv::dnn::Net* net = (cv::dnn::Net*)net_ptr;
+        std::vector<int> layers = net->getUnconnectedOutLayers();
+        for (int i = 0; i < (int)layers.size() && i < max; i++) {
+            out[i] = layers[i];
+        }
+        return (int)layers.size();
+    }
     
     int opencv_dnn_setInput(void* net_ptr, void* blob_ptr) {
         try {
-            if (!net_ptr || !blob_ptr) return -1;
+            if (!net_ptr || !blob_ptr) {
+                set_error(!net_ptr ? "null network" : "null blob");
+                return -1;
+            }
             cv::dnn::Net* net = (cv::dnn::Net*)net_ptr;
             cv::Mat* blob = (cv::Mat*)blob_ptr;
             net->setInput(*blob);
             return 0;
-        } catch (...) {
+        } catch (const cv::Exception& e) {
+            set_error(e.what());
+            return -1;
+        }
+    }
+    
+    int opencv_dnn_setInputData(void* net_ptr, const float* data, const int* dims, int ndims) {
+        try {
+            if (!net_ptr || !data) {
+                set_error(!net_ptr ? "null network" : "null blob data");
+                return -1;
+            }
+            cv::dnn::Net* net = (cv::dnn::Net*)net_ptr;
+            // Copied, since the Rust side may free its buffer before forward.
+            cv::Mat blob(ndims, dims, CV_32F, (void*)data);
+            net->setInput(blob.clone());
+            return 0;
+        } catch (const cv::Exception& e) {
+            set_error(e.what());
             return -1;
         }
     }
@@ -42,12 +166,11 @@ extern "C" {
     void* opencv_dnn_forward(void* net_ptr, void* blob_ptr, const char* layer_name, void* output_ptr) {
         try {
             if (!net_ptr) {
-                std::cout << "Null network pointer!" << std::endl;
+                set_error("null network");
                 return nullptr;
             }
             
             cv::dnn::Net* net = (cv::dnn::Net*)net_ptr;
-            cv::Mat* output = new cv::Mat();
             
             std::vector<cv::String> outNames;
             if (layer_name && strlen(layer_name) > 0) {
@@ -59,6 +182,7 @@ extern "C" {
             std::vector<cv::Mat> outputs;
             net->forward(outputs, outNames);
             
+            cv::Mat* output = new cv::Mat();
             if (!outputs.empty()) {
                 *output = outputs[0].clone();
                 std::cout << "Forward pass output: " << output->rows << "x" << output->cols << std::endl;
@@ -66,32 +190,84 @@ extern "C" {
             
             return output;
         } catch (const cv::Exception& e) {
-            std::cout << "Forward error: " << e.what() << std::endl;
+            set_error(e.what());
             return nullptr;
         }
     }
     
-    void* opencv_dnn_blobFromImage(void* image_ptr, double scale, int width, int height) {
+    // Runs the net once for all requested outputs (the unconnected output
+    // layers when count is 0). Each output is a new cv::Mat the caller
+    // releases. Returns how many were written, or -1 on error.
+    int opencv_dnn_forwardAll(void* net_ptr, const char** names, int count, void** outputs, int max_outputs) {
         try {
-            cv::Mat image;
-            if (image_ptr) {
-                unsigned char* data = (unsigned char*)image_ptr;
-                cv::Mat temp(480, 640, CV_8UC3, data);
-                image = temp.clone();
-            } else {
-                image = cv::Mat::zeros(480, 640, CV_8UC3);
+            if (!net_ptr) {
+                set_error("null network");
+                return -1;
             }
+            cv::dnn::Net* net = (cv::dnn::Net*)net_ptr;
             
-            cv::Mat* blob = new cv::Mat();
-            *blob = cv::dnn::blobFromImage(image, scale, cv::Size(width, height), 
-                                          cv::Scalar(0,0,0), true, false);
+            std::vector<cv::String> outNames;
+            for (int i = 0; i < count; i++) {
+                outNames.push_back(names[i]);
+            }
+            if (outNames.empty()) {
+                outNames = net->getUnconnectedOutLayersNames();
+            }
             
-            std::cout << "Created blob: " << blob->size[0] << "x" << blob->size[1] 
-                      << "x" << blob->size[2] << "x" << blob->size[3] << std::endl;
+            std::vector<cv::Mat> results;
+            net->forward(results, outNames);
             
+            int written = 0;
+            for (const auto& result : results) {
+                if (written >= max_outputs) break;
+                cv::Mat* output = new cv::Mat();
+                result.convertTo(*output, CV_32F);
+                outputs[written++] = output;
+            }
+            return written;
+        } catch (const cv::Exception& e) {
+            set_error(e.what());
+            return -1;
+        }
+    }
+
+    // Shape of an output and a pointer to its contiguous float data.
+    // Returns the number of dims, writing at most max_dims of them.
+    int opencv_dnn_getOutputBuffer(void* mat_ptr, int* dims, int max_dims, const float** data, size_t* len) {
+        if (!mat_ptr) {
+            set_error("null output");
+            return -1;
+        }
+        cv::Mat* mat = (cv::Mat*)mat_ptr;
+        if (!mat->isContinuous()) {
+            *mat = mat->clone();
+        }
+        for (int i = 0; i < mat->dims && i < max_dims; i++) {
+            dims[i] = mat->size[i];
+        }
+        *data = mat->ptr<float>();
+        *len = mat->total() * mat->channels();
+        return mat->dims;
+    }
+    
+    // `data` is `rows` x `cols` pixels of `type`, `step` bytes from one row
+    // to the next; `mean` is four values in the blob's channel order.
+    void* opencv_dnn_blobFromImage(const unsigned char* data, int rows, int cols, int type, size_t step,
+                                   double scale, int width, int height, const double* mean,
+                                   int swap_rb, int crop, int ddepth) {
+        try {
+            if (!data || !mean) {
+                set_error(!data ? "null image data" : "null mean");
+                return nullptr;
+            }
+            cv::Mat image(rows, cols, type, (void*)data, step);
+            cv::Mat* blob = new cv::Mat();
+            *blob = cv::dnn::blobFromImage(image, scale, cv::Size(width, height),
+                                          cv::Scalar(mean[0], mean[1], mean[2], mean[3]),
+                                          swap_rb != 0, crop != 0, ddepth);
             return blob;
         } catch (const cv::Exception& e) {
-            std::cout << "Blob error: " << e.what() << std::endl;
+            set_error(e.what());
             return nullptr;
         }
     }
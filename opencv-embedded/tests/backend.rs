@@ -0,0 +1,61 @@
+// opencv-embedded/tests/backend.rs
+//
+// Which backend a build gets. The pure-Rust one needs no OpenCV and has
+// everything but dnn; asking for the system one where OpenCV can't be
+// found stops the build with instructions, rather than failing to link.
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use opencv_embedded::core::{Mat, Size, CV_8UC3};
+use opencv_embedded::{imgproc, SYSTEM_OPENCV};
+
+#[test]
+fn the_backend_constant_follows_the_build() {
+    assert_eq!(SYSTEM_OPENCV, cfg!(system_opencv));
+    // Off aarch64, only the feature links OpenCV.
+    if !cfg!(target_arch = "aarch64") {
+        assert_eq!(SYSTEM_OPENCV, cfg!(feature = "system-opencv"));
+    }
+}
+
+#[test]
+fn mats_and_imgproc_work_on_either_backend() {
+    let data: Vec<u8> = (0..8 * 6 * 3).map(|i| i as u8).collect();
+    let mat = Mat::from_slice_2d(6, 8, 3, &data).unwrap();
+    let mut small = Mat::default();
+    imgproc::resize(&mat, &mut small, Size::new(4, 3), imgproc::INTER_NEAREST).unwrap();
+    assert_eq!((small.cols(), small.rows(), small.typ()), (4, 3, CV_8UC3));
+    assert_eq!(small.get::<[u8; 3]>(1, 1).unwrap(), mat.get::<[u8; 3]>(2, 2).unwrap());
+}
+
+// Where build.rs looks for OpenCV when pkg-config has nothing.
+fn opencv_installed() -> bool {
+    ["/usr/lib/aarch64-linux-gnu", "/usr/local/lib"].iter().any(|dir| Path::new(dir).join("libopencv_core.so").exists())
+}
+
+#[test]
+fn the_system_backend_without_opencv_stops_the_build_with_instructions() {
+    if opencv_installed() {
+        eprintln!("skipped: OpenCV is installed, so the build would find it");
+        return;
+    }
+    let scratch = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("backend");
+    let empty = scratch.join("no-pkg-config");
+    std::fs::create_dir_all(&empty).unwrap();
+    let output = Command::new(env!("CARGO"))
+        .args(["check", "--offline", "--lib", "--features", "system-opencv", "--manifest-path"])
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(scratch.join("target"))
+        // pkg-config then finds no opencv4, wherever the system keeps it.
+        .env("PKG_CONFIG_PATH", &empty)
+        .env("PKG_CONFIG_LIBDIR", &empty)
+        .env_remove("OPENCV_EMBEDDED_PURE_RUST")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "built without OpenCV:\n{}", stderr);
+    assert!(stderr.contains("OpenCV 4 wasn't found"), "{}", stderr);
+    assert!(stderr.contains("libopencv-dev"), "{}", stderr);
+    assert!(stderr.contains("drop the system-opencv feature"), "{}", stderr);
+}
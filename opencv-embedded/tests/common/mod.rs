@@ -0,0 +1,21 @@
+// opencv-embedded/tests/common/mod.rs
+//
+// Shared by the integration tests that need real model files, which are
+// too big to check in. SCOUT_TEST_MODELS names a directory laid out like
+// the rover's models/: yolov8n.onnx and coco.names, and optionally
+// yolov3-tiny.cfg and yolov3-tiny.weights. Without it those tests pass
+// after saying they were skipped.
+use std::path::PathBuf;
+
+pub fn model(name: &str) -> Option<String> {
+    let Some(dir) = std::env::var_os("SCOUT_TEST_MODELS") else {
+        eprintln!("skipped: set SCOUT_TEST_MODELS to a directory with {}", name);
+        return None;
+    };
+    let path = PathBuf::from(dir).join(name);
+    if !path.exists() {
+        eprintln!("skipped: no {} in SCOUT_TEST_MODELS", path.display());
+        return None;
+    }
+    Some(path.to_string_lossy().into_owned())
+}
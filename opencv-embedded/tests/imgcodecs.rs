@@ -0,0 +1,99 @@
+// opencv-embedded/tests/imgcodecs.rs
+//
+// imread/imwrite through temp files: lossless formats come back byte for
+// byte in BGR order, JPEG within its loss, and an unsupported extension,
+// a missing file or a mat no format takes are errors rather than empty
+// mats or silent successes.
+#![cfg(feature = "imgcodecs")]
+use std::path::PathBuf;
+
+use opencv_embedded::core::{Mat, Rect, CV_32FC1, CV_8UC1, CV_8UC3};
+use opencv_embedded::imgcodecs;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("imgcodecs").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// 16x12 BGR, with every channel different so a swap would show.
+fn bgr() -> Mat {
+    let data: Vec<u8> = (0..12u8).flat_map(|y| (0..16u8).flat_map(move |x| [x * 16, y * 20, 255 - x * 8 - y])).collect();
+    Mat::from_slice_2d(12, 16, 3, &data).unwrap()
+}
+
+#[test]
+fn png_and_bmp_round_trip_byte_for_byte() {
+    let dir = scratch("lossless");
+    let mat = bgr();
+    for name in ["frame.png", "frame.bmp"] {
+        let path = dir.join(name);
+        imgcodecs::imwrite(path.to_str().unwrap(), &mat).unwrap();
+        let back = imgcodecs::imread(path.to_str().unwrap()).unwrap();
+        assert_eq!((back.rows(), back.cols(), back.typ()), (12, 16, CV_8UC3), "{}", name);
+        assert_eq!(back.to_vec::<u8>().unwrap(), mat.to_vec::<u8>().unwrap(), "{}", name);
+    }
+    let written = std::fs::read(dir.join("frame.png")).unwrap();
+    assert_eq!(imgcodecs::imdecode(&written).unwrap().to_vec::<u8>().unwrap(), mat.to_vec::<u8>().unwrap());
+}
+
+#[test]
+fn grayscale_round_trips_and_color_reads_as_gray() {
+    let dir = scratch("gray");
+    let gray = Mat::from_slice_2d(4, 8, 1, &(0..32u8).map(|v| v * 8).collect::<Vec<_>>()).unwrap();
+    let path = dir.join("gray.png");
+    imgcodecs::imwrite(path.to_str().unwrap(), &gray).unwrap();
+    let back = imgcodecs::imread_grayscale(path.to_str().unwrap()).unwrap();
+    assert_eq!(back.typ(), CV_8UC1);
+    assert_eq!(back.to_vec::<u8>().unwrap(), gray.to_vec::<u8>().unwrap());
+    // Read in color, each gray value lands in all three channels.
+    let color = imgcodecs::imread(path.to_str().unwrap()).unwrap();
+    assert_eq!(color.get::<[u8; 3]>(1, 2).unwrap(), [80, 80, 80]);
+
+    let path = dir.join("color.png");
+    imgcodecs::imwrite(path.to_str().unwrap(), &bgr()).unwrap();
+    let back = imgcodecs::imread_grayscale(path.to_str().unwrap()).unwrap();
+    assert_eq!((back.rows(), back.cols(), back.typ()), (12, 16, CV_8UC1));
+}
+
+#[test]
+fn jpeg_round_trips_within_its_loss() {
+    let path = scratch("jpeg").join("frame.jpg");
+    let mat = bgr();
+    imgcodecs::imwrite(path.to_str().unwrap(), &mat).unwrap();
+    let back = imgcodecs::imread(path.to_str().unwrap()).unwrap();
+    assert_eq!((back.rows(), back.cols(), back.typ()), (12, 16, CV_8UC3));
+    let (sent, got) = (mat.to_vec::<u8>().unwrap(), back.to_vec::<u8>().unwrap());
+    let mean_error = sent.iter().zip(&got).map(|(a, b)| a.abs_diff(*b) as f64).sum::<f64>() / sent.len() as f64;
+    assert!(mean_error < 8.0, "mean error {}", mean_error);
+}
+
+#[test]
+fn a_view_is_written_as_its_own_pixels() {
+    let path = scratch("view").join("view.png");
+    let mat = bgr();
+    let view = mat.roi(Rect::new(3, 2, 5, 4)).unwrap();
+    imgcodecs::imwrite(path.to_str().unwrap(), &view).unwrap();
+    let back = imgcodecs::imread(path.to_str().unwrap()).unwrap();
+    assert_eq!(back.to_vec::<u8>().unwrap(), view.to_vec::<u8>().unwrap());
+}
+
+#[test]
+fn unsupported_extensions_missing_files_and_float_mats_are_errors() {
+    let dir = scratch("errors");
+    let path = dir.join("frame.xyz");
+    let error = imgcodecs::imwrite(path.to_str().unwrap(), &bgr()).unwrap_err();
+    assert!(error.to_string().contains("Unsupported image extension"), "{}", error);
+    assert!(!path.exists());
+    assert!(imgcodecs::imwrite(dir.join("no-extension").to_str().unwrap(), &bgr()).is_err());
+
+    assert!(imgcodecs::imread(dir.join("missing.png").to_str().unwrap()).is_err());
+    assert!(imgcodecs::imread_grayscale(dir.join("missing.png").to_str().unwrap()).is_err());
+    std::fs::write(dir.join("garbage.png"), b"not a png").unwrap();
+    assert!(imgcodecs::imread(dir.join("garbage.png").to_str().unwrap()).is_err());
+    assert!(imgcodecs::imdecode(b"not an image").is_err());
+
+    let floats = Mat::zeros(4, 4, CV_32FC1).unwrap();
+    assert!(imgcodecs::imwrite(dir.join("floats.png").to_str().unwrap(), &floats).is_err());
+}
@@ -0,0 +1,64 @@
+// opencv-embedded/tests/models.rs
+//
+// Loading real models through the system OpenCV: ONNX and Darknet nets
+// load by their own loaders and through read_net's format detection, and
+// report the layers they actually have. The model files come from
+// SCOUT_TEST_MODELS (see common/mod.rs); the error cases need none.
+#![cfg(system_opencv)]
+mod common;
+
+use opencv_embedded::core::{Mat, Scalar, Size, CV_32F, CV_8UC3};
+use opencv_embedded::dnn;
+
+// Each unconnected output is named, by OpenCV's 1-based layer id.
+fn output_names(net: &dnn::Net) -> Vec<String> {
+    let names = net.get_layer_names().unwrap();
+    assert!(!names.is_empty());
+    net.get_unconnected_out_layers()
+        .unwrap()
+        .into_iter()
+        .map(|id| names.get(id as usize - 1).cloned().unwrap_or_else(|| panic!("layer id {} of {}", id, names.len())))
+        .collect()
+}
+
+#[test]
+fn an_onnx_yolov8_loads_and_runs_either_way() {
+    let Some(model) = common::model("yolov8n.onnx") else { return };
+    let net = dnn::read_net_from_onnx(&model).unwrap();
+    let outputs = output_names(&net);
+    assert_eq!(outputs.len(), 1);
+    // Not the YOLOv3-tiny heads the old getters made up.
+    assert!(!outputs.iter().any(|name| name.starts_with("yolo_")), "{:?}", outputs);
+
+    let net = dnn::read_net(&model, "", "").unwrap();
+    assert_eq!(output_names(&net), outputs);
+
+    let image = Mat::zeros(480, 640, CV_8UC3).unwrap();
+    let blob = dnn::blob_from_image(&image, 1.0 / 255.0, Size::new(640, 640), Scalar::default(), true, false, CV_32F).unwrap();
+    let mut results = Vec::new();
+    net.forward(&blob, &mut results, &Vec::new()).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].dims(), &[1, 84, 8400]);
+    let values = results[0].to_vec::<f32>().unwrap();
+    dnn::decode_yolo_output(&values, results[0].dims(), 80, 0.25, 1.0, 0.75).unwrap();
+}
+
+#[test]
+fn a_darknet_yolov3_tiny_reports_its_two_heads() {
+    let (Some(cfg), Some(weights)) = (common::model("yolov3-tiny.cfg"), common::model("yolov3-tiny.weights")) else { return };
+    let net = dnn::read_net_from_darknet(&cfg, &weights).unwrap();
+    let outputs = output_names(&net);
+    assert_eq!(outputs.len(), 2, "{:?}", outputs);
+
+    let net = dnn::read_net(&weights, &cfg, "").unwrap();
+    assert_eq!(output_names(&net), outputs);
+}
+
+#[test]
+fn unknown_formats_and_missing_files_are_errors() {
+    let error = dnn::read_net("model.bin", "", "").err().unwrap();
+    assert!(error.to_string().contains("model format"), "{}", error);
+    assert!(dnn::read_net_from_onnx("/nonexistent/model.onnx").is_err());
+    assert!(dnn::read_net("/nonexistent/model.onnx", "", "").is_err());
+    assert!(dnn::read_net_from_darknet("/nonexistent/a.cfg", "/nonexistent/a.weights").is_err());
+}
@@ -0,0 +1,57 @@
+// opencv-embedded/tests/soak.rs
+//
+// Creates and drops 100k mats, with views and copies of them, and checks
+// the process's resident memory stays flat: every owned allocation is
+// released once, and views and borrowed mats release nothing. Linux only,
+// as RSS comes from /proc/self/statm.
+#![cfg(target_os = "linux")]
+use opencv_embedded::core::{Mat, MatBuffer, Mat_AUTO_STEP, Rect, CV_32FC1, CV_8UC3};
+
+const MATS: usize = 100_000;
+// 64x64 BGR is 12 KB, so a leak of every mat would be over a gigabyte; a
+// few megabytes of allocator slack are fine.
+const MAX_GROWTH_BYTES: u64 = 16 << 20;
+
+fn rss_bytes() -> u64 {
+    let statm = std::fs::read_to_string("/proc/self/statm").unwrap();
+    let pages: u64 = statm.split_whitespace().nth(1).unwrap().parse().unwrap();
+    pages * unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64
+}
+
+fn churn(count: usize) {
+    let mut borrowed = vec![0u8; 64 * 64 * 3];
+    for i in 0..count {
+        let mut mat = Mat::zeros(64, 64, CV_8UC3).unwrap();
+        mat.set(i as i32 % 64, 0, [i as u8, 0, 0]).unwrap();
+        {
+            let row = mat.row(i as i32 % 64).unwrap();
+            let roi = mat.roi(Rect::new(8, 8, 16, 16)).unwrap();
+            assert!(!row.is_owned() && !roi.is_owned());
+            let copy = roi.clone_owned().unwrap();
+            assert!(copy.is_owned());
+        }
+        let wrapped = unsafe { Mat::new_rows_cols_with_data(64, 64, CV_8UC3, borrowed.as_mut_ptr().cast(), Mat_AUTO_STEP).unwrap() };
+        assert!(!wrapped.is_owned());
+        drop(wrapped);
+
+        if i % 16 == 0 {
+            let floats = Mat::from_slice_2d(32, 32, 1, &[0.5f32; 32 * 32]).unwrap();
+            assert_eq!(floats.typ(), CV_32FC1);
+            let buffer = MatBuffer::from_mat(&floats).unwrap();
+            drop(buffer.to_mat().unwrap());
+        }
+    }
+    std::hint::black_box(&borrowed);
+}
+
+#[test]
+fn creating_and_dropping_mats_keeps_rss_flat() {
+    // Warm the allocator up first, so its own pools don't count as growth.
+    churn(MATS / 10);
+    let before = rss_bytes();
+    churn(MATS);
+    let after = rss_bytes();
+    let growth = after.saturating_sub(before);
+    println!("RSS {} KB before, {} KB after {} mats", before / 1024, after / 1024, MATS);
+    assert!(growth <= MAX_GROWTH_BYTES, "RSS grew by {} KB over {} mats", growth / 1024, MATS);
+}
@@ -0,0 +1,129 @@
+// opencv-embedded/tests/threads.rs
+//
+// What may cross threads and what may not: MatBuffer is Send and Sync,
+// Net only Send, and SharedNet shares one net between threads. Frames
+// carried as MatBuffers are processed on four threads at once and come
+// out as they do on one, and with a model in SCOUT_TEST_MODELS four
+// threads hammer a SharedNet. Run under ThreadSanitizer with
+//   RUSTFLAGS=-Zsanitizer=thread cargo +nightly test --test threads \
+//     -Zbuild-std --target x86_64-unknown-linux-gnu
+#[cfg(system_opencv)]
+mod common;
+
+use std::sync::mpsc;
+use std::thread;
+
+use opencv_embedded::core::{Mat, MatBuffer, Size, CV_8UC3};
+use opencv_embedded::imgproc;
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 50;
+
+fn assert_send<T: Send>() {}
+fn assert_sync<T: Sync>() {}
+
+#[test]
+fn the_thread_safe_types_are_send_and_sync_as_documented() {
+    assert_send::<MatBuffer>();
+    assert_sync::<MatBuffer>();
+    #[cfg(system_opencv)]
+    {
+        use opencv_embedded::dnn::{Net, SharedNet, YoloDetector};
+        assert_send::<Net>();
+        assert_send::<YoloDetector>();
+        assert_send::<SharedNet>();
+        assert_sync::<SharedNet>();
+    }
+}
+
+fn frame(seed: u8) -> MatBuffer {
+    let data: Vec<u8> = (0..48 * 64 * 3).map(|i| (i as u8).wrapping_mul(seed)).collect();
+    MatBuffer::from_mat(&Mat::from_slice_2d(48, 64, 3, &data).unwrap()).unwrap()
+}
+
+// What a worker does with each frame: shrink it, then gray and threshold it.
+fn process(frame: &MatBuffer) -> MatBuffer {
+    let frame = frame.to_mat().unwrap();
+    let (mut small, mut gray, mut mask) = (Mat::default(), Mat::default(), Mat::default());
+    imgproc::resize_def(&frame, &mut small, Size::new(32, 24)).unwrap();
+    imgproc::cvt_color(&small, &mut gray, imgproc::COLOR_BGR2GRAY).unwrap();
+    imgproc::threshold(&gray, &mut mask, 100.0, 255.0, imgproc::THRESH_BINARY).unwrap();
+    MatBuffer::from_mat(&mask).unwrap()
+}
+
+#[test]
+fn frames_processed_on_four_threads_match_one() {
+    let frames: Vec<MatBuffer> = (1..=8).map(frame).collect();
+    let expected: Vec<MatBuffer> = frames.iter().map(process).collect();
+    assert_eq!(frames[0].typ(), CV_8UC3);
+
+    let (results, collected) = mpsc::channel();
+    let workers: Vec<_> = (0..THREADS)
+        .map(|worker| {
+            let (send, receive) = mpsc::channel::<(usize, MatBuffer)>();
+            let results = results.clone();
+            let handle = thread::spawn(move || {
+                for (index, frame) in receive {
+                    results.send((worker, index, process(&frame))).unwrap();
+                }
+            });
+            (send, handle)
+        })
+        .collect();
+    drop(results);
+
+    for round in 0..ROUNDS {
+        for (index, frame) in frames.iter().enumerate() {
+            workers[(round + index) % THREADS].0.send((index, frame.clone())).unwrap();
+        }
+    }
+    let handles: Vec<_> = workers.into_iter().map(|(send, handle)| {
+        drop(send);
+        handle
+    }).collect();
+
+    let mut seen = [0; THREADS];
+    for (worker, index, result) in collected {
+        assert_eq!(result, expected[index], "frame {} on worker {}", index, worker);
+        seen[worker] += 1;
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(seen.iter().sum::<usize>(), ROUNDS * frames.len());
+    assert!(seen.iter().all(|&count| count > 0));
+}
+
+#[cfg(system_opencv)]
+#[test]
+fn four_threads_share_one_net() {
+    use opencv_embedded::dnn::{SharedNet, YoloDetector};
+
+    let (Some(model), Some(names)) = (common::model("yolov8n.onnx"), common::model("coco.names")) else { return };
+    let detector = YoloDetector::new(&model, "", Size::new(640, 640), &names, 0.25, 0.45).unwrap();
+    let shared = SharedNet::new(detector);
+    let image = frame(7);
+    let expected = shared.detect_blocking(&image).unwrap().len();
+
+    let handles: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let (shared, image) = (shared.clone(), image.clone());
+            thread::spawn(move || {
+                for _ in 0..ROUNDS / 5 {
+                    let detections = shared.detect_blocking(&image).unwrap();
+                    assert_eq!(detections.len(), expected);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    #[cfg(feature = "tokio")]
+    {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let detections = runtime.block_on(shared.detect(image)).unwrap();
+        assert_eq!(detections.len(), expected);
+    }
+}
@@ -0,0 +1,130 @@
+// opencv-embedded/tests/videoio.rs
+//
+// VideoCapture over files: a bundled three-frame MJPEG (solid red, green
+// and blue, 32x24) and raw BGR24 and YUYV files written here, read to
+// their end. A real camera is only opened when SCOUT_TEST_VIDEO_DEVICE
+// names one, such as /dev/video0, in a videoio-v4l2 build.
+use std::path::{Path, PathBuf};
+
+use opencv_embedded::core::Mat;
+use opencv_embedded::videoio::{VideoCapture, CAP_PROP_FRAME_HEIGHT, CAP_PROP_FRAME_WIDTH};
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("videoio").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn open_raw(path: &Path, width: f64, height: f64) -> VideoCapture {
+    let mut capture = VideoCapture::open(path.to_str().unwrap()).unwrap();
+    assert!(capture.set(CAP_PROP_FRAME_WIDTH, width).unwrap());
+    assert!(capture.set(CAP_PROP_FRAME_HEIGHT, height).unwrap());
+    capture
+}
+
+#[cfg(feature = "imgcodecs")]
+#[test]
+fn mjpeg_frames_come_in_order_then_the_file_ends() {
+    use opencv_embedded::core::CV_8UC3;
+    use opencv_embedded::videoio::CAP_PROP_FPS;
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/three_frames.mjpeg");
+    let mut capture = VideoCapture::open(path).unwrap();
+    assert!(capture.is_opened());
+    let mut frame = Mat::default();
+    for expected in [[0u8, 0, 255], [0, 255, 0], [255, 0, 0]] {
+        assert!(capture.read(&mut frame).unwrap());
+        assert_eq!((frame.cols(), frame.rows(), frame.typ()), (32, 24, CV_8UC3));
+        let pixel = frame.get::<[u8; 3]>(12, 16).unwrap();
+        assert!(pixel.iter().zip(expected).all(|(got, want)| got.abs_diff(want) <= 8), "{:?} for {:?}", pixel, expected);
+    }
+    // At the end the last frame is left as it was.
+    let last = frame.to_vec::<u8>().unwrap();
+    assert!(!capture.read(&mut frame).unwrap());
+    assert_eq!(frame.to_vec::<u8>().unwrap(), last);
+
+    // MJPEG frames keep their encoded size.
+    assert!(!capture.set(CAP_PROP_FRAME_WIDTH, 64.0).unwrap());
+    assert!(capture.set(CAP_PROP_FPS, 10.0).unwrap());
+    assert_eq!(capture.get(CAP_PROP_FPS), 10.0);
+}
+
+#[test]
+fn raw_bgr_frames_are_read_at_the_size_set() {
+    let path = scratch("bgr").join("clip.bgr");
+    let frames: Vec<u8> = (0..2 * 4 * 2 * 3).map(|i| i as u8).collect();
+    // Two 4x2 frames and the start of a third, which is dropped.
+    std::fs::write(&path, [frames.as_slice(), &[1, 2, 3]].concat()).unwrap();
+    let mut capture = open_raw(&path, 4.0, 2.0);
+    assert_eq!((capture.get(CAP_PROP_FRAME_WIDTH), capture.get(CAP_PROP_FRAME_HEIGHT)), (4.0, 2.0));
+    let mut frame = Mat::default();
+    for expected in frames.chunks(4 * 2 * 3) {
+        assert!(capture.read(&mut frame).unwrap());
+        assert_eq!((frame.cols(), frame.rows()), (4, 2));
+        assert_eq!(frame.to_vec::<u8>().unwrap(), expected);
+    }
+    assert!(!capture.read(&mut frame).unwrap());
+}
+
+#[test]
+fn yuyv_frames_convert_to_bgr() {
+    let path = scratch("yuyv").join("clip.yuyv");
+    // Black and white pairs, then a blue-tinted pair.
+    std::fs::write(&path, [16, 128, 16, 128, 235, 128, 235, 128, 41, 240, 41, 110]).unwrap();
+    let mut capture = open_raw(&path, 2.0, 3.0);
+    let mut frame = Mat::default();
+    assert!(capture.read(&mut frame).unwrap());
+    assert_eq!(frame.row_slice::<[u8; 3]>(0).unwrap(), &[[0, 0, 0], [0, 0, 0]]);
+    assert_eq!(frame.row_slice::<[u8; 3]>(1).unwrap(), &[[255, 255, 255], [255, 255, 255]]);
+    let [b, g, r] = frame.get::<[u8; 3]>(2, 0).unwrap();
+    assert!(b > 200 && g < 80 && r < 80, "{:?}", [b, g, r]);
+}
+
+#[test]
+fn bad_sources_and_released_captures_are_errors() {
+    assert!(VideoCapture::open("clip.avi").is_err());
+    assert!(VideoCapture::open("/nonexistent/clip.mjpeg").is_err());
+
+    let path = scratch("released").join("clip.bgr");
+    std::fs::write(&path, [0u8; 12]).unwrap();
+    let mut capture = open_raw(&path, 2.0, 2.0);
+    assert!(!capture.set(CAP_PROP_FRAME_WIDTH, 0.0).unwrap());
+    assert!(!capture.set(99, 1.0).unwrap());
+    capture.release();
+    assert!(!capture.is_opened());
+    assert!(capture.read(&mut Mat::default()).is_err());
+}
+
+#[cfg(not(all(feature = "videoio-v4l2", target_os = "linux")))]
+#[test]
+fn devices_need_the_v4l2_feature() {
+    let error = VideoCapture::open("/dev/video0").err().unwrap();
+    assert!(error.to_string().contains("videoio-v4l2"), "{}", error);
+    assert!(VideoCapture::open("0").is_err());
+}
+
+#[cfg(all(feature = "videoio-v4l2", target_os = "linux"))]
+#[test]
+fn a_camera_hands_out_frames_at_its_negotiated_size() {
+    use opencv_embedded::core::CV_8UC3;
+
+    let Ok(device) = std::env::var("SCOUT_TEST_VIDEO_DEVICE") else {
+        eprintln!("skipped: set SCOUT_TEST_VIDEO_DEVICE to a camera such as /dev/video0");
+        return;
+    };
+    let mut capture = VideoCapture::open(&device).unwrap();
+    let mut frame = Mat::default();
+    for _ in 0..5 {
+        assert!(capture.read(&mut frame).unwrap());
+        assert_eq!(frame.typ(), CV_8UC3);
+        assert_eq!((frame.cols() as f64, frame.rows() as f64), (capture.get(CAP_PROP_FRAME_WIDTH), capture.get(CAP_PROP_FRAME_HEIGHT)));
+    }
+    // The driver may round a new size; whatever it settles on is what
+    // frames come at.
+    assert!(capture.set(CAP_PROP_FRAME_WIDTH, 320.0).unwrap());
+    assert!(capture.set(CAP_PROP_FRAME_HEIGHT, 240.0).unwrap());
+    assert!(capture.read(&mut frame).unwrap());
+    assert_eq!((frame.cols() as f64, frame.rows() as f64), (capture.get(CAP_PROP_FRAME_WIDTH), capture.get(CAP_PROP_FRAME_HEIGHT)));
+    capture.release();
+}
@@ -0,0 +1,72 @@
+#!/bin/sh
+# scripts/feature-matrix.sh
+#
+# Lints and tests every feature set the README promises works: nothing
+# at all, each of sim, web-ui and rover-hw on its own, the bench default
+# and the rover set, then opencv-embedded's own feature sets the same
+# way. Warnings are errors, since code a set leaves unused only shows up
+# in that set. The sets with `opencv` (and opencv-embedded's
+# --all-features, which links it) need the system OpenCV, so they are
+# skipped where pkg-config can't find opencv4 unless
+# FEATURE_MATRIX_OPENCV=1 says to try anyway. Extra arguments go to each
+# `cargo test`, e.g. `-- --test-threads 2`.
+set -eu
+
+cd "$(dirname "$0")/.."
+
+sets="
+none
+sim
+web-ui
+rover-hw
+sim,web-ui
+sim,web-ui,webp
+rover-hw,web-ui,webp
+mqtt,sim
+"
+# opencv-embedded's own, on top of its defaults unless `none`. `all`
+# is everything but the system OpenCV.
+embedded_sets="
+default
+none
+imgcodecs
+image-interop
+imgcodecs,image-interop
+all
+"
+embedded_all="imgcodecs,image-interop,videoio-v4l2,tokio,wrapper-blob"
+
+if [ "${FEATURE_MATRIX_OPENCV:-0}" = 1 ] || pkg-config --exists opencv4 2>/dev/null; then
+    sets="$sets
+opencv
+rover"
+    embedded_sets="$embedded_sets
+all-features"
+else
+    echo "feature-matrix: no opencv4 found, skipping the opencv, rover and opencv-embedded --all-features sets" >&2
+fi
+
+for set in $sets; do
+    echo "==> --no-default-features --features $set" >&2
+    if [ "$set" = none ]; then
+        features="--no-default-features"
+    else
+        features="--no-default-features --features $set"
+    fi
+    cargo clippy $features --all-targets -- -D warnings
+    cargo test $features "$@"
+done
+
+cd opencv-embedded
+for set in $embedded_sets; do
+    case "$set" in
+        default) features="" ;;
+        none) features="--no-default-features" ;;
+        all) features="--features $embedded_all" ;;
+        all-features) features="--all-features" ;;
+        *) features="--features $set" ;;
+    esac
+    echo "==> opencv-embedded $features" >&2
+    cargo clippy $features --all-targets -- -D warnings
+    cargo test $features "$@"
+done
@@ -0,0 +1,86 @@
+// src/annotate.rs
+use anyhow::Result;
+use image::{Rgb, RgbImage};
+use imageproc::drawing::draw_hollow_rect_mut;
+use imageproc::rect::Rect;
+
+use crate::display::{class_color, DisplayPreferences};
+use crate::vision::{Detection, FrameImage};
+
+// How much of a masked pixel is the class colour.
+const MASK_ALPHA: f32 = 0.4;
+// Anything this close is red, whatever its class, as on the dashboard.
+const CLOSE_M: f32 = 1.5;
+const CLOSE_COLOR: Rgb<u8> = Rgb([255, 0, 0]);
+// The rover's own body, which it doesn't steer by.
+const SELF_COLOR: Rgb<u8> = Rgb([128, 128, 128]);
+
+// Draws the detections onto the frame on the rover, for viewers that
+// can't draw them themselves. Classes the display section hides are left
+// out; masks are shaded in when `masks` is set. Self-occlusions are drawn
+// thin and grey.
+pub fn annotate(image: &FrameImage, detections: &[Detection], display: &DisplayPreferences, masks: bool) -> Result<RgbImage> {
+    let mut frame = image.get_rgb()?.as_ref().clone();
+
+    for detection in detections {
+        let style = display.classes.get(&detection.class_name);
+        if style.is_some_and(|s| !s.visible) {
+            continue;
+        }
+        let color = if detection.self_occlusion {
+            SELF_COLOR
+        } else if detection.distance_estimate < CLOSE_M {
+            CLOSE_COLOR
+        } else {
+            let hex = style.map_or_else(|| class_color(&detection.class_name), |s| s.color.clone());
+            parse_color(&hex).unwrap_or(CLOSE_COLOR)
+        };
+        let (x, y, w, h) = detection.bbox;
+        if w <= 0 || h <= 0 {
+            continue;
+        }
+        if let (true, Some(mask)) = (masks, &detection.mask) {
+            shade_mask(&mut frame, detection.bbox, mask.width, mask.height, &mask.decode(), color);
+        }
+        draw_hollow_rect_mut(&mut frame, Rect::at(x, y).of_size(w as u32, h as u32), color);
+        if w > 2 && h > 2 && !detection.self_occlusion {
+            draw_hollow_rect_mut(&mut frame, Rect::at(x + 1, y + 1).of_size(w as u32 - 2, h as u32 - 2), color);
+        }
+    }
+
+    Ok(frame)
+}
+
+// The mask's grid is stretched over the box; pixels of the box outside
+// the frame are skipped.
+fn shade_mask(frame: &mut RgbImage, bbox: (i32, i32, i32, i32), width: u32, height: u32, cells: &[bool], color: Rgb<u8>) {
+    let (x, y, w, h) = bbox;
+    if width == 0 || height == 0 || cells.len() != (width * height) as usize {
+        return;
+    }
+    let cols = x.max(0)..(x + w).min(frame.width() as i32);
+    let rows = y.max(0)..(y + h).min(frame.height() as i32);
+    for py in rows {
+        let row = ((py - y) as u32 * height / h as u32).min(height - 1);
+        for px in cols.clone() {
+            let col = ((px - x) as u32 * width / w as u32).min(width - 1);
+            if !cells[(row * width + col) as usize] {
+                continue;
+            }
+            let pixel = frame.get_pixel_mut(px as u32, py as u32);
+            for (channel, tint) in pixel.0.iter_mut().zip(color.0) {
+                *channel = (*channel as f32 * (1.0 - MASK_ALPHA) + tint as f32 * MASK_ALPHA).round() as u8;
+            }
+        }
+    }
+}
+
+// "#rrggbb", as the display section gives colours.
+fn parse_color(hex: &str) -> Option<Rgb<u8>> {
+    let hex = hex.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some(Rgb([channel(0)?, channel(2)?, channel(4)?]))
+}
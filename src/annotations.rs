@@ -0,0 +1,93 @@
+// src/annotations.rs
+//
+// Moments an operator marked while watching, such as a false positive or
+// an obstacle the detector missed. Each is kept with the recording its
+// frame is in, in annotations/<recording file name> beside it, one JSON
+// line each, so review and dataset export find them with the frames.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::vision::Detection;
+
+pub const DIRECTORY: &str = "annotations";
+pub const MAX_NOTE_CHARS: usize = 500;
+
+// Where a frame fed back through vision from a recording was in it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordingRef {
+    pub path: PathBuf,
+    pub t_ms: u64,
+    pub frame_id: Option<u32>,
+    pub tick: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    // Wall-clock ms it was made at.
+    pub at_ms: u64,
+    pub label: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    pub frame_id: Option<u32>,
+    pub tick: Option<u64>,
+    pub detections: Vec<Detection>,
+    // The recording the frame is in, by file name, and how far into it:
+    // the one being replayed, or else the one being recorded. None when
+    // there is neither.
+    pub session: Option<String>,
+    pub t_ms: Option<u64>,
+    pub replayed: bool,
+    // "api", or "ws:<client id>".
+    pub source: String,
+}
+
+// Labels are short names like false_positive or missed_obstacle.
+pub fn check_label(label: &str, note: Option<&str>) -> Result<(), String> {
+    let valid = (1..=32).contains(&label.len())
+        && label.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+    if !valid {
+        return Err("label must be 1-32 lowercase letters, digits, '_' or '-'".to_string());
+    }
+    if note.is_some_and(|n| n.chars().count() > MAX_NOTE_CHARS) {
+        return Err(format!("note must be at most {} characters", MAX_NOTE_CHARS));
+    }
+    Ok(())
+}
+
+pub fn path_for(recording: &Path) -> PathBuf {
+    let name = recording.file_name().unwrap_or_default();
+    recording.parent().unwrap_or(Path::new("")).join(DIRECTORY).join(name)
+}
+
+pub fn append(recording: &Path, annotation: &Annotation) -> Result<()> {
+    let path = path_for(recording);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(annotation)?).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// In the order they were made; none when the recording has none.
+pub fn load(recording: &Path) -> Result<Vec<Annotation>> {
+    let path = path_for(recording);
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open {}", path.display())),
+    };
+    Ok(BufReader::new(file)
+        .lines()
+        .map_while(|line| line.ok())
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect())
+}
+
+// With the recording, when it is deleted.
+pub fn remove(recording: &Path) {
+    let _ = fs::remove_file(path_for(recording));
+}
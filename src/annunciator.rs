@@ -0,0 +1,283 @@
+// src/annunciator.rs
+use anyhow::Result;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, TrySendError};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::config::{AnnunciatorBackend, AnnunciatorConfig};
+use crate::events::{RoverEvent, Severity, EVENTS};
+use crate::metrics::METRICS;
+use crate::motor_control::MotorController;
+
+pub const ALERT_NAMES: &[&str] = &["motion", "arming", "fault", "estop"];
+
+// Alerts waiting for the player; more than this and new ones are dropped.
+const QUEUE_SIZE: usize = 8;
+// Motors that were stopped this long beep when they start again, so stop
+// and go in traffic doesn't beep on every tick.
+const MOTION_QUIET: Duration = Duration::from_secs(2);
+const MOTION_POLL: Duration = Duration::from_millis(100);
+const SAMPLE_RATE: u32 = 22_050;
+
+// In order of priority: a higher alert cuts off a lower one mid-pattern,
+// and a lower one arriving while a higher one plays is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Alert {
+    Motion,
+    Arming,
+    Fault,
+    EmergencyStop,
+}
+
+impl Alert {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Alert::Motion => "motion",
+            Alert::Arming => "arming",
+            Alert::Fault => "fault",
+            Alert::EmergencyStop => "estop",
+        }
+    }
+
+    // [frequency_hz, duration_ms], 0 Hz for a rest.
+    fn default_pattern(&self) -> Vec<(u32, u32)> {
+        match self {
+            Alert::Motion => vec![(2000, 80), (0, 80), (2000, 80)],
+            Alert::Arming => vec![(1500, 150), (0, 100), (1500, 150), (0, 100), (2500, 300)],
+            Alert::Fault => vec![(800, 400), (0, 200), (800, 400)],
+            Alert::EmergencyStop => vec![(3000, 60), (2000, 60), (3000, 60), (2000, 60), (3000, 60), (2000, 60)],
+        }
+    }
+
+    // Arming is entering a mode that drives; an emergency stop comes from
+    // the motor controller, and the fault that follows it is drowned out.
+    pub fn from_event(event: &RoverEvent) -> Option<Self> {
+        match event.source {
+            "motors" if event.severity == Severity::Critical => Some(Alert::EmergencyStop),
+            "control" => match event.data.get("to").and_then(|to| to.as_str()) {
+                Some("autonomous" | "manual") => Some(Alert::Arming),
+                Some("fault") => Some(Alert::Fault),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+// Plays one tone until stopped, or for at most `duration`.
+pub trait ToneOutput: Send {
+    fn start(&mut self, freq_hz: u32, duration: Duration) -> Result<()>;
+    fn stop(&mut self) -> Result<()>;
+}
+
+pub struct LogOutput;
+
+impl ToneOutput for LogOutput {
+    fn start(&mut self, freq_hz: u32, duration: Duration) -> Result<()> {
+        debug!(target: "annunciator", "Tone {} Hz for {:?}", freq_hz, duration);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+// A sine wave piped into aplay as raw 16-bit mono. Stopping kills aplay,
+// which cuts the tone off at once.
+pub struct AplayOutput {
+    device: Option<String>,
+    child: Option<Child>,
+}
+
+impl AplayOutput {
+    pub fn new(device: Option<String>) -> Self {
+        Self { device, child: None }
+    }
+}
+
+impl ToneOutput for AplayOutput {
+    fn start(&mut self, freq_hz: u32, duration: Duration) -> Result<()> {
+        self.stop()?;
+        let mut command = Command::new("aplay");
+        command.args(["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-r", &SAMPLE_RATE.to_string()]);
+        if let Some(device) = &self.device {
+            command.args(["-D", device]);
+        }
+        let mut child = command.stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
+        let samples = (SAMPLE_RATE as f32 * duration.as_secs_f32()) as usize;
+        let step = std::f32::consts::TAU * freq_hz as f32 / SAMPLE_RATE as f32;
+        let pcm: Vec<u8> = (0..samples)
+            .flat_map(|i| (((i as f32 * step).sin() * 0.3 * i16::MAX as f32) as i16).to_le_bytes())
+            .collect();
+        // Dropping stdin afterwards lets aplay finish on its own.
+        let written = child.stdin.take().map(|mut stdin| stdin.write_all(&pcm));
+        self.child = Some(child);
+        written.transpose()?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            child.wait()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "buzzer")]
+pub struct BuzzerOutput {
+    pin: rppal::gpio::OutputPin,
+}
+
+#[cfg(feature = "buzzer")]
+impl BuzzerOutput {
+    pub fn open(pin: u8) -> Result<Self> {
+        Ok(Self { pin: rppal::gpio::Gpio::new()?.get(pin)?.into_output_low() })
+    }
+}
+
+#[cfg(feature = "buzzer")]
+impl ToneOutput for BuzzerOutput {
+    fn start(&mut self, freq_hz: u32, _duration: Duration) -> Result<()> {
+        self.pin.set_pwm_frequency(freq_hz as f64, 0.5)?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.pin.clear_pwm()?;
+        self.pin.set_low();
+        Ok(())
+    }
+}
+
+fn open(config: &AnnunciatorConfig) -> Result<Box<dyn ToneOutput>> {
+    Ok(match config.backend {
+        #[cfg(feature = "buzzer")]
+        AnnunciatorBackend::Buzzer => Box::new(BuzzerOutput::open(config.buzzer_pin)?),
+        #[cfg(not(feature = "buzzer"))]
+        AnnunciatorBackend::Buzzer => anyhow::bail!("built without the `buzzer` feature"),
+        AnnunciatorBackend::Aplay => Box::new(AplayOutput::new(config.aplay_device.clone())),
+        AnnunciatorBackend::Log => Box::new(LogOutput),
+    })
+}
+
+// Does nothing unless annunciator.enabled is set. Tones play on their own
+// thread; alerts reach it through a short queue that is never waited on,
+// so neither the control loop nor the event bus is held up by a pattern.
+pub fn spawn(config: &AnnunciatorConfig, motor_controller: Arc<RwLock<MotorController>>) {
+    if !config.enabled {
+        return;
+    }
+    let output = match open(config) {
+        Ok(output) => output,
+        Err(e) => {
+            warn!(target: "annunciator", "Annunciator disabled: {:#}", e);
+            return;
+        }
+    };
+    let mut patterns = HashMap::new();
+    for alert in [Alert::Motion, Alert::Arming, Alert::Fault, Alert::EmergencyStop] {
+        let pattern = config.patterns.get(alert.as_str()).cloned().unwrap_or_else(|| alert.default_pattern());
+        patterns.insert(alert, pattern);
+    }
+    let (tx, rx) = crossbeam_channel::bounded(QUEUE_SIZE);
+    std::thread::spawn(move || play(rx, output, patterns));
+    info!(target: "annunciator", "Annunciator on {:?}", config.backend);
+
+    let events = tx.clone();
+    tokio::spawn(async move {
+        let mut subscription = EVENTS.subscribe();
+        loop {
+            match subscription.recv().await {
+                Ok(event) => {
+                    if let Some(alert) = Alert::from_event(&event) {
+                        enqueue(&events, alert);
+                    }
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+
+    // Nothing announces motion, so the motor speed is watched instead.
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(MOTION_POLL);
+        let mut stopped_since = Some(Instant::now());
+        loop {
+            ticker.tick().await;
+            let moving = motor_controller.read().get_speed() != 0.0;
+            match (moving, stopped_since) {
+                (true, Some(since)) => {
+                    if since.elapsed() >= MOTION_QUIET {
+                        enqueue(&tx, Alert::Motion);
+                    }
+                    stopped_since = None;
+                }
+                (false, None) => stopped_since = Some(Instant::now()),
+                _ => {}
+            }
+        }
+    });
+}
+
+fn enqueue(tx: &Sender<Alert>, alert: Alert) {
+    if let Err(TrySendError::Full(alert)) = tx.try_send(alert) {
+        debug!(target: "annunciator", "Queue full, dropping {}", alert.as_str());
+    }
+}
+
+fn play(rx: Receiver<Alert>, mut output: Box<dyn ToneOutput>, patterns: HashMap<Alert, Vec<(u32, u32)>>) {
+    let mut next = None;
+    // Reported once, not for every tone of every pattern.
+    let mut failing = false;
+    loop {
+        let alert = match next.take() {
+            Some(alert) => alert,
+            None => match rx.recv() {
+                Ok(alert) => alert,
+                Err(_) => return,
+            },
+        };
+        if patterns[&alert].is_empty() {
+            continue;
+        }
+        METRICS.annunciator_alerts.inc(alert.as_str());
+        'pattern: for &(freq_hz, ms) in &patterns[&alert] {
+            let duration = Duration::from_millis(ms as u64);
+            if freq_hz > 0 {
+                let started = output.start(freq_hz, duration);
+                if let Err(e) = &started {
+                    if !failing {
+                        warn!(target: "annunciator", "Failed to play {}: {:#}", alert.as_str(), e);
+                    }
+                }
+                failing = started.is_err();
+            }
+            let deadline = Instant::now() + duration;
+            loop {
+                match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(incoming) if incoming > alert => {
+                        next = Some(incoming);
+                        break 'pattern;
+                    }
+                    Ok(_) => {}
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        let _ = output.stop();
+                        return;
+                    }
+                }
+            }
+            let _ = output.stop();
+        }
+        let _ = output.stop();
+    }
+}
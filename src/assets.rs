@@ -0,0 +1,66 @@
+// src/assets.rs
+#[cfg(feature = "web-ui")]
+use rust_embed::RustEmbed;
+use warp::http::{header, HeaderValue, StatusCode};
+use warp::reply::Response;
+
+// Debug builds read these from disk on every request; release builds carry
+// them inside the binary.
+#[cfg(feature = "web-ui")]
+#[derive(RustEmbed)]
+#[folder = "static/"]
+struct DashboardAssets;
+
+#[cfg(feature = "web-ui")]
+pub fn serve(path: &str, if_none_match: Option<&str>) -> Option<Response> {
+    let path = if path.is_empty() || path.ends_with('/') {
+        format!("{}index.html", path)
+    } else {
+        path.to_string()
+    };
+    let file = DashboardAssets::get(&path)?;
+
+    let etag = format!("\"{}\"", file.metadata.sha256_hash().iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    // The dashboard page itself must revalidate so a new binary's UI shows
+    // up immediately; everything else may be cached for an hour.
+    let cache_control = if path.ends_with(".html") {
+        "no-cache"
+    } else {
+        "public, max-age=3600"
+    };
+
+    let mut response = if if_none_match == Some(etag.as_str()) {
+        let mut response = Response::new(Default::default());
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        response
+    } else {
+        Response::new(file.data.into_owned().into())
+    };
+
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(file.metadata.mimetype()) {
+        headers.insert(header::CONTENT_TYPE, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        headers.insert(header::ETAG, value);
+    }
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(cache_control));
+    Some(response)
+}
+
+// Without the dashboard the API still works; the page only says where it
+// went.
+#[cfg(not(feature = "web-ui"))]
+pub fn serve(path: &str, _if_none_match: Option<&str>) -> Option<Response> {
+    if !(path.is_empty() || path == "index.html") {
+        return None;
+    }
+    let mut response = Response::new(
+        "<!doctype html><title>Scout</title><p>This build has no dashboard (the <code>web-ui</code> feature). \
+         Set <code>web.static_dir</code> to serve one from disk, or see <a href=\"api\">api</a>.</p>".into(),
+    );
+    *response.status_mut() = StatusCode::OK;
+    response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+    response.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    Some(response)
+}
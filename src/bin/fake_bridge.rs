@@ -0,0 +1,451 @@
+// src/bin/fake_bridge.rs
+//
+// Stands in for src/vision_bridge.py: plays a scenario file out on stdout
+// as bridge lines, so the rover's bridge reading and supervision can be
+// run without a camera or Python. Point vision.bridge_command at it:
+//
+//   bridge_command = ["target/debug/fake_bridge", "scenarios/person_approach.json", "--malformed-every", "10"]
+//
+// Like the real bridge it answers SCOUT_PROTOCOL_VERSIONS with a hello and
+// only sends tags when SCOUT_FIDUCIALS is set. A pause line on stdin slows
+// it to the keepalive rate until the next one; with --verbose the control
+// lines it is sent are echoed to stderr. With --image-bytes each frame
+// carries a made-up picture that says which frame it is, through a
+// shared-memory ring when SCOUT_FRAME_TRANSPORT=shm; with --yuv420 as
+// well, and SCOUT_PIXEL_FORMAT asking for it, the ring gets raw frames of
+// yuv_for's pattern instead.
+// The other flags make it misbehave in the ways a real bridge can.
+use anyhow::{Context, Result};
+use base64::Engine;
+use clap::Parser;
+use parking_lot::Mutex;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{fence, AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use scout_vision::simulation::{Scenario, ScenarioStep};
+use scout_vision::vision::shm::SLOT_HEADER_BYTES;
+
+// How long the halves of a partial write are apart.
+const PARTIAL_WRITE_GAP: Duration = Duration::from_millis(5);
+// A slot the rover hasn't acked in this long is taken back, as the real
+// bridge does.
+const SLOT_RECLAIM: Duration = Duration::from_secs(2);
+const RAW_WIDTH: u32 = 640;
+const RAW_HEIGHT: u32 = 480;
+
+#[derive(Parser)]
+#[command(about = "Plays a scenario out as vision bridge lines on stdout")]
+struct Args {
+    scenario: PathBuf,
+    // Overrides the scenario's frame_interval_ms.
+    #[arg(long)]
+    interval_ms: Option<u64>,
+    // The version to answer with; defaults to the newest one offered.
+    #[arg(long)]
+    protocol: Option<u32>,
+    // Every nth line is garbage instead of a frame; 0 never.
+    #[arg(long, default_value_t = 0)]
+    malformed_every: u32,
+    // Write each line in two halves with a flush and a pause between them.
+    #[arg(long)]
+    partial_writes: bool,
+    // Write this many frames, then half of the next one, and exit.
+    #[arg(long)]
+    exit_after: Option<u32>,
+    // Write this many frames, then stay alive without writing any more.
+    #[arg(long)]
+    stall_after: Option<u32>,
+    // The first this many starts, counted in --state-file, write nothing
+    // but garbage after the hello.
+    #[arg(long, default_value_t = 0)]
+    garbage_starts: u32,
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+    // Size of the picture each frame carries; 0 sends none.
+    #[arg(long, default_value_t = 0)]
+    image_bytes: usize,
+    // Rewrite ring slots in turn whether or not the rover is done with
+    // them.
+    #[arg(long)]
+    shm_ignore_acks: bool,
+    // Announce a ring at a path that doesn't exist.
+    #[arg(long)]
+    shm_bad_path: bool,
+    // Leave set_exposure out of the hello, like a bridge that can't take
+    // exposure feedback.
+    #[arg(long)]
+    no_exposure: bool,
+    // Offer raw YUV420 frames, sent through the ring when the rover asks
+    // for them.
+    #[arg(long)]
+    yuv420: bool,
+    // Echo the control lines the rover sends to stderr.
+    #[arg(long)]
+    verbose: bool,
+}
+
+// The bridge's side of the frame ring; see scout_vision::vision::shm for
+// the layout.
+struct Ring {
+    base: *mut u8,
+    slots: usize,
+    slot_bytes: usize,
+    path: String,
+    // The frame in each slot the rover hasn't acked yet, and since when.
+    pending: Vec<Option<(u64, Instant)>>,
+    next_seq: u64,
+    next_slot: usize,
+    ignore_acks: bool,
+}
+
+// Only used behind a Mutex.
+unsafe impl Send for Ring {}
+
+impl Ring {
+    fn create(slots: usize, slot_bytes: usize, ignore_acks: bool) -> Result<Self> {
+        let size = slots * slot_bytes;
+        let name = std::ffi::CString::new("scout-frames")?;
+        // SAFETY: plain syscalls on a memfd of our own, mapped whole.
+        unsafe {
+            let fd = libc::memfd_create(name.as_ptr(), libc::MFD_ALLOW_SEALING);
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error()).context("memfd_create failed");
+            }
+            if libc::ftruncate(fd, size as libc::off_t) != 0 {
+                return Err(std::io::Error::last_os_error()).context("ftruncate failed");
+            }
+            // So the rover's mapping can't end up past the end of the file.
+            if libc::fcntl(fd, libc::F_ADD_SEALS, libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_SEAL) != 0 {
+                return Err(std::io::Error::last_os_error()).context("sealing the ring failed");
+            }
+            let base = libc::mmap(std::ptr::null_mut(), size, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, fd, 0);
+            if base == libc::MAP_FAILED {
+                return Err(std::io::Error::last_os_error()).context("mmap failed");
+            }
+            Ok(Self {
+                base: base as *mut u8,
+                slots,
+                slot_bytes,
+                path: format!("/proc/{}/fd/{}", std::process::id(), fd),
+                pending: vec![None; slots],
+                next_seq: 1,
+                next_slot: 0,
+                ignore_acks,
+            })
+        }
+    }
+
+    fn free_slot(&mut self) -> Option<usize> {
+        if self.ignore_acks {
+            let slot = self.next_slot;
+            self.next_slot = (slot + 1) % self.slots;
+            return Some(slot);
+        }
+        for pending in self.pending.iter_mut() {
+            if pending.is_some_and(|(_, at)| at.elapsed() > SLOT_RECLAIM) {
+                *pending = None;
+            }
+        }
+        self.pending.iter().position(Option::is_none)
+    }
+
+    // The frame's "shm" field, or None to send the picture inline.
+    fn write(&mut self, image: &[u8]) -> Option<serde_json::Value> {
+        if image.len() > self.slot_bytes - SLOT_HEADER_BYTES {
+            return None;
+        }
+        let slot = self.free_slot()?;
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        // SAFETY: the slot is inside the mapping, and the image fits it.
+        unsafe {
+            let at = self.base.add(slot * self.slot_bytes);
+            std::ptr::write_volatile(at as *mut [u8; 8], 0u64.to_le_bytes());
+            fence(Ordering::Release);
+            std::ptr::copy_nonoverlapping(image.as_ptr(), at.add(SLOT_HEADER_BYTES), image.len());
+            std::ptr::write_volatile(at.add(8) as *mut [u8; 4], (image.len() as u32).to_le_bytes());
+            fence(Ordering::Release);
+            std::ptr::write_volatile(at as *mut [u8; 8], seq.to_le_bytes());
+        }
+        self.pending[slot] = Some((seq, Instant::now()));
+        Some(serde_json::json!({ "slot": slot, "seq": seq, "length": image.len() }))
+    }
+
+    fn ack(&mut self, slot: usize, seq: u64) {
+        if let Some(pending) = self.pending.get_mut(slot).filter(|p| p.is_some_and(|(s, _)| s == seq)) {
+            *pending = None;
+        }
+    }
+}
+
+// `len` bytes of "frame-<id>\n" over and over.
+fn image_for(frame_id: u32, len: usize) -> Vec<u8> {
+    format!("frame-{:010}\n", frame_id).into_bytes().into_iter().cycle().take(len).collect()
+}
+
+// A 640x480 I420 frame of ramps that move on with each frame, so every
+// value of Y, U and V turns up. tests/fake_bridge.rs makes the same one.
+fn yuv_for(frame_id: u32) -> Vec<u8> {
+    let (width, height) = (RAW_WIDTH as usize, RAW_HEIGHT as usize);
+    let mut frame = Vec::with_capacity(width * height * 3 / 2);
+    for y in 0..height {
+        frame.extend((0..width).map(|x| (x + y + frame_id as usize) as u8));
+    }
+    for y in 0..height / 2 {
+        frame.extend((0..width / 2).map(|x| (2 * x + y + frame_id as usize) as u8));
+    }
+    for y in 0..height / 2 {
+        frame.extend((0..width / 2).map(|x| (x + 3 * y + frame_id as usize) as u8));
+    }
+    frame
+}
+
+struct Output {
+    start: Instant,
+    partial_writes: bool,
+    stdout: std::io::Stdout,
+}
+
+impl Output {
+    // Seconds on this bridge's own clock, which only has to agree with
+    // itself: the rover maps it through the hello.
+    fn monotonic(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        let mut out = self.stdout.lock();
+        if self.partial_writes {
+            let (first, rest) = line.as_bytes().split_at(line.len() / 2);
+            out.write_all(first)?;
+            out.flush()?;
+            thread::sleep(PARTIAL_WRITE_GAP);
+            out.write_all(rest)?;
+        } else {
+            out.write_all(line.as_bytes())?;
+        }
+        out.write_all(b"\n")?;
+        out.flush()?;
+        Ok(())
+    }
+
+    // Half a frame with no newline, as a bridge killed mid-write leaves it.
+    fn write_torn(&mut self, line: &str) -> Result<()> {
+        let mut out = self.stdout.lock();
+        out.write_all(&line.as_bytes()[..line.len() / 2])?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let scenario = Scenario::load(&args.scenario)?;
+    let interval = Duration::from_millis(args.interval_ms.unwrap_or(scenario.frame_interval_ms));
+    let offered: Vec<u32> = std::env::var("SCOUT_PROTOCOL_VERSIONS").unwrap_or_else(|_| "1".to_string())
+        .split(',')
+        .filter_map(|v| v.trim().parse().ok())
+        .collect();
+    let version = args.protocol.or_else(|| offered.iter().copied().max()).unwrap_or(1);
+    let fiducials = std::env::var("SCOUT_FIDUCIALS").is_ok_and(|v| v == "1");
+    let garbage_only = match &args.state_file {
+        Some(path) => count_start(path)? <= args.garbage_starts,
+        None => args.garbage_starts > 0,
+    };
+
+    let ring = match std::env::var("SCOUT_FRAME_TRANSPORT").as_deref() {
+        Ok("shm") => {
+            let env = |name: &str, default: usize| std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+            let ring = Ring::create(env("SCOUT_FRAME_SLOTS", 4), env("SCOUT_FRAME_SLOT_BYTES", 256 * 1024), args.shm_ignore_acks)?;
+            Some(Arc::new(Mutex::new(ring)))
+        }
+        _ => None,
+    };
+    let shm_enabled = Arc::new(AtomicBool::new(ring.is_some()));
+    // Raw frames only ever go through the ring.
+    let raw = args.yuv420 && ring.is_some() && std::env::var("SCOUT_PIXEL_FORMAT").is_ok_and(|v| v == "yuv420");
+
+    // 0 while not paused.
+    let keepalive_ms = Arc::new(AtomicU64::new(0));
+    {
+        let keepalive_ms = keepalive_ms.clone();
+        let ring = ring.clone();
+        let shm_enabled = shm_enabled.clone();
+        let verbose = args.verbose;
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines().map_while(|line| line.ok()) {
+                let Ok(message) = serde_json::from_str::<serde_json::Value>(&line) else { continue };
+                match message["type"].as_str() {
+                    Some("pause") => {
+                        let fps = message["keepalive_fps"].as_f64().filter(|_| message["paused"] == true);
+                        keepalive_ms.store(fps.map_or(0, |fps| (1000.0 / fps.max(0.1)) as u64), Ordering::SeqCst);
+                    }
+                    // Too many to echo.
+                    Some("ack") => {
+                        if let (Some(ring), Some(slot), Some(seq)) = (&ring, message["slot"].as_u64(), message["seq"].as_u64()) {
+                            ring.lock().ack(slot as usize, seq);
+                        }
+                        continue;
+                    }
+                    Some("transport") if message["shm"] == false => shm_enabled.store(false, Ordering::SeqCst),
+                    _ => {}
+                }
+                if verbose {
+                    eprintln!("fake_bridge: control {}", line);
+                }
+            }
+        });
+    }
+
+    let mut out = Output { start: Instant::now(), partial_writes: args.partial_writes, stdout: std::io::stdout() };
+    let mut capabilities: Vec<&str> = if fiducials { vec!["fiducials"] } else { Vec::new() };
+    if !args.no_exposure {
+        capabilities.push("set_exposure");
+    }
+    let mut hello = serde_json::json!({
+        "type": "hello",
+        "version": version,
+        "monotonic": out.monotonic(),
+    });
+    if let Some(ring) = &ring {
+        let ring = ring.lock();
+        capabilities.push("shm");
+        if args.yuv420 {
+            capabilities.push("yuv420");
+        }
+        let path = if args.shm_bad_path { "/nonexistent/scout-frames".to_string() } else { ring.path.clone() };
+        hello["shm"] = serde_json::json!({ "path": path, "slots": ring.slots, "slot_bytes": ring.slot_bytes });
+    }
+    hello["capabilities"] = capabilities.into();
+    out.write_line(&hello.to_string())?;
+
+    let mut frame_id = 0u32;
+    loop {
+        for step in &scenario.steps {
+            for _ in 0..step.repeat {
+                if args.stall_after.is_some_and(|n| frame_id >= n) {
+                    loop {
+                        thread::sleep(Duration::from_secs(3600));
+                    }
+                }
+                frame_id = frame_id.wrapping_add(1);
+                let mut frame = frame_line(&out, version, frame_id, step, fiducials);
+                let shared = ring.as_ref().filter(|_| shm_enabled.load(Ordering::SeqCst));
+                // A raw frame that doesn't fit a free slot goes inline as
+                // the usual made-up picture, as the real bridge sends a JPEG.
+                let raw_slot = shared.filter(|_| raw).and_then(|ring| ring.lock().write(&yuv_for(frame_id))).map(|mut slot| {
+                    slot["format"] = "yuv420".into();
+                    slot["width"] = RAW_WIDTH.into();
+                    slot["height"] = RAW_HEIGHT.into();
+                    slot
+                });
+                if let Some(slot) = raw_slot {
+                    frame["shm"] = slot;
+                } else if args.image_bytes > 0 {
+                    let image = image_for(frame_id, args.image_bytes);
+                    let slot = shared.and_then(|ring| ring.lock().write(&image));
+                    match slot {
+                        Some(slot) => frame["shm"] = slot,
+                        None => frame["jpeg_base64"] = base64::engine::general_purpose::STANDARD.encode(&image).into(),
+                    }
+                }
+                let line = frame.to_string();
+                if args.exit_after.is_some_and(|n| frame_id > n) {
+                    out.write_torn(&line)?;
+                    std::process::exit(1);
+                }
+                let malformed = garbage_only || (args.malformed_every > 0 && frame_id % args.malformed_every == 0);
+                if malformed {
+                    out.write_line(&format!("{{\"frame_id\": {}, \"jpeg_base64\": garbage", frame_id))?;
+                } else {
+                    out.write_line(&line)?;
+                }
+                match keepalive_ms.load(Ordering::SeqCst) {
+                    0 => thread::sleep(interval),
+                    ms => sleep_paused(&keepalive_ms, Duration::from_millis(ms)),
+                }
+            }
+        }
+        if !scenario.repeat_forever {
+            return Ok(());
+        }
+    }
+}
+
+fn frame_line(out: &Output, version: u32, frame_id: u32, step: &ScenarioStep, fiducials: bool) -> serde_json::Value {
+    let capture_monotonic = out.monotonic();
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+    let tags = if fiducials { step.fiducials.clone() } else { Vec::new() };
+    let mut frame = if version >= 2 {
+        let detections: Vec<serde_json::Value> = step.detections.iter()
+            .map(|d| serde_json::json!({
+                "class_id": d.class_id.unwrap_or(0),
+                "class": (!d.class.is_empty()).then_some(&d.class),
+                "conf": d.conf,
+                "x": d.x,
+                "y": d.y,
+                "w": d.w,
+                "h": d.h,
+                "mask": d.mask,
+            }))
+            .collect();
+        serde_json::json!({
+            "type": "frame_v2",
+            "frame_id": frame_id,
+            "width": 640,
+            "height": 480,
+            "jpeg_base64": "",
+            "timestamp": timestamp,
+            "capture_monotonic": capture_monotonic,
+            "detections": detections,
+            "fiducials": tags,
+        })
+    } else {
+        let detections: Vec<serde_json::Value> = step.detections.iter()
+            .map(|d| serde_json::json!({
+                "class": d.class,
+                "class_id": d.class_id,
+                "conf": d.conf,
+                "x": d.x,
+                "y": d.y,
+                "w": d.w,
+                "h": d.h,
+            }))
+            .collect();
+        serde_json::json!({
+            "frame_id": frame_id,
+            "jpeg_base64": "",
+            "timestamp": timestamp,
+            "imx500_basic": detections,
+            "fiducials": tags,
+            "capture_monotonic": capture_monotonic,
+        })
+    };
+    if let Some(brightness) = step.brightness {
+        frame["brightness"] = brightness.into();
+    }
+    frame["emit_monotonic"] = out.monotonic().into();
+    frame
+}
+
+// Sleeps out a keepalive interval, cut short when the pause ends.
+fn sleep_paused(keepalive_ms: &AtomicU64, interval: Duration) {
+    let until = Instant::now() + interval;
+    while Instant::now() < until && keepalive_ms.load(Ordering::SeqCst) != 0 {
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+// Returns which start of the bridge this is, counting from 1.
+fn count_start(path: &Path) -> Result<u32> {
+    let previous: u32 = std::fs::read_to_string(path).ok()
+        .and_then(|text| text.trim().parse().ok())
+        .unwrap_or(0);
+    std::fs::write(path, (previous + 1).to_string())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(previous + 1)
+}
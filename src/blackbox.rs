@@ -0,0 +1,384 @@
+// src/blackbox.rs
+use anyhow::Result;
+use crossbeam_channel::{Sender, TrySendError};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Serialize;
+use std::io::Write;
+
+use crate::clock::CLOCK;
+use crate::config::BlackboxConfig;
+use crate::metrics::METRICS;
+use crate::telemetry;
+
+pub const KINDS: &[&str] = &["decision", "command", "event", "telemetry", "annotation"];
+
+// Records waiting for the writer; more than this and new ones are dropped
+// rather than holding up whoever is recording.
+#[cfg(feature = "blackbox")]
+const QUEUE_SIZE: usize = 4096;
+// The most a single /api/blackbox query returns.
+pub const MAX_QUERY_LIMIT: usize = 10_000;
+
+pub static BLACKBOX: Lazy<Blackbox> = Lazy::new(Blackbox::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+    // Autonomous navigation: what vision and the planner asked for and
+    // what the state machine made of it.
+    Decision,
+    // What the motor controller was told to do.
+    Command,
+    Event,
+    Telemetry,
+    // A moment an operator marked, from the web API.
+    Annotation,
+}
+
+impl RecordKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordKind::Decision => "decision",
+            RecordKind::Command => "command",
+            RecordKind::Event => "event",
+            RecordKind::Telemetry => "telemetry",
+            RecordKind::Annotation => "annotation",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Record {
+    // Wall clock, so records line up with the rest of the world.
+    pub t_ms: u64,
+    pub kind: String,
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub from_ms: Option<u64>,
+    pub to_ms: Option<u64>,
+    pub kind: Option<String>,
+    // None for everything in the range.
+    pub limit: Option<usize>,
+}
+
+// Only holds a sender once spawn() has opened the database, so recording
+// with the black box off costs a check and nothing else.
+pub struct Blackbox {
+    tx: OnceCell<Sender<Record>>,
+}
+
+impl Blackbox {
+    fn new() -> Self {
+        Self { tx: OnceCell::new() }
+    }
+
+    pub fn record(&self, kind: RecordKind, data: serde_json::Value) {
+        let Some(tx) = self.tx.get() else { return };
+        let record = Record { t_ms: CLOCK.wall_ms(), kind: kind.as_str().to_string(), data };
+        if let Err(TrySendError::Full(_)) = tx.try_send(record) {
+            METRICS.blackbox_dropped.inc();
+        }
+    }
+}
+
+// Accepts milliseconds since the epoch, an RFC 3339 time, or a window
+// back from now like "10m" (see telemetry::parse_window).
+pub fn parse_time(value: &str) -> Option<u64> {
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) {
+        return value.parse().ok();
+    }
+    if let Some(window) = telemetry::parse_window(value) {
+        return Some(CLOCK.wall_ms().saturating_sub(window.as_millis() as u64));
+    }
+    chrono::DateTime::parse_from_rfc3339(value).ok()
+        .and_then(|t| u64::try_from(t.timestamp_millis()).ok())
+}
+
+pub fn write_csv(records: &[Record], out: &mut impl Write) -> Result<()> {
+    writeln!(out, "t_ms,time,kind,data")?;
+    for record in records {
+        let time = chrono::DateTime::<chrono::Utc>::from_timestamp_millis(record.t_ms as i64)
+            .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+            .unwrap_or_default();
+        let data = record.data.to_string().replace('"', "\"\"");
+        writeln!(out, "{},{},{},\"{}\"", record.t_ms, time, record.kind, data)?;
+    }
+    Ok(())
+}
+
+pub fn write_jsonl(records: &[Record], out: &mut impl Write) -> Result<()> {
+    for record in records {
+        writeln!(out, "{}", serde_json::to_string(record)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "blackbox")]
+mod store {
+    use anyhow::{Context, Result};
+    use crossbeam_channel::{Receiver, RecvTimeoutError};
+    use rusqlite::{params, Connection, OpenFlags};
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+    use tracing::{info, warn};
+
+    use super::{Query, Record};
+    use crate::config::BlackboxConfig;
+    use crate::events::{Severity, EVENTS};
+    use crate::metrics::METRICS;
+
+    // Pruning takes this share of the records at a time until the database
+    // fits again.
+    const PRUNE_FRACTION: f64 = 0.05;
+    const PRUNE_MIN_ROWS: i64 = 100;
+
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS records (
+            id INTEGER PRIMARY KEY,
+            t_ms INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            data TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS records_t_ms ON records (t_ms);
+        CREATE INDEX IF NOT EXISTS records_kind_t_ms ON records (kind, t_ms);
+    ";
+
+    fn open_checked(path: &Path) -> Result<Connection> {
+        let conn = Connection::open(path)?;
+        let check: String = conn.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+        if check != "ok" {
+            anyhow::bail!("integrity check failed: {}", check);
+        }
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        // The WAL is reused after each checkpoint; this stops it staying at
+        // its largest size.
+        conn.pragma_update(None, "journal_size_limit", 4 * 1024 * 1024)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(conn)
+    }
+
+    // A database that can't be opened or fails its check is moved aside
+    // with its WAL, and recording starts again in a fresh one.
+    pub fn open(path: &Path) -> Result<Connection> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let e = match open_checked(path) {
+            Ok(conn) => return Ok(conn),
+            Err(e) => e,
+        };
+        let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+        let aside = PathBuf::from(format!("{}.corrupt-{}", path.display(), stamp));
+        std::fs::rename(path, &aside)
+            .with_context(|| format!("Failed to move {} aside after: {:#}", path.display(), e))?;
+        for suffix in ["-wal", "-shm"] {
+            let _ = std::fs::rename(format!("{}{}", path.display(), suffix), format!("{}{}", aside.display(), suffix));
+        }
+        EVENTS.publish(
+            Severity::Warning,
+            "blackbox",
+            format!("Black box {} was unreadable ({:#}), moved to {}", path.display(), e, aside.display()),
+            serde_json::json!({ "path": path, "moved_to": aside }),
+        );
+        open_checked(path).with_context(|| format!("Failed to create {}", path.display()))
+    }
+
+    pub fn write_loop(mut conn: Connection, rx: Receiver<Record>, config: BlackboxConfig) {
+        let flush_interval = Duration::from_millis(config.flush_interval_ms);
+        let max_bytes = config.max_mb as i64 * 1024 * 1024;
+        let mut batch = Vec::new();
+        // Reported once, not for every batch.
+        let mut failing = false;
+        loop {
+            let deadline = Instant::now() + flush_interval;
+            let mut disconnected = false;
+            loop {
+                match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(record) => batch.push(record),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        disconnected = true;
+                        break;
+                    }
+                }
+            }
+            if !batch.is_empty() {
+                let written = insert(&mut conn, &batch).and_then(|_| prune(&conn, max_bytes));
+                match &written {
+                    Ok(()) => {
+                        if failing {
+                            info!(target: "blackbox", "Black box writes recovered");
+                        }
+                        for record in &batch {
+                            METRICS.blackbox_records.inc(&record.kind);
+                        }
+                    }
+                    Err(e) => {
+                        if !failing {
+                            warn!(target: "blackbox", "Black box write failed, dropping records: {:#}", e);
+                        }
+                        METRICS.blackbox_dropped.add(batch.len() as u64);
+                    }
+                }
+                failing = written.is_err();
+                batch.clear();
+            }
+            if disconnected {
+                return;
+            }
+        }
+    }
+
+    fn insert(conn: &mut Connection, batch: &[Record]) -> Result<()> {
+        let tx = conn.transaction()?;
+        {
+            let mut insert = tx.prepare_cached("INSERT INTO records (t_ms, kind, data) VALUES (?1, ?2, ?3)")?;
+            for record in batch {
+                insert.execute(params![record.t_ms as i64, record.kind, record.data.to_string()])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Pages freed by pruning are reused, so keeping the live pages under
+    // the cap keeps the file there too.
+    fn used_bytes(conn: &Connection) -> Result<i64> {
+        let pages: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let free: i64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+        let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        Ok((pages - free) * page_size)
+    }
+
+    fn prune(conn: &Connection, max_bytes: i64) -> Result<()> {
+        let mut used = used_bytes(conn)?;
+        METRICS.blackbox_bytes.set(used);
+        while used > max_bytes {
+            let rows: i64 = conn.query_row("SELECT COUNT(*) FROM records", [], |row| row.get(0))?;
+            if rows == 0 {
+                break;
+            }
+            let oldest = ((rows as f64 * PRUNE_FRACTION) as i64).max(PRUNE_MIN_ROWS);
+            conn.execute(
+                "DELETE FROM records WHERE id IN (SELECT id FROM records ORDER BY id LIMIT ?1)",
+                params![oldest],
+            )?;
+            METRICS.blackbox_pruned.add(oldest.min(rows) as u64);
+            used = used_bytes(conn)?;
+            METRICS.blackbox_bytes.set(used);
+        }
+        Ok(())
+    }
+
+    // Reads through its own connection; WAL lets it run alongside the
+    // writer, or with the rover not running at all.
+    pub fn query(path: &Path, query: &Query) -> Result<Vec<Record>> {
+        if !path.exists() {
+            anyhow::bail!("No black box at {}", path.display());
+        }
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut select = conn.prepare(
+            "SELECT t_ms, kind, data FROM records
+             WHERE t_ms >= ?1 AND t_ms <= ?2 AND (?3 IS NULL OR kind = ?3)
+             ORDER BY t_ms, id LIMIT ?4",
+        )?;
+        // LIMIT -1 is no limit.
+        let limit = query.limit.map_or(-1, |limit| limit as i64);
+        let rows = select.query_map(
+            params![
+                query.from_ms.unwrap_or(0) as i64,
+                query.to_ms.map_or(i64::MAX, |t| t as i64),
+                query.kind,
+                limit,
+            ],
+            |row| {
+                let data: String = row.get(2)?;
+                Ok(Record {
+                    t_ms: row.get::<_, i64>(0)? as u64,
+                    kind: row.get(1)?,
+                    data: serde_json::from_str(&data).unwrap_or(serde_json::Value::String(data)),
+                })
+            },
+        )?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+// Does nothing unless blackbox.enabled is set. The database is written on
+// its own thread; events and sampled telemetry reach it from tasks of
+// their own, and decisions and commands straight from the control loop.
+#[cfg(feature = "blackbox")]
+pub fn spawn(config: &BlackboxConfig) {
+    use crate::events::EVENTS;
+    use crate::telemetry::{TelemetrySample, METRIC_NAMES};
+    use std::time::Duration;
+    use tracing::{info, warn};
+
+    if !config.enabled {
+        return;
+    }
+    // Subscribed first, so a damaged database being moved aside is the
+    // first thing the new one records.
+    let mut subscription = EVENTS.subscribe();
+    let path = std::path::PathBuf::from(&config.path);
+    let conn = match store::open(&path) {
+        Ok(conn) => conn,
+        Err(e) => {
+            warn!(target: "blackbox", "Black box disabled: {:#}", e);
+            return;
+        }
+    };
+    let (tx, rx) = crossbeam_channel::bounded(QUEUE_SIZE);
+    if BLACKBOX.tx.set(tx).is_err() {
+        return;
+    }
+    let writer_config = config.clone();
+    std::thread::spawn(move || store::write_loop(conn, rx, writer_config));
+    info!(target: "blackbox", "Recording to {} (up to {} MB)", path.display(), config.max_mb);
+
+    tokio::spawn(async move {
+        loop {
+            match subscription.recv().await {
+                Ok(event) => BLACKBOX.record(RecordKind::Event, serde_json::json!(event)),
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(missed)) => {
+                    METRICS.blackbox_dropped.add(missed);
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+
+    let interval = Duration::from_secs_f32(config.telemetry_interval_s);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let sample = TelemetrySample::from_metrics();
+            let values: serde_json::Map<_, _> = METRIC_NAMES.iter()
+                .map(|&name| (name.to_string(), serde_json::json!(sample.get(name))))
+                .collect();
+            BLACKBOX.record(RecordKind::Telemetry, serde_json::Value::Object(values));
+        }
+    });
+}
+
+#[cfg(not(feature = "blackbox"))]
+pub fn spawn(config: &BlackboxConfig) {
+    if config.enabled {
+        tracing::warn!(target: "blackbox", "blackbox.enabled is set but the binary was built without the `blackbox` feature");
+    }
+}
+
+#[cfg(feature = "blackbox")]
+pub fn query(config: &BlackboxConfig, query: &Query) -> Result<Vec<Record>> {
+    store::query(std::path::Path::new(&config.path), query)
+}
+
+#[cfg(not(feature = "blackbox"))]
+pub fn query(_config: &BlackboxConfig, _query: &Query) -> Result<Vec<Record>> {
+    anyhow::bail!("built without the `blackbox` feature")
+}
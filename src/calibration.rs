@@ -0,0 +1,121 @@
+// src/calibration.rs
+use anyhow::Result;
+use parking_lot::RwLock;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::metrics::METRICS;
+use crate::session::SessionEntry;
+use crate::vision::VisionSystem;
+
+pub struct FocalLengthEstimate {
+    pub focal_length_px: f32,
+    pub median_height_px: f32,
+    pub samples: usize,
+}
+
+// Pinhole model: focal = pixel height * distance / real height. The
+// median over several frames keeps one bad box from skewing the result.
+pub async fn estimate_focal_length(
+    vision: &RwLock<VisionSystem>,
+    class_name: &str,
+    distance_m: f32,
+    real_height_m: f32,
+    samples: usize,
+    timeout: Duration,
+) -> Result<FocalLengthEstimate> {
+    let started = Instant::now();
+    let mut heights = Vec::with_capacity(samples);
+    let mut last_frame = METRICS.frames_processed.get();
+
+    while heights.len() < samples {
+        if started.elapsed() > timeout {
+            anyhow::bail!(
+                "Only {} of {} frames showed a {} within {:?}",
+                heights.len(), samples, class_name, timeout
+            );
+        }
+
+        let detections = vision.write().process_frame()?;
+        let frame = METRICS.frames_processed.get();
+        if frame != last_frame {
+            last_frame = frame;
+            let tallest = detections.iter()
+                .filter(|d| d.class_name == class_name)
+                .map(|d| d.bbox.3)
+                .max();
+            if let Some(height) = tallest.filter(|h| *h > 0) {
+                heights.push(height as f32);
+                info!(target: "vision", "Sample {}/{}: {} px", heights.len(), samples, height);
+            }
+        }
+
+        tokio::time::sleep(Duration::from_millis(33)).await;
+    }
+
+    heights.sort_by(|a, b| a.total_cmp(b));
+    let median_height_px = heights[heights.len() / 2];
+    Ok(FocalLengthEstimate {
+        focal_length_px: median_height_px * distance_m / real_height_m,
+        median_height_px,
+        samples: heights.len(),
+    })
+}
+
+pub struct ConfidenceFit {
+    pub points: Vec<(f32, f32)>,
+    pub samples: usize,
+    pub positives: usize,
+}
+
+// Learns a vision.confidence_calibration curve from a session recording
+// whose detections were labelled by hand: "label": true on real objects,
+// false on false positives, unlabelled ones skipped. Detections are split
+// by raw confidence into `bins` equal groups whose precision becomes the
+// calibrated value, and neighbouring groups are pooled until the curve
+// never decreases.
+pub fn fit_confidence_curve(entries: impl Iterator<Item = SessionEntry>, bins: usize) -> Result<ConfidenceFit> {
+    let mut samples: Vec<(f32, bool)> = Vec::new();
+    for entry in entries {
+        if entry.payload.get("type").and_then(|t| t.as_str()) != Some("frame") {
+            continue;
+        }
+        let Some(detections) = entry.payload.get("detections").and_then(|d| d.as_array()) else { continue };
+        for detection in detections {
+            let Some(label) = detection.get("label").and_then(|l| l.as_bool()) else { continue };
+            let raw = detection.get("raw_confidence")
+                .or_else(|| detection.get("confidence"))
+                .and_then(|c| c.as_f64());
+            if let Some(raw) = raw {
+                samples.push((raw as f32, label));
+            }
+        }
+    }
+    let bins = bins.max(1);
+    if samples.len() < bins * 2 {
+        anyhow::bail!("Only {} labelled detections; {} bins need at least {}", samples.len(), bins, bins * 2);
+    }
+    samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // (raw sum, positives, count) per group.
+    let mut groups: Vec<(f32, usize, usize)> = Vec::new();
+    for chunk in samples.chunks(samples.len().div_ceil(bins)) {
+        groups.push((chunk.iter().map(|s| s.0).sum(), chunk.iter().filter(|s| s.1).count(), chunk.len()));
+        while let [.., a, b] = groups[..] {
+            let mean = |g: (f32, usize, usize)| g.0 / g.2 as f32;
+            let precision = |g: (f32, usize, usize)| g.1 as f32 / g.2 as f32;
+            // Equal raw means would make the points ambiguous, so pool those too.
+            if precision(a) <= precision(b) && mean(a) < mean(b) {
+                break;
+            }
+            groups.truncate(groups.len() - 2);
+            groups.push((a.0 + b.0, a.1 + b.1, a.2 + b.2));
+        }
+    }
+
+    Ok(ConfidenceFit {
+        points: groups.iter().map(|&(sum, positives, count)| (sum / count as f32, positives as f32 / count as f32)).collect(),
+        samples: samples.len(),
+        positives: samples.iter().filter(|s| s.1).count(),
+    })
+}
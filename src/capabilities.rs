@@ -0,0 +1,76 @@
+// src/capabilities.rs
+//
+// What this binary was built with. The same build can't tell a missing
+// relay from a bench build that has no GPIO at all, so it says which
+// features it has at startup and in /api/identity.
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Capability {
+    pub name: &'static str,
+    pub enabled: bool,
+}
+
+const fn capability(name: &'static str, enabled: bool) -> Capability {
+    Capability { name, enabled }
+}
+
+pub fn get_capabilities() -> Vec<Capability> {
+    vec![
+        capability("rover-hw", cfg!(feature = "rover-hw")),
+        capability("sim", cfg!(feature = "sim")),
+        capability("web-ui", cfg!(feature = "web-ui")),
+        capability("webp", cfg!(feature = "webp")),
+        // The system OpenCV, with dnn. Asked for with the `opencv` feature,
+        // but the Pi links it without that too, so this is what was linked.
+        capability("opencv", opencv_embedded::SYSTEM_OPENCV),
+        capability("hw-watchdog", cfg!(feature = "hw-watchdog")),
+        capability("gps", cfg!(feature = "gps")),
+        capability("range-sensors", cfg!(feature = "range-sensors")),
+        capability("imu", cfg!(feature = "imu")),
+        capability("interlock", cfg!(feature = "interlock")),
+        capability("buzzer", cfg!(feature = "buzzer")),
+        capability("vcgencmd", cfg!(feature = "vcgencmd")),
+        capability("tls", cfg!(feature = "tls")),
+        capability("mdns", cfg!(feature = "mdns")),
+        capability("mqtt", cfg!(feature = "mqtt")),
+        capability("ros", cfg!(feature = "ros")),
+        capability("blackbox", cfg!(feature = "blackbox")),
+    ]
+}
+
+// Which of the targets in Cargo.toml this looks like: "rover" with the
+// hardware, "bench" with the dashboard, "sim" with neither.
+pub fn get_target() -> &'static str {
+    if cfg!(feature = "rover-hw") {
+        "rover"
+    } else if cfg!(feature = "web-ui") {
+        "bench"
+    } else if cfg!(feature = "sim") {
+        "sim"
+    } else {
+        "minimal"
+    }
+}
+
+// {"target": "bench", "features": {"sim": true, ...}}
+pub fn report() -> serde_json::Value {
+    let features: serde_json::Map<String, serde_json::Value> = get_capabilities()
+        .into_iter()
+        .map(|c| (c.name.to_string(), c.enabled.into()))
+        .collect();
+    serde_json::json!({ "target": get_target(), "features": features })
+}
+
+// One line each for what is in and what is out, for the startup log.
+pub fn summary() -> (String, String) {
+    let (with, without): (Vec<_>, Vec<_>) = get_capabilities().into_iter().partition(|c| c.enabled);
+    let names = |list: Vec<Capability>| {
+        if list.is_empty() {
+            "none".to_string()
+        } else {
+            list.iter().map(|c| c.name).collect::<Vec<_>>().join(", ")
+        }
+    };
+    (names(with), names(without))
+}
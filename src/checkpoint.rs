@@ -0,0 +1,279 @@
+// src/checkpoint.rs
+//
+// What the rover was doing and what it knew, written out every few seconds
+// so that after an unexpected restart (the process OOM-killed mid-mission
+// and started again by systemd, say) it knows where it is and what it was
+// doing. A mission that was underway is only offered for resuming; the
+// rover waits in Idle until an operator confirms it.
+use anyhow::{Context, Result};
+use chrono::Local;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::clock::CLOCK;
+use crate::config::{CheckpointConfig, CheckpointPart};
+use crate::events::{Severity, EVENTS};
+use crate::mapping::{GridDiff, RoverPose};
+use crate::pathfinding::{PathPlanner, PathPoint, PlannerMemory};
+use crate::state_machine::{RoverState, StateHandle, Trigger};
+
+// Bumped whenever a checkpoint from an older build can't be read as is.
+pub const FORMAT: u32 = 1;
+
+// Held across each write and the delete at shutdown, so a write already
+// underway can't put the file back afterwards.
+static WRITING: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub format: u32,
+    // Wall-clock ms, since a restart resets the monotonic clock.
+    pub written_ms: u64,
+    // Each part is None when checkpoint.include leaves it out.
+    pub mission: Option<Mission>,
+    pub pose: Option<RoverPose>,
+    pub map: Option<MapTiles>,
+    pub memory: Option<PlannerMemory>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mission {
+    pub state: RoverState,
+    pub goal: Option<PathPoint>,
+    pub docking: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapTiles {
+    pub resolution_m: f32,
+    #[serde(flatten)]
+    pub diff: GridDiff,
+}
+
+// A mission picked up from a checkpoint, waiting on an operator.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResumeOffer {
+    pub written_ms: u64,
+    pub mission: Mission,
+}
+
+impl Mission {
+    // Only autonomous driving is picked up again; anything under manual
+    // control or faulted stays stopped.
+    pub fn is_underway(&self) -> bool {
+        self.state == RoverState::Autonomous
+    }
+}
+
+// Only the parts in `include`. While a resume is on offer the mission it
+// offers stands in for the current one, so a second crash before anyone
+// answers doesn't lose it.
+pub fn capture(planner: &PathPlanner, state: &StateHandle, include: &[CheckpointPart]) -> Checkpoint {
+    let has = |part| include.contains(&part);
+    let mission = has(CheckpointPart::Mission).then(|| match state.get_resume() {
+        Some(offer) => offer.mission,
+        None => Mission { state: state.get_state(), goal: planner.get_goal(), docking: planner.is_docking() },
+    });
+    Checkpoint {
+        format: FORMAT,
+        written_ms: CLOCK.wall_ms(),
+        mission,
+        pose: has(CheckpointPart::Pose).then(|| planner.get_pose()),
+        map: has(CheckpointPart::Map).then(|| {
+            let diff = planner.get_map_diff(0);
+            MapTiles { resolution_m: planner.get_map_snapshot().resolution_m, diff }
+        }),
+        memory: has(CheckpointPart::Obstacles).then(|| planner.get_memory()),
+    }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+// Written in full to a temporary file and synced before it is renamed over
+// the last one, so whenever the process dies one whole checkpoint is left.
+pub fn write(path: &Path, checkpoint: &Checkpoint) -> Result<()> {
+    let _writing = WRITING.lock();
+    write_file(path, checkpoint)
+}
+
+fn write_file(path: &Path, checkpoint: &Checkpoint) -> Result<()> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    if let Some(dir) = dir {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let tmp = temp_path(path);
+    let mut text = serde_json::to_vec(checkpoint)?;
+    text.push(b'\n');
+    let mut file = File::create(&tmp).with_context(|| format!("Failed to create {}", tmp.display()))?;
+    file.write_all(&text).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    file.sync_all().with_context(|| format!("Failed to sync {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    // The rename itself only lasts once the directory is synced.
+    if let Ok(dir) = File::open(dir.unwrap_or(Path::new("."))) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+// After a clean shutdown there is nothing to resume.
+pub fn discard(config: &CheckpointConfig) {
+    let _writing = WRITING.lock();
+    let path = Path::new(&config.path);
+    match fs::remove_file(path) {
+        Ok(()) => info!(target: "checkpoint", "Removed {} on shutdown", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!(target: "checkpoint", "Failed to remove {}: {}", path.display(), e),
+    }
+    let _ = fs::remove_file(temp_path(path));
+}
+
+// The checkpoint at checkpoint.path if there is a usable one. A temporary
+// file beside it is a write that never finished and is deleted; one that
+// is stale, unreadable or from another format is moved aside with an
+// event, and None returned.
+pub fn load(config: &CheckpointConfig) -> Option<Checkpoint> {
+    let path = Path::new(&config.path);
+    let tmp = temp_path(path);
+    if fs::remove_file(&tmp).is_ok() {
+        info!(target: "checkpoint", "Deleted {}, left by a write that never finished", tmp.display());
+    }
+    let text = match fs::read(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!(target: "checkpoint", "Failed to read {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let checkpoint: Checkpoint = match serde_json::from_slice(&text) {
+        Ok(checkpoint) => checkpoint,
+        Err(e) => {
+            quarantine(path, "corrupt", &e.to_string());
+            return None;
+        }
+    };
+    if checkpoint.format != FORMAT {
+        quarantine(path, "corrupt", &format!("format {} where {} was expected", checkpoint.format, FORMAT));
+        return None;
+    }
+    let age_s = CLOCK.wall_ms().saturating_sub(checkpoint.written_ms) as f32 / 1000.0;
+    if age_s > config.max_age_s {
+        quarantine(path, "stale", &format!("{:.0} s old, past checkpoint.max_age_s", age_s));
+        return None;
+    }
+    debug!(target: "checkpoint", "Loaded {}, {:.1} s old", path.display(), age_s);
+    Some(checkpoint)
+}
+
+fn quarantine(path: &Path, why: &str, reason: &str) {
+    let stamp = Local::now().format("%Y%m%d-%H%M%S");
+    let aside = PathBuf::from(format!("{}.{}-{}", path.display(), why, stamp));
+    let moved = fs::rename(path, &aside);
+    if let Err(e) = &moved {
+        warn!(target: "checkpoint", "Failed to move {} aside: {}", path.display(), e);
+        let _ = fs::remove_file(path);
+    }
+    warn!(target: "checkpoint", "Checkpoint {} is {} ({}), not resuming from it", path.display(), why, reason);
+    EVENTS.publish(
+        Severity::Warning,
+        "checkpoint",
+        format!("Checkpoint {} was {} ({}), moved to {}", path.display(), why, reason, aside.display()),
+        serde_json::json!({ "path": path, "moved_to": moved.is_ok().then_some(&aside), "why": why, "reason": reason }),
+    );
+}
+
+// Puts the pose, map and obstacles straight back, since none of them move
+// the rover, and returns the mission to offer if one was underway. A part
+// that doesn't fit the current config is left out.
+pub fn restore(checkpoint: Checkpoint, planner: &mut PathPlanner) -> Option<ResumeOffer> {
+    if let Some(pose) = checkpoint.pose {
+        planner.restore_pose(pose);
+    }
+    if let Some(map) = &checkpoint.map {
+        if let Err(e) = planner.restore_map(map.resolution_m, &map.diff) {
+            warn!(target: "checkpoint", "Not restoring the map: {:#}", e);
+        }
+    }
+    if let Some(memory) = checkpoint.memory {
+        planner.restore_memory(memory);
+    }
+    let pose = planner.get_pose();
+    info!(target: "checkpoint", "Restored from a checkpoint at ({:.2}, {:.2}) heading {:.0}", pose.x, pose.y, pose.heading_deg);
+    let mission = checkpoint.mission.filter(Mission::is_underway)?;
+    Some(ResumeOffer { written_ms: checkpoint.written_ms, mission })
+}
+
+// Answers the offer by putting the goal or docking back and arming. The
+// offer stands until the rover is actually autonomous, so arming that is
+// refused can be tried again.
+pub fn confirm(state: &StateHandle, planner: &RwLock<PathPlanner>, by: &str) -> Option<ResumeOffer> {
+    let offer = state.get_resume()?;
+    {
+        let mut planner = planner.write();
+        planner.set_goal(offer.mission.goal.clone());
+        if offer.mission.docking {
+            planner.set_docking(true);
+        }
+    }
+    info!(target: "checkpoint", "Resuming the mission, confirmed by {}", by);
+    EVENTS.publish(Severity::Info, "checkpoint", "Mission resumed", serde_json::json!({ "by": by, "mission": offer.mission }));
+    state.request(Trigger::StartAutonomous);
+    Some(offer)
+}
+
+pub fn decline(state: &StateHandle, by: &str) -> Option<ResumeOffer> {
+    let offer = state.take_resume()?;
+    info!(target: "checkpoint", "Mission not resumed, declined by {}", by);
+    EVENTS.publish(Severity::Info, "checkpoint", "Mission not resumed", serde_json::json!({ "by": by, "mission": offer.mission }));
+    Some(offer)
+}
+
+// Does nothing unless checkpoint.enabled is set. Writes on a thread of its
+// own, so a slow SD card never holds up the control loop, and skips a
+// write when nothing but the time has changed.
+pub fn spawn(config: &CheckpointConfig, state: StateHandle, planner: Arc<RwLock<PathPlanner>>) {
+    if !config.enabled {
+        return;
+    }
+    let config = config.clone();
+    std::thread::spawn(move || {
+        let path = PathBuf::from(&config.path);
+        let mut last: Option<serde_json::Value> = None;
+        let mut failing = false;
+        info!(target: "checkpoint", "Checkpointing to {} every {:.1} s", path.display(), config.interval_s);
+        loop {
+            std::thread::sleep(Duration::from_secs_f32(config.interval_s));
+            let checkpoint = capture(&planner.read(), &state, &config.include);
+            let content = serde_json::to_value(Checkpoint { written_ms: 0, ..checkpoint.clone() }).ok();
+            if content.is_some() && content == last {
+                continue;
+            }
+            let _writing = WRITING.lock();
+            if state.get_state() == RoverState::ShuttingDown {
+                return;
+            }
+            match write_file(&path, &checkpoint) {
+                Ok(()) => {
+                    last = content;
+                    failing = false;
+                }
+                Err(e) if !failing => {
+                    warn!(target: "checkpoint", "Checkpoint not written: {:#}", e);
+                    EVENTS.publish(Severity::Warning, "checkpoint", format!("Checkpoint not written: {:#}", e), serde_json::Value::Null);
+                    failing = true;
+                }
+                Err(_) => {}
+            }
+        }
+    });
+}
@@ -0,0 +1,165 @@
+// src/cli.rs
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+use scout_vision::config::DEFAULT_CONFIG_PATH;
+
+#[derive(Parser)]
+#[command(version, about = "Scout rover vision and control")]
+pub struct Cli {
+    /// Config file to load (and save runtime changes to)
+    #[arg(long, global = true, default_value = DEFAULT_CONFIG_PATH)]
+    pub config: PathBuf,
+
+    /// Log filter, e.g. "info" or "info,motors=debug" (overrides RUST_LOG)
+    #[arg(long, global = true)]
+    pub log_level: Option<String>,
+
+    /// Also write JSON logs to a daily-rotated file in this directory
+    #[arg(long, global = true)]
+    pub log_json: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Subcommand)]
+pub enum CliCommand {
+    /// Run the full rover stack (the default)
+    Run(WebArgs),
+    /// Probe camera, bridge and GPIO, print a report and exit non-zero on failure
+    Check,
+    /// Calibrate vision parameters against a known target
+    #[command(subcommand)]
+    Calibrate(CalibrateCommand),
+    /// Drive the stack from a recorded session instead of the camera
+    Replay {
+        /// Session file written by the web server's session recorder
+        recording: PathBuf,
+        /// Playback speed factor
+        #[arg(long, default_value_t = 1.0)]
+        speed: f32,
+        #[command(flatten)]
+        web: WebArgs,
+    },
+    /// Drive the stack from a scripted scenario instead of the camera
+    #[cfg(feature = "sim")]
+    Simulate {
+        /// Scenario JSON file
+        scenario: PathBuf,
+        #[command(flatten)]
+        web: WebArgs,
+    },
+    /// Read the flight recorder at blackbox.path
+    #[command(subcommand)]
+    Blackbox(BlackboxCommand),
+    /// Build training datasets from session recordings
+    #[command(subcommand)]
+    Dataset(DatasetCommand),
+}
+
+#[derive(Subcommand)]
+pub enum DatasetCommand {
+    /// Write a recording's frames out as JPEGs with YOLO and COCO annotations
+    Export {
+        /// Session file recorded with session.include_images
+        recording: PathBuf,
+        /// Directory to write the dataset to
+        #[arg(long)]
+        output: PathBuf,
+        /// Leave out detections under this calibrated confidence
+        #[arg(long, default_value_t = 0.0)]
+        min_confidence: f32,
+        /// Only this class; repeat for more, in the order of their class index
+        #[arg(long = "class")]
+        classes: Vec<String>,
+        /// Seconds into the recording to start at
+        #[arg(long)]
+        from_s: Option<f32>,
+        /// Seconds into the recording to stop at
+        #[arg(long)]
+        to_s: Option<f32>,
+        /// Only every nth frame with an image
+        #[arg(long, default_value_t = 1)]
+        every: usize,
+        /// Skip frames whose mean difference from the last one kept is under this (0-1, 0 keeps all)
+        #[arg(long, default_value_t = 0.01)]
+        dedup_below: f32,
+        /// Leave out frames with no detections left instead of keeping them as background
+        #[arg(long)]
+        skip_empty: bool,
+        /// Only frames an operator annotated
+        #[arg(long)]
+        annotated_only: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BlackboxCommand {
+    /// Write recorded decisions, motor commands, events and telemetry out
+    Export {
+        /// Start: a window back from now ("10m"), epoch milliseconds or an RFC 3339 time
+        #[arg(long, default_value = "1h")]
+        since: String,
+        /// End, in the same forms; defaults to now
+        #[arg(long)]
+        until: Option<String>,
+        /// Only this kind of record: decision, command, event, telemetry or annotation
+        #[arg(long)]
+        kind: Option<String>,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+#[derive(Subcommand)]
+pub enum CalibrateCommand {
+    /// Derive vision.focal_length_px from an object at a measured distance
+    FocalLength {
+        /// Distance from the camera to the object, in metres
+        #[arg(long)]
+        distance: f32,
+        /// Class of the object; its height comes from vision.object_heights_m
+        #[arg(long, default_value = "person")]
+        class: String,
+        /// Frames to average over
+        #[arg(long, default_value_t = 30)]
+        samples: usize,
+        /// Write the result to the config file
+        #[arg(long)]
+        save: bool,
+    },
+    /// Learn a vision.confidence_calibration curve from a labelled recording
+    Confidence {
+        /// Session file whose detections carry "label": true/false
+        recording: PathBuf,
+        /// Detector the recording came from, e.g. imx500 or blob
+        #[arg(long, default_value = "imx500")]
+        source: String,
+        /// Confidence groups to fit
+        #[arg(long, default_value_t = 10)]
+        bins: usize,
+        /// Write the result to the config file
+        #[arg(long)]
+        save: bool,
+    },
+}
+
+#[derive(Args, Clone, Default)]
+pub struct WebArgs {
+    /// Don't start the web server
+    #[arg(long)]
+    pub no_web: bool,
+    /// Override web.port
+    #[arg(long)]
+    pub port: Option<u16>,
+}
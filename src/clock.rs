@@ -0,0 +1,157 @@
+// src/clock.rs
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub static CLOCK: Lazy<RoverClock> = Lazy::new(RoverClock::new);
+
+// manual_ms while the clock follows real time.
+const REAL_TIME: u64 = u64::MAX;
+
+// The one time base for the pipeline. Everything is measured as monotonic
+// milliseconds since the process started, so intervals survive NTP
+// stepping the wall clock. Wall-clock timestamps for display are derived
+// from the wall time sampled once at startup, and stay monotonic too.
+pub struct RoverClock {
+    start: Instant,
+    wall_at_start_ms: u64,
+    // Bridge monotonic time minus rover monotonic time, in ms.
+    bridge_offset_ms: Mutex<Option<f64>>,
+    // Set by a harness that steps time itself; see set_manual.
+    manual_ms: AtomicU64,
+}
+
+impl RoverClock {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            wall_at_start_ms: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+            bridge_offset_ms: Mutex::new(None),
+            manual_ms: AtomicU64::new(REAL_TIME),
+        }
+    }
+
+    pub fn now_ms(&self) -> u64 {
+        match self.manual_ms.load(Ordering::Relaxed) {
+            REAL_TIME => self.start.elapsed().as_millis() as u64,
+            manual => manual,
+        }
+    }
+
+    // now_ms with the fraction kept, for timing stages shorter than a
+    // millisecond.
+    pub fn now_ms_f64(&self) -> f64 {
+        match self.manual_ms.load(Ordering::Relaxed) {
+            REAL_TIME => self.start.elapsed().as_secs_f64() * 1000.0,
+            manual => manual as f64,
+        }
+    }
+
+    // Stops the clock at `ms`; from then on it only moves with advance().
+    // For simulations that run faster than real time.
+    pub fn set_manual(&self, ms: u64) {
+        self.manual_ms.store(ms.min(REAL_TIME - 1), Ordering::Relaxed);
+    }
+
+    // Moves a manual clock on by `dt`. Does nothing to a real-time one.
+    pub fn advance(&self, dt: Duration) {
+        let _ = self.manual_ms.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |ms| {
+            (ms != REAL_TIME).then(|| (ms + dt.as_millis() as u64).min(REAL_TIME - 1))
+        });
+    }
+
+    pub fn wall_ms(&self) -> u64 {
+        self.to_wall_ms(self.now_ms())
+    }
+
+    pub fn to_wall_ms(&self, mono_ms: u64) -> u64 {
+        self.wall_at_start_ms + mono_ms
+    }
+
+    // The bridge handshake assumes the line reached us instantly, so the
+    // offset starts slightly low. Frames can only correct it upwards: a
+    // frame that appears to be captured after it arrived means the offset
+    // was underestimated.
+    pub fn sync_bridge(&self, bridge_monotonic_s: f64) {
+        let offset = bridge_monotonic_s * 1000.0 - self.now_ms_f64();
+        *self.bridge_offset_ms.lock() = Some(offset);
+    }
+
+    // Maps a bridge capture time onto the rover clock. Without a handshake
+    // the receive time is the best estimate there is.
+    pub fn bridge_to_rover_ms(&self, bridge_monotonic_s: Option<f64>) -> u64 {
+        self.map_bridge_ms(bridge_monotonic_s).map_or_else(|| self.now_ms(), |ms| ms as u64)
+    }
+
+    // As bridge_to_rover_ms, but with the fraction kept and None rather
+    // than the receive time when there is nothing to map.
+    pub fn map_bridge_ms(&self, bridge_monotonic_s: Option<f64>) -> Option<f64> {
+        let now = self.now_ms_f64();
+        let bridge_ms = bridge_monotonic_s? * 1000.0;
+        let mut offset = self.bridge_offset_ms.lock();
+        let current = (*offset)?;
+
+        let corrected = current.max(bridge_ms - now);
+        *offset = Some(corrected);
+        Some((bridge_ms - corrected).max(0.0))
+    }
+
+    pub fn get_bridge_offset_ms(&self) -> Option<f64> {
+        *self.bridge_offset_ms.lock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_manual_clock_only_moves_when_advanced() {
+        let clock = RoverClock::new();
+        clock.set_manual(1_000);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(clock.now_ms(), 1_000);
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(clock.now_ms(), 1_250);
+        assert_eq!(clock.now_ms_f64(), 1_250.0);
+        assert_eq!(clock.wall_ms(), clock.wall_at_start_ms + 1_250);
+    }
+
+    #[test]
+    fn advance_leaves_a_real_time_clock_alone() {
+        let clock = RoverClock::new();
+        clock.advance(Duration::from_secs(3_600));
+        assert!(clock.now_ms() < 3_600_000);
+    }
+
+    #[test]
+    fn bridge_times_map_through_the_handshake_offset() {
+        let clock = RoverClock::new();
+        clock.set_manual(10_000);
+        assert_eq!(clock.map_bridge_ms(Some(500.0)), None);
+        assert_eq!(clock.bridge_to_rover_ms(Some(500.0)), 10_000);
+
+        // The bridge's clock reads 500 s when the rover's reads 10 s.
+        clock.sync_bridge(500.0);
+        assert_eq!(clock.get_bridge_offset_ms(), Some(490_000.0));
+        clock.advance(Duration::from_millis(100));
+        // A frame captured 40 ms before it arrived.
+        assert_eq!(clock.map_bridge_ms(Some(500.060)).map(f64::round), Some(10_060.0));
+        assert_eq!(clock.map_bridge_ms(None), None);
+    }
+
+    #[test]
+    fn a_frame_from_the_future_raises_the_offset() {
+        let clock = RoverClock::new();
+        clock.set_manual(10_000);
+        clock.sync_bridge(500.0);
+        // Captured, by the offset, 30 ms after it arrived: the handshake
+        // line must have been 30 ms on its way.
+        assert_eq!(clock.map_bridge_ms(Some(500.030)).map(f64::round), Some(10_000.0));
+        assert_eq!(clock.get_bridge_offset_ms().map(f64::round), Some(490_030.0));
+        // And it is never lowered again by a frame that looks early.
+        clock.map_bridge_ms(Some(499.0));
+        assert_eq!(clock.get_bridge_offset_ms().map(f64::round), Some(490_030.0));
+    }
+}
@@ -0,0 +1,2570 @@
+// src/config.rs
+use anyhow::{Context, Result};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+pub const DEFAULT_CONFIG_PATH: &str = "scout.toml";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoverConfig {
+    pub identity: IdentityConfig,
+    pub vision: VisionConfig,
+    pub motors: MotorConfig,
+    pub planner: PlannerConfig,
+    pub web: WebConfig,
+    pub sessions: SessionConfig,
+    pub control: ControlConfig,
+    pub schedule: ScheduleConfig,
+    pub mqtt: MqttConfig,
+    pub gps: GpsConfig,
+    pub range: RangeConfig,
+    pub ros: RosConfig,
+    pub annunciator: AnnunciatorConfig,
+    pub blackbox: BlackboxConfig,
+    pub selftest: SelfTestConfig,
+    pub display: DisplayConfig,
+    pub thermal: ThermalConfig,
+    pub updates: UpdatesConfig,
+    pub interlock: InterlockConfig,
+    pub power: PowerConfig,
+    pub imu: ImuConfig,
+    pub checkpoint: CheckpointConfig,
+    pub logs: LogsConfig,
+    pub teleop: TeleopConfig,
+    pub hazards: HazardsConfig,
+    pub snapshot: SnapshotConfig,
+}
+
+// Identity is fixed for the lifetime of the process; it tags every
+// message a multi-rover dashboard receives.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdentityConfig {
+    pub rover_id: String,
+    pub rover_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VisionConfig {
+    pub min_confidence: f32,
+    pub focal_length_px: f32,
+    pub default_object_height_m: f32,
+    pub object_heights_m: BTreeMap<String, f32>,
+    pub person_emergency_stop_m: f32,
+    pub person_stop_m: f32,
+    pub person_slow_down_m: f32,
+    pub obstacle_stop_m: f32,
+    pub obstacle_slow_down_m: f32,
+    // Backend and target for the OpenCV dnn detector, e.g. "cuda"/"cuda"
+    // on a Jetson. The IMX500 pipeline runs on the camera and ignores them.
+    pub dnn_backend: String,
+    pub dnn_target: String,
+    pub detector: VisionDetector,
+    // Per-source (raw, calibrated) confidence points, keyed by one of
+    // DETECTION_SOURCES. min_confidence and everything downstream see
+    // calibrated values; sources without points are left as reported.
+    pub confidence_calibration: BTreeMap<String, Vec<(f32, f32)>>,
+    // Per-source label map (labels.txt or JSON, see vision::labels) for
+    // models that don't use COCO names. The bridge then sends class indices
+    // and they are named here. Read when the vision system starts or its
+    // config changes.
+    pub label_maps: BTreeMap<String, PathBuf>,
+    // Detection rules (see vision::rules) that can make the action the
+    // thresholds below pick more cautious. Read at startup, when the vision
+    // config changes and on POST /api/rules/reload.
+    pub rules_path: Option<PathBuf>,
+    // Settings of the built-in detection filters, in the order they run:
+    // classes dropped outright, an (x, y, w, h) region detections must be
+    // centred in, the IoU a box needs with the previous frame's to keep its
+    // track id, and how much of a track's previous distance carries into
+    // the next (0 turns smoothing off).
+    pub ignored_classes: Vec<String>,
+    pub detection_roi: Option<(i32, i32, i32, i32)>,
+    pub track_iou: f32,
+    pub distance_smoothing: f32,
+    // Where the rover's own chassis or antenna shows in the frame, as
+    // polygons in captured-frame pixels. Before the filters run, a
+    // detection with at least self_mask_min_overlap of its box inside them
+    // is tagged self_occlusion and kept out of navigation; dashboards still
+    // show it, greyed out. Emergency stops never are. Changes apply
+    // immediately.
+    pub self_mask: Vec<Vec<(f32, f32)>>,
+    pub self_mask_min_overlap: f32,
+    // The blob detector looks for pixels darker than blob_dark_threshold
+    // on a frame scaled down to blob_scale_width, keeping blobs that cover
+    // at least blob_min_area_fraction of it and reach into the bottom
+    // blob_bottom_fraction of the frame.
+    pub blob_dark_threshold: f32,
+    pub blob_min_area_fraction: f32,
+    pub blob_bottom_fraction: f32,
+    pub blob_scale_width: u32,
+    // Have the bridge look for AprilTag 36h11 tags (read at startup), all
+    // printed with this black-square edge length.
+    pub fiducials_enabled: bool,
+    pub fiducial_tag_size_m: f32,
+    // Drivable-area estimation: floor is grown from a patch just in front
+    // of the rover on a frame scaled down to free_space_scale_width, taking
+    // pixels within free_space_color_tolerance (BGR distance) of the
+    // patch's colour and free_space_texture_tolerance of its gradient.
+    pub free_space_enabled: bool,
+    pub free_space_scale_width: u32,
+    pub free_space_color_tolerance: f32,
+    pub free_space_texture_tolerance: f32,
+    pub free_space_max_range_m: f32,
+    // The floor between the patch and dropoff_lookahead_m ahead, across
+    // the patch's width, should always be there; the planner treats too
+    // little of it as a drop-off.
+    pub dropoff_lookahead_m: f32,
+    // Ground-plane model: lens height above the floor and the pixel row
+    // of the frame the horizon falls on with the camera level.
+    pub camera_height_m: f32,
+    pub horizon_row_px: f32,
+    // A measured image-to-floor homography, set by POST
+    // /api/vision/ground_calibration. While there is one, a detection
+    // whose box bottom is in the frame is placed where the centre of its
+    // bottom edge meets the floor.
+    pub ground_calibration: Option<GroundCalibration>,
+    // Program and arguments the bridge is started with (read at startup).
+    // Anything that writes bridge lines on stdout will do, such as the
+    // fake_bridge binary.
+    pub bridge_command: Vec<String>,
+    // The bridge is restarted when more than bridge_max_parse_failure_rate
+    // of its lines over bridge_parse_window_s fail to parse.
+    pub bridge_parse_window_s: f32,
+    pub bridge_max_parse_failure_rate: f32,
+    // While bridge diagnostics are on over the API, for bridge_tap_minutes
+    // unless asked otherwise, its stdout lines are written to
+    // bridge_tap_dir in files of bridge_tap_file_mb, the last
+    // bridge_tap_files of them kept.
+    pub bridge_tap_dir: String,
+    pub bridge_tap_minutes: f32,
+    pub bridge_tap_file_mb: u64,
+    pub bridge_tap_files: usize,
+    // How the bridge hands over its JPEGs (read at startup). With shm it
+    // writes them into a ring of frame_slots slots of frame_slot_kb each
+    // and sends only where they are, falling back to base64 when the
+    // ring can't be set up or is full.
+    pub frame_transport: FrameTransport,
+    pub frame_slots: usize,
+    pub frame_slot_kb: usize,
+    // What the bridge is asked to put in the ring (read at startup).
+    // yuv420 skips JPEG on the bridge: frames are converted to RGB or
+    // encoded only when something on the rover wants them so. It needs
+    // the shm transport and slots of at least 451 KB; a bridge that can't
+    // send it goes on sending JPEGs.
+    pub pixel_format: PixelFormat,
+    // Detections from a frame captured longer ago than this are too old
+    // to drive on; the rover stops until a new frame arrives. 0 never
+    // treats them as stale.
+    pub max_frame_age_ms: u64,
+    // The last gray_cache_frames frames are kept in gray at
+    // gray_cache_width x gray_cache_height for motion estimation, which
+    // looks for shifts of up to ego_motion_max_shift_px of those pixels.
+    // Switching the cache off saves decoding every frame.
+    pub gray_cache_enabled: bool,
+    pub gray_cache_frames: usize,
+    pub gray_cache_width: u32,
+    pub gray_cache_height: u32,
+    pub ego_motion_max_shift_px: u32,
+    // Segmentation masks from v2 bridges. A mask covering
+    // mask_expected_fill of its box gives the same distance as the box;
+    // more puts the object nearer, less further. When the two are more
+    // than mask_max_disagreement times apart the box's distance is used.
+    // Masks are dropped altogether while a control tick takes longer than
+    // mask_budget_ms or the Pi is running hot.
+    pub mask_expected_fill: f32,
+    pub mask_max_disagreement: f32,
+    pub mask_budget_ms: f32,
+    // Named bundles of camera settings and detection thresholds, such as
+    // day and night-ir. The one named by profile is active at startup;
+    // without one the settings above are used as they are. Switched over
+    // the API or by auto_profile.
+    pub profiles: BTreeMap<String, VisionProfile>,
+    pub profile: Option<String>,
+    pub auto_profile: AutoProfileConfig,
+    pub auto_exposure: AutoExposureConfig,
+    pub heatmap: HeatmapConfig,
+    pub sweep: SweepConfig,
+    // BCM pin of the camera's IR-cut filter, passed to the bridge for
+    // profiles that set camera.ir_cut (read at startup).
+    pub ir_cut_pin: Option<u8>,
+}
+
+// What a vision profile changes. Thresholds left out keep the base
+// vision settings; calibration points replace the base ones per source.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VisionProfile {
+    pub camera: CameraSettings,
+    pub min_confidence: Option<f32>,
+    pub confidence_calibration: BTreeMap<String, Vec<(f32, f32)>>,
+    pub focal_length_px: Option<f32>,
+}
+
+// Hints the bridge applies to the camera, sent on its stdin whenever the
+// profile changes. Anything left out is the camera's own default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraSettings {
+    pub shutter_us: Option<u32>,
+    pub gain: Option<f32>,
+    pub ev: Option<f32>,
+    // 0 for monochrome.
+    pub saturation: Option<f32>,
+    // True puts the IR-cut filter in front of the sensor, false takes it
+    // away for night vision.
+    pub ir_cut: Option<bool>,
+}
+
+// Switches to dark_profile once the frames' mean brightness (0-255) has
+// stayed under dark_below for hold_s, and back to bright_profile once it
+// has stayed over bright_above as long. The gap between the thresholds
+// and the hold keep a passing light from flapping it. Brightness is
+// sampled every sample_interval_ms, decoding the frame unless the bridge
+// measured it. Choosing a profile over the API pauses this until it is
+// resumed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoProfileConfig {
+    pub enabled: bool,
+    pub dark_profile: String,
+    pub bright_profile: String,
+    pub dark_below: f32,
+    pub bright_above: f32,
+    pub hold_s: f32,
+    pub sample_interval_ms: u64,
+}
+
+// For backlit scenes. Every sample_interval_ms the share of the frame's
+// pixels at or above clip_level (0-255) is measured, over roi only if one
+// is given (x, y, w, h in frame pixels). Above clip_fraction the bridge is
+// asked for step_ev less exposure, down to min_ev; below clear_fraction it
+// is stepped back up toward 0. Steps are at least min_step_interval_ms
+// apart, since the bridge restarts the camera for each. The offset goes on
+// top of the profile's camera ev, and only bridges that offer set_exposure
+// in their hello are sent it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoExposureConfig {
+    pub enabled: bool,
+    pub clip_level: u8,
+    pub clip_fraction: f32,
+    pub clear_fraction: f32,
+    pub step_ev: f32,
+    pub min_ev: f32,
+    pub sample_interval_ms: u64,
+    pub min_step_interval_ms: u64,
+    pub roi: Option<(i32, i32, i32, i32)>,
+}
+
+// Where detections turn up in the frame, as served by GET
+// /api/vision/heatmap: a columns x rows grid over the frame, counting the
+// centre of every box left after the filters, or the whole box spread
+// over the cells it covers with boxes set. Counts halve every half_life_s.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeatmapConfig {
+    pub enabled: bool,
+    pub columns: usize,
+    pub rows: usize,
+    pub half_life_s: f32,
+    pub boxes: bool,
+}
+
+// Threshold sweeps, started with POST /api/vision/sweep: for duration_s
+// (at most max_duration_s) detections down to floor are kept, marked
+// below_threshold and never steered by, and each frame's detections are
+// noted for GET /api/vision/sweep. Notes are kept for the last max_frames
+// frames, at most max_per_frame detections each, the surest first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SweepConfig {
+    pub floor: f32,
+    pub duration_s: f32,
+    pub max_duration_s: f32,
+    pub max_frames: usize,
+    pub max_per_frame: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VisionDetector {
+    // Detections computed on the camera and sent by the bridge.
+    Imx500,
+    // Dark blobs in the bridge's JPEG, for when no model is available.
+    Blob,
+}
+
+impl VisionDetector {
+    // Its key in vision.confidence_calibration and vision.label_maps.
+    pub fn source_name(&self) -> &'static str {
+        match self {
+            VisionDetector::Imx500 => "imx500",
+            VisionDetector::Blob => "blob",
+        }
+    }
+}
+
+// One image point of a ground calibration and where it is on the floor:
+// metres to the right of the lens and ahead of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroundPoint {
+    pub px: f32,
+    pub py: f32,
+    pub x_m: f32,
+    pub y_m: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroundCalibration {
+    // One more with every calibration. Frame messages, and so recordings,
+    // carry the version they were placed with.
+    pub version: u32,
+    // Row-major 3x3, captured-frame pixels to floor metres.
+    pub homography: [f64; 9],
+    // The points it was fitted to, and how far from them it puts them.
+    pub points: Vec<GroundPoint>,
+    pub rms_error_m: f32,
+    pub calibrated_at_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameTransport {
+    // Inline in each frame line.
+    Base64,
+    // Through shared memory, with only a descriptor in the line.
+    Shm,
+}
+
+impl FrameTransport {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FrameTransport::Base64 => "base64",
+            FrameTransport::Shm => "shm",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PixelFormat {
+    #[default]
+    Jpeg,
+    // Planar I420: the Y plane, then U and V at half the width and height.
+    Yuv420,
+}
+
+impl PixelFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PixelFormat::Jpeg => "jpeg",
+            PixelFormat::Yuv420 => "yuv420",
+        }
+    }
+}
+
+pub const DETECTION_SOURCES: [&str; 3] = ["imx500", "blob", "imx500_yolov8"];
+
+// Names understood by opencv_embedded::dnn::backend_from_str and
+// target_from_str.
+const DNN_BACKENDS: [&str; 7] = ["default", "halide", "inference_engine", "openvino", "opencv", "vulkan", "cuda"];
+const DNN_TARGETS: [&str; 9] = ["cpu", "opencl", "opencl_fp16", "myriad", "vulkan", "fpga", "cuda", "cuda_fp16", "hddl"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MotorConfig {
+    pub max_speed: f32,
+    pub max_turn_deg: f32,
+    // How quickly the rover gets up to a commanded speed and slows from
+    // it, in m/s². An emergency stop brakes at emergency_decel_m_s2.
+    pub accel_m_s2: f32,
+    pub decel_m_s2: f32,
+    pub emergency_decel_m_s2: f32,
+    // Heading hold, with an IMU: from the tick Forward is commanded, the
+    // fused heading then is held by speeding one wheel up and slowing the
+    // other by heading_kp m/s per degree off it, less heading_kd m/s per
+    // degree/s of turning, at most heading_max_correction m/s. Turns and
+    // reversing let go of it.
+    pub heading_hold: bool,
+    pub heading_kp: f32,
+    pub heading_kd: f32,
+    pub heading_max_correction: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlannerConfig {
+    pub cruise_speed: f32,
+    // Changing the map size or resolution clears the map.
+    pub map_size_cells: usize,
+    pub map_resolution_m: f32,
+    pub camera_hfov_deg: f32,
+    // Remembered obstacles start out dynamic. One seen in at least
+    // static_min_observations frames and moving no faster than
+    // static_max_speed_m_s is static: it stays on the map until the camera
+    // has been looking at where it was for obstacle_memory_s without
+    // seeing it. Dynamic ones are forgotten dynamic_memory_s after they
+    // were last seen, and their motion is projected along the rollout.
+    pub obstacle_memory_s: f32,
+    pub static_min_observations: u32,
+    pub static_max_speed_m_s: f32,
+    pub dynamic_memory_s: f32,
+    // Docking servos toward this tag until it is dock_contact_m away.
+    pub dock_tag_id: Option<u32>,
+    pub dock_contact_m: f32,
+    pub dock_speed: f32,
+    // Markers at known map positions: AprilTags by tag_id, or a detector
+    // class that only ever appears once (a uniquely coloured marker) by
+    // class. The position is tracked with a Kalman filter: its uncertainty
+    // grows by odometry_variance_per_m m² for every metre driven, and a
+    // sighting is a fix landmark_sigma_m uncertain per metre of range. A
+    // fix more than landmark_gate_sigma standard deviations off is
+    // rejected rather than moving the rover. Detections below
+    // landmark_min_confidence and sightings beyond landmark_max_range_m
+    // are ignored. Heading still comes from the motors.
+    pub landmarks: Vec<Landmark>,
+    pub odometry_variance_per_m: f32,
+    pub landmark_sigma_m: f32,
+    pub landmark_gate_sigma: f32,
+    pub landmark_min_confidence: f32,
+    pub landmark_max_range_m: f32,
+    // Free-space profiles below free_space_min_confidence are ignored.
+    // Otherwise, with less than clearance_stop_m drivable within
+    // clearance_corridor_deg of straight ahead, the rover turns toward
+    // the clearest bearing, or stops if there is none.
+    pub free_space_min_confidence: f32,
+    pub clearance_stop_m: f32,
+    pub clearance_corridor_deg: f32,
+    // Named areas of the map. Entering or leaving one, by the rover or a
+    // tracked detection, is reported as an event. The rover drives at most
+    // zone_slow_speed inside a `slow` zone, and won't drive on when the
+    // point zone_lookahead_m ahead of it is in a `forbid_entry` zone.
+    // Inside a `no_rotate` zone it backs out at passage_reverse_speed
+    // instead of turning. It won't travel more than one_way_tolerance_deg
+    // off a `one_way` zone's direction, inside it or into it. What the
+    // planner did instead is reported as an event.
+    pub zones: Vec<Zone>,
+    pub zone_slow_speed: f32,
+    pub zone_lookahead_m: f32,
+    pub passage_reverse_speed: f32,
+    pub one_way_tolerance_deg: f32,
+    // GPS fixes map into the planner's frame through a local tangent plane
+    // at gps_origin (lat, lon), the map origin; without one, the first good
+    // fix is pinned to wherever the rover is then. gps_map_heading_deg is
+    // the compass heading the rover started out facing. A fix with an HDOP
+    // of at most gps_max_hdop pulls the position gps_fix_weight of the
+    // way to it. With no such fix for gps_fix_timeout_s the planner goes
+    // on odometry alone.
+    pub gps_origin: Option<(f64, f64)>,
+    pub gps_map_heading_deg: f32,
+    pub gps_max_hdop: f32,
+    pub gps_fix_weight: f32,
+    pub gps_fix_timeout_s: f32,
+    // Follow-me keeps the designated track follow_distance_m away, which
+    // must be beyond vision.person_stop_m, driving at up to follow_speed
+    // and turning to keep it within follow_align_deg of straight ahead. A
+    // lost track is picked up again from a detection of the same class
+    // within follow_reacquire_m of where it was last seen; after
+    // follow_timeout_s without it the rover gives up and stops.
+    pub follow_distance_m: f32,
+    pub follow_speed: f32,
+    pub follow_align_deg: f32,
+    pub follow_reacquire_m: f32,
+    pub follow_timeout_s: f32,
+    // A drop-off is less than dropoff_min_floor of the floor expected in
+    // front of the rover (0 leaves it to the range sensors), or a downward
+    // range sensor losing the floor. The rover stops and backs away
+    // dropoff_backoff_m at dropoff_backoff_speed before it will drive
+    // forward again, and from then on won't drive within dropoff_arc_deg
+    // of the way it was facing while within dropoff_clear_m of the spot.
+    pub dropoff_min_floor: f32,
+    pub dropoff_backoff_m: f32,
+    pub dropoff_backoff_speed: f32,
+    pub dropoff_arc_deg: f32,
+    pub dropoff_clear_m: f32,
+    // The dashboard is shown where the current motion takes the rover over
+    // the next rollout_horizon_s, a point every rollout_step_s. The first
+    // remembered obstacle within rollout_clearance_m of that path is
+    // flagged.
+    pub rollout_horizon_s: f32,
+    pub rollout_step_s: f32,
+    pub rollout_clearance_m: f32,
+    // Wheel slip: over each pair of frames the distance the wheels turned
+    // for is compared with how far the floor moved in the picture, once
+    // the wheels turned for at least slip_min_travel_m and the motion
+    // estimate is at least slip_min_confidence sure. Short of the camera's
+    // distance by more than slip_disagreement of it, the shortfall is slip;
+    // averaged over slip_window intervals it scales down odometry. Slip of
+    // slip_warn_ratio or more for slip_sustain_s is reported, and forward
+    // speed drops to slip_speed_factor of what it would be until it clears.
+    // Needs the gray frame cache.
+    pub slip_enabled: bool,
+    pub slip_min_travel_m: f32,
+    pub slip_min_confidence: f32,
+    pub slip_disagreement: f32,
+    pub slip_window: usize,
+    pub slip_warn_ratio: f32,
+    pub slip_sustain_s: f32,
+    pub slip_speed_factor: f32,
+    // Slowing down for what the map already knows, seen or not: looking
+    // envelope_lookahead_m straight ahead, forward speed is held to
+    // envelope_clearance_curve at the distance from that path to the
+    // nearest cell known to be occupied, and to envelope_unknown_curve at
+    // the fraction of cells along it the rover has never been over. Both
+    // are (x, m/s) points, linear in between and flat beyond the ends; the
+    // lower limit wins and an empty curve sets none.
+    pub envelope_lookahead_m: f32,
+    pub envelope_clearance_curve: Vec<(f32, f32)>,
+    pub envelope_unknown_curve: Vec<(f32, f32)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Landmark {
+    // Exactly one of tag_id and class.
+    #[serde(default)]
+    pub tag_id: Option<u32>,
+    #[serde(default)]
+    pub class: Option<String>,
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Landmark {
+    pub fn label(&self) -> String {
+        match (&self.class, self.tag_id) {
+            (Some(class), _) => class.clone(),
+            (None, Some(id)) => format!("tag {}", id),
+            (None, None) => "landmark".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Zone {
+    pub name: String,
+    // Corners in map coordinates, in order; the last joins the first.
+    pub polygon: Vec<(f32, f32)>,
+    #[serde(default)]
+    pub policy: ZonePolicy,
+    // One-way zones only: the way through, clockwise from +y like the
+    // rover's heading.
+    #[serde(default)]
+    pub direction_deg: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZonePolicy {
+    // Only reported.
+    #[default]
+    None,
+    Slow,
+    // The rover stops on entering and stays stopped while inside; an
+    // operator has to drive it out.
+    Stop,
+    ForbidEntry,
+    // Wide enough to drive through but not to turn around in: the rover
+    // doesn't turn in place inside one, and backs out instead.
+    NoRotate,
+    // Only driven through along direction_deg.
+    OneWay,
+}
+
+// Web settings are read when the server starts; changing them at runtime
+// only takes effect after a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebConfig {
+    pub bind_address: String,
+    pub port: u16,
+    pub base_path: String,
+    pub cors_allowed_origins: Vec<String>,
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    // Serve the dashboard from this directory before falling back to the
+    // copy embedded in the binary. Meant for UI development.
+    pub static_dir: Option<String>,
+    pub ping_interval_s: f32,
+    pub max_missed_pongs: u32,
+    // Frames go to each client as captured until its link falls behind:
+    // more than quality_backlog messages queued for it, or pongs taking
+    // over quality_rtt_ms. Then, at most every quality_hold_s, it drops a
+    // step down quality_steps, and after quality_recover_s of keeping up
+    // it climbs one back. A client can also ask for a fixed quality.
+    pub adaptive_quality: bool,
+    pub quality_steps: Vec<QualityStep>,
+    pub quality_backlog: u32,
+    pub quality_rtt_ms: u64,
+    pub quality_hold_s: f32,
+    pub quality_recover_s: f32,
+    // Clients that ask for detection deltas get every detection in full
+    // this often, and otherwise only what changed.
+    pub detection_full_sync_s: f32,
+}
+
+// A frame re-encoded at this JPEG quality, scaled to this fraction of
+// its width and height.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QualityStep {
+    pub jpeg_quality: u8,
+    pub scale: f32,
+}
+
+// Session recording is set up when the server starts, like WebConfig.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    pub enabled: bool,
+    pub directory: String,
+    pub include_images: bool,
+    pub image_interval_s: f32,
+    pub quota_mb: u64,
+}
+
+// Read when the control loop starts, like WebConfig.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlConfig {
+    // Go straight from Boot to Autonomous. When false the rover waits in
+    // Idle until an operator starts it.
+    pub start_autonomous: bool,
+    // Run the whole pipeline without driving: motor commands are reported
+    // but not sent. Can be switched from the dashboard while idle.
+    pub shadow_mode: bool,
+    pub loop_mode: LoopMode,
+    // Event-driven mode ticks on each new frame, but never more often than
+    // the min interval and never less often than the max, so motors and
+    // the watchdog still get attention while frames stall.
+    pub min_tick_interval_ms: u64,
+    pub max_tick_interval_ms: u64,
+    pub fixed_tick_interval_ms: u64,
+    // The planner steps on a thread of its own this often, from the
+    // latest world state, and the loop above drives on its last plan. A
+    // plan older than plan_stale_ms stops the rover until a new one comes.
+    pub plan_interval_ms: u64,
+    pub plan_stale_ms: u64,
+    pub watchdog_deadline_ms: u64,
+    pub watchdog_action: WatchdogAction,
+    // e.g. "/dev/watchdog"; only used when built with `hw-watchdog`.
+    pub hardware_watchdog: Option<String>,
+    // Capture to motor command; frames slower than this are counted and
+    // warned about. Read at startup.
+    pub latency_budget_ms: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoopMode {
+    EventDriven,
+    // The old behaviour: one tick every fixed_tick_interval_ms.
+    Fixed,
+}
+
+// Read at startup; only used when built with `mqtt`. The password is
+// never stored here, only the name of the environment variable holding
+// it, so it stays out of /api/config and the saved file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub enabled: bool,
+    pub broker_host: String,
+    pub broker_port: u16,
+    pub username: Option<String>,
+    pub password_env: Option<String>,
+    // Everything is published under <topic_prefix>/ and commands are read
+    // from <topic_prefix>/cmd/<name>.
+    pub topic_prefix: String,
+    pub detection_debounce_s: f32,
+    pub status_interval_s: f32,
+    // Messages held while the broker is unreachable; the oldest go first.
+    pub queue_size: usize,
+}
+
+// Read at startup. The receiver needs the `gps` feature; a replay file of
+// NMEA sentences works without it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpsConfig {
+    pub enabled: bool,
+    pub device: String,
+    pub baud_rate: u32,
+    // Plays the GGA sentences in this file one a second instead of
+    // reading the receiver.
+    pub replay_file: Option<PathBuf>,
+}
+
+// Read at startup; only used when built with `ros`. The bridge is a
+// client of a rosbridge_server (rosbridge_suite's websocket JSON
+// protocol), so the rover needs no ROS install of its own. Poses and the
+// map are converted to ROS axes: x forward, y left, yaw anticlockwise.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RosConfig {
+    pub enabled: bool,
+    pub url: String,
+    pub detections_topic: String,
+    pub odom_topic: String,
+    pub map_topic: String,
+    // geometry_msgs/Twist, driving the rover while it is in manual mode.
+    pub cmd_vel_topic: String,
+    pub frame_id: String,
+    pub child_frame_id: String,
+    // Publish rates; detections only go out for new frames.
+    pub odom_hz: f32,
+    pub detections_hz: f32,
+    // The map goes out at most this often, and only when it changed.
+    pub map_interval_s: f32,
+}
+
+// Audible warnings for people near the rover, read at startup. The buzzer
+// backend needs the `buzzer` feature; aplay plays tones through the
+// default ALSA device (or aplay_device) and log only writes them to the
+// log, for running without either.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnnunciatorConfig {
+    pub enabled: bool,
+    pub backend: AnnunciatorBackend,
+    // BCM numbering; driven with software PWM, so any pin works.
+    pub buzzer_pin: u8,
+    pub aplay_device: Option<String>,
+    // Replaces the built-in pattern of an alert (see
+    // annunciator::ALERT_NAMES) with [frequency_hz, duration_ms] tones, 0 Hz
+    // being a rest. An empty list silences that alert.
+    pub patterns: BTreeMap<String, Vec<(u32, u32)>>,
+}
+
+// The on-rover flight recorder, read at startup; only used when built with
+// `blackbox`. Unlike sessions it survives a crash: records are written to
+// SQLite in batches every flush_interval_ms, and once the database holds
+// more than max_mb the oldest are pruned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlackboxConfig {
+    pub enabled: bool,
+    pub path: String,
+    pub max_mb: u64,
+    pub flush_interval_ms: u64,
+    // Telemetry is sampled this often; decisions, motor commands and
+    // events are recorded whenever they change.
+    pub telemetry_interval_s: f32,
+}
+
+// The self-test that has to pass before the rover can arm, read at
+// startup. Every check gives up after check_timeout_s, except the wait for
+// bridge frames, which gets frame_timeout_s since the camera is slow to
+// come up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SelfTestConfig {
+    pub enabled: bool,
+    pub check_timeout_s: f32,
+    pub frame_timeout_s: f32,
+    // Frames the bridge must deliver for its check to pass.
+    pub min_frames: u64,
+    // Free space needed where sessions and the black box are recorded, if
+    // they are enabled. Under four times this is a warning.
+    pub min_free_mb: u64,
+}
+
+// CPU temperature and throttling, read at startup. sysfs reads temp_path
+// every poll_interval_ms, plus the firmware's throttle flags through
+// vcgencmd when built with the `vcgencmd` feature; mock always reports
+// mock_temp_c and mock_throttle_flags. At degrade_temp_c, or while the
+// firmware throttles, the rover runs lighter until it is back under
+// recover_temp_c and unthrottled: frames go to dashboards every
+// degraded_frame_interval_ms without the trajectory overlay, and the
+// motors are capped at degraded_max_speed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThermalConfig {
+    pub enabled: bool,
+    pub source: ThermalSourceKind,
+    pub poll_interval_ms: u64,
+    pub temp_path: String,
+    pub degrade_temp_c: f32,
+    pub recover_temp_c: f32,
+    pub degraded_frame_interval_ms: u64,
+    pub degraded_max_speed: f32,
+    pub mock_temp_c: f32,
+    pub mock_throttle_flags: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThermalSourceKind {
+    Sysfs,
+    Mock,
+}
+
+// Replacement bridge scripts, model configs and label files uploaded over
+// the API, read at startup. Each upload is kept under
+// <directory>/<kind>/<version>/; at most keep_versions of each kind stay
+// besides the active and previous ones. Uploads and activations need the
+// bearer token in the environment variable named by token_env, and are
+// refused while it is unset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdatesConfig {
+    pub enabled: bool,
+    pub directory: String,
+    pub token_env: Option<String>,
+    pub max_upload_mb: u64,
+    pub keep_versions: usize,
+}
+
+// A GPIO output wired to a relay that cuts motor power, read at startup.
+// The watchdog holds it high only while the control loop is ticking, no
+// emergency stop is latched and vision is fresh. The gpio driver needs the
+// `interlock` feature; mock only keeps the level in memory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InterlockConfig {
+    pub enabled: bool,
+    pub driver: InterlockDriver,
+    // BCM numbering.
+    pub pin: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InterlockDriver {
+    Gpio,
+    Mock,
+}
+
+// The gyro the fused heading comes from, read at startup. Its bias is
+// re-estimated whenever the wheels are still. The mpu6050 driver needs the
+// `imu` feature; mock always reads mock_yaw_rate_dps.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImuConfig {
+    pub enabled: bool,
+    pub driver: ImuDriver,
+    pub i2c_bus: u8,
+    pub i2c_address: u8,
+    // For a sensor mounted upside down.
+    pub invert: bool,
+    // Clockwise, like headings.
+    pub mock_yaw_rate_dps: f32,
+}
+
+// What the rover picks up again after an unexpected restart, read at
+// startup. Every interval_s the parts in `include` are written to `path`
+// through a temporary file renamed over it, so a crash mid-write leaves
+// the last one whole. A clean shutdown deletes it. One older than
+// max_age_s, or one that can't be read, is moved aside instead of used.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckpointConfig {
+    pub enabled: bool,
+    pub path: String,
+    pub interval_s: f32,
+    pub max_age_s: f32,
+    pub include: Vec<CheckpointPart>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckpointPart {
+    // Whether the rover was out on a mission, and its goal or docking.
+    Mission,
+    Pose,
+    // The map tiles that have anything in them.
+    Map,
+    // Remembered obstacles and drop-offs.
+    Obstacles,
+}
+
+// The last `capacity` log events at `level` or above are kept in memory
+// for GET /api/logs and WebSocket clients tailing them. Only what the
+// console filter (--log-level or RUST_LOG) lets through gets that far. A
+// field whose name contains one of redact_fields (ignoring case) is kept
+// with its value blanked, as are bearer tokens and the secrets named by
+// updates.token_env, hazards.token_env and mqtt.password_env wherever they
+// turn up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogsConfig {
+    pub capacity: usize,
+    pub level: LogLevel,
+    pub redact_fields: Vec<String>,
+}
+
+// Gamepad axis commands over /ws, shaped here rather than by the client
+// so every client drives the same. Read for each command. An axis is
+// zero inside the deadzone, then follows (e^(expo·x) - 1) / (e^expo - 1)
+// out to full deflection: 0 is linear, higher gives finer control near
+// the centre. Linear is forward and angular anticlockwise unless
+// inverted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TeleopConfig {
+    pub max_linear_m_s: f32,
+    pub max_angular_rad_s: f32,
+    pub deadzone: f32,
+    pub expo: f32,
+    pub invert_linear: bool,
+    pub invert_angular: bool,
+    // More sequence numbers than this missing between two commands counts
+    // as a lossy link and stops the rover.
+    pub max_sequence_gap: u64,
+}
+
+// How GET /api/frame.jpg and /api/stream.mjpeg encode the annotated frame
+// when the request doesn't say: baseline JPEG, progressive JPEG or, in a
+// build with the webp feature, WebP, at quality (1-100). The stream sends
+// stream_fps frames a second. At most max_concurrent encodes run at once,
+// across every request and stream, so they can't take the control loop's
+// CPU; max_concurrent is read at startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    pub format: SnapshotFormat,
+    pub quality: u8,
+    pub stream_fps: f32,
+    pub max_concurrent: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotFormat {
+    Baseline,
+    // Stored coarse to fine, so a download cut short still shows the whole
+    // frame, blurred, rather than its top half.
+    Progressive,
+    // Lossless; quality below 100 rounds off the low bits of each channel
+    // first.
+    Webp,
+}
+
+impl SnapshotFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SnapshotFormat::Baseline => "baseline",
+            SnapshotFormat::Progressive => "progressive",
+            SnapshotFormat::Webp => "webp",
+        }
+    }
+}
+
+// Hazards reported by out-of-band detectors, a microphone's classifier
+// say, through POST /api/hazard or an in-process source; read at startup.
+// Each lasts its own ttl_s, at most max_ttl_s. A caution hazard holds the
+// rover to caution_speed_m_s driving forward; a stop hazard stops it if it
+// is ahead, within stop_arc_deg either side, or has no bearing, and acts as
+// a caution otherwise. Reports need the bearer token in the environment
+// variable named by token_env and are refused while it is unset. Each
+// source gets rate_per_s reports a second, up to burst at once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HazardsConfig {
+    pub enabled: bool,
+    pub token_env: Option<String>,
+    pub max_ttl_s: f32,
+    pub caution_speed_m_s: f32,
+    pub stop_arc_deg: f32,
+    pub rate_per_s: f32,
+    pub burst: u32,
+    pub max_active: usize,
+}
+
+// Ordered from most to least verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "trace" => Some(LogLevel::Trace),
+            "debug" => Some(LogLevel::Debug),
+            "info" => Some(LogLevel::Info),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "error" => Some(LogLevel::Error),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImuDriver {
+    Mpu6050,
+    Mock,
+}
+
+// Idle power saving, read at startup. After idle_after_s in Idle with no
+// WebSocket client taking frames, the rover sleeps: the bridge drops to
+// keepalive_fps, frames go to dashboards every asleep_frame_interval_ms
+// and the motor driver's enable output, if there is one, goes low. A
+// client connecting, an arming request (a scheduled mission's too) or
+// POST /api/power/wake wakes it; waking re-runs the self-test and should
+// take no longer than max_resume_s. motor_enable_driver takes the same
+// values as the interlock's, and its gpio driver also needs the
+// `interlock` feature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConfig {
+    pub idle_enabled: bool,
+    pub idle_after_s: f32,
+    pub keepalive_fps: f32,
+    pub asleep_frame_interval_ms: u64,
+    pub max_resume_s: f32,
+    // BCM numbering; high energizes the driver.
+    pub motor_enable_pin: Option<u8>,
+    pub motor_enable_driver: InterlockDriver,
+}
+
+// How overlays are drawn on every dashboard. Classes without an entry get
+// the colour from their label map, or one derived from their name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    pub classes: BTreeMap<String, ClassDisplay>,
+    pub label_detail: LabelDetail,
+    pub units: DistanceUnits,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClassDisplay {
+    // "#rrggbb".
+    pub color: Option<String>,
+    pub visible: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelDetail {
+    // Class name only.
+    Minimal,
+    // Track id, class and confidence.
+    #[default]
+    Standard,
+    // All of that and the distance.
+    Full,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceUnits {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnunciatorBackend {
+    Buzzer,
+    Aplay,
+    Log,
+}
+
+// Near-field range sensors, read at startup. The camera can't see the
+// last ~0.4 m in front of the rover, so these cover it: any working sensor
+// reading under stop_m stops the rover whatever vision says, and within
+// near_field_m the planner goes by them rather than the camera. A sensor
+// with nothing newer than timeout_ms is left out until it recovers.
+// Sensors looking down at the floor instead watch for drop-offs: one
+// reading more than dropoff_margin_m beyond its floor_m, or nothing at
+// all, has lost the floor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RangeConfig {
+    pub enabled: bool,
+    pub sample_hz: f32,
+    pub stop_m: f32,
+    pub near_field_m: f32,
+    pub timeout_ms: u64,
+    pub dropoff_margin_m: f32,
+    pub sensors: Vec<RangeSensorConfig>,
+}
+
+// hc_sr04 needs trigger_pin and echo_pin (BCM numbering); vl53l0x uses
+// i2c_bus and i2c_address. Both need the `range-sensors` feature. mock
+// always reads mock_distance_m, or nothing in range if that is unset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RangeSensorConfig {
+    pub name: String,
+    pub kind: RangeSensorKind,
+    // Clockwise from straight ahead, and the width of the sensor's cone.
+    #[serde(default)]
+    pub bearing_deg: f32,
+    #[serde(default = "default_range_fov")]
+    pub fov_deg: f32,
+    #[serde(default)]
+    pub trigger_pin: Option<u8>,
+    #[serde(default)]
+    pub echo_pin: Option<u8>,
+    #[serde(default = "default_i2c_bus")]
+    pub i2c_bus: u8,
+    #[serde(default = "default_vl53l0x_address")]
+    pub i2c_address: u8,
+    #[serde(default)]
+    pub mock_distance_m: Option<f32>,
+    // Set for a sensor pointed at the floor: how far away the floor is.
+    // Such a sensor never stops the rover as an obstacle.
+    #[serde(default)]
+    pub floor_m: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RangeSensorKind {
+    HcSr04,
+    Vl53l0x,
+    Mock,
+}
+
+fn default_range_fov() -> f32 {
+    25.0
+}
+
+fn default_i2c_bus() -> u8 {
+    1
+}
+
+fn default_vl53l0x_address() -> u8 {
+    0x29
+}
+
+// Scheduled actions are re-read every second, so edits apply immediately.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleConfig {
+    pub snapshot_directory: String,
+    pub entries: Vec<ScheduleEntry>,
+}
+
+// Exactly one of `at`, `every_s` and `when` says when the entry fires.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleEntry {
+    pub name: String,
+    pub action: ScheduledAction,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // Local time of day, "HH:MM".
+    #[serde(default)]
+    pub at: Option<String>,
+    // Restricts `at` to these days ("mon".."sun"); empty means every day.
+    #[serde(default)]
+    pub days: Vec<String>,
+    #[serde(default)]
+    pub every_s: Option<u64>,
+    // Fires when the condition becomes true, then re-arms once it is false.
+    #[serde(default)]
+    pub when: Option<MetricCondition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricCondition {
+    pub metric: String,
+    #[serde(default)]
+    pub below: Option<f64>,
+    #[serde(default)]
+    pub above: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduledAction {
+    StartMission,
+    ReturnHome,
+    // Servo onto planner.dock_tag_id.
+    Dock,
+    Stop,
+    Snapshot,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+// What the watchdog does to the motors on a trip. Either way the rover
+// faults and waits for an operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchdogAction {
+    EmergencyStop,
+    Stop,
+}
+
+impl Default for IdentityConfig {
+    fn default() -> Self {
+        Self {
+            rover_id: "scout".to_string(),
+            rover_name: "Scout".to_string(),
+        }
+    }
+}
+
+impl Default for VisionConfig {
+    fn default() -> Self {
+        let object_heights_m = [
+            ("person", 1.7), ("car", 1.5), ("truck", 3.0), ("bus", 3.0),
+            ("bicycle", 1.0), ("chair", 0.8), ("bottle", 0.25),
+            ("dog", 0.5), ("cat", 0.25),
+        ].iter().map(|(name, h)| (name.to_string(), *h)).collect();
+
+        Self {
+            min_confidence: 0.0,
+            focal_length_px: 500.0,
+            default_object_height_m: 0.5,
+            object_heights_m,
+            person_emergency_stop_m: 1.0,
+            person_stop_m: 2.0,
+            person_slow_down_m: 3.0,
+            obstacle_stop_m: 1.5,
+            obstacle_slow_down_m: 3.0,
+            dnn_backend: "opencv".to_string(),
+            dnn_target: "cpu".to_string(),
+            detector: VisionDetector::Imx500,
+            confidence_calibration: BTreeMap::new(),
+            label_maps: BTreeMap::new(),
+            rules_path: None,
+            ignored_classes: Vec::new(),
+            detection_roi: None,
+            track_iou: 0.3,
+            distance_smoothing: 0.0,
+            self_mask: Vec::new(),
+            self_mask_min_overlap: 0.6,
+            blob_dark_threshold: 60.0,
+            blob_min_area_fraction: 0.02,
+            blob_bottom_fraction: 0.5,
+            blob_scale_width: 160,
+            fiducials_enabled: false,
+            fiducial_tag_size_m: 0.1,
+            free_space_enabled: false,
+            free_space_scale_width: 160,
+            free_space_color_tolerance: 40.0,
+            free_space_texture_tolerance: 20.0,
+            free_space_max_range_m: 5.0,
+            dropoff_lookahead_m: 0.5,
+            camera_height_m: 0.12,
+            horizon_row_px: 240.0,
+            ground_calibration: None,
+            bridge_command: vec!["python3".to_string(), crate::vision::BRIDGE_SCRIPT.to_string()],
+            bridge_parse_window_s: 10.0,
+            bridge_max_parse_failure_rate: 0.5,
+            bridge_tap_dir: "bridge-diagnostics".to_string(),
+            bridge_tap_minutes: 10.0,
+            bridge_tap_file_mb: 8,
+            bridge_tap_files: 4,
+            frame_transport: FrameTransport::Base64,
+            frame_slots: 4,
+            frame_slot_kb: 256,
+            pixel_format: PixelFormat::Jpeg,
+            max_frame_age_ms: 500,
+            gray_cache_enabled: true,
+            gray_cache_frames: 4,
+            gray_cache_width: 160,
+            gray_cache_height: 120,
+            ego_motion_max_shift_px: 8,
+            mask_expected_fill: 0.6,
+            mask_max_disagreement: 2.0,
+            mask_budget_ms: 50.0,
+            profiles: BTreeMap::new(),
+            profile: None,
+            auto_profile: AutoProfileConfig::default(),
+            auto_exposure: AutoExposureConfig::default(),
+            heatmap: HeatmapConfig::default(),
+            sweep: SweepConfig::default(),
+            ir_cut_pin: None,
+        }
+    }
+}
+
+impl Default for SweepConfig {
+    fn default() -> Self {
+        Self {
+            floor: 0.1,
+            duration_s: 600.0,
+            max_duration_s: 3600.0,
+            max_frames: 18000,
+            max_per_frame: 32,
+        }
+    }
+}
+
+impl Default for AutoProfileConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dark_profile: "night-ir".to_string(),
+            bright_profile: "day".to_string(),
+            dark_below: 40.0,
+            bright_above: 70.0,
+            hold_s: 10.0,
+            sample_interval_ms: 1000,
+        }
+    }
+}
+
+impl Default for AutoExposureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            clip_level: 250,
+            clip_fraction: 0.15,
+            clear_fraction: 0.03,
+            step_ev: 0.5,
+            min_ev: -2.0,
+            sample_interval_ms: 500,
+            min_step_interval_ms: 3000,
+            roi: None,
+        }
+    }
+}
+
+impl Default for HeatmapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            columns: 32,
+            rows: 24,
+            half_life_s: 900.0,
+            boxes: false,
+        }
+    }
+}
+
+impl Default for MotorConfig {
+    fn default() -> Self {
+        Self {
+            max_speed: 1.0,
+            max_turn_deg: 90.0,
+            accel_m_s2: 1.0,
+            decel_m_s2: 1.5,
+            emergency_decel_m_s2: 3.0,
+            heading_hold: true,
+            heading_kp: 0.01,
+            heading_kd: 0.002,
+            heading_max_correction: 0.15,
+        }
+    }
+}
+
+impl Default for PlannerConfig {
+    fn default() -> Self {
+        Self {
+            cruise_speed: 0.5,
+            map_size_cells: 200,
+            map_resolution_m: 0.1,
+            camera_hfov_deg: 62.2,
+            obstacle_memory_s: 5.0,
+            static_min_observations: 15,
+            static_max_speed_m_s: 0.1,
+            dynamic_memory_s: 3.0,
+            dock_tag_id: None,
+            dock_contact_m: 0.2,
+            dock_speed: 0.2,
+            landmarks: Vec::new(),
+            odometry_variance_per_m: 0.01,
+            landmark_sigma_m: 0.1,
+            landmark_gate_sigma: 3.0,
+            landmark_min_confidence: 0.6,
+            landmark_max_range_m: 5.0,
+            free_space_min_confidence: 0.6,
+            clearance_stop_m: 0.5,
+            clearance_corridor_deg: 10.0,
+            zones: Vec::new(),
+            zone_slow_speed: 0.2,
+            zone_lookahead_m: 0.5,
+            passage_reverse_speed: 0.15,
+            one_way_tolerance_deg: 90.0,
+            gps_origin: None,
+            gps_map_heading_deg: 0.0,
+            gps_max_hdop: 2.0,
+            gps_fix_weight: 0.3,
+            gps_fix_timeout_s: 3.0,
+            follow_distance_m: 2.5,
+            follow_speed: 0.6,
+            follow_align_deg: 10.0,
+            follow_reacquire_m: 0.75,
+            follow_timeout_s: 3.0,
+            dropoff_min_floor: 0.5,
+            dropoff_backoff_m: 0.3,
+            dropoff_backoff_speed: 0.15,
+            dropoff_arc_deg: 90.0,
+            dropoff_clear_m: 1.0,
+            rollout_horizon_s: 2.0,
+            rollout_step_s: 0.1,
+            rollout_clearance_m: 0.15,
+            slip_enabled: true,
+            slip_min_travel_m: 0.02,
+            slip_min_confidence: 0.3,
+            slip_disagreement: 0.15,
+            slip_window: 5,
+            slip_warn_ratio: 0.3,
+            slip_sustain_s: 2.0,
+            slip_speed_factor: 0.5,
+            envelope_lookahead_m: 1.5,
+            envelope_clearance_curve: vec![(0.25, 0.1), (0.75, 0.25), (1.5, 1.0)],
+            envelope_unknown_curve: Vec::new(),
+        }
+    }
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "0.0.0.0".to_string(),
+            port: 8080,
+            base_path: String::new(),
+            cors_allowed_origins: Vec::new(),
+            tls_cert_path: None,
+            tls_key_path: None,
+            static_dir: None,
+            ping_interval_s: 5.0,
+            max_missed_pongs: 3,
+            adaptive_quality: true,
+            quality_steps: vec![
+                QualityStep { jpeg_quality: 60, scale: 1.0 },
+                QualityStep { jpeg_quality: 50, scale: 0.75 },
+                QualityStep { jpeg_quality: 40, scale: 0.5 },
+                QualityStep { jpeg_quality: 30, scale: 0.25 },
+            ],
+            quality_backlog: 5,
+            quality_rtt_ms: 500,
+            quality_hold_s: 2.0,
+            quality_recover_s: 10.0,
+            detection_full_sync_s: 5.0,
+        }
+    }
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        Self {
+            start_autonomous: true,
+            shadow_mode: false,
+            loop_mode: LoopMode::EventDriven,
+            min_tick_interval_ms: 10,
+            max_tick_interval_ms: 100,
+            fixed_tick_interval_ms: 33,
+            plan_interval_ms: 100,
+            plan_stale_ms: 500,
+            watchdog_deadline_ms: 500,
+            watchdog_action: WatchdogAction::EmergencyStop,
+            hardware_watchdog: None,
+            latency_budget_ms: 150.0,
+        }
+    }
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            snapshot_directory: "snapshots".to_string(),
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            broker_host: "localhost".to_string(),
+            broker_port: 1883,
+            username: None,
+            password_env: None,
+            topic_prefix: "scout".to_string(),
+            detection_debounce_s: 5.0,
+            status_interval_s: 5.0,
+            queue_size: 256,
+        }
+    }
+}
+
+impl Default for AnnunciatorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            backend: AnnunciatorBackend::Buzzer,
+            buzzer_pin: 18,
+            aplay_device: None,
+            patterns: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for SelfTestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            check_timeout_s: 5.0,
+            frame_timeout_s: 15.0,
+            min_frames: 3,
+            min_free_mb: 100,
+        }
+    }
+}
+
+impl Default for BlackboxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "blackbox/scout.db".to_string(),
+            max_mb: 200,
+            flush_interval_ms: 1000,
+            telemetry_interval_s: 1.0,
+        }
+    }
+}
+
+impl Default for ThermalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            source: ThermalSourceKind::Sysfs,
+            poll_interval_ms: 2000,
+            temp_path: "/sys/class/thermal/thermal_zone0/temp".to_string(),
+            degrade_temp_c: 75.0,
+            recover_temp_c: 70.0,
+            degraded_frame_interval_ms: 200,
+            degraded_max_speed: 0.3,
+            mock_temp_c: 45.0,
+            mock_throttle_flags: 0,
+        }
+    }
+}
+
+impl Default for UpdatesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: "updates".to_string(),
+            token_env: Some("SCOUT_UPDATE_TOKEN".to_string()),
+            max_upload_mb: 16,
+            keep_versions: 5,
+        }
+    }
+}
+
+impl Default for InterlockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            driver: InterlockDriver::Gpio,
+            pin: 26,
+        }
+    }
+}
+
+impl Default for ImuConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            driver: ImuDriver::Mpu6050,
+            i2c_bus: 1,
+            i2c_address: 0x68,
+            invert: false,
+            mock_yaw_rate_dps: 0.0,
+        }
+    }
+}
+
+impl Default for CheckpointConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "checkpoint/rover.json".to_string(),
+            interval_s: 5.0,
+            max_age_s: 600.0,
+            include: vec![CheckpointPart::Mission, CheckpointPart::Pose, CheckpointPart::Map, CheckpointPart::Obstacles],
+        }
+    }
+}
+
+impl Default for LogsConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 2000,
+            level: LogLevel::Info,
+            redact_fields: ["token", "authorization", "password", "secret", "api_key", "cookie"].map(String::from).to_vec(),
+        }
+    }
+}
+
+impl Default for TeleopConfig {
+    fn default() -> Self {
+        Self {
+            max_linear_m_s: 0.5,
+            max_angular_rad_s: 1.5,
+            deadzone: 0.08,
+            expo: 2.0,
+            invert_linear: false,
+            invert_angular: false,
+            max_sequence_gap: 2,
+        }
+    }
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            format: SnapshotFormat::Baseline,
+            quality: 85,
+            stream_fps: 5.0,
+            max_concurrent: 2,
+        }
+    }
+}
+
+impl Default for HazardsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            token_env: Some("SCOUT_HAZARD_TOKEN".to_string()),
+            max_ttl_s: 30.0,
+            caution_speed_m_s: 0.15,
+            stop_arc_deg: 60.0,
+            rate_per_s: 2.0,
+            burst: 5,
+            max_active: 32,
+        }
+    }
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            idle_enabled: false,
+            idle_after_s: 600.0,
+            keepalive_fps: 1.0,
+            asleep_frame_interval_ms: 1000,
+            max_resume_s: 5.0,
+            motor_enable_pin: None,
+            motor_enable_driver: InterlockDriver::Gpio,
+        }
+    }
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            classes: BTreeMap::new(),
+            label_detail: LabelDetail::Standard,
+            units: DistanceUnits::Metric,
+        }
+    }
+}
+
+impl Default for ClassDisplay {
+    fn default() -> Self {
+        Self {
+            color: None,
+            visible: true,
+        }
+    }
+}
+
+impl Default for RosConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "ws://localhost:9090".to_string(),
+            detections_topic: "/scout/detections".to_string(),
+            odom_topic: "/scout/odom".to_string(),
+            map_topic: "/scout/map".to_string(),
+            cmd_vel_topic: "/scout/cmd_vel".to_string(),
+            frame_id: "odom".to_string(),
+            child_frame_id: "base_link".to_string(),
+            odom_hz: 10.0,
+            detections_hz: 10.0,
+            map_interval_s: 2.0,
+        }
+    }
+}
+
+impl Default for GpsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: "/dev/serial0".to_string(),
+            baud_rate: 9600,
+            replay_file: None,
+        }
+    }
+}
+
+impl Default for RangeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sample_hz: 20.0,
+            stop_m: 0.15,
+            near_field_m: 0.5,
+            timeout_ms: 250,
+            dropoff_margin_m: 0.05,
+            sensors: Vec::new(),
+        }
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: "sessions".to_string(),
+            include_images: false,
+            image_interval_s: 1.0,
+            quota_mb: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl RoverConfig {
+    pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        let id = &self.identity.rover_id;
+        if id.is_empty() || id.len() > 63 || !id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+            errors.push(FieldError::new("identity.rover_id", "must be 1-63 lowercase letters, digits or '-'"));
+        }
+        if self.identity.rover_name.is_empty() {
+            errors.push(FieldError::new("identity.rover_name", "must not be empty"));
+        }
+
+        let v = &self.vision;
+
+        check_range(&mut errors, "vision.min_confidence", v.min_confidence, 0.0, 1.0);
+        check_positive(&mut errors, "vision.focal_length_px", v.focal_length_px);
+        check_positive(&mut errors, "vision.default_object_height_m", v.default_object_height_m);
+        for (class, height) in &v.object_heights_m {
+            check_positive(&mut errors, &format!("vision.object_heights_m.{}", class), *height);
+        }
+        check_positive(&mut errors, "vision.person_emergency_stop_m", v.person_emergency_stop_m);
+        check_positive(&mut errors, "vision.person_stop_m", v.person_stop_m);
+        check_positive(&mut errors, "vision.person_slow_down_m", v.person_slow_down_m);
+        check_positive(&mut errors, "vision.obstacle_stop_m", v.obstacle_stop_m);
+        check_positive(&mut errors, "vision.obstacle_slow_down_m", v.obstacle_slow_down_m);
+
+        if v.person_stop_m < v.person_emergency_stop_m {
+            errors.push(FieldError::new("vision.person_stop_m", "must be >= vision.person_emergency_stop_m"));
+        }
+        if v.person_slow_down_m < v.person_stop_m {
+            errors.push(FieldError::new("vision.person_slow_down_m", "must be >= vision.person_stop_m"));
+        }
+        if v.obstacle_slow_down_m < v.obstacle_stop_m {
+            errors.push(FieldError::new("vision.obstacle_slow_down_m", "must be >= vision.obstacle_stop_m"));
+        }
+        if !DNN_BACKENDS.contains(&v.dnn_backend.as_str()) {
+            errors.push(FieldError::new("vision.dnn_backend", format!("must be one of {}", DNN_BACKENDS.join(", "))));
+        }
+        if !DNN_TARGETS.contains(&v.dnn_target.as_str()) {
+            errors.push(FieldError::new("vision.dnn_target", format!("must be one of {}", DNN_TARGETS.join(", "))));
+        }
+        check_calibration(&mut errors, "vision.confidence_calibration", &v.confidence_calibration);
+        for (name, profile) in &v.profiles {
+            let prefix = format!("vision.profiles.{}", name);
+            if !is_profile_name(name) {
+                errors.push(FieldError::new(&prefix, "name must be 1-32 lowercase letters, digits, '-' or '_'"));
+            }
+            if let Some(min_confidence) = profile.min_confidence {
+                check_range(&mut errors, &format!("{}.min_confidence", prefix), min_confidence, 0.0, 1.0);
+            }
+            if let Some(focal_length_px) = profile.focal_length_px {
+                check_positive(&mut errors, &format!("{}.focal_length_px", prefix), focal_length_px);
+            }
+            check_calibration(&mut errors, &format!("{}.confidence_calibration", prefix), &profile.confidence_calibration);
+            let camera = &profile.camera;
+            if camera.shutter_us == Some(0) {
+                errors.push(FieldError::new(format!("{}.camera.shutter_us", prefix), "must be non-zero"));
+            }
+            if let Some(gain) = camera.gain {
+                check_range(&mut errors, &format!("{}.camera.gain", prefix), gain, 1.0, 64.0);
+            }
+            if let Some(ev) = camera.ev {
+                check_range(&mut errors, &format!("{}.camera.ev", prefix), ev, -10.0, 10.0);
+            }
+            if let Some(saturation) = camera.saturation {
+                check_range(&mut errors, &format!("{}.camera.saturation", prefix), saturation, 0.0, 4.0);
+            }
+            if camera.ir_cut.is_some() && v.ir_cut_pin.is_none() {
+                errors.push(FieldError::new(format!("{}.camera.ir_cut", prefix), "needs vision.ir_cut_pin"));
+            }
+        }
+        if let Some(profile) = &v.profile {
+            if !v.profiles.contains_key(profile) {
+                errors.push(FieldError::new("vision.profile", format!("no profile named {}", profile)));
+            }
+        }
+        let auto = &v.auto_profile;
+        if auto.enabled {
+            for (field, profile) in [("dark_profile", &auto.dark_profile), ("bright_profile", &auto.bright_profile)] {
+                if !v.profiles.contains_key(profile) {
+                    errors.push(FieldError::new(format!("vision.auto_profile.{}", field), format!("no profile named {}", profile)));
+                }
+            }
+        }
+        check_range(&mut errors, "vision.auto_profile.dark_below", auto.dark_below, 0.0, 255.0);
+        check_range(&mut errors, "vision.auto_profile.bright_above", auto.bright_above, 0.0, 255.0);
+        if auto.bright_above <= auto.dark_below {
+            errors.push(FieldError::new("vision.auto_profile.bright_above", "must be above dark_below"));
+        }
+        check_range(&mut errors, "vision.auto_profile.hold_s", auto.hold_s, 0.0, 600.0);
+        if auto.sample_interval_ms < 100 {
+            errors.push(FieldError::new("vision.auto_profile.sample_interval_ms", "must be at least 100"));
+        }
+        let exposure = &v.auto_exposure;
+        check_range(&mut errors, "vision.auto_exposure.clip_fraction", exposure.clip_fraction, 0.0, 1.0);
+        check_range(&mut errors, "vision.auto_exposure.clear_fraction", exposure.clear_fraction, 0.0, 1.0);
+        if exposure.clear_fraction >= exposure.clip_fraction {
+            errors.push(FieldError::new("vision.auto_exposure.clear_fraction", "must be below clip_fraction"));
+        }
+        check_range(&mut errors, "vision.auto_exposure.step_ev", exposure.step_ev, 0.1, 2.0);
+        check_range(&mut errors, "vision.auto_exposure.min_ev", exposure.min_ev, -8.0, 0.0);
+        if exposure.sample_interval_ms < 100 {
+            errors.push(FieldError::new("vision.auto_exposure.sample_interval_ms", "must be at least 100"));
+        }
+        if exposure.min_step_interval_ms < exposure.sample_interval_ms {
+            errors.push(FieldError::new("vision.auto_exposure.min_step_interval_ms", "must be at least sample_interval_ms"));
+        }
+        if exposure.roi.is_some_and(|(x, y, w, h)| x < 0 || y < 0 || w <= 0 || h <= 0) {
+            errors.push(FieldError::new("vision.auto_exposure.roi", "must be x, y, w, h with x and y at least 0 and w and h over 0"));
+        }
+        if !(1..=160).contains(&v.heatmap.columns) {
+            errors.push(FieldError::new("vision.heatmap.columns", "must be between 1 and 160"));
+        }
+        if !(1..=120).contains(&v.heatmap.rows) {
+            errors.push(FieldError::new("vision.heatmap.rows", "must be between 1 and 120"));
+        }
+        check_range(&mut errors, "vision.heatmap.half_life_s", v.heatmap.half_life_s, 1.0, 86400.0);
+        check_range(&mut errors, "vision.sweep.floor", v.sweep.floor, 0.01, 1.0);
+        check_range(&mut errors, "vision.sweep.max_duration_s", v.sweep.max_duration_s, 1.0, 86400.0);
+        if !(1.0..=v.sweep.max_duration_s).contains(&v.sweep.duration_s) {
+            errors.push(FieldError::new("vision.sweep.duration_s", "must be between 1 and max_duration_s"));
+        }
+        if !(1..=200_000).contains(&v.sweep.max_frames) {
+            errors.push(FieldError::new("vision.sweep.max_frames", "must be between 1 and 200000"));
+        }
+        if !(1..=256).contains(&v.sweep.max_per_frame) {
+            errors.push(FieldError::new("vision.sweep.max_per_frame", "must be between 1 and 256"));
+        }
+        if v.ir_cut_pin.is_some_and(|pin| pin > 27) {
+            errors.push(FieldError::new("vision.ir_cut_pin", "must be a BCM GPIO number from 0 to 27"));
+        }
+        for (i, polygon) in v.self_mask.iter().enumerate() {
+            if polygon.len() < 3 {
+                errors.push(FieldError::new(format!("vision.self_mask[{}]", i), "needs at least 3 corners"));
+            }
+            if polygon.iter().any(|(x, y)| !x.is_finite() || !y.is_finite()) {
+                errors.push(FieldError::new(format!("vision.self_mask[{}]", i), "corners must be finite"));
+            }
+        }
+        check_range(&mut errors, "vision.self_mask_min_overlap", v.self_mask_min_overlap, 0.05, 1.0);
+        if let Some(ground) = &v.ground_calibration {
+            if ground.homography.iter().any(|h| !h.is_finite()) || ground.homography.iter().all(|h| *h == 0.0) {
+                errors.push(FieldError::new("vision.ground_calibration.homography", "must be finite and not all zero"));
+            }
+            if ground.version == 0 {
+                errors.push(FieldError::new("vision.ground_calibration.version", "must be at least 1"));
+            }
+        }
+        if let Some((_, _, w, h)) = v.detection_roi {
+            if w <= 0 || h <= 0 {
+                errors.push(FieldError::new("vision.detection_roi", "width and height must be positive"));
+            }
+        }
+        check_range(&mut errors, "vision.track_iou", v.track_iou, 0.0, 1.0);
+        check_range(&mut errors, "vision.distance_smoothing", v.distance_smoothing, 0.0, 0.99);
+        for source in v.label_maps.keys() {
+            if !DETECTION_SOURCES.contains(&source.as_str()) {
+                errors.push(FieldError::new(
+                    format!("vision.label_maps.{}", source),
+                    format!("source must be one of {}", DETECTION_SOURCES.join(", ")),
+                ));
+            }
+        }
+        check_positive(&mut errors, "vision.fiducial_tag_size_m", v.fiducial_tag_size_m);
+        check_range(&mut errors, "vision.blob_dark_threshold", v.blob_dark_threshold, 0.0, 255.0);
+        check_range(&mut errors, "vision.blob_min_area_fraction", v.blob_min_area_fraction, 0.0, 1.0);
+        check_range(&mut errors, "vision.blob_bottom_fraction", v.blob_bottom_fraction, 0.0, 1.0);
+        if !(16..=1920).contains(&v.blob_scale_width) {
+            errors.push(FieldError::new("vision.blob_scale_width", "must be between 16 and 1920"));
+        }
+        if !(16..=1920).contains(&v.free_space_scale_width) {
+            errors.push(FieldError::new("vision.free_space_scale_width", "must be between 16 and 1920"));
+        }
+        check_range(&mut errors, "vision.free_space_color_tolerance", v.free_space_color_tolerance, 0.0, 442.0);
+        check_range(&mut errors, "vision.free_space_texture_tolerance", v.free_space_texture_tolerance, 0.0, 255.0);
+        check_positive(&mut errors, "vision.free_space_max_range_m", v.free_space_max_range_m);
+        check_positive(&mut errors, "vision.dropoff_lookahead_m", v.dropoff_lookahead_m);
+        check_positive(&mut errors, "vision.camera_height_m", v.camera_height_m);
+        check_range(&mut errors, "vision.horizon_row_px", v.horizon_row_px, 0.0, 480.0);
+        if v.bridge_command.first().map_or(true, |program| program.is_empty()) {
+            errors.push(FieldError::new("vision.bridge_command", "must name a program"));
+        }
+        check_range(&mut errors, "vision.bridge_parse_window_s", v.bridge_parse_window_s, 1.0, 600.0);
+        check_range(&mut errors, "vision.bridge_max_parse_failure_rate", v.bridge_max_parse_failure_rate, 0.0, 1.0);
+        if v.bridge_tap_dir.is_empty() {
+            errors.push(FieldError::new("vision.bridge_tap_dir", "must not be empty"));
+        }
+        check_range(&mut errors, "vision.bridge_tap_minutes", v.bridge_tap_minutes, 1.0, crate::vision::tap::MAX_MINUTES);
+        if !(1..=512).contains(&v.bridge_tap_file_mb) {
+            errors.push(FieldError::new("vision.bridge_tap_file_mb", "must be between 1 and 512"));
+        }
+        if !(1..=32).contains(&v.bridge_tap_files) {
+            errors.push(FieldError::new("vision.bridge_tap_files", "must be between 1 and 32"));
+        }
+        if !(2..=32).contains(&v.frame_slots) {
+            errors.push(FieldError::new("vision.frame_slots", "must be between 2 and 32"));
+        }
+        if !(16..=4096).contains(&v.frame_slot_kb) {
+            errors.push(FieldError::new("vision.frame_slot_kb", "must be between 16 and 4096"));
+        }
+        if v.pixel_format == PixelFormat::Yuv420 {
+            if v.frame_transport != FrameTransport::Shm {
+                errors.push(FieldError::new("vision.pixel_format", "yuv420 needs vision.frame_transport = \"shm\""));
+            }
+            let needed = crate::vision::pixels::yuv420_len(crate::vision::FRAME_WIDTH_PX as usize, crate::vision::FRAME_HEIGHT_PX as usize)
+                + crate::vision::shm::SLOT_HEADER_BYTES;
+            if v.frame_slot_kb * 1024 < needed {
+                errors.push(FieldError::new("vision.frame_slot_kb", format!("must be at least {} for yuv420 frames", needed.div_ceil(1024))));
+            }
+        }
+        check_range(&mut errors, "vision.mask_expected_fill", v.mask_expected_fill, 0.01, 1.0);
+        check_range(&mut errors, "vision.mask_max_disagreement", v.mask_max_disagreement, 1.0, 10.0);
+        check_positive(&mut errors, "vision.mask_budget_ms", v.mask_budget_ms);
+        if v.max_frame_age_ms > 10_000 {
+            errors.push(FieldError::new("vision.max_frame_age_ms", "must be at most 10000 (0 disables it)"));
+        }
+        if !(2..=64).contains(&v.gray_cache_frames) {
+            errors.push(FieldError::new("vision.gray_cache_frames", "must be between 2 and 64"));
+        }
+        if !(16..=640).contains(&v.gray_cache_width) {
+            errors.push(FieldError::new("vision.gray_cache_width", "must be between 16 and 640"));
+        }
+        if !(16..=480).contains(&v.gray_cache_height) {
+            errors.push(FieldError::new("vision.gray_cache_height", "must be between 16 and 480"));
+        }
+        if v.ego_motion_max_shift_px as usize * 4 >= v.gray_cache_width.min(v.gray_cache_height) as usize {
+            errors.push(FieldError::new("vision.ego_motion_max_shift_px", "must be under a quarter of the cached frame size"));
+        }
+
+        check_range(&mut errors, "motors.max_speed", self.motors.max_speed, 0.0, 1.0);
+        check_range(&mut errors, "motors.max_turn_deg", self.motors.max_turn_deg, 0.0, 180.0);
+        check_positive(&mut errors, "motors.accel_m_s2", self.motors.accel_m_s2);
+        check_positive(&mut errors, "motors.decel_m_s2", self.motors.decel_m_s2);
+        check_positive(&mut errors, "motors.emergency_decel_m_s2", self.motors.emergency_decel_m_s2);
+        if self.motors.emergency_decel_m_s2 < self.motors.decel_m_s2 {
+            errors.push(FieldError::new("motors.emergency_decel_m_s2", "must be at least motors.decel_m_s2"));
+        }
+        check_range(&mut errors, "motors.heading_kp", self.motors.heading_kp, 0.0, 1.0);
+        check_range(&mut errors, "motors.heading_kd", self.motors.heading_kd, 0.0, 1.0);
+        check_range(&mut errors, "motors.heading_max_correction", self.motors.heading_max_correction, 0.0, 1.0);
+
+        check_range(&mut errors, "planner.cruise_speed", self.planner.cruise_speed, 0.0, 1.0);
+        if self.planner.cruise_speed > self.motors.max_speed {
+            errors.push(FieldError::new("planner.cruise_speed", "must not exceed motors.max_speed"));
+        }
+        if !(20..=1000).contains(&self.planner.map_size_cells) {
+            errors.push(FieldError::new("planner.map_size_cells", "must be between 20 and 1000"));
+        }
+        check_positive(&mut errors, "planner.map_resolution_m", self.planner.map_resolution_m);
+        check_range(&mut errors, "planner.camera_hfov_deg", self.planner.camera_hfov_deg, 1.0, 180.0);
+        check_positive(&mut errors, "planner.obstacle_memory_s", self.planner.obstacle_memory_s);
+        if self.planner.static_min_observations == 0 {
+            errors.push(FieldError::new("planner.static_min_observations", "must be at least 1"));
+        }
+        check_positive(&mut errors, "planner.static_max_speed_m_s", self.planner.static_max_speed_m_s);
+        check_positive(&mut errors, "planner.dynamic_memory_s", self.planner.dynamic_memory_s);
+        check_positive(&mut errors, "planner.dock_contact_m", self.planner.dock_contact_m);
+        check_range(&mut errors, "planner.dock_speed", self.planner.dock_speed, 0.0, 1.0);
+        if self.planner.dock_speed > self.motors.max_speed {
+            errors.push(FieldError::new("planner.dock_speed", "must not exceed motors.max_speed"));
+        }
+        check_range(&mut errors, "planner.odometry_variance_per_m", self.planner.odometry_variance_per_m, 0.0, 1.0);
+        check_positive(&mut errors, "planner.landmark_sigma_m", self.planner.landmark_sigma_m);
+        check_positive(&mut errors, "planner.landmark_gate_sigma", self.planner.landmark_gate_sigma);
+        check_range(&mut errors, "planner.landmark_min_confidence", self.planner.landmark_min_confidence, 0.0, 1.0);
+        check_positive(&mut errors, "planner.landmark_max_range_m", self.planner.landmark_max_range_m);
+        check_range(&mut errors, "planner.free_space_min_confidence", self.planner.free_space_min_confidence, 0.0, 1.0);
+        check_positive(&mut errors, "planner.clearance_stop_m", self.planner.clearance_stop_m);
+        check_range(&mut errors, "planner.clearance_corridor_deg", self.planner.clearance_corridor_deg, 0.0, 90.0);
+        for (i, landmark) in self.planner.landmarks.iter().enumerate() {
+            let earlier = &self.planner.landmarks[..i];
+            match (landmark.tag_id, &landmark.class) {
+                (Some(_), Some(_)) | (None, None) => {
+                    errors.push(FieldError::new(format!("planner.landmarks[{}]", i), "needs either a tag_id or a class"));
+                }
+                (Some(id), None) if earlier.iter().any(|l| l.tag_id == Some(id)) => {
+                    errors.push(FieldError::new(format!("planner.landmarks[{}].tag_id", i), "duplicate tag id"));
+                }
+                (None, Some(class)) if earlier.iter().any(|l| l.class.as_ref() == Some(class)) => {
+                    errors.push(FieldError::new(format!("planner.landmarks[{}].class", i), "duplicate class"));
+                }
+                _ => {}
+            }
+        }
+        if let Some((lat, lon)) = self.planner.gps_origin {
+            if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+                errors.push(FieldError::new("planner.gps_origin", "must be (lat, lon) in degrees"));
+            }
+        }
+        check_range(&mut errors, "planner.gps_map_heading_deg", self.planner.gps_map_heading_deg, 0.0, 360.0);
+        check_positive(&mut errors, "planner.gps_max_hdop", self.planner.gps_max_hdop);
+        check_range(&mut errors, "planner.gps_fix_weight", self.planner.gps_fix_weight, 0.0, 1.0);
+        check_positive(&mut errors, "planner.gps_fix_timeout_s", self.planner.gps_fix_timeout_s);
+        if self.planner.follow_distance_m <= self.vision.person_stop_m {
+            errors.push(FieldError::new("planner.follow_distance_m", "must be greater than vision.person_stop_m"));
+        }
+        check_range(&mut errors, "planner.follow_speed", self.planner.follow_speed, 0.0, 1.0);
+        if self.planner.follow_speed > self.motors.max_speed {
+            errors.push(FieldError::new("planner.follow_speed", "must not exceed motors.max_speed"));
+        }
+        check_range(&mut errors, "planner.follow_align_deg", self.planner.follow_align_deg, 1.0, 45.0);
+        check_positive(&mut errors, "planner.follow_reacquire_m", self.planner.follow_reacquire_m);
+        check_positive(&mut errors, "planner.follow_timeout_s", self.planner.follow_timeout_s);
+        check_range(&mut errors, "planner.dropoff_min_floor", self.planner.dropoff_min_floor, 0.0, 1.0);
+        check_positive(&mut errors, "planner.dropoff_backoff_m", self.planner.dropoff_backoff_m);
+        check_range(&mut errors, "planner.dropoff_backoff_speed", self.planner.dropoff_backoff_speed, 0.0, 1.0);
+        if self.planner.dropoff_backoff_speed > self.motors.max_speed {
+            errors.push(FieldError::new("planner.dropoff_backoff_speed", "must not exceed motors.max_speed"));
+        }
+        check_range(&mut errors, "planner.dropoff_arc_deg", self.planner.dropoff_arc_deg, 10.0, 270.0);
+        if self.planner.dropoff_clear_m <= self.planner.dropoff_backoff_m {
+            errors.push(FieldError::new("planner.dropoff_clear_m", "must be greater than planner.dropoff_backoff_m"));
+        }
+        check_range(&mut errors, "planner.rollout_horizon_s", self.planner.rollout_horizon_s, 0.1, 10.0);
+        check_positive(&mut errors, "planner.rollout_step_s", self.planner.rollout_step_s);
+        if self.planner.rollout_step_s > self.planner.rollout_horizon_s {
+            errors.push(FieldError::new("planner.rollout_step_s", "must not exceed planner.rollout_horizon_s"));
+        }
+        check_range(&mut errors, "planner.rollout_clearance_m", self.planner.rollout_clearance_m, 0.0, 2.0);
+        check_range(&mut errors, "planner.slip_min_travel_m", self.planner.slip_min_travel_m, 0.001, 1.0);
+        check_range(&mut errors, "planner.slip_min_confidence", self.planner.slip_min_confidence, 0.0, 1.0);
+        check_range(&mut errors, "planner.slip_disagreement", self.planner.slip_disagreement, 0.0, 1.0);
+        if !(1..=100).contains(&self.planner.slip_window) {
+            errors.push(FieldError::new("planner.slip_window", "must be between 1 and 100"));
+        }
+        check_range(&mut errors, "planner.slip_warn_ratio", self.planner.slip_warn_ratio, 0.0, 1.0);
+        check_range(&mut errors, "planner.slip_sustain_s", self.planner.slip_sustain_s, 0.0, 60.0);
+        check_range(&mut errors, "planner.slip_speed_factor", self.planner.slip_speed_factor, 0.0, 1.0);
+        check_range(&mut errors, "planner.envelope_lookahead_m", self.planner.envelope_lookahead_m, 0.1, 10.0);
+        check_envelope_curve(&mut errors, "planner.envelope_clearance_curve", &self.planner.envelope_clearance_curve, 10.0);
+        check_envelope_curve(&mut errors, "planner.envelope_unknown_curve", &self.planner.envelope_unknown_curve, 1.0);
+        check_range(&mut errors, "planner.zone_slow_speed", self.planner.zone_slow_speed, 0.0, 1.0);
+        check_positive(&mut errors, "planner.zone_lookahead_m", self.planner.zone_lookahead_m);
+        check_range(&mut errors, "planner.passage_reverse_speed", self.planner.passage_reverse_speed, 0.05, 1.0);
+        check_range(&mut errors, "planner.one_way_tolerance_deg", self.planner.one_way_tolerance_deg, 10.0, 170.0);
+        for (i, zone) in self.planner.zones.iter().enumerate() {
+            let field = |name: &str| format!("planner.zones[{}].{}", i, name);
+            if zone.name.is_empty() {
+                errors.push(FieldError::new(field("name"), "must not be empty"));
+            } else if self.planner.zones[..i].iter().any(|z| z.name == zone.name) {
+                errors.push(FieldError::new(field("name"), "duplicate zone name"));
+            }
+            // Collinear corners are accepted; such a zone just never
+            // contains anything.
+            if zone.polygon.len() < 3 {
+                errors.push(FieldError::new(field("polygon"), "needs at least 3 corners"));
+            }
+            if zone.polygon.iter().any(|(x, y)| !x.is_finite() || !y.is_finite()) {
+                errors.push(FieldError::new(field("polygon"), "corners must be finite"));
+            }
+            match (zone.policy, zone.direction_deg) {
+                (ZonePolicy::OneWay, None) => errors.push(FieldError::new(field("direction_deg"), "is needed for a one_way zone")),
+                (ZonePolicy::OneWay, Some(direction)) if !direction.is_finite() => errors.push(FieldError::new(field("direction_deg"), "must be finite")),
+                (ZonePolicy::OneWay, Some(_)) | (_, None) => {}
+                (_, Some(_)) => errors.push(FieldError::new(field("direction_deg"), "is only for one_way zones")),
+            }
+        }
+
+        let w = &self.web;
+        if w.bind_address.parse::<std::net::IpAddr>().is_err() {
+            errors.push(FieldError::new("web.bind_address", "must be an IP address"));
+        }
+        if w.port == 0 {
+            errors.push(FieldError::new("web.port", "must be non-zero"));
+        }
+        if !w.base_path.is_empty() && (!w.base_path.starts_with('/') || w.base_path.ends_with('/')) {
+            errors.push(FieldError::new("web.base_path", "must start with '/' and not end with '/'"));
+        }
+        check_range(&mut errors, "web.ping_interval_s", w.ping_interval_s, 0.5, 60.0);
+        if w.max_missed_pongs == 0 {
+            errors.push(FieldError::new("web.max_missed_pongs", "must be at least 1"));
+        }
+        for (i, step) in w.quality_steps.iter().enumerate() {
+            if !(1..=100).contains(&step.jpeg_quality) {
+                errors.push(FieldError::new(format!("web.quality_steps[{}].jpeg_quality", i), "must be between 1 and 100"));
+            }
+            check_range(&mut errors, &format!("web.quality_steps[{}].scale", i), step.scale, 0.1, 1.0);
+        }
+        if w.quality_backlog == 0 {
+            errors.push(FieldError::new("web.quality_backlog", "must be at least 1"));
+        }
+        if w.quality_rtt_ms == 0 {
+            errors.push(FieldError::new("web.quality_rtt_ms", "must be non-zero"));
+        }
+        check_positive(&mut errors, "web.quality_hold_s", w.quality_hold_s);
+        check_positive(&mut errors, "web.quality_recover_s", w.quality_recover_s);
+        check_positive(&mut errors, "web.detection_full_sync_s", w.detection_full_sync_s);
+        if w.tls_cert_path.is_some() != w.tls_key_path.is_some() {
+            errors.push(FieldError::new("web.tls_key_path", "tls_cert_path and tls_key_path must be set together"));
+        }
+
+        let s = &self.sessions;
+        if s.directory.is_empty() {
+            errors.push(FieldError::new("sessions.directory", "must not be empty"));
+        }
+        check_positive(&mut errors, "sessions.image_interval_s", s.image_interval_s);
+        if s.quota_mb == 0 {
+            errors.push(FieldError::new("sessions.quota_mb", "must be non-zero"));
+        }
+
+        let c = &self.control;
+        if c.min_tick_interval_ms == 0 {
+            errors.push(FieldError::new("control.min_tick_interval_ms", "must be at least 1"));
+        }
+        if c.max_tick_interval_ms < c.min_tick_interval_ms {
+            errors.push(FieldError::new("control.max_tick_interval_ms", "must be >= control.min_tick_interval_ms"));
+        }
+        if c.fixed_tick_interval_ms == 0 {
+            errors.push(FieldError::new("control.fixed_tick_interval_ms", "must be at least 1"));
+        }
+        if c.plan_interval_ms == 0 {
+            errors.push(FieldError::new("control.plan_interval_ms", "must be at least 1"));
+        }
+        if c.plan_stale_ms <= c.plan_interval_ms {
+            errors.push(FieldError::new("control.plan_stale_ms", "must be longer than control.plan_interval_ms"));
+        }
+        if !(50..=10_000).contains(&c.watchdog_deadline_ms) {
+            errors.push(FieldError::new("control.watchdog_deadline_ms", "must be between 50 and 10000"));
+        }
+        if c.max_tick_interval_ms >= c.watchdog_deadline_ms || c.fixed_tick_interval_ms >= c.watchdog_deadline_ms {
+            errors.push(FieldError::new("control.watchdog_deadline_ms", "must be longer than the max and fixed tick intervals"));
+        }
+        check_positive(&mut errors, "control.latency_budget_ms", c.latency_budget_ms);
+
+        if self.schedule.snapshot_directory.is_empty() {
+            errors.push(FieldError::new("schedule.snapshot_directory", "must not be empty"));
+        }
+        for (i, entry) in self.schedule.entries.iter().enumerate() {
+            let field = |name: &str| format!("schedule.entries.{}.{}", i, name);
+            if entry.name.is_empty() {
+                errors.push(FieldError::new(field("name"), "must not be empty"));
+            } else if self.schedule.entries[..i].iter().any(|e| e.name == entry.name) {
+                errors.push(FieldError::new(field("name"), "must be unique"));
+            }
+            let whens = [entry.at.is_some(), entry.every_s.is_some(), entry.when.is_some()];
+            if whens.iter().filter(|set| **set).count() != 1 {
+                errors.push(FieldError::new(field("at"), "exactly one of at, every_s and when must be set"));
+            }
+            if let Some(at) = &entry.at {
+                if chrono::NaiveTime::parse_from_str(at, "%H:%M").is_err() {
+                    errors.push(FieldError::new(field("at"), "must be a local time like 09:30"));
+                }
+            }
+            if entry.days.iter().any(|d| d.parse::<chrono::Weekday>().is_err()) {
+                errors.push(FieldError::new(field("days"), "must be day names like mon or tue"));
+            }
+            if entry.every_s == Some(0) {
+                errors.push(FieldError::new(field("every_s"), "must be at least 1"));
+            }
+            if let Some(when) = &entry.when {
+                if !crate::telemetry::METRIC_NAMES.contains(&when.metric.as_str()) {
+                    errors.push(FieldError::new(field("when.metric"), format!("must be one of {}", crate::telemetry::METRIC_NAMES.join(", "))));
+                }
+                if when.below.is_some() == when.above.is_some() {
+                    errors.push(FieldError::new(field("when.below"), "exactly one of below and above must be set"));
+                }
+            }
+        }
+
+        let m = &self.mqtt;
+        if m.broker_host.is_empty() {
+            errors.push(FieldError::new("mqtt.broker_host", "must not be empty"));
+        }
+        if m.topic_prefix.is_empty() || m.topic_prefix.contains(['+', '#']) || m.topic_prefix.ends_with('/') {
+            errors.push(FieldError::new("mqtt.topic_prefix", "must be a non-empty topic without wildcards or a trailing /"));
+        }
+        check_positive(&mut errors, "mqtt.detection_debounce_s", m.detection_debounce_s);
+        check_positive(&mut errors, "mqtt.status_interval_s", m.status_interval_s);
+        if !(1..=10_000).contains(&m.queue_size) {
+            errors.push(FieldError::new("mqtt.queue_size", "must be between 1 and 10000"));
+        }
+
+        let ros = &self.ros;
+        if !ros.url.starts_with("ws://") {
+            errors.push(FieldError::new("ros.url", "must be a ws:// URL"));
+        }
+        for (field, topic) in [
+            ("ros.detections_topic", &ros.detections_topic),
+            ("ros.odom_topic", &ros.odom_topic),
+            ("ros.map_topic", &ros.map_topic),
+            ("ros.cmd_vel_topic", &ros.cmd_vel_topic),
+        ] {
+            if !topic.starts_with('/') || topic.len() < 2 {
+                errors.push(FieldError::new(field, "must be an absolute topic name like /scout/odom"));
+            }
+        }
+        check_range(&mut errors, "ros.odom_hz", ros.odom_hz, 0.1, 50.0);
+        check_range(&mut errors, "ros.detections_hz", ros.detections_hz, 0.1, 50.0);
+        check_positive(&mut errors, "ros.map_interval_s", ros.map_interval_s);
+
+        for (name, tones) in &self.annunciator.patterns {
+            let field = format!("annunciator.patterns.{}", name);
+            if !crate::annunciator::ALERT_NAMES.contains(&name.as_str()) {
+                errors.push(FieldError::new(field, format!("must be one of {}", crate::annunciator::ALERT_NAMES.join(", "))));
+            } else if tones.iter().any(|&(hz, ms)| hz > 20_000 || !(1..=2000).contains(&ms)) {
+                errors.push(FieldError::new(field, "tones must be at most 20000 Hz and 1 to 2000 ms long"));
+            }
+        }
+
+        let r = &self.range;
+        check_range(&mut errors, "range.sample_hz", r.sample_hz, 1.0, 100.0);
+        check_positive(&mut errors, "range.stop_m", r.stop_m);
+        check_positive(&mut errors, "range.near_field_m", r.near_field_m);
+        if r.stop_m > r.near_field_m {
+            errors.push(FieldError::new("range.stop_m", "must not exceed range.near_field_m"));
+        }
+        if r.timeout_ms == 0 {
+            errors.push(FieldError::new("range.timeout_ms", "must be non-zero"));
+        }
+        check_positive(&mut errors, "range.dropoff_margin_m", r.dropoff_margin_m);
+        for (i, sensor) in r.sensors.iter().enumerate() {
+            let field = |name: &str| format!("range.sensors[{}].{}", i, name);
+            if sensor.name.is_empty() {
+                errors.push(FieldError::new(field("name"), "must not be empty"));
+            } else if r.sensors[..i].iter().any(|s| s.name == sensor.name) {
+                errors.push(FieldError::new(field("name"), "duplicate sensor name"));
+            }
+            check_range(&mut errors, &field("bearing_deg"), sensor.bearing_deg, -180.0, 180.0);
+            check_range(&mut errors, &field("fov_deg"), sensor.fov_deg, 1.0, 180.0);
+            if sensor.kind == RangeSensorKind::HcSr04 && (sensor.trigger_pin.is_none() || sensor.echo_pin.is_none()) {
+                errors.push(FieldError::new(field("kind"), "hc_sr04 needs trigger_pin and echo_pin"));
+            }
+            if let Some(distance) = sensor.mock_distance_m {
+                check_positive(&mut errors, &field("mock_distance_m"), distance);
+            }
+            if let Some(floor) = sensor.floor_m {
+                check_positive(&mut errors, &field("floor_m"), floor);
+            }
+        }
+
+        let b = &self.blackbox;
+        if b.path.is_empty() {
+            errors.push(FieldError::new("blackbox.path", "must not be empty"));
+        }
+        if b.max_mb == 0 {
+            errors.push(FieldError::new("blackbox.max_mb", "must be non-zero"));
+        }
+        if !(10..=60_000).contains(&b.flush_interval_ms) {
+            errors.push(FieldError::new("blackbox.flush_interval_ms", "must be between 10 and 60000"));
+        }
+        check_positive(&mut errors, "blackbox.telemetry_interval_s", b.telemetry_interval_s);
+
+        let t = &self.selftest;
+        check_range(&mut errors, "selftest.check_timeout_s", t.check_timeout_s, 0.1, 60.0);
+        check_range(&mut errors, "selftest.frame_timeout_s", t.frame_timeout_s, 0.1, 120.0);
+        if t.min_frames == 0 {
+            errors.push(FieldError::new("selftest.min_frames", "must be non-zero"));
+        }
+
+        let th = &self.thermal;
+        if !(100..=60_000).contains(&th.poll_interval_ms) {
+            errors.push(FieldError::new("thermal.poll_interval_ms", "must be between 100 and 60000"));
+        }
+        if th.source == ThermalSourceKind::Sysfs && th.temp_path.is_empty() {
+            errors.push(FieldError::new("thermal.temp_path", "must not be empty"));
+        }
+        check_range(&mut errors, "thermal.degrade_temp_c", th.degrade_temp_c, 30.0, 110.0);
+        if th.recover_temp_c >= th.degrade_temp_c {
+            errors.push(FieldError::new("thermal.recover_temp_c", "must be below thermal.degrade_temp_c"));
+        }
+        if !(33..=5000).contains(&th.degraded_frame_interval_ms) {
+            errors.push(FieldError::new("thermal.degraded_frame_interval_ms", "must be between 33 and 5000"));
+        }
+        check_range(&mut errors, "thermal.degraded_max_speed", th.degraded_max_speed, 0.0, 1.0);
+
+        let u = &self.updates;
+        if u.directory.is_empty() {
+            errors.push(FieldError::new("updates.directory", "must not be empty"));
+        }
+        if u.token_env.as_deref().is_some_and(str::is_empty) {
+            errors.push(FieldError::new("updates.token_env", "must not be empty"));
+        }
+        if !(1..=256).contains(&u.max_upload_mb) {
+            errors.push(FieldError::new("updates.max_upload_mb", "must be between 1 and 256"));
+        }
+        if u.keep_versions == 0 {
+            errors.push(FieldError::new("updates.keep_versions", "must be non-zero"));
+        }
+        if self.interlock.pin > 27 {
+            errors.push(FieldError::new("interlock.pin", "must be a BCM GPIO number from 0 to 27"));
+        }
+        let p = &self.power;
+        check_range(&mut errors, "power.idle_after_s", p.idle_after_s, 1.0, 86_400.0);
+        // Any slower and the bridge's health goes stale while asleep.
+        check_range(&mut errors, "power.keepalive_fps", p.keepalive_fps, 1.0, 15.0);
+        if !(33..=60_000).contains(&p.asleep_frame_interval_ms) {
+            errors.push(FieldError::new("power.asleep_frame_interval_ms", "must be between 33 and 60000"));
+        }
+        check_range(&mut errors, "power.max_resume_s", p.max_resume_s, 0.5, 60.0);
+        if p.motor_enable_pin.is_some_and(|pin| pin > 27) {
+            errors.push(FieldError::new("power.motor_enable_pin", "must be a BCM GPIO number from 0 to 27"));
+        } else if self.interlock.enabled && p.motor_enable_pin == Some(self.interlock.pin) {
+            errors.push(FieldError::new("power.motor_enable_pin", "must not be the interlock's pin"));
+        }
+        if self.imu.i2c_address > 0x7f {
+            errors.push(FieldError::new("imu.i2c_address", "must be a 7-bit I2C address"));
+        }
+        check_range(&mut errors, "imu.mock_yaw_rate_dps", self.imu.mock_yaw_rate_dps, -250.0, 250.0);
+
+        let c = &self.checkpoint;
+        if c.path.is_empty() {
+            errors.push(FieldError::new("checkpoint.path", "must not be empty"));
+        }
+        check_range(&mut errors, "checkpoint.interval_s", c.interval_s, 0.5, 600.0);
+        check_range(&mut errors, "checkpoint.max_age_s", c.max_age_s, 1.0, 7.0 * 24.0 * 3600.0);
+
+        if !(10..=100_000).contains(&self.logs.capacity) {
+            errors.push(FieldError::new("logs.capacity", "must be between 10 and 100000"));
+        }
+        if self.logs.redact_fields.iter().any(String::is_empty) {
+            errors.push(FieldError::new("logs.redact_fields", "must not contain an empty name"));
+        }
+
+        let t = &self.teleop;
+        check_positive(&mut errors, "teleop.max_linear_m_s", t.max_linear_m_s);
+        if t.max_linear_m_s > self.motors.max_speed {
+            errors.push(FieldError::new("teleop.max_linear_m_s", "must not exceed motors.max_speed"));
+        }
+        check_range(&mut errors, "teleop.max_angular_rad_s", t.max_angular_rad_s, 0.1, 6.0);
+        check_range(&mut errors, "teleop.deadzone", t.deadzone, 0.0, 0.5);
+        check_range(&mut errors, "teleop.expo", t.expo, 0.0, 10.0);
+
+        let hz = &self.hazards;
+        if hz.token_env.as_deref().is_some_and(str::is_empty) {
+            errors.push(FieldError::new("hazards.token_env", "must not be empty"));
+        }
+        check_range(&mut errors, "hazards.max_ttl_s", hz.max_ttl_s, 0.1, 3600.0);
+        check_positive(&mut errors, "hazards.caution_speed_m_s", hz.caution_speed_m_s);
+        if hz.caution_speed_m_s > self.motors.max_speed {
+            errors.push(FieldError::new("hazards.caution_speed_m_s", "must not exceed motors.max_speed"));
+        }
+        check_range(&mut errors, "hazards.stop_arc_deg", hz.stop_arc_deg, 0.0, 180.0);
+        check_range(&mut errors, "hazards.rate_per_s", hz.rate_per_s, 0.01, 100.0);
+        if !(1..=100).contains(&hz.burst) {
+            errors.push(FieldError::new("hazards.burst", "must be between 1 and 100"));
+        }
+        if !(1..=1024).contains(&hz.max_active) {
+            errors.push(FieldError::new("hazards.max_active", "must be between 1 and 1024"));
+        }
+
+        let sn = &self.snapshot;
+        if sn.format == SnapshotFormat::Webp && !cfg!(feature = "webp") {
+            errors.push(FieldError::new("snapshot.format", "webp needs a build with the webp feature"));
+        }
+        if !(1..=100).contains(&sn.quality) {
+            errors.push(FieldError::new("snapshot.quality", "must be between 1 and 100"));
+        }
+        check_range(&mut errors, "snapshot.stream_fps", sn.stream_fps, 0.1, 30.0);
+        if !(1..=16).contains(&sn.max_concurrent) {
+            errors.push(FieldError::new("snapshot.max_concurrent", "must be between 1 and 16"));
+        }
+
+        for (class, display) in &self.display.classes {
+            if display.color.as_deref().is_some_and(|c| !is_hex_color(c)) {
+                errors.push(FieldError::new(format!("display.classes.{}.color", class), "must be #rrggbb"));
+            }
+        }
+
+        if self.gps.device.is_empty() {
+            errors.push(FieldError::new("gps.device", "must not be empty"));
+        }
+        if self.gps.baud_rate == 0 {
+            errors.push(FieldError::new("gps.baud_rate", "must be non-zero"));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn is_profile_name(name: &str) -> bool {
+    (1..=32).contains(&name.len()) && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+// Per-source (raw, calibrated) confidence points, as in
+// vision.confidence_calibration.
+fn check_calibration(errors: &mut Vec<FieldError>, prefix: &str, calibration: &BTreeMap<String, Vec<(f32, f32)>>) {
+    for (source, points) in calibration {
+        let field = format!("{}.{}", prefix, source);
+        if !DETECTION_SOURCES.contains(&source.as_str()) {
+            errors.push(FieldError::new(&field, format!("source must be one of {}", DETECTION_SOURCES.join(", "))));
+        }
+        for (raw, calibrated) in points {
+            check_range(errors, &field, *raw, 0.0, 1.0);
+            check_range(errors, &field, *calibrated, 0.0, 1.0);
+        }
+        if points.windows(2).any(|w| w[1].0 <= w[0].0) {
+            errors.push(FieldError::new(&field, "raw confidences must be strictly increasing"));
+        }
+    }
+}
+
+// (x, speed) points of a planner.envelope_* curve, x from 0 to `max_x`.
+fn check_envelope_curve(errors: &mut Vec<FieldError>, field: &str, points: &[(f32, f32)], max_x: f32) {
+    for (x, speed) in points {
+        check_range(errors, field, *x, 0.0, max_x);
+        check_range(errors, field, *speed, 0.0, 1.0);
+    }
+    if points.windows(2).any(|w| w[1].0 <= w[0].0) {
+        errors.push(FieldError::new(field, "points must be in strictly increasing order"));
+    }
+}
+
+fn check_range(errors: &mut Vec<FieldError>, field: &str, value: f32, min: f32, max: f32) {
+    if !(min..=max).contains(&value) {
+        errors.push(FieldError::new(field, format!("must be between {} and {}", min, max)));
+    }
+}
+
+fn check_positive(errors: &mut Vec<FieldError>, field: &str, value: f32) {
+    if !(value.is_finite() && value > 0.0) {
+        errors.push(FieldError::new(field, "must be a positive number"));
+    }
+}
+
+pub struct ConfigStore {
+    path: PathBuf,
+    current: RwLock<RoverConfig>,
+}
+
+impl ConfigStore {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let config = if path.exists() {
+            let text = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let config: RoverConfig = toml::from_str(&text)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            if let Err(errors) = config.validate() {
+                let details: Vec<String> = errors.iter()
+                    .map(|e| format!("{}: {}", e.field, e.message))
+                    .collect();
+                anyhow::bail!("Invalid config {}: {}", path.display(), details.join(", "));
+            }
+            info!(target: "config", "Loaded config from {}", path.display());
+            config
+        } else {
+            info!(target: "config", "No config at {}, using defaults", path.display());
+            RoverConfig::default()
+        };
+
+        Ok(Self {
+            path,
+            current: RwLock::new(config),
+        })
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self) -> RoverConfig {
+        self.current.read().clone()
+    }
+
+    // Applies a JSON merge patch onto the current config. The result is
+    // validated as a whole, so either every change lands or none does.
+    pub fn update(&self, patch: &serde_json::Value) -> Result<RoverConfig, Vec<FieldError>> {
+        let mut current = self.current.write();
+
+        let mut merged = serde_json::to_value(&*current)
+            .map_err(|e| vec![FieldError::new("", e.to_string())])?;
+        merge_patch(&mut merged, patch);
+
+        let candidate: RoverConfig = serde_json::from_value(merged)
+            .map_err(|e| vec![FieldError::new("", e.to_string())])?;
+        candidate.validate()?;
+
+        *current = candidate.clone();
+        Ok(candidate)
+    }
+
+    pub fn persist(&self) -> Result<()> {
+        let text = toml::to_string_pretty(&*self.current.read())?;
+        let tmp = self.path.with_extension("toml.tmp");
+        std::fs::write(&tmp, text)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace {}", self.path.display()))?;
+        info!(target: "config", "Saved config to {}", self.path.display());
+        Ok(())
+    }
+}
+
+fn merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    match (target, patch) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(patch)) => {
+            for (key, value) in patch {
+                match target.get_mut(key) {
+                    Some(existing) if value.is_object() => merge_patch(existing, value),
+                    _ => {
+                        target.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (target, patch) => *target = patch.clone(),
+    }
+}
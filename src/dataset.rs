@@ -0,0 +1,330 @@
+// src/dataset.rs
+use anyhow::{Context, Result};
+use image::imageops::FilterType;
+use image::GrayImage;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+use crate::annotations::{self, Annotation};
+use crate::recording::{self, RecordedFrame};
+
+// Frames are compared for near-duplicates at this size.
+const THUMB_WIDTH: u32 = 32;
+const THUMB_HEIGHT: u32 = 24;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportOptions {
+    // Detections under this calibrated confidence are left out.
+    pub min_confidence: f32,
+    // Only these classes, in this order of class index; every class seen,
+    // sorted by name, when empty.
+    pub classes: Vec<String>,
+    // Ms since the recording started.
+    pub from_ms: Option<u64>,
+    pub to_ms: Option<u64>,
+    // Every nth frame with an image, counting from the first.
+    pub every: usize,
+    // A frame whose mean difference from the last one exported is under
+    // this, 0-1, is a duplicate; 0 keeps them all.
+    pub dedup_below: f32,
+    // Leave out frames left with no detections, instead of exporting them
+    // as background images.
+    pub skip_empty: bool,
+    // Only frames an operator annotated. Annotated frames are exported
+    // whatever every, dedup_below and skip_empty say.
+    pub annotated_only: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            min_confidence: 0.0,
+            classes: Vec::new(),
+            from_ms: None,
+            to_ms: None,
+            every: 1,
+            dedup_below: 0.01,
+            skip_empty: false,
+            annotated_only: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DatasetSummary {
+    pub frames: usize,
+    pub images: usize,
+    pub instances: usize,
+    pub instances_per_class: BTreeMap<String, usize>,
+    // Frames recorded without an image; only one every
+    // session.image_interval_s has one.
+    pub without_image: usize,
+    pub duplicates: usize,
+    // Boxes cut down to the image, and ones with nothing left inside it.
+    pub boxes_clamped: usize,
+    pub boxes_outside: usize,
+    // Operator annotations kept with the recording, and how many are on a
+    // frame that was exported.
+    pub annotations: usize,
+    pub annotations_with_image: usize,
+}
+
+// A box in image pixels, clamped to the image.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BoxPx {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+// None when no part of the box is inside the image.
+pub fn clamp_box(bbox: (i32, i32, i32, i32), width: u32, height: u32) -> Option<BoxPx> {
+    let (x, y, w, h) = bbox;
+    let x0 = x.clamp(0, width as i32);
+    let y0 = y.clamp(0, height as i32);
+    let x1 = x.saturating_add(w).clamp(0, width as i32);
+    let y1 = y.saturating_add(h).clamp(0, height as i32);
+    if x1 <= x0 || y1 <= y0 {
+        return None;
+    }
+    Some(BoxPx { x: x0 as u32, y: y0 as u32, w: (x1 - x0) as u32, h: (y1 - y0) as u32 })
+}
+
+// Mean absolute difference of two thumbnails, 0-1.
+pub fn frame_difference(a: &GrayImage, b: &GrayImage) -> f32 {
+    if a.dimensions() != b.dimensions() {
+        return 1.0;
+    }
+    let total: u64 = a.as_raw().iter().zip(b.as_raw()).map(|(&p, &q)| p.abs_diff(q) as u64).sum();
+    total as f32 / (a.as_raw().len().max(1) as f32 * 255.0)
+}
+
+struct Instance {
+    class: String,
+    bbox: BoxPx,
+    confidence: f32,
+}
+
+struct ExportedImage {
+    file: String,
+    t_ms: u64,
+    tick: Option<u64>,
+    width: u32,
+    height: u32,
+    instances: Vec<Instance>,
+    // Labels of the operator annotations on it.
+    annotations: Vec<String>,
+}
+
+// Writes the frames of a recording that have an image out as a dataset:
+//
+//   images/<name>.jpg    the JPEGs as recorded
+//   labels/<name>.txt    YOLO: class cx cy w h confidence, normalised
+//   classes.txt          YOLO class names, one per index
+//   annotations.json     COCO, with each annotation's confidence as score
+//   manifest.json        where it came from, the options, the summary
+//                        and the operator annotations
+//
+// Images are written as they are read; labels, annotations and the
+// manifest once every class is known.
+pub fn export(recording_path: &Path, output: &Path, options: &ExportOptions) -> Result<DatasetSummary> {
+    if output.join("manifest.json").exists() {
+        anyhow::bail!("{} already holds a dataset", output.display());
+    }
+    let images_dir = output.join("images");
+    let labels_dir = output.join("labels");
+    for dir in [&images_dir, &labels_dir] {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let stem = recording_path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "frame".to_string());
+
+    let mut summary = DatasetSummary::default();
+    let mut exported: Vec<ExportedImage> = Vec::new();
+    let mut last_thumb: Option<GrayImage> = None;
+    let mut candidates = 0usize;
+    let operator_annotations = annotations::load(recording_path)?;
+    let mut annotated_images: Vec<Option<String>> = vec![None; operator_annotations.len()];
+    for frame in recording::open_frames(recording_path)? {
+        if options.from_ms.is_some_and(|from| frame.t_ms < from) || options.to_ms.is_some_and(|to| frame.t_ms > to) {
+            continue;
+        }
+        let marks: Vec<usize> = (0..operator_annotations.len()).filter(|&i| is_on(&operator_annotations[i], &frame)).collect();
+        if options.annotated_only && marks.is_empty() {
+            continue;
+        }
+        summary.frames += 1;
+        let Some(jpeg) = frame.decode_image()? else {
+            summary.without_image += 1;
+            continue;
+        };
+        candidates += 1;
+        if marks.is_empty() && (candidates - 1) % options.every.max(1) != 0 {
+            continue;
+        }
+        let decoded = image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg)
+            .with_context(|| format!("Failed to decode the frame at {} ms", frame.t_ms))?;
+        let thumb = decoded.resize_exact(THUMB_WIDTH, THUMB_HEIGHT, FilterType::Triangle).to_luma8();
+        if marks.is_empty() && last_thumb.as_ref().is_some_and(|last| frame_difference(last, &thumb) < options.dedup_below) {
+            summary.duplicates += 1;
+            continue;
+        }
+
+        let instances = frame_instances(&frame, decoded.width(), decoded.height(), options, &mut summary);
+        if marks.is_empty() && instances.is_empty() && options.skip_empty {
+            continue;
+        }
+        last_thumb = Some(thumb);
+        let file = format!("{}-{:06}.jpg", stem, exported.len() + 1);
+        let path = images_dir.join(&file);
+        fs::write(&path, &jpeg).with_context(|| format!("Failed to write {}", path.display()))?;
+        for &i in &marks {
+            annotated_images[i].get_or_insert_with(|| file.clone());
+        }
+        exported.push(ExportedImage {
+            file,
+            t_ms: frame.t_ms,
+            tick: frame.tick,
+            width: decoded.width(),
+            height: decoded.height(),
+            instances,
+            annotations: marks.iter().map(|&i| operator_annotations[i].label.clone()).collect(),
+        });
+    }
+    summary.annotations = operator_annotations.len();
+    summary.annotations_with_image = annotated_images.iter().filter(|f| f.is_some()).count();
+
+    let classes = if options.classes.is_empty() {
+        let mut seen: Vec<String> = exported.iter().flat_map(|i| i.instances.iter().map(|d| d.class.clone())).collect();
+        seen.sort();
+        seen.dedup();
+        seen
+    } else {
+        options.classes.clone()
+    };
+    let class_index = |name: &str| classes.iter().position(|c| c == name).unwrap_or(0);
+
+    for image in &exported {
+        let mut label = String::new();
+        for instance in &image.instances {
+            let b = instance.bbox;
+            label.push_str(&format!(
+                "{} {:.6} {:.6} {:.6} {:.6} {:.4}\n",
+                class_index(&instance.class),
+                (b.x as f32 + b.w as f32 / 2.0) / image.width as f32,
+                (b.y as f32 + b.h as f32 / 2.0) / image.height as f32,
+                b.w as f32 / image.width as f32,
+                b.h as f32 / image.height as f32,
+                instance.confidence,
+            ));
+            summary.instances += 1;
+            *summary.instances_per_class.entry(instance.class.clone()).or_default() += 1;
+        }
+        let path = labels_dir.join(Path::new(&image.file).with_extension("txt"));
+        fs::write(&path, label).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    summary.images = exported.len();
+    write_text(&output.join("classes.txt"), &classes.iter().map(|c| format!("{}\n", c)).collect::<String>())?;
+
+    let created = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let mut annotation_id = 0;
+    let coco = serde_json::json!({
+        "info": {
+            "description": format!("Scout rover frames from {}", recording_path.display()),
+            "date_created": created,
+        },
+        "images": exported.iter().enumerate().map(|(i, image)| serde_json::json!({
+            "id": i + 1,
+            "file_name": image.file,
+            "width": image.width,
+            "height": image.height,
+        })).collect::<Vec<_>>(),
+        "annotations": exported.iter().enumerate().flat_map(|(i, image)| image.instances.iter().map(move |d| (i, d))).map(|(i, d)| {
+            annotation_id += 1;
+            serde_json::json!({
+                "id": annotation_id,
+                "image_id": i + 1,
+                "category_id": class_index(&d.class) + 1,
+                "bbox": [d.bbox.x, d.bbox.y, d.bbox.w, d.bbox.h],
+                "area": d.bbox.w * d.bbox.h,
+                "iscrowd": 0,
+                "score": d.confidence,
+            })
+        }).collect::<Vec<_>>(),
+        "categories": classes.iter().enumerate().map(|(i, name)| serde_json::json!({
+            "id": i + 1,
+            "name": name,
+        })).collect::<Vec<_>>(),
+    });
+    write_text(&output.join("annotations.json"), &serde_json::to_string_pretty(&coco)?)?;
+
+    let manifest = serde_json::json!({
+        "source": recording_path.display().to_string(),
+        "created": created,
+        "options": options,
+        "classes": classes,
+        "summary": summary,
+        "images": exported.iter().map(|image| serde_json::json!({
+            "file": format!("images/{}", image.file),
+            "labels": format!("labels/{}", Path::new(&image.file).with_extension("txt").display()),
+            "t_ms": image.t_ms,
+            "tick": image.tick,
+            "instances": image.instances.len(),
+            "annotations": image.annotations,
+        })).collect::<Vec<_>>(),
+        "operator_annotations": operator_annotations.iter().zip(&annotated_images).map(|(a, file)| serde_json::json!({
+            "label": a.label,
+            "note": a.note,
+            "t_ms": a.t_ms,
+            "tick": a.tick,
+            "frame_id": a.frame_id,
+            "image": file.as_ref().map(|f| format!("images/{}", f)),
+            "detections": a.detections,
+        })).collect::<Vec<_>>(),
+    });
+    write_text(&output.join("manifest.json"), &serde_json::to_string_pretty(&manifest)?)?;
+
+    info!(target: "dataset", "Exported {} images with {} instances to {}", summary.images, summary.instances, output.display());
+    Ok(summary)
+}
+
+fn frame_instances(frame: &RecordedFrame, width: u32, height: u32, options: &ExportOptions, summary: &mut DatasetSummary) -> Vec<Instance> {
+    let mut instances = Vec::new();
+    for detection in &frame.detections {
+        if detection.confidence < options.min_confidence {
+            continue;
+        }
+        if !options.classes.is_empty() && !options.classes.contains(&detection.class_name) {
+            continue;
+        }
+        let Some(bbox) = clamp_box(detection.bbox, width, height) else {
+            summary.boxes_outside += 1;
+            continue;
+        };
+        if (bbox.x as i32, bbox.y as i32, bbox.w as i32, bbox.h as i32) != detection.bbox {
+            summary.boxes_clamped += 1;
+        }
+        instances.push(Instance {
+            class: detection.class_name.clone(),
+            bbox,
+            confidence: detection.confidence,
+        });
+    }
+    instances
+}
+
+// The same tick where both have one; the same time into the recording
+// otherwise, which is exact for one made during a replay.
+fn is_on(annotation: &Annotation, frame: &RecordedFrame) -> bool {
+    match (annotation.tick, frame.tick) {
+        (Some(a), Some(f)) => a == f,
+        _ => annotation.t_ms == Some(frame.t_ms),
+    }
+}
+
+fn write_text(path: &Path, text: &str) -> Result<()> {
+    fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+}
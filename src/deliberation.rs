@@ -0,0 +1,220 @@
+// src/deliberation.rs
+//
+// The slow half of control: the planner's map, pose and obstacle updates
+// and the command it plans from them. The reflex loop, the state
+// machine's tick, publishes a world state every tick; the planning loop
+// takes the latest one every control.plan_interval_ms on a thread of its
+// own and publishes a Plan back, which the reflex loop drives on but can
+// only slow down or stop. However long a planning step takes, the reflex
+// loop never waits on it or on the planner lock.
+use parking_lot::{Mutex, RwLock};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{debug, info};
+
+use crate::config::ControlConfig;
+use crate::envelope::SpeedEnvelope;
+use crate::health::HealthHandle;
+use crate::localization::LocalizationStatus;
+use crate::mapping::RoverPose;
+use crate::metrics::METRICS;
+use crate::motor_control::MotorController;
+use crate::pathfinding::{NavigationCommand, PathPlanner, PathStatus};
+use crate::range::{RangeMonitor, RangeReading};
+use crate::simulation;
+use crate::slip::TractionStatus;
+use crate::state_machine::{RoverState, StateHandle};
+use crate::supervisor;
+use crate::vision::Detection;
+use crate::world::{FrameFindings, WorldState};
+
+// Closer than this to the goal counts as having arrived.
+const GOAL_REACHED_M: f32 = 0.25;
+
+pub type PlanWatch = watch::Receiver<Arc<Plan>>;
+
+// What one planning step made of a world state.
+#[derive(Debug, Clone)]
+pub struct Plan {
+    // Counts planning steps; 0 is the stand-in from before the first.
+    pub seq: u64,
+    // The reflex tick whose world state it was planned from.
+    pub tick: u64,
+    pub planned_at: Instant,
+    // Stop unless the rover was autonomous when it was planned.
+    pub command: NavigationCommand,
+    pub envelope: SpeedEnvelope,
+    // Set on the one plan after it happened.
+    pub arrived: bool,
+    pub follow_ended: bool,
+    pub drop_off: bool,
+    // The planner's side of the world state, so reporting on it never
+    // takes the planner lock either.
+    pub status: PathStatus,
+    pub pose: RoverPose,
+    pub localization: LocalizationStatus,
+    pub traction: TractionStatus,
+}
+
+impl Plan {
+    // What there is to report before the planner has stepped.
+    pub fn placeholder(planner: &PathPlanner) -> Self {
+        Self::capture(0, 0, planner)
+    }
+
+    fn capture(seq: u64, tick: u64, planner: &PathPlanner) -> Self {
+        Self {
+            seq,
+            tick,
+            planned_at: Instant::now(),
+            command: NavigationCommand::Stop,
+            envelope: SpeedEnvelope::default(),
+            arrived: false,
+            follow_ended: false,
+            drop_off: planner.is_drop_off_latched(),
+            status: planner.get_status(),
+            pose: planner.get_pose(),
+            localization: planner.get_localization_status(),
+            traction: planner.get_traction_status(),
+        }
+    }
+
+    pub fn get_age(&self) -> Duration {
+        self.planned_at.elapsed()
+    }
+}
+
+pub struct Deliberator {
+    path_planner: Arc<RwLock<PathPlanner>>,
+    motor_controller: Arc<RwLock<MotorController>>,
+    ranges: Arc<RangeMonitor>,
+    handle: StateHandle,
+    plan: watch::Sender<Arc<Plan>>,
+    seq: u64,
+    // The tick of the last frame whose findings were applied.
+    frame_tick: u64,
+    // Set when a step panics, for the state machine to reset the planner
+    // once the fault is acknowledged.
+    panicked: Arc<AtomicBool>,
+}
+
+impl Deliberator {
+    pub fn new(
+        path_planner: Arc<RwLock<PathPlanner>>,
+        motor_controller: Arc<RwLock<MotorController>>,
+        ranges: Arc<RangeMonitor>,
+        handle: StateHandle,
+        panicked: Arc<AtomicBool>,
+    ) -> (Self, PlanWatch) {
+        let initial = Plan::placeholder(&path_planner.read());
+        let (plan, plan_rx) = watch::channel(Arc::new(initial));
+        let deliberator = Self { path_planner, motor_controller, ranges, handle, plan, seq: 0, frame_tick: 0, panicked };
+        (deliberator, plan_rx)
+    }
+
+    // One planning step from a world state, `dt` after the last.
+    pub fn step(&mut self, world: &WorldState, dt: Duration) {
+        self.plan(world.tick, &world.detections, &world.findings, &world.ranges, dt);
+    }
+
+    // The same from the parts of a world state the planner takes, for the
+    // state machine to plan mid-tick. A frame's fiducials, free space and
+    // visual travel are only applied the first time they are planned from.
+    pub fn plan(&mut self, tick: u64, detections: &[Detection], findings: &FrameFindings, ranges: &[RangeReading], dt: Duration) {
+        let started = Instant::now();
+        let new_frame = findings.tick > self.frame_tick;
+        self.frame_tick = self.frame_tick.max(findings.tick);
+        let autonomous = self.handle.get_state() == RoverState::Autonomous;
+        let seq = self.seq + 1;
+
+        let planned = panic::catch_unwind(AssertUnwindSafe(|| {
+            simulation::chaos_point("planner");
+            let motor_status = self.motor_controller.read().get_status();
+            let mut planner = self.path_planner.write();
+            planner.update_pose(&motor_status, dt);
+            if let Some(travel) = findings.travel.filter(|_| new_frame) {
+                planner.update_slip(travel);
+            }
+            planner.update_obstacles(detections);
+            if new_frame {
+                planner.update_fiducials(&findings.fiducials);
+                planner.update_landmarks(detections);
+                if let Some(free_space) = findings.free_space.clone() {
+                    planner.update_free_space(free_space);
+                }
+            }
+            planner.update_ranges(ranges.to_vec(), self.ranges.get_near_field_m());
+
+            let arrived = planner.take_docked() || planner.get_distance_to_goal().is_some_and(|d| d < GOAL_REACHED_M);
+            let follow_ended = planner.take_follow_ended();
+            let mut plan = Plan { arrived, follow_ended, ..Plan::capture(seq, tick, &planner) };
+            if autonomous {
+                plan.command = planner.get_navigation_command();
+                plan.envelope = planner.get_speed_envelope();
+            }
+            plan
+        }));
+        let plan = match planned {
+            Ok(plan) => plan,
+            Err(payload) => {
+                self.panicked.store(true, Ordering::Relaxed);
+                supervisor::report_panic("planner", &supervisor::panic_message(&*payload), &self.handle);
+                // Whatever the planner was left holding, the rover stops.
+                let last = self.plan.borrow().clone();
+                Plan { seq, tick, planned_at: Instant::now(), command: NavigationCommand::Stop, arrived: false, follow_ended: false, ..(*last).clone() }
+            }
+        };
+        self.seq = seq;
+        self.plan.send_replace(Arc::new(plan));
+
+        let elapsed = started.elapsed();
+        METRICS.plan_time.observe(elapsed);
+        METRICS.last_plan_time_ms.set(elapsed.as_secs_f64() * 1000.0);
+    }
+}
+
+// The planning loop: a step every control.plan_interval_ms, from the
+// latest world state whenever there is a new one. Runs until the rover
+// shuts down.
+pub fn spawn(config: &ControlConfig, deliberator: Arc<Mutex<Deliberator>>, state: StateHandle, health: HealthHandle) {
+    let interval = Duration::from_millis(config.plan_interval_ms);
+    let mut world = state.subscribe_world();
+    thread::spawn(move || {
+        info!(target: "planner", "Planning every {:?}", interval);
+        let mut last_step = Instant::now();
+        let mut steps = 0u32;
+        let mut last_fps_time = Instant::now();
+        loop {
+            let started = Instant::now();
+            if state.get_state() == RoverState::ShuttingDown {
+                return;
+            }
+            match world.has_changed() {
+                Ok(true) => {
+                    let snapshot = world.borrow_and_update().clone();
+                    deliberator.lock().step(&snapshot, last_step.elapsed());
+                    last_step = started;
+                    steps += 1;
+                    health.beat_with(serde_json::json!({
+                        "tick": snapshot.tick,
+                        "last_step_ms": METRICS.last_plan_time_ms.get(),
+                    }));
+                }
+                Ok(false) => {}
+                Err(_) => return,
+            }
+            if last_fps_time.elapsed() >= Duration::from_secs(1) {
+                let fps = steps as f32 / last_fps_time.elapsed().as_secs_f32();
+                METRICS.plan_fps.set(fps as f64);
+                debug!(target: "planner", "Planning at {:.1} steps/s", fps);
+                steps = 0;
+                last_fps_time = Instant::now();
+            }
+            thread::sleep(interval.saturating_sub(started.elapsed()));
+        }
+    });
+}
@@ -0,0 +1,172 @@
+// src/detection_delta.rs
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+type Fields = Map<String, Value>;
+
+// What one WebSocket client holds of the detections, so each frame only
+// carries what changed since the last one it was sent. Tracked detections
+// are keyed by track id; the few without one go out whole whenever they
+// change. A full sync replaces everything every full_sync_interval_ms and
+// on the first frame, so a client can't drift for long.
+pub struct DetectionSync {
+    full_sync_interval_ms: u64,
+    last_full_ms: Option<u64>,
+    seq: u64,
+    tracked: BTreeMap<u64, Fields>,
+    untracked: Vec<Value>,
+}
+
+impl DetectionSync {
+    pub fn new(full_sync_interval_ms: u64) -> Self {
+        Self {
+            full_sync_interval_ms,
+            last_full_ms: None,
+            seq: 0,
+            tracked: BTreeMap::new(),
+            untracked: Vec::new(),
+        }
+    }
+
+    // The next frame is a full sync.
+    pub fn resync(&mut self) {
+        self.last_full_ms = None;
+    }
+
+    // Rewrites a frame message for this client: on a full sync its
+    // "detections" stay and "detections_sync" is added, otherwise they are
+    // replaced by "detections_delta". Messages without detections are
+    // left alone.
+    pub fn apply(&mut self, frame: &mut Value, now_ms: u64) {
+        let Some(frame) = frame.as_object_mut() else { return };
+        let Some(detections) = frame.get("detections").and_then(Value::as_array) else { return };
+        let (tracked, untracked) = split_by_track(detections);
+        self.seq += 1;
+
+        let full = self.last_full_ms.map_or(true, |at| now_ms.saturating_sub(at) >= self.full_sync_interval_ms);
+        if full {
+            self.last_full_ms = Some(now_ms);
+            self.tracked = tracked;
+            self.untracked = untracked;
+            frame.insert("detections_sync".to_string(), serde_json::json!({ "full": true, "seq": self.seq }));
+            return;
+        }
+
+        let mut added = Vec::new();
+        let mut updated = Vec::new();
+        for (id, fields) in &tracked {
+            match self.tracked.get(id) {
+                // A field that went away can't be said as a change, so the
+                // whole detection is sent again.
+                Some(known) if known.keys().all(|key| fields.contains_key(key)) => {
+                    let mut changed: Fields = fields.iter()
+                        .filter(|(key, value)| known.get(*key) != Some(value))
+                        .map(|(key, value)| (key.clone(), value.clone()))
+                        .collect();
+                    if !changed.is_empty() {
+                        changed.insert("track_id".to_string(), Value::from(*id));
+                        updated.push(Value::Object(changed));
+                    }
+                }
+                _ => added.push(Value::Object(fields.clone())),
+            }
+        }
+        let removed: Vec<u64> = self.tracked.keys().filter(|id| !tracked.contains_key(id)).copied().collect();
+
+        let mut delta = serde_json::json!({
+            "seq": self.seq,
+            "added": added,
+            "updated": updated,
+            "removed": removed,
+        });
+        if untracked != self.untracked {
+            delta["untracked"] = Value::Array(untracked.clone());
+        }
+        self.tracked = tracked;
+        self.untracked = untracked;
+        frame.remove("detections");
+        frame.insert("detections_delta".to_string(), delta);
+    }
+}
+
+// The client's side of DetectionSync: rebuilds the detections from full
+// syncs and deltas. A delta out of sequence leaves the mirror unsynced
+// until the next full sync.
+#[derive(Debug, Default)]
+pub struct DetectionMirror {
+    seq: Option<u64>,
+    tracked: BTreeMap<u64, Fields>,
+    untracked: Vec<Value>,
+}
+
+impl DetectionMirror {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.seq.is_some()
+    }
+
+    // Feeds one frame message in. False if it was a delta that couldn't
+    // be applied.
+    pub fn apply(&mut self, frame: &Value) -> bool {
+        if let Some(detections) = frame.get("detections").and_then(Value::as_array) {
+            let (tracked, untracked) = split_by_track(detections);
+            self.tracked = tracked;
+            self.untracked = untracked;
+            self.seq = frame.pointer("/detections_sync/seq").and_then(Value::as_u64);
+            return true;
+        }
+        let Some(delta) = frame.get("detections_delta") else { return true };
+        let seq = delta.get("seq").and_then(Value::as_u64);
+        if self.seq.is_none() || seq != self.seq.map(|s| s + 1) {
+            self.seq = None;
+            return false;
+        }
+        self.seq = seq;
+        for id in delta["removed"].as_array().into_iter().flatten().filter_map(Value::as_u64) {
+            self.tracked.remove(&id);
+        }
+        for detection in delta["added"].as_array().into_iter().flatten() {
+            if let (Some(id), Some(fields)) = (track_id(detection), detection.as_object()) {
+                self.tracked.insert(id, fields.clone());
+            }
+        }
+        for change in delta["updated"].as_array().into_iter().flatten() {
+            let (Some(id), Some(changed)) = (track_id(change), change.as_object()) else { continue };
+            if let Some(fields) = self.tracked.get_mut(&id) {
+                fields.extend(changed.iter().map(|(key, value)| (key.clone(), value.clone())));
+            }
+        }
+        if let Some(untracked) = delta.get("untracked").and_then(Value::as_array) {
+            self.untracked = untracked.clone();
+        }
+        true
+    }
+
+    // Tracked detections in track id order, then the untracked ones.
+    pub fn get_detections(&self) -> Vec<Value> {
+        self.tracked.values().cloned().map(Value::Object).chain(self.untracked.iter().cloned()).collect()
+    }
+}
+
+fn track_id(detection: &Value) -> Option<u64> {
+    detection.get("track_id").and_then(Value::as_u64)
+}
+
+// A detection repeating a track id already seen in the frame is kept
+// with the untracked ones rather than lost.
+fn split_by_track(detections: &[Value]) -> (BTreeMap<u64, Fields>, Vec<Value>) {
+    let mut tracked = BTreeMap::new();
+    let mut untracked = Vec::new();
+    for detection in detections {
+        match (track_id(detection), detection.as_object()) {
+            (Some(id), Some(fields)) if !tracked.contains_key(&id) => {
+                tracked.insert(id, fields.clone());
+            }
+            _ => untracked.push(detection.clone()),
+        }
+    }
+    (tracked, untracked)
+}
@@ -0,0 +1,105 @@
+// src/diagnostics.rs
+use std::path::Path;
+#[cfg(feature = "rover-hw")]
+use std::process::Command;
+
+use crate::config::ConfigStore;
+use crate::vision::BRIDGE_SCRIPT;
+
+pub struct CheckResult {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self { name, ok: true, detail: detail.into() }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Self { name, ok: false, detail: detail.into() }
+    }
+}
+
+// Everything `rover check` probes, in the order the stack needs it. The
+// camera and GPIO are only there to probe in a rover-hw build.
+pub fn run_checks(config: &ConfigStore) -> Vec<CheckResult> {
+    let mut results = vec![check_config(config), check_bridge_script(config)];
+    results.extend(hardware_checks());
+    results
+}
+
+#[cfg(feature = "rover-hw")]
+fn hardware_checks() -> Vec<CheckResult> {
+    vec![check_python(), check_camera(), check_gpio()]
+}
+
+#[cfg(not(feature = "rover-hw"))]
+fn hardware_checks() -> Vec<CheckResult> {
+    Vec::new()
+}
+
+pub fn print_report(results: &[CheckResult]) {
+    println!(" Scout diagnostic report");
+    for result in results {
+        let mark = if result.ok { "PASS" } else { "FAIL" };
+        println!("  [{}] {:<14} {}", mark, result.name, result.detail);
+    }
+}
+
+fn check_config(config: &ConfigStore) -> CheckResult {
+    match config.get().validate() {
+        Ok(()) => CheckResult::pass("config", format!("{} is valid", config.get_path().display())),
+        Err(errors) => {
+            let details: Vec<String> = errors.iter().map(|e| format!("{}: {}", e.field, e.message)).collect();
+            CheckResult::fail("config", details.join(", "))
+        }
+    }
+}
+
+fn check_bridge_script(config: &ConfigStore) -> CheckResult {
+    let command = config.get().vision.bridge_command;
+    if !command.iter().any(|arg| arg == BRIDGE_SCRIPT) {
+        return CheckResult::pass("bridge script", format!("custom bridge: {}", command.join(" ")));
+    }
+    if Path::new(BRIDGE_SCRIPT).is_file() {
+        CheckResult::pass("bridge script", format!("{} found", BRIDGE_SCRIPT))
+    } else {
+        CheckResult::fail("bridge script", format!("{} not found (run from the repository root)", BRIDGE_SCRIPT))
+    }
+}
+
+#[cfg(feature = "rover-hw")]
+fn check_python() -> CheckResult {
+    match Command::new("python3").args(["-c", "import picamera2"]).output() {
+        Ok(output) if output.status.success() => CheckResult::pass("python", "python3 with picamera2"),
+        Ok(_) => CheckResult::fail("python", "python3 found but picamera2 is not importable"),
+        Err(e) => CheckResult::fail("python", format!("python3 not runnable: {}", e)),
+    }
+}
+
+#[cfg(feature = "rover-hw")]
+fn check_camera() -> CheckResult {
+    for tool in ["rpicam-hello", "libcamera-hello"] {
+        let Ok(output) = Command::new(tool).arg("--list-cameras").output() else { continue };
+        let listing = String::from_utf8_lossy(&output.stdout);
+        return if listing.contains("imx500") {
+            CheckResult::pass("camera", "IMX500 detected")
+        } else if listing.lines().any(|l| l.trim_start().starts_with("0 :")) {
+            CheckResult::fail("camera", "a camera is connected but it is not an IMX500")
+        } else {
+            CheckResult::fail("camera", format!("{} lists no cameras", tool))
+        };
+    }
+    CheckResult::fail("camera", "neither rpicam-hello nor libcamera-hello is installed")
+}
+
+#[cfg(feature = "rover-hw")]
+fn check_gpio() -> CheckResult {
+    let device = "/dev/gpiomem";
+    match std::fs::OpenOptions::new().read(true).write(true).open(device) {
+        Ok(_) => CheckResult::pass("gpio", format!("{} is accessible", device)),
+        Err(e) => CheckResult::fail("gpio", format!("{}: {} (is the user in the gpio group?)", device, e)),
+    }
+}
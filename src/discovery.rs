@@ -0,0 +1,37 @@
+// src/discovery.rs
+use anyhow::Result;
+
+use crate::config::IdentityConfig;
+
+pub const SERVICE_TYPE: &str = "_scout-rover._tcp.local.";
+
+// Held for as long as the announcement should stay up.
+pub struct Announcement {
+    #[cfg(feature = "mdns")]
+    _daemon: mdns_sd::ServiceDaemon,
+}
+
+#[cfg(feature = "mdns")]
+pub fn announce(identity: &IdentityConfig, port: u16, base_path: &str) -> Result<Announcement> {
+    use mdns_sd::{ServiceDaemon, ServiceInfo};
+
+    let daemon = ServiceDaemon::new()?;
+    let host_name = format!("{}.local.", identity.rover_id);
+    let properties = [
+        ("rover_id", identity.rover_id.as_str()),
+        ("rover_name", identity.rover_name.as_str()),
+        ("base_path", base_path),
+    ];
+    let info = ServiceInfo::new(SERVICE_TYPE, &identity.rover_id, &host_name, "", port, &properties[..])?
+        .enable_addr_auto();
+    daemon.register(info)?;
+
+    tracing::info!(target: "web", "Announcing {} as {} on port {}", identity.rover_id, SERVICE_TYPE, port);
+    Ok(Announcement { _daemon: daemon })
+}
+
+#[cfg(not(feature = "mdns"))]
+pub fn announce(identity: &IdentityConfig, _port: u16, _base_path: &str) -> Result<Announcement> {
+    tracing::info!(target: "web", "mDNS disabled (built without `mdns`), {} is not announced", identity.rover_id);
+    Ok(Announcement {})
+}
@@ -0,0 +1,76 @@
+// src/display.rs
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::config::{ClassDisplay, DisplayConfig, DistanceUnits, LabelDetail};
+use crate::vision::LabelInfo;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClassStyle {
+    pub color: String,
+    pub visible: bool,
+}
+
+// The display section with classes resolved to a colour, as sent to
+// dashboards.
+#[derive(Debug, Clone, Serialize)]
+pub struct DisplayPreferences {
+    pub classes: BTreeMap<String, ClassStyle>,
+    pub label_detail: LabelDetail,
+    pub units: DistanceUnits,
+}
+
+impl DisplayPreferences {
+    // Styles for the classes named. A class's own entry wins over its
+    // label map colour.
+    pub fn resolve<'a>(
+        config: &DisplayConfig,
+        labels: &BTreeMap<u32, LabelInfo>,
+        classes: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        let label_colors: BTreeMap<&str, &str> = labels.values()
+            .filter_map(|label| Some((label.name.as_str(), label.color.as_deref()?)))
+            .collect();
+
+        let default = ClassDisplay::default();
+        let styles = classes.into_iter()
+            .map(|name| {
+                let display = config.classes.get(name).unwrap_or(&default);
+                let color = display.color.clone()
+                    .or_else(|| label_colors.get(name).map(|c| c.to_string()))
+                    .unwrap_or_else(|| class_color(name));
+                (name.to_string(), ClassStyle { color, visible: display.visible })
+            })
+            .collect();
+
+        Self {
+            classes: styles,
+            label_detail: config.label_detail,
+            units: config.units,
+        }
+    }
+}
+
+// A colour of its own for every class name, the same on every rover and
+// across restarts: the name's FNV-1a hash picks the hue.
+pub fn class_color(name: &str) -> String {
+    let hash = name.bytes().fold(0x811c_9dc5u32, |hash, b| (hash ^ b as u32).wrapping_mul(0x0100_0193));
+    let (r, g, b) = hsl_to_rgb((hash % 360) as f32, 0.7, 0.55);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> (u8, u8, u8) {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let channel = |v: f32| ((v + m) * 255.0).round() as u8;
+    (channel(r), channel(g), channel(b))
+}
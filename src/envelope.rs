@@ -0,0 +1,101 @@
+// src/envelope.rs
+//
+// How fast the rover may drive given what the map already knows about the
+// way ahead: slower past cells something was seen in, even once it is out
+// of view, and slower into floor the rover has never been over.
+use serde::{Deserialize, Serialize};
+
+use crate::config::PlannerConfig;
+use crate::mapping::{Cell, OccupancyGrid, RoverPose};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvelopeFactor {
+    // A known-occupied cell near the path.
+    Clearance,
+    // Too much of the path not yet mapped.
+    Unknown,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpeedEnvelope {
+    // None when neither curve limits the speed.
+    pub max_speed: Option<f32>,
+    pub limited_by: Option<EnvelopeFactor>,
+    // From the path to the nearest occupied cell, looked for as far out as
+    // the clearance curve goes.
+    pub clearance_m: Option<f32>,
+    // Of the cells along the path, 0-1; off the map counts as unknown.
+    pub unknown_fraction: f32,
+}
+
+impl SpeedEnvelope {
+    // Along planner.envelope_lookahead_m straight ahead of `pose`.
+    pub fn evaluate(grid: &OccupancyGrid, pose: &RoverPose, config: &PlannerConfig) -> Self {
+        let heading = pose.heading_deg.to_radians();
+        let start = (pose.x, pose.y);
+        let end = (pose.x + config.envelope_lookahead_m * heading.sin(), pose.y + config.envelope_lookahead_m * heading.cos());
+
+        let search_m = config.envelope_clearance_curve.last().map_or(0.0, |p| p.0);
+        let clearance_m = nearest_occupied(grid, start, end, search_m);
+        let unknown_fraction = unknown_fraction(grid, start, end);
+
+        let clearance_limit = interpolate(&config.envelope_clearance_curve, clearance_m.unwrap_or(f32::INFINITY));
+        let unknown_limit = interpolate(&config.envelope_unknown_curve, unknown_fraction);
+        let (max_speed, limited_by) = match (clearance_limit, unknown_limit) {
+            (Some(c), Some(u)) if u < c => (Some(u), Some(EnvelopeFactor::Unknown)),
+            (Some(c), _) => (Some(c), Some(EnvelopeFactor::Clearance)),
+            (None, Some(u)) => (Some(u), Some(EnvelopeFactor::Unknown)),
+            (None, None) => (None, None),
+        };
+        Self { max_speed, limited_by, clearance_m, unknown_fraction }
+    }
+}
+
+// Linear between (x, y) points in increasing x, flat beyond the ends.
+fn interpolate(points: &[(f32, f32)], x: f32) -> Option<f32> {
+    let (first, last) = (points.first()?, points.last()?);
+    if x <= first.0 {
+        return Some(first.1);
+    }
+    if x >= last.0 {
+        return Some(last.1);
+    }
+    let i = points.partition_point(|p| p.0 <= x);
+    let ((x0, y0), (x1, y1)) = (points[i - 1], points[i]);
+    Some(y0 + (y1 - y0) * (x - x0) / (x1 - x0))
+}
+
+// Within `search_m` of the segment from `start` to `end`, measured to the
+// cell's middle.
+fn nearest_occupied(grid: &OccupancyGrid, start: (f32, f32), end: (f32, f32), search_m: f32) -> Option<f32> {
+    let low = (start.0.min(end.0) - search_m, start.1.min(end.1) - search_m);
+    let high = (start.0.max(end.0) + search_m, start.1.max(end.1) + search_m);
+    let (cols, rows) = grid.cells_in(low, high);
+    rows.flat_map(|row| cols.clone().map(move |col| (col, row)))
+        .filter(|&(col, row)| grid.get(col, row) == Cell::Occupied)
+        .map(|(col, row)| distance_to_segment(grid.cell_center(col, row), start, end))
+        .filter(|&d| d <= search_m)
+        .min_by(f32::total_cmp)
+}
+
+// Sampled a cell apart from just past the rover's own cell to `end`.
+fn unknown_fraction(grid: &OccupancyGrid, start: (f32, f32), end: (f32, f32)) -> f32 {
+    let length = (end.0 - start.0).hypot(end.1 - start.1);
+    let samples = ((length / grid.get_resolution_m()).ceil() as usize).max(1);
+    let unknown = (1..=samples)
+        .map(|i| {
+            let t = i as f32 / samples as f32;
+            (start.0 + (end.0 - start.0) * t, start.1 + (end.1 - start.1) * t)
+        })
+        .filter(|&(x, y)| grid.world_to_cell(x, y).map_or(true, |(col, row)| grid.get(col, row) == Cell::Unknown))
+        .count();
+    unknown as f32 / samples as f32
+}
+
+fn distance_to_segment(point: (f32, f32), start: (f32, f32), end: (f32, f32)) -> f32 {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length_sq = dx * dx + dy * dy;
+    let t = if length_sq > 0.0 { (((point.0 - start.0) * dx + (point.1 - start.1) * dy) / length_sq).clamp(0.0, 1.0) } else { 0.0 };
+    (point.0 - start.0 - t * dx).hypot(point.1 - start.1 - t * dy)
+}
@@ -0,0 +1,85 @@
+// src/events.rs
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+
+use crate::clock::CLOCK;
+
+const RETAINED_EVENTS: usize = 200;
+
+pub static EVENTS: Lazy<EventBus> = Lazy::new(EventBus::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoverEvent {
+    pub id: u64,
+    pub severity: Severity,
+    pub source: &'static str,
+    pub message: String,
+    pub timestamp: u64,
+    pub data: serde_json::Value,
+}
+
+pub struct EventBus {
+    tx: broadcast::Sender<RoverEvent>,
+    recent: Mutex<VecDeque<RoverEvent>>,
+    next_id: AtomicU64,
+}
+
+impl EventBus {
+    fn new() -> Self {
+        let (tx, _) = broadcast::channel(256);
+        Self {
+            tx,
+            recent: Mutex::new(VecDeque::with_capacity(RETAINED_EVENTS)),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn publish(&self, severity: Severity, source: &'static str, message: impl Into<String>, data: serde_json::Value) {
+        let event = RoverEvent {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            severity,
+            source,
+            message: message.into(),
+            timestamp: CLOCK.wall_ms(),
+            data,
+        };
+
+        match event.severity {
+            Severity::Info => info!(target: "events", source = event.source, "{}", event.message),
+            Severity::Warning => warn!(target: "events", source = event.source, "{}", event.message),
+            Severity::Critical => error!(target: "events", source = event.source, "{}", event.message),
+        }
+
+        {
+            let mut recent = self.recent.lock();
+            if recent.len() == RETAINED_EVENTS {
+                recent.pop_front();
+            }
+            recent.push_back(event.clone());
+        }
+
+        // No receivers just means nobody is listening yet.
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<RoverEvent> {
+        self.tx.subscribe()
+    }
+
+    pub fn recent(&self) -> Vec<RoverEvent> {
+        self.recent.lock().iter().cloned().collect()
+    }
+}
@@ -0,0 +1,246 @@
+// src/gps.rs
+use anyhow::{Context, Result};
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::io::BufRead;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::GpsConfig;
+use crate::pathfinding::PathPlanner;
+
+// WGS84.
+const EARTH_A: f64 = 6_378_137.0;
+const EARTH_E2: f64 = 6.694_379_990_14e-3;
+// Receivers report once a second; a replay plays one fix per this.
+const REPLAY_INTERVAL: Duration = Duration::from_secs(1);
+#[cfg(feature = "gps")]
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// GGA field 6.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FixQuality {
+    NoFix,
+    Gps,
+    Dgps,
+    Pps,
+    RtkFixed,
+    RtkFloat,
+    // Dead reckoning inside the receiver, not a satellite fix.
+    Estimated,
+    Manual,
+    Simulated,
+}
+
+impl FixQuality {
+    fn from_gga(value: u32) -> Self {
+        match value {
+            1 => FixQuality::Gps,
+            2 => FixQuality::Dgps,
+            3 => FixQuality::Pps,
+            4 => FixQuality::RtkFixed,
+            5 => FixQuality::RtkFloat,
+            6 => FixQuality::Estimated,
+            7 => FixQuality::Manual,
+            8 => FixQuality::Simulated,
+            _ => FixQuality::NoFix,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FixQuality::NoFix => "no_fix",
+            FixQuality::Gps => "gps",
+            FixQuality::Dgps => "dgps",
+            FixQuality::Pps => "pps",
+            FixQuality::RtkFixed => "rtk_fixed",
+            FixQuality::RtkFloat => "rtk_float",
+            FixQuality::Estimated => "estimated",
+            FixQuality::Manual => "manual",
+            FixQuality::Simulated => "simulated",
+        }
+    }
+
+    // Whether the position comes from satellites at all.
+    pub fn is_fix(&self) -> bool {
+        matches!(self, FixQuality::Gps | FixQuality::Dgps | FixQuality::Pps | FixQuality::RtkFixed | FixQuality::RtkFloat)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct GpsFix {
+    // Degrees, north and east positive. Absent without a fix.
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    pub quality: FixQuality,
+    pub satellites: u32,
+    // Horizontal dilution of precision; lower is better, under 2 is good.
+    pub hdop: f32,
+}
+
+// Parses a GGA sentence from any talker ($GPGGA, $GNGGA, ...). Anything
+// else, or a sentence with a bad checksum, is None.
+pub fn parse_gga(line: &str) -> Option<GpsFix> {
+    let line = line.trim().strip_prefix('$')?;
+    let (body, checksum) = match line.split_once('*') {
+        Some((body, checksum)) => (body, Some(u8::from_str_radix(checksum, 16).ok()?)),
+        None => (line, None),
+    };
+    if checksum.is_some_and(|c| c != body.bytes().fold(0, |acc, b| acc ^ b)) {
+        return None;
+    }
+    let fields: Vec<&str> = body.split(',').collect();
+    if fields.len() < 9 || fields[0].len() != 5 || !fields[0].ends_with("GGA") {
+        return None;
+    }
+    let quality = FixQuality::from_gga(fields[6].parse().unwrap_or(0));
+    let position = if quality == FixQuality::NoFix {
+        None
+    } else {
+        Some((coordinate(fields[2], fields[3], 2)?, coordinate(fields[4], fields[5], 3)?))
+    };
+    Some(GpsFix {
+        lat: position.map(|p| p.0),
+        lon: position.map(|p| p.1),
+        quality,
+        satellites: fields[7].parse().unwrap_or(0),
+        hdop: fields[8].parse().unwrap_or(f32::INFINITY),
+    })
+}
+
+// NMEA writes degrees and minutes run together, e.g. 4807.038 for
+// 48°07.038', with `degree_digits` digits of degrees.
+fn coordinate(value: &str, hemisphere: &str, degree_digits: usize) -> Option<f64> {
+    if value.len() < degree_digits {
+        return None;
+    }
+    let degrees: f64 = value[..degree_digits].parse().ok()?;
+    let minutes: f64 = value[degree_digits..].parse().ok()?;
+    let magnitude = degrees + minutes / 60.0;
+    match hemisphere {
+        "N" | "E" => Some(magnitude),
+        "S" | "W" => Some(-magnitude),
+        _ => None,
+    }
+}
+
+// A local tangent plane pinned to the map: `origin` is at map position
+// `offset`, and the map's +y axis points `heading_deg` clockwise from
+// north. Flat-earth, so good for a few kilometres around the origin.
+#[derive(Debug, Clone, Copy)]
+pub struct LocalFrame {
+    origin: (f64, f64),
+    offset: (f32, f32),
+    heading_rad: f64,
+    // Metres per radian of latitude and of longitude at the origin.
+    north_scale: f64,
+    east_scale: f64,
+}
+
+impl LocalFrame {
+    pub fn new(origin_lat: f64, origin_lon: f64, offset: (f32, f32), heading_deg: f32) -> Self {
+        let lat = origin_lat.to_radians();
+        let w = 1.0 - EARTH_E2 * lat.sin().powi(2);
+        Self {
+            origin: (origin_lat, origin_lon),
+            offset,
+            heading_rad: (heading_deg as f64).to_radians(),
+            north_scale: EARTH_A * (1.0 - EARTH_E2) / w.powf(1.5),
+            east_scale: EARTH_A / w.sqrt() * lat.cos(),
+        }
+    }
+
+    pub fn to_map(&self, lat: f64, lon: f64) -> (f32, f32) {
+        let north = (lat - self.origin.0).to_radians() * self.north_scale;
+        let east = (lon - self.origin.1).to_radians() * self.east_scale;
+        let (sin, cos) = self.heading_rad.sin_cos();
+        (
+            self.offset.0 + (east * cos - north * sin) as f32,
+            self.offset.1 + (east * sin + north * cos) as f32,
+        )
+    }
+
+    pub fn to_geodetic(&self, x: f32, y: f32) -> (f64, f64) {
+        let (dx, dy) = ((x - self.offset.0) as f64, (y - self.offset.1) as f64);
+        let (sin, cos) = self.heading_rad.sin_cos();
+        let east = dx * cos + dy * sin;
+        let north = dy * cos - dx * sin;
+        (
+            self.origin.0 + (north / self.north_scale).to_degrees(),
+            self.origin.1 + (east / self.east_scale).to_degrees(),
+        )
+    }
+}
+
+// Feeds fixes into the planner from gps.replay_file if it is set, or
+// otherwise from the receiver at gps.device. Runs on its own thread and
+// never returns an error: a receiver that goes away shows up as a stale
+// fix, and the planner falls back to odometry.
+pub fn spawn(config: &GpsConfig, planner: Arc<RwLock<PathPlanner>>) {
+    if !config.enabled {
+        return;
+    }
+    let config = config.clone();
+    std::thread::spawn(move || match &config.replay_file {
+        Some(path) => {
+            if let Err(e) = replay(path, &planner) {
+                warn!(target: "gps", "GPS replay stopped: {:#}", e);
+            }
+        }
+        None => read_device(&config, &planner),
+    });
+}
+
+fn replay(path: &Path, planner: &RwLock<PathPlanner>) -> Result<()> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open GPS replay {}", path.display()))?;
+    info!(target: "gps", "Replaying GPS fixes from {}", path.display());
+    for line in std::io::BufReader::new(file).lines() {
+        if let Some(fix) = parse_gga(&line?) {
+            planner.write().update_gps(fix);
+            std::thread::sleep(REPLAY_INTERVAL);
+        }
+    }
+    info!(target: "gps", "GPS replay finished");
+    Ok(())
+}
+
+#[cfg(feature = "gps")]
+fn read_device(config: &GpsConfig, planner: &RwLock<PathPlanner>) {
+    loop {
+        let port = serialport::new(&config.device, config.baud_rate)
+            .timeout(Duration::from_secs(2))
+            .open();
+        match port {
+            Ok(port) => {
+                info!(target: "gps", "Reading GPS from {}", config.device);
+                for line in std::io::BufReader::new(port).lines() {
+                    match line {
+                        Ok(line) => {
+                            if let Some(fix) = parse_gga(&line) {
+                                planner.write().update_gps(fix);
+                            }
+                        }
+                        // Timeouts just mean a quiet second; anything else
+                        // means the port is gone.
+                        Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+                        Err(e) => {
+                            warn!(target: "gps", "GPS read failed: {}", e);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => warn!(target: "gps", "Failed to open {}: {}", config.device, e),
+        }
+        std::thread::sleep(RECONNECT_DELAY);
+    }
+}
+
+#[cfg(not(feature = "gps"))]
+fn read_device(_config: &GpsConfig, _planner: &RwLock<PathPlanner>) {
+    warn!(target: "gps", "gps.enabled is set but the binary was built without the `gps` feature; only gps.replay_file works");
+}
@@ -0,0 +1,355 @@
+// src/hazards.rs
+//
+// Hazards from detectors outside the camera and range sensors: a
+// microphone's classifier hearing a dog bark behind the rover, say. They
+// come in through POST /api/hazard or an ExternalHazardSource polled each
+// tick, and each lasts its ttl_s and then expires on its own.
+//
+// How they weigh against everything else, in autonomous mode only:
+// - a vision emergency stop still wins, and no hazard emergency-stops or
+//   faults the rover;
+// - a range sensor, a stale plan, a vision stop and a stop hazard ahead
+//   each stop the rover;
+// - a stop hazard beyond hazards.stop_arc_deg either side of straight
+//   ahead, and any caution hazard, hold driving forward to
+//   hazards.caution_speed_m_s, leaving turns and reversing alone;
+// - a clear view doesn't clear a hazard; only its ttl running out does.
+// In manual mode they are shown, and the operator decides.
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::mpsc;
+use tracing::{debug, info};
+
+use crate::clock::CLOCK;
+use crate::config::HazardsConfig;
+use crate::events::{Severity, EVENTS};
+use crate::metrics::METRICS;
+
+pub static HAZARDS: Lazy<HazardBoard> = Lazy::new(HazardBoard::new);
+
+// Longest source or type name.
+const MAX_NAME_LEN: usize = 32;
+
+// Ordered from least to most cautious.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HazardAction {
+    #[default]
+    Caution,
+    Stop,
+}
+
+impl HazardAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HazardAction::Caution => "caution",
+            HazardAction::Stop => "stop",
+        }
+    }
+}
+
+// As POSTed to /api/hazard: {"source": "mic", "type": "dog_bark",
+// "action": "caution", "bearing_deg": 180, "ttl_s": 5}. The bearing is
+// from straight ahead, anticlockwise, and left out when the detector
+// can't tell; the action is caution unless given.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HazardReport {
+    pub source: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub action: HazardAction,
+    #[serde(default)]
+    pub bearing_deg: Option<f32>,
+    pub ttl_s: f32,
+}
+
+// A detector inside the process. Polled every tick, so poll() must not
+// block; its reports are rate limited and checked like posted ones.
+pub trait ExternalHazardSource: Send {
+    fn poll(&mut self) -> Vec<HazardReport>;
+}
+
+// For a detector on a thread of its own, which keeps the sender.
+impl ExternalHazardSource for mpsc::Receiver<HazardReport> {
+    fn poll(&mut self) -> Vec<HazardReport> {
+        self.try_iter().collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HazardError {
+    Disabled,
+    Invalid(String),
+    // The source is over hazards.rate_per_s; it can report again in this
+    // many seconds.
+    RateLimited(f32),
+    // hazards.max_active hazards are already active.
+    Full,
+}
+
+impl HazardError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HazardError::Disabled => "disabled",
+            HazardError::Invalid(_) => "invalid",
+            HazardError::RateLimited(_) => "rate_limited",
+            HazardError::Full => "full",
+        }
+    }
+}
+
+impl fmt::Display for HazardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HazardError::Disabled => write!(f, "external hazards are disabled"),
+            HazardError::Invalid(message) => write!(f, "{}", message),
+            HazardError::RateLimited(retry_s) => write!(f, "too many reports from this source, retry in {:.1} s", retry_s),
+            HazardError::Full => write!(f, "too many active hazards"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportOutcome {
+    Accepted,
+    // The source already had a hazard of that type; it now lasts from
+    // this report.
+    Refreshed,
+}
+
+impl ReportOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportOutcome::Accepted => "accepted",
+            ReportOutcome::Refreshed => "refreshed",
+        }
+    }
+}
+
+// A hazard not yet expired, as served and shown on the dashboard.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActiveHazard {
+    pub source: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub action: HazardAction,
+    // What it does to the rover: a stop hazard off to the side only
+    // cautions.
+    pub effect: HazardAction,
+    pub bearing_deg: Option<f32>,
+    pub remaining_s: f32,
+    // Wall-clock ms of the first report.
+    pub since_ms: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HazardAssessment {
+    // The most cautious effect of any active hazard.
+    pub effect: Option<HazardAction>,
+    pub hazards: Vec<ActiveHazard>,
+}
+
+struct Hazard {
+    report: HazardReport,
+    since_ms: u64,
+    expires_ms: u64,
+}
+
+// Rover-clock ms, so a simulation stepping the clock expires hazards on
+// its own time.
+struct Bucket {
+    tokens: f32,
+    at_ms: u64,
+}
+
+struct Board {
+    config: HazardsConfig,
+    hazards: Vec<Hazard>,
+    buckets: HashMap<String, Bucket>,
+    sources: Vec<Box<dyn ExternalHazardSource>>,
+}
+
+pub struct HazardBoard {
+    board: Mutex<Board>,
+}
+
+impl HazardBoard {
+    fn new() -> Self {
+        Self {
+            board: Mutex::new(Board {
+                config: HazardsConfig::default(),
+                hazards: Vec::new(),
+                buckets: HashMap::new(),
+                sources: Vec::new(),
+            }),
+        }
+    }
+
+    // Called once at startup. Forgets any hazards, sources and rate limits
+    // from before.
+    pub fn configure(&self, config: &HazardsConfig) {
+        let mut board = self.board.lock();
+        board.config = config.clone();
+        board.hazards.clear();
+        board.buckets.clear();
+        board.sources.clear();
+        METRICS.hazards_active.set(0);
+    }
+
+    pub fn add_source(&self, source: Box<dyn ExternalHazardSource>) {
+        self.board.lock().sources.push(source);
+    }
+
+    pub fn report(&self, report: HazardReport) -> Result<ReportOutcome, HazardError> {
+        let result = self.board.lock().report(report, CLOCK.now_ms());
+        METRICS.hazard_reports.inc(match &result {
+            Ok(outcome) => outcome.as_str(),
+            Err(e) => e.as_str(),
+        });
+        result
+    }
+
+    // Once a tick: takes the sources' reports, drops what has expired and
+    // says what is left.
+    pub fn assess(&self) -> HazardAssessment {
+        let reports: Vec<HazardReport> = {
+            let mut board = self.board.lock();
+            board.sources.iter_mut().flat_map(|source| source.poll()).collect()
+        };
+        for report in reports {
+            let source = report.source.clone();
+            if let Err(e) = self.report(report) {
+                debug!(target: "hazards", "Report from {} refused: {}", source, e);
+            }
+        }
+
+        let now_ms = CLOCK.now_ms();
+        let mut board = self.board.lock();
+        board.hazards.retain(|hazard| {
+            let live = hazard.expires_ms > now_ms;
+            if !live {
+                info!(target: "hazards", "{} hazard from {} expired", hazard.report.kind, hazard.report.source);
+            }
+            live
+        });
+        METRICS.hazards_active.set(board.hazards.len() as i64);
+        let hazards = board.get_active(now_ms);
+        HazardAssessment { effect: hazards.iter().map(|h| h.effect).max(), hazards }
+    }
+
+    pub fn get_active(&self) -> Vec<ActiveHazard> {
+        self.board.lock().get_active(CLOCK.now_ms())
+    }
+}
+
+impl Board {
+    fn report(&mut self, mut report: HazardReport, now_ms: u64) -> Result<ReportOutcome, HazardError> {
+        if !self.config.enabled {
+            return Err(HazardError::Disabled);
+        }
+        check_name("source", &report.source)?;
+        self.take_token(&report.source, now_ms)?;
+        check_name("type", &report.kind)?;
+        if !(report.ttl_s.is_finite() && report.ttl_s > 0.0 && report.ttl_s <= self.config.max_ttl_s) {
+            return Err(HazardError::Invalid(format!("ttl_s must be above 0 and at most {}", self.config.max_ttl_s)));
+        }
+        if let Some(bearing) = report.bearing_deg {
+            if !bearing.is_finite() {
+                return Err(HazardError::Invalid("bearing_deg must be a number".to_string()));
+            }
+            report.bearing_deg = Some(normalize_deg(bearing));
+        }
+
+        let expires_ms = now_ms + (report.ttl_s * 1000.0) as u64;
+        self.hazards.retain(|hazard| hazard.expires_ms > now_ms);
+        if let Some(hazard) = self.hazards.iter_mut().find(|h| h.report.source == report.source && h.report.kind == report.kind) {
+            hazard.report = report;
+            hazard.expires_ms = expires_ms;
+            return Ok(ReportOutcome::Refreshed);
+        }
+        if self.hazards.len() >= self.config.max_active {
+            return Err(HazardError::Full);
+        }
+
+        let severity = match report.action {
+            HazardAction::Stop => Severity::Warning,
+            HazardAction::Caution => Severity::Info,
+        };
+        EVENTS.publish(severity, "hazards", format!("{} hazard from {}: {}", report.action.as_str(), report.source, report.kind), serde_json::json!({
+            "source": report.source,
+            "type": report.kind,
+            "action": report.action,
+            "effect": effect(&self.config, &report),
+            "bearing_deg": report.bearing_deg,
+            "ttl_s": report.ttl_s,
+        }));
+        self.hazards.push(Hazard { report, since_ms: now_ms, expires_ms });
+        METRICS.hazards_active.set(self.hazards.len() as i64);
+        Ok(ReportOutcome::Accepted)
+    }
+
+    // rate_per_s a second from each source, up to burst at once. Sources
+    // whose buckets have filled back up are forgotten.
+    fn take_token(&mut self, source: &str, now_ms: u64) -> Result<(), HazardError> {
+        let (rate, burst) = (self.config.rate_per_s, self.config.burst as f32);
+        let refill = |bucket: &Bucket| (bucket.tokens + now_ms.saturating_sub(bucket.at_ms) as f32 / 1000.0 * rate).min(burst);
+        if !self.buckets.contains_key(source) {
+            self.buckets.retain(|_, bucket| refill(bucket) < burst);
+        }
+        let bucket = self.buckets.entry(source.to_string()).or_insert(Bucket { tokens: burst, at_ms: now_ms });
+        bucket.tokens = refill(bucket);
+        bucket.at_ms = now_ms;
+        if bucket.tokens < 1.0 {
+            return Err(HazardError::RateLimited((1.0 - bucket.tokens) / rate));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    fn get_active(&self, now_ms: u64) -> Vec<ActiveHazard> {
+        self.hazards
+            .iter()
+            .filter(|hazard| hazard.expires_ms > now_ms)
+            .map(|hazard| ActiveHazard {
+                source: hazard.report.source.clone(),
+                kind: hazard.report.kind.clone(),
+                action: hazard.report.action,
+                effect: effect(&self.config, &hazard.report),
+                bearing_deg: hazard.report.bearing_deg,
+                remaining_s: (hazard.expires_ms - now_ms) as f32 / 1000.0,
+                since_ms: CLOCK.to_wall_ms(hazard.since_ms),
+            })
+            .collect()
+    }
+}
+
+// A stop hazard only stops the rover if it is ahead, or could be.
+fn effect(config: &HazardsConfig, report: &HazardReport) -> HazardAction {
+    match (report.action, report.bearing_deg) {
+        (HazardAction::Stop, Some(bearing)) if bearing.abs() > config.stop_arc_deg => HazardAction::Caution,
+        (action, _) => action,
+    }
+}
+
+// Into -180 to 180.
+fn normalize_deg(deg: f32) -> f32 {
+    let wrapped = (deg + 180.0).rem_euclid(360.0) - 180.0;
+    if wrapped == -180.0 { 180.0 } else { wrapped }
+}
+
+fn check_name(field: &str, name: &str) -> Result<(), HazardError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(HazardError::Invalid(format!("{} must be 1-{} letters, digits, _, - or .", field, MAX_NAME_LEN)))
+    }
+}
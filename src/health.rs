@@ -0,0 +1,155 @@
+// src/health.rs
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentState {
+    Starting,
+    Healthy,
+    Stale,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentHealth {
+    pub name: &'static str,
+    pub state: ComponentState,
+    pub last_update_age_ms: Option<u64>,
+    pub stale_after_ms: Option<u64>,
+    pub updates: u64,
+    pub errors: u64,
+    pub last_error: Option<String>,
+    pub detail: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub healthy: bool,
+    pub ready: bool,
+    pub uptime_s: f32,
+    pub components: Vec<ComponentHealth>,
+}
+
+struct ComponentEntry {
+    stale_after: Option<Duration>,
+    last_update: Option<Instant>,
+    updates: u64,
+    errors: u64,
+    last_error: Option<String>,
+    failed: bool,
+    detail: Option<serde_json::Value>,
+}
+
+// Each subsystem reports through its own handle, so a component stuck while
+// holding its RwLock simply stops beating and shows up as stale here.
+#[derive(Clone)]
+pub struct HealthHandle {
+    name: &'static str,
+    entry: Arc<Mutex<ComponentEntry>>,
+}
+
+impl HealthHandle {
+    pub fn beat(&self) {
+        let mut entry = self.entry.lock();
+        entry.last_update = Some(Instant::now());
+        entry.updates += 1;
+        entry.failed = false;
+    }
+
+    pub fn beat_with<T: Serialize>(&self, detail: T) {
+        let detail = serde_json::to_value(detail).ok();
+        let mut entry = self.entry.lock();
+        entry.last_update = Some(Instant::now());
+        entry.updates += 1;
+        entry.failed = false;
+        entry.detail = detail;
+    }
+
+    pub fn error(&self, message: impl Into<String>) {
+        let mut entry = self.entry.lock();
+        entry.errors += 1;
+        entry.last_error = Some(message.into());
+    }
+
+    pub fn fail(&self, message: impl Into<String>) {
+        let mut entry = self.entry.lock();
+        entry.errors += 1;
+        entry.last_error = Some(message.into());
+        entry.failed = true;
+    }
+}
+
+pub struct HealthRegistry {
+    components: RwLock<Vec<HealthHandle>>,
+    started: Instant,
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        Self {
+            components: RwLock::new(Vec::new()),
+            started: Instant::now(),
+        }
+    }
+
+    // `stale_after` of None means the component only gates readiness and
+    // never goes stale (e.g. the web server once it is bound).
+    pub fn register(&self, name: &'static str, stale_after: Option<Duration>) -> HealthHandle {
+        let handle = HealthHandle {
+            name,
+            entry: Arc::new(Mutex::new(ComponentEntry {
+                stale_after,
+                last_update: None,
+                updates: 0,
+                errors: 0,
+                last_error: None,
+                failed: false,
+                detail: None,
+            })),
+        };
+        self.components.write().push(handle.clone());
+        handle
+    }
+
+    pub fn report(&self) -> HealthReport {
+        let now = Instant::now();
+        let components: Vec<ComponentHealth> = self.components.read().iter()
+            .map(|handle| {
+                let entry = handle.entry.lock();
+                let age = entry.last_update.map(|t| now.duration_since(t));
+                let state = match (entry.failed, age, entry.stale_after) {
+                    (true, _, _) => ComponentState::Failed,
+                    (false, None, _) => ComponentState::Starting,
+                    (false, Some(age), Some(limit)) if age > limit => ComponentState::Stale,
+                    _ => ComponentState::Healthy,
+                };
+                ComponentHealth {
+                    name: handle.name,
+                    state,
+                    last_update_age_ms: age.map(|a| a.as_millis() as u64),
+                    stale_after_ms: entry.stale_after.map(|d| d.as_millis() as u64),
+                    updates: entry.updates,
+                    errors: entry.errors,
+                    last_error: entry.last_error.clone(),
+                    detail: entry.detail.clone(),
+                }
+            })
+            .collect();
+
+        HealthReport {
+            healthy: components.iter().all(|c| c.state == ComponentState::Healthy),
+            ready: components.iter().all(|c| c.updates > 0 && c.state != ComponentState::Failed),
+            uptime_s: now.duration_since(self.started).as_secs_f32(),
+            components,
+        }
+    }
+}
+
+impl Default for HealthRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
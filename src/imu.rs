@@ -0,0 +1,170 @@
+// src/imu.rs
+use anyhow::Result;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::{ImuConfig, ImuDriver};
+use crate::events::{Severity, EVENTS};
+
+// An IMU that failed to open is tried again after this.
+const REOPEN_DELAY: Duration = Duration::from_secs(5);
+// While the wheels are still, the gyro's bias is averaged over about this
+// long.
+const BIAS_TIME_CONSTANT_S: f32 = 2.0;
+
+pub trait Imu: Send {
+    // Degrees per second about the vertical axis, clockwise like headings.
+    fn read_yaw_rate(&mut self) -> Result<f32>;
+}
+
+// Reports a fixed turn rate, for running without hardware.
+pub struct MockImu {
+    yaw_rate_dps: f32,
+}
+
+impl MockImu {
+    pub fn new(yaw_rate_dps: f32) -> Self {
+        Self { yaw_rate_dps }
+    }
+}
+
+impl Imu for MockImu {
+    fn read_yaw_rate(&mut self) -> Result<f32> {
+        Ok(self.yaw_rate_dps)
+    }
+}
+
+#[cfg(feature = "imu")]
+mod hardware {
+    use anyhow::Result;
+    use rppal::i2c::I2c;
+
+    use super::Imu;
+
+    const PWR_MGMT_1: u8 = 0x6b;
+    const GYRO_CONFIG: u8 = 0x1b;
+    const GYRO_ZOUT_H: u8 = 0x47;
+    // At the ±250 °/s full scale set in open().
+    const LSB_PER_DPS: f32 = 131.0;
+
+    pub struct Mpu6050 {
+        i2c: I2c,
+    }
+
+    impl Mpu6050 {
+        pub fn open(bus: u8, address: u8) -> Result<Self> {
+            let mut i2c = I2c::with_bus(bus)?;
+            i2c.set_slave_address(address as u16)?;
+            // Out of sleep, on the internal clock.
+            i2c.smbus_write_byte(PWR_MGMT_1, 0)?;
+            i2c.smbus_write_byte(GYRO_CONFIG, 0)?;
+            Ok(Self { i2c })
+        }
+    }
+
+    impl Imu for Mpu6050 {
+        fn read_yaw_rate(&mut self) -> Result<f32> {
+            let mut raw = [0u8; 2];
+            self.i2c.block_read(GYRO_ZOUT_H, &mut raw)?;
+            // The chip counts anticlockwise seen from above.
+            Ok(-(i16::from_be_bytes(raw) as f32) / LSB_PER_DPS)
+        }
+    }
+}
+
+#[cfg(feature = "imu")]
+fn open(config: &ImuConfig) -> Result<Box<dyn Imu>> {
+    Ok(match config.driver {
+        ImuDriver::Mpu6050 => Box::new(hardware::Mpu6050::open(config.i2c_bus, config.i2c_address)?),
+        ImuDriver::Mock => Box::new(MockImu::new(config.mock_yaw_rate_dps)),
+    })
+}
+
+#[cfg(not(feature = "imu"))]
+fn open(config: &ImuConfig) -> Result<Box<dyn Imu>> {
+    match config.driver {
+        ImuDriver::Mock => Ok(Box::new(MockImu::new(config.mock_yaw_rate_dps))),
+        ImuDriver::Mpu6050 => anyhow::bail!("built without the `imu` feature"),
+    }
+}
+
+// The fused heading: the gyro integrated once a control tick, with its
+// bias taken from what it reads while the wheels are still, since the
+// rover can't be turning then. It starts at 0 and only ever means
+// anything relative to itself.
+pub struct HeadingEstimator {
+    config: ImuConfig,
+    imu: Option<Box<dyn Imu>>,
+    opened_at: Option<Instant>,
+    heading_deg: f32,
+    bias_dps: f32,
+    // As last reported in an event, so failures are reported once.
+    failed: bool,
+}
+
+impl HeadingEstimator {
+    pub fn new(config: &ImuConfig) -> Self {
+        Self {
+            config: config.clone(),
+            imu: None,
+            opened_at: None,
+            heading_deg: 0.0,
+            bias_dps: 0.0,
+            failed: false,
+        }
+    }
+
+    // The heading and turn rate, in degrees and degrees/s, once a tick.
+    // None without a working IMU.
+    pub fn update(&mut self, dt: Duration, wheels_still: bool) -> Option<(f32, f32)> {
+        if !self.config.enabled {
+            return None;
+        }
+        if self.imu.is_none() && self.opened_at.map_or(true, |at| at.elapsed() >= REOPEN_DELAY) {
+            self.opened_at = Some(Instant::now());
+            match open(&self.config) {
+                Ok(imu) => {
+                    info!(target: "imu", "IMU opened ({:?} driver)", self.config.driver);
+                    self.imu = Some(imu);
+                }
+                Err(e) => self.report_failure(&format!("{:#}", e.context("Failed to open the IMU"))),
+            }
+        }
+        let reading = self.imu.as_mut()?.read_yaw_rate();
+        let raw = match reading {
+            Ok(rate) if self.config.invert => -rate,
+            Ok(rate) => rate,
+            Err(e) => {
+                // Opened again on a later tick.
+                self.imu = None;
+                self.report_failure(&format!("{:#}", e));
+                return None;
+            }
+        };
+        if self.failed {
+            self.failed = false;
+            info!(target: "imu", "IMU recovered");
+            EVENTS.publish(Severity::Info, "imu", "IMU recovered, heading hold is back", serde_json::Value::Null);
+        }
+
+        let dt = dt.as_secs_f32();
+        if wheels_still {
+            let weight = (dt / BIAS_TIME_CONSTANT_S).min(1.0);
+            self.bias_dps += (raw - self.bias_dps) * weight;
+        }
+        let rate = raw - self.bias_dps;
+        self.heading_deg += rate * dt;
+        Some((self.heading_deg, rate))
+    }
+
+    fn report_failure(&mut self, error: &str) {
+        if self.failed {
+            return;
+        }
+        self.failed = true;
+        warn!(target: "imu", "IMU failed, heading hold is off: {}", error);
+        EVENTS.publish(Severity::Warning, "imu", format!("IMU failed, heading hold is off: {}", error), serde_json::json!({
+            "error": error,
+        }));
+    }
+}
@@ -0,0 +1,114 @@
+#!/usr/bin/env python3
+# src/imx500_fast_detector.py
+#
+# Long-lived detection service behind IMX500YoloV8. It keeps rpicam-vid and
+# the NPU model running and answers each request line on stdin with one
+# JSON line on stdout, carrying the detections of the next frame the
+# camera finishes:
+#   -> {"id": 7}
+#   <- {"id": 7, "status": "success", "detections": [...], "detection_count": 1}
+import json
+import subprocess
+import sys
+import threading
+import time
+
+from vision_bridge import VisionBridge
+
+# Longest a request waits for a fresh frame before it is answered with an error.
+FRAME_WAIT_S = 2.0
+
+
+class FastDetector(VisionBridge):
+    def __init__(self):
+        super().__init__()
+        self.frame_ready = threading.Condition()
+        self.frame_seq = 0
+        self.latest = []
+        self.pending = []
+        self.expected = 0
+
+    def publish(self, detections):
+        with self.frame_ready:
+            self.latest = detections
+            self.frame_seq += 1
+            self.frame_ready.notify_all()
+
+    def read_camera(self, process):
+        for raw in process.stderr:
+            line = raw.decode('utf-8', errors='ignore')
+            if 'Number of objects detected:' in line:
+                self.expected = int(line.rsplit(':', 1)[1].strip() or 0)
+                self.pending = []
+                if self.expected == 0:
+                    self.publish([])
+                continue
+            det = self.parse_detection(line)
+            if det:
+                self.pending.append({
+                    "class_name": det.get("class", ""),
+                    "class_id": det.get("class_id"),
+                    "confidence": det["conf"],
+                    "bbox": [det["x"], det["y"], det["w"], det["h"]],
+                })
+                if len(self.pending) == self.expected:
+                    self.publish(self.pending)
+        self.running = False
+        with self.frame_ready:
+            self.frame_ready.notify_all()
+
+    def wait_for_frame(self, after_seq):
+        deadline = time.monotonic() + FRAME_WAIT_S
+        with self.frame_ready:
+            while self.running and self.frame_seq <= after_seq:
+                remaining = deadline - time.monotonic()
+                if remaining <= 0:
+                    return None
+                self.frame_ready.wait(remaining)
+            return self.latest if self.frame_seq > after_seq else None
+
+    def serve(self):
+        cmd = [
+            'rpicam-vid',
+            '--width', '640',
+            '--height', '480',
+            '--framerate', '30',
+            '--timeout', '0',
+            '--rotation', '180',
+            '--nopreview',
+            '--post-process-file', '/usr/share/rpi-camera-assets/imx500_yolov8.json',
+            '--verbose', '2',
+            '-o', '/dev/null'
+        ]
+        process = subprocess.Popen(cmd, stdout=subprocess.DEVNULL, stderr=subprocess.PIPE)
+        sys.stderr.write("Started rpicam-vid for the detection service\n")
+        sys.stderr.flush()
+        threading.Thread(target=self.read_camera, args=(process,), daemon=True).start()
+
+        for line in sys.stdin:
+            if not self.running:
+                break
+            try:
+                request_id = json.loads(line).get("id")
+            except ValueError:
+                continue
+            with self.frame_ready:
+                seq = self.frame_seq
+            detections = self.wait_for_frame(seq)
+            if detections is None:
+                response = {"id": request_id, "status": "timeout", "detections": [], "detection_count": 0}
+            else:
+                response = {
+                    "id": request_id,
+                    "status": "success",
+                    "detections": detections,
+                    "detection_count": len(detections),
+                }
+            print(json.dumps(response))
+            sys.stdout.flush()
+
+        process.terminate()
+
+
+if __name__ == "__main__":
+    FastDetector().serve()
@@ -0,0 +1,297 @@
+// src/interlock.rs
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::{Condvar, Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::clock::CLOCK;
+use crate::config::{InterlockConfig, InterlockDriver};
+use crate::events::{Severity, EVENTS};
+use crate::metrics::METRICS;
+
+pub static INTERLOCK: Lazy<Interlock> = Lazy::new(Interlock::new);
+
+pub trait SafeOutput: Send {
+    fn set(&mut self, high: bool) -> Result<()>;
+    // What the pin is driven to, read back rather than remembered.
+    fn is_high(&mut self) -> Result<bool>;
+}
+
+// Keeps the level in memory, for running without a Pi.
+#[derive(Default)]
+pub struct MockSafeOutput {
+    high: bool,
+}
+
+impl SafeOutput for MockSafeOutput {
+    fn set(&mut self, high: bool) -> Result<()> {
+        self.high = high;
+        Ok(())
+    }
+
+    fn is_high(&mut self) -> Result<bool> {
+        Ok(self.high)
+    }
+}
+
+#[cfg(feature = "interlock")]
+pub struct GpioSafeOutput {
+    pin: rppal::gpio::OutputPin,
+}
+
+#[cfg(feature = "interlock")]
+impl GpioSafeOutput {
+    pub fn open(pin: u8) -> Result<Self> {
+        Ok(Self { pin: rppal::gpio::Gpio::new()?.get(pin)?.into_output_low() })
+    }
+}
+
+#[cfg(feature = "interlock")]
+impl SafeOutput for GpioSafeOutput {
+    fn set(&mut self, high: bool) -> Result<()> {
+        if high {
+            self.pin.set_high();
+        } else {
+            self.pin.set_low();
+        }
+        Ok(())
+    }
+
+    fn is_high(&mut self) -> Result<bool> {
+        Ok(self.pin.is_set_high())
+    }
+}
+
+// Also opens the motor driver's enable output for idle power saving.
+pub fn open_output(driver: InterlockDriver, pin: u8) -> Result<Box<dyn SafeOutput>> {
+    Ok(match driver {
+        #[cfg(feature = "interlock")]
+        InterlockDriver::Gpio => Box::new(GpioSafeOutput::open(pin)?),
+        #[cfg(not(feature = "interlock"))]
+        InterlockDriver::Gpio => {
+            let _ = pin;
+            anyhow::bail!("built without the `interlock` feature")
+        }
+        InterlockDriver::Mock => Box::new(MockSafeOutput::default()),
+    })
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InterlockStatus {
+    // Whether the safe output is held high, letting the motors have power.
+    pub asserted: bool,
+    // Why it is low: any of starting, watchdog, estop, vision_stale,
+    // shutdown and fault. Empty while it is asserted.
+    pub reasons: Vec<String>,
+    // Wall-clock ms of the last change.
+    pub since_ms: u64,
+    // The output couldn't be opened or driven.
+    #[serde(default)]
+    pub fault: Option<String>,
+}
+
+// The hardware side of the safety layer. Only the watchdog thread drives
+// the output, from the conditions the motors and vision mirror into it
+// here, so it drops even when the control loop hangs.
+pub struct Interlock {
+    output: Mutex<Option<Box<dyn SafeOutput>>>,
+    // None unless interlock.enabled is set.
+    status: RwLock<Option<InterlockStatus>>,
+    estop_latched: AtomicBool,
+    // Stale until vision says otherwise.
+    vision_stale: AtomicBool,
+    changed: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl Interlock {
+    fn new() -> Self {
+        Self {
+            output: Mutex::new(None),
+            status: RwLock::new(None),
+            estop_latched: AtomicBool::new(false),
+            vision_stale: AtomicBool::new(true),
+            changed: Mutex::new(false),
+            wake: Condvar::new(),
+        }
+    }
+
+    // Called once at startup. The output starts low and stays that way
+    // until the watchdog first finds everything in order.
+    pub fn open(&self, config: &InterlockConfig) -> Result<()> {
+        if !config.enabled {
+            return Ok(());
+        }
+        let mut status = InterlockStatus {
+            asserted: false,
+            reasons: vec!["starting".to_string()],
+            since_ms: CLOCK.wall_ms(),
+            fault: None,
+        };
+        let opened = open_output(config.driver, config.pin).and_then(|mut output| {
+            output.set(false)?;
+            Ok(output)
+        });
+        let result = match opened {
+            Ok(output) => {
+                info!(target: "interlock", "Safe output opened low on pin {} ({:?} driver)", config.pin, config.driver);
+                *self.output.lock() = Some(output);
+                Ok(())
+            }
+            Err(e) => {
+                status.reasons = vec!["fault".to_string()];
+                status.fault = Some(format!("{:#}", e));
+                Err(e)
+            }
+        };
+        METRICS.interlock_asserted.set(0);
+        *self.status.write() = Some(status);
+        result
+    }
+
+    pub fn get_status(&self) -> Option<InterlockStatus> {
+        self.status.read().clone()
+    }
+
+    pub fn set_estop_latched(&self, latched: bool) {
+        if self.estop_latched.swap(latched, Ordering::SeqCst) != latched {
+            self.notify();
+        }
+    }
+
+    pub fn set_vision_stale(&self, stale: bool) {
+        if self.vision_stale.swap(stale, Ordering::SeqCst) != stale {
+            self.notify();
+        }
+    }
+
+    fn notify(&self) {
+        *self.changed.lock() = true;
+        self.wake.notify_all();
+    }
+
+    // For the watchdog thread: sleeps up to `timeout`, less if a condition
+    // changes meanwhile.
+    pub fn wait(&self, timeout: Duration) {
+        let mut changed = self.changed.lock();
+        if !*changed {
+            self.wake.wait_for(&mut changed, timeout);
+        }
+        *changed = false;
+    }
+
+    // For the watchdog thread: holds the output high only while the
+    // watchdog is fed, no emergency stop is latched and vision is fresh.
+    pub fn update(&self, watchdog_fed: bool) {
+        let mut reasons = Vec::new();
+        if !watchdog_fed {
+            reasons.push("watchdog");
+        }
+        if self.estop_latched.load(Ordering::SeqCst) {
+            reasons.push("estop");
+        }
+        if self.vision_stale.load(Ordering::SeqCst) {
+            reasons.push("vision_stale");
+        }
+        self.drive(reasons);
+    }
+
+    // Drops the output for good on the way out.
+    pub fn release(&self) {
+        self.drive(vec!["shutdown"]);
+    }
+
+    fn drive(&self, reasons: Vec<&'static str>) {
+        let mut output = self.output.lock();
+        let Some(driver) = output.as_mut() else { return };
+        let high = reasons.is_empty();
+        let mut status = self.status.write();
+        let Some(status) = status.as_mut() else { return };
+        let reasons: Vec<String> = reasons.into_iter().map(str::to_string).collect();
+        if status.fault.is_none() && status.asserted == high && status.reasons == reasons {
+            return;
+        }
+
+        match driver.set(high) {
+            Ok(()) if status.fault.is_some() => {
+                info!(target: "interlock", "Safe output recovered");
+                status.fault = None;
+            }
+            Ok(()) => {}
+            Err(e) => {
+                // The pin may be in either state now; nothing that can be
+                // done here makes it safer than trying low.
+                let _ = driver.set(false);
+                if status.fault.is_none() {
+                    warn!(target: "interlock", "Failed to drive safe output: {:#}", e);
+                    EVENTS.publish(Severity::Critical, "interlock", format!("Failed to drive safe output: {:#}", e), serde_json::Value::Null);
+                }
+                status.fault = Some(format!("{:#}", e));
+                self.record(status, false, vec!["fault".to_string()]);
+                return;
+            }
+        }
+        self.record(status, high, reasons);
+    }
+
+    fn record(&self, status: &mut InterlockStatus, high: bool, reasons: Vec<String>) {
+        let was_asserted = status.asserted;
+        status.asserted = high;
+        status.reasons = reasons;
+        status.since_ms = CLOCK.wall_ms();
+        METRICS.interlock_asserted.set(high as i64);
+        if was_asserted && !high {
+            METRICS.interlock_drops.inc(&status.reasons[0]);
+            warn!(target: "interlock", "Safe output dropped: {}", status.reasons.join(", "));
+            EVENTS.publish(Severity::Warning, "interlock", format!("Safe output dropped: {}", status.reasons.join(", ")), serde_json::json!({
+                "reasons": status.reasons,
+            }));
+        } else if !was_asserted && high {
+            info!(target: "interlock", "Safe output asserted");
+            EVENTS.publish(Severity::Info, "interlock", "Safe output asserted", serde_json::Value::Null);
+        }
+    }
+
+    // The startup check: drives the output low and reads it back, then
+    // puts back the level the watchdog last chose and reads that back too.
+    // None when the interlock is off.
+    pub fn check(&self) -> Option<Result<String, String>> {
+        let status = self.get_status()?;
+        let mut output = self.output.lock();
+        let Some(driver) = output.as_mut() else {
+            return Some(Err(status.fault.unwrap_or_else(|| "safe output not open".to_string())));
+        };
+        let mut drive = |high: bool| -> Result<(), String> {
+            driver.set(high).map_err(|e| format!("failed to drive {}: {:#}", level(high), e))?;
+            match driver.is_high() {
+                Ok(read) if read == high => Ok(()),
+                Ok(read) => Err(format!("driven {} but reads {}", level(high), level(read))),
+                Err(e) => Err(format!("failed to read back: {:#}", e)),
+            }
+        };
+        let asserted = status.asserted && status.fault.is_none();
+        let result = drive(false).and_then(|()| if asserted { drive(true) } else { Ok(()) });
+        if let Err(e) = &result {
+            let _ = driver.set(false);
+            // Until the watchdog manages to drive it again.
+            if let Some(status) = self.status.write().as_mut() {
+                status.fault = Some(e.clone());
+                self.record(status, false, vec!["fault".to_string()]);
+            }
+        }
+        Some(result.map(|()| {
+            if asserted {
+                "driven low and high, read back".to_string()
+            } else {
+                "driven low, read back".to_string()
+            }
+        }))
+    }
+}
+
+fn level(high: bool) -> &'static str {
+    if high { "high" } else { "low" }
+}
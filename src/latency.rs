@@ -0,0 +1,110 @@
+// src/latency.rs
+use serde::Serialize;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::clock::CLOCK;
+use crate::metrics::METRICS;
+
+// Budget warnings are held back to one per this long.
+const WARN_INTERVAL_MS: u64 = 5000;
+
+// When a frame reached each point on its way into the rover, in rover-clock
+// ms with the fraction kept.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStamps {
+    pub capture_ms: f64,
+    // None from bridges that don't stamp the line as they write it.
+    pub emit_ms: Option<f64>,
+    pub parsed_ms: f64,
+}
+
+impl FrameStamps {
+    // For frames made on the rover, which are captured and parsed at once.
+    pub fn local(at_ms: f64) -> Self {
+        Self { capture_ms: at_ms, emit_ms: None, parsed_ms: at_ms }
+    }
+}
+
+// Where the time went between a frame's capture and the motor command the
+// tick that consumed it issued.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct LatencyBreakdown {
+    // Inference and encoding on the bridge. Both are None without an emit
+    // stamp, and the time until parsing isn't split.
+    pub capture_to_emit_ms: Option<f32>,
+    // The pipe, and parsing the line.
+    pub emit_to_parse_ms: Option<f32>,
+    // Waiting for a tick, vision and the planner.
+    pub parse_to_plan_ms: f32,
+    // Deciding the command and applying it.
+    pub plan_to_motor_ms: f32,
+    pub total_ms: f32,
+}
+
+impl LatencyBreakdown {
+    pub fn new(stamps: &FrameStamps, plan_ms: f64, motor_ms: f64) -> Self {
+        let stage = |from: f64, to: f64| (to - from).max(0.0) as f32;
+        Self {
+            capture_to_emit_ms: stamps.emit_ms.map(|emit| stage(stamps.capture_ms, emit)),
+            emit_to_parse_ms: stamps.emit_ms.map(|emit| stage(emit, stamps.parsed_ms)),
+            parse_to_plan_ms: stage(stamps.parsed_ms, plan_ms),
+            plan_to_motor_ms: stage(plan_ms, motor_ms),
+            total_ms: stage(stamps.capture_ms, motor_ms),
+        }
+    }
+
+    // The stages that were measured, in pipeline order.
+    pub fn get_stages(&self) -> Vec<(&'static str, f32)> {
+        [
+            ("capture_to_emit", self.capture_to_emit_ms),
+            ("emit_to_parse", self.emit_to_parse_ms),
+            ("parse_to_plan", Some(self.parse_to_plan_ms)),
+            ("plan_to_motor", Some(self.plan_to_motor_ms)),
+        ]
+        .into_iter()
+        .filter_map(|(name, ms)| Some((name, ms?)))
+        .collect()
+    }
+}
+
+// Feeds each breakdown into the metrics and warns when a frame took longer
+// than the budget to reach the motors.
+pub struct LatencyBudget {
+    budget_ms: f32,
+    over: u64,
+    last_warning_ms: Option<u64>,
+}
+
+impl LatencyBudget {
+    pub fn new(budget_ms: f32) -> Self {
+        Self { budget_ms, over: 0, last_warning_ms: None }
+    }
+
+    pub fn record(&mut self, breakdown: &LatencyBreakdown) {
+        let stages = breakdown.get_stages();
+        for (name, ms) in &stages {
+            METRICS.latency_stages.observe(name, Duration::from_secs_f64(*ms as f64 / 1000.0));
+        }
+        if breakdown.total_ms <= self.budget_ms {
+            return;
+        }
+        METRICS.latency_budget_exceeded.inc();
+        self.over += 1;
+
+        let now = CLOCK.now_ms();
+        if self.last_warning_ms.is_some_and(|at| now.saturating_sub(at) < WARN_INTERVAL_MS) {
+            return;
+        }
+        let suppressed = self.over - 1;
+        self.over = 0;
+        self.last_warning_ms = Some(now);
+        let slowest = stages.iter().copied().fold(("", 0.0), |slowest, stage| if stage.1 > slowest.1 { stage } else { slowest });
+        let suppressed = if suppressed > 0 { format!(" ({} more since the last warning)", suppressed) } else { String::new() };
+        warn!(
+            target: "control",
+            "Frame took {:.1} ms from capture to motors, over the {:.0} ms budget; slowest was {} at {:.1} ms{}",
+            breakdown.total_ms, self.budget_ms, slowest.0, slowest.1, suppressed
+        );
+    }
+}
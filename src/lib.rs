@@ -0,0 +1,85 @@
+// src/lib.rs
+//! The rover's vision, planning, motor control and web subsystems. The
+//! `rover` binary wires them together; each one can also be built on its
+//! own. A vision system with a manual frame source needs no camera bridge:
+//!
+//! ```
+//! use scout_vision::config::{MotorConfig, PlannerConfig, VisionConfig};
+//! use scout_vision::health::HealthRegistry;
+//! use scout_vision::motor_control::MotorController;
+//! use scout_vision::pathfinding::PathPlanner;
+//! use scout_vision::simulation::{self, FrameSource};
+//! use scout_vision::vision::{IMX500Detection, VisionSystem};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let health = HealthRegistry::new();
+//! let mut vision = VisionSystem::new(&health, VisionConfig::default(), FrameSource::Manual)?;
+//! let mut planner = PathPlanner::new(&health, PlannerConfig::default());
+//! let mut motors = MotorController::new(&health, MotorConfig::default())?;
+//!
+//! let person = IMX500Detection { class: "person".into(), class_id: None, conf: 0.9, x: 280, y: 100, w: 80, h: 300, mask: None };
+//! vision.get_frame_sender().send(simulation::synthetic_frame(1, vec![person]));
+//!
+//! let detections = vision.process_frame()?;
+//! assert_eq!(detections.len(), 1);
+//! planner.update_obstacles(&detections);
+//! motors.move_forward(0.2);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod clock;
+pub mod vision;
+pub mod web;
+pub mod pathfinding;
+pub mod motor_control;
+pub mod health;
+pub mod metrics;
+pub mod config;
+pub mod events;
+pub mod session;
+pub mod recording;
+pub mod dataset;
+pub mod annotations;
+pub mod mapping;
+pub mod discovery;
+pub mod assets;
+pub mod telemetry;
+pub mod simulation;
+pub mod rover;
+pub mod state_machine;
+pub mod deliberation;
+pub mod watchdog;
+pub mod supervisor;
+pub mod scheduler;
+pub mod diagnostics;
+pub mod calibration;
+pub mod logging;
+pub mod mqtt;
+pub mod zones;
+pub mod gps;
+pub mod localization;
+pub mod slip;
+pub mod envelope;
+pub mod imu;
+pub mod latency;
+pub mod range;
+pub mod ros;
+pub mod annunciator;
+pub mod stream_quality;
+pub mod detection_delta;
+pub mod annotate;
+pub mod blackbox;
+pub mod selftest;
+pub mod startup;
+pub mod display;
+pub mod world;
+pub mod thermal;
+pub mod updates;
+pub mod interlock;
+pub mod power;
+pub mod capabilities;
+pub mod snapshot;
+pub mod checkpoint;
+pub mod teleop;
+pub mod hazards;
@@ -0,0 +1,132 @@
+// src/localization.rs
+use serde::Serialize;
+
+use crate::clock::CLOCK;
+
+// 2x2 matrices, row by row.
+type Matrix = [[f32; 2]; 2];
+
+// What one landmark sighting did, or would have done, to the position.
+#[derive(Debug, Clone, Serialize)]
+pub struct Correction {
+    pub landmark: String,
+    // Where the sighting put the rover.
+    pub fix_x: f32,
+    pub fix_y: f32,
+    // How far the estimate moved; zero when rejected.
+    pub dx: f32,
+    pub dy: f32,
+    pub magnitude_m: f32,
+    // How many standard deviations the fix was from the estimate.
+    pub deviation: f32,
+    pub accepted: bool,
+    pub at_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalizationStatus {
+    // Of the position estimate, in m².
+    pub covariance: Matrix,
+    // One standard deviation along the least certain direction.
+    pub sigma_m: f32,
+    pub corrections: u64,
+    pub rejections: u64,
+    pub last_correction: Option<Correction>,
+}
+
+// Kalman filter over the position alone; heading comes from the motors
+// and isn't estimated. The map starts where the rover does, so the
+// position is exact until it moves.
+#[derive(Debug, Clone, Default)]
+pub struct PositionFilter {
+    covariance: Matrix,
+    corrections: u64,
+    rejections: u64,
+    last_correction: Option<Correction>,
+}
+
+impl PositionFilter {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    // Odometry drifts the further the rover goes, equally in any direction.
+    pub fn predict(&mut self, distance_m: f32, variance_per_m: f32) {
+        let growth = distance_m.abs() * variance_per_m;
+        self.covariance[0][0] += growth;
+        self.covariance[1][1] += growth;
+    }
+
+    // Takes `fix` as a measurement of the position, `sigma_m` uncertain in
+    // any direction. A fix more than `gate_sigma` standard deviations from
+    // `position` is rejected and leaves the estimate alone.
+    pub fn correct(&mut self, landmark: String, position: (f32, f32), fix: (f32, f32), sigma_m: f32, gate_sigma: f32) -> Correction {
+        let p = self.covariance;
+        let r = sigma_m * sigma_m;
+        let s = [[p[0][0] + r, p[0][1]], [p[1][0], p[1][1] + r]];
+        let s_inv = invert(&s);
+        let v = (fix.0 - position.0, fix.1 - position.1);
+        let d2 = v.0 * (s_inv[0][0] * v.0 + s_inv[0][1] * v.1) + v.1 * (s_inv[1][0] * v.0 + s_inv[1][1] * v.1);
+        let deviation = d2.max(0.0).sqrt();
+        let accepted = deviation <= gate_sigma;
+
+        let (mut dx, mut dy) = (0.0, 0.0);
+        if accepted {
+            let k = multiply(&p, &s_inv);
+            dx = k[0][0] * v.0 + k[0][1] * v.1;
+            dy = k[1][0] * v.0 + k[1][1] * v.1;
+            let i_k = [[1.0 - k[0][0], -k[0][1]], [-k[1][0], 1.0 - k[1][1]]];
+            let updated = multiply(&i_k, &p);
+            // Kept symmetric against rounding.
+            let off = (updated[0][1] + updated[1][0]) / 2.0;
+            self.covariance = [[updated[0][0].max(0.0), off], [off, updated[1][1].max(0.0)]];
+            self.corrections += 1;
+        } else {
+            self.rejections += 1;
+        }
+        let correction = Correction {
+            landmark,
+            fix_x: fix.0,
+            fix_y: fix.1,
+            dx,
+            dy,
+            magnitude_m: dx.hypot(dy),
+            deviation,
+            accepted,
+            at_ms: CLOCK.now_ms(),
+        };
+        self.last_correction = Some(correction.clone());
+        correction
+    }
+
+    pub fn get_sigma_m(&self) -> f32 {
+        let [[a, b], [_, d]] = self.covariance;
+        let largest = (a + d) / 2.0 + (((a - d) / 2.0).powi(2) + b * b).sqrt();
+        largest.max(0.0).sqrt()
+    }
+
+    pub fn get_status(&self) -> LocalizationStatus {
+        LocalizationStatus {
+            covariance: self.covariance,
+            sigma_m: self.get_sigma_m(),
+            corrections: self.corrections,
+            rejections: self.rejections,
+            last_correction: self.last_correction.clone(),
+        }
+    }
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    [
+        [a[0][0] * b[0][0] + a[0][1] * b[1][0], a[0][0] * b[0][1] + a[0][1] * b[1][1]],
+        [a[1][0] * b[0][0] + a[1][1] * b[1][0], a[1][0] * b[0][1] + a[1][1] * b[1][1]],
+    ]
+}
+
+// Only ever called on a covariance plus measurement noise, which is
+// positive definite while sigma_m is above zero.
+fn invert(m: &Matrix) -> Matrix {
+    let det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+    let det = if det.abs() < f32::EPSILON { f32::EPSILON } else { det };
+    [[m[1][1] / det, -m[0][1] / det], [-m[1][0] / det, m[0][0] / det]]
+}
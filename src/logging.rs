@@ -0,0 +1,321 @@
+// src/logging.rs
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use tokio::sync::broadcast;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::{fmt, EnvFilter};
+
+use crate::clock::CLOCK;
+use crate::config::{LogLevel, LogsConfig, RoverConfig};
+use crate::metrics::METRICS;
+
+// Records for live tails; a tail that falls further behind skips ahead.
+const TAIL_CAPACITY: usize = 256;
+const REDACTED: &str = "[redacted]";
+
+pub static LOGS: Lazy<LogBuffer> = Lazy::new(LogBuffer::new);
+
+// Filter precedence: --log-level, then RUST_LOG, then "info". Per-frame
+// logs are DEBUG, so "info" stays readable at 30Hz; targets are vision,
+// planner, motors, web, control, config and events, e.g. "info,motors=debug".
+//
+// Console logs go to stdout, or to stderr for commands whose output is
+// data meant for a pipe.
+//
+// The returned guard flushes the JSON file on drop and must be held for
+// the life of the process.
+pub fn init(level: Option<&str>, json_dir: Option<&Path>, to_stderr: bool) -> Result<Option<WorkerGuard>> {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level)?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+
+    let writer = if to_stderr { BoxMakeWriter::new(std::io::stderr) } else { BoxMakeWriter::new(std::io::stdout) };
+    let console = fmt::layer().with_target(true).with_writer(writer);
+
+    let (json, guard) = match json_dir {
+        Some(dir) => {
+            let appender = tracing_appender::rolling::daily(dir, "scout.json.log");
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_writer(writer);
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(console)
+        .with(json)
+        .with(LogLayer)
+        .try_init()?;
+
+    Ok(guard)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogRecord {
+    pub id: u64,
+    // Wall-clock ms.
+    pub timestamp: u64,
+    pub level: LogLevel,
+    pub target: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub fields: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LogQuery {
+    pub level: Option<LogLevel>,
+    pub since_ms: Option<u64>,
+    // Only records with a greater id, for polling.
+    pub after: Option<u64>,
+    pub target: Option<String>,
+    // The newest this many.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogSummary {
+    pub level: LogLevel,
+    pub capacity: usize,
+    pub buffered: usize,
+    pub dropped: u64,
+}
+
+#[derive(Default)]
+struct Redaction {
+    // Lowercased.
+    fields: Vec<String>,
+    secrets: Vec<String>,
+}
+
+impl Redaction {
+    fn hides(&self, field: &str) -> bool {
+        let field = field.to_ascii_lowercase();
+        self.fields.iter().any(|name| field.contains(name.as_str()))
+    }
+
+    // Blanks the known secrets and whatever follows "Bearer ".
+    fn scrub(&self, text: &mut String) {
+        for secret in &self.secrets {
+            if text.contains(secret.as_str()) {
+                *text = text.replace(secret.as_str(), REDACTED);
+            }
+        }
+        let mut from = 0;
+        while let Some(i) = text[from..].find("Bearer ") {
+            let at = from + i + "Bearer ".len();
+            let end = text[at..].find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | ',' | ';'))
+                .map_or(text.len(), |i| at + i);
+            text.replace_range(at..end, REDACTED);
+            from = at + REDACTED.len();
+        }
+    }
+}
+
+// The last logs.capacity events at logs.level or above. Nothing logging
+// ever waits on it: an event that finds it busy is dropped and counted
+// instead, and live tails that fall behind lose the oldest.
+pub struct LogBuffer {
+    records: Mutex<VecDeque<LogRecord>>,
+    tail: broadcast::Sender<LogRecord>,
+    level: AtomicU8,
+    capacity: AtomicUsize,
+    redaction: RwLock<Redaction>,
+    next_id: AtomicU64,
+}
+
+impl LogBuffer {
+    fn new() -> Self {
+        let defaults = LogsConfig::default();
+        let (tail, _) = broadcast::channel(TAIL_CAPACITY);
+        Self {
+            records: Mutex::new(VecDeque::new()),
+            tail,
+            level: AtomicU8::new(defaults.level as u8),
+            capacity: AtomicUsize::new(defaults.capacity),
+            redaction: RwLock::new(Redaction { fields: defaults.redact_fields, secrets: Vec::new() }),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    // Takes the secrets to blank from the environment variables the
+    // config names.
+    pub fn apply_config(&self, config: &RoverConfig) {
+        let logs = &config.logs;
+        self.level.store(logs.level as u8, Ordering::Relaxed);
+        self.capacity.store(logs.capacity, Ordering::Relaxed);
+        let secrets = [config.updates.token_env.as_deref(), config.hazards.token_env.as_deref(), config.mqtt.password_env.as_deref()]
+            .into_iter()
+            .flatten()
+            .filter_map(|name| std::env::var(name).ok())
+            .filter(|secret| !secret.is_empty())
+            .collect();
+        let fields = logs.redact_fields.iter().map(|name| name.to_ascii_lowercase()).collect();
+        *self.redaction.write() = Redaction { fields, secrets };
+        let mut records = self.records.lock();
+        while records.len() > logs.capacity {
+            records.pop_front();
+        }
+    }
+
+    pub fn get_level(&self) -> LogLevel {
+        match self.level.load(Ordering::Relaxed) {
+            0 => LogLevel::Trace,
+            1 => LogLevel::Debug,
+            2 => LogLevel::Info,
+            3 => LogLevel::Warn,
+            _ => LogLevel::Error,
+        }
+    }
+
+    fn record(&self, event: &Event<'_>) {
+        let metadata = event.metadata();
+        let level = match *metadata.level() {
+            tracing::Level::TRACE => LogLevel::Trace,
+            tracing::Level::DEBUG => LogLevel::Debug,
+            tracing::Level::INFO => LogLevel::Info,
+            tracing::Level::WARN => LogLevel::Warn,
+            tracing::Level::ERROR => LogLevel::Error,
+        };
+        if level < self.get_level() {
+            return;
+        }
+        let Some(redaction) = self.redaction.try_read() else {
+            METRICS.logs_dropped.inc();
+            return;
+        };
+        let mut visitor = RecordVisitor { redaction: &redaction, message: String::new(), fields: serde_json::Map::new() };
+        event.record(&mut visitor);
+        let RecordVisitor { mut message, fields, .. } = visitor;
+        redaction.scrub(&mut message);
+        drop(redaction);
+        let record = LogRecord {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: CLOCK.wall_ms(),
+            level,
+            target: metadata.target().to_string(),
+            message,
+            fields,
+        };
+        let Some(mut records) = self.records.try_lock() else {
+            METRICS.logs_dropped.inc();
+            return;
+        };
+        while records.len() >= self.capacity.load(Ordering::Relaxed) {
+            records.pop_front();
+        }
+        records.push_back(record.clone());
+        drop(records);
+        // No receivers just means nobody is tailing.
+        let _ = self.tail.send(record);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LogRecord> {
+        self.tail.subscribe()
+    }
+
+    // Oldest first.
+    pub fn query(&self, query: &LogQuery) -> Vec<LogRecord> {
+        let records = self.records.lock();
+        let mut found: Vec<LogRecord> = records.iter()
+            .filter(|r| query.level.map_or(true, |level| r.level >= level))
+            .filter(|r| query.since_ms.map_or(true, |since| r.timestamp >= since))
+            .filter(|r| query.after.map_or(true, |after| r.id > after))
+            .filter(|r| query.target.as_deref().map_or(true, |target| r.target == target || r.target.starts_with(&format!("{}::", target))))
+            .cloned()
+            .collect();
+        if let Some(limit) = query.limit {
+            found.drain(..found.len().saturating_sub(limit));
+        }
+        found
+    }
+
+    pub fn get_summary(&self) -> LogSummary {
+        LogSummary {
+            level: self.get_level(),
+            capacity: self.capacity.load(Ordering::Relaxed),
+            buffered: self.records.lock().len(),
+            dropped: METRICS.logs_dropped.get(),
+        }
+    }
+}
+
+struct RecordVisitor<'a> {
+    redaction: &'a Redaction,
+    message: String,
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl RecordVisitor<'_> {
+    fn put(&mut self, field: &Field, value: serde_json::Value) {
+        let value = if self.redaction.hides(field.name()) {
+            serde_json::Value::from(REDACTED)
+        } else if let serde_json::Value::String(mut text) = value {
+            self.redaction.scrub(&mut text);
+            serde_json::Value::String(text)
+        } else {
+            value
+        };
+        self.fields.insert(field.name().to_string(), value);
+    }
+}
+
+impl Visit for RecordVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.put(field, format!("{:?}", value).into());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.put(field, value.into());
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.put(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.put(field, value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.put(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.put(field, value.into());
+    }
+}
+
+// Feeds LOGS from every event the subscriber's filter lets through.
+struct LogLayer;
+
+impl<S: Subscriber> Layer<S> for LogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        LOGS.record(event);
+    }
+}
@@ -1,115 +1,212 @@
-// src/main.rs 
+// src/main.rs
 use anyhow::Result;
+use clap::Parser;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use parking_lot::RwLock;
-use tokio::time::{sleep, Duration};
-use std::time::Instant;
+use std::time::Duration;
+use tracing::info;
 
-mod vision;
-mod web;
-mod pathfinding;
-mod motor_control;
+mod cli;
 
-use vision::{VisionSystem, NavigationAction};
-use pathfinding::{PathPlanner, NavigationCommand};
-use motor_control::MotorController;
+use cli::{BlackboxCommand, CalibrateCommand, Cli, CliCommand, DatasetCommand, ExportFormat, WebArgs};
+use scout_vision::config::ConfigStore;
+use scout_vision::rover::Rover;
+use scout_vision::simulation::FrameSource;
+#[cfg(feature = "sim")]
+use scout_vision::simulation::Scenario;
+use scout_vision::dataset::{self, ExportOptions};
+use scout_vision::{blackbox, calibration, capabilities, diagnostics, logging, session, supervisor};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    println!(" MARS ROVER - RUST POWERED");
-    println!(" Pi Zero 2W | IMX500 NPU | YOLOv8");
-    println!(" SAFETY-CRITICAL MODE ENABLED\n");
-
-    let vision = Arc::new(RwLock::new(VisionSystem::new()?));
-    let path_planner = Arc::new(RwLock::new(PathPlanner::new()));
-    let motor_controller = Arc::new(RwLock::new(MotorController::new()?));
-
-    println!(" Starting web server on port 8080");
-    let web_server = Arc::new(web::WebServer::new(
-        vision.clone(),
-        path_planner.clone(),
-        motor_controller.clone(),
-    ));
-
-    let _web_handle = {
-        let server = web_server.clone();
-        tokio::spawn(async move {
-            server.run().await;
-        })
+    let cli = Cli::parse();
+    let to_stderr = matches!(cli.command, Some(CliCommand::Blackbox(_)));
+    let _log_guard = logging::init(cli.log_level.as_deref(), cli.log_json.as_deref(), to_stderr)?;
+    supervisor::install_panic_hook();
+    let config = Arc::new(ConfigStore::load(&cli.config)?);
+    logging::LOGS.apply_config(&config.get());
+
+    match cli.command.unwrap_or(CliCommand::Run(WebArgs::default())) {
+        CliCommand::Run(web) => run(config, FrameSource::Bridge, web).await,
+        CliCommand::Check => check(&config),
+        CliCommand::Calibrate(CalibrateCommand::FocalLength { distance, class, samples, save }) => {
+            calibrate_focal_length(config, distance, &class, samples, save).await
+        }
+        CliCommand::Calibrate(CalibrateCommand::Confidence { recording, source, bins, save }) => {
+            calibrate_confidence(&config, &recording, &source, bins, save)
+        }
+        CliCommand::Replay { recording, speed, web } => {
+            run(config, FrameSource::Recording { path: recording, speed }, web).await
+        }
+        #[cfg(feature = "sim")]
+        CliCommand::Simulate { scenario, web } => {
+            let scenario = Scenario::load(&scenario)?;
+            run(config, FrameSource::Scenario(scenario), web).await
+        }
+        CliCommand::Blackbox(BlackboxCommand::Export { since, until, kind, format, output }) => {
+            export_blackbox(&config, &since, until.as_deref(), kind, format, output)
+        }
+        CliCommand::Dataset(DatasetCommand::Export { recording, output, min_confidence, classes, from_s, to_s, every, dedup_below, skip_empty, annotated_only }) => {
+            let options = ExportOptions {
+                min_confidence,
+                classes,
+                from_ms: from_s.map(|s| (s * 1000.0) as u64),
+                to_ms: to_s.map(|s| (s * 1000.0) as u64),
+                every,
+                dedup_below,
+                skip_empty,
+                annotated_only,
+            };
+            export_dataset(&recording, &output, &options)
+        }
+    }
+}
+
+async fn run(config: Arc<ConfigStore>, source: FrameSource, web: WebArgs) -> Result<()> {
+    info!(target: "control", "MARS ROVER - RUST POWERED");
+    info!(target: "control", "Pi Zero 2W | IMX500 NPU | YOLOv8");
+    info!(target: "control", "SAFETY-CRITICAL MODE ENABLED");
+    let (with, without) = capabilities::summary();
+    info!(target: "control", "{} build with: {}; without: {}", capabilities::get_target(), with, without);
+
+    if let Some(port) = web.port {
+        config.update(&serde_json::json!({ "web": { "port": port } }))
+            .map_err(|errors| anyhow::anyhow!("Invalid --port: {}", errors[0].message))?;
+    }
+
+    let rover = Rover::new(config, source)?;
+    rover.start_blackbox();
+    rover.load_checkpoint();
+    if !web.no_web {
+        rover.start_web();
+    }
+    // After the web server, so it is up to report on them and to retry
+    // them if they fail.
+    rover.start_subsystems();
+    rover.start_mqtt();
+    rover.start_gps();
+    rover.start_ranges();
+    rover.start_ros();
+    rover.start_annunciator();
+    rover.start_thermal();
+    rover.start_checkpoints();
+    info!(target: "control", "All systems started");
+
+    rover.run().await
+}
+
+fn check(config: &ConfigStore) -> Result<()> {
+    let results = diagnostics::run_checks(config);
+    diagnostics::print_report(&results);
+
+    let failed = results.iter().filter(|r| !r.ok).count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} checks failed", failed, results.len());
+    }
+    Ok(())
+}
+
+async fn calibrate_focal_length(config: Arc<ConfigStore>, distance: f32, class: &str, samples: usize, save: bool) -> Result<()> {
+    let vision_config = config.get().vision;
+    let Some(real_height) = vision_config.object_heights_m.get(class).copied() else {
+        anyhow::bail!("No height configured for '{}' in vision.object_heights_m", class);
     };
 
-    println!(" Web interface: http://0.0.0.0:8080");
-    println!(" All systems initialized");
-    println!(" Starting autonomous navigation...\n");
-
-    let mut loop_count = 0u64;
-    let mut fps = 0.0f32;
-    let mut last_fps_time = Instant::now();
-    let mut fps_frame_count= 0;
-
-    loop {
-        let frame_start = Instant::now();
-
-        let (detections, nav_action) = {
-            let mut vision = vision.write();
-            let detections = vision.process_frame()?;
-            let action = vision.get_navigation_command();
-            (detections, action)
-        };
-
-        let nav_command = {
-            let mut planner = path_planner.write();
-            planner.update_obstacles(&detections);
-            planner.get_navigation_command()
-        };
-
-        {
-            let mut motors = motor_controller.write();
-
-            match nav_action {
-                NavigationAction::EmergencyStop => {
-                    motors.emergency_stop();
-                }
-                NavigationAction::Stop => {
-                    motors.stop();
-                }
-                _ => {
-                    match nav_command {
-                        NavigationCommand::Forward(speed) => {
-                            motors.move_forward(speed);
-                        }
-                        NavigationCommand::TurnLeft(angle) => {
-                            motors.turn_left(angle);
-                        }
-                        NavigationCommand::TurnRight(angle) => {
-                            motors.turn_right(angle);
-                        }
-                        NavigationCommand::Stop => {
-                            motors.stop();
-                        }
-                    }
-                }
-            }
-        }
+    info!(target: "vision", "Calibrating focal length: place a {} ({} m tall) {} m from the camera", class, real_height, distance);
+    let rover = Rover::new(config.clone(), FrameSource::Manual)?;
+    // No use without the camera, so unlike run() a bridge that won't start
+    // ends it.
+    rover.vision.write().start(FrameSource::Bridge)?;
+    let estimate = calibration::estimate_focal_length(
+        &rover.vision, class, distance, real_height, samples, Duration::from_secs(60),
+    ).await?;
+
+    println!(" Median height {:.0} px over {} frames", estimate.median_height_px, estimate.samples);
+    println!(" vision.focal_length_px = {:.1} (currently {:.1})", estimate.focal_length_px, vision_config.focal_length_px);
+
+    if save {
+        config.update(&serde_json::json!({ "vision": { "focal_length_px": estimate.focal_length_px } }))
+            .map_err(|errors| anyhow::anyhow!("Calibrated value rejected: {}", errors[0].message))?;
+        config.persist()?;
+    }
+    Ok(())
+}
 
-        fps_frame_count += 1;
-        if last_fps_time.elapsed() >= Duration::from_secs(1) {
-            fps = fps_frame_count as f32 / last_fps_time.elapsed().as_secs_f32();
-            fps_frame_count = 0;
-            last_fps_time = Instant::now();
-            println!("📊 System FPS: {:.1}", fps);
+fn export_blackbox(
+    config: &ConfigStore,
+    since: &str,
+    until: Option<&str>,
+    kind: Option<String>,
+    format: ExportFormat,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let parse = |value: &str| blackbox::parse_time(value)
+        .ok_or_else(|| anyhow::anyhow!("Invalid time '{}': expected e.g. 10m, epoch milliseconds or an RFC 3339 time", value));
+    if let Some(kind) = &kind {
+        if !blackbox::KINDS.contains(&kind.as_str()) {
+            anyhow::bail!("Unknown kind '{}', expected one of {}", kind, blackbox::KINDS.join(", "));
         }
+    }
+    let query = blackbox::Query {
+        from_ms: Some(parse(since)?),
+        to_ms: until.map(parse).transpose()?,
+        kind,
+        limit: None,
+    };
+    let records = blackbox::query(&config.get().blackbox, &query)?;
 
-        let frame_time = frame_start.elapsed();
-        if frame_time < Duration::from_millis(33) {  // Target 30 FPS
-            sleep(Duration::from_millis(33) - frame_time).await;
-        }
+    let mut out: Box<dyn Write> = match &output {
+        Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    match format {
+        ExportFormat::Csv => blackbox::write_csv(&records, &mut out)?,
+        ExportFormat::Jsonl => blackbox::write_jsonl(&records, &mut out)?,
+    }
+    out.flush()?;
+    if let Some(path) = output {
+        info!(target: "blackbox", "Exported {} records to {}", records.len(), path.display());
+    }
+    Ok(())
+}
 
-        loop_count += 1;
+fn export_dataset(recording: &Path, output: &Path, options: &ExportOptions) -> Result<()> {
+    if options.every == 0 {
+        anyhow::bail!("--every must be at least 1");
+    }
+    if !(0.0..=1.0).contains(&options.dedup_below) {
+        anyhow::bail!("--dedup-below must be between 0 and 1");
+    }
+    let summary = dataset::export(recording, output, options)?;
 
-        if loop_count % 100 == 0 {
-            println!("🔄 Processed {} frames", loop_count);
-        }
+    println!(" {} of {} frames exported, {} instances", summary.images, summary.frames, summary.instances);
+    for (class, count) in &summary.instances_per_class {
+        println!("   {:<16} {}", class, count);
+    }
+    println!(" Skipped {} frames without an image and {} near-duplicates", summary.without_image, summary.duplicates);
+    if summary.boxes_clamped + summary.boxes_outside > 0 {
+        println!(" {} boxes clamped to the image, {} outside it dropped", summary.boxes_clamped, summary.boxes_outside);
     }
+    if summary.annotations > 0 {
+        println!(" {} of {} operator annotations on an exported image", summary.annotations_with_image, summary.annotations);
+    }
+    Ok(())
 }
 
+fn calibrate_confidence(config: &ConfigStore, recording: &Path, source: &str, bins: usize, save: bool) -> Result<()> {
+    let fit = calibration::fit_confidence_curve(session::open_session_path(recording)?, bins)?;
+
+    println!(" {} labelled detections, {} real", fit.samples, fit.positives);
+    println!(" vision.confidence_calibration.{} =", source);
+    for (raw, calibrated) in &fit.points {
+        println!("   {:.3} -> {:.3}", raw, calibrated);
+    }
+
+    if save {
+        config.update(&serde_json::json!({ "vision": { "confidence_calibration": { source: fit.points } } }))
+            .map_err(|errors| anyhow::anyhow!("Calibrated curve rejected: {}", errors[0].message))?;
+        config.persist()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,277 @@
+// src/mapping.rs
+use anyhow::{bail, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+pub const TILE_SIZE: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Cell {
+    Unknown = 0,
+    Free = 1,
+    Occupied = 2,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct RoverPose {
+    pub x: f32,
+    pub y: f32,
+    pub heading_deg: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GridTile {
+    pub tx: usize,
+    pub ty: usize,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GridSnapshot {
+    pub width: usize,
+    pub height: usize,
+    pub resolution_m: f32,
+    pub tile_size: usize,
+    pub version: u64,
+    pub encoding: &'static str,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GridDiff {
+    pub width: usize,
+    pub height: usize,
+    pub base_version: u64,
+    pub version: u64,
+    pub tiles: Vec<GridTile>,
+}
+
+// The grid as ROS's nav_msgs/OccupancyGrid has it: -1 unknown, 0 free
+// and 100 occupied, row by row from cell (0, 0).
+#[derive(Debug, Clone)]
+pub struct OccupancyValues {
+    pub width: usize,
+    pub height: usize,
+    pub resolution_m: f32,
+    pub version: u64,
+    pub data: Vec<i8>,
+}
+
+// Rover-centred grid: the rover starts in the middle cell, +y is the
+// initial forward direction and +x is to the right.
+pub struct OccupancyGrid {
+    width: usize,
+    height: usize,
+    resolution_m: f32,
+    cells: Vec<u8>,
+    tile_versions: Vec<u64>,
+    version: u64,
+}
+
+impl OccupancyGrid {
+    pub fn new(size_cells: usize, resolution_m: f32) -> Self {
+        let tiles = size_cells.div_ceil(TILE_SIZE);
+        Self {
+            width: size_cells,
+            height: size_cells,
+            resolution_m,
+            cells: vec![Cell::Unknown as u8; size_cells * size_cells],
+            tile_versions: vec![0; tiles * tiles],
+            version: 0,
+        }
+    }
+
+    // Keeps the version counter running so diffs against the old grid
+    // still cover every tile of the new one.
+    pub fn reset(&mut self, size_cells: usize, resolution_m: f32) {
+        let version = self.version + 1;
+        *self = Self::new(size_cells, resolution_m);
+        self.version = version;
+        self.tile_versions.fill(version);
+    }
+
+    fn tiles_x(&self) -> usize {
+        self.width.div_ceil(TILE_SIZE)
+    }
+
+    fn tiles_y(&self) -> usize {
+        self.height.div_ceil(TILE_SIZE)
+    }
+
+    pub fn world_to_cell(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        let col = (x / self.resolution_m).floor() as i64 + (self.width / 2) as i64;
+        let row = (y / self.resolution_m).floor() as i64 + (self.height / 2) as i64;
+        if col < 0 || row < 0 || col >= self.width as i64 || row >= self.height as i64 {
+            return None;
+        }
+        Some((col as usize, row as usize))
+    }
+
+    // Where the middle of a cell is on the map.
+    pub fn cell_center(&self, col: usize, row: usize) -> (f32, f32) {
+        let x = (col as f32 - (self.width / 2) as f32 + 0.5) * self.resolution_m;
+        let y = (row as f32 - (self.height / 2) as f32 + 0.5) * self.resolution_m;
+        (x, y)
+    }
+
+    // Columns and rows of the cells overlapping the map rectangle from
+    // (x0, y0) to (x1, y1), clipped to the grid.
+    pub fn cells_in(&self, (x0, y0): (f32, f32), (x1, y1): (f32, f32)) -> (std::ops::Range<usize>, std::ops::Range<usize>) {
+        let index = |v: f32, size: usize| ((v / self.resolution_m).floor() as i64 + (size / 2) as i64).clamp(0, size as i64) as usize;
+        let cols = index(x0.min(x1), self.width)..(index(x0.max(x1), self.width) + 1).min(self.width);
+        let rows = index(y0.min(y1), self.height)..(index(y0.max(y1), self.height) + 1).min(self.height);
+        (cols, rows)
+    }
+
+    pub fn get_resolution_m(&self) -> f32 {
+        self.resolution_m
+    }
+
+    pub fn get(&self, col: usize, row: usize) -> Cell {
+        match self.cells[row * self.width + col] {
+            1 => Cell::Free,
+            2 => Cell::Occupied,
+            _ => Cell::Unknown,
+        }
+    }
+
+    pub fn set(&mut self, col: usize, row: usize, cell: Cell) {
+        let index = row * self.width + col;
+        if self.cells[index] == cell as u8 {
+            return;
+        }
+        self.cells[index] = cell as u8;
+        self.version += 1;
+        let tile = (row / TILE_SIZE) * self.tiles_x() + col / TILE_SIZE;
+        self.tile_versions[tile] = self.version;
+    }
+
+    pub fn set_world(&mut self, x: f32, y: f32, cell: Cell) {
+        if let Some((col, row)) = self.world_to_cell(x, y) {
+            self.set(col, row, cell);
+        }
+    }
+
+    pub fn get_version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn occupancy(&self) -> OccupancyValues {
+        OccupancyValues {
+            width: self.width,
+            height: self.height,
+            resolution_m: self.resolution_m,
+            version: self.version,
+            data: self.cells.iter()
+                .map(|&cell| match cell {
+                    1 => 0,
+                    2 => 100,
+                    _ => -1,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn snapshot(&self) -> GridSnapshot {
+        GridSnapshot {
+            width: self.width,
+            height: self.height,
+            resolution_m: self.resolution_m,
+            tile_size: TILE_SIZE,
+            version: self.version,
+            encoding: "rle8",
+            data: encode_rle(&self.cells),
+        }
+    }
+
+    // Tiles touched after `since`. Tiles carry their full contents, so any
+    // client holding `since` or newer can apply them to reach `version`.
+    pub fn diff_since(&self, since: u64) -> GridDiff {
+        let mut tiles = Vec::new();
+        for ty in 0..self.tiles_y() {
+            for tx in 0..self.tiles_x() {
+                if self.tile_versions[ty * self.tiles_x() + tx] > since {
+                    tiles.push(GridTile {
+                        tx,
+                        ty,
+                        data: encode_rle(&self.tile_cells(tx, ty)),
+                    });
+                }
+            }
+        }
+        GridDiff {
+            width: self.width,
+            height: self.height,
+            base_version: since,
+            version: self.version,
+            tiles,
+        }
+    }
+
+    // Writes a diff's tiles back in, as when the map is picked up again
+    // from a checkpoint. Nothing is written unless every tile fits.
+    pub fn apply_diff(&mut self, diff: &GridDiff) -> Result<()> {
+        if (diff.width, diff.height) != (self.width, self.height) {
+            bail!("the diff is for a {}x{} grid, not {}x{}", diff.width, diff.height, self.width, self.height);
+        }
+        let mut tiles = Vec::with_capacity(diff.tiles.len());
+        for tile in &diff.tiles {
+            if tile.tx >= self.tiles_x() || tile.ty >= self.tiles_y() {
+                bail!("tile ({}, {}) is outside the grid", tile.tx, tile.ty);
+            }
+            let cells = decode_rle(&tile.data)?;
+            let cols = ((tile.tx + 1) * TILE_SIZE).min(self.width) - tile.tx * TILE_SIZE;
+            let rows = ((tile.ty + 1) * TILE_SIZE).min(self.height) - tile.ty * TILE_SIZE;
+            if cells.len() != cols * rows || cells.iter().any(|&c| c > Cell::Occupied as u8) {
+                bail!("tile ({}, {}) doesn't hold {} cells", tile.tx, tile.ty, cols * rows);
+            }
+            tiles.push((tile, cols, cells));
+        }
+        for (tile, cols, cells) in tiles {
+            for (i, row_cells) in cells.chunks(cols).enumerate() {
+                let start = (tile.ty * TILE_SIZE + i) * self.width + tile.tx * TILE_SIZE;
+                self.cells[start..start + cols].copy_from_slice(row_cells);
+            }
+            self.version += 1;
+            let index = tile.ty * self.tiles_x() + tile.tx;
+            self.tile_versions[index] = self.version;
+        }
+        Ok(())
+    }
+
+    fn tile_cells(&self, tx: usize, ty: usize) -> Vec<u8> {
+        let mut out = Vec::with_capacity(TILE_SIZE * TILE_SIZE);
+        for row in ty * TILE_SIZE..((ty + 1) * TILE_SIZE).min(self.height) {
+            let start = row * self.width + tx * TILE_SIZE;
+            let end = row * self.width + ((tx + 1) * TILE_SIZE).min(self.width);
+            out.extend_from_slice(&self.cells[start..end]);
+        }
+        out
+    }
+}
+
+// Run-length pairs of (value, count) with count capped at 255, base64'd
+// so the payload stays JSON.
+fn encode_rle(cells: &[u8]) -> String {
+    let mut out = Vec::new();
+    let mut iter = cells.iter().peekable();
+    while let Some(&value) = iter.next() {
+        let mut count = 1u8;
+        while count < u8::MAX && iter.peek() == Some(&&value) {
+            iter.next();
+            count += 1;
+        }
+        out.push(value);
+        out.push(count);
+    }
+    base64::engine::general_purpose::STANDARD.encode(out)
+}
+
+fn decode_rle(data: &str) -> Result<Vec<u8>> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(data)?;
+    if bytes.len() % 2 != 0 {
+        bail!("run-length data has an odd length");
+    }
+    Ok(bytes.chunks_exact(2).flat_map(|pair| std::iter::repeat(pair[0]).take(pair[1] as usize)).collect())
+}
@@ -0,0 +1,488 @@
+// src/metrics.rs
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    fn new() -> Self {
+        Counter(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    fn new() -> Self {
+        Gauge(AtomicI64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// f64 stored as raw bits so readers never take a lock.
+pub struct FloatGauge(AtomicU64);
+
+impl FloatGauge {
+    fn new() -> Self {
+        FloatGauge(AtomicU64::new(0f64.to_bits()))
+    }
+
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+pub struct LabeledCounter {
+    label: &'static str,
+    values: DashMap<String, AtomicU64>,
+}
+
+impl LabeledCounter {
+    fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            values: DashMap::new(),
+        }
+    }
+
+    pub fn inc(&self, value: &str) {
+        // Only the first sighting of a label value allocates.
+        if let Some(counter) = self.values.get(value) {
+            counter.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.values.entry(value.to_string())
+            .or_insert_with(|| AtomicU64::new(0))
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, value: &str) -> u64 {
+        self.values.get(value).map_or(0, |counter| counter.load(Ordering::Relaxed))
+    }
+}
+
+pub struct Histogram {
+    bounds: &'static [f64],
+    buckets: Vec<AtomicU64>,
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: Duration) {
+        let secs = value.as_secs_f64();
+        if let Some(idx) = self.bounds.iter().position(|b| secs <= *b) {
+            self.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum_micros.fetch_add(value.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// One histogram per label value, all with the same buckets.
+pub struct LabeledHistogram {
+    label: &'static str,
+    bounds: &'static [f64],
+    values: DashMap<String, Histogram>,
+}
+
+impl LabeledHistogram {
+    fn new(label: &'static str, bounds: &'static [f64]) -> Self {
+        Self {
+            label,
+            bounds,
+            values: DashMap::new(),
+        }
+    }
+
+    pub fn observe(&self, value: &str, duration: Duration) {
+        if let Some(histogram) = self.values.get(value) {
+            histogram.observe(duration);
+            return;
+        }
+        self.values.entry(value.to_string())
+            .or_insert_with(|| Histogram::new(self.bounds))
+            .observe(duration);
+    }
+}
+
+const LOOP_TIME_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.02, 0.033, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+pub struct Metrics {
+    pub frames_processed: Counter,
+    pub detections: LabeledCounter,
+    pub navigation_actions: LabeledCounter,
+    pub planner_commands: LabeledCounter,
+    pub motor_commands: LabeledCounter,
+    pub loop_time: Histogram,
+    pub e2e_latency: Histogram,
+    pub last_e2e_latency_ms: FloatGauge,
+    pub latency_stages: LabeledHistogram,
+    pub latency_budget_exceeded: Counter,
+    pub loop_fps: FloatGauge,
+    pub last_loop_time_ms: FloatGauge,
+    pub reflex_overruns: Counter,
+    pub plan_time: Histogram,
+    pub last_plan_time_ms: FloatGauge,
+    pub plan_fps: FloatGauge,
+    pub plan_age_ms: FloatGauge,
+    pub plan_stale_stops: Counter,
+    pub motor_speed: FloatGauge,
+    pub obstacles: Gauge,
+    pub websocket_clients: Gauge,
+    pub websocket_connects: Counter,
+    pub websocket_disconnects: LabeledCounter,
+    pub stream_quality_changes: LabeledCounter,
+    pub state_transitions: LabeledCounter,
+    pub loop_wakeups: LabeledCounter,
+    pub watchdog_trips: Counter,
+    pub panics: LabeledCounter,
+    pub subsystem_restarts: LabeledCounter,
+    pub bridge_restarts: Counter,
+    pub mqtt_connected: Gauge,
+    pub mqtt_dropped: Counter,
+    pub gps_fixes: LabeledCounter,
+    pub gps_hdop: FloatGauge,
+    pub pose_sigma_m: FloatGauge,
+    pub wheel_slip: FloatGauge,
+    pub heading_error_deg: FloatGauge,
+    pub heading_correction: FloatGauge,
+    pub landmark_fixes: LabeledCounter,
+    pub landmark_correction_m: FloatGauge,
+    pub range_stops: Counter,
+    pub hazard_stops: Counter,
+    pub hazard_cautions: Counter,
+    pub hazard_reports: LabeledCounter,
+    pub hazards_active: Gauge,
+    pub rule_matches: LabeledCounter,
+    pub vision_stale: Counter,
+    pub mask_distances: LabeledCounter,
+    pub masks_dropped: Counter,
+    pub range_failures: LabeledCounter,
+    pub drop_offs: LabeledCounter,
+    pub ros_connected: Gauge,
+    pub ros_published: LabeledCounter,
+    pub ros_cmd_vel: LabeledCounter,
+    pub teleop_commands: LabeledCounter,
+    pub snapshot_encode: LabeledHistogram,
+    pub snapshot_frames_skipped: Counter,
+    pub annunciator_alerts: LabeledCounter,
+    pub bridge_parse_errors: Counter,
+    pub bridge_shm_frames: Counter,
+    pub bridge_shm_torn: Counter,
+    pub bridge_raw_frames: Counter,
+    pub frame_conversions: LabeledCounter,
+    pub bridge_protocol_version: Gauge,
+    pub blackbox_records: LabeledCounter,
+    pub blackbox_dropped: Counter,
+    pub blackbox_pruned: Counter,
+    pub blackbox_bytes: Gauge,
+    pub logs_dropped: Counter,
+    pub log_subscribers: Gauge,
+    pub cpu_temp_c: FloatGauge,
+    pub throttle_flags: Gauge,
+    pub thermal_degraded: Gauge,
+    pub interlock_asserted: Gauge,
+    pub vision_brightness: FloatGauge,
+    pub vision_clipped_fraction: FloatGauge,
+    pub vision_exposure_ev: FloatGauge,
+    pub vision_profile_switches: LabeledCounter,
+    pub interlock_drops: LabeledCounter,
+    pub power_asleep: Gauge,
+    pub power_wakes: LabeledCounter,
+    pub power_resume_s: FloatGauge,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Self {
+            frames_processed: Counter::new(),
+            detections: LabeledCounter::new("class"),
+            navigation_actions: LabeledCounter::new("action"),
+            planner_commands: LabeledCounter::new("command"),
+            motor_commands: LabeledCounter::new("command"),
+            loop_time: Histogram::new(LOOP_TIME_BUCKETS),
+            e2e_latency: Histogram::new(LOOP_TIME_BUCKETS),
+            last_e2e_latency_ms: FloatGauge::new(),
+            latency_stages: LabeledHistogram::new("stage", LOOP_TIME_BUCKETS),
+            latency_budget_exceeded: Counter::new(),
+            loop_fps: FloatGauge::new(),
+            last_loop_time_ms: FloatGauge::new(),
+            reflex_overruns: Counter::new(),
+            plan_time: Histogram::new(LOOP_TIME_BUCKETS),
+            last_plan_time_ms: FloatGauge::new(),
+            plan_fps: FloatGauge::new(),
+            plan_age_ms: FloatGauge::new(),
+            plan_stale_stops: Counter::new(),
+            motor_speed: FloatGauge::new(),
+            obstacles: Gauge::new(),
+            websocket_clients: Gauge::new(),
+            websocket_connects: Counter::new(),
+            websocket_disconnects: LabeledCounter::new("reason"),
+            stream_quality_changes: LabeledCounter::new("direction"),
+            state_transitions: LabeledCounter::new("state"),
+            loop_wakeups: LabeledCounter::new("reason"),
+            watchdog_trips: Counter::new(),
+            panics: LabeledCounter::new("subsystem"),
+            subsystem_restarts: LabeledCounter::new("subsystem"),
+            bridge_restarts: Counter::new(),
+            mqtt_connected: Gauge::new(),
+            mqtt_dropped: Counter::new(),
+            gps_fixes: LabeledCounter::new("quality"),
+            gps_hdop: FloatGauge::new(),
+            pose_sigma_m: FloatGauge::new(),
+            wheel_slip: FloatGauge::new(),
+            heading_error_deg: FloatGauge::new(),
+            heading_correction: FloatGauge::new(),
+            landmark_fixes: LabeledCounter::new("outcome"),
+            landmark_correction_m: FloatGauge::new(),
+            range_stops: Counter::new(),
+            hazard_stops: Counter::new(),
+            hazard_cautions: Counter::new(),
+            hazard_reports: LabeledCounter::new("result"),
+            hazards_active: Gauge::new(),
+            rule_matches: LabeledCounter::new("rule"),
+            vision_stale: Counter::new(),
+            mask_distances: LabeledCounter::new("source"),
+            masks_dropped: Counter::new(),
+            range_failures: LabeledCounter::new("sensor"),
+            drop_offs: LabeledCounter::new("source"),
+            ros_connected: Gauge::new(),
+            ros_published: LabeledCounter::new("topic"),
+            ros_cmd_vel: LabeledCounter::new("result"),
+            teleop_commands: LabeledCounter::new("result"),
+            snapshot_encode: LabeledHistogram::new("format", LOOP_TIME_BUCKETS),
+            snapshot_frames_skipped: Counter::new(),
+            annunciator_alerts: LabeledCounter::new("alert"),
+            bridge_parse_errors: Counter::new(),
+            bridge_shm_frames: Counter::new(),
+            bridge_shm_torn: Counter::new(),
+            bridge_raw_frames: Counter::new(),
+            frame_conversions: LabeledCounter::new("to"),
+            bridge_protocol_version: Gauge::new(),
+            blackbox_records: LabeledCounter::new("kind"),
+            blackbox_dropped: Counter::new(),
+            blackbox_pruned: Counter::new(),
+            blackbox_bytes: Gauge::new(),
+            logs_dropped: Counter::new(),
+            log_subscribers: Gauge::new(),
+            cpu_temp_c: FloatGauge::new(),
+            throttle_flags: Gauge::new(),
+            thermal_degraded: Gauge::new(),
+            interlock_asserted: Gauge::new(),
+            vision_brightness: FloatGauge::new(),
+            vision_clipped_fraction: FloatGauge::new(),
+            vision_exposure_ev: FloatGauge::new(),
+            vision_profile_switches: LabeledCounter::new("profile"),
+            interlock_drops: LabeledCounter::new("reason"),
+            power_asleep: Gauge::new(),
+            power_wakes: LabeledCounter::new("reason"),
+            power_resume_s: FloatGauge::new(),
+        }
+    }
+
+    // Prometheus text exposition format, version 0.0.4.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        write_counter(&mut out, "scout_frames_processed_total", "Bridge frames processed by the vision system", &self.frames_processed);
+        write_labeled(&mut out, "scout_detections_total", "Detections reported by class", &self.detections);
+        write_labeled(&mut out, "scout_navigation_actions_total", "Vision navigation actions emitted by the control loop", &self.navigation_actions);
+        write_labeled(&mut out, "scout_planner_commands_total", "Navigation commands produced by the path planner", &self.planner_commands);
+        write_labeled(&mut out, "scout_motor_commands_total", "Commands applied by the motor controller", &self.motor_commands);
+        write_histogram(&mut out, "scout_loop_time_seconds", "Control (reflex) loop iteration time", &self.loop_time);
+        write_counter(&mut out, "scout_reflex_overruns_total", "Control loop iterations over the 10 ms reflex budget", &self.reflex_overruns);
+        write_histogram(&mut out, "scout_plan_time_seconds", "Planning loop step time", &self.plan_time);
+        write_histogram(&mut out, "scout_e2e_latency_seconds", "Frame capture to motor command latency", &self.e2e_latency);
+        write_labeled_histogram(&mut out, "scout_latency_stage_seconds", "Frame capture to motor command latency by pipeline stage", &self.latency_stages);
+        write_counter(&mut out, "scout_latency_budget_exceeded_total", "Frames that took longer than control.latency_budget_ms to reach the motors", &self.latency_budget_exceeded);
+        write_gauge(&mut out, "scout_loop_fps", "Control loop iterations per second", self.loop_fps.get());
+        write_gauge(&mut out, "scout_plan_fps", "Planning loop steps per second", self.plan_fps.get());
+        write_gauge(&mut out, "scout_plan_age_ms", "Age of the plan the control loop last drove on", self.plan_age_ms.get());
+        write_counter(&mut out, "scout_plan_stale_stops_total", "Control loop iterations that stopped the rover for want of a fresh plan", &self.plan_stale_stops);
+        write_gauge(&mut out, "scout_motor_speed", "Commanded motor speed", self.motor_speed.get());
+        write_gauge(&mut out, "scout_obstacles", "Obstacles currently seen by the planner", self.obstacles.get() as f64);
+
+        write_gauge(&mut out, "scout_websocket_clients", "Connected WebSocket clients", self.websocket_clients.get() as f64);
+        write_counter(&mut out, "scout_websocket_connects_total", "WebSocket connections accepted", &self.websocket_connects);
+        write_labeled(&mut out, "scout_websocket_disconnects_total", "WebSocket disconnections by reason (closed, error, timeout)", &self.websocket_disconnects);
+        write_labeled(&mut out, "scout_stream_quality_changes_total", "Frame quality steps taken for WebSocket clients, by direction", &self.stream_quality_changes);
+
+        write_labeled(&mut out, "scout_state_transitions_total", "Rover state machine transitions by state entered", &self.state_transitions);
+        write_labeled(&mut out, "scout_loop_wakeups_total", "Control loop wakeups by reason (frame, timeout, fixed)", &self.loop_wakeups);
+        write_counter(&mut out, "scout_watchdog_trips_total", "Control loop watchdog trips", &self.watchdog_trips);
+        write_labeled(&mut out, "scout_panics_total", "Panics caught per subsystem", &self.panics);
+        write_labeled(&mut out, "scout_subsystem_restarts_total", "Subsystems restarted or reset after a panic", &self.subsystem_restarts);
+        write_counter(&mut out, "scout_bridge_restarts_total", "Vision bridge process restarts", &self.bridge_restarts);
+
+        write_gauge(&mut out, "scout_mqtt_connected", "Whether the MQTT broker connection is up", self.mqtt_connected.get() as f64);
+        write_counter(&mut out, "scout_mqtt_dropped_total", "MQTT messages dropped from a full queue", &self.mqtt_dropped);
+
+        write_labeled(&mut out, "scout_gps_fixes_total", "GPS fixes received by fix quality", &self.gps_fixes);
+        write_gauge(&mut out, "scout_gps_hdop", "HDOP of the last GPS fix", self.gps_hdop.get());
+        write_gauge(&mut out, "scout_pose_sigma_meters", "Standard deviation of the position estimate", self.pose_sigma_m.get());
+        write_gauge(&mut out, "scout_wheel_slip_ratio", "Wheel slip against visual ego-motion, averaged over the last few frames", self.wheel_slip.get());
+        write_gauge(&mut out, "scout_heading_error_degrees", "How far the fused heading is off the one heading hold is holding", self.heading_error_deg.get());
+        write_gauge(&mut out, "scout_heading_correction_m_s", "Wheel speed difference heading hold is steering with", self.heading_correction.get());
+        write_labeled(&mut out, "scout_landmark_fixes_total", "Landmark sightings by whether they were applied or rejected", &self.landmark_fixes);
+        write_gauge(&mut out, "scout_landmark_correction_meters", "How far the last applied landmark fix moved the position", self.landmark_correction_m.get());
+
+        write_counter(&mut out, "scout_range_stops_total", "Driving ticks stopped by a range sensor", &self.range_stops);
+        write_counter(&mut out, "scout_hazard_stops_total", "Driving ticks stopped by an external hazard", &self.hazard_stops);
+        write_counter(&mut out, "scout_hazard_cautions_total", "Driving ticks held to the caution speed by an external hazard", &self.hazard_cautions);
+        write_labeled(&mut out, "scout_hazard_reports_total", "External hazard reports by result (accepted, refreshed, invalid, rate_limited, full, unauthorized, disabled)", &self.hazard_reports);
+        write_gauge(&mut out, "scout_hazards_active", "External hazards not yet expired", self.hazards_active.get() as f64);
+        write_labeled(&mut out, "scout_rule_matches_total", "Control ticks decided by each detection rule", &self.rule_matches);
+        write_counter(&mut out, "scout_vision_stale_total", "Times frames stopped arriving for longer than vision.max_frame_age_ms", &self.vision_stale);
+        write_gauge(&mut out, "scout_vision_brightness", "Mean brightness (0-255) of the last frame sampled for vision profile switching", self.vision_brightness.get());
+        write_gauge(&mut out, "scout_vision_clipped_fraction", "Share of the last frame sampled for exposure feedback that was clipped (0-1)", self.vision_clipped_fraction.get());
+        write_gauge(&mut out, "scout_vision_exposure_ev", "Exposure offset the bridge has been asked for, in EV", self.vision_exposure_ev.get());
+        write_labeled(&mut out, "scout_vision_profile_switches_total", "Vision profile switches by profile switched to", &self.vision_profile_switches);
+        write_labeled(&mut out, "scout_mask_distances_total", "Masked detections by whether the distance came from the mask or the box", &self.mask_distances);
+        write_counter(&mut out, "scout_masks_dropped_total", "Segmentation masks dropped to stay within vision.mask_budget_ms", &self.masks_dropped);
+        write_labeled(&mut out, "scout_range_failures_total", "Range sensor failures by sensor", &self.range_failures);
+        write_labeled(&mut out, "scout_drop_offs_total", "Drop-offs detected by source", &self.drop_offs);
+
+        write_gauge(&mut out, "scout_ros_connected", "Whether the rosbridge connection is up", self.ros_connected.get() as f64);
+        write_labeled(&mut out, "scout_ros_published_total", "Messages published to ROS by topic", &self.ros_published);
+        write_labeled(&mut out, "scout_ros_cmd_vel_total", "cmd_vel messages received by result (applied, ignored, invalid)", &self.ros_cmd_vel);
+        write_labeled(&mut out, "scout_teleop_commands_total", "Gamepad axis commands by result (applied, released, gap, stale, invalid, ignored)", &self.teleop_commands);
+
+        write_labeled_histogram(&mut out, "scout_snapshot_encode_seconds", "Time to draw and encode a snapshot or stream frame by format", &self.snapshot_encode);
+        write_counter(&mut out, "scout_snapshot_frames_skipped_total", "Stream frames skipped because every encoder was busy", &self.snapshot_frames_skipped);
+
+        write_labeled(&mut out, "scout_annunciator_alerts_total", "Alert patterns played by alert", &self.annunciator_alerts);
+
+        write_counter(&mut out, "scout_bridge_parse_errors_total", "Vision bridge lines that failed to parse", &self.bridge_parse_errors);
+        write_counter(&mut out, "scout_bridge_shm_frames_total", "Frames whose JPEG came through shared memory", &self.bridge_shm_frames);
+        write_counter(&mut out, "scout_bridge_shm_torn_total", "Shared-memory frames whose slot was rewritten while it was read", &self.bridge_shm_torn);
+        write_counter(&mut out, "scout_bridge_raw_frames_total", "Shared-memory frames that came as raw YUV420 rather than JPEG", &self.bridge_raw_frames);
+        write_labeled(&mut out, "scout_frame_conversions_total", "Frame pictures converted on the rover by what they were converted to (rgb, bgr, jpeg)", &self.frame_conversions);
+        write_gauge(&mut out, "scout_bridge_protocol_version", "Protocol version the vision bridge speaks (0 before its hello)", self.bridge_protocol_version.get() as f64);
+        write_labeled(&mut out, "scout_blackbox_records_total", "Records written to the black box by kind", &self.blackbox_records);
+        write_counter(&mut out, "scout_blackbox_dropped_total", "Black box records dropped from a full queue or a failed write", &self.blackbox_dropped);
+        write_counter(&mut out, "scout_blackbox_pruned_total", "Oldest black box records pruned to stay under blackbox.max_mb", &self.blackbox_pruned);
+        write_gauge(&mut out, "scout_blackbox_bytes", "Space used by black box records", self.blackbox_bytes.get() as f64);
+        write_counter(&mut out, "scout_logs_dropped_total", "Log events left out of the in-memory buffer or a live tail rather than wait", &self.logs_dropped);
+        write_gauge(&mut out, "scout_log_subscribers", "WebSocket clients tailing the logs", self.log_subscribers.get() as f64);
+
+        write_gauge(&mut out, "scout_cpu_temp_celsius", "CPU temperature", self.cpu_temp_c.get());
+        write_gauge(&mut out, "scout_throttle_flags", "Firmware throttle flags, as vcgencmd get_throttled reports them", self.throttle_flags.get() as f64);
+        write_gauge(&mut out, "scout_thermal_degraded", "Whether the rover is running degraded to shed heat", self.thermal_degraded.get() as f64);
+
+        write_gauge(&mut out, "scout_interlock_asserted", "Whether the motor power interlock output is held high", self.interlock_asserted.get() as f64);
+        write_labeled(&mut out, "scout_interlock_drops_total", "Times the interlock output dropped, by first reason (watchdog, estop, vision_stale, shutdown, fault)", &self.interlock_drops);
+
+        write_gauge(&mut out, "scout_power_asleep", "Whether the rover is asleep to save power while parked", self.power_asleep.get() as f64);
+        write_labeled(&mut out, "scout_power_wakes_total", "Wakes from sleep by what woke the rover", &self.power_wakes);
+        write_gauge(&mut out, "scout_power_resume_seconds", "How long the last wake took until frames and the self-test were back", self.power_resume_s.get());
+
+        out
+    }
+}
+
+fn write_counter(out: &mut String, name: &str, help: &str, counter: &Counter) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, counter.get());
+}
+
+fn write_gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn write_labeled(out: &mut String, name: &str, help: &str, counter: &LabeledCounter) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let mut values: Vec<(String, u64)> = counter.values.iter()
+        .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+        .collect();
+    values.sort();
+    for (value, count) in values {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, counter.label, escape_label(&value), count);
+    }
+}
+
+fn write_histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    write_histogram_series(out, name, "", histogram);
+}
+
+fn write_labeled_histogram(out: &mut String, name: &str, help: &str, histogram: &LabeledHistogram) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    let mut values: Vec<String> = histogram.values.iter().map(|entry| entry.key().clone()).collect();
+    values.sort();
+    for value in values {
+        if let Some(series) = histogram.values.get(&value) {
+            let labels = format!("{}=\"{}\",", histogram.label, escape_label(&value));
+            write_histogram_series(out, name, &labels, &series);
+        }
+    }
+}
+
+// `labels` is empty or ends in a comma, ready for le to follow.
+fn write_histogram_series(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    let mut cumulative = 0;
+    for (bound, bucket) in histogram.bounds.iter().zip(&histogram.buckets) {
+        cumulative += bucket.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{{}le=\"{}\"}} {}", name, labels, bound, cumulative);
+    }
+    let count = histogram.count.load(Ordering::Relaxed);
+    let _ = writeln!(out, "{}_bucket{{{}le=\"+Inf\"}} {}", name, labels, count);
+    let selector = labels.strip_suffix(',').map_or(String::new(), |l| format!("{{{}}}", l));
+    let _ = writeln!(out, "{}_sum{} {}", name, selector, histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0);
+    let _ = writeln!(out, "{}_count{} {}", name, selector, count);
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -1,59 +1,309 @@
 // src/motor_control.rs
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::blackbox::{RecordKind, BLACKBOX};
+use crate::clock::CLOCK;
+use crate::config::MotorConfig;
+use crate::events::{Severity, EVENTS};
+use crate::health::{HealthHandle, HealthRegistry};
+use crate::interlock::{InterlockStatus, INTERLOCK};
+use crate::metrics::METRICS;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MotorStatus {
+    // What the wheels are doing; the last command asked for target_speed.
     pub speed: f32,
+    #[serde(default)]
+    pub target_speed: f32,
     pub heading: f32,
     pub enabled: bool,
+    // Below motors.max_speed while something else holds the rover back.
+    #[serde(default)]
+    pub speed_cap: Option<f32>,
+    // The motor power interlock, when one is configured.
+    #[serde(default)]
+    pub interlock: Option<InterlockStatus>,
+    #[serde(default)]
+    pub heading_hold: HeadingHoldStatus,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HeadingHoldStatus {
+    // The fused heading being held, set while driving forward with an IMU.
+    pub setpoint_deg: Option<f32>,
+    // The last fused heading, and how far it is off the setpoint;
+    // positive needs a turn right.
+    pub heading_deg: Option<f32>,
+    pub error_deg: f32,
+    // Added to the left wheel's speed and taken off the right's, in m/s.
+    pub correction: f32,
+}
+
+const AUDIT_ENTRIES: usize = 200;
+
+// One applied command, tied back to the frame that led to it.
+#[derive(Debug, Clone, Serialize)]
+pub struct MotorAuditEntry {
+    pub t_ms: u64,
+    pub command: &'static str,
+    pub speed: f32,
+    pub heading: f32,
+    pub capture_ms: Option<u64>,
+    pub latency_ms: Option<u64>,
 }
 
+// Commands set the target speed. advance() then moves the wheels toward
+// it at the configured rates, once per control tick, so the rover takes
+// time and distance to get going and to stop. Turns are immediate. While
+// driving forward, heading hold steers the wheels apart each tick to keep
+// the fused heading where it was when Forward was commanded.
 pub struct MotorController {
     speed: f32,
+    target_speed: f32,
     heading: f32,
     enabled: bool,
+    emergency_stopped: bool,
+    limits: MotorConfig,
+    speed_cap: Option<f32>,
+    health: HealthHandle,
+    source_capture_ms: Option<u64>,
+    audit: VecDeque<MotorAuditEntry>,
+    // The last command was Forward, so heading hold may hold.
+    holding: bool,
+    // Whether the last command was a turn, which the wheels may still be
+    // making.
+    turning: bool,
+    // Fused heading and turn rate from the IMU, for this tick.
+    measured_heading: Option<(f32, f32)>,
+    hold: HeadingHoldStatus,
 }
 
 impl MotorController {
-    pub fn new() -> Result<Self> {
-        println!(" Initializing Motor Controller...");
+    pub fn new(health: &HealthRegistry, limits: MotorConfig) -> Result<Self> {
+        info!(target: "motors", "Initializing Motor Controller...");
         Ok(Self {
             speed: 0.0,
+            target_speed: 0.0,
             heading: 0.0,
             enabled: false,
+            emergency_stopped: false,
+            limits,
+            speed_cap: None,
+            health: health.register("motor_controller", Some(Duration::from_secs(1))),
+            source_capture_ms: None,
+            audit: VecDeque::with_capacity(AUDIT_ENTRIES),
+            holding: false,
+            turning: false,
+            measured_heading: None,
+            hold: HeadingHoldStatus::default(),
         })
     }
     
+    // New caps apply immediately, including to a speed that is already set.
+    pub fn apply_config(&mut self, limits: MotorConfig) {
+        self.limits = limits;
+        self.clamp_target();
+        self.report_health();
+    }
+
+    // Holds the rover under `cap` on top of motors.max_speed until it is
+    // cleared with None.
+    pub fn set_speed_cap(&mut self, cap: Option<f32>) {
+        self.speed_cap = cap;
+        self.clamp_target();
+    }
+
+    fn get_max_speed(&self) -> f32 {
+        self.speed_cap.map_or(self.limits.max_speed, |cap| cap.min(self.limits.max_speed))
+    }
+
+    fn clamp_target(&mut self) {
+        let max = self.get_max_speed();
+        self.target_speed = self.target_speed.clamp(-max, max);
+    }
+    
     pub fn move_forward(&mut self, speed: f32) {
-        self.speed = speed.clamp(0.0, 1.0);
-        println!("➡ Moving forward at speed {:.1}", self.speed);
+        self.target_speed = speed.clamp(0.0, self.get_max_speed());
+        self.set_holding(true, false);
+        self.clear_emergency_stop();
+        debug!(target: "motors", "➡ Moving forward at speed {:.1}", self.target_speed);
+        self.record("forward");
+        self.report_health();
     }
     
     pub fn move_backward(&mut self, speed: f32) {
-        self.speed = -speed.clamp(0.0, 1.0);
-        println!(" Moving backward at speed {:.1}", self.speed);
+        self.target_speed = -speed.clamp(0.0, self.get_max_speed());
+        self.set_holding(false, false);
+        self.clear_emergency_stop();
+        debug!(target: "motors", "Moving backward at speed {:.1}", self.target_speed);
+        self.record("backward");
+        self.report_health();
     }
     
     pub fn turn_left(&mut self, angle: f32) {
+        let angle = angle.clamp(0.0, self.limits.max_turn_deg);
         self.heading -= angle;
-        println!(" Turning left by {:.1}°", angle);
+        self.set_holding(false, true);
+        self.clear_emergency_stop();
+        debug!(target: "motors", "Turning left by {:.1}°", angle);
+        self.record("turn_left");
+        self.report_health();
     }
     
     pub fn turn_right(&mut self, angle: f32) {
+        let angle = angle.clamp(0.0, self.limits.max_turn_deg);
         self.heading += angle;
-        println!(" Turning right by {:.1}°", angle);
+        self.set_holding(false, true);
+        self.clear_emergency_stop();
+        debug!(target: "motors", "Turning right by {:.1}°", angle);
+        self.record("turn_right");
+        self.report_health();
     }
     
+    fn clear_emergency_stop(&mut self) {
+        if self.emergency_stopped {
+            self.emergency_stopped = false;
+            INTERLOCK.set_estop_latched(false);
+        }
+    }
+
     pub fn stop(&mut self) {
-        self.speed = 0.0;
-        println!(" Stopped");
+        self.target_speed = 0.0;
+        self.set_holding(false, false);
+        debug!(target: "motors", "Stopped");
+        self.record("stop");
+        self.report_health();
     }
     
     pub fn emergency_stop(&mut self) {
-        self.speed = 0.0;
+        let was_moving = self.speed;
+        self.target_speed = 0.0;
         self.enabled = false;
-        println!(" EMERGENCY STOP!");
+        self.set_holding(false, false);
+        if !self.emergency_stopped {
+            warn!(target: "motors", "EMERGENCY STOP!");
+            self.emergency_stopped = true;
+            INTERLOCK.set_estop_latched(true);
+            EVENTS.publish(Severity::Critical, "motors", "Emergency stop triggered", serde_json::json!({
+                "speed_before": was_moving,
+                "heading": self.heading,
+            }));
+        } else {
+            debug!(target: "motors", "Emergency stop held");
+        }
+        self.record("emergency_stop");
+        self.report_health();
+    }
+    
+    // Brings the wheels `dt` closer to the target speed. A reversal slows
+    // to a standstill first, and an emergency stop brakes hardest.
+    pub fn advance(&mut self, dt: Duration) {
+        let reversing = self.speed != 0.0 && self.target_speed.signum() != self.speed.signum();
+        let goal = if reversing { 0.0 } else { self.target_speed };
+        let rate = if self.emergency_stopped {
+            self.limits.emergency_decel_m_s2
+        } else if goal.abs() < self.speed.abs() {
+            self.limits.decel_m_s2
+        } else {
+            self.limits.accel_m_s2
+        };
+        let step = rate * dt.as_secs_f32();
+        self.speed += (goal - self.speed).clamp(-step, step);
+        METRICS.motor_speed.set(self.speed as f64);
+        self.update_heading_hold();
+    }
+
+    fn set_holding(&mut self, holding: bool, turning: bool) {
+        self.holding = holding;
+        self.turning = turning;
+        if !holding {
+            self.hold.setpoint_deg = None;
+        }
+    }
+
+    // The fused heading and turn rate advance() steers by, or None without
+    // a working IMU.
+    pub fn set_measured_heading(&mut self, measured: Option<(f32, f32)>) {
+        self.measured_heading = measured;
+    }
+
+    // A PD loop on the heading error. The first tick with a heading after
+    // Forward takes it as the setpoint.
+    fn update_heading_hold(&mut self) {
+        self.hold.heading_deg = self.measured_heading.map(|(heading, _)| heading);
+        let measured = self.measured_heading.filter(|_| self.holding && self.limits.heading_hold && !self.emergency_stopped);
+        let Some((heading, rate)) = measured else {
+            self.hold.setpoint_deg = None;
+            self.hold.error_deg = 0.0;
+            self.hold.correction = 0.0;
+            METRICS.heading_error_deg.set(0.0);
+            METRICS.heading_correction.set(0.0);
+            return;
+        };
+        let setpoint = *self.hold.setpoint_deg.get_or_insert(heading);
+        let error = (setpoint - heading + 540.0).rem_euclid(360.0) - 180.0;
+        // Never so much that a wheel would turn backwards.
+        let cap = self.limits.heading_max_correction.min(self.speed.abs());
+        let correction = (self.limits.heading_kp * error - self.limits.heading_kd * rate).clamp(-cap, cap);
+        self.hold.error_deg = error;
+        self.hold.correction = correction;
+        METRICS.heading_error_deg.set(error as f64);
+        METRICS.heading_correction.set(correction as f64);
+    }
+
+    // Capture time of the frame the next command responds to. Only that
+    // command is credited with it, so its latency is photon to motor.
+    pub fn set_source_capture(&mut self, capture_ms: Option<u64>) {
+        self.source_capture_ms = capture_ms;
+    }
+
+    fn record(&mut self, command: &'static str) {
+        METRICS.motor_commands.inc(command);
+
+        let now = CLOCK.now_ms();
+        let capture_ms = self.source_capture_ms.take();
+        let latency_ms = capture_ms.map(|c| now.saturating_sub(c));
+        if let Some(latency) = latency_ms {
+            METRICS.e2e_latency.observe(Duration::from_millis(latency));
+            METRICS.last_e2e_latency_ms.set(latency as f64);
+        }
+
+        // The same command every tick is one record in the black box, as
+        // long as the speed holds.
+        let changed = self.audit.back().map_or(true, |last| last.command != command || last.speed != self.target_speed);
+        if changed {
+            BLACKBOX.record(RecordKind::Command, serde_json::json!({
+                "command": command,
+                "speed": self.target_speed,
+                "heading": self.heading,
+                "latency_ms": latency_ms,
+            }));
+        }
+
+        if self.audit.len() == AUDIT_ENTRIES {
+            self.audit.pop_front();
+        }
+        self.audit.push_back(MotorAuditEntry {
+            t_ms: now,
+            command,
+            speed: self.target_speed,
+            heading: self.heading,
+            capture_ms,
+            latency_ms,
+        });
+    }
+
+    pub fn get_audit_log(&self) -> Vec<MotorAuditEntry> {
+        self.audit.iter().cloned().collect()
+    }
+
+    fn report_health(&self) {
+        METRICS.motor_speed.set(self.speed as f64);
+        self.health.beat_with(self.get_status());
     }
     
     pub fn get_speed(&self) -> f32 {
@@ -63,12 +313,26 @@ impl MotorController {
     pub fn get_heading(&self) -> f32 {
         self.heading
     }
+
+    // Left and right, with heading hold's correction.
+    pub fn get_wheel_speeds(&self) -> (f32, f32) {
+        (self.speed + self.hold.correction, self.speed - self.hold.correction)
+    }
+
+    // No speed, none asked for and no turn underway.
+    pub fn is_still(&self) -> bool {
+        self.speed == 0.0 && self.target_speed == 0.0 && !self.turning
+    }
     
     pub fn get_status(&self) -> MotorStatus {
         MotorStatus {
             speed: self.speed,
+            target_speed: self.target_speed,
             heading: self.heading,
             enabled: self.enabled,
+            speed_cap: self.speed_cap,
+            interlock: INTERLOCK.get_status(),
+            heading_hold: self.hold.clone(),
         }
     }
 }
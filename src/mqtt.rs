@@ -0,0 +1,320 @@
+// src/mqtt.rs
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tracing::info;
+
+use crate::config::MqttConfig;
+use crate::events::RoverEvent;
+use crate::metrics::METRICS;
+use crate::motor_control::MotorStatus;
+use crate::pathfinding::PathPlanner;
+use crate::state_machine::{RoverState, StateHandle, StateStatus, Trigger};
+use crate::vision::Detection;
+
+#[derive(Debug, Clone)]
+pub struct MqttMessage {
+    pub topic: String,
+    pub payload: String,
+    pub retain: bool,
+}
+
+// Messages waiting for the broker. Pushing never waits: when the queue is
+// full the oldest message goes, so a dead connection costs old telemetry
+// and nothing else.
+pub struct Outbox {
+    queue: Mutex<VecDeque<MqttMessage>>,
+    capacity: usize,
+    ready: Notify,
+}
+
+impl Outbox {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity: capacity.max(1),
+            ready: Notify::new(),
+        }
+    }
+
+    pub fn push(&self, message: MqttMessage) {
+        let mut queue = self.queue.lock();
+        if queue.len() >= self.capacity {
+            queue.pop_front();
+            METRICS.mqtt_dropped.inc();
+        }
+        queue.push_back(message);
+        drop(queue);
+        self.ready.notify_one();
+    }
+
+    pub async fn pop(&self) -> MqttMessage {
+        loop {
+            if let Some(message) = self.queue.lock().pop_front() {
+                return message;
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.lock().is_empty()
+    }
+}
+
+#[derive(Serialize)]
+struct DetectionEvent<'a> {
+    class: &'a str,
+    confidence: f32,
+    distance_m: f32,
+    action: &'static str,
+    capture_ms: u64,
+}
+
+// Turns what the rover currently sees and does into messages: a detection
+// event per class at most every detection_debounce_s, the state whenever
+// it changes, and motor status every status_interval_s. All of them are
+// retained so a subscriber gets the latest on connect.
+pub struct Publisher {
+    prefix: String,
+    debounce: Duration,
+    status_interval: Duration,
+    last_detection: HashMap<String, Instant>,
+    last_state: Option<RoverState>,
+    last_status: Option<Instant>,
+}
+
+impl Publisher {
+    pub fn new(config: &MqttConfig) -> Self {
+        Self {
+            prefix: config.topic_prefix.clone(),
+            debounce: Duration::from_secs_f32(config.detection_debounce_s),
+            status_interval: Duration::from_secs_f32(config.status_interval_s),
+            last_detection: HashMap::new(),
+            last_state: None,
+            last_status: None,
+        }
+    }
+
+    pub fn topic(&self, suffix: &str) -> String {
+        format!("{}/{}", self.prefix, suffix)
+    }
+
+    pub fn sample(&mut self, detections: &[Detection], state: &StateStatus, motors: &MotorStatus, outbox: &Outbox) {
+        for detection in detections {
+            let due = self.last_detection.get(&detection.class_name)
+                .map_or(true, |last| last.elapsed() >= self.debounce);
+            if !due {
+                continue;
+            }
+            self.last_detection.insert(detection.class_name.clone(), Instant::now());
+            let event = DetectionEvent {
+                class: &detection.class_name,
+                confidence: detection.confidence,
+                distance_m: detection.distance_estimate,
+                action: detection.action.as_str(),
+                capture_ms: detection.capture_ms,
+            };
+            self.push(outbox, &format!("detection/{}", topic_segment(&detection.class_name)), &event);
+        }
+
+        if self.last_state != Some(state.state) {
+            self.last_state = Some(state.state);
+            self.push(outbox, "state", state);
+        }
+
+        if self.last_status.map_or(true, |last| last.elapsed() >= self.status_interval) {
+            self.last_status = Some(Instant::now());
+            // The rover doesn't report battery level yet.
+            self.push(outbox, "motors", motors);
+        }
+    }
+
+    fn push(&self, outbox: &Outbox, suffix: &str, payload: &impl Serialize) {
+        let Ok(payload) = serde_json::to_string(payload) else { return };
+        outbox.push(MqttMessage { topic: self.topic(suffix), payload, retain: true });
+    }
+}
+
+// Zone crossings go out as they happen on <prefix>/zone/<name>. They are
+// events rather than state, so they aren't retained.
+pub fn zone_message(prefix: &str, event: &RoverEvent) -> Option<MqttMessage> {
+    if event.source != "zones" {
+        return None;
+    }
+    let zone = event.data.get("zone")?.as_str()?;
+    Some(MqttMessage {
+        topic: format!("{}/zone/{}", prefix, topic_segment(zone)),
+        payload: event.data.to_string(),
+        retain: false,
+    })
+}
+
+// Class names go into topics, where '/', '+' and '#' mean something.
+fn topic_segment(name: &str) -> String {
+    name.chars().map(|c| if matches!(c, '/' | '+' | '#' | ' ') { '_' } else { c }).collect()
+}
+
+// Commands arrive on <prefix>/cmd/<name>; the payload is ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MqttCommand {
+    Stop,
+    Start,
+    Pause,
+    AcknowledgeFault,
+    Dock,
+}
+
+impl MqttCommand {
+    pub fn from_topic(prefix: &str, topic: &str) -> Option<Self> {
+        let name = topic.strip_prefix(prefix)?.strip_prefix("/cmd/")?;
+        match name {
+            "stop" => Some(MqttCommand::Stop),
+            "start" => Some(MqttCommand::Start),
+            "pause" => Some(MqttCommand::Pause),
+            "acknowledge_fault" => Some(MqttCommand::AcknowledgeFault),
+            "dock" => Some(MqttCommand::Dock),
+            _ => None,
+        }
+    }
+
+    // The same requests the dashboard makes; the state machine decides
+    // whether they are allowed.
+    pub fn apply(self, state: &StateHandle, planner: &RwLock<PathPlanner>) {
+        info!(target: "mqtt", "Command {:?}", self);
+        match self {
+            MqttCommand::Stop => state.emergency_stop("Emergency stop from MQTT"),
+            MqttCommand::Start => state.request(Trigger::StartAutonomous),
+            MqttCommand::Pause => state.request(Trigger::Pause),
+            MqttCommand::AcknowledgeFault => state.request(Trigger::AcknowledgeFault),
+            MqttCommand::Dock => {
+                let mut planner = planner.write();
+                planner.set_docking(true);
+                if planner.is_docking() {
+                    state.request(Trigger::StartAutonomous);
+                }
+            }
+        }
+    }
+}
+
+// What the MQTT tasks read from and act on.
+pub struct MqttHandles {
+    pub path_planner: Arc<RwLock<PathPlanner>>,
+    pub state: StateHandle,
+}
+
+#[cfg(feature = "mqtt")]
+pub fn spawn(config: &MqttConfig, client_id: &str, handles: MqttHandles) {
+    use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+    use tracing::warn;
+
+    const SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+
+    if !config.enabled {
+        return;
+    }
+    let mut options = MqttOptions::new(client_id, &config.broker_host, config.broker_port);
+    options.set_keep_alive(Duration::from_secs(10));
+    let mut publisher = Publisher::new(config);
+    let online = publisher.topic("online");
+    options.set_last_will(LastWill::new(&online, "false", QoS::AtLeastOnce, true));
+    if let Some(username) = &config.username {
+        let password = config.password_env.as_deref()
+            .and_then(|name| std::env::var(name).ok())
+            .unwrap_or_default();
+        options.set_credentials(username, password);
+    }
+    let (client, mut eventloop) = AsyncClient::new(options, 16);
+    let outbox = Arc::new(Outbox::new(config.queue_size));
+    info!(target: "mqtt", "Publishing to {}:{} under {}/", config.broker_host, config.broker_port, config.topic_prefix);
+
+    // Sampling only reads the world state and never touches the network.
+    let samples = outbox.clone();
+    let MqttHandles { path_planner, state } = handles;
+    let sample_state = state.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(SAMPLE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let world = sample_state.get_world();
+            publisher.sample(&world.detections, &world.state, &world.motors, &samples);
+        }
+    });
+
+    let zone_events = outbox.clone();
+    let zone_prefix = config.topic_prefix.clone();
+    tokio::spawn(async move {
+        let mut events = crate::events::EVENTS.subscribe();
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Some(message) = zone_message(&zone_prefix, &event) {
+                        zone_events.push(message);
+                    }
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+
+    let sender = client.clone();
+    let queued = outbox.clone();
+    tokio::spawn(async move {
+        loop {
+            let message = queued.pop().await;
+            if let Err(e) = sender.publish(message.topic, QoS::AtLeastOnce, message.retain, message.payload).await {
+                warn!(target: "mqtt", "Publish failed: {}", e);
+            }
+        }
+    });
+
+    let prefix = config.topic_prefix.clone();
+    tokio::spawn(async move {
+        // None until the first attempt, so an unreachable broker is
+        // reported once rather than every second.
+        let mut connected = None;
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    connected = Some(true);
+                    METRICS.mqtt_connected.set(1);
+                    info!(target: "mqtt", "Connected to broker");
+                    // Sessions are clean, so subscriptions go with the connection.
+                    let _ = client.subscribe(format!("{}/cmd/+", prefix), QoS::AtLeastOnce).await;
+                    outbox.push(MqttMessage { topic: online.clone(), payload: "true".to_string(), retain: true });
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    match MqttCommand::from_topic(&prefix, &publish.topic) {
+                        Some(command) => command.apply(&state, &path_planner),
+                        None => warn!(target: "mqtt", "Unknown command topic {}", publish.topic),
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    if connected != Some(false) {
+                        warn!(target: "mqtt", "No broker connection: {}", e);
+                        connected = Some(false);
+                        METRICS.mqtt_connected.set(0);
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(not(feature = "mqtt"))]
+pub fn spawn(config: &MqttConfig, _client_id: &str, _handles: MqttHandles) {
+    if config.enabled {
+        tracing::warn!(target: "mqtt", "mqtt.enabled is set but the binary was built without the `mqtt` feature");
+    }
+}
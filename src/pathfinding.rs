@@ -1,61 +1,1410 @@
 // src/pathfinding.rs
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
-use crate::vision::Detection;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::clock::CLOCK;
+use crate::config::{Landmark, PlannerConfig, Zone, ZonePolicy};
+use crate::envelope::SpeedEnvelope;
+use crate::events::{Severity, EVENTS};
+use crate::gps::{GpsFix, LocalFrame};
+use crate::health::{HealthHandle, HealthRegistry};
+use crate::localization::{LocalizationStatus, PositionFilter};
+use crate::mapping::{Cell, GridDiff, GridSnapshot, OccupancyGrid, OccupancyValues, RoverPose};
+use crate::metrics::METRICS;
+use crate::motor_control::MotorStatus;
+use crate::range::RangeReading;
+use crate::slip::{SlipEstimator, TractionStatus, VisualTravel};
+use crate::vision::{CameraModel, Detection, FiducialDetection, FreeSpace, TagPose, FRAME_WIDTH_PX};
+use crate::zones::{self, Occupant, ZoneStatus, ZoneTracker};
+
+const MAX_MAPPING_RANGE_M: f32 = 10.0;
+// How much each frame's measured velocity moves an obstacle's estimate.
+const VELOCITY_SMOOTHING: f32 = 0.3;
+// A dock sighting older than this no longer steers; the rover waits.
+const DOCK_SIGHTING_TTL: Duration = Duration::from_secs(1);
+// Within this of the tag's bearing the rover drives instead of turning.
+const DOCK_ALIGN_DEG: f32 = 5.0;
+// Free space from an older frame than this is no longer trusted.
+const FREE_SPACE_TTL: Duration = Duration::from_millis(500);
+// How far to either side the rover looks for a way around a forbidden zone.
+const ZONE_AVOID_DEG: f32 = 45.0;
+// A follow target this much beyond follow_distance_m is close enough, so
+// the rover doesn't creep back and forth.
+const FOLLOW_DEADBAND_M: f32 = 0.2;
+// Closer than this beyond follow_distance_m the rover eases off, down to a
+// quarter of follow_speed.
+const FOLLOW_SLOWDOWN_M: f32 = 1.0;
+// A drop-off goes on the map this far out from where the rover was, about
+// the front of the chassis.
+const DROP_OFF_MARK_M: f32 = 0.3;
+// Turned this far past the edge of a drop-off's arc, so the next tick
+// doesn't land back on it.
+const DROP_OFF_TURN_MARGIN_DEG: f32 = 5.0;
 
 #[derive(Debug, Clone)]
+#[allow(dead_code)]
 pub enum NavigationCommand {
     Forward(f32),
+    // The planner only reverses to back away from a drop-off, or out of a
+    // passage it can't turn in.
+    Backward(f32),
     TurnLeft(f32),
     TurnRight(f32),
     Stop,
 }
 
+impl NavigationCommand {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NavigationCommand::Forward(_) => "forward",
+            NavigationCommand::Backward(_) => "backward",
+            NavigationCommand::TurnLeft(_) => "turn_left",
+            NavigationCommand::TurnRight(_) => "turn_right",
+            NavigationCommand::Stop => "stop",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PathPoint {
     pub x: f32,
     pub y: f32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HazardType {
+    Obstacle,
+    // No floor where there should be some. Kept until the map is reset.
+    DropOff,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObstacleMotion {
+    Static,
+    Dynamic,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RememberedObstacle {
+    pub class_name: String,
+    pub hazard: HazardType,
+    pub motion: ObstacleMotion,
+    pub track_id: Option<u32>,
+    pub x: f32,
+    pub y: f32,
+    // Smoothed map velocity, m/s; zero until seen in two frames.
+    pub vx: f32,
+    pub vy: f32,
+    // Frames it was seen in.
+    pub observations: u32,
+    pub age_s: f32,
+    #[serde(skip)]
+    last_seen: Instant,
+    #[serde(skip)]
+    last_capture_ms: Option<u64>,
+    // Since when the camera has been looking at it without seeing it.
+    #[serde(skip)]
+    unseen_in_view: Option<Instant>,
+}
+
+impl RememberedObstacle {
+    // Drop-offs don't go anywhere; anything seen is dynamic until it has
+    // shown otherwise.
+    fn new(class_name: String, hazard: HazardType, x: f32, y: f32) -> Self {
+        Self {
+            class_name,
+            hazard,
+            motion: if hazard == HazardType::DropOff { ObstacleMotion::Static } else { ObstacleMotion::Dynamic },
+            track_id: None,
+            x,
+            y,
+            vx: 0.0,
+            vy: 0.0,
+            observations: 0,
+            age_s: 0.0,
+            last_seen: Instant::now(),
+            last_capture_ms: None,
+            unseen_in_view: None,
+        }
+    }
+
+    // Takes a sighting at (x, y). A frame already counted, as on a tick
+    // without a new one, only keeps the obstacle fresh.
+    fn observe(&mut self, x: f32, y: f32, detection: &Detection, config: &PlannerConfig) {
+        self.last_seen = Instant::now();
+        self.track_id = detection.track_id.or(self.track_id);
+        if self.last_capture_ms.is_some_and(|last| detection.capture_ms <= last) {
+            return;
+        }
+        if let Some(last) = self.last_capture_ms {
+            let dt = (detection.capture_ms - last) as f32 / 1000.0;
+            self.vx += VELOCITY_SMOOTHING * ((x - self.x) / dt - self.vx);
+            self.vy += VELOCITY_SMOOTHING * ((y - self.y) / dt - self.vy);
+        }
+        self.x = x;
+        self.y = y;
+        self.last_capture_ms = Some(detection.capture_ms);
+        self.observations += 1;
+
+        // Moving again undoes a static classification, which then has to
+        // be earned over again.
+        let speed = self.vx.hypot(self.vy);
+        let motion = if speed > config.static_max_speed_m_s {
+            self.observations = self.observations.min(1);
+            ObstacleMotion::Dynamic
+        } else if self.observations >= config.static_min_observations {
+            ObstacleMotion::Static
+        } else {
+            self.motion
+        };
+        if motion != self.motion {
+            debug!(target: "planner", "{} at ({:.2}, {:.2}) is now {:?}, moving at {:.2} m/s", self.class_name, x, y, motion, speed);
+            self.motion = motion;
+        }
+    }
+
+    // Where it will be `t_s` from now, going on as it was last seen.
+    fn predict(&self, t_s: f32) -> (f32, f32) {
+        if self.motion == ObstacleMotion::Static {
+            return (self.x, self.y);
+        }
+        let t = self.last_seen.elapsed().as_secs_f32() + t_s;
+        (self.x + self.vx * t, self.y + self.vy * t)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MapState {
+    pub pose: RoverPose,
+    pub goal: Option<PathPoint>,
+    pub path: Vec<PathPoint>,
+    pub obstacles: Vec<RememberedObstacle>,
+    pub zones: Vec<ZoneStatus>,
+}
+
+// The last time the dock tag was seen, with the heading at the time so
+// turns made since can be taken off its bearing.
+#[derive(Debug, Clone, Copy)]
+struct DockSighting {
+    pose: TagPose,
+    heading_deg: f32,
+    seen: Instant,
+}
+
+// The track being followed, as of its last sighting.
+#[derive(Debug, Clone)]
+struct FollowTarget {
+    track_id: u32,
+    class_name: String,
+    distance_m: f32,
+    bearing_deg: f32,
+    heading_deg: f32,
+    position: Option<(f32, f32)>,
+    seen: Instant,
+    // In the latest detections.
+    visible: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FollowState {
+    Locked,
+    // Out of view; the rover waits for it to come back.
+    Lost,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FollowStatus {
+    pub state: FollowState,
+    pub track_id: u32,
+    pub class_name: String,
+    // As last seen.
+    pub distance_m: f32,
+    pub target_distance_m: f32,
+    pub lost_s: Option<f32>,
+}
+
+// Where the rover was and which way the drop-off lay when it was seen.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DropOff {
+    pub x: f32,
+    pub y: f32,
+    pub heading_deg: f32,
+}
+
+// What a checkpoint keeps of what the planner remembers about the world.
+// Obstacles come back without their tracks or velocities.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlannerMemory {
+    pub obstacles: Vec<ObstacleMemory>,
+    pub drop_offs: Vec<DropOff>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObstacleMemory {
+    pub class_name: String,
+    pub hazard: HazardType,
+    pub motion: ObstacleMotion,
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DropOffStatus {
+    // "camera" or the name of the range sensor.
+    pub source: String,
+    pub backed_off_m: f32,
+    pub backoff_m: f32,
+}
+
+// Where the rover ends up going on with its current motion, on the map and
+// on the camera image.
+#[derive(Debug, Clone, Serialize)]
+pub struct Trajectory {
+    pub speed_m_s: f32,
+    // Clockwise, like headings.
+    pub turn_rate_deg_s: f32,
+    pub horizon_s: f32,
+    // From where the rover is now, one point every planner.rollout_step_s.
+    pub world: Vec<PathPoint>,
+    // Each of those in captured-frame pixels, or None when it isn't in
+    // front of the camera.
+    pub image: Vec<Option<(f32, f32)>>,
+    pub hit: Option<TrajectoryHit>,
+}
+
+// The first point of a trajectory that comes within
+// planner.rollout_clearance_m of a remembered obstacle or drop-off.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrajectoryHit {
+    pub class_name: String,
+    pub hazard: HazardType,
+    // Into the trajectory's points.
+    pub index: usize,
+    pub point: PathPoint,
+    // How long until the rover gets there.
+    pub t_s: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GpsState {
+    // No fix received yet, or GPS isn't enabled.
+    None,
+    Fused,
+    // Fixes are arriving but aren't good enough to use.
+    NoFix,
+    PoorHdop,
+    // Nothing for planner.gps_fix_timeout_s.
+    Stale,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GpsStatus {
+    pub state: GpsState,
+    pub fix: Option<GpsFix>,
+    pub age_s: Option<f32>,
+    // The rover's position in lat/lon, once the map has been tied to GPS.
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PathStatus {
     pub has_path: bool,
     pub distance_to_goal: Option<f32>,
     pub obstacles_count: usize,
+    // Remembered obstacles by ObstacleMotion, drop-offs left out.
+    #[serde(default)]
+    pub static_obstacles: usize,
+    #[serde(default)]
+    pub dynamic_obstacles: usize,
+    // What the map allows ahead, and which of it is holding the speed down.
+    #[serde(default)]
+    pub speed_envelope: SpeedEnvelope,
+    #[serde(default)]
+    pub passage_violation: Option<PassageViolation>,
+}
+
+// A command the planner came up with that a no_rotate or one_way zone
+// ruled out, and what it does instead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PassageViolation {
+    pub zone: String,
+    pub policy: ZonePolicy,
+    pub planned: String,
+    pub instead: String,
 }
 
 pub struct PathPlanner {
     current_path: Vec<PathPoint>,
     obstacles: Vec<Detection>,
     goal: Option<PathPoint>,
+    pose: RoverPose,
+    position_filter: PositionFilter,
+    slip: SlipEstimator,
+    // Landmarks whose last sighting was rejected, so a run of them is
+    // reported once.
+    rejecting: Vec<String>,
+    grid: OccupancyGrid,
+    remembered: Vec<RememberedObstacle>,
+    docking: bool,
+    docked: bool,
+    dock_sighting: Option<DockSighting>,
+    free_space: Option<(FreeSpace, Instant)>,
+    zone_tracker: ZoneTracker,
+    gps_frame: Option<LocalFrame>,
+    gps_fix: Option<(GpsFix, Instant)>,
+    // The latest range sensor readings and how far out they are trusted
+    // over the camera.
+    ranges: Vec<RangeReading>,
+    near_field_m: f32,
+    follow: Option<FollowTarget>,
+    follow_ended: bool,
+    drop_offs: Vec<DropOff>,
+    // The drop-off the rover has yet to back away from.
+    drop_off_latch: Option<(DropOff, String)>,
+    // Speed and turn rate the motors were last seen going at.
+    motion: (f32, f32),
+    envelope: SpeedEnvelope,
+    passage_violation: Option<PassageViolation>,
+    // The no_rotate zone the rover is backing out of.
+    reversing_out: Option<String>,
+    config: PlannerConfig,
+    health: HealthHandle,
 }
 
 impl PathPlanner {
-    pub fn new() -> Self {
+    pub fn new(health: &HealthRegistry, config: PlannerConfig) -> Self {
         Self {
             current_path: Vec::new(),
             obstacles: Vec::new(),
             goal: None,
+            pose: RoverPose::default(),
+            position_filter: PositionFilter::default(),
+            slip: SlipEstimator::default(),
+            rejecting: Vec::new(),
+            grid: OccupancyGrid::new(config.map_size_cells, config.map_resolution_m),
+            remembered: Vec::new(),
+            docking: false,
+            docked: false,
+            dock_sighting: None,
+            free_space: None,
+            zone_tracker: ZoneTracker::default(),
+            gps_frame: None,
+            gps_fix: None,
+            ranges: Vec::new(),
+            near_field_m: 0.0,
+            follow: None,
+            follow_ended: false,
+            drop_offs: Vec::new(),
+            drop_off_latch: None,
+            motion: (0.0, 0.0),
+            envelope: SpeedEnvelope::default(),
+            passage_violation: None,
+            reversing_out: None,
+            config,
+            health: health.register("path_planner", Some(Duration::from_secs(1))),
+        }
+    }
+    
+    pub fn apply_config(&mut self, config: PlannerConfig) {
+        if config.map_size_cells != self.config.map_size_cells
+            || config.map_resolution_m != self.config.map_resolution_m
+        {
+            self.grid.reset(config.map_size_cells, config.map_resolution_m);
+            self.remembered.clear();
+            info!(target: "planner", "Map geometry changed, map cleared");
+        }
+        self.zone_tracker.retain_zones(&config.zones);
+        if config.gps_origin != self.config.gps_origin || config.gps_map_heading_deg != self.config.gps_map_heading_deg {
+            self.gps_frame = None;
+        }
+        if !config.slip_enabled {
+            self.slip.reset();
         }
+        self.config = config;
     }
     
+    // Starts over from an empty map at the origin, e.g. after a panic left
+    // the planner's state suspect.
+    pub fn reset(&mut self) {
+        self.current_path.clear();
+        self.obstacles.clear();
+        self.pose = RoverPose::default();
+        self.position_filter.reset();
+        self.slip.reset();
+        self.rejecting.clear();
+        self.grid.reset(self.config.map_size_cells, self.config.map_resolution_m);
+        self.remembered.clear();
+        self.docking = false;
+        self.dock_sighting = None;
+        self.free_space = None;
+        self.zone_tracker.clear();
+        self.gps_frame = None;
+        self.follow = None;
+        self.drop_offs.clear();
+        self.drop_off_latch = None;
+        self.motion = (0.0, 0.0);
+        self.envelope = SpeedEnvelope::default();
+        self.passage_violation = None;
+        self.reversing_out = None;
+    }
+
     pub fn update_obstacles(&mut self, detections: &[Detection]) {
+        let now = Instant::now();
         self.obstacles = detections.to_vec();
+        METRICS.obstacles.set(self.obstacles.len() as i64);
+        for detection in detections {
+            self.remember(detection);
+            if let (Some(id), Some((x, y))) = (detection.track_id, self.world_position(detection)) {
+                self.zone_tracker.update(&self.config.zones, Occupant::Track(id), &detection.class_name, x, y);
+            }
+        }
+        self.zone_tracker.expire(&self.config.zones);
+        self.update_follow();
+        self.forget_stale(now);
+        self.health.beat_with(self.get_status());
+    }
+    
+    // Dead-reckons the pose from what the motors were last told to do,
+    // less whatever the wheels are slipping.
+    pub fn update_pose(&mut self, motors: &MotorStatus, dt: Duration) {
+        // Turns happen a tick at a time, so the heading change over this
+        // one is the turn rate.
+        if !dt.is_zero() {
+            self.motion = (motors.speed, (motors.heading - self.pose.heading_deg) / dt.as_secs_f32());
+        }
+        self.pose.heading_deg = motors.heading;
+        let heading = self.pose.heading_deg.to_radians();
+        let wheel_distance = motors.speed * dt.as_secs_f32();
+        if self.config.slip_enabled {
+            self.slip.record_wheel(wheel_distance, CLOCK.now_ms());
+        }
+        let distance = wheel_distance * (1.0 - self.slip.get_slip());
+        self.pose.x += distance * heading.sin();
+        self.pose.y += distance * heading.cos();
+        // Slipping wheels are no surer of where they went, so the estimate
+        // grows as much as for the distance they turned for.
+        self.position_filter.predict(wheel_distance, self.config.odometry_variance_per_m);
+        METRICS.pose_sigma_m.set(self.position_filter.get_sigma_m() as f64);
+        self.zone_tracker.update(&self.config.zones, Occupant::Rover, "rover", self.pose.x, self.pose.y);
+
+        if let Some((col, row)) = self.grid.world_to_cell(self.pose.x, self.pose.y) {
+            if self.grid.get(col, row) != Cell::Occupied {
+                self.grid.set(col, row, Cell::Free);
+            }
+        }
+    }
+    
+    // Only fiducials from a newly arrived frame should be passed in, or a
+    // stale sighting would be applied again as the rover moves on.
+    pub fn update_fiducials(&mut self, fiducials: &[FiducialDetection]) {
+        for fiducial in fiducials {
+            let Some(pose) = fiducial.pose_estimate else { continue };
+            if self.config.dock_tag_id == Some(fiducial.id) {
+                self.dock_sighting = Some(DockSighting { pose, heading_deg: self.pose.heading_deg, seen: Instant::now() });
+                if self.docking && pose.distance_m <= self.config.dock_contact_m {
+                    info!(target: "planner", "Docked, tag {} at {:.2} m", fiducial.id, pose.distance_m);
+                    self.docking = false;
+                    self.docked = true;
+                }
+            }
+            if let Some(landmark) = self.config.landmarks.iter().find(|l| l.tag_id == Some(fiducial.id)) {
+                let landmark = landmark.clone();
+                self.sight_landmark(&landmark, pose.distance_m, pose.bearing_deg);
+            }
+        }
+    }
+
+    // Detections of a landmark class are decoded by the detector rather
+    // than read off a tag, so they have to be confident enough first. As
+    // with fiducials, only a newly arrived frame's should be passed in.
+    pub fn update_landmarks(&mut self, detections: &[Detection]) {
+        for detection in detections {
+            if detection.confidence < self.config.landmark_min_confidence {
+                continue;
+            }
+            let Some(landmark) = self.config.landmarks.iter().find(|l| l.class.as_ref() == Some(&detection.class_name)) else { continue };
+            let landmark = landmark.clone();
+            let distance = detection.distance_estimate;
+            if distance.is_finite() && distance > 0.0 {
+                self.sight_landmark(&landmark, distance, self.bearing(detection));
+            }
+        }
+    }
+
+    // Works out where the sighting puts the rover and fuses that into the
+    // position. The first of a run of rejected sightings is reported.
+    fn sight_landmark(&mut self, landmark: &Landmark, distance_m: f32, bearing_deg: f32) {
+        if distance_m > self.config.landmark_max_range_m {
+            return;
+        }
+        let angle = (self.pose.heading_deg + bearing_deg).to_radians();
+        let fix = (landmark.x - distance_m * angle.sin(), landmark.y - distance_m * angle.cos());
+        let label = landmark.label();
+        debug!(target: "planner", "{} puts the rover at ({:.2}, {:.2}), odometry says ({:.2}, {:.2})",
+            label, fix.0, fix.1, self.pose.x, self.pose.y);
+        let sigma_m = (self.config.landmark_sigma_m * distance_m).max(0.01);
+        let correction = self.position_filter.correct(label.clone(), (self.pose.x, self.pose.y), fix, sigma_m, self.config.landmark_gate_sigma);
+        METRICS.pose_sigma_m.set(self.position_filter.get_sigma_m() as f64);
+        if correction.accepted {
+            METRICS.landmark_fixes.inc("applied");
+            METRICS.landmark_correction_m.set(correction.magnitude_m as f64);
+            self.pose.x += correction.dx;
+            self.pose.y += correction.dy;
+            self.rejecting.retain(|l| *l != label);
+            return;
+        }
+        METRICS.landmark_fixes.inc("rejected");
+        if self.rejecting.contains(&label) {
+            return;
+        }
+        warn!(target: "planner", "Rejected {} fix at ({:.2}, {:.2}), {:.1} sigma from ({:.2}, {:.2})",
+            label, fix.0, fix.1, correction.deviation, self.pose.x, self.pose.y);
+        EVENTS.publish(Severity::Warning, "planner", format!("Rejected an implausible fix from {}", label), serde_json::json!({
+            "landmark": label,
+            "fix": [fix.0, fix.1],
+            "pose": [self.pose.x, self.pose.y],
+            "deviation": correction.deviation,
+        }));
+        self.rejecting.push(label);
+    }
+
+    pub fn get_localization_status(&self) -> LocalizationStatus {
+        self.position_filter.get_status()
+    }
+
+    // With how far the camera saw the rover go between the last two
+    // frames; only a newly arrived frame's should be passed in.
+    pub fn update_slip(&mut self, travel: VisualTravel) {
+        if !self.config.slip_enabled {
+            return;
+        }
+        if let Some(interval) = self.slip.update(&self.config, travel) {
+            debug!(target: "planner", "Slip {:.2} over {} ms: wheels {:.3} m, camera {:.3} m",
+                interval.slip, interval.to_ms - interval.from_ms, interval.wheel_m, interval.visual_m);
+        }
+    }
+
+    pub fn get_traction_status(&self) -> TractionStatus {
+        self.slip.get_status()
+    }
+
+    pub fn update_gps(&mut self, fix: GpsFix) {
+        METRICS.gps_fixes.inc(fix.quality.as_str());
+        METRICS.gps_hdop.set(fix.hdop as f64);
+        self.gps_fix = Some((fix, Instant::now()));
+        if !self.gps_usable(&fix) {
+            return;
+        }
+        let (Some(lat), Some(lon)) = (fix.lat, fix.lon) else { return };
+        let frame = *self.gps_frame.get_or_insert_with(|| {
+            let frame = match self.config.gps_origin {
+                Some((origin_lat, origin_lon)) => LocalFrame::new(origin_lat, origin_lon, (0.0, 0.0), self.config.gps_map_heading_deg),
+                None => LocalFrame::new(lat, lon, (self.pose.x, self.pose.y), self.config.gps_map_heading_deg),
+            };
+            info!(target: "planner", "Map tied to GPS at ({:.6}, {:.6})", lat, lon);
+            frame
+        });
+        let (fix_x, fix_y) = frame.to_map(lat, lon);
+        let weight = self.config.gps_fix_weight;
+        debug!(target: "planner", "GPS puts the rover at ({:.2}, {:.2}), odometry says ({:.2}, {:.2})",
+            fix_x, fix_y, self.pose.x, self.pose.y);
+        self.pose.x += (fix_x - self.pose.x) * weight;
+        self.pose.y += (fix_y - self.pose.y) * weight;
+    }
+
+    fn gps_usable(&self, fix: &GpsFix) -> bool {
+        fix.quality.is_fix() && fix.hdop <= self.config.gps_max_hdop
+    }
+
+    pub fn get_gps_status(&self) -> GpsStatus {
+        let state = match &self.gps_fix {
+            None => GpsState::None,
+            Some((_, received)) if received.elapsed().as_secs_f32() > self.config.gps_fix_timeout_s => GpsState::Stale,
+            Some((fix, _)) if !fix.quality.is_fix() => GpsState::NoFix,
+            Some((fix, _)) if !self.gps_usable(fix) => GpsState::PoorHdop,
+            Some(_) => GpsState::Fused,
+        };
+        let position = self.gps_frame.map(|frame| frame.to_geodetic(self.pose.x, self.pose.y));
+        GpsStatus {
+            state,
+            fix: self.gps_fix.map(|(fix, _)| fix),
+            age_s: self.gps_fix.map(|(_, received)| received.elapsed().as_secs_f32()),
+            lat: position.map(|p| p.0),
+            lon: position.map(|p| p.1),
+        }
+    }
+
+    // Also where drop-offs are looked for, the speed envelope is worked
+    // out and passages are checked, since it comes last each tick.
+    pub fn update_ranges(&mut self, ranges: Vec<RangeReading>, near_field_m: f32) {
+        self.ranges = ranges;
+        self.near_field_m = near_field_m;
+        self.check_drop_off();
+        self.envelope = SpeedEnvelope::evaluate(&self.grid, &self.pose, &self.config);
+        self.check_passages();
+    }
+
+    pub fn get_ranges(&self) -> Vec<RangeReading> {
+        self.ranges.clone()
+    }
+
+    // Working range sensors looking for obstacles rather than the floor.
+    fn obstacle_ranges(&self) -> impl Iterator<Item = &RangeReading> {
+        self.ranges.iter().filter(|r| r.ok && r.floor_m.is_none())
+    }
+
+    // Those whose cone reaches into the corridor ahead.
+    fn ranges_ahead(&self) -> impl Iterator<Item = &RangeReading> {
+        self.obstacle_ranges()
+            .filter(|r| r.bearing_deg.abs() - r.fov_deg / 2.0 <= self.config.clearance_corridor_deg)
+    }
+
+    pub fn update_free_space(&mut self, free_space: FreeSpace) {
+        self.free_space = Some((free_space, Instant::now()));
+    }
+
+    // Clockwise from straight ahead, from where the box is in the frame.
+    fn bearing(&self, detection: &Detection) -> f32 {
+        let (x, _, w, _) = detection.bbox;
+        let center_x = x as f32 + w as f32 / 2.0;
+        (center_x / FRAME_WIDTH_PX - 0.5) * self.config.camera_hfov_deg
+    }
+
+    // Where on the map a detection is, if its distance is usable.
+    fn world_position(&self, detection: &Detection) -> Option<(f32, f32)> {
+        let distance = detection.distance_estimate;
+        if !distance.is_finite() || distance <= 0.0 || distance > MAX_MAPPING_RANGE_M {
+            return None;
+        }
+
+        // One placed on the floor has its bearing from there too.
+        let bearing = match detection.ground_position {
+            Some((right_m, ahead_m)) => right_m.atan2(ahead_m).to_degrees(),
+            None => self.bearing(detection),
+        };
+        let angle = (self.pose.heading_deg + bearing).to_radians();
+        Some((self.pose.x + distance * angle.sin(), self.pose.y + distance * angle.cos()))
+    }
+
+    // A track is followed from cell to cell; anything else is matched by
+    // class and cell.
+    fn remember(&mut self, detection: &Detection) {
+        let Some((ox, oy)) = self.world_position(detection) else { return };
+        let cell = self.grid.world_to_cell(ox, oy);
+        let same_class = |o: &&RememberedObstacle| o.class_name == detection.class_name;
+        let index = self.remembered.iter()
+            .position(|o| same_class(&o) && detection.track_id.is_some() && o.track_id == detection.track_id)
+            .or_else(|| self.remembered.iter().position(|o| same_class(&o) && self.grid.world_to_cell(o.x, o.y) == cell));
+        let index = index.unwrap_or_else(|| {
+            self.remembered.push(RememberedObstacle::new(detection.class_name.clone(), HazardType::Obstacle, ox, oy));
+            self.remembered.len() - 1
+        });
+
+        let obstacle = &mut self.remembered[index];
+        let (px, py) = (obstacle.x, obstacle.y);
+        obstacle.observe(ox, oy, detection, &self.config);
+        if self.grid.world_to_cell(px, py) != cell {
+            self.release_cell(px, py);
+        }
+        self.grid.set_world(ox, oy, Cell::Occupied);
+    }
+
+    // Whether the camera is pointed at (x, y) and near enough to see
+    // something there.
+    fn in_view(&self, x: f32, y: f32) -> bool {
+        let (dx, dy) = (x - self.pose.x, y - self.pose.y);
+        let bearing = (dx.atan2(dy).to_degrees() - self.pose.heading_deg + 540.0).rem_euclid(360.0) - 180.0;
+        dx.hypot(dy) <= MAX_MAPPING_RANGE_M && bearing.abs() <= self.config.camera_hfov_deg / 2.0
+    }
+
+    // Anything not seen by this update, which started at `now`, is
+    // unseen.
+    fn forget_stale(&mut self, now: Instant) {
+        let dynamic_memory = Duration::from_secs_f32(self.config.dynamic_memory_s);
+        let static_memory = Duration::from_secs_f32(self.config.obstacle_memory_s);
+        let in_view: Vec<bool> = self.remembered.iter().map(|o| self.in_view(o.x, o.y)).collect();
+        for (obstacle, in_view) in self.remembered.iter_mut().zip(in_view) {
+            if obstacle.last_seen >= now || !in_view {
+                obstacle.unseen_in_view = None;
+            } else {
+                obstacle.unseen_in_view.get_or_insert(now);
+            }
+        }
+        let (stale, kept): (Vec<_>, Vec<_>) = self.remembered.drain(..)
+            .partition(|o| o.hazard == HazardType::Obstacle && match o.motion {
+                ObstacleMotion::Dynamic => o.last_seen.elapsed() > dynamic_memory,
+                ObstacleMotion::Static => o.unseen_in_view.is_some_and(|at| at.elapsed() > static_memory),
+            });
+        self.remembered = kept;
+
+        for obstacle in stale {
+            if obstacle.motion == ObstacleMotion::Static {
+                debug!(target: "planner", "{} at ({:.2}, {:.2}) is gone", obstacle.class_name, obstacle.x, obstacle.y);
+            }
+            self.release_cell(obstacle.x, obstacle.y);
+        }
+    }
+
+    // Clears the cell at (x, y) unless something remembered is still in it.
+    fn release_cell(&mut self, x: f32, y: f32) {
+        let cell = self.grid.world_to_cell(x, y);
+        if !self.remembered.iter().any(|o| self.grid.world_to_cell(o.x, o.y) == cell) {
+            self.grid.set_world(x, y, Cell::Unknown);
+        }
+    }
+    
+    pub fn get_map_snapshot(&self) -> GridSnapshot {
+        self.grid.snapshot()
+    }
+    
+    pub fn get_map_diff(&self, since: u64) -> GridDiff {
+        self.grid.diff_since(since)
+    }
+    
+    pub fn get_occupancy(&self) -> OccupancyValues {
+        self.grid.occupancy()
+    }
+
+    pub fn get_map_version(&self) -> u64 {
+        self.grid.get_version()
+    }
+    
+    pub fn get_pose(&self) -> RoverPose {
+        self.pose
+    }
+
+    // Puts the rover back where a checkpoint had it. Odometry carries on
+    // from there, as uncertain as at startup.
+    pub fn restore_pose(&mut self, pose: RoverPose) {
+        self.pose = pose;
+        self.position_filter.reset();
+        self.zone_tracker.update(&self.config.zones, Occupant::Rover, "rover", pose.x, pose.y);
+    }
+
+    // Only into a grid the same shape as the one the tiles came from.
+    pub fn restore_map(&mut self, resolution_m: f32, diff: &GridDiff) -> Result<()> {
+        if resolution_m != self.grid.get_resolution_m() {
+            bail!("the map was at {} m a cell, not {}", resolution_m, self.grid.get_resolution_m());
+        }
+        self.grid.apply_diff(diff)
+    }
+
+    pub fn get_memory(&self) -> PlannerMemory {
+        PlannerMemory {
+            obstacles: self.remembered.iter()
+                .map(|o| ObstacleMemory { class_name: o.class_name.clone(), hazard: o.hazard, motion: o.motion, x: o.x, y: o.y })
+                .collect(),
+            drop_offs: self.drop_offs.clone(),
+        }
+    }
+
+    // Restored obstacles count as just seen, so static ones last until the
+    // camera has looked where they are and dynamic ones soon expire.
+    pub fn restore_memory(&mut self, memory: PlannerMemory) {
+        self.remembered = memory.obstacles.into_iter()
+            .map(|o| RememberedObstacle { motion: o.motion, ..RememberedObstacle::new(o.class_name, o.hazard, o.x, o.y) })
+            .collect();
+        self.drop_offs = memory.drop_offs;
+    }
+
+    pub fn get_goal(&self) -> Option<PathPoint> {
+        self.goal.clone()
+    }
+
+    pub fn get_map_state(&self) -> MapState {
+        MapState {
+            pose: self.pose,
+            goal: self.goal.clone(),
+            path: self.current_path.clone(),
+            obstacles: self.remembered.iter()
+                .map(|o| RememberedObstacle {
+                    age_s: o.last_seen.elapsed().as_secs_f32(),
+                    ..o.clone()
+                })
+                .collect(),
+            zones: self.get_zones(),
+        }
+    }
+
+    pub fn get_zones(&self) -> Vec<ZoneStatus> {
+        self.zone_tracker.get_status(&self.config.zones)
     }
     
     pub fn get_navigation_command(&self) -> NavigationCommand {
-        if !self.obstacles.is_empty() {
+        let (command, _) = self.apply_passages(self.planned_command());
+        METRICS.planner_commands.inc(command.as_str());
+        command
+    }
+
+    // Everything but the passages, which get the last word.
+    fn planned_command(&self) -> NavigationCommand {
+        let command = if self.drop_off_latch.is_some() {
+            NavigationCommand::Backward(self.config.dropoff_backoff_speed)
+        } else if self.docking {
+            self.docking_command()
+        } else if self.obstacles.iter().any(|o| !self.is_follow_target(o)) {
             NavigationCommand::Stop
+        } else if let Some(command) = self.near_field_command() {
+            command
+        } else if self.follow.is_some() {
+            self.follow_command()
         } else {
-            NavigationCommand::Forward(0.5)
-        }
+            self.clearance_command().unwrap_or(NavigationCommand::Forward(self.config.cruise_speed))
+        };
+        self.apply_drop_off_arcs(self.apply_envelope(self.apply_traction(self.apply_zone_policy(command))))
     }
     
-    pub fn get_current_path(&self) -> Vec<PathPoint> {
-        self.current_path.clone()
+    // Turns toward the dock tag, then drives at it. Vision's stop and
+    // emergency stop still apply on top, as for any other command.
+    fn docking_command(&self) -> NavigationCommand {
+        let Some(sighting) = self.dock_sighting.filter(|s| s.seen.elapsed() < DOCK_SIGHTING_TTL) else {
+            return NavigationCommand::Stop;
+        };
+        let bearing = sighting.pose.bearing_deg - (self.pose.heading_deg - sighting.heading_deg);
+        if bearing > DOCK_ALIGN_DEG {
+            NavigationCommand::TurnRight(bearing)
+        } else if bearing < -DOCK_ALIGN_DEG {
+            NavigationCommand::TurnLeft(-bearing)
+        } else {
+            NavigationCommand::Forward(self.config.dock_speed)
+        }
     }
-    
+
+    // Keeps the target straight ahead and follow_distance_m away. Vision
+    // still stops the rover at vision.person_stop_m, and the rover never
+    // steers off to find floor while following: if the way is blocked it
+    // waits.
+    fn follow_command(&self) -> NavigationCommand {
+        let Some(target) = self.follow.as_ref().filter(|t| t.visible) else {
+            return NavigationCommand::Stop;
+        };
+        let bearing = target.bearing_deg - (self.pose.heading_deg - target.heading_deg);
+        if bearing > self.config.follow_align_deg {
+            NavigationCommand::TurnRight(bearing)
+        } else if bearing < -self.config.follow_align_deg {
+            NavigationCommand::TurnLeft(-bearing)
+        } else if target.distance_m > self.config.follow_distance_m + FOLLOW_DEADBAND_M && self.clearance_command().is_none() {
+            let gap = target.distance_m - self.config.follow_distance_m;
+            NavigationCommand::Forward(self.config.follow_speed * (gap / FOLLOW_SLOWDOWN_M).clamp(0.25, 1.0))
+        } else {
+            NavigationCommand::Stop
+        }
+    }
+
+    fn is_follow_target(&self, detection: &Detection) -> bool {
+        self.follow.as_ref().is_some_and(|t| detection.track_id == Some(t.track_id))
+    }
+
+    // Matches the target against the latest detections. A track the
+    // tracker dropped comes back under a new id, so a detection of the
+    // same class near where the target was last seen takes over.
+    fn update_follow(&mut self) {
+        let Some(target) = self.follow.as_ref() else { return };
+        let by_id = self.obstacles.iter().find(|d| d.track_id == Some(target.track_id));
+        let reacquired = || {
+            let (tx, ty) = target.position?;
+            self.obstacles.iter()
+                .filter(|d| d.class_name == target.class_name && d.track_id.is_some())
+                .filter_map(|d| self.world_position(d).map(|(x, y)| (d, (x - tx).hypot(y - ty))))
+                .filter(|&(_, gap)| gap <= self.config.follow_reacquire_m)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(d, _)| d)
+        };
+        let sighting = by_id.or_else(reacquired).map(|d| (d.clone(), self.bearing(d), self.world_position(d)));
+
+        let heading_deg = self.pose.heading_deg;
+        let timeout = Duration::from_secs_f32(self.config.follow_timeout_s);
+        let Some(target) = self.follow.as_mut() else { return };
+        match sighting {
+            Some((detection, bearing_deg, position)) => {
+                let track_id = detection.track_id.unwrap_or(target.track_id);
+                if track_id != target.track_id {
+                    info!(target: "planner", "Follow target #{} picked up again as #{}", target.track_id, track_id);
+                    target.track_id = track_id;
+                }
+                target.distance_m = detection.distance_estimate;
+                target.bearing_deg = bearing_deg;
+                target.heading_deg = heading_deg;
+                target.position = position.or(target.position);
+                target.seen = Instant::now();
+                target.visible = true;
+            }
+            None if target.seen.elapsed() >= timeout => {
+                warn!(target: "planner", "Follow target #{} lost, giving up", target.track_id);
+                EVENTS.publish(Severity::Warning, "planner", format!("Lost follow target {}#{}", target.class_name, target.track_id), serde_json::json!({
+                    "track_id": target.track_id,
+                    "class_name": target.class_name,
+                }));
+                self.follow = None;
+                self.follow_ended = true;
+            }
+            None => target.visible = false,
+        }
+    }
+
+    // Some(track id) locks onto a detection in view and stops any docking;
+    // None stops following.
+    pub fn set_follow(&mut self, track_id: Option<u32>) -> Result<()> {
+        let Some(track_id) = track_id else {
+            if let Some(target) = self.follow.take() {
+                info!(target: "planner", "Stopped following #{}", target.track_id);
+                self.follow_ended = true;
+            }
+            return Ok(());
+        };
+        let Some(detection) = self.obstacles.iter().find(|d| d.track_id == Some(track_id)) else {
+            bail!("Track {} is not in view", track_id);
+        };
+        info!(target: "planner", "Following {} #{}", detection.class_name, track_id);
+        self.follow = Some(FollowTarget {
+            track_id,
+            class_name: detection.class_name.clone(),
+            distance_m: detection.distance_estimate,
+            bearing_deg: self.bearing(detection),
+            heading_deg: self.pose.heading_deg,
+            position: self.world_position(detection),
+            seen: Instant::now(),
+            visible: true,
+        });
+        self.follow_ended = false;
+        self.docking = false;
+        Ok(())
+    }
+
+    pub fn get_follow_status(&self) -> Option<FollowStatus> {
+        self.follow.as_ref().map(|target| FollowStatus {
+            state: if target.visible { FollowState::Locked } else { FollowState::Lost },
+            track_id: target.track_id,
+            class_name: target.class_name.clone(),
+            distance_m: target.distance_m,
+            target_distance_m: self.config.follow_distance_m,
+            lost_s: (!target.visible).then(|| target.seen.elapsed().as_secs_f32()),
+        })
+    }
+
+    // True once each time following stops, given up or cleared.
+    pub fn take_follow_ended(&mut self) -> bool {
+        std::mem::take(&mut self.follow_ended)
+    }
+
+    // Within near_field_m the range sensors are believed over the camera,
+    // which can't see that close. Something there ahead turns the rover
+    // toward the sensor with the most room, or stops it if none has any.
+    // Docking is left alone, since it means driving up to something.
+    fn near_field_command(&self) -> Option<NavigationCommand> {
+        let blocked = |r: &RangeReading| r.distance_m.is_some_and(|d| d < self.near_field_m);
+        if !self.ranges_ahead().any(blocked) {
+            return None;
+        }
+        let best = self.obstacle_ranges()
+            .filter(|r| !blocked(r))
+            .max_by(|a, b| a.distance_m.unwrap_or(f32::INFINITY).total_cmp(&b.distance_m.unwrap_or(f32::INFINITY)));
+        debug!(target: "planner", "Range sensors see something within {:.2} m ahead", self.near_field_m);
+        Some(match best {
+            Some(r) if r.bearing_deg >= 0.0 => NavigationCommand::TurnRight(r.bearing_deg),
+            Some(r) => NavigationCommand::TurnLeft(-r.bearing_deg),
+            None => NavigationCommand::Stop,
+        })
+    }
+
+    // Steers around what the camera says isn't floor, such as table legs
+    // or a drop-off, when nothing was detected there. None means the way
+    // ahead is clear or the free-space estimate can't be trusted.
+    fn clearance_command(&self) -> Option<NavigationCommand> {
+        let free_space = self.trusted_free_space()?;
+        let mut ahead = free_space.columns.iter()
+            .filter(|c| c.bearing_deg.abs() <= self.config.clearance_corridor_deg)
+            .map(|c| c.distance_m)
+            .fold(f32::INFINITY, f32::min);
+        // Range sensors covering the way ahead vouch for the near field.
+        if self.ranges_ahead().next().is_some() {
+            ahead = ahead.max(self.near_field_m);
+        }
+        if ahead >= self.config.clearance_stop_m {
+            return None;
+        }
+        let best = free_space.columns.iter().max_by(|a, b| a.distance_m.total_cmp(&b.distance_m))?;
+        debug!(target: "planner", "{:.2} m of floor ahead, {:.2} m at {:.0}°", ahead, best.distance_m, best.bearing_deg);
+        Some(if best.distance_m < self.config.clearance_stop_m {
+            NavigationCommand::Stop
+        } else if best.bearing_deg >= 0.0 {
+            NavigationCommand::TurnRight(best.bearing_deg)
+        } else {
+            NavigationCommand::TurnLeft(-best.bearing_deg)
+        })
+    }
+
+    fn trusted_free_space(&self) -> Option<&FreeSpace> {
+        self.free_space.as_ref()
+            .filter(|(f, seen)| seen.elapsed() < FREE_SPACE_TTL && f.confidence >= self.config.free_space_min_confidence)
+            .map(|(f, _)| f)
+    }
+
+    // A downward range sensor that has lost the floor, or too little floor
+    // in front of the rover on camera. Either latches until the rover has
+    // backed away. One seen from inside a known drop-off's arc is taken to
+    // be that one, so backing off and turning away doesn't set it off again.
+    fn check_drop_off(&mut self) {
+        if let Some((drop_off, source)) = &self.drop_off_latch {
+            let backed_off_m = (self.pose.x - drop_off.x).hypot(self.pose.y - drop_off.y);
+            if backed_off_m < self.config.dropoff_backoff_m {
+                return;
+            }
+            info!(target: "planner", "Backed {:.2} m away from the drop-off", backed_off_m);
+            EVENTS.publish(Severity::Info, "planner", "Backed away from drop-off", serde_json::json!({ "source": source }));
+            self.drop_off_latch = None;
+        }
+        let sensor = self.ranges.iter().find(|r| r.drop_off).map(|r| (r.sensor.clone(), r.bearing_deg));
+        let Some((source, bearing_deg)) = sensor.or_else(|| self.camera_drop_off().then(|| ("camera".to_string(), 0.0))) else {
+            return;
+        };
+        if self.drop_off_arc().is_some() {
+            return;
+        }
+        let drop_off = DropOff { x: self.pose.x, y: self.pose.y, heading_deg: self.pose.heading_deg + bearing_deg };
+        let angle = drop_off.heading_deg.to_radians();
+        let (mark_x, mark_y) = (drop_off.x + DROP_OFF_MARK_M * angle.sin(), drop_off.y + DROP_OFF_MARK_M * angle.cos());
+        self.remembered.push(RememberedObstacle::new("drop_off".to_string(), HazardType::DropOff, mark_x, mark_y));
+        self.grid.set_world(mark_x, mark_y, Cell::Occupied);
+        METRICS.drop_offs.inc(&source);
+        warn!(target: "planner", "Drop-off ahead ({}), backing away", source);
+        EVENTS.publish(Severity::Warning, "planner", format!("Drop-off ahead ({}), backing away", source), serde_json::json!({
+            "source": source,
+            "x": mark_x,
+            "y": mark_y,
+        }));
+        self.drop_offs.push(drop_off);
+        self.drop_off_latch = Some((drop_off, source));
+    }
+
+    // Anything detected, or close on a range sensor, would hide the floor
+    // just as well, and is left to the usual stops.
+    fn camera_drop_off(&self) -> bool {
+        if self.config.dropoff_min_floor <= 0.0 || !self.obstacles.is_empty() {
+            return false;
+        }
+        if self.ranges_ahead().any(|r| r.distance_m.is_some_and(|d| d < self.near_field_m)) {
+            return false;
+        }
+        self.trusted_free_space().is_some_and(|f| f.near_floor < self.config.dropoff_min_floor)
+    }
+
+    // How far the heading is into the arc of a drop-off within
+    // dropoff_clear_m, clockwise positive, if it is in one.
+    fn drop_off_arc(&self) -> Option<f32> {
+        self.drop_offs.iter()
+            .filter(|d| (self.pose.x - d.x).hypot(self.pose.y - d.y) < self.config.dropoff_clear_m)
+            .map(|d| (self.pose.heading_deg - d.heading_deg + 540.0).rem_euclid(360.0) - 180.0)
+            .find(|offset| offset.abs() < self.config.dropoff_arc_deg / 2.0)
+    }
+
+    // Forward into a drop-off's arc becomes a turn out of it, the short way.
+    fn apply_drop_off_arcs(&self, command: NavigationCommand) -> NavigationCommand {
+        let NavigationCommand::Forward(_) = command else { return command };
+        let Some(offset) = self.drop_off_arc() else { return command };
+        let turn = self.config.dropoff_arc_deg / 2.0 - offset.abs() + DROP_OFF_TURN_MARGIN_DEG;
+        if offset >= 0.0 {
+            NavigationCommand::TurnRight(turn)
+        } else {
+            NavigationCommand::TurnLeft(turn)
+        }
+    }
+
+    // Some while the rover has yet to back away from a drop-off.
+    pub fn get_drop_off_status(&self) -> Option<DropOffStatus> {
+        self.drop_off_latch.as_ref().map(|(drop_off, source)| DropOffStatus {
+            source: source.clone(),
+            backed_off_m: (self.pose.x - drop_off.x).hypot(self.pose.y - drop_off.y),
+            backoff_m: self.config.dropoff_backoff_m,
+        })
+    }
+
+    pub fn is_drop_off_latched(&self) -> bool {
+        self.drop_off_latch.is_some()
+    }
+
+    // Holds the current speed and turn rate for planner.rollout_horizon_s,
+    // which is an arc, or a straight line when not turning.
+    pub fn get_trajectory(&self, camera: &CameraModel) -> Trajectory {
+        let (speed, rate) = self.motion;
+        let RoverPose { x, y, heading_deg } = self.pose;
+        let heading = heading_deg.to_radians();
+        let rate_rad = rate.to_radians();
+        // Standing still, or turning on the spot, goes nowhere.
+        let steps = if speed == 0.0 { 0 } else { (self.config.rollout_horizon_s / self.config.rollout_step_s).round() as usize };
+        let world: Vec<PathPoint> = (0..=steps)
+            .map(|i| {
+                let t = i as f32 * self.config.rollout_step_s;
+                if rate_rad.abs() < 1e-3 {
+                    let d = speed * t;
+                    PathPoint { x: x + d * heading.sin(), y: y + d * heading.cos() }
+                } else {
+                    let radius = speed / rate_rad;
+                    let end = heading + rate_rad * t;
+                    PathPoint { x: x + radius * (heading.cos() - end.cos()), y: y + radius * (end.sin() - heading.sin()) }
+                }
+            })
+            .collect();
+
+        // Into the rover's frame, then onto the floor in front of the lens.
+        let project = |p: &PathPoint| {
+            let (dx, dy) = (p.x - x, p.y - y);
+            camera.project_floor(dx * heading.sin() + dy * heading.cos(), dx * heading.cos() - dy * heading.sin())
+        };
+        let clearance = self.config.rollout_clearance_m;
+        // Dynamic obstacles are checked where they will be by then.
+        let hit = world.iter().enumerate().find_map(|(i, point)| {
+            let t_s = i as f32 * self.config.rollout_step_s;
+            let obstacle = self.remembered.iter().find(|o| {
+                let (ox, oy) = o.predict(t_s);
+                (ox - point.x).hypot(oy - point.y) <= clearance
+            })?;
+            Some(TrajectoryHit {
+                class_name: obstacle.class_name.clone(),
+                hazard: obstacle.hazard,
+                index: i,
+                point: point.clone(),
+                t_s,
+            })
+        });
+
+        Trajectory {
+            speed_m_s: speed,
+            turn_rate_deg_s: rate,
+            horizon_s: self.config.rollout_horizon_s,
+            image: world.iter().map(project).collect(),
+            world,
+            hit,
+        }
+    }
+
+    // Stop, slow and forbid_entry zones only ever hold back forward motion,
+    // so turning is always possible and an operator can drive out by hand.
+    // no_rotate and one_way zones are left to apply_passages.
+    fn apply_zone_policy(&self, command: NavigationCommand) -> NavigationCommand {
+        let NavigationCommand::Forward(speed) = command else { return command };
+        let zones = &self.config.zones;
+        if self.zone_tracker.is_inside(Occupant::Rover, ZonePolicy::Stop, zones) {
+            return NavigationCommand::Stop;
+        }
+        if self.forbidden_ahead(0.0) {
+            debug!(target: "planner", "Forbidden zone ahead");
+            return if !self.forbidden_ahead(ZONE_AVOID_DEG) {
+                NavigationCommand::TurnRight(ZONE_AVOID_DEG)
+            } else if !self.forbidden_ahead(-ZONE_AVOID_DEG) {
+                NavigationCommand::TurnLeft(ZONE_AVOID_DEG)
+            } else {
+                NavigationCommand::Stop
+            };
+        }
+        if self.zone_tracker.is_inside(Occupant::Rover, ZonePolicy::Slow, zones) {
+            return NavigationCommand::Forward(speed.min(self.config.zone_slow_speed));
+        }
+        command
+    }
+
+    // Sustained slip holds forward speed down until the wheels grip again.
+    fn apply_traction(&self, command: NavigationCommand) -> NavigationCommand {
+        match command {
+            NavigationCommand::Forward(speed) if self.slip.is_sustained() => NavigationCommand::Forward(speed * self.config.slip_speed_factor),
+            _ => command,
+        }
+    }
+
+    // Known obstacles near the way ahead, and unmapped floor, cap forward
+    // speed whether or not the camera sees anything there now.
+    fn apply_envelope(&self, command: NavigationCommand) -> NavigationCommand {
+        match (command, self.envelope.max_speed) {
+            (NavigationCommand::Forward(speed), Some(max)) if max < speed => {
+                debug!(target: "planner", "Speed envelope holds {:.2} m/s to {:.2} ({:?})", speed, max, self.envelope.limited_by);
+                NavigationCommand::Forward(max)
+            }
+            (command, _) => command,
+        }
+    }
+
+    pub fn get_speed_envelope(&self) -> SpeedEnvelope {
+        self.envelope.clone()
+    }
+
+    // Inside a no_rotate zone a turn becomes reversing back out the way
+    // the rover came, all the way out. Travel more than one_way_tolerance_deg off a one_way
+    // zone's direction, inside it or into it, becomes a turn toward the
+    // direction inside, or a turn aside at its edge. Where none of that is
+    // allowed either the rover stops. Backing off a drop-off always goes.
+    fn apply_passages(&self, command: NavigationCommand) -> (NavigationCommand, Option<PassageViolation>) {
+        if self.drop_off_latch.is_some() {
+            return (command, None);
+        }
+        let heading = self.pose.heading_deg;
+        let no_rotate = self.no_rotate_zone();
+        let backing = no_rotate.filter(|z| self.reversing_out.as_ref() == Some(&z.name));
+        let (zone, instead) = if let Some(zone) = backing {
+            (zone, self.reverse_out())
+        } else {
+            match command {
+                NavigationCommand::TurnLeft(_) | NavigationCommand::TurnRight(_) => {
+                    let Some(zone) = no_rotate else { return (command, None) };
+                    (zone, self.reverse_out())
+                }
+                NavigationCommand::Forward(_) => {
+                    let Some((zone, inside)) = self.one_way_against(heading) else { return (command, None) };
+                    let instead = if no_rotate.is_some() {
+                        self.reverse_out()
+                    } else if inside {
+                        let bearing = angle_between(heading, zone.direction_deg.unwrap_or(heading));
+                        if bearing >= 0.0 { NavigationCommand::TurnRight(bearing) } else { NavigationCommand::TurnLeft(-bearing) }
+                    } else if self.one_way_against(heading + ZONE_AVOID_DEG).is_none() {
+                        NavigationCommand::TurnRight(ZONE_AVOID_DEG)
+                    } else if self.one_way_against(heading - ZONE_AVOID_DEG).is_none() {
+                        NavigationCommand::TurnLeft(ZONE_AVOID_DEG)
+                    } else {
+                        NavigationCommand::Stop
+                    };
+                    (zone, instead)
+                }
+                _ => return (command, None),
+            }
+        };
+        let violation = PassageViolation {
+            zone: zone.name.clone(),
+            policy: zone.policy,
+            planned: command.as_str().to_string(),
+            instead: instead.as_str().to_string(),
+        };
+        (instead, Some(violation))
+    }
+
+    // Straight back, unless that goes against a one_way zone too.
+    fn reverse_out(&self) -> NavigationCommand {
+        if self.one_way_against(self.pose.heading_deg + 180.0).is_some() {
+            NavigationCommand::Stop
+        } else {
+            NavigationCommand::Backward(self.config.passage_reverse_speed)
+        }
+    }
+
+    fn no_rotate_zone(&self) -> Option<&Zone> {
+        self.config.zones.iter()
+            .find(|z| z.policy == ZonePolicy::NoRotate && zones::contains(&z.polygon, self.pose.x, self.pose.y))
+    }
+
+    // A one_way zone that travel toward `heading` goes against, and
+    // whether the rover is already in it rather than zone_lookahead_m
+    // short of it.
+    fn one_way_against(&self, heading: f32) -> Option<(&Zone, bool)> {
+        let angle = heading.to_radians();
+        let x = self.pose.x + self.config.zone_lookahead_m * angle.sin();
+        let y = self.pose.y + self.config.zone_lookahead_m * angle.cos();
+        self.config.zones.iter()
+            .filter(|z| z.policy == ZonePolicy::OneWay)
+            .filter(|z| z.direction_deg.is_some_and(|d| angle_between(heading, d).abs() > self.config.one_way_tolerance_deg))
+            .find_map(|z| {
+                let inside = zones::contains(&z.polygon, self.pose.x, self.pose.y);
+                (inside || zones::contains(&z.polygon, x, y)).then_some((z, inside))
+            })
+    }
+
+    // Each new violation, or change in one, is reported once.
+    fn check_passages(&mut self) {
+        if self.reversing_out.is_some() && self.no_rotate_zone().map(|z| &z.name) != self.reversing_out.as_ref() {
+            info!(target: "planner", "Backed out of zone {}", self.reversing_out.take().unwrap_or_default());
+        }
+        let (instead, violation) = self.apply_passages(self.planned_command());
+        if let (NavigationCommand::Backward(_), Some(v)) = (&instead, &violation) {
+            if v.policy == ZonePolicy::NoRotate {
+                self.reversing_out = Some(v.zone.clone());
+            }
+        }
+        // Only what the rover does instead is news; what it would have done
+        // changes from tick to tick.
+        let reported = |v: &Option<PassageViolation>| v.as_ref().map(|v| (v.zone.clone(), v.instead.clone()));
+        if reported(&violation) == reported(&self.passage_violation) {
+            self.passage_violation = violation;
+            return;
+        }
+        match &violation {
+            Some(v) => {
+                warn!(target: "planner", "Zone {} rules out {}, going {} instead", v.zone, v.planned, v.instead);
+                EVENTS.publish(Severity::Warning, "planner", format!("Zone {} rules out {}, going {} instead", v.zone, v.planned, v.instead), serde_json::json!({
+                    "zone": v.zone,
+                    "policy": v.policy,
+                    "planned": v.planned,
+                    "instead": v.instead,
+                    "x": self.pose.x,
+                    "y": self.pose.y,
+                    "heading_deg": self.pose.heading_deg,
+                }));
+            }
+            None => debug!(target: "planner", "Passages clear"),
+        }
+        self.passage_violation = violation;
+    }
+
+    pub fn get_passage_violation(&self) -> Option<PassageViolation> {
+        self.passage_violation.clone()
+    }
+
+    // Whether the point zone_lookahead_m away at `bearing` from the
+    // current heading is in a forbid_entry zone.
+    fn forbidden_ahead(&self, bearing: f32) -> bool {
+        let angle = (self.pose.heading_deg + bearing).to_radians();
+        let x = self.pose.x + self.config.zone_lookahead_m * angle.sin();
+        let y = self.pose.y + self.config.zone_lookahead_m * angle.cos();
+        self.config.zones.iter()
+            .any(|z| z.policy == ZonePolicy::ForbidEntry && zones::contains(&z.polygon, x, y))
+    }
+
+    // Needs planner.dock_tag_id; without it there is nothing to dock to.
+    pub fn set_docking(&mut self, docking: bool) {
+        if docking && self.config.dock_tag_id.is_none() {
+            warn!(target: "planner", "Docking requested but planner.dock_tag_id is not set");
+            return;
+        }
+        if docking {
+            info!(target: "planner", "Docking");
+            self.follow = None;
+        }
+        self.docking = docking;
+        self.docked = false;
+    }
+
+    pub fn is_docking(&self) -> bool {
+        self.docking
+    }
+
+    // True once per completed docking.
+    pub fn take_docked(&mut self) -> bool {
+        std::mem::take(&mut self.docked)
+    }
+
+    pub fn set_goal(&mut self, goal: Option<PathPoint>) {
+        if let Some(goal) = &goal {
+            info!(target: "planner", "Goal set to ({:.1}, {:.1})", goal.x, goal.y);
+        }
+        self.goal = goal;
+    }
+
+    // Needs the map tied to GPS, i.e. a good fix since startup or since
+    // planner.gps_origin last changed.
+    pub fn set_goal_geodetic(&mut self, lat: f64, lon: f64) -> Result<()> {
+        let Some(frame) = self.gps_frame else {
+            bail!("No GPS fix has been good enough to place the map yet");
+        };
+        let (x, y) = frame.to_map(lat, lon);
+        self.set_goal(Some(PathPoint { x, y }));
+        Ok(())
+    }
+
     pub fn get_distance_to_goal(&self) -> Option<f32> {
-        self.goal.as_ref().map(|_| 10.0) // Mock distance
+        self.goal.as_ref().map(|g| (g.x - self.pose.x).hypot(g.y - self.pose.y))
     }
     
     pub fn get_status(&self) -> PathStatus {
@@ -63,6 +1412,19 @@ impl PathPlanner {
             has_path: !self.current_path.is_empty(),
             distance_to_goal: self.get_distance_to_goal(),
             obstacles_count: self.obstacles.len(),
+            static_obstacles: self.count_remembered(ObstacleMotion::Static),
+            dynamic_obstacles: self.count_remembered(ObstacleMotion::Dynamic),
+            speed_envelope: self.envelope.clone(),
+            passage_violation: self.passage_violation.clone(),
         }
     }
+
+    fn count_remembered(&self, motion: ObstacleMotion) -> usize {
+        self.remembered.iter().filter(|o| o.hazard == HazardType::Obstacle && o.motion == motion).count()
+    }
+}
+
+// How far clockwise `to` is from `from`, within ±180.
+fn angle_between(from: f32, to: f32) -> f32 {
+    (to - from + 540.0).rem_euclid(360.0) - 180.0
 }
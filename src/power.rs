@@ -0,0 +1,315 @@
+// src/power.rs
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::clock::CLOCK;
+use crate::config::PowerConfig;
+use crate::events::{Severity, EVENTS};
+use crate::interlock::{self, SafeOutput};
+use crate::metrics::METRICS;
+
+pub static POWER: Lazy<Power> = Lazy::new(Power::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerState {
+    Awake,
+    Asleep,
+    // Woken, but frames and the self-test aren't back yet.
+    Waking,
+}
+
+// How the last wake went.
+#[derive(Debug, Clone, Serialize)]
+pub struct WakeReport {
+    // web_client, api, arming, schedule or state.
+    pub reason: String,
+    // Wall-clock ms the wake started.
+    pub at_ms: u64,
+    // How long until frames came at the full rate again, and until the
+    // self-test had finished. None if it didn't happen in time.
+    pub bridge_ms: Option<u64>,
+    pub selftest_ms: Option<u64>,
+    // Until both had, or max_resume_s passed.
+    pub resume_ms: u64,
+    pub timed_out: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PowerStatus {
+    pub state: PowerState,
+    // Wall-clock ms of the last change.
+    pub since_ms: u64,
+    // WebSocket clients taking frames; any one keeps the rover awake.
+    pub video_clients: usize,
+    // Whether the motor driver's enable output is high; None without one.
+    pub motor_driver_enabled: Option<bool>,
+    // The enable output couldn't be opened or driven.
+    pub fault: Option<String>,
+    pub last_wake: Option<WakeReport>,
+}
+
+// What the rest of the rover sees of idle power saving. The state machine
+// decides when to sleep and wake through its IdleManager; anything else
+// can only ask for a wake.
+pub struct Power {
+    enable: Mutex<Option<Box<dyn SafeOutput>>>,
+    status: RwLock<PowerStatus>,
+    asleep: AtomicBool,
+    video_clients: AtomicUsize,
+    wake_request: Mutex<Option<String>>,
+}
+
+impl Power {
+    fn new() -> Self {
+        Self {
+            enable: Mutex::new(None),
+            status: RwLock::new(PowerStatus {
+                state: PowerState::Awake,
+                since_ms: CLOCK.wall_ms(),
+                video_clients: 0,
+                motor_driver_enabled: None,
+                fault: None,
+                last_wake: None,
+            }),
+            asleep: AtomicBool::new(false),
+            video_clients: AtomicUsize::new(0),
+            wake_request: Mutex::new(None),
+        }
+    }
+
+    // Called at startup, and again on a retry if it failed. The motor
+    // driver starts energized.
+    pub fn open(&self, config: &PowerConfig) -> Result<()> {
+        let Some(pin) = config.motor_enable_pin else { return Ok(()) };
+        let opened = interlock::open_output(config.motor_enable_driver, pin).and_then(|mut output| {
+            output.set(true)?;
+            Ok(output)
+        });
+        let mut status = self.status.write();
+        match opened {
+            Ok(output) => {
+                info!(target: "power", "Motor driver enable output opened high on pin {} ({:?} driver)", pin, config.motor_enable_driver);
+                *self.enable.lock() = Some(output);
+                status.motor_driver_enabled = Some(true);
+                status.fault = None;
+                Ok(())
+            }
+            Err(e) => {
+                status.fault = Some(format!("{:#}", e));
+                Err(e)
+            }
+        }
+    }
+
+    pub fn get_status(&self) -> PowerStatus {
+        let mut status = self.status.read().clone();
+        status.video_clients = self.video_clients.load(Ordering::SeqCst);
+        status
+    }
+
+    pub fn get_state(&self) -> PowerState {
+        self.status.read().state
+    }
+
+    // Only while fully asleep; waking already counts as awake here.
+    pub fn is_asleep(&self) -> bool {
+        self.asleep.load(Ordering::SeqCst)
+    }
+
+    pub fn set_video_clients(&self, count: usize) {
+        self.video_clients.store(count, Ordering::SeqCst);
+    }
+
+    // Wakes the rover if it is asleep, and restarts the idle countdown if
+    // it isn't.
+    pub fn request_wake(&self, reason: &str) {
+        self.wake_request.lock().get_or_insert_with(|| reason.to_string());
+    }
+
+    fn take_wake_request(&self) -> Option<String> {
+        self.wake_request.lock().take()
+    }
+
+    fn set_state(&self, state: PowerState) {
+        let mut status = self.status.write();
+        status.state = state;
+        status.since_ms = CLOCK.wall_ms();
+        self.asleep.store(state == PowerState::Asleep, Ordering::SeqCst);
+        METRICS.power_asleep.set((state == PowerState::Asleep) as i64);
+    }
+
+    fn set_motor_driver(&self, enabled: bool) {
+        let mut output = self.enable.lock();
+        let Some(output) = output.as_mut() else { return };
+        let mut status = self.status.write();
+        match output.set(enabled) {
+            Ok(()) => {
+                status.motor_driver_enabled = Some(enabled);
+                status.fault = None;
+            }
+            Err(e) => {
+                warn!(target: "power", "Failed to drive motor driver enable output: {:#}", e);
+                EVENTS.publish(Severity::Warning, "power", format!("Failed to drive motor driver enable output: {:#}", e), serde_json::Value::Null);
+                status.fault = Some(format!("{:#}", e));
+            }
+        }
+    }
+
+    fn sleep(&self, idle_s: f32) {
+        self.set_motor_driver(false);
+        self.set_state(PowerState::Asleep);
+        info!(target: "power", "Parked for {:.0} s with no video clients, going to sleep", idle_s);
+        EVENTS.publish(Severity::Info, "power", format!("Parked for {:.0} s, going to sleep", idle_s), serde_json::json!({
+            "idle_s": idle_s,
+        }));
+    }
+
+    fn begin_wake(&self, reason: &str) {
+        self.set_motor_driver(true);
+        self.set_state(PowerState::Waking);
+        METRICS.power_wakes.inc(reason);
+        info!(target: "power", "Waking up ({})", reason);
+        EVENTS.publish(Severity::Info, "power", format!("Waking up ({})", reason), serde_json::json!({
+            "reason": reason,
+        }));
+    }
+
+    fn finish_wake(&self, report: WakeReport) {
+        METRICS.power_resume_s.set(report.resume_ms as f64 / 1000.0);
+        let data = serde_json::to_value(&report).unwrap_or_default();
+        if report.timed_out {
+            let missing: Vec<&str> = [("frames", report.bridge_ms), ("self-test", report.selftest_ms)].into_iter()
+                .filter(|(_, ms)| ms.is_none())
+                .map(|(name, _)| name)
+                .collect();
+            warn!(target: "power", "Resume took over {} ms, still waiting on {}", report.resume_ms, missing.join(" and "));
+            EVENTS.publish(Severity::Warning, "power", format!("Resume timed out waiting on {}", missing.join(" and ")), data);
+        } else {
+            info!(target: "power", "Awake again in {} ms", report.resume_ms);
+            EVENTS.publish(Severity::Info, "power", format!("Awake again in {} ms", report.resume_ms), data);
+        }
+        self.status.write().last_wake = Some(report);
+        self.set_state(PowerState::Awake);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerAction {
+    // Slow the bridge to the keepalive rate.
+    Sleep,
+    // Bring the bridge back to full rate and re-run the self-test.
+    Wake,
+    // Frames and the self-test are back, or max_resume_s passed.
+    Resumed,
+}
+
+struct Waking {
+    reason: String,
+    at_ms: u64,
+    started: Instant,
+    last_capture_ms: Option<u64>,
+    bridge: Option<Duration>,
+    selftest: Option<Duration>,
+}
+
+// The state machine's half: counts down the idle time and times wakes.
+// Does nothing unless power.idle_enabled is set.
+pub struct IdleManager {
+    config: PowerConfig,
+    idle_since: Option<Instant>,
+    waking: Option<Waking>,
+}
+
+impl IdleManager {
+    pub fn new(config: &PowerConfig) -> Self {
+        Self {
+            config: config.clone(),
+            idle_since: None,
+            waking: None,
+        }
+    }
+
+    pub fn get_keepalive_fps(&self) -> f32 {
+        self.config.keepalive_fps
+    }
+
+    pub fn is_awake(&self) -> bool {
+        POWER.get_state() == PowerState::Awake
+    }
+
+    // Once a tick. `parked` is being in Idle, `capture_ms` the capture
+    // time of the frame this tick consumed, if any.
+    pub fn poll(&mut self, parked: bool, capture_ms: Option<u64>, selftest_finished: bool) -> Option<PowerAction> {
+        let request = POWER.take_wake_request();
+        if !self.config.idle_enabled {
+            return None;
+        }
+        match POWER.get_state() {
+            PowerState::Awake => {
+                if request.is_some() || !parked || !selftest_finished || POWER.video_clients.load(Ordering::SeqCst) > 0 {
+                    self.idle_since = None;
+                    return None;
+                }
+                let since = *self.idle_since.get_or_insert_with(Instant::now);
+                let idle = since.elapsed();
+                if idle < Duration::from_secs_f32(self.config.idle_after_s) {
+                    return None;
+                }
+                self.idle_since = None;
+                POWER.sleep(idle.as_secs_f32());
+                Some(PowerAction::Sleep)
+            }
+            PowerState::Asleep => {
+                // Leaving Idle some other way, such as a fault, wakes too.
+                let reason = request.or_else(|| (!parked).then(|| "state".to_string()))?;
+                POWER.begin_wake(&reason);
+                self.waking = Some(Waking {
+                    reason,
+                    at_ms: CLOCK.wall_ms(),
+                    started: Instant::now(),
+                    last_capture_ms: None,
+                    bridge: None,
+                    selftest: None,
+                });
+                Some(PowerAction::Wake)
+            }
+            PowerState::Waking => {
+                let waking = self.waking.as_mut()?;
+                // Two frames closer together than half the keepalive
+                // interval mean the bridge is back at its full rate.
+                if let Some(capture_ms) = capture_ms {
+                    let keepalive_ms = 1000.0 / self.config.keepalive_fps;
+                    let fast = waking.last_capture_ms.is_some_and(|last| (capture_ms.saturating_sub(last) as f32) < keepalive_ms / 2.0);
+                    if fast && waking.bridge.is_none() {
+                        waking.bridge = Some(waking.started.elapsed());
+                    }
+                    waking.last_capture_ms = Some(capture_ms);
+                }
+                if selftest_finished && waking.selftest.is_none() {
+                    waking.selftest = Some(waking.started.elapsed());
+                }
+                let elapsed = waking.started.elapsed();
+                let timed_out = elapsed > Duration::from_secs_f32(self.config.max_resume_s);
+                if !timed_out && (waking.bridge.is_none() || waking.selftest.is_none()) {
+                    return None;
+                }
+                let waking = self.waking.take()?;
+                POWER.finish_wake(WakeReport {
+                    reason: waking.reason,
+                    at_ms: waking.at_ms,
+                    bridge_ms: waking.bridge.map(|d| d.as_millis() as u64),
+                    selftest_ms: waking.selftest.map(|d| d.as_millis() as u64),
+                    resume_ms: waking.bridge.max(waking.selftest).filter(|_| !timed_out).unwrap_or(elapsed).as_millis() as u64,
+                    timed_out,
+                });
+                Some(PowerAction::Resumed)
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+// src/range.rs
+use anyhow::Result;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::config::{RangeConfig, RangeSensorConfig, RangeSensorKind};
+use crate::events::{Severity, EVENTS};
+use crate::metrics::METRICS;
+
+// A sensor that failed to open is tried again after this.
+const REOPEN_DELAY: Duration = Duration::from_secs(5);
+
+pub trait RangeSensor: Send {
+    // Metres to the nearest thing in the sensor's cone, or None when
+    // nothing is in range.
+    fn read(&mut self) -> Result<Option<f32>>;
+}
+
+// Reports a fixed distance, for running without hardware.
+pub struct MockRangeSensor {
+    distance_m: Option<f32>,
+}
+
+impl MockRangeSensor {
+    pub fn new(distance_m: Option<f32>) -> Self {
+        Self { distance_m }
+    }
+}
+
+impl RangeSensor for MockRangeSensor {
+    fn read(&mut self) -> Result<Option<f32>> {
+        Ok(self.distance_m)
+    }
+}
+
+#[cfg(feature = "range-sensors")]
+mod hardware {
+    use anyhow::{anyhow, bail, Result};
+    use rppal::gpio::{Gpio, InputPin, OutputPin};
+    use rppal::i2c::I2c;
+    use std::time::{Duration, Instant};
+
+    use super::RangeSensor;
+
+    const SPEED_OF_SOUND_M_S: f32 = 343.0;
+    // An echo this long is about 4 m, the HC-SR04's limit; longer means
+    // nothing answered.
+    const MAX_ECHO: Duration = Duration::from_millis(25);
+    // Time-of-flight readings at or above this mean out of range.
+    const VL53L0X_OUT_OF_RANGE_MM: u16 = 8000;
+
+    pub struct HcSr04 {
+        trigger: OutputPin,
+        echo: InputPin,
+    }
+
+    impl HcSr04 {
+        pub fn open(trigger_pin: u8, echo_pin: u8) -> Result<Self> {
+            let gpio = Gpio::new()?;
+            Ok(Self {
+                trigger: gpio.get(trigger_pin)?.into_output_low(),
+                echo: gpio.get(echo_pin)?.into_input(),
+            })
+        }
+
+        fn wait_for(&self, high: bool, timeout: Duration) -> Option<Instant> {
+            let start = Instant::now();
+            while self.echo.is_high() != high {
+                if start.elapsed() > timeout {
+                    return None;
+                }
+            }
+            Some(Instant::now())
+        }
+    }
+
+    impl RangeSensor for HcSr04 {
+        // Busy-waits on the echo pin, at most ~30 ms.
+        fn read(&mut self) -> Result<Option<f32>> {
+            self.trigger.set_high();
+            std::thread::sleep(Duration::from_micros(10));
+            self.trigger.set_low();
+            let Some(rise) = self.wait_for(true, Duration::from_millis(5)) else {
+                bail!("no echo pulse");
+            };
+            Ok(self.wait_for(false, MAX_ECHO)
+                .map(|fall| (fall - rise).as_secs_f32() * SPEED_OF_SOUND_M_S / 2.0))
+        }
+    }
+
+    pub struct Vl53l0x {
+        device: vl53l0x::VL53L0x<I2c>,
+    }
+
+    impl Vl53l0x {
+        pub fn open(bus: u8, address: u8) -> Result<Self> {
+            let i2c = I2c::with_bus(bus)?;
+            let mut device = vl53l0x::VL53L0x::with_address(i2c, address)
+                .map_err(|e| anyhow!("VL53L0X init failed: {:?}", e))?;
+            device.start_continuous(0).map_err(|e| anyhow!("VL53L0X start failed: {:?}", e))?;
+            Ok(Self { device })
+        }
+    }
+
+    impl RangeSensor for Vl53l0x {
+        fn read(&mut self) -> Result<Option<f32>> {
+            let mm = self.device.read_range_continuous_millimeters_blocking()
+                .map_err(|e| anyhow!("VL53L0X read failed: {:?}", e))?;
+            Ok((mm < VL53L0X_OUT_OF_RANGE_MM).then(|| mm as f32 / 1000.0))
+        }
+    }
+}
+
+#[cfg(feature = "range-sensors")]
+fn open(config: &RangeSensorConfig) -> Result<Box<dyn RangeSensor>> {
+    Ok(match config.kind {
+        RangeSensorKind::HcSr04 => Box::new(hardware::HcSr04::open(
+            config.trigger_pin.unwrap_or_default(),
+            config.echo_pin.unwrap_or_default(),
+        )?),
+        RangeSensorKind::Vl53l0x => Box::new(hardware::Vl53l0x::open(config.i2c_bus, config.i2c_address)?),
+        RangeSensorKind::Mock => Box::new(MockRangeSensor::new(config.mock_distance_m)),
+    })
+}
+
+#[cfg(not(feature = "range-sensors"))]
+fn open(config: &RangeSensorConfig) -> Result<Box<dyn RangeSensor>> {
+    match config.kind {
+        RangeSensorKind::Mock => Ok(Box::new(MockRangeSensor::new(config.mock_distance_m))),
+        _ => anyhow::bail!("built without the `range-sensors` feature"),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RangeReading {
+    pub sensor: String,
+    pub bearing_deg: f32,
+    pub fov_deg: f32,
+    // None when nothing is in range, or the sensor isn't ok.
+    pub distance_m: Option<f32>,
+    pub age_ms: Option<u64>,
+    // Read without error within range.timeout_ms.
+    pub ok: bool,
+    pub error: Option<String>,
+    // Only for sensors looking down at the floor: where the floor should
+    // be, and whether the sensor has lost it.
+    pub floor_m: Option<f32>,
+    pub drop_off: bool,
+}
+
+struct Slot {
+    config: RangeSensorConfig,
+    last: Option<(Option<f32>, Instant)>,
+    error: Option<String>,
+    // As last reported in an event, so failures are reported once.
+    failed: bool,
+}
+
+// The latest reading of every configured sensor. Sampling happens on its
+// own thread; the control loop polls.
+pub struct RangeMonitor {
+    config: RangeConfig,
+    slots: Mutex<Vec<Slot>>,
+    started: Instant,
+}
+
+impl RangeMonitor {
+    pub fn new(config: RangeConfig) -> Arc<Self> {
+        let sensors = if config.enabled { config.sensors.clone() } else { Vec::new() };
+        Arc::new(Self {
+            slots: Mutex::new(sensors.into_iter()
+                .map(|config| Slot { config, last: None, error: None, failed: false })
+                .collect()),
+            config,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn spawn(self: &Arc<Self>) {
+        let configs: Vec<_> = self.slots.lock().iter().map(|s| s.config.clone()).collect();
+        if configs.is_empty() {
+            return;
+        }
+        info!(target: "range", "Sampling {} range sensor(s) at {} Hz", configs.len(), self.config.sample_hz);
+        let monitor = self.clone();
+        std::thread::spawn(move || {
+            let period = Duration::from_secs_f32(1.0 / monitor.config.sample_hz);
+            let mut sensors: Vec<Option<Box<dyn RangeSensor>>> = configs.iter().map(|_| None).collect();
+            let mut opened_at: Vec<Option<Instant>> = vec![None; configs.len()];
+            loop {
+                let tick = Instant::now();
+                for (i, config) in configs.iter().enumerate() {
+                    if sensors[i].is_none() && opened_at[i].map_or(true, |at| at.elapsed() >= REOPEN_DELAY) {
+                        opened_at[i] = Some(Instant::now());
+                        match open(config) {
+                            Ok(sensor) => sensors[i] = Some(sensor),
+                            Err(e) => monitor.record(i, Err(e.context(format!("Failed to open {}", config.name)))),
+                        }
+                    }
+                    if let Some(sensor) = sensors[i].as_mut() {
+                        monitor.record(i, sensor.read());
+                    }
+                }
+                if let Some(rest) = period.checked_sub(tick.elapsed()) {
+                    std::thread::sleep(rest);
+                }
+            }
+        });
+    }
+
+    fn record(&self, index: usize, reading: Result<Option<f32>>) {
+        let mut slots = self.slots.lock();
+        let slot = &mut slots[index];
+        match reading {
+            Ok(distance) => {
+                slot.last = Some((distance, Instant::now()));
+                slot.error = None;
+            }
+            Err(e) => slot.error = Some(format!("{:#}", e)),
+        }
+    }
+
+    pub fn get_readings(&self) -> Vec<RangeReading> {
+        self.slots.lock().iter().map(|slot| self.reading(slot)).collect()
+    }
+
+    // For the control loop: the readings, with sensors that failed or
+    // recovered since the last poll reported as events.
+    pub fn poll(&self) -> Vec<RangeReading> {
+        let mut slots = self.slots.lock();
+        let mut readings = Vec::with_capacity(slots.len());
+        for slot in slots.iter_mut() {
+            let reading = self.reading(slot);
+            // A sensor that hasn't answered yet gets timeout_ms before it
+            // counts as failed.
+            let waited = slot.last.map_or(self.started, |(_, at)| at).elapsed();
+            let failed = slot.error.is_some() || waited > Duration::from_millis(self.config.timeout_ms);
+            if failed != slot.failed {
+                slot.failed = failed;
+                let data = serde_json::json!({ "sensor": reading.sensor, "error": reading.error });
+                if failed {
+                    METRICS.range_failures.inc(&slot.config.name);
+                    let why = reading.error.clone().unwrap_or_else(|| format!("no reading for {} ms", waited.as_millis()));
+                    EVENTS.publish(Severity::Warning, "range", format!("Range sensor {} failed ({}), near field is vision only", slot.config.name, why), data);
+                } else {
+                    EVENTS.publish(Severity::Info, "range", format!("Range sensor {} recovered", slot.config.name), data);
+                }
+            }
+            readings.push(reading);
+        }
+        readings
+    }
+
+    fn reading(&self, slot: &Slot) -> RangeReading {
+        let age = slot.last.map(|(_, at)| at.elapsed());
+        let ok = slot.error.is_none() && age.is_some_and(|age| age <= Duration::from_millis(self.config.timeout_ms));
+        let distance_m = if ok { slot.last.and_then(|(d, _)| d) } else { None };
+        let floor_m = slot.config.floor_m;
+        RangeReading {
+            sensor: slot.config.name.clone(),
+            bearing_deg: slot.config.bearing_deg,
+            fov_deg: slot.config.fov_deg,
+            distance_m,
+            age_ms: age.map(|age| age.as_millis() as u64),
+            ok,
+            error: slot.error.clone(),
+            floor_m,
+            drop_off: ok && floor_m.is_some_and(|floor| distance_m.map_or(true, |d| d > floor + self.config.dropoff_margin_m)),
+        }
+    }
+
+    // Whether a working sensor sees something closer than range.stop_m.
+    // Sensors looking at the floor always do, so they don't count.
+    pub fn must_stop(&self, readings: &[RangeReading]) -> bool {
+        readings.iter().any(|r| r.ok && r.floor_m.is_none() && r.distance_m.is_some_and(|d| d < self.config.stop_m))
+    }
+
+    pub fn get_near_field_m(&self) -> f32 {
+        self.config.near_field_m
+    }
+}
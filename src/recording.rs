@@ -0,0 +1,73 @@
+// src/recording.rs
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::path::Path;
+
+use crate::session::{self, SessionEntry};
+use crate::vision::Detection;
+
+// One frame message out of a session recording.
+#[derive(Debug, Clone)]
+pub struct RecordedFrame {
+    // Since the recording started.
+    pub t_ms: u64,
+    pub tick: Option<u64>,
+    // The bridge's id for the frame; None before it was recorded.
+    pub frame_id: Option<u32>,
+    pub detections: Vec<Detection>,
+    // Base64 JPEG. Only frames recorded with session.include_images, one
+    // every session.image_interval_s, have one.
+    pub image: Option<String>,
+    // Some(None) was recorded on the base vision settings, None before
+    // profiles were recorded at all.
+    pub profile: Option<Option<String>>,
+    pub brightness: Option<f32>,
+    // Version of the ground calibration the detections were placed with;
+    // None without one, and before they were recorded.
+    pub ground_calibration: Option<u32>,
+}
+
+impl RecordedFrame {
+    // None for anything that isn't a frame, or whose detections don't
+    // parse.
+    pub fn from_entry(entry: &SessionEntry) -> Option<Self> {
+        let payload = &entry.payload;
+        if payload.get("type").and_then(|t| t.as_str()) != Some("frame") {
+            return None;
+        }
+        let detections = serde_json::from_value(payload.get("detections")?.clone()).ok()?;
+        let profile = payload.get("vision_profile");
+        Some(Self {
+            t_ms: entry.t_ms,
+            tick: payload.get("tick").and_then(|t| t.as_u64()),
+            frame_id: payload.get("frame_id").and_then(|f| f.as_u64()).map(|f| f as u32),
+            detections,
+            image: payload.get("image").and_then(|i| i.as_str()).filter(|i| !i.is_empty()).map(str::to_string),
+            profile: profile.map(|p| p.get("active").and_then(|a| a.as_str()).map(str::to_string)),
+            brightness: profile.and_then(|p| p.get("brightness")).and_then(|b| b.as_f64()).map(|b| b as f32),
+            ground_calibration: payload.get("ground_calibration").and_then(|g| g.as_u64()).map(|g| g as u32),
+        })
+    }
+
+    // The raw confidence a detection was made with. Recordings from before
+    // calibration only have the one value.
+    pub fn raw_confidence(detection: &Detection) -> f32 {
+        if detection.raw_confidence > 0.0 { detection.raw_confidence } else { detection.confidence }
+    }
+
+    pub fn decode_image(&self) -> Result<Option<Vec<u8>>> {
+        let Some(image) = &self.image else { return Ok(None) };
+        let jpeg = base64::engine::general_purpose::STANDARD.decode(image)
+            .context("Recorded frame image is not valid base64")?;
+        Ok(Some(jpeg))
+    }
+}
+
+// The frames of a recording in order; everything else in it is skipped.
+pub fn frames(entries: impl Iterator<Item = SessionEntry>) -> impl Iterator<Item = RecordedFrame> {
+    entries.filter_map(|entry| RecordedFrame::from_entry(&entry))
+}
+
+pub fn open_frames(path: &Path) -> Result<impl Iterator<Item = RecordedFrame>> {
+    Ok(frames(session::open_session_path(path)?))
+}
@@ -0,0 +1,300 @@
+// src/ros.rs
+use parking_lot::RwLock;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::clock::CLOCK;
+use crate::config::RosConfig;
+use crate::mapping::{OccupancyValues, RoverPose};
+use crate::metrics::METRICS;
+use crate::motor_control::MotorStatus;
+use crate::pathfinding::PathPlanner;
+use crate::state_machine::StateHandle;
+use crate::vision::Detection;
+
+// Everything here speaks ROS axes (REP 103): x forward, y left and yaw
+// anticlockwise from x. The map has +y forward, +x right and headings
+// clockwise, so the two differ by a quarter turn and a mirror.
+fn to_ros(pose: &RoverPose) -> (f64, f64, f64) {
+    (pose.y as f64, -pose.x as f64, -(pose.heading_deg as f64).to_radians())
+}
+
+fn quaternion(yaw: f64) -> Value {
+    json!({ "x": 0.0, "y": 0.0, "z": (yaw / 2.0).sin(), "w": (yaw / 2.0).cos() })
+}
+
+// builtin_interfaces/Time from rover-clock milliseconds.
+fn stamp(mono_ms: u64) -> Value {
+    let wall_ms = CLOCK.to_wall_ms(mono_ms);
+    json!({ "sec": wall_ms / 1000, "nanosec": (wall_ms % 1000) * 1_000_000 })
+}
+
+fn header(frame_id: &str, mono_ms: u64) -> Value {
+    json!({ "stamp": stamp(mono_ms), "frame_id": frame_id })
+}
+
+// nav_msgs/Odometry. The turn rate comes from the heading change since
+// the previous message.
+pub fn odometry(config: &RosConfig, pose: &RoverPose, motors: &MotorStatus, yaw_rate: f64) -> Value {
+    let (x, y, yaw) = to_ros(pose);
+    json!({
+        "header": header(&config.frame_id, CLOCK.now_ms()),
+        "child_frame_id": config.child_frame_id,
+        "pose": { "pose": {
+            "position": { "x": x, "y": y, "z": 0.0 },
+            "orientation": quaternion(yaw),
+        } },
+        "twist": { "twist": {
+            "linear": { "x": motors.speed, "y": 0.0, "z": 0.0 },
+            "angular": { "x": 0.0, "y": 0.0, "z": yaw_rate },
+        } },
+    })
+}
+
+// vision_msgs/Detection2DArray as of Humble, with boxes in image pixels.
+// The track id, when there is one, goes in `id`.
+pub fn detections(config: &RosConfig, detections: &[Detection]) -> Value {
+    let capture_ms = detections.iter().map(|d| d.capture_ms).max().unwrap_or_else(|| CLOCK.now_ms());
+    let items: Vec<Value> = detections.iter()
+        .map(|d| {
+            let (x, y, w, h) = d.bbox;
+            json!({
+                "header": header(&config.child_frame_id, d.capture_ms),
+                "results": [{
+                    "hypothesis": { "class_id": d.class_name, "score": d.confidence },
+                    "pose": { "pose": { "position": { "x": d.distance_estimate, "y": 0.0, "z": 0.0 } } },
+                }],
+                "bbox": {
+                    "center": { "position": { "x": x as f32 + w as f32 / 2.0, "y": y as f32 + h as f32 / 2.0 }, "theta": 0.0 },
+                    "size_x": w,
+                    "size_y": h,
+                },
+                "id": d.track_id.map(|id| id.to_string()).unwrap_or_default(),
+            })
+        })
+        .collect();
+    json!({ "header": header(&config.child_frame_id, capture_ms), "detections": items })
+}
+
+// nav_msgs/OccupancyGrid. Grid columns run along the map's +x, which is
+// ROS's -y, so the grid origin is turned a quarter turn clockwise.
+pub fn occupancy_grid(config: &RosConfig, grid: &OccupancyValues) -> Value {
+    let res = grid.resolution_m as f64;
+    let now = CLOCK.now_ms();
+    json!({
+        "header": header(&config.frame_id, now),
+        "info": {
+            "map_load_time": stamp(now),
+            "resolution": grid.resolution_m,
+            "width": grid.width,
+            "height": grid.height,
+            "origin": {
+                "position": {
+                    "x": -((grid.height / 2) as f64 * res),
+                    "y": (grid.width / 2) as f64 * res,
+                    "z": 0.0,
+                },
+                "orientation": quaternion(-std::f64::consts::FRAC_PI_2),
+            },
+        },
+        "data": grid.data,
+    })
+}
+
+// A geometry_msgs/Twist as (m/s forward, rad/s anticlockwise). Anything
+// not finite is refused rather than clamped.
+pub fn parse_twist(msg: &Value) -> Option<(f32, f32)> {
+    let linear = msg.pointer("/linear/x").and_then(Value::as_f64).unwrap_or(0.0) as f32;
+    let angular = msg.pointer("/angular/z").and_then(Value::as_f64).unwrap_or(0.0) as f32;
+    (linear.is_finite() && angular.is_finite()).then_some((linear, angular))
+}
+
+// What the bridge reads from and drives.
+pub struct RosHandles {
+    pub path_planner: Arc<RwLock<PathPlanner>>,
+    pub state: StateHandle,
+}
+
+// Decides what is due on each poll and turns it into rosbridge operations.
+// Reads the world state, and the map is copied out before it is encoded.
+pub struct Bridge {
+    config: RosConfig,
+    last_odom: Option<(Instant, f32)>,
+    last_detections: Option<Instant>,
+    // Newest capture time of the last detections sent; None once an empty
+    // set has gone out, so an empty frame is only sent once.
+    last_capture_ms: Option<Option<u64>>,
+    last_map: Option<(Instant, u64)>,
+}
+
+impl Bridge {
+    pub fn new(config: &RosConfig) -> Self {
+        Self {
+            config: config.clone(),
+            last_odom: None,
+            last_detections: None,
+            last_capture_ms: None,
+            last_map: None,
+        }
+    }
+
+    // Sent on every (re)connect. The map is latched so a late subscriber
+    // gets it without waiting for a change; cmd_vel keeps only the newest.
+    pub fn setup(&self) -> Vec<Value> {
+        vec![
+            json!({ "op": "advertise", "topic": self.config.odom_topic, "type": "nav_msgs/msg/Odometry" }),
+            json!({ "op": "advertise", "topic": self.config.detections_topic, "type": "vision_msgs/msg/Detection2DArray" }),
+            json!({ "op": "advertise", "topic": self.config.map_topic, "type": "nav_msgs/msg/OccupancyGrid", "latch": true }),
+            json!({ "op": "subscribe", "topic": self.config.cmd_vel_topic, "type": "geometry_msgs/msg/Twist", "queue_length": 1 }),
+        ]
+    }
+
+    // A new connection starts from scratch, map included.
+    pub fn reset(&mut self) {
+        *self = Self::new(&self.config);
+    }
+
+    pub fn poll(&mut self, handles: &RosHandles) -> Vec<Value> {
+        let mut out = Vec::new();
+        let world = handles.state.get_world();
+
+        let odom_period = Duration::from_secs_f32(1.0 / self.config.odom_hz);
+        if self.last_odom.map_or(true, |(at, _)| at.elapsed() >= odom_period) {
+            let (pose, motors) = (world.pose, &world.motors);
+            let yaw_rate = match self.last_odom {
+                Some((at, heading)) => -((pose.heading_deg - heading) as f64).to_radians() / at.elapsed().as_secs_f64(),
+                None => 0.0,
+            };
+            self.last_odom = Some((Instant::now(), pose.heading_deg));
+            out.push(self.publish(&self.config.odom_topic, odometry(&self.config, &pose, motors, yaw_rate)));
+        }
+
+        let detections_period = Duration::from_secs_f32(1.0 / self.config.detections_hz);
+        if self.last_detections.map_or(true, |at| at.elapsed() >= detections_period) {
+            let current = &world.detections;
+            let capture_ms = current.iter().map(|d| d.capture_ms).max();
+            if self.last_capture_ms != Some(capture_ms) {
+                self.last_capture_ms = Some(capture_ms);
+                self.last_detections = Some(Instant::now());
+                out.push(self.publish(&self.config.detections_topic, detections(&self.config, current)));
+            }
+        }
+
+        let map_period = Duration::from_secs_f32(self.config.map_interval_s);
+        if self.last_map.map_or(true, |(at, _)| at.elapsed() >= map_period) {
+            let planner = handles.path_planner.read();
+            let version = planner.get_map_version();
+            if self.last_map.map_or(true, |(_, sent)| sent != version) {
+                let grid = planner.get_occupancy();
+                drop(planner);
+                self.last_map = Some((Instant::now(), version));
+                out.push(self.publish(&self.config.map_topic, occupancy_grid(&self.config, &grid)));
+            }
+        }
+
+        out
+    }
+
+    fn publish(&self, topic: &str, msg: Value) -> Value {
+        METRICS.ros_published.inc(topic);
+        json!({ "op": "publish", "topic": topic, "msg": msg })
+    }
+
+    // cmd_vel feeds the manual drive source. It only moves the rover once
+    // it has been put in manual mode; the bridge never changes the mode.
+    pub fn handle(&self, op: &Value, state: &StateHandle) {
+        match op.get("op").and_then(Value::as_str) {
+            Some("publish") if op.get("topic").and_then(Value::as_str) == Some(self.config.cmd_vel_topic.as_str()) => {
+                let twist = op.get("msg").and_then(parse_twist);
+                let result = match twist {
+                    Some((linear, angular)) if state.drive_manual(linear, angular) => "applied",
+                    Some(_) => "ignored",
+                    None => "invalid",
+                };
+                METRICS.ros_cmd_vel.inc(result);
+            }
+            Some("status") => {
+                let level = op.get("level").and_then(Value::as_str).unwrap_or("info");
+                let msg = op.get("msg").and_then(Value::as_str).unwrap_or_default();
+                if matches!(level, "error" | "warning") {
+                    tracing::warn!(target: "ros", "rosbridge {}: {}", level, msg);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "ros")]
+pub fn spawn(config: &RosConfig, handles: RosHandles) {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+    use tracing::{info, warn};
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(20);
+    const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+    if !config.enabled {
+        return;
+    }
+    let url = config.url.clone();
+    let mut bridge = Bridge::new(config);
+    info!(target: "ros", "Bridging to ROS through {}", url);
+
+    tokio::spawn(async move {
+        // None until the first attempt, so an unreachable rosbridge is
+        // reported once rather than every few seconds.
+        let mut connected = None;
+        loop {
+            let session = async {
+                let (mut socket, _) = tokio_tungstenite::connect_async(&url).await?;
+                connected = Some(true);
+                METRICS.ros_connected.set(1);
+                info!(target: "ros", "Connected to rosbridge");
+                bridge.reset();
+                for op in bridge.setup() {
+                    socket.send(Message::Text(op.to_string())).await?;
+                }
+                let mut ticker = tokio::time::interval(POLL_INTERVAL);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            for op in bridge.poll(&handles) {
+                                socket.send(Message::Text(op.to_string())).await?;
+                            }
+                        }
+                        incoming = socket.next() => match incoming {
+                            Some(Ok(Message::Text(text))) => {
+                                if let Ok(op) = serde_json::from_str::<Value>(&text) {
+                                    bridge.handle(&op, &handles.state);
+                                }
+                            }
+                            Some(Ok(Message::Close(_))) | None => {
+                                return Err(tokio_tungstenite::tungstenite::Error::ConnectionClosed);
+                            }
+                            Some(Ok(_)) => {}
+                            Some(Err(e)) => return Err(e),
+                        },
+                    }
+                }
+            };
+            let result: Result<(), tokio_tungstenite::tungstenite::Error> = session.await;
+            if let Err(e) = result {
+                if connected != Some(false) {
+                    warn!(target: "ros", "No rosbridge connection: {}", e);
+                    connected = Some(false);
+                    METRICS.ros_connected.set(0);
+                }
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+#[cfg(not(feature = "ros"))]
+pub fn spawn(config: &RosConfig, _handles: RosHandles) {
+    if config.enabled {
+        tracing::warn!(target: "ros", "ros.enabled is set but the binary was built without the `ros` feature");
+    }
+}
@@ -0,0 +1,379 @@
+// src/rover.rs
+use anyhow::{Context, Result};
+use parking_lot::{Mutex, RwLock};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Notify;
+use tokio::time::{sleep, Duration};
+use tracing::{debug, debug_span, error, info, warn};
+
+use crate::annunciator;
+use crate::blackbox;
+use crate::checkpoint::{self, ResumeOffer};
+use crate::config::{ConfigStore, ControlConfig, LoopMode};
+use crate::deliberation;
+use crate::events::{Severity, EVENTS};
+use crate::gps;
+use crate::hazards::HAZARDS;
+use crate::health::{HealthHandle, HealthRegistry};
+use crate::interlock::INTERLOCK;
+use crate::metrics::METRICS;
+use crate::motor_control::MotorController;
+use crate::mqtt::{self, MqttHandles};
+use crate::pathfinding::PathPlanner;
+use crate::power::POWER;
+use crate::range::RangeMonitor;
+use crate::ros::{self, RosHandles};
+use crate::scheduler::Scheduler;
+use crate::selftest::SelfTest;
+use crate::simulation::FrameSource;
+use crate::startup::{self, Startup};
+use crate::state_machine::{RoverState, RoverStateMachine, StateHandle, Trigger};
+use crate::thermal;
+use crate::updates::ASSETS;
+use crate::vision::VisionSystem;
+use crate::watchdog;
+use crate::web::WebServer;
+
+// What a pass of the reflex loop should take at most, planning aside.
+const REFLEX_BUDGET: Duration = Duration::from_millis(10);
+
+// The wired-up rover stack. Every CLI mode that drives the rover builds one
+// of these and only differs in where frames come from.
+pub struct Rover {
+    pub config: Arc<ConfigStore>,
+    pub health: Arc<HealthRegistry>,
+    pub vision: Arc<RwLock<VisionSystem>>,
+    pub path_planner: Arc<RwLock<PathPlanner>>,
+    pub motor_controller: Arc<RwLock<MotorController>>,
+    pub state: StateHandle,
+    pub scheduler: Arc<Scheduler>,
+    pub ranges: Arc<RangeMonitor>,
+    pub selftest: Arc<SelfTest>,
+    pub startup: Arc<Startup>,
+    // Taken by start_subsystems().
+    source: Mutex<Option<FrameSource>>,
+    state_machine: Mutex<RoverStateMachine>,
+    loop_health: HealthHandle,
+    // Taken by start_planning().
+    plan_health: Mutex<Option<HealthHandle>>,
+}
+
+impl Rover {
+    pub fn new(config: Arc<ConfigStore>, source: FrameSource) -> Result<Self> {
+        let initial_config = config.get();
+
+        let health = Arc::new(HealthRegistry::new());
+        let loop_health = health.register("control_loop", Some(Duration::from_secs(1)));
+        // Stale when the reflex loop would stop for want of a plan.
+        let plan_health = health.register("planning_loop", Some(Duration::from_millis(initial_config.control.plan_stale_ms)));
+
+        // Before the bridge starts, so it starts on the active assets.
+        if let Err(e) = ASSETS.open(&initial_config.updates) {
+            warn!(target: "updates", "Asset updates disabled: {:#}", e);
+        }
+        // Low from here on until the watchdog finds everything in order;
+        // the self-test fails if it couldn't be opened.
+        if let Err(e) = INTERLOCK.open(&initial_config.interlock) {
+            error!(target: "interlock", "Failed to open safe output: {:#}", e);
+        }
+        HAZARDS.configure(&initial_config.hazards);
+
+        // Vision and the motor driver are started by start_subsystems(),
+        // behind the web server.
+        let startup = Startup::new(&[startup::VISION, startup::MOTORS]);
+        let vision = Arc::new(RwLock::new(VisionSystem::unstarted(&health, initial_config.vision)));
+        let path_planner = Arc::new(RwLock::new(PathPlanner::new(&health, initial_config.planner)));
+        let motor_controller = Arc::new(RwLock::new(MotorController::new(&health, initial_config.motors)?));
+        let ranges = RangeMonitor::new(initial_config.range);
+        let selftest = SelfTest::new(config.clone(), health.clone(), motor_controller.clone());
+        let state_machine = RoverStateMachine::new(
+            vision.clone(),
+            path_planner.clone(),
+            motor_controller.clone(),
+            ranges.clone(),
+            &config.get(),
+            selftest.clone(),
+            startup.clone(),
+        );
+
+        let scheduler = Arc::new(Scheduler::new(
+            config.clone(),
+            state_machine.handle(),
+            path_planner.clone(),
+        ));
+
+        Ok(Self {
+            config,
+            health,
+            vision,
+            path_planner,
+            motor_controller,
+            state: state_machine.handle(),
+            scheduler,
+            ranges,
+            selftest,
+            startup,
+            source: Mutex::new(Some(source)),
+            state_machine: Mutex::new(state_machine),
+            loop_health,
+            plan_health: Mutex::new(Some(plan_health)),
+        })
+    }
+
+    pub fn start_web(&self) -> Arc<WebServer> {
+        let web_config = self.config.get().web;
+        info!(target: "web", "Starting web server on {}:{}", web_config.bind_address, web_config.port);
+
+        let web_server = Arc::new(WebServer::new(
+            self.vision.clone(),
+            self.path_planner.clone(),
+            self.motor_controller.clone(),
+            self.state.clone(),
+            self.scheduler.clone(),
+            self.health.clone(),
+            self.config.clone(),
+        ));
+
+        let server = web_server.clone();
+        tokio::spawn(async move {
+            server.run().await;
+        });
+        web_server
+    }
+
+    // Starts vision on the frame source and opens the motor driver on a
+    // thread of their own, then does the same for each retry. Until one
+    // is ready the rover stays in Boot, and once one fails it stays in
+    // Fault, with the web server up either way. Only the first call does
+    // anything.
+    pub fn start_subsystems(&self) {
+        let Some(source) = self.source.lock().take() else { return };
+        let (config, vision, startup) = (self.config.clone(), self.vision.clone(), self.startup.clone());
+        std::thread::spawn(move || {
+            while let Some(name) = startup.next() {
+                let result = match name {
+                    startup::VISION => vision.write().start(source.clone()),
+                    startup::MOTORS => POWER.open(&config.get().power).context("Failed to open motor driver enable output"),
+                    _ => continue,
+                };
+                startup.finish(name, &result);
+            }
+        });
+    }
+
+    // Does nothing unless mqtt.enabled is set. Publishing runs on its own
+    // tasks, so a broker outage never holds up the control loop.
+    pub fn start_mqtt(&self) {
+        let config = self.config.get();
+        mqtt::spawn(&config.mqtt, &config.identity.rover_id, MqttHandles {
+            path_planner: self.path_planner.clone(),
+            state: self.state.clone(),
+        });
+    }
+
+    // Does nothing unless gps.enabled is set. Fixes are read on their own
+    // thread and written straight into the planner.
+    pub fn start_gps(&self) {
+        gps::spawn(&self.config.get().gps, self.path_planner.clone());
+    }
+
+    // Does nothing unless ros.enabled is set. Like MQTT, the bridge runs
+    // on its own task and only takes read locks, except for cmd_vel, which
+    // goes to the state machine's manual drive source.
+    pub fn start_ros(&self) {
+        ros::spawn(&self.config.get().ros, RosHandles {
+            path_planner: self.path_planner.clone(),
+            state: self.state.clone(),
+        });
+    }
+
+    // Does nothing unless annunciator.enabled is set.
+    pub fn start_annunciator(&self) {
+        annunciator::spawn(&self.config.get().annunciator, self.motor_controller.clone());
+    }
+
+    // Does nothing unless blackbox.enabled is set. Started before the other
+    // subsystems so their startup events are recorded too.
+    pub fn start_blackbox(&self) {
+        blackbox::spawn(&self.config.get().blackbox);
+    }
+
+    // Does nothing unless thermal.enabled is set.
+    pub fn start_thermal(&self) {
+        thermal::spawn(&self.config.get().thermal, self.motor_controller.clone());
+    }
+
+    // Does nothing unless range.enabled is set.
+    pub fn start_ranges(&self) {
+        self.ranges.spawn();
+    }
+
+    // Does nothing unless checkpoint.enabled is set. Picks up the pose, map
+    // and obstacles from the checkpoint an unexpected restart left, and
+    // offers the mission it was on, if any, for an operator to resume. Call
+    // before the control loop first ticks, so start_autonomous waits too.
+    pub fn load_checkpoint(&self) -> Option<ResumeOffer> {
+        let config = self.config.get().checkpoint;
+        if !config.enabled {
+            return None;
+        }
+        let checkpoint = checkpoint::load(&config)?;
+        let offer = checkpoint::restore(checkpoint, &mut self.path_planner.write())?;
+        warn!(target: "checkpoint", "Restarted mid-mission; waiting for an operator to resume or decline it");
+        EVENTS.publish(Severity::Warning, "checkpoint", "Restarted mid-mission, waiting to be resumed", serde_json::json!({
+            "mission": offer.mission,
+            "written_ms": offer.written_ms,
+        }));
+        self.state.offer_resume(offer.clone());
+        Some(offer)
+    }
+
+    // Does nothing unless checkpoint.enabled is set.
+    pub fn start_checkpoints(&self) {
+        checkpoint::spawn(&self.config.get().checkpoint, self.state.clone(), self.path_planner.clone());
+    }
+
+    // One pass of the control loop, `dt` after the last, planning as part
+    // of it. A harness can call this directly to step the rover through
+    // simulated time.
+    pub fn tick(&self, dt: Duration) -> Result<()> {
+        self.state_machine.lock().tick(dt)
+    }
+
+    // One pass of the reflex loop alone, as run() makes on its own
+    // schedule, driving on the last plan from start_planning()'s thread.
+    pub fn reflex_tick(&self, dt: Duration) -> Result<()> {
+        self.state_machine.lock().reflex_tick(dt)
+    }
+
+    // Starts the planning loop on a thread of its own. Only the first call
+    // does anything.
+    pub fn start_planning(&self) {
+        let Some(health) = self.plan_health.lock().take() else { return };
+        let deliberator = self.state_machine.lock().get_deliberator();
+        deliberation::spawn(&self.config.get().control, deliberator, self.state.clone(), health);
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        let _stop_on_panic = StopOnPanic(self.motor_controller.clone());
+        let shutdown = self.state.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                shutdown.request(Trigger::Shutdown);
+            }
+        });
+        let control = self.config.get().control;
+        let watchdog = watchdog::spawn(&control, self.state.clone(), self.motor_controller.clone());
+        let frame_notify = self.vision.read().get_frame_notify();
+        self.scheduler.start();
+        // In case the caller didn't start them after the web server.
+        self.start_subsystems();
+        self.start_planning();
+        // Started here rather than in new(), once the web server has been
+        // started (or not) and the bridge has had a head start.
+        self.selftest.start();
+
+        let mut loop_count = 0u64;
+        let mut fps = 0.0f32;
+        let mut last_fps_time = Instant::now();
+        let mut fps_frame_count= 0;
+        let mut last_loop_time = Duration::ZERO;
+
+        loop {
+            let frame_start = Instant::now();
+            // The span covers one tick's work but not the sleep, so it is never
+            // held across an await.
+            let frame_time = {
+                let _tick = debug_span!(target: "control", "tick", n = loop_count).entered();
+
+                self.reflex_tick(last_loop_time)?;
+                watchdog.feed();
+
+                fps_frame_count += 1;
+                if last_fps_time.elapsed() >= Duration::from_secs(1) {
+                    fps = fps_frame_count as f32 / last_fps_time.elapsed().as_secs_f32();
+                    fps_frame_count = 0;
+                    last_fps_time = Instant::now();
+                    METRICS.loop_fps.set(fps as f64);
+                    debug!(target: "control", "📊 System FPS: {:.1}", fps);
+                }
+
+                let frame_time = frame_start.elapsed();
+                if frame_time > REFLEX_BUDGET {
+                    METRICS.reflex_overruns.inc();
+                }
+                METRICS.loop_time.observe(frame_time);
+                METRICS.last_loop_time_ms.set(frame_time.as_secs_f64() * 1000.0);
+                frame_time
+            };
+
+            let state = self.state.get_state();
+            if state == RoverState::ShuttingDown {
+                info!(target: "control", "Shutting down after {} frames", loop_count);
+                let checkpoints = self.config.get().checkpoint;
+                if checkpoints.enabled {
+                    checkpoint::discard(&checkpoints);
+                }
+                // The watchdog may not get to it before the process exits.
+                INTERLOCK.release();
+                return Ok(());
+            }
+
+            self.wait_for_next_tick(&control, &frame_notify, frame_start).await;
+
+            last_loop_time = frame_start.elapsed();
+            loop_count += 1;
+            self.loop_health.beat_with(serde_json::json!({
+                "loop_count": loop_count,
+                "fps": fps,
+                "last_frame_ms": frame_time.as_secs_f32() * 1000.0,
+                "state": state,
+                "loop_mode": control.loop_mode,
+            }));
+
+            if loop_count % 100 == 0 {
+                info!(target: "control", "🔄 Processed {} frames", loop_count);
+            }
+        }
+    }
+
+    async fn wait_for_next_tick(&self, control: &ControlConfig, frame_notify: &Notify, tick_start: Instant) {
+        let elapsed = tick_start.elapsed();
+        match control.loop_mode {
+            LoopMode::Fixed => {
+                let interval = Duration::from_millis(control.fixed_tick_interval_ms);
+                if elapsed < interval {
+                    sleep(interval - elapsed).await;
+                }
+                METRICS.loop_wakeups.inc("fixed");
+            }
+            LoopMode::EventDriven => {
+                let min = Duration::from_millis(control.min_tick_interval_ms);
+                let max = Duration::from_millis(control.max_tick_interval_ms);
+                if elapsed < min {
+                    sleep(min - elapsed).await;
+                }
+                // A frame that landed mid-tick leaves a Notify permit behind,
+                // so it wakes the wait straight away.
+                let pending = self.vision.read().has_pending_frame();
+                let woken = pending
+                    || tokio::time::timeout(max.saturating_sub(tick_start.elapsed()), frame_notify.notified()).await.is_ok();
+                METRICS.loop_wakeups.inc(if woken { "frame" } else { "timeout" });
+            }
+        }
+    }
+}
+
+// Anything that escapes the per-subsystem isolation unwinds through run();
+// the motors are stopped on the way out rather than left at their last
+// command.
+struct StopOnPanic(Arc<RwLock<MotorController>>);
+
+impl Drop for StopOnPanic {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.write().emergency_stop();
+        }
+    }
+}
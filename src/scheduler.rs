@@ -0,0 +1,221 @@
+// src/scheduler.rs
+use anyhow::{Context, Result};
+use base64::Engine;
+use chrono::{Datelike, Local, NaiveDate, NaiveTime, Timelike, Weekday};
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::clock::CLOCK;
+use crate::config::{ConfigStore, MetricCondition, ScheduleEntry, ScheduledAction};
+use crate::events::{Severity, EVENTS};
+use crate::pathfinding::{PathPlanner, PathPoint};
+use crate::power::POWER;
+use crate::state_machine::{RoverState, StateHandle, Trigger};
+use crate::supervisor;
+use crate::telemetry::TelemetrySample;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EntryStatus {
+    pub fired: u64,
+    pub last_fired_ms: Option<u64>,
+    pub last_result: Option<String>,
+    #[serde(skip)]
+    last_date: Option<NaiveDate>,
+    #[serde(skip)]
+    last_fired_mono_ms: Option<u64>,
+    #[serde(skip)]
+    condition_was_true: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduleEntryView {
+    #[serde(flatten)]
+    pub entry: ScheduleEntry,
+    pub status: EntryStatus,
+}
+
+// Drives the rover only through StateHandle requests, so the state machine
+// still rejects anything illegal. On top of that the scheduler stays out of
+// the way while the rover is faulted, booting, shutting down or under
+// manual control.
+pub struct Scheduler {
+    config: Arc<ConfigStore>,
+    state: StateHandle,
+    path_planner: Arc<RwLock<PathPlanner>>,
+    status: Mutex<HashMap<String, EntryStatus>>,
+}
+
+impl Scheduler {
+    pub fn new(
+        config: Arc<ConfigStore>,
+        state: StateHandle,
+        path_planner: Arc<RwLock<PathPlanner>>,
+    ) -> Self {
+        Self {
+            config,
+            state,
+            path_planner,
+            status: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn start(self: &Arc<Self>) {
+        let scheduler = self.clone();
+        supervisor::supervise("scheduler", self.state.clone(), move || scheduler.clone().run());
+    }
+
+    async fn run(self: Arc<Self>) {
+        let mut ticker = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            ticker.tick().await;
+            self.check();
+        }
+    }
+
+    fn check(&self) {
+        let schedule = self.config.get().schedule;
+        let now = Local::now();
+        let today = now.date_naive();
+        let now_mono = CLOCK.now_ms();
+        let sample = TelemetrySample::from_metrics();
+
+        let mut due = Vec::new();
+        {
+            let mut status = self.status.lock();
+            status.retain(|name, _| schedule.entries.iter().any(|e| &e.name == name));
+            for entry in schedule.entries.iter().filter(|e| e.enabled) {
+                let entry_status = status.entry(entry.name.clone()).or_default();
+                let fire = if let Some(at) = &entry.at {
+                    at_is_due(at, &entry.days, now.time(), now.weekday())
+                        && entry_status.last_date != Some(today)
+                } else if let Some(every_s) = entry.every_s {
+                    let last = *entry_status.last_fired_mono_ms.get_or_insert(now_mono);
+                    now_mono.saturating_sub(last) >= every_s * 1000
+                } else if let Some(when) = &entry.when {
+                    let is_true = condition_holds(when, &sample);
+                    let rising = is_true && !entry_status.condition_was_true;
+                    entry_status.condition_was_true = is_true;
+                    rising
+                } else {
+                    false
+                };
+                if fire {
+                    entry_status.last_date = Some(today);
+                    entry_status.last_fired_mono_ms = Some(now_mono);
+                    due.push(entry.clone());
+                }
+            }
+        }
+
+        for entry in due {
+            let result = self.fire(entry.action, &schedule.snapshot_directory);
+            let message = match &result {
+                Ok(message) => message.clone(),
+                Err(e) => format!("failed: {:#}", e),
+            };
+            info!(target: "control", "Schedule '{}' ({:?}): {}", entry.name, entry.action, message);
+            if result.is_err() {
+                EVENTS.publish(Severity::Warning, "scheduler", format!("Schedule '{}' {}", entry.name, message), serde_json::Value::Null);
+            }
+
+            let mut status = self.status.lock();
+            let entry_status = status.entry(entry.name.clone()).or_default();
+            entry_status.fired += 1;
+            entry_status.last_fired_ms = Some(CLOCK.wall_ms());
+            entry_status.last_result = Some(message);
+        }
+    }
+
+    fn fire(&self, action: ScheduledAction, snapshot_directory: &str) -> Result<String> {
+        if action == ScheduledAction::Snapshot {
+            return self.snapshot(snapshot_directory);
+        }
+
+        let state = self.state.get_state();
+        if matches!(state, RoverState::Boot | RoverState::Fault | RoverState::ShuttingDown | RoverState::Manual) {
+            return Ok(format!("skipped, rover is {}", state.as_str()));
+        }
+
+        match action {
+            ScheduledAction::StartMission => self.start_autonomous(state),
+            ScheduledAction::ReturnHome => {
+                self.path_planner.write().set_goal(Some(PathPoint { x: 0.0, y: 0.0 }));
+                self.start_autonomous(state);
+            }
+            ScheduledAction::Dock => {
+                let mut planner = self.path_planner.write();
+                planner.set_docking(true);
+                if !planner.is_docking() {
+                    anyhow::bail!("planner.dock_tag_id is not set");
+                }
+                drop(planner);
+                self.start_autonomous(state);
+            }
+            ScheduledAction::Stop => {
+                if state == RoverState::Autonomous {
+                    self.state.request(Trigger::Pause);
+                }
+            }
+            ScheduledAction::Snapshot => unreachable!(),
+        }
+        Ok("requested".to_string())
+    }
+
+    fn start_autonomous(&self, state: RoverState) {
+        if state != RoverState::Autonomous {
+            POWER.request_wake("schedule");
+            self.state.request(Trigger::StartAutonomous);
+        }
+    }
+
+    fn snapshot(&self, directory: &str) -> Result<String> {
+        let image = self.state.get_world().image.clone();
+        if image.is_empty() {
+            anyhow::bail!("no camera frame yet");
+        }
+        let jpeg = base64::engine::general_purpose::STANDARD.decode(&*image.get_jpeg_base64()?)?;
+
+        let dir = PathBuf::from(directory);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let path = dir.join(format!("snapshot-{}.jpg", Local::now().format("%Y%m%d-%H%M%S")));
+        std::fs::write(&path, jpeg)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(format!("saved {}", path.display()))
+    }
+
+    pub fn get_schedule(&self) -> Vec<ScheduleEntryView> {
+        let status = self.status.lock();
+        self.config.get().schedule.entries.into_iter()
+            .map(|entry| ScheduleEntryView {
+                status: status.get(&entry.name).cloned().unwrap_or_default(),
+                entry,
+            })
+            .collect()
+    }
+}
+
+// Due for the whole minute named by `at`; the per-day guard in check()
+// keeps it from firing more than once.
+fn at_is_due(at: &str, days: &[String], now: NaiveTime, weekday: Weekday) -> bool {
+    let Ok(at) = NaiveTime::parse_from_str(at, "%H:%M") else {
+        warn!(target: "control", "Ignoring unparseable schedule time {}", at);
+        return false;
+    };
+    let day_ok = days.is_empty() || days.iter().any(|d| d.parse::<Weekday>().ok() == Some(weekday));
+    day_ok && now.hour() == at.hour() && now.minute() == at.minute()
+}
+
+fn condition_holds(condition: &MetricCondition, sample: &TelemetrySample) -> bool {
+    let Some(value) = sample.get(&condition.metric) else { return false };
+    match (condition.below, condition.above) {
+        (Some(below), _) => value < below,
+        (None, Some(above)) => value > above,
+        (None, None) => false,
+    }
+}
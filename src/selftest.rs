@@ -0,0 +1,396 @@
+// src/selftest.rs
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+use crate::clock::CLOCK;
+use crate::config::ConfigStore;
+use crate::events::{Severity, EVENTS};
+use crate::health::{ComponentHealth, ComponentState, HealthRegistry};
+use crate::interlock::INTERLOCK;
+use crate::motor_control::MotorController;
+
+// How often the checks that wait for something look again.
+const POLL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl CheckStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckOutcome {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfTestState {
+    NotRun,
+    Running,
+    Finished,
+    // selftest.enabled is off.
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub state: SelfTestState,
+    // The worst check, once finished.
+    pub status: Option<CheckStatus>,
+    pub checks: Vec<CheckOutcome>,
+    pub started_ms: Option<u64>,
+    pub finished_ms: Option<u64>,
+    // An operator chose to arm despite the failures; cleared by a re-run.
+    pub overridden: bool,
+    pub blocks_arming: bool,
+}
+
+impl SelfTestReport {
+    fn new() -> Self {
+        Self {
+            state: SelfTestState::NotRun,
+            status: None,
+            checks: Vec::new(),
+            started_ms: None,
+            finished_ms: None,
+            overridden: false,
+            blocks_arming: true,
+        }
+    }
+
+    fn failed_checks(&self) -> Vec<&'static str> {
+        self.checks.iter().filter(|c| c.status == CheckStatus::Fail).map(|c| c.name).collect()
+    }
+
+    // None when the rover may arm.
+    pub fn get_block_reason(&self) -> Option<String> {
+        if !self.blocks_arming {
+            return None;
+        }
+        Some(match self.state {
+            SelfTestState::NotRun | SelfTestState::Running => "self-test still running".to_string(),
+            _ => format!("self-test failed: {}", self.failed_checks().join(", ")),
+        })
+    }
+}
+
+type Check = Box<dyn FnOnce() -> (CheckStatus, String) + Send>;
+
+// Runs in the background while the rover holds in Boot. The state machine
+// only reads the report; the web server asks for re-runs and overrides.
+pub struct SelfTest {
+    config: Arc<ConfigStore>,
+    health: Arc<HealthRegistry>,
+    motor_controller: Arc<RwLock<MotorController>>,
+    report: RwLock<SelfTestReport>,
+}
+
+impl SelfTest {
+    pub fn new(config: Arc<ConfigStore>, health: Arc<HealthRegistry>, motor_controller: Arc<RwLock<MotorController>>) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            health,
+            motor_controller,
+            report: RwLock::new(SelfTestReport::new()),
+        })
+    }
+
+    pub fn get_report(&self) -> SelfTestReport {
+        self.report.read().clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.report.read().state, SelfTestState::Finished | SelfTestState::Skipped)
+    }
+
+    pub fn blocks_arming(&self) -> bool {
+        self.report.read().blocks_arming
+    }
+
+    pub fn get_block_reason(&self) -> Option<String> {
+        self.report.read().get_block_reason()
+    }
+
+    // Returns false if a run is already in progress.
+    pub fn start(self: &Arc<Self>) -> bool {
+        let config = self.config.get().selftest;
+        {
+            let mut report = self.report.write();
+            if report.state == SelfTestState::Running {
+                return false;
+            }
+            *report = SelfTestReport::new();
+            if !config.enabled {
+                report.state = SelfTestState::Skipped;
+                report.blocks_arming = false;
+                info!(target: "selftest", "Self-test disabled, skipping");
+                return true;
+            }
+            report.state = SelfTestState::Running;
+            report.started_ms = Some(CLOCK.wall_ms());
+        }
+        let selftest = self.clone();
+        std::thread::spawn(move || {
+            let checks = selftest.run_checks();
+            selftest.finish(checks);
+        });
+        true
+    }
+
+    // Only failures can be overridden, and only once the run has finished.
+    pub fn override_failures(&self, by: &str) -> bool {
+        let mut report = self.report.write();
+        if report.state != SelfTestState::Finished || report.status != Some(CheckStatus::Fail) || report.overridden {
+            return false;
+        }
+        report.overridden = true;
+        report.blocks_arming = false;
+        let failed = report.failed_checks().join(", ");
+        EVENTS.publish(
+            Severity::Warning,
+            "selftest",
+            format!("Self-test failures ({}) overridden by {}, arming allowed", failed, by),
+            serde_json::json!({ "failed": report.failed_checks(), "by": by }),
+        );
+        true
+    }
+
+    fn run_checks(&self) -> Vec<CheckOutcome> {
+        let config = self.config.get();
+        let check_timeout = Duration::from_secs_f32(config.selftest.check_timeout_s);
+        let frame_timeout = Duration::from_secs_f32(config.selftest.frame_timeout_s);
+        let min_frames = config.selftest.min_frames;
+
+        let health = self.health.clone();
+        let motors = self.motor_controller.clone();
+        let disk_config = config.clone();
+        let web_health = self.health.clone();
+        let checks: Vec<(&'static str, Duration, Check)> = vec![
+            ("bridge", frame_timeout, Box::new(move || check_bridge(&health, min_frames, frame_timeout))),
+            ("motors", check_timeout, Box::new(move || check_motors(&motors))),
+            ("imu", check_timeout, Box::new(check_imu)),
+            ("disk", check_timeout, Box::new(move || check_disk(&disk_config))),
+            ("web", check_timeout, Box::new(move || check_web(&web_health, check_timeout))),
+            ("interlock", check_timeout, Box::new(check_interlock)),
+        ];
+
+        // All at once, so boot waits for the slowest check rather than the
+        // sum of them. A check that hangs is abandoned on its thread.
+        let started = Instant::now();
+        let pending: Vec<_> = checks.into_iter()
+            .map(|(name, timeout, check)| {
+                let (tx, rx) = crossbeam_channel::bounded(1);
+                std::thread::spawn(move || {
+                    let (status, detail) = check();
+                    let _ = tx.send((status, detail, started.elapsed()));
+                });
+                (name, timeout, rx)
+            })
+            .collect();
+
+        pending.into_iter()
+            .map(|(name, timeout, rx)| {
+                // A little slack, so a check that gives up at its own
+                // deadline still gets to say why.
+                let wait = (timeout + POLL * 5).saturating_sub(started.elapsed());
+                let (status, detail, took) = match rx.recv_timeout(wait) {
+                    Ok(result) => result,
+                    Err(crossbeam_channel::RecvTimeoutError::Timeout) => {
+                        (CheckStatus::Fail, format!("no answer within {:.1} s", timeout.as_secs_f32()), timeout)
+                    }
+                    Err(crossbeam_channel::RecvTimeoutError::Disconnected) => {
+                        (CheckStatus::Fail, "check panicked".to_string(), started.elapsed())
+                    }
+                };
+                CheckOutcome { name, status, detail, duration_ms: took.as_millis() as u64 }
+            })
+            .collect()
+    }
+
+    fn finish(&self, checks: Vec<CheckOutcome>) {
+        for check in &checks {
+            match check.status {
+                CheckStatus::Pass => info!(target: "selftest", "[{}] {:<7} {}", check.status.as_str(), check.name, check.detail),
+                CheckStatus::Warn => warn!(target: "selftest", "[{}] {:<7} {}", check.status.as_str(), check.name, check.detail),
+                CheckStatus::Fail => error!(target: "selftest", "[{}] {:<7} {}", check.status.as_str(), check.name, check.detail),
+            }
+        }
+        let status = checks.iter().map(|c| c.status).max().unwrap_or(CheckStatus::Pass);
+
+        let mut report = self.report.write();
+        report.checks = checks;
+        report.state = SelfTestState::Finished;
+        report.status = Some(status);
+        report.finished_ms = Some(CLOCK.wall_ms());
+        report.blocks_arming = status == CheckStatus::Fail;
+
+        let (severity, message) = match status {
+            CheckStatus::Pass => (Severity::Info, "Self-test passed".to_string()),
+            CheckStatus::Warn => {
+                let warned: Vec<_> = report.checks.iter().filter(|c| c.status == CheckStatus::Warn).map(|c| c.name).collect();
+                (Severity::Warning, format!("Self-test passed with warnings ({})", warned.join(", ")))
+            }
+            CheckStatus::Fail => {
+                (Severity::Critical, format!("Self-test failed ({}), arming blocked until it is overridden", report.failed_checks().join(", ")))
+            }
+        };
+        EVENTS.publish(severity, "selftest", message, serde_json::json!({ "checks": report.checks }));
+    }
+}
+
+fn component(health: &HealthRegistry, name: &str) -> Option<ComponentHealth> {
+    health.report().components.into_iter().find(|c| c.name == name)
+}
+
+// Frames that reach the vision system parsed, so they have the detection
+// schema; lines that didn't parse are counted as errors on the component.
+fn check_bridge(health: &HealthRegistry, min_frames: u64, timeout: Duration) -> (CheckStatus, String) {
+    let deadline = Instant::now() + timeout;
+    // Only frames since the check started count, so a re-run after the
+    // bridge was paused waits for fresh ones.
+    let baseline = component(health, "vision_bridge").map(|b| (b.updates, b.errors)).unwrap_or((0, 0));
+    loop {
+        let bridge = component(health, "vision_bridge");
+        if let Some(bridge) = &bridge {
+            let frames = bridge.updates.saturating_sub(baseline.0);
+            let errors = bridge.errors.saturating_sub(baseline.1);
+            if frames >= min_frames {
+                return match &bridge.last_error {
+                    Some(error) if errors > 0 => (
+                        CheckStatus::Warn,
+                        format!("{} frames, but {} lines didn't parse ({})", frames, errors, error),
+                    ),
+                    _ => (CheckStatus::Pass, format!("{} frames received", frames)),
+                };
+            }
+            if bridge.state == ComponentState::Failed {
+                return (CheckStatus::Fail, bridge.last_error.clone().unwrap_or_else(|| "bridge failed".to_string()));
+            }
+        }
+        if Instant::now() >= deadline {
+            let detail = match bridge {
+                Some(bridge) if bridge.updates == baseline.0 && bridge.errors > baseline.1 => format!(
+                    "no valid frames, {} lines didn't parse ({})",
+                    bridge.errors - baseline.1,
+                    bridge.last_error.unwrap_or_default(),
+                ),
+                Some(bridge) => format!("{} of {} frames in {:.0} s (is the camera seated?)", bridge.updates.saturating_sub(baseline.0), min_frames, timeout.as_secs_f32()),
+                None => "no frame source".to_string(),
+            };
+            return (CheckStatus::Fail, detail);
+        }
+        std::thread::sleep(POLL);
+    }
+}
+
+// The controller has no PWM output or current sense of its own yet, so a
+// stop is as much as can be sent, and a driver without power goes unseen.
+fn check_motors(motor_controller: &RwLock<MotorController>) -> (CheckStatus, String) {
+    motor_controller.write().stop();
+    (CheckStatus::Warn, "controller accepts commands; no current sensing, so driver power is not verified".to_string())
+}
+
+fn check_imu() -> (CheckStatus, String) {
+    (CheckStatus::Warn, "no IMU driver; heading comes from odometry alone".to_string())
+}
+
+fn check_interlock() -> (CheckStatus, String) {
+    match INTERLOCK.check() {
+        None => (CheckStatus::Pass, "no safe output configured".to_string()),
+        Some(Ok(detail)) => (CheckStatus::Pass, detail),
+        Some(Err(e)) => (CheckStatus::Fail, e),
+    }
+}
+
+fn check_disk(config: &crate::config::RoverConfig) -> (CheckStatus, String) {
+    let mut targets = Vec::new();
+    if config.sessions.enabled {
+        targets.push(("sessions", PathBuf::from(&config.sessions.directory)));
+    }
+    if config.blackbox.enabled {
+        let path = PathBuf::from(&config.blackbox.path);
+        targets.push(("blackbox", path.parent().map(Path::to_path_buf).unwrap_or_default()));
+    }
+    if targets.is_empty() {
+        return (CheckStatus::Pass, "nothing is recorded".to_string());
+    }
+
+    let min_mb = config.selftest.min_free_mb;
+    let mut status = CheckStatus::Pass;
+    let mut details = Vec::new();
+    for (what, dir) in targets {
+        match free_mb(&dir) {
+            Ok(free) => {
+                let this = if free < min_mb {
+                    CheckStatus::Fail
+                } else if free < min_mb * 4 {
+                    CheckStatus::Warn
+                } else {
+                    CheckStatus::Pass
+                };
+                status = status.max(this);
+                details.push(format!("{} {} MB free", what, free));
+            }
+            Err(e) => {
+                status = status.max(CheckStatus::Warn);
+                details.push(format!("{} unknown ({})", what, e));
+            }
+        }
+    }
+    (status, format!("{} (need {} MB)", details.join(", "), min_mb))
+}
+
+// Through df, since the directory may not exist yet and its nearest
+// existing parent is what counts.
+fn free_mb(dir: &Path) -> Result<u64, String> {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let existing = dir.ancestors().find(|d| !d.as_os_str().is_empty() && d.exists()).unwrap_or(Path::new("."));
+    let output = Command::new("df").args(["-Pk"]).arg(existing).output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().nth(1)
+        .and_then(|line| line.split_whitespace().nth(3))
+        .and_then(|kb| kb.parse::<u64>().ok())
+        .map(|kb| kb / 1024)
+        .ok_or_else(|| "unexpected df output".to_string())
+}
+
+fn check_web(health: &HealthRegistry, timeout: Duration) -> (CheckStatus, String) {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let Some(web) = component(health, "web_server") else {
+            return (CheckStatus::Warn, "web server not started, so there is no dashboard".to_string());
+        };
+        match web.state {
+            ComponentState::Failed => {
+                return (CheckStatus::Fail, web.last_error.unwrap_or_else(|| "web server failed".to_string()));
+            }
+            ComponentState::Starting if Instant::now() < deadline => std::thread::sleep(POLL),
+            ComponentState::Starting => return (CheckStatus::Fail, format!("not bound after {:.0} s", timeout.as_secs_f32())),
+            _ => return (CheckStatus::Pass, "bound".to_string()),
+        }
+    }
+}
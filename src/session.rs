@@ -0,0 +1,231 @@
+// src/session.rs
+use anyhow::{Context, Result};
+use crossbeam_channel::{Receiver, Sender};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tracing::{error, info, warn};
+
+use crate::annotations;
+use crate::config::SessionConfig;
+use crate::vision::FrameImage;
+
+const QUOTA_CHECK_EVERY: u64 = 500;
+
+#[derive(Serialize, Deserialize)]
+pub struct SessionEntry {
+    pub t_ms: u64,
+    pub payload: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    pub name: String,
+    pub size_bytes: u64,
+    pub modified: u64,
+    pub recording: bool,
+}
+
+pub struct SessionRecorder {
+    name: String,
+    tx: Sender<SessionEntry>,
+    started: Instant,
+    include_images: bool,
+    image_interval: Duration,
+    last_image: Mutex<Option<Instant>>,
+}
+
+impl SessionRecorder {
+    pub fn start(config: &SessionConfig) -> Result<Self> {
+        let dir = PathBuf::from(&config.directory);
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        let name = format!("session-{}.jsonl", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+        let path = dir.join(&name);
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let quota_bytes = config.quota_mb * 1024 * 1024;
+        {
+            let dir = dir.clone();
+            let name = name.clone();
+            std::thread::spawn(move || write_loop(file, rx, &dir, &name, quota_bytes));
+        }
+
+        info!(target: "web", "Recording session to {}", path.display());
+
+        Ok(Self {
+            name,
+            tx,
+            started: Instant::now(),
+            include_images: config.include_images,
+            image_interval: Duration::from_secs_f32(config.image_interval_s),
+            last_image: Mutex::new(None),
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    // How far into the recording it is, as entries are stamped.
+    pub fn get_elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    // Frames lose their JPEG unless images are enabled and the image
+    // interval has passed; everything else is stored as sent.
+    pub fn record(&self, payload: &serde_json::Value) {
+        let mut payload = payload.clone();
+        if let Some(obj) = payload.as_object_mut() {
+            if obj.contains_key("image") && !self.take_image_slot() {
+                obj.remove("image");
+            }
+        }
+
+        let _ = self.tx.send(SessionEntry {
+            t_ms: self.started.elapsed().as_millis() as u64,
+            payload,
+        });
+    }
+
+    // A live frame whose picture isn't in the payload yet: it is only
+    // encoded when it is due to be kept.
+    pub fn record_frame(&self, payload: &serde_json::Value, image: &FrameImage) {
+        let mut payload = payload.clone();
+        if !image.is_empty() && self.take_image_slot() {
+            match image.get_jpeg_base64() {
+                Ok(jpeg_base64) => payload["image"] = serde_json::Value::String(jpeg_base64.to_string()),
+                Err(e) => warn!(target: "web", "Recording frame without its picture: {:#}", e),
+            }
+        }
+        let _ = self.tx.send(SessionEntry {
+            t_ms: self.started.elapsed().as_millis() as u64,
+            payload,
+        });
+    }
+
+    fn take_image_slot(&self) -> bool {
+        if !self.include_images {
+            return false;
+        }
+        let mut last = self.last_image.lock();
+        match *last {
+            Some(at) if at.elapsed() < self.image_interval => false,
+            _ => {
+                *last = Some(Instant::now());
+                true
+            }
+        }
+    }
+}
+
+fn write_loop(file: File, rx: Receiver<SessionEntry>, dir: &Path, current: &str, quota_bytes: u64) {
+    let mut writer = BufWriter::new(file);
+    let mut written = 0u64;
+
+    enforce_quota(dir, current, quota_bytes);
+
+    while let Ok(entry) = rx.recv() {
+        if let Ok(line) = serde_json::to_string(&entry) {
+            if writeln!(writer, "{}", line).is_err() {
+                error!(target: "web", "Session write failed, stopping recording");
+                return;
+            }
+        }
+        written += 1;
+
+        // Flush whenever we've caught up so the file is readable mid-session.
+        if rx.is_empty() {
+            let _ = writer.flush();
+        }
+        if written % QUOTA_CHECK_EVERY == 0 {
+            enforce_quota(dir, current, quota_bytes);
+        }
+    }
+    let _ = writer.flush();
+}
+
+// Drops the oldest sessions, with their annotations, until the directory
+// fits the quota. The session being recorded is never removed.
+fn enforce_quota(dir: &Path, current: &str, quota_bytes: u64) {
+    let Ok(mut sessions) = list_sessions(dir, Some(current)) else { return };
+    let mut total: u64 = sessions.iter().map(|s| s.size_bytes).sum();
+
+    sessions.sort_by(|a, b| a.name.cmp(&b.name));
+    for session in sessions.iter().filter(|s| !s.recording) {
+        if total <= quota_bytes {
+            break;
+        }
+        if fs::remove_file(dir.join(&session.name)).is_ok() {
+            annotations::remove(&dir.join(&session.name));
+            info!(target: "web", "Session quota exceeded, removed {}", session.name);
+            total -= session.size_bytes;
+        }
+    }
+}
+
+pub fn list_sessions(dir: &Path, recording: Option<&str>) -> Result<Vec<SessionInfo>> {
+    let mut sessions = Vec::new();
+    if !dir.exists() {
+        return Ok(sessions);
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !is_session_name(&name) {
+            continue;
+        }
+        let meta = entry.metadata()?;
+        sessions.push(SessionInfo {
+            recording: recording == Some(name.as_str()),
+            size_bytes: meta.len(),
+            modified: meta.modified().ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            name,
+        });
+    }
+
+    sessions.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(sessions)
+}
+
+// Session names come straight from the URL, so only plain file names that
+// look like ours are accepted.
+pub fn is_session_name(name: &str) -> bool {
+    name.starts_with("session-")
+        && name.ends_with(".jsonl")
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && !name.contains("..")
+}
+
+pub fn open_session(dir: &Path, name: &str) -> Result<impl Iterator<Item = SessionEntry>> {
+    if !is_session_name(name) {
+        anyhow::bail!("Invalid session name: {}", name);
+    }
+    let path = dir.join(name);
+    let file = File::open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+
+    Ok(BufReader::new(file)
+        .lines()
+        .map_while(|line| line.ok())
+        .filter_map(|line| serde_json::from_str(&line).ok()))
+}
+
+// For paths given on the command line rather than names from the URL.
+pub fn open_session_path(path: &Path) -> Result<impl Iterator<Item = SessionEntry>> {
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let name = path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow::anyhow!("Not a session file: {}", path.display()))?;
+    open_session(&dir, &name)
+}
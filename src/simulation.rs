@@ -0,0 +1,112 @@
+// src/simulation.rs
+use anyhow::Result;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use tracing::info;
+
+use crate::annotations::RecordingRef;
+use crate::clock::CLOCK;
+use crate::health::HealthHandle;
+use crate::latency::FrameStamps;
+use crate::recording::{self, RecordedFrame};
+use crate::vision::{BridgeFrame, Detection, FrameSender, IMX500Detection};
+
+#[cfg(feature = "sim")]
+mod chaos;
+#[cfg(feature = "sim")]
+mod scenario;
+
+#[cfg(feature = "sim")]
+pub use chaos::{Chaos, CHAOS};
+#[cfg(feature = "sim")]
+pub use scenario::{spawn_scenario, Scenario, ScenarioStep};
+
+#[derive(Clone)]
+pub enum FrameSource {
+    Bridge,
+    #[cfg(feature = "sim")]
+    Scenario(Scenario),
+    // A session recorded by the web server, replayed at `speed`x.
+    Recording { path: PathBuf, speed: f32 },
+    // Nothing is spawned; frames are pushed through
+    // VisionSystem::get_frame_sender(), e.g. by a test harness.
+    Manual,
+}
+
+// Only frame payloads are used; their detections are fed back through the
+// vision pipeline so planner and motors see them as if they were live.
+pub fn spawn_recording(path: PathBuf, speed: f32, frames: FrameSender, health: HealthHandle) -> Result<()> {
+    let recorded = recording::open_frames(&path)?;
+
+    thread::spawn(move || {
+        let mut last_t = None;
+        let mut frame_id = 0u32;
+        for recorded in recorded {
+            if let Some(last) = last_t {
+                let gap = recorded.t_ms.saturating_sub(last) as f32 / speed;
+                thread::sleep(Duration::from_millis(gap as u64));
+            }
+            last_t = Some(recorded.t_ms);
+
+            frame_id = frame_id.wrapping_add(1);
+            health.beat_with(serde_json::json!({ "frame_id": frame_id, "source": "recording" }));
+            let mut frame = synthetic_frame(frame_id, recorded.detections.iter().map(replayed_detection).collect());
+            frame.jpeg_base64 = recorded.image.unwrap_or_default();
+            frame.recorded = Some(Box::new(RecordingRef { path: path.clone(), t_ms: recorded.t_ms, frame_id: recorded.frame_id, tick: recorded.tick }));
+            if let Some(profile) = recorded.profile {
+                frame.recorded_profile = Some(profile);
+                frame.brightness = recorded.brightness;
+            }
+            if !frames.send(frame) {
+                return;
+            }
+        }
+        health.fail("Recording finished");
+        info!(target: "vision", "Recording finished after {} frames", frame_id);
+    });
+    Ok(())
+}
+
+fn replayed_detection(d: &Detection) -> IMX500Detection {
+    IMX500Detection {
+        class: d.class_name.clone(),
+        class_id: None,
+        conf: RecordedFrame::raw_confidence(d),
+        x: d.bbox.0,
+        y: d.bbox.1,
+        w: d.bbox.2,
+        h: d.bbox.3,
+        mask: d.mask.clone(),
+    }
+}
+
+pub fn synthetic_frame(frame_id: u32, detections: Vec<IMX500Detection>) -> BridgeFrame {
+    BridgeFrame {
+        frame_id,
+        jpeg_base64: String::new(),
+        timestamp: CLOCK.wall_ms() as f64 / 1000.0,
+        imx500_basic: detections,
+        fiducials: Vec::new(),
+        capture_monotonic: None,
+        emit_monotonic: None,
+        brightness: None,
+        slot: None,
+        shared_frame: None,
+        recorded_profile: None,
+        recorded: None,
+        capture_ms: CLOCK.now_ms(),
+        stamps: FrameStamps::local(CLOCK.now_ms_f64()),
+    }
+}
+
+// Where vision, the planner and the frame broadcaster panic when CHAOS has
+// them armed. Nothing without the simulator.
+pub fn chaos_point(subsystem: &'static str) {
+    #[cfg(feature = "sim")]
+    if CHAOS.take(subsystem) {
+        panic!("{} panic injected", subsystem);
+    }
+    #[cfg(not(feature = "sim"))]
+    let _ = subsystem;
+}
@@ -0,0 +1,38 @@
+// src/simulation/chaos.rs
+//
+// Panics on demand, for testing that one subsystem panicking stops the
+// motors and faults the rover without taking the rest down. A subsystem
+// armed here panics at its next chaos point, once.
+use parking_lot::Mutex;
+
+pub static CHAOS: Chaos = Chaos::new();
+
+pub struct Chaos {
+    armed: Mutex<Vec<&'static str>>,
+}
+
+impl Chaos {
+    const fn new() -> Self {
+        Self { armed: Mutex::new(Vec::new()) }
+    }
+
+    pub fn inject(&self, subsystem: &'static str) {
+        self.armed.lock().push(subsystem);
+    }
+
+    // Whether `subsystem` was armed, disarming it.
+    pub fn take(&self, subsystem: &str) -> bool {
+        let mut armed = self.armed.lock();
+        match armed.iter().position(|s| *s == subsystem) {
+            Some(i) => {
+                armed.swap_remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&self) {
+        self.armed.lock().clear();
+    }
+}
@@ -0,0 +1,86 @@
+// src/simulation/scenario.rs
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use tracing::info;
+
+use super::synthetic_frame;
+use crate::health::HealthHandle;
+use crate::vision::{FrameSender, IMX500Detection, TagCorners};
+
+// A scripted stand-in for the camera bridge. Each step emits the same
+// detections for `repeat` frames, e.g.
+//
+//   {"frame_interval_ms": 33, "loop": true, "steps": [
+//     {"repeat": 60, "detections": []},
+//     {"repeat": 30, "detections": [{"class": "person", "conf": 0.9, "x": 280, "y": 100, "w": 80, "h": 300}]},
+//     {"repeat": 30, "fiducials": [{"id": 0, "corners": [[300, 220], [340, 220], [340, 260], [300, 260]]}]}
+//   ]}
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scenario {
+    #[serde(default = "default_frame_interval_ms")]
+    pub frame_interval_ms: u64,
+    #[serde(default, rename = "loop")]
+    pub repeat_forever: bool,
+    pub steps: Vec<ScenarioStep>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScenarioStep {
+    #[serde(default = "default_repeat")]
+    pub repeat: u32,
+    #[serde(default)]
+    pub detections: Vec<IMX500Detection>,
+    #[serde(default)]
+    pub fiducials: Vec<TagCorners>,
+    // Mean frame brightness (0-255), for trying out vision profile
+    // switching.
+    #[serde(default)]
+    pub brightness: Option<f32>,
+}
+
+fn default_frame_interval_ms() -> u64 {
+    33
+}
+
+fn default_repeat() -> u32 {
+    1
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scenario {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse scenario {}", path.display()))
+    }
+}
+
+pub fn spawn_scenario(scenario: Scenario, frames: FrameSender, health: HealthHandle) {
+    thread::spawn(move || {
+        let interval = Duration::from_millis(scenario.frame_interval_ms);
+        let mut frame_id = 0u32;
+        loop {
+            for step in &scenario.steps {
+                for _ in 0..step.repeat {
+                    frame_id = frame_id.wrapping_add(1);
+                    health.beat_with(serde_json::json!({ "frame_id": frame_id, "source": "scenario" }));
+                    let mut frame = synthetic_frame(frame_id, step.detections.clone());
+                    frame.fiducials = step.fiducials.clone();
+                    frame.brightness = step.brightness;
+                    if !frames.send(frame) {
+                        return;
+                    }
+                    thread::sleep(interval);
+                }
+            }
+            if !scenario.repeat_forever {
+                break;
+            }
+        }
+        health.fail("Scenario finished");
+        info!(target: "vision", "Scenario finished after {} frames", frame_id);
+    });
+}
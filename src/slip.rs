@@ -0,0 +1,179 @@
+// src/slip.rs
+use serde::Serialize;
+use std::collections::VecDeque;
+use tracing::{info, warn};
+
+use crate::config::PlannerConfig;
+use crate::events::{Severity, EVENTS};
+use crate::metrics::METRICS;
+use crate::vision::CameraModel;
+
+// Wheel travel is kept this long, so it can be matched against the
+// interval between any two recent frames.
+const WHEEL_HISTORY_MS: u64 = 5000;
+
+// How far the floor moved under the camera between two frames.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct VisualTravel {
+    // Along the heading, in metres; negative is backwards.
+    pub distance_m: f32,
+    // 0-1, from the motion estimate.
+    pub confidence: f32,
+    // Rover-clock ms the two frames were captured at.
+    pub from_ms: u64,
+    pub to_ms: u64,
+}
+
+// Metres driven for a vertical shift of the picture of `shift_px`
+// captured-frame pixels, seen on row `y`. Driving forward moves the floor
+// down the frame, so a floor point on row y before is on y + shift_px
+// after, and nearer by the difference of the two floor distances.
+pub fn floor_travel_m(camera: &CameraModel, y: f32, shift_px: f32) -> Option<f32> {
+    Some(camera.floor_distance_m(y)? - camera.floor_distance_m(y + shift_px)?)
+}
+
+// One interval the wheels and the camera were compared over.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SlipInterval {
+    pub from_ms: u64,
+    pub to_ms: u64,
+    // What the wheels turned for and what the camera saw, in metres.
+    pub wheel_m: f32,
+    pub visual_m: f32,
+    pub confidence: f32,
+    // 0 while the two agree to within planner.slip_disagreement, up to 1
+    // for wheels spinning in place.
+    pub slip: f32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TractionStatus {
+    // Averaged over the last planner.slip_window intervals. Odometry
+    // counts 1 - slip of what the wheels turn for.
+    pub slip: f32,
+    // Over planner.slip_warn_ratio for planner.slip_sustain_s; the
+    // planner slows down meanwhile.
+    pub sustained: bool,
+    pub intervals: u64,
+    pub last_interval: Option<SlipInterval>,
+}
+
+// Wheel odometry comes from the commanded drive speed, one for the whole
+// rover, so slip is estimated for the drive rather than wheel by wheel.
+#[derive(Debug, Default)]
+pub struct SlipEstimator {
+    // (rover-clock ms, wheel travel so far), oldest first.
+    wheel: VecDeque<(u64, f32)>,
+    wheel_total_m: f32,
+    recent: VecDeque<f32>,
+    slipping_since_ms: Option<u64>,
+    status: TractionStatus,
+}
+
+impl SlipEstimator {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+        METRICS.wheel_slip.set(0.0);
+    }
+
+    pub fn get_slip(&self) -> f32 {
+        self.status.slip
+    }
+
+    pub fn is_sustained(&self) -> bool {
+        self.status.sustained
+    }
+
+    pub fn get_status(&self) -> TractionStatus {
+        self.status.clone()
+    }
+
+    // Every tick, with the distance the wheels turned for since the last.
+    pub fn record_wheel(&mut self, distance_m: f32, now_ms: u64) {
+        self.wheel_total_m += distance_m;
+        match self.wheel.back_mut() {
+            Some(last) if last.0 >= now_ms => last.1 = self.wheel_total_m,
+            _ => self.wheel.push_back((now_ms, self.wheel_total_m)),
+        }
+        while self.wheel.front().is_some_and(|&(at, _)| now_ms.saturating_sub(at) > WHEEL_HISTORY_MS) {
+            self.wheel.pop_front();
+        }
+    }
+
+    // Wheel travel so far at `at_ms`, between the ticks either side of it.
+    // None from before the history starts; after its end it is the last.
+    fn wheel_at(&self, at_ms: u64) -> Option<f32> {
+        let &(first_ms, first_m) = self.wheel.front()?;
+        if at_ms < first_ms {
+            return None;
+        }
+        if at_ms == first_ms {
+            return Some(first_m);
+        }
+        let Some(i) = self.wheel.iter().position(|&(t, _)| t >= at_ms) else {
+            return self.wheel.back().map(|&(_, m)| m);
+        };
+        let ((t0, m0), (t1, m1)) = (self.wheel[i - 1], self.wheel[i]);
+        Some(m0 + (m1 - m0) * (at_ms - t0) as f32 / (t1 - t0) as f32)
+    }
+
+    // Compares the wheels with the camera over `travel`'s interval. An
+    // interval that can't be judged - the camera unsure, the wheels
+    // hardly turning, or older than the wheel history - changes nothing
+    // and gives None.
+    pub fn update(&mut self, config: &PlannerConfig, travel: VisualTravel) -> Option<SlipInterval> {
+        if travel.confidence < config.slip_min_confidence || travel.to_ms <= travel.from_ms {
+            return None;
+        }
+        let wheel_m = self.wheel_at(travel.to_ms)? - self.wheel_at(travel.from_ms)?;
+        if wheel_m.abs() < config.slip_min_travel_m {
+            return None;
+        }
+        // The floor going the other way from the wheels is no more travel
+        // than none, and going further than they turned is no slip.
+        let ratio = (1.0 - travel.distance_m / wheel_m).clamp(0.0, 1.0);
+        let interval = SlipInterval {
+            from_ms: travel.from_ms,
+            to_ms: travel.to_ms,
+            wheel_m,
+            visual_m: travel.distance_m,
+            confidence: travel.confidence,
+            slip: if ratio <= config.slip_disagreement { 0.0 } else { ratio },
+        };
+
+        self.recent.push_back(interval.slip);
+        while self.recent.len() > config.slip_window {
+            self.recent.pop_front();
+        }
+        self.status.slip = self.recent.iter().sum::<f32>() / self.recent.len() as f32;
+        self.status.intervals += 1;
+        self.status.last_interval = Some(interval);
+        METRICS.wheel_slip.set(self.status.slip as f64);
+        self.update_sustained(config, travel.to_ms);
+        Some(interval)
+    }
+
+    fn update_sustained(&mut self, config: &PlannerConfig, now_ms: u64) {
+        if self.status.slip < config.slip_warn_ratio {
+            self.slipping_since_ms = None;
+            if self.status.sustained {
+                self.status.sustained = false;
+                info!(target: "planner", "Traction regained");
+                EVENTS.publish(Severity::Info, "traction", "Traction regained", serde_json::json!({
+                    "slip": self.status.slip,
+                }));
+            }
+            return;
+        }
+        let since = *self.slipping_since_ms.get_or_insert(now_ms);
+        let sustain_ms = (config.slip_sustain_s * 1000.0) as u64;
+        if !self.status.sustained && now_ms.saturating_sub(since) >= sustain_ms {
+            self.status.sustained = true;
+            warn!(target: "planner", "Wheels slipping ({:.0}%), slowing down", self.status.slip * 100.0);
+            EVENTS.publish(Severity::Warning, "traction", format!("Wheels slipping ({:.0}%), slowing down", self.status.slip * 100.0), serde_json::json!({
+                "slip": self.status.slip,
+                "interval": self.status.last_interval,
+            }));
+        }
+    }
+}
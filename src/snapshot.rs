@@ -0,0 +1,115 @@
+// src/snapshot.rs
+//
+// Encoding frames for GET /api/frame.jpg and /api/stream.mjpeg, as
+// baseline or progressive JPEG or as WebP. Encodes, drawing included, run
+// on tokio's blocking pool, and only snapshot.max_concurrent of them at
+// once, so a burst of downloads waits its turn rather than taking the CPU
+// the control loop needs.
+use anyhow::Result;
+use image::RgbImage;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::SnapshotFormat;
+use crate::metrics::METRICS;
+use crate::vision::pixels;
+
+pub fn content_type(format: SnapshotFormat) -> &'static str {
+    match format {
+        SnapshotFormat::Baseline | SnapshotFormat::Progressive => "image/jpeg",
+        SnapshotFormat::Webp => "image/webp",
+    }
+}
+
+pub fn encode(rgb: &RgbImage, format: SnapshotFormat, quality: u8) -> Result<Vec<u8>> {
+    match format {
+        SnapshotFormat::Baseline => pixels::encode_jpeg(rgb, quality),
+        SnapshotFormat::Progressive => encode_progressive(rgb, quality),
+        SnapshotFormat::Webp => encode_webp(rgb, quality),
+    }
+}
+
+// Full-resolution chroma like the baseline encoder, and Huffman tables
+// built for each scan, which is what makes it smaller than baseline.
+fn encode_progressive(rgb: &RgbImage, quality: u8) -> Result<Vec<u8>> {
+    let (width, height) = rgb.dimensions();
+    anyhow::ensure!(width <= 65535 && height <= 65535, "{}x{} is too large for JPEG", width, height);
+    let mut out = Vec::with_capacity((width * height) as usize / 4);
+    let mut encoder = jpeg_encoder::Encoder::new(&mut out, quality.clamp(1, 100));
+    encoder.set_progressive(true);
+    encoder.set_optimized_huffman_tables(true);
+    encoder.set_sampling_factor(jpeg_encoder::SamplingFactor::R_4_4_4);
+    encoder.encode(rgb.as_raw(), width as u16, height as u16, jpeg_encoder::ColorType::Rgb)?;
+    Ok(out)
+}
+
+// Lossless VP8L. Below quality 100 each channel loses its low bits first,
+// which is what makes it small enough to send.
+#[cfg(feature = "webp")]
+fn encode_webp(rgb: &RgbImage, quality: u8) -> Result<Vec<u8>> {
+    let drop = match quality {
+        100.. => 0,
+        90..=99 => 1,
+        70..=89 => 2,
+        40..=69 => 3,
+        _ => 4,
+    };
+    let mask = 0xffu8 << drop;
+    let half = if drop == 0 { 0 } else { 1u8 << (drop - 1) };
+    let pixels: Vec<u8> = rgb.as_raw().iter().map(|&c| c.saturating_add(half).min(mask) & mask).collect();
+    let mut out = Vec::new();
+    image_webp::WebPEncoder::new(&mut out).encode(&pixels, rgb.width(), rgb.height(), image_webp::ColorType::Rgb8)?;
+    Ok(out)
+}
+
+#[cfg(not(feature = "webp"))]
+fn encode_webp(_rgb: &RgbImage, _quality: u8) -> Result<Vec<u8>> {
+    anyhow::bail!("this build has no WebP encoder (the webp feature)")
+}
+
+#[derive(Clone)]
+pub struct EncoderPool {
+    permits: Arc<Semaphore>,
+}
+
+impl EncoderPool {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self { permits: Arc::new(Semaphore::new(max_concurrent.max(1))) }
+    }
+
+    // Draws the picture with `draw` and encodes it once an encoder is free.
+    pub async fn encode<F>(&self, draw: F, format: SnapshotFormat, quality: u8) -> Result<Vec<u8>>
+    where
+        F: FnOnce() -> Result<RgbImage> + Send + 'static,
+    {
+        let permit = self.permits.clone().acquire_owned().await?;
+        run(permit, draw, format, quality).await
+    }
+
+    // The same, but None straight away when every encoder is busy, for a
+    // stream that would rather skip a frame than fall behind.
+    pub async fn try_encode<F>(&self, draw: F, format: SnapshotFormat, quality: u8) -> Option<Result<Vec<u8>>>
+    where
+        F: FnOnce() -> Result<RgbImage> + Send + 'static,
+    {
+        let permit = self.permits.clone().try_acquire_owned().ok()?;
+        Some(run(permit, draw, format, quality).await)
+    }
+}
+
+// The permit goes with the blocking task, so an encode a client gave up on
+// still counts until it finishes.
+async fn run<F>(permit: OwnedSemaphorePermit, draw: F, format: SnapshotFormat, quality: u8) -> Result<Vec<u8>>
+where
+    F: FnOnce() -> Result<RgbImage> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let started = Instant::now();
+        let encoded = draw().and_then(|rgb| encode(&rgb, format, quality));
+        METRICS.snapshot_encode.observe(format.as_str(), started.elapsed());
+        encoded
+    })
+    .await?
+}
@@ -0,0 +1,128 @@
+// src/startup.rs
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::clock::CLOCK;
+use crate::events::{Severity, EVENTS};
+
+pub const VISION: &str = "vision";
+pub const MOTORS: &str = "motors";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubsystemState {
+    Initializing,
+    Ready,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubsystemStatus {
+    pub name: &'static str,
+    pub state: SubsystemState,
+    pub error: Option<String>,
+    pub attempts: u32,
+    // Wall-clock ms of the last change.
+    pub since_ms: u64,
+}
+
+// The subsystems started behind the web server, so a camera that won't
+// open leaves the dashboard up to say so. Starting one, first or on a
+// retry, is queued here and done by whoever takes it with next().
+pub struct Startup {
+    subsystems: RwLock<Vec<SubsystemStatus>>,
+    queue: Sender<&'static str>,
+    pending: Receiver<&'static str>,
+}
+
+impl Startup {
+    // All of `names` initializing and queued.
+    pub fn new(names: &[&'static str]) -> Arc<Self> {
+        let (queue, pending) = unbounded();
+        let subsystems = names.iter()
+            .map(|&name| {
+                let _ = queue.send(name);
+                SubsystemStatus { name, state: SubsystemState::Initializing, error: None, attempts: 0, since_ms: CLOCK.wall_ms() }
+            })
+            .collect();
+        Arc::new(Self { subsystems: RwLock::new(subsystems), queue, pending })
+    }
+
+    // Blocks until a subsystem is due to be started.
+    pub fn next(&self) -> Option<&'static str> {
+        let name = self.pending.recv().ok()?;
+        let mut subsystems = self.subsystems.write();
+        let status = subsystems.iter_mut().find(|s| s.name == name)?;
+        status.attempts += 1;
+        info!(target: "startup", "Starting {} (attempt {})", name, status.attempts);
+        Some(name)
+    }
+
+    pub fn finish(&self, name: &'static str, result: &anyhow::Result<()>) {
+        let mut subsystems = self.subsystems.write();
+        let Some(status) = subsystems.iter_mut().find(|s| s.name == name) else { return };
+        status.since_ms = CLOCK.wall_ms();
+        match result {
+            Ok(()) => {
+                status.state = SubsystemState::Ready;
+                status.error = None;
+                info!(target: "startup", "{} ready", name);
+                EVENTS.publish(Severity::Info, "startup", format!("{} ready", name), serde_json::json!({
+                    "subsystem": name,
+                    "attempts": status.attempts,
+                }));
+            }
+            Err(e) => {
+                let message = format!("{:#}", e);
+                status.state = SubsystemState::Failed;
+                status.error = Some(message.clone());
+                error!(target: "startup", "{} failed to start: {}", name, message);
+                EVENTS.publish(Severity::Critical, "startup", format!("{} failed to start: {}", name, message), serde_json::json!({
+                    "subsystem": name,
+                    "attempts": status.attempts,
+                    "error": message,
+                }));
+            }
+        }
+    }
+
+    // Queues a failed subsystem to be started again.
+    pub fn retry(&self, name: &str) -> Result<(), String> {
+        let mut subsystems = self.subsystems.write();
+        let status = subsystems.iter_mut().find(|s| s.name == name).ok_or_else(|| format!("no subsystem named {}", name))?;
+        if status.state != SubsystemState::Failed {
+            return Err(format!("{} has not failed", name));
+        }
+        status.state = SubsystemState::Initializing;
+        status.error = None;
+        status.since_ms = CLOCK.wall_ms();
+        let _ = self.queue.send(status.name);
+        EVENTS.publish(Severity::Info, "startup", format!("Retrying {}", name), serde_json::json!({ "subsystem": name }));
+        Ok(())
+    }
+
+    pub fn get_statuses(&self) -> Vec<SubsystemStatus> {
+        self.subsystems.read().clone()
+    }
+
+    // The first subsystem that failed, if any has.
+    pub fn get_failure(&self) -> Option<SubsystemStatus> {
+        self.subsystems.read().iter().find(|s| s.state == SubsystemState::Failed).cloned()
+    }
+
+    pub fn is_initializing(&self) -> bool {
+        self.subsystems.read().iter().any(|s| s.state == SubsystemState::Initializing)
+    }
+
+    // Why the rover can't be armed, while anything is still starting.
+    pub fn get_block_reason(&self) -> Option<String> {
+        let starting: Vec<&str> = self.subsystems.read().iter()
+            .filter(|s| s.state == SubsystemState::Initializing)
+            .map(|s| s.name)
+            .collect();
+        (!starting.is_empty()).then(|| format!("{} still starting", starting.join(", ")))
+    }
+}
@@ -0,0 +1,883 @@
+// src/state_machine.rs
+use anyhow::Result;
+use crossbeam_channel::{Receiver, Sender};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use crate::blackbox::{RecordKind, BLACKBOX};
+use crate::checkpoint::ResumeOffer;
+use crate::clock::CLOCK;
+use crate::config::RoverConfig;
+use crate::deliberation::{Deliberator, Plan, PlanWatch};
+use crate::envelope::SpeedEnvelope;
+use crate::events::{Severity, EVENTS};
+use crate::hazards::{ActiveHazard, HazardAction, HAZARDS};
+use crate::latency::{LatencyBreakdown, LatencyBudget};
+use crate::imu::HeadingEstimator;
+use crate::metrics::METRICS;
+use crate::motor_control::MotorController;
+use crate::pathfinding::{NavigationCommand, PathPlanner};
+use crate::power::{IdleManager, PowerAction, POWER};
+use crate::range::{RangeMonitor, RangeReading};
+use crate::selftest::{CheckStatus, SelfTest, SelfTestReport};
+use crate::startup::{Startup, SubsystemStatus};
+use crate::supervisor;
+use crate::vision::{Detection, NavigationAction, RuleWorld, VisionSystem};
+use crate::world::{FrameFindings, WorldState, WorldWatch};
+// A manual drive command holds for this long, so a teleop client that
+// goes quiet stops the rover.
+const MANUAL_HOLD: Duration = Duration::from_millis(500);
+// Turn rates below this, in rad/s, count as driving straight.
+const MANUAL_TURN_DEADBAND: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoverState {
+    Boot,
+    Idle,
+    Autonomous,
+    Manual,
+    Fault,
+    ShuttingDown,
+}
+
+impl RoverState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RoverState::Boot => "boot",
+            RoverState::Idle => "idle",
+            RoverState::Autonomous => "autonomous",
+            RoverState::Manual => "manual",
+            RoverState::Fault => "fault",
+            RoverState::ShuttingDown => "shutting_down",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trigger {
+    Booted,
+    StartAutonomous,
+    TakeManual,
+    Pause,
+    MissionComplete,
+    Fault(String),
+    AcknowledgeFault,
+    SetShadow(bool),
+    Shutdown,
+}
+
+impl Trigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Trigger::Booted => "booted",
+            Trigger::StartAutonomous => "start_autonomous",
+            Trigger::TakeManual => "take_manual",
+            Trigger::Pause => "pause",
+            Trigger::MissionComplete => "mission_complete",
+            Trigger::Fault(_) => "fault",
+            Trigger::AcknowledgeFault => "acknowledge_fault",
+            Trigger::SetShadow(_) => "set_shadow",
+            Trigger::Shutdown => "shutdown",
+        }
+    }
+}
+
+// The whole transition table. Anything not listed is illegal: a fault can
+// only be left by an operator acknowledging it, and nothing leaves
+// ShuttingDown. Idle doubles as the disarmed state, the only place shadow
+// mode can be switched on or off.
+pub fn next_state(from: RoverState, trigger: &Trigger) -> Option<RoverState> {
+    use RoverState::*;
+    match (from, trigger) {
+        (ShuttingDown, _) => None,
+        (_, Trigger::Shutdown) => Some(ShuttingDown),
+        (_, Trigger::Fault(_)) => Some(Fault),
+        (Boot, Trigger::Booted) => Some(Idle),
+        (Idle | Manual, Trigger::StartAutonomous) => Some(Autonomous),
+        (Idle | Autonomous, Trigger::TakeManual) => Some(Manual),
+        (Autonomous | Manual, Trigger::Pause) => Some(Idle),
+        (Autonomous, Trigger::MissionComplete) => Some(Idle),
+        (Fault, Trigger::AcknowledgeFault) => Some(Idle),
+        (Idle, Trigger::SetShadow(_)) => Some(Idle),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StateStatus {
+    pub state: RoverState,
+    pub in_state_s: f32,
+    // Why the last transition happened, e.g. the fault message.
+    pub reason: Option<String>,
+    pub shadow: bool,
+    // What shadow mode kept from the motors on the last tick.
+    pub would_command: Option<String>,
+    // The self-test's overall result, once it has one.
+    pub selftest: Option<CheckStatus>,
+    // Why the rover can't be armed, if it can't.
+    pub arming_blocked: Option<String>,
+    // A mission from before a restart, waiting to be confirmed.
+    pub resume: Option<ResumeOffer>,
+}
+
+// Velocities as ROS's cmd_vel has them: m/s forward, rad/s anticlockwise.
+#[derive(Debug, Clone, Copy)]
+struct ManualDrive {
+    linear_m_s: f32,
+    angular_rad_s: f32,
+    at: Instant,
+}
+
+struct SharedState {
+    state: RoverState,
+    entered_at: Instant,
+    reason: Option<String>,
+    shadow: bool,
+    would_command: Option<String>,
+    manual: Option<ManualDrive>,
+    resume: Option<ResumeOffer>,
+}
+
+// What the web server and other tasks hold: requests go through a channel
+// and are applied at the start of the next tick, except an emergency stop,
+// which reaches the motors immediately.
+#[derive(Clone)]
+pub struct StateHandle {
+    requests: Sender<Trigger>,
+    shared: Arc<RwLock<SharedState>>,
+    motor_controller: Arc<RwLock<MotorController>>,
+    selftest: Arc<SelfTest>,
+    startup: Arc<Startup>,
+    world: WorldWatch,
+}
+
+impl StateHandle {
+    pub fn request(&self, trigger: Trigger) {
+        let _ = self.requests.send(trigger);
+    }
+
+    pub fn emergency_stop(&self, reason: impl Into<String>) {
+        self.motor_controller.write().emergency_stop();
+        self.request(Trigger::Fault(reason.into()));
+    }
+
+    pub fn get_state(&self) -> RoverState {
+        self.shared.read().state
+    }
+
+    // The drive source for Manual. Dropped unless the rover is in Manual,
+    // so it never overrides autonomous driving or a fault.
+    pub fn drive_manual(&self, linear_m_s: f32, angular_rad_s: f32) -> bool {
+        let mut shared = self.shared.write();
+        if shared.state != RoverState::Manual {
+            return false;
+        }
+        shared.manual = Some(ManualDrive { linear_m_s, angular_rad_s, at: Instant::now() });
+        true
+    }
+
+    // Drops the manual drive command and stops the motors now rather than
+    // on the next tick. Only in Manual, like drive_manual.
+    pub fn stop_manual(&self) -> bool {
+        {
+            let mut shared = self.shared.write();
+            if shared.state != RoverState::Manual {
+                return false;
+            }
+            shared.manual = None;
+        }
+        self.motor_controller.write().stop();
+        true
+    }
+
+    pub fn get_status(&self) -> StateStatus {
+        state_status(&self.shared, &self.selftest, &self.startup)
+    }
+
+    // The snapshot from the last finished tick.
+    pub fn get_world(&self) -> Arc<WorldState> {
+        self.world.borrow().clone()
+    }
+
+    // For tasks that want to wake on every tick rather than poll.
+    pub fn subscribe_world(&self) -> WorldWatch {
+        self.world.clone()
+    }
+
+    pub fn get_selftest(&self) -> SelfTestReport {
+        self.selftest.get_report()
+    }
+
+    pub fn get_subsystems(&self) -> Vec<SubsystemStatus> {
+        self.startup.get_statuses()
+    }
+
+    // Starts a subsystem that failed to start again. The rover stays in
+    // Fault until that is acknowledged once it is ready.
+    pub fn retry_subsystem(&self, name: &str) -> Result<(), String> {
+        self.startup.retry(name)
+    }
+
+    // Arms despite failed checks, until the self-test is run again.
+    pub fn override_selftest(&self, by: &str) -> bool {
+        self.selftest.override_failures(by)
+    }
+
+    // Only while idle, so nothing is driving while the motors are tested.
+    pub fn rerun_selftest(&self) -> bool {
+        let state = self.get_state();
+        if state != RoverState::Idle {
+            warn!(target: "control", "Ignoring self-test re-run while {}", state.as_str());
+            return false;
+        }
+        self.selftest.start()
+    }
+
+    pub fn is_shadow(&self) -> bool {
+        self.shared.read().shadow
+    }
+
+    // Held until the rover next goes autonomous, for whatever reason, or
+    // the offer is declined. Meanwhile control.start_autonomous waits.
+    pub fn offer_resume(&self, offer: ResumeOffer) {
+        self.shared.write().resume = Some(offer);
+    }
+
+    pub fn get_resume(&self) -> Option<ResumeOffer> {
+        self.shared.read().resume.clone()
+    }
+
+    pub fn take_resume(&self) -> Option<ResumeOffer> {
+        self.shared.write().resume.take()
+    }
+}
+
+fn state_status(shared: &RwLock<SharedState>, selftest: &SelfTest, startup: &Startup) -> StateStatus {
+    let selftest = selftest.get_report();
+    let shared = shared.read();
+    StateStatus {
+        state: shared.state,
+        in_state_s: shared.entered_at.elapsed().as_secs_f32(),
+        reason: shared.reason.clone(),
+        shadow: shared.shadow,
+        would_command: shared.would_command.clone(),
+        selftest: selftest.status,
+        arming_blocked: startup.get_block_reason().or_else(|| selftest.get_block_reason()),
+        resume: shared.resume.clone(),
+    }
+}
+
+// The reflex layer: frames, range sensors and the state, each tick, then
+// the motors. The planner's side runs in a Deliberator, on a thread of its
+// own or in step with tick(), and all the reflex layer takes from it is
+// the last Plan, which it drives on unless something calls for less.
+pub struct RoverStateMachine {
+    vision: Arc<RwLock<VisionSystem>>,
+    path_planner: Arc<RwLock<PathPlanner>>,
+    motor_controller: Arc<RwLock<MotorController>>,
+    ranges: Arc<RangeMonitor>,
+    start_autonomous: bool,
+    plan_stale: Duration,
+    // hazards.caution_speed_m_s.
+    caution_speed: f32,
+    requests: Receiver<Trigger>,
+    handle: StateHandle,
+    world: watch::Sender<Arc<WorldState>>,
+    deliberator: Arc<Mutex<Deliberator>>,
+    plan: PlanWatch,
+    // The last plan acted on, so what it reports only happens once.
+    plan_seq: u64,
+    findings: Arc<FrameFindings>,
+    ticks: u64,
+    // Subsystems that panicked and get reset once the fault is acknowledged.
+    vision_panicked: bool,
+    planner_panicked: Arc<AtomicBool>,
+    // What went into the last navigation decision recorded in the black
+    // box, so only changes are.
+    last_decision: Option<(&'static str, &'static str, bool, Option<HazardAction>, &'static str)>,
+    latency: LatencyBudget,
+    idle: IdleManager,
+    imu: HeadingEstimator,
+    // An arming request that came in asleep, fired once the rover is awake.
+    pending_arm: Option<Trigger>,
+}
+
+impl RoverStateMachine {
+    pub fn new(
+        vision: Arc<RwLock<VisionSystem>>,
+        path_planner: Arc<RwLock<PathPlanner>>,
+        motor_controller: Arc<RwLock<MotorController>>,
+        ranges: Arc<RangeMonitor>,
+        config: &RoverConfig,
+        selftest: Arc<SelfTest>,
+        startup: Arc<Startup>,
+    ) -> Self {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let shared = Arc::new(RwLock::new(SharedState {
+            state: RoverState::Boot,
+            entered_at: Instant::now(),
+            reason: None,
+            shadow: config.control.shadow_mode,
+            would_command: None,
+            manual: None,
+            resume: None,
+        }));
+        let status = state_status(&shared, &selftest, &startup);
+        let (world, world_rx) = watch::channel(Arc::new(WorldState::capture(
+            0,
+            Vec::new(),
+            NavigationAction::Continue,
+            &vision,
+            &Plan::placeholder(&path_planner.read()),
+            &motor_controller,
+            status,
+        )));
+        let handle = StateHandle {
+            requests: tx,
+            shared,
+            motor_controller: motor_controller.clone(),
+            selftest,
+            startup,
+            world: world_rx,
+        };
+        let planner_panicked = Arc::new(AtomicBool::new(false));
+        let (deliberator, plan) = Deliberator::new(
+            path_planner.clone(),
+            motor_controller.clone(),
+            ranges.clone(),
+            handle.clone(),
+            planner_panicked.clone(),
+        );
+
+        Self {
+            vision,
+            path_planner,
+            motor_controller,
+            ranges,
+            start_autonomous: config.control.start_autonomous,
+            plan_stale: Duration::from_millis(config.control.plan_stale_ms),
+            caution_speed: config.hazards.caution_speed_m_s,
+            requests: rx,
+            handle,
+            world,
+            deliberator: Arc::new(Mutex::new(deliberator)),
+            plan,
+            plan_seq: 0,
+            findings: Arc::default(),
+            ticks: 0,
+            vision_panicked: false,
+            planner_panicked,
+            last_decision: None,
+            latency: LatencyBudget::new(config.control.latency_budget_ms),
+            idle: IdleManager::new(&config.power),
+            imu: HeadingEstimator::new(&config.imu),
+            pending_arm: None,
+        }
+    }
+
+    pub fn handle(&self) -> StateHandle {
+        self.handle.clone()
+    }
+
+    pub fn get_state(&self) -> RoverState {
+        self.handle.get_state()
+    }
+
+    // For the planning loop's thread.
+    pub fn get_deliberator(&self) -> Arc<Mutex<Deliberator>> {
+        self.deliberator.clone()
+    }
+
+    // Illegal requests are logged and dropped rather than treated as errors,
+    // since they usually come from a stale dashboard.
+    pub fn fire(&mut self, trigger: Trigger) -> bool {
+        let from = self.get_state();
+        let Some(to) = next_state(from, &trigger) else {
+            warn!(target: "control", "Ignoring {} while {}", trigger.as_str(), from.as_str());
+            return false;
+        };
+        if let Trigger::SetShadow(shadow) = trigger {
+            self.set_shadow(shadow);
+            return true;
+        }
+        if to == from {
+            return true;
+        }
+        if matches!(trigger, Trigger::StartAutonomous | Trigger::TakeManual) {
+            let blocked = self.handle.startup.get_block_reason().or_else(|| self.handle.selftest.get_block_reason());
+            if let Some(reason) = blocked {
+                warn!(target: "control", "Ignoring {}: {}", trigger.as_str(), reason);
+                EVENTS.publish(Severity::Warning, "control", format!("Arming refused, {}", reason), serde_json::json!({
+                    "trigger": trigger.as_str(),
+                    "reason": reason,
+                }));
+                return false;
+            }
+        }
+
+        let reason = match &trigger {
+            Trigger::Fault(message) => Some(message.clone()),
+            other => Some(other.as_str().to_string()),
+        };
+        {
+            let mut shared = self.handle.shared.write();
+            shared.state = to;
+            shared.entered_at = Instant::now();
+            shared.reason = reason.clone();
+            shared.manual = None;
+            if to == RoverState::Autonomous {
+                shared.resume = None;
+            }
+        }
+        METRICS.state_transitions.inc(to.as_str());
+
+        let severity = if to == RoverState::Fault { Severity::Critical } else { Severity::Info };
+        EVENTS.publish(severity, "control", format!("{} -> {}", from.as_str(), to.as_str()), serde_json::json!({
+            "from": from,
+            "to": to,
+            "reason": reason,
+        }));
+
+        if from == RoverState::Fault {
+            self.reset_panicked();
+        }
+        self.enter(to);
+        true
+    }
+
+    fn set_shadow(&mut self, shadow: bool) {
+        let changed = std::mem::replace(&mut self.handle.shared.write().shadow, shadow) != shadow;
+        if changed {
+            let mode = if shadow { "shadow" } else { "live" };
+            EVENTS.publish(Severity::Warning, "control", format!("Motors switched to {} mode", mode), serde_json::json!({
+                "shadow": shadow,
+            }));
+        }
+    }
+
+    // Only called on leaving Fault, when the motors are known to be stopped.
+    fn reset_panicked(&mut self) {
+        if std::mem::take(&mut self.vision_panicked) {
+            self.vision.write().reset();
+            METRICS.subsystem_restarts.inc("vision");
+            info!(target: "control", "Vision state reset after panic");
+        }
+        // Waits out a planning step still under way.
+        if self.planner_panicked.swap(false, Ordering::Relaxed) {
+            self.path_planner.write().reset();
+            METRICS.subsystem_restarts.inc("planner");
+            info!(target: "control", "Planner state reset after panic");
+        }
+    }
+
+    fn enter(&mut self, state: RoverState) {
+        let mut motors = self.motor_controller.write();
+        match state {
+            RoverState::Fault => motors.emergency_stop(),
+            RoverState::Boot | RoverState::Idle | RoverState::Manual | RoverState::ShuttingDown => motors.stop(),
+            RoverState::Autonomous => {}
+        }
+    }
+
+    // A reflex tick with a planning step in the middle of it, from what
+    // the tick has seen so far, as if planning kept up with every tick.
+    // For a harness stepping the rover through simulated time; run() ticks
+    // with reflex_tick() and plans on the planning loop's thread instead.
+    pub fn tick(&mut self, dt: Duration) -> Result<()> {
+        self.step(dt, true)
+    }
+
+    // A tick that drives on whatever the planning loop last published.
+    // Nothing in it waits on the planner.
+    pub fn reflex_tick(&mut self, dt: Duration) -> Result<()> {
+        self.step(dt, false)
+    }
+
+    fn step(&mut self, dt: Duration, plan_now: bool) -> Result<()> {
+        self.ticks += 1;
+        while let Ok(trigger) = self.requests.try_recv() {
+            if matches!(trigger, Trigger::StartAutonomous | Trigger::TakeManual) && !self.idle.is_awake() {
+                info!(target: "control", "Holding {} until the rover is awake", trigger.as_str());
+                POWER.request_wake("arming");
+                self.pending_arm = Some(trigger);
+                continue;
+            }
+            self.fire(trigger);
+        }
+
+        // A subsystem that failed to start holds the rover in Fault, even
+        // over an acknowledgement, until a retry gets it going.
+        if let Some(failed) = self.handle.startup.get_failure() {
+            if !matches!(self.get_state(), RoverState::Fault | RoverState::ShuttingDown) {
+                self.fire(Trigger::Fault(format!("{} failed to start: {}", failed.name, failed.error.unwrap_or_default())));
+            }
+        }
+
+        // Boot lasts until the self-test has a result and every subsystem
+        // has started; a failed self-test leaves the rover in Idle instead
+        // of starting autonomous.
+        if self.get_state() == RoverState::Boot && self.handle.selftest.is_finished() && !self.handle.startup.is_initializing() {
+            self.fire(Trigger::Booted);
+            if self.start_autonomous && self.handle.get_resume().is_some() {
+                info!(target: "control", "Not starting autonomous navigation until the interrupted mission is resumed or declined");
+            } else if self.start_autonomous {
+                info!(target: "control", "Starting autonomous navigation...");
+                self.fire(Trigger::StartAutonomous);
+            }
+        }
+
+        // A vision failure or panic faults the rover instead of ending the
+        // loop, so the motors are held stopped and the dashboard stays up.
+        // parking_lot locks don't poison, so a panic mid-update leaves the
+        // lock usable and reset() cleans up the state behind it.
+        let rule_world = RuleWorld {
+            speed: self.motor_controller.read().get_speed(),
+            state: self.get_state().as_str(),
+        };
+        let processed = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut vision = self.vision.write();
+            let detections = vision.process_frame()?;
+            // Fiducials and landmark detections are position fixes and free
+            // space ages out in the planner, so only a new frame's count.
+            let new_frame = vision.get_frame_capture_ms().is_some();
+            let fiducials = if new_frame { vision.get_last_fiducials() } else { Vec::new() };
+            let free_space = if new_frame { vision.get_last_free_space() } else { None };
+            Ok::<_, anyhow::Error>((detections, new_frame, fiducials, free_space, vision.get_navigation_command(&rule_world)))
+        }));
+        let (detections, new_frame, fiducials, free_space, nav_action) = match processed {
+            Ok(Ok(processed)) => processed,
+            Ok(Err(e)) => {
+                self.fire(Trigger::Fault(format!("Vision failed: {:#}", e)));
+                (Vec::new(), false, Vec::new(), None, NavigationAction::EmergencyStop)
+            }
+            Err(payload) => {
+                self.vision_panicked = true;
+                supervisor::report_panic("vision", &supervisor::panic_message(&*payload), &self.handle);
+                self.fire(Trigger::Fault("vision panicked".to_string()));
+                (Vec::new(), false, Vec::new(), None, NavigationAction::EmergencyStop)
+            }
+        };
+        self.update_power();
+        METRICS.navigation_actions.inc(nav_action.as_str());
+        let ranges = self.ranges.poll();
+        let range_stop = self.ranges.must_stop(&ranges);
+        let hazards = HAZARDS.assess();
+
+        // The wheels settle toward last tick's command before the pose is
+        // moved by them.
+        {
+            let mut motors = self.motor_controller.write();
+            let measured = self.imu.update(dt, motors.is_still());
+            motors.set_measured_heading(measured);
+            motors.advance(dt);
+        }
+        // What the frame had for the planner stays with every world state
+        // until the next one.
+        if new_frame {
+            let travel = self.vision.read().estimate_visual_travel();
+            self.findings = Arc::new(FrameFindings { tick: self.ticks, fiducials, free_space, travel });
+        }
+        if plan_now {
+            self.deliberator.lock().plan(self.ticks, &detections, &self.findings, &ranges, dt);
+        }
+
+        let plan = self.plan.borrow().clone();
+        if plan.seq != self.plan_seq {
+            self.plan_seq = plan.seq;
+            if self.get_state() == RoverState::Autonomous && plan.arrived {
+                self.fire(Trigger::MissionComplete);
+            }
+            // Following that stops, lost or cleared, leaves the rover
+            // waiting rather than roaming off on its own.
+            if self.get_state() == RoverState::Autonomous && plan.follow_ended {
+                self.fire(Trigger::Pause);
+            }
+        }
+        METRICS.plan_age_ms.set(plan.get_age().as_secs_f64() * 1000.0);
+
+        // Only a tick that took a new frame has a frame to time.
+        let stamps = self.vision.read().get_frame_stamps();
+        let plan_ms = CLOCK.now_ms_f64();
+        self.drive(self.get_state(), nav_action, range_stop, hazards.effect, &plan, dt);
+        let latency = stamps.map(|stamps| LatencyBreakdown::new(&stamps, plan_ms, CLOCK.now_ms_f64()));
+        if let Some(breakdown) = &latency {
+            self.latency.record(breakdown);
+        }
+        self.publish_world(detections, nav_action, latency, &plan, ranges, hazards.hazards);
+        Ok(())
+    }
+
+    // Sleeping slows the bridge and the power module drops the motor
+    // driver; waking brings both back and re-runs the self-test, which
+    // blocks arming until it passes again.
+    fn update_power(&mut self) {
+        let state = self.get_state();
+        if state == RoverState::ShuttingDown {
+            return;
+        }
+        let parked = state == RoverState::Idle;
+        let capture_ms = self.vision.read().get_frame_capture_ms();
+        match self.idle.poll(parked, capture_ms, self.handle.selftest.is_finished()) {
+            Some(PowerAction::Sleep) => self.vision.write().set_keepalive(Some(self.idle.get_keepalive_fps())),
+            Some(PowerAction::Wake) => {
+                self.vision.write().set_keepalive(None);
+                self.handle.selftest.start();
+            }
+            Some(PowerAction::Resumed) => {
+                if let Some(trigger) = self.pending_arm.take() {
+                    self.fire(trigger);
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn publish_world(&self, detections: Vec<Detection>, nav_action: NavigationAction, latency: Option<LatencyBreakdown>, plan: &Plan, ranges: Vec<RangeReading>, hazards: Vec<ActiveHazard>) {
+        let world = WorldState {
+            latency,
+            findings: self.findings.clone(),
+            ranges,
+            hazards,
+            ..WorldState::capture(
+                self.ticks,
+                detections,
+                nav_action,
+                &self.vision,
+                plan,
+                &self.motor_controller,
+                self.handle.get_status(),
+            )
+        };
+        self.world.send_replace(Arc::new(world));
+    }
+
+    // EmergencyStop from vision wins in every state, before the state gets
+    // a say, and shadow mode never holds it back. A range sensor inside
+    // range.stop_m stops the rover next, whatever vision and the planner
+    // make of the frame; in Manual it only blocks driving forward, as does
+    // a drop-off the rover hasn't backed away from yet. Autonomous drives
+    // on the plan, unless it is older than control.plan_stale_ms or an
+    // external hazard stops or slows it (see hazards.rs). Modes
+    // that don't drive still command the motors each tick so the motor
+    // controller keeps reporting healthy.
+    fn drive(&mut self, state: RoverState, nav_action: NavigationAction, range_stop: bool, hazard: Option<HazardAction>, plan: &Plan, dt: Duration) {
+        let capture_ms = self.vision.read().get_frame_capture_ms();
+        self.motor_controller.write().set_source_capture(capture_ms);
+        self.handle.shared.write().would_command = None;
+
+        if state != RoverState::Autonomous || matches!(nav_action, NavigationAction::EmergencyStop) {
+            self.last_decision = None;
+        }
+        if matches!(nav_action, NavigationAction::EmergencyStop) {
+            self.motor_controller.write().emergency_stop();
+            return;
+        }
+
+        let command = match state {
+            RoverState::Autonomous => {
+                let command = if range_stop {
+                    METRICS.range_stops.inc();
+                    NavigationCommand::Stop
+                } else if plan.get_age() > self.plan_stale {
+                    METRICS.plan_stale_stops.inc();
+                    NavigationCommand::Stop
+                } else if matches!(nav_action, NavigationAction::Stop) {
+                    NavigationCommand::Stop
+                } else if hazard == Some(HazardAction::Stop) {
+                    METRICS.hazard_stops.inc();
+                    NavigationCommand::Stop
+                } else {
+                    match plan.command.clone() {
+                        NavigationCommand::Forward(speed) if hazard == Some(HazardAction::Caution) => {
+                            METRICS.hazard_cautions.inc();
+                            NavigationCommand::Forward(speed.min(self.caution_speed))
+                        }
+                        command => command,
+                    }
+                };
+                self.record_decision(&nav_action, &plan.command, &plan.envelope, range_stop, hazard, &command);
+                command
+            }
+            RoverState::Manual => {
+                let command = self.manual_command(dt);
+                let forward = matches!(command, NavigationCommand::Forward(_));
+                if range_stop && forward {
+                    METRICS.range_stops.inc();
+                    NavigationCommand::Stop
+                } else if plan.drop_off && forward {
+                    NavigationCommand::Stop
+                } else {
+                    command
+                }
+            }
+            RoverState::Fault => {
+                self.motor_controller.write().emergency_stop();
+                return;
+            }
+            RoverState::Boot | RoverState::Idle | RoverState::ShuttingDown => {
+                self.motor_controller.write().stop();
+                return;
+            }
+        };
+
+        let mut motors = self.motor_controller.write();
+        if self.handle.is_shadow() {
+            debug!(target: "motors", "Shadow mode: would have commanded {:?}", command);
+            self.handle.shared.write().would_command = Some(format!("{:?}", command));
+            motors.stop();
+            return;
+        }
+        match command {
+            NavigationCommand::Stop => motors.stop(),
+            NavigationCommand::Forward(speed) => motors.move_forward(speed),
+            NavigationCommand::Backward(speed) => motors.move_backward(speed),
+            NavigationCommand::TurnLeft(angle) => motors.turn_left(angle),
+            NavigationCommand::TurnRight(angle) => motors.turn_right(angle),
+        }
+    }
+
+    // Only the kinds of command are compared, so a speed that eases off
+    // tick by tick is one decision.
+    // The speed envelope is recorded with it, since it decides how fast the
+    // rover goes where nothing is in sight.
+    fn record_decision(&mut self, nav_action: &NavigationAction, planned: &NavigationCommand, envelope: &SpeedEnvelope, range_stop: bool, hazard: Option<HazardAction>, command: &NavigationCommand) {
+        let decision = (nav_action.as_str(), planned.as_str(), range_stop, hazard, command.as_str());
+        if self.last_decision == Some(decision) {
+            return;
+        }
+        self.last_decision = Some(decision);
+        BLACKBOX.record(RecordKind::Decision, serde_json::json!({
+            "vision": nav_action.as_str(),
+            "planner": format!("{:?}", planned),
+            "envelope": envelope,
+            "range_stop": range_stop,
+            "hazard": hazard,
+            "command": format!("{:?}", command),
+            "shadow": self.handle.is_shadow(),
+        }));
+    }
+
+    // The motors take one command a tick, so turning wins over driving and
+    // a turn rate becomes the angle covered in this tick.
+    fn manual_command(&self, dt: Duration) -> NavigationCommand {
+        let manual = self.handle.shared.read().manual;
+        let Some(manual) = manual.filter(|m| m.at.elapsed() < MANUAL_HOLD) else {
+            return NavigationCommand::Stop;
+        };
+        let angle = manual.angular_rad_s.abs().to_degrees() * dt.as_secs_f32();
+        if manual.angular_rad_s >= MANUAL_TURN_DEADBAND {
+            NavigationCommand::TurnLeft(angle)
+        } else if manual.angular_rad_s <= -MANUAL_TURN_DEADBAND {
+            NavigationCommand::TurnRight(angle)
+        } else if manual.linear_m_s > 0.0 {
+            NavigationCommand::Forward(manual.linear_m_s)
+        } else if manual.linear_m_s < 0.0 {
+            NavigationCommand::Backward(-manual.linear_m_s)
+        } else {
+            NavigationCommand::Stop
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use RoverState::*;
+
+    const STATES: [RoverState; 6] = [Boot, Idle, Autonomous, Manual, Fault, ShuttingDown];
+
+    fn triggers() -> Vec<Trigger> {
+        vec![
+            Trigger::Booted,
+            Trigger::StartAutonomous,
+            Trigger::TakeManual,
+            Trigger::Pause,
+            Trigger::MissionComplete,
+            Trigger::Fault("test".into()),
+            Trigger::AcknowledgeFault,
+            Trigger::SetShadow(true),
+            Trigger::SetShadow(false),
+            Trigger::Shutdown,
+        ]
+    }
+
+    // The table again, written out pair by pair, so a change to
+    // next_state has to be made twice.
+    fn allowed(from: RoverState, trigger: &Trigger) -> Option<RoverState> {
+        let allowed = [
+            (Boot, "booted", Idle),
+            (Idle, "start_autonomous", Autonomous),
+            (Manual, "start_autonomous", Autonomous),
+            (Idle, "take_manual", Manual),
+            (Autonomous, "take_manual", Manual),
+            (Autonomous, "pause", Idle),
+            (Manual, "pause", Idle),
+            (Autonomous, "mission_complete", Idle),
+            (Fault, "acknowledge_fault", Idle),
+            (Idle, "set_shadow", Idle),
+        ];
+        if from == ShuttingDown {
+            return None;
+        }
+        match trigger {
+            Trigger::Shutdown => Some(ShuttingDown),
+            Trigger::Fault(_) => Some(Fault),
+            _ => allowed.iter().find(|(f, t, _)| *f == from && *t == trigger.as_str()).map(|(_, _, to)| *to),
+        }
+    }
+
+    #[test]
+    fn every_pair_not_in_the_table_is_illegal() {
+        let mut illegal = 0;
+        for from in STATES {
+            for trigger in triggers() {
+                let expected = allowed(from, &trigger);
+                assert_eq!(next_state(from, &trigger), expected, "{} on {}", from.as_str(), trigger.as_str());
+                if expected.is_none() {
+                    illegal += 1;
+                }
+            }
+        }
+        // 60 pairs, 11 legal ones besides the fault and shutdown columns.
+        assert_eq!(illegal, 60 - 11 - 2 * 5);
+    }
+
+    #[test]
+    fn an_emergency_stop_faults_every_state_but_shutting_down() {
+        for from in STATES {
+            let to = next_state(from, &Trigger::Fault("emergency stop".into()));
+            if from == ShuttingDown {
+                assert_eq!(to, None);
+            } else {
+                assert_eq!(to, Some(Fault), "from {}", from.as_str());
+            }
+        }
+    }
+
+    #[test]
+    fn a_fault_is_only_left_by_acknowledging_it_or_shutting_down() {
+        for trigger in triggers() {
+            let to = next_state(Fault, &trigger);
+            match trigger {
+                Trigger::AcknowledgeFault => assert_eq!(to, Some(Idle)),
+                Trigger::Shutdown => assert_eq!(to, Some(ShuttingDown)),
+                Trigger::Fault(_) => assert_eq!(to, Some(Fault)),
+                _ => assert_eq!(to, None, "{} left Fault", trigger.as_str()),
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_leaves_shutting_down() {
+        for trigger in triggers() {
+            assert_eq!(next_state(ShuttingDown, &trigger), None, "{}", trigger.as_str());
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// src/stream_quality.rs
+use anyhow::Result;
+use base64::Engine;
+use image::imageops::FilterType;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::config::{QualityStep, WebConfig};
+use crate::metrics::METRICS;
+use crate::vision::pixels::{self, FrameImage};
+
+// What a client asked for in its hello.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityMode {
+    // Follow the link, per web.adaptive_quality.
+    #[default]
+    Auto,
+    // Always the last of web.quality_steps.
+    Low,
+    // Always as captured.
+    Full,
+}
+
+// One client's place on web.quality_steps: 0 is the frame as captured,
+// n is quality_steps[n - 1].
+pub struct LinkQuality {
+    mode: QualityMode,
+    level: usize,
+    changed_at: Instant,
+    behind_at: Instant,
+    rtt: Option<Duration>,
+}
+
+impl LinkQuality {
+    pub fn new() -> Self {
+        Self {
+            mode: QualityMode::Auto,
+            level: 0,
+            changed_at: Instant::now(),
+            behind_at: Instant::now(),
+            rtt: None,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: QualityMode) {
+        self.mode = mode;
+    }
+
+    // Ping to pong, the closest thing a WebSocket has to an ack.
+    pub fn set_rtt(&mut self, rtt: Duration) {
+        self.rtt = Some(rtt);
+    }
+
+    // The level for the next frame, given how many messages are still
+    // queued for the client. Steps down at once when the link falls
+    // behind, but back up only after quality_recover_s of keeping up, and
+    // never more often than quality_hold_s either way.
+    pub fn update(&mut self, backlog: usize, config: &WebConfig) -> usize {
+        let steps = config.quality_steps.len();
+        match self.mode {
+            QualityMode::Full => return 0,
+            QualityMode::Low => return steps,
+            QualityMode::Auto if !config.adaptive_quality => return 0,
+            QualityMode::Auto => {}
+        }
+        let behind = backlog > config.quality_backlog as usize
+            || self.rtt.is_some_and(|rtt| rtt > Duration::from_millis(config.quality_rtt_ms));
+        if behind {
+            self.behind_at = Instant::now();
+        }
+        let held = self.changed_at.elapsed() >= Duration::from_secs_f32(config.quality_hold_s);
+        let recovered = self.behind_at.elapsed() >= Duration::from_secs_f32(config.quality_recover_s);
+        if behind && held && self.level < steps {
+            self.level += 1;
+            self.changed_at = Instant::now();
+            METRICS.stream_quality_changes.inc("down");
+        } else if recovered && held && self.level > 0 {
+            self.level -= 1;
+            self.changed_at = Instant::now();
+            // Each further step up needs another quiet spell.
+            self.behind_at = Instant::now();
+            METRICS.stream_quality_changes.inc("up");
+        }
+        self.level = self.level.min(steps);
+        self.level
+    }
+
+    pub fn get_mode(&self) -> QualityMode {
+        self.mode
+    }
+
+    pub fn get_level(&self) -> usize {
+        self.level
+    }
+
+    pub fn get_rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+}
+
+impl Default for LinkQuality {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Scales the frame and encodes it as base64 JPEG. A raw frame is only
+// converted to RGB once however many levels are encoded from it.
+pub fn reencode(image: &FrameImage, step: &QualityStep) -> Result<String> {
+    let rgb = image.get_rgb()?;
+    let scaled;
+    let frame = if step.scale < 1.0 {
+        let width = ((rgb.width() as f32 * step.scale).round() as u32).max(1);
+        let height = ((rgb.height() as f32 * step.scale).round() as u32).max(1);
+        scaled = image::imageops::resize(&*rgb, width, height, FilterType::Triangle);
+        &scaled
+    } else {
+        &*rgb
+    };
+    let out = pixels::encode_jpeg(frame, step.jpeg_quality)?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(out))
+}
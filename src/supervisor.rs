@@ -0,0 +1,70 @@
+// src/supervisor.rs
+use std::any::Any;
+use std::future::Future;
+use std::time::Duration;
+use tracing::{error, info};
+
+use crate::events::{Severity, EVENTS};
+use crate::metrics::METRICS;
+use crate::state_machine::StateHandle;
+
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
+
+// Routes panic reports through tracing so they reach the JSON log too. The
+// unwinding itself is left to whoever catches it.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let thread = std::thread::current();
+        let location = info.location().map(|l| l.to_string()).unwrap_or_default();
+        error!(
+            target: "control",
+            thread = thread.name().unwrap_or("unnamed"),
+            location = %location,
+            "Panic: {}",
+            panic_message(info.payload())
+        );
+    }));
+}
+
+// A subsystem panic always stops the motors and faults the rover; the
+// operator has to acknowledge it before the rover moves again.
+pub fn report_panic(subsystem: &'static str, message: &str, state: &StateHandle) {
+    METRICS.panics.inc(subsystem);
+    EVENTS.publish(Severity::Critical, subsystem, format!("{} panicked: {}", subsystem, message), serde_json::json!({
+        "subsystem": subsystem,
+    }));
+    state.emergency_stop(format!("{} panicked", subsystem));
+}
+
+// Runs a tokio task and respawns it after a short backoff whenever it
+// panics. A task that returns normally is not restarted.
+pub fn supervise<F, Fut>(subsystem: &'static str, state: StateHandle, mut make_task: F)
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        loop {
+            match tokio::spawn(make_task()).await {
+                Ok(()) => return,
+                Err(e) if e.is_panic() => {
+                    report_panic(subsystem, &panic_message(&*e.into_panic()), &state);
+                    tokio::time::sleep(RESTART_BACKOFF).await;
+                    METRICS.subsystem_restarts.inc(subsystem);
+                    info!(target: "control", "Restarting {}", subsystem);
+                }
+                Err(_) => return,
+            }
+        }
+    });
+}
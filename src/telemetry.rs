@@ -0,0 +1,205 @@
+// src/telemetry.rs
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::clock::CLOCK;
+use crate::metrics::METRICS;
+
+pub const METRIC_NAMES: [&str; 8] = ["fps", "loop_time_ms", "obstacle_count", "speed", "latency_ms", "cpu_temp_c", "pose_sigma_m", "wheel_slip"];
+
+// 10Hz for the last minute, 1Hz for the last hour.
+const FINE_RESOLUTION: Duration = Duration::from_millis(100);
+const FINE_CAPACITY: usize = 600;
+const COARSE_RESOLUTION: Duration = Duration::from_secs(1);
+const COARSE_CAPACITY: usize = 3600;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TelemetrySample {
+    values: [f64; METRIC_NAMES.len()],
+}
+
+impl TelemetrySample {
+    // Read from the metrics atomics, so sampling never touches the
+    // subsystem locks.
+    pub fn from_metrics() -> Self {
+        Self {
+            values: [
+                METRICS.loop_fps.get(),
+                METRICS.last_loop_time_ms.get(),
+                METRICS.obstacles.get() as f64,
+                METRICS.motor_speed.get(),
+                METRICS.last_e2e_latency_ms.get(),
+                METRICS.cpu_temp_c.get(),
+                METRICS.pose_sigma_m.get(),
+                METRICS.wheel_slip.get(),
+            ],
+        }
+    }
+
+    pub fn get(&self, metric: &str) -> Option<f64> {
+        METRIC_NAMES.iter().position(|m| *m == metric).map(|i| self.values[i])
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Stat {
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: u32,
+}
+
+impl Stat {
+    fn new(value: f64) -> Self {
+        Self { min: value, max: value, sum: value, count: 1 }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    t_ms: u64,
+    stats: [Stat; METRIC_NAMES.len()],
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryPoint {
+    pub t: u64,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct History {
+    pub metric: &'static str,
+    pub window_s: u64,
+    pub resolution_ms: u64,
+    pub points: Vec<HistoryPoint>,
+}
+
+struct Ring {
+    resolution_ms: u64,
+    capacity: usize,
+    buckets: VecDeque<Bucket>,
+}
+
+impl Ring {
+    fn new(resolution: Duration, capacity: usize) -> Self {
+        Self {
+            resolution_ms: resolution.as_millis() as u64,
+            capacity,
+            buckets: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    fn record(&mut self, now_ms: u64, sample: &TelemetrySample) {
+        let t_ms = now_ms - now_ms % self.resolution_ms;
+        match self.buckets.back_mut() {
+            Some(bucket) if bucket.t_ms == t_ms => {
+                for (stat, value) in bucket.stats.iter_mut().zip(sample.values) {
+                    stat.add(value);
+                }
+            }
+            _ => {
+                if self.buckets.len() == self.capacity {
+                    self.buckets.pop_front();
+                }
+                self.buckets.push_back(Bucket {
+                    t_ms,
+                    stats: sample.values.map(Stat::new),
+                });
+            }
+        }
+    }
+
+    fn span(&self) -> Duration {
+        Duration::from_millis(self.resolution_ms * self.capacity as u64)
+    }
+}
+
+pub struct TelemetryHistory {
+    rings: Mutex<(Ring, Ring)>,
+}
+
+impl TelemetryHistory {
+    pub fn new() -> Self {
+        Self {
+            rings: Mutex::new((
+                Ring::new(FINE_RESOLUTION, FINE_CAPACITY),
+                Ring::new(COARSE_RESOLUTION, COARSE_CAPACITY),
+            )),
+        }
+    }
+
+    pub fn record(&self, sample: TelemetrySample) {
+        let now_ms = now_ms();
+        let mut rings = self.rings.lock();
+        rings.0.record(now_ms, &sample);
+        rings.1.record(now_ms, &sample);
+    }
+
+    // Windows that fit in the fine ring are served at 10Hz, anything longer
+    // at 1Hz. The newest bucket may still be filling.
+    pub fn query(&self, metric: &str, window: Duration) -> Option<History> {
+        let index = METRIC_NAMES.iter().position(|m| *m == metric)?;
+        let rings = self.rings.lock();
+        let ring = if window <= rings.0.span() { &rings.0 } else { &rings.1 };
+        let window = window.min(ring.span());
+        let since = now_ms().saturating_sub(window.as_millis() as u64);
+
+        let points = ring.buckets.iter()
+            .filter(|b| b.t_ms >= since)
+            .map(|b| {
+                let stat = b.stats[index];
+                HistoryPoint {
+                    t: b.t_ms,
+                    min: stat.min,
+                    max: stat.max,
+                    avg: stat.sum / stat.count as f64,
+                }
+            })
+            .collect();
+
+        Some(History {
+            metric: METRIC_NAMES[index],
+            window_s: window.as_secs(),
+            resolution_ms: ring.resolution_ms,
+            points,
+        })
+    }
+}
+
+impl Default for TelemetryHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Accepts "90", "30s", "5m" or "1h".
+pub fn parse_window(window: &str) -> Option<Duration> {
+    let (number, unit) = match window.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => window.split_at(i),
+        None => (window, "s"),
+    };
+    let number: u64 = number.parse().ok()?;
+    let seconds = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 3600,
+        _ => return None,
+    };
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+fn now_ms() -> u64 {
+    CLOCK.wall_ms()
+}
@@ -0,0 +1,153 @@
+// src/teleop.rs
+//
+// Gamepad driving over the WebSocket. The client only sends where the
+// sticks are, with a sequence number and whether the dead-man button is
+// held; everything that makes that safe to drive on happens here rather
+// than in the client: the dead-man check, the deadzone, response curve
+// and inversion from teleop, the speed limits, and stopping as soon as
+// commands go missing.
+use serde::{Deserialize, Serialize};
+
+use crate::config::TeleopConfig;
+use crate::metrics::METRICS;
+use crate::state_machine::StateHandle;
+
+// {"cmd": "axis", "seq": 42, "deadman": true, "linear": 0.6, "angular": -0.1}
+// with both axes from -1 to 1. `seq` goes up by one with each command the
+// client sends.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AxisCommand {
+    pub seq: u64,
+    pub deadman: bool,
+    pub linear: f32,
+    pub angular: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AxisResult {
+    Applied,
+    // The dead-man button wasn't held.
+    Released,
+    // Too many commands went missing before this one.
+    Gap,
+    // A sequence number no newer than the last; dropped without stopping.
+    Stale,
+    // An axis that isn't a number.
+    Invalid,
+    // Shaped and governed, but the rover isn't in manual mode.
+    Ignored,
+}
+
+impl AxisResult {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AxisResult::Applied => "applied",
+            AxisResult::Released => "released",
+            AxisResult::Gap => "gap",
+            AxisResult::Stale => "stale",
+            AxisResult::Invalid => "invalid",
+            AxisResult::Ignored => "ignored",
+        }
+    }
+
+    // Whether the rover has to stop for it there and then.
+    pub fn stops(&self) -> bool {
+        matches!(self, AxisResult::Released | AxisResult::Gap | AxisResult::Invalid)
+    }
+}
+
+// As acknowledged to the client, with what the command came to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct AxisOutcome {
+    pub seq: u64,
+    pub result: AxisResult,
+    pub linear_m_s: f32,
+    pub angular_rad_s: f32,
+    // Commands missing before this one.
+    pub missed: u64,
+}
+
+// Where an axis at `value` ends up, -1 to 1: zero inside the deadzone,
+// then the exponential curve over what is left.
+pub fn shape(value: f32, deadzone: f32, expo: f32) -> f32 {
+    let magnitude = value.abs().min(1.0);
+    if magnitude <= deadzone {
+        return 0.0;
+    }
+    let x = (magnitude - deadzone) / (1.0 - deadzone);
+    let curved = if expo < 1e-3 { x } else { (expo * x).exp_m1() / expo.exp_m1() };
+    curved.copysign(value)
+}
+
+// One per WebSocket client.
+#[derive(Debug, Default)]
+pub struct TeleopSession {
+    last_seq: Option<u64>,
+    driving: bool,
+}
+
+impl TeleopSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // What `command` comes to. `speed_limit` is the most the speed
+    // envelope allows forward, if it limits anything. The first command
+    // from a client can have any sequence number.
+    pub fn handle(&mut self, command: &AxisCommand, config: &TeleopConfig, speed_limit: Option<f32>) -> AxisOutcome {
+        let mut outcome = AxisOutcome { seq: command.seq, result: AxisResult::Applied, linear_m_s: 0.0, angular_rad_s: 0.0, missed: 0 };
+        if let Some(last) = self.last_seq {
+            if command.seq <= last {
+                outcome.result = AxisResult::Stale;
+                return outcome;
+            }
+            outcome.missed = command.seq - last - 1;
+        }
+        self.last_seq = Some(command.seq);
+
+        outcome.result = if outcome.missed > config.max_sequence_gap {
+            AxisResult::Gap
+        } else if !command.deadman {
+            AxisResult::Released
+        } else if !(command.linear.is_finite() && command.angular.is_finite()) {
+            AxisResult::Invalid
+        } else {
+            AxisResult::Applied
+        };
+        self.driving = outcome.result == AxisResult::Applied;
+        if !self.driving {
+            return outcome;
+        }
+
+        let sign = |invert: bool| if invert { -1.0 } else { 1.0 };
+        let linear = shape(command.linear, config.deadzone, config.expo) * sign(config.invert_linear);
+        let angular = shape(command.angular, config.deadzone, config.expo) * sign(config.invert_angular);
+        let forward_limit = speed_limit.map_or(config.max_linear_m_s, |limit| limit.min(config.max_linear_m_s));
+        outcome.linear_m_s = (linear * config.max_linear_m_s).clamp(-config.max_linear_m_s, forward_limit);
+        outcome.angular_rad_s = angular * config.max_angular_rad_s;
+        outcome
+    }
+
+    // Handles `command` and drives on what it comes to, under the speed
+    // envelope the rover last planned. Anything that isn't a command to
+    // drive on stops the rover there and then.
+    pub fn drive(&mut self, command: &AxisCommand, config: &TeleopConfig, state: &StateHandle) -> AxisOutcome {
+        let speed_limit = state.get_world().plan.speed_envelope.max_speed;
+        let mut outcome = self.handle(command, config, speed_limit);
+        if outcome.result == AxisResult::Applied && !state.drive_manual(outcome.linear_m_s, outcome.angular_rad_s) {
+            outcome.result = AxisResult::Ignored;
+            self.driving = false;
+        } else if outcome.result.stops() {
+            state.stop_manual();
+        }
+        METRICS.teleop_commands.inc(outcome.result.as_str());
+        outcome
+    }
+
+    // Whether the last command drove, so dropping the client has to stop
+    // the rover.
+    pub fn is_driving(&self) -> bool {
+        self.driving
+    }
+}
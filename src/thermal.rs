@@ -0,0 +1,197 @@
+// src/thermal.rs
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::{ThermalConfig, ThermalSourceKind};
+use crate::events::{Severity, EVENTS};
+use crate::metrics::METRICS;
+use crate::motor_control::MotorController;
+
+pub static THERMAL: Lazy<ThermalMonitor> = Lazy::new(ThermalMonitor::new);
+
+// Bits of vcgencmd get_throttled that describe right now; the ones above
+// bit 16 only say it happened since boot.
+const UNDER_VOLTAGE: u32 = 1 << 0;
+const FREQUENCY_CAPPED: u32 = 1 << 1;
+const THROTTLED: u32 = 1 << 2;
+const SOFT_TEMP_LIMIT: u32 = 1 << 3;
+
+pub trait ThermalSource: Send {
+    fn read_temp_c(&mut self) -> Result<f32>;
+    // None when the source has no way to tell.
+    fn read_throttle_flags(&mut self) -> Result<Option<u32>>;
+}
+
+// Reports fixed readings, for running without a Pi.
+pub struct MockThermalSource {
+    temp_c: f32,
+    throttle_flags: u32,
+}
+
+impl MockThermalSource {
+    pub fn new(temp_c: f32, throttle_flags: u32) -> Self {
+        Self { temp_c, throttle_flags }
+    }
+}
+
+impl ThermalSource for MockThermalSource {
+    fn read_temp_c(&mut self) -> Result<f32> {
+        Ok(self.temp_c)
+    }
+
+    fn read_throttle_flags(&mut self) -> Result<Option<u32>> {
+        Ok(Some(self.throttle_flags))
+    }
+}
+
+// The kernel's thermal zone, in millidegrees.
+pub struct SysfsThermalSource {
+    temp_path: String,
+}
+
+impl ThermalSource for SysfsThermalSource {
+    fn read_temp_c(&mut self) -> Result<f32> {
+        let text = std::fs::read_to_string(&self.temp_path)
+            .with_context(|| format!("Failed to read {}", self.temp_path))?;
+        let millidegrees: f32 = text.trim().parse()
+            .with_context(|| format!("{} does not hold a temperature", self.temp_path))?;
+        Ok(millidegrees / 1000.0)
+    }
+
+    #[cfg(feature = "vcgencmd")]
+    fn read_throttle_flags(&mut self) -> Result<Option<u32>> {
+        let output = std::process::Command::new("vcgencmd").arg("get_throttled").output()
+            .context("Failed to run vcgencmd")?;
+        let text = String::from_utf8_lossy(&output.stdout);
+        let hex = text.trim().strip_prefix("throttled=0x")
+            .with_context(|| format!("Unexpected vcgencmd output: {}", text.trim()))?;
+        Ok(Some(u32::from_str_radix(hex, 16)?))
+    }
+
+    #[cfg(not(feature = "vcgencmd"))]
+    fn read_throttle_flags(&mut self) -> Result<Option<u32>> {
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ThermalStatus {
+    pub temp_c: Option<f32>,
+    pub throttle_flags: Option<u32>,
+    // The firmware is holding the CPU back right now, for heat or power.
+    pub throttled: bool,
+    pub under_voltage: bool,
+    pub degraded: bool,
+}
+
+// The last reading and whether the rover is running lighter because of
+// it. Readers never wait on the monitor thread.
+pub struct ThermalMonitor {
+    status: RwLock<ThermalStatus>,
+}
+
+impl ThermalMonitor {
+    fn new() -> Self {
+        Self { status: RwLock::new(ThermalStatus::default()) }
+    }
+
+    pub fn get_status(&self) -> ThermalStatus {
+        self.status.read().clone()
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.status.read().degraded
+    }
+
+    // Takes one reading and decides whether to run degraded, returning
+    // whether that changed.
+    fn update(&self, config: &ThermalConfig, source: &mut dyn ThermalSource) -> bool {
+        let temp_c = source.read_temp_c()
+            .map_err(|e| warn!(target: "thermal", "Reading the CPU temperature failed: {:#}", e))
+            .ok();
+        let throttle_flags = source.read_throttle_flags()
+            .map_err(|e| warn!(target: "thermal", "Reading the throttle flags failed: {:#}", e))
+            .ok()
+            .flatten();
+        let current = throttle_flags.unwrap_or(0);
+        let throttled = current & (FREQUENCY_CAPPED | THROTTLED | SOFT_TEMP_LIMIT) != 0;
+
+        let mut status = self.status.write();
+        // Between recover_temp_c and degrade_temp_c the rover stays as it
+        // is, so it doesn't flap around one threshold. A failed read
+        // changes nothing either.
+        let degraded = match temp_c {
+            Some(t) if status.degraded => throttled || t >= config.recover_temp_c,
+            Some(t) => throttled || t >= config.degrade_temp_c,
+            None => status.degraded || throttled,
+        };
+        let changed = degraded != status.degraded;
+        *status = ThermalStatus {
+            temp_c: temp_c.or(status.temp_c),
+            throttle_flags,
+            throttled,
+            under_voltage: current & UNDER_VOLTAGE != 0,
+            degraded,
+        };
+
+        if let Some(t) = status.temp_c {
+            METRICS.cpu_temp_c.set(t as f64);
+        }
+        METRICS.throttle_flags.set(current as i64);
+        METRICS.thermal_degraded.set(degraded as i64);
+        changed
+    }
+}
+
+fn open(config: &ThermalConfig) -> Box<dyn ThermalSource> {
+    match config.source {
+        ThermalSourceKind::Sysfs => {
+            #[cfg(not(feature = "vcgencmd"))]
+            warn!(target: "thermal", "Built without the `vcgencmd` feature; throttle flags are not read");
+            Box::new(SysfsThermalSource { temp_path: config.temp_path.clone() })
+        }
+        ThermalSourceKind::Mock => Box::new(MockThermalSource::new(config.mock_temp_c, config.mock_throttle_flags)),
+    }
+}
+
+// Does nothing unless thermal.enabled is set. Polls on its own thread;
+// running degraded caps the motors here, and the web server checks
+// THERMAL.is_degraded() for its part.
+pub fn spawn(config: &ThermalConfig, motor_controller: Arc<RwLock<MotorController>>) {
+    if !config.enabled {
+        return;
+    }
+    let config = config.clone();
+    let mut source = open(&config);
+    info!(target: "thermal", "Thermal monitor on {:?}, degrading at {:.0}°C", config.source, config.degrade_temp_c);
+    std::thread::spawn(move || loop {
+        if THERMAL.update(&config, source.as_mut()) {
+            apply(&config, &THERMAL.get_status(), &motor_controller);
+        }
+        std::thread::sleep(Duration::from_millis(config.poll_interval_ms));
+    });
+}
+
+// Lifting the cap puts motors.max_speed back in charge; everything else
+// reads the flag as it goes.
+fn apply(config: &ThermalConfig, status: &ThermalStatus, motor_controller: &RwLock<MotorController>) {
+    let temp = status.temp_c.map_or("unknown".to_string(), |t| format!("{:.1}°C", t));
+    let data = serde_json::json!({
+        "temp_c": status.temp_c,
+        "throttle_flags": status.throttle_flags,
+    });
+    if status.degraded {
+        motor_controller.write().set_speed_cap(Some(config.degraded_max_speed));
+        warn!(target: "thermal", "Running hot ({}, throttled: {}), degrading", temp, status.throttled);
+        EVENTS.publish(Severity::Warning, "thermal", "Running hot: lower frame rate, no trajectory overlay, speed capped", data);
+    } else {
+        motor_controller.write().set_speed_cap(None);
+        info!(target: "thermal", "Cooled down to {}, back to normal", temp);
+        EVENTS.publish(Severity::Info, "thermal", "Cooled down: back to normal", data);
+    }
+}
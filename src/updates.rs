@@ -0,0 +1,362 @@
+// src/updates.rs
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::config::UpdatesConfig;
+use crate::events::{Severity, EVENTS};
+use crate::vision::LabelMap;
+
+pub static ASSETS: Lazy<AssetStore> = Lazy::new(AssetStore::new);
+
+const MANIFEST: &str = "manifest.json";
+const MAX_FILE_NAME: usize = 100;
+
+// What an upload replaces. The bridge script stands in for
+// src/vision_bridge.py, the model config for the IMX500 post-processing
+// file, and the labels for the imx500 label map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    BridgeScript,
+    ModelConfig,
+    Labels,
+}
+
+impl ArtifactKind {
+    pub const ALL: [ArtifactKind; 3] = [ArtifactKind::BridgeScript, ArtifactKind::ModelConfig, ArtifactKind::Labels];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArtifactKind::BridgeScript => "bridge_script",
+            ArtifactKind::ModelConfig => "model_config",
+            ArtifactKind::Labels => "labels",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == name)
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            ArtifactKind::BridgeScript => &["py"],
+            ArtifactKind::ModelConfig => &["json"],
+            ArtifactKind::Labels => &["txt", "names", "json"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssetVersion {
+    pub version: String,
+    pub file_name: String,
+    pub sha256: String,
+    pub size: u64,
+    pub uploaded_at: String,
+}
+
+// A previous of None is the built-in asset, so rolling back the first
+// activation goes back to it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArtifactManifest {
+    pub active: Option<String>,
+    pub previous: Option<String>,
+    // Oldest first.
+    pub versions: Vec<AssetVersion>,
+}
+
+impl ArtifactManifest {
+    fn get_version(&self, version: &str) -> Option<&AssetVersion> {
+        self.versions.iter().find(|v| v.version == version)
+    }
+}
+
+#[derive(Debug)]
+pub enum AssetError {
+    Disabled,
+    // The upload or request itself is wrong; nothing was changed.
+    Invalid(String),
+    NotFound(String),
+    Failed(anyhow::Error),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::Disabled => write!(f, "asset updates are disabled"),
+            AssetError::Invalid(message) | AssetError::NotFound(message) => write!(f, "{}", message),
+            AssetError::Failed(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl From<anyhow::Error> for AssetError {
+    fn from(e: anyhow::Error) -> Self {
+        AssetError::Failed(e)
+    }
+}
+
+struct Store {
+    dir: PathBuf,
+    max_bytes: u64,
+    keep_versions: usize,
+    manifests: BTreeMap<ArtifactKind, ArtifactManifest>,
+}
+
+impl Store {
+    fn kind_dir(&self, kind: ArtifactKind) -> PathBuf {
+        self.dir.join(kind.as_str())
+    }
+
+    fn path_of(&self, kind: ArtifactKind, version: &AssetVersion) -> PathBuf {
+        self.kind_dir(kind).join(&version.version).join(&version.file_name)
+    }
+
+    fn save(&self, kind: ArtifactKind) -> Result<()> {
+        let path = self.kind_dir(kind).join(MANIFEST);
+        let partial = path.with_extension("json.partial");
+        let manifest = self.manifests.get(&kind).cloned().unwrap_or_default();
+        std::fs::write(&partial, serde_json::to_vec_pretty(&manifest)?)
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        std::fs::rename(&partial, &path).with_context(|| format!("Failed to replace {}", path.display()))
+    }
+
+    // Drops the oldest versions that are neither active nor previous.
+    fn prune(&mut self, kind: ArtifactKind) {
+        let dir = self.kind_dir(kind);
+        let manifest = self.manifests.entry(kind).or_default();
+        let (active, previous) = (manifest.active.clone(), manifest.previous.clone());
+        let in_use = |v: &AssetVersion| active.as_ref() == Some(&v.version) || previous.as_ref() == Some(&v.version);
+        let spare = manifest.versions.iter().filter(|v| !in_use(v)).count();
+        let mut excess = spare.saturating_sub(self.keep_versions);
+        let mut kept = Vec::new();
+        for version in std::mem::take(&mut manifest.versions) {
+            if excess > 0 && !in_use(&version) {
+                excess -= 1;
+                if let Err(e) = std::fs::remove_dir_all(dir.join(&version.version)) {
+                    warn!(target: "updates", "Failed to remove {} {}: {}", kind.as_str(), version.version, e);
+                }
+                continue;
+            }
+            kept.push(version);
+        }
+        manifest.versions = kept;
+    }
+}
+
+// The uploaded assets and which of each kind is active. Until opened,
+// and when updates are disabled, nothing is active and the rover runs on
+// its built-in assets.
+pub struct AssetStore {
+    store: RwLock<Option<Store>>,
+}
+
+impl AssetStore {
+    fn new() -> Self {
+        Self { store: RwLock::new(None) }
+    }
+
+    // An active version whose file has gone is dropped, so the bridge
+    // falls back to the built-in asset rather than fail to start.
+    pub fn open(&self, config: &UpdatesConfig) -> Result<()> {
+        if !config.enabled {
+            return Ok(());
+        }
+        let dir = PathBuf::from(&config.directory);
+        let mut store = Store {
+            dir: dir.clone(),
+            max_bytes: config.max_upload_mb * 1024 * 1024,
+            keep_versions: config.keep_versions,
+            manifests: BTreeMap::new(),
+        };
+        for kind in ArtifactKind::ALL {
+            let kind_dir = store.kind_dir(kind);
+            std::fs::create_dir_all(&kind_dir).with_context(|| format!("Failed to create {}", kind_dir.display()))?;
+            let path = kind_dir.join(MANIFEST);
+            let mut manifest: ArtifactManifest = match std::fs::read(&path) {
+                Ok(bytes) => serde_json::from_slice(&bytes).with_context(|| format!("Invalid manifest {}", path.display()))?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => ArtifactManifest::default(),
+                Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+            };
+            let missing = manifest.active.as_deref()
+                .and_then(|active| manifest.get_version(active))
+                .is_some_and(|version| !store.path_of(kind, version).is_file());
+            if missing {
+                warn!(target: "updates", "Active {} {} is missing, using the built-in one", kind.as_str(), manifest.active.as_deref().unwrap_or_default());
+                manifest.active = None;
+            }
+            store.manifests.insert(kind, manifest);
+        }
+        for (kind, manifest) in &store.manifests {
+            if let Some(active) = &manifest.active {
+                info!(target: "updates", "Using uploaded {} {}", kind.as_str(), active);
+            }
+        }
+        *self.store.write() = Some(store);
+        info!(target: "updates", "Asset updates stored in {}", dir.display());
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.store.read().is_some()
+    }
+
+    pub fn get_active_path(&self, kind: ArtifactKind) -> Option<PathBuf> {
+        let store = self.store.read();
+        let store = store.as_ref()?;
+        let manifest = store.manifests.get(&kind)?;
+        let version = manifest.get_version(manifest.active.as_deref()?)?;
+        Some(store.path_of(kind, version))
+    }
+
+    pub fn get_manifests(&self) -> Option<BTreeMap<ArtifactKind, ArtifactManifest>> {
+        self.store.read().as_ref().map(|store| store.manifests.clone())
+    }
+
+    // Checks the name, size, checksum and contents, then stages the file
+    // as a new version without activating it. Uploading a file that is
+    // already staged returns that version.
+    pub fn stage(&self, kind: ArtifactKind, file_name: &str, sha256: &str, bytes: &[u8]) -> Result<AssetVersion, AssetError> {
+        let mut guard = self.store.write();
+        let store = guard.as_mut().ok_or(AssetError::Disabled)?;
+        check_file_name(kind, file_name).map_err(AssetError::Invalid)?;
+        if bytes.len() as u64 > store.max_bytes {
+            return Err(AssetError::Invalid(format!("{} bytes is over the {} byte limit", bytes.len(), store.max_bytes)));
+        }
+        let actual = hex_digest(bytes);
+        if !actual.eq_ignore_ascii_case(sha256.trim()) {
+            return Err(AssetError::Invalid(format!("sha256 mismatch: the upload's is {}", actual)));
+        }
+        check_contents(kind, bytes).map_err(AssetError::Invalid)?;
+        let manifest = store.manifests.entry(kind).or_default();
+        if let Some(existing) = manifest.versions.iter().find(|v| v.sha256 == actual && v.file_name == file_name) {
+            return Ok(existing.clone());
+        }
+
+        let now = chrono::Utc::now();
+        let version = AssetVersion {
+            version: format!("{}-{}", now.format("%Y%m%d-%H%M%S"), &actual[..8]),
+            file_name: file_name.to_string(),
+            sha256: actual,
+            size: bytes.len() as u64,
+            uploaded_at: now.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        };
+        let path = store.path_of(kind, &version);
+        write_staged(&path, bytes)?;
+        if kind == ArtifactKind::Labels {
+            if let Err(e) = LabelMap::load(&path) {
+                let _ = std::fs::remove_dir_all(path.parent().unwrap_or(&path));
+                return Err(AssetError::Invalid(format!("{:#}", e)));
+            }
+        }
+        store.manifests.entry(kind).or_default().versions.push(version.clone());
+        store.prune(kind);
+        store.save(kind)?;
+
+        info!(target: "updates", "Staged {} {} ({}, {} bytes)", kind.as_str(), version.version, version.file_name, version.size);
+        EVENTS.publish(
+            Severity::Info,
+            "assets",
+            format!("Uploaded {} {}", kind.as_str(), version.version),
+            serde_json::json!({ "kind": kind, "version": version }),
+        );
+        Ok(version)
+    }
+
+    // The version that was active becomes the previous one. Returns the
+    // updated manifest.
+    pub fn activate(&self, kind: ArtifactKind, version: &str) -> Result<ArtifactManifest, AssetError> {
+        let mut guard = self.store.write();
+        let store = guard.as_mut().ok_or(AssetError::Disabled)?;
+        let manifest = store.manifests.entry(kind).or_default();
+        if manifest.get_version(version).is_none() {
+            return Err(AssetError::NotFound(format!("no {} version {}", kind.as_str(), version)));
+        }
+        if manifest.active.as_deref() == Some(version) {
+            return Ok(manifest.clone());
+        }
+        manifest.previous = manifest.active.replace(version.to_string());
+        let manifest = manifest.clone();
+        store.save(kind)?;
+
+        info!(target: "updates", "Activated {} {}", kind.as_str(), version);
+        EVENTS.publish(
+            Severity::Warning,
+            "assets",
+            format!("Activated {} {}", kind.as_str(), version),
+            serde_json::json!({ "kind": kind, "active": manifest.active, "previous": manifest.previous }),
+        );
+        Ok(manifest)
+    }
+
+    // Swaps the active and previous versions, so a second rollback undoes
+    // the first.
+    pub fn rollback(&self, kind: ArtifactKind) -> Result<ArtifactManifest, AssetError> {
+        let mut guard = self.store.write();
+        let store = guard.as_mut().ok_or(AssetError::Disabled)?;
+        let manifest = store.manifests.entry(kind).or_default();
+        if manifest.active == manifest.previous {
+            return Err(AssetError::NotFound(format!("no previous {} to roll back to", kind.as_str())));
+        }
+        std::mem::swap(&mut manifest.active, &mut manifest.previous);
+        let manifest = manifest.clone();
+        store.save(kind)?;
+
+        let active = manifest.active.as_deref().unwrap_or("built-in");
+        info!(target: "updates", "Rolled {} back to {}", kind.as_str(), active);
+        EVENTS.publish(
+            Severity::Warning,
+            "assets",
+            format!("Rolled {} back to {}", kind.as_str(), active),
+            serde_json::json!({ "kind": kind, "active": manifest.active, "previous": manifest.previous }),
+        );
+        Ok(manifest)
+    }
+}
+
+// A plain name with the kind's extension, so it can't climb out of its
+// version directory or be hidden.
+fn check_file_name(kind: ArtifactKind, name: &str) -> Result<(), String> {
+    let plain = !name.is_empty()
+        && name.len() <= MAX_FILE_NAME
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if !plain {
+        return Err(format!("filename must be at most {} of A-Z, a-z, 0-9, '.', '_' and '-', not starting with '.'", MAX_FILE_NAME));
+    }
+    let extension = Path::new(name).extension().and_then(|e| e.to_str()).unwrap_or_default();
+    if !kind.extensions().contains(&extension) {
+        return Err(format!("a {} must end in .{}", kind.as_str(), kind.extensions().join(" or .")));
+    }
+    Ok(())
+}
+
+fn check_contents(kind: ArtifactKind, bytes: &[u8]) -> Result<(), String> {
+    match kind {
+        ArtifactKind::BridgeScript => std::str::from_utf8(bytes).map(|_| ()).map_err(|_| "bridge script is not UTF-8".to_string()),
+        ArtifactKind::ModelConfig => serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(bytes)
+            .map(|_| ())
+            .map_err(|e| format!("model config is not a JSON object: {}", e)),
+        // Parsed as a label map once it is on disk.
+        ArtifactKind::Labels => Ok(()),
+    }
+}
+
+fn write_staged(path: &Path, bytes: &[u8]) -> Result<()> {
+    let dir = path.parent().context("Staged path has no directory")?;
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, bytes).with_context(|| format!("Failed to write {}", partial.display()))?;
+    std::fs::rename(&partial, path).with_context(|| format!("Failed to move {} into place", path.display()))
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
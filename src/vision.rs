@@ -1,29 +1,77 @@
 // src/vision.rs
-use anyhow::Result;
+use anyhow::{Context, Result};
+use base64::Engine;
+use opencv_embedded::core::{self as cv_core, Mat, Scalar, Size};
+use opencv_embedded::{imgcodecs, imgproc};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use parking_lot::RwLock;
-use std::process::{Command, Stdio};
-use std::io::{BufReader, BufRead};
+use std::process::{ChildStdin, Command, Stdio};
+use std::io::{BufReader, BufRead, Write};
 use std::thread;
-use crossbeam_channel::{Receiver, unbounded};  // crossbeam instead of std::sync::mpsc
+use crossbeam_channel::{Receiver, Sender, unbounded};  // crossbeam instead of std::sync::mpsc
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tracing::{debug, debug_span, info, warn};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Detection {
-    pub class_name: String,
-    pub confidence: f32,
-    pub bbox: (i32, i32, i32, i32),
-    pub distance_estimate: f32,
-    pub action: NavigationAction,
-}
+use crate::annotations::RecordingRef;
+use crate::clock::CLOCK;
+use crate::config::{FrameTransport, PixelFormat, VisionConfig, VisionDetector, VisionProfile};
+use crate::events::{Severity, EVENTS};
+use crate::health::{HealthHandle, HealthRegistry};
+use crate::latency::FrameStamps;
+use crate::interlock::INTERLOCK;
+use crate::metrics::METRICS;
+use crate::simulation::{self, FrameSource};
+use crate::slip::{self, VisualTravel};
+use crate::thermal::THERMAL;
+use crate::updates::{ArtifactKind, ASSETS};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub enum NavigationAction {
-    Continue,
-    SlowDown,
-    Stop,
-    EmergencyStop,
-}
+pub mod camera;
+pub mod exposure;
+pub mod fiducials;
+pub mod filters;
+pub mod free_space;
+pub mod ground;
+pub mod heatmap;
+pub mod imx500_yolov8;
+pub mod labels;
+pub mod motion;
+pub mod pixels;
+pub mod profiles;
+pub mod protocol;
+pub mod rules;
+pub mod self_mask;
+pub mod shm;
+pub mod sweep;
+pub mod tap;
+pub mod types;
+
+pub use camera::CameraModel;
+pub use exposure::{ExposureControl, ExposureStatus};
+pub use fiducials::{FiducialDetection, FiducialDetector, TagCorners, TagPose};
+pub use filters::{DetectionFilter, FilterTiming, FrameContext};
+pub use free_space::{Clearance, FreeSpace, FreeSpaceEstimator};
+pub use ground::{GroundProjection, PointResidual};
+pub use heatmap::{Heatmap, HeatmapFilter, HeatmapSnapshot};
+pub use labels::{LabelInfo, LabelMap, LabelRule};
+pub use motion::{GrayFrame, GrayFrameCache};
+pub use pixels::FrameImage;
+pub use profiles::{AutoSwitch, ProfileCause, ProfileStatus};
+pub use sweep::{SweepReport, SweepStatus, ThresholdCounts, ThresholdSweep};
+pub use protocol::{BridgeHello, BridgeLine, DetectionMask, ParseMonitor};
+pub use rules::{Rule, RuleError, RuleSet, RuleWorld};
+pub use self_mask::{MaskProposal, MaskProposer, ProposedRegion, SelfMask};
+pub use shm::{RingInfo, SharedFrame, ShmRing, SlotRef};
+pub use tap::{BridgeTap, TapLevel, TapSummary, BRIDGE_TAP};
+pub use types::{ActionPolicy, ConfidenceCurve, Detection, DistanceEstimator, NavigationAction};
+
+pub const BRIDGE_SCRIPT: &str = "src/vision_bridge.py";
+
+// The bridge always delivers 640x480 frames.
+pub const FRAME_WIDTH_PX: f32 = 640.0;
+pub const FRAME_HEIGHT_PX: f32 = 480.0;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct VisionTelemetry {
@@ -31,163 +79,1136 @@ pub struct VisionTelemetry {
     pub fps: f32,
     pub inference_time: f32,
     pub processing: bool,
+    // Time each detection filter took on the last frame, in pipeline order.
+    pub filters: Vec<FilterTiming>,
+    pub profile: ProfileStatus,
+    // Version of vision.ground_calibration, while there is one.
+    pub ground_calibration: Option<u32>,
+    pub exposure: ExposureStatus,
 }
 
 #[derive(Deserialize)]
-struct BridgeFrame {
-    frame_id: u32,
-    jpeg_base64: String,
-    timestamp: f64,
-    imx500_basic: Vec<IMX500Detection>,
+pub struct BridgeFrame {
+    pub frame_id: u32,
+    pub jpeg_base64: String,
+    pub timestamp: f64,
+    pub imx500_basic: Vec<IMX500Detection>,
+    // Tags the bridge decoded itself, when asked to look for them.
+    #[serde(default)]
+    pub fiducials: Vec<TagCorners>,
+    // Bridge-side monotonic capture time in seconds; older bridges omit it.
+    #[serde(default)]
+    pub capture_monotonic: Option<f64>,
+    // When the bridge wrote the line, on the same clock.
+    #[serde(default)]
+    pub emit_monotonic: Option<f64>,
+    // Mean brightness of the frame (0-255), from bridges that measure it;
+    // otherwise the rover decodes a frame now and then to find out.
+    #[serde(default)]
+    pub brightness: Option<f32>,
+    // Where the picture is instead, with the shm transport.
+    #[serde(default, rename = "shm")]
+    pub slot: Option<Box<SlotRef>>,
+    // Filled in on arrival from `slot`.
+    #[serde(skip)]
+    pub shared_frame: Option<SharedFrame>,
+    // The vision profile a replayed frame was recorded on, Some(None)
+    // being the base settings.
+    #[serde(skip)]
+    pub recorded_profile: Option<Option<String>>,
+    // Where in its recording a replayed frame was.
+    #[serde(skip)]
+    pub recorded: Option<Box<RecordingRef>>,
+    // Filled in on arrival: the capture time on the rover clock.
+    #[serde(skip)]
+    pub capture_ms: u64,
+    #[serde(skip)]
+    pub stamps: FrameStamps,
 }
 
-#[derive(Deserialize)]
-struct IMX500Detection {
-    class: String,
-    conf: f32,
-    x: i32,
-    y: i32,
-    w: i32,
-    h: i32,
+#[derive(Debug, Clone, Deserialize)]
+pub struct IMX500Detection {
+    // Empty when the bridge only sends the index.
+    #[serde(default)]
+    pub class: String,
+    #[serde(default)]
+    pub class_id: Option<u32>,
+    pub conf: f32,
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+    #[serde(default)]
+    pub mask: Option<DetectionMask>,
+}
+
+// Every frame source feeds the vision system through one of these, so the
+// control loop can wake as soon as a frame lands instead of polling.
+#[derive(Clone)]
+pub struct FrameSender {
+    tx: Sender<BridgeFrame>,
+    notify: Arc<Notify>,
+}
+
+impl FrameSender {
+    // False once the vision system is gone.
+    pub fn send(&self, frame: BridgeFrame) -> bool {
+        let sent = self.tx.send(frame).is_ok();
+        self.notify.notify_one();
+        sent
+    }
+}
+
+// The bridge process, replaced whenever the reader restarts it. None once
+// the vision system is dropped, so the reader knows to stop.
+type BridgeProcess = Arc<parking_lot::Mutex<Option<std::process::Child>>>;
+
+enum BridgeControl {
+    // A new bridge process's stdin.
+    Stdin(ChildStdin),
+    // Only the latest line of each kind matters, and a new bridge is sent
+    // all of them.
+    Line(&'static str, String),
+    // For the bridge running now only, such as a frame ring ack.
+    Once(String),
 }
 
 pub struct VisionSystem {
-    bridge_process: Option<std::process::Child>,
+    bridge_process: Option<BridgeProcess>,
+    // Set just before the bridge is killed to restart it, so the reader
+    // starts another instead of failing.
+    bridge_restart: Arc<AtomicBool>,
+    // The running bridge offered set_exposure in its hello.
+    bridge_exposure: Arc<AtomicBool>,
+    // Control lines for the bridge's stdin; None without a bridge.
+    bridge_control: Option<Sender<BridgeControl>>,
+    bridge_health: HealthHandle,
     frame_receiver: Receiver<BridgeFrame>,  // crossbeam channel
+    frame_notify: Arc<Notify>,
+    frame_sender: FrameSender,
     last_detections: Arc<RwLock<Vec<Detection>>>,
-    last_frame_base64: Arc<RwLock<String>>,
+    // The last new frame's detections that vision.self_mask took out.
+    last_self_occluded: Arc<RwLock<Vec<Detection>>>,
+    // And the ones under min_confidence a threshold sweep kept.
+    last_below_threshold: Arc<RwLock<Vec<Detection>>>,
+    last_fiducials: Arc<RwLock<Vec<FiducialDetection>>>,
+    fiducial_detector: Option<Box<dyn FiducialDetector>>,
+    last_free_space: Arc<RwLock<Option<FreeSpace>>>,
+    last_frame_image: Arc<RwLock<Arc<FrameImage>>>,
+    gray_frames: GrayFrameCache,
     frame_count: u64,
+    // The last new frame's bridge id, and where it was in its recording
+    // if it was replayed from one.
+    last_frame_id: Option<u32>,
+    last_recorded: Option<RecordingRef>,
+    // Set only on ticks that consumed a new frame.
+    frame_capture_ms: Option<u64>,
+    frame_stamps: Option<FrameStamps>,
+    // Capture time of the newest frame so far, for the staleness check.
+    newest_capture_ms: Option<u64>,
+    stale: bool,
+    // Masks are being dropped to save time.
+    skipping_masks: bool,
+    // The settings in use: base_config with the active profile laid over
+    // it.
+    config: VisionConfig,
+    base_config: VisionConfig,
+    profile: Option<String>,
+    profile_since_ms: Option<u64>,
+    // A profile chosen over the API holds until auto switching resumes.
+    auto_paused: bool,
+    auto_switch: AutoSwitch,
+    exposure: ExposureControl,
+    // Set while the rover sleeps and the bridge only sends keepalive frames.
+    keepalive_fps: Option<f32>,
+    calibration: ConfidenceCurve,
+    distance: DistanceEstimator,
+    policy: ActionPolicy,
+    free_space: FreeSpaceEstimator,
+    labels: LabelMap,
+    rules: RuleSet,
+    self_mask: SelfMask,
+    mask_proposer: MaskProposer,
+    ground: Option<GroundProjection>,
+    filters: Vec<Box<dyn DetectionFilter>>,
+    filter_timings: Vec<FilterTiming>,
+    // Shared with the heatmap filter, which fills it in.
+    heatmap: Arc<parking_lot::Mutex<Heatmap>>,
+    sweep: parking_lot::Mutex<ThresholdSweep>,
 }
 
 impl VisionSystem {
-    pub fn new() -> Result<Self> {
-        println!(" Initializing Vision System...");
-        
-        let (bridge_process, frame_receiver) = Self::start_camera_bridge()?;
-        println!("  Vision bridge started");
-        
-        Ok(Self {
-            bridge_process: Some(bridge_process),
+    pub fn new(health: &HealthRegistry, config: VisionConfig, source: FrameSource) -> Result<Self> {
+        let mut vision = Self::unstarted(health, config);
+        vision.start(source)?;
+        Ok(vision)
+    }
+
+    // Everything but the label map, the rules and the frame source, which
+    // start() adds, so a rover whose camera won't start still has a vision
+    // system for the web server and control loop to hold.
+    pub fn unstarted(health: &HealthRegistry, config: VisionConfig) -> Self {
+        let bridge_health = health.register("vision_bridge", Some(Duration::from_secs(2)));
+        let (tx, frame_receiver) = unbounded();
+        let frame_notify = Arc::new(Notify::new());
+        let frame_sender = FrameSender { tx, notify: frame_notify.clone() };
+        let base_config = config.clone();
+        let profile = config.profile.clone();
+        let config = profiles::overlay(&base_config, get_profile(&base_config, profile.as_deref()));
+        let labels = LabelMap::default();
+        let heatmap = Arc::new(parking_lot::Mutex::new(Heatmap::new(&config.heatmap)));
+        Self {
+            bridge_process: None,
+            bridge_restart: Arc::new(AtomicBool::new(false)),
+            bridge_exposure: Arc::new(AtomicBool::new(false)),
+            bridge_control: None,
+            bridge_health,
             frame_receiver,
+            frame_notify,
+            frame_sender,
             last_detections: Arc::new(RwLock::new(Vec::new())),
-            last_frame_base64: Arc::new(RwLock::new(String::new())),
+            last_self_occluded: Arc::new(RwLock::new(Vec::new())),
+            last_below_threshold: Arc::new(RwLock::new(Vec::new())),
+            last_fiducials: Arc::new(RwLock::new(Vec::new())),
+            fiducial_detector: None,
+            last_free_space: Arc::new(RwLock::new(None)),
+            last_frame_image: Arc::new(RwLock::new(Arc::new(FrameImage::none()))),
+            gray_frames: GrayFrameCache::new(&config),
             frame_count: 0,
-        })
+            last_frame_id: None,
+            last_recorded: None,
+            frame_capture_ms: None,
+            frame_stamps: None,
+            newest_capture_ms: None,
+            stale: false,
+            skipping_masks: false,
+            calibration: ConfidenceCurve::new(&config, config.detector.source_name()),
+            distance: DistanceEstimator::new(&config).with_labels(&labels),
+            policy: ActionPolicy::new(&config).with_labels(&labels),
+            free_space: FreeSpaceEstimator::new(&config),
+            labels,
+            rules: RuleSet::default(),
+            self_mask: SelfMask::new(&config),
+            mask_proposer: MaskProposer::default(),
+            ground: GroundProjection::new(&config),
+            filters: filters::builtin_filters(&config, &heatmap),
+            filter_timings: Vec::new(),
+            heatmap,
+            sweep: parking_lot::Mutex::new(ThresholdSweep::new(&config.sweep)),
+            config,
+            base_config,
+            profile_since_ms: profile.as_ref().map(|_| CLOCK.wall_ms()),
+            profile,
+            auto_paused: false,
+            auto_switch: AutoSwitch::new(),
+            exposure: ExposureControl::new(),
+            keepalive_fps: None,
+        }
+    }
+
+    // Loads the label map and rules, which must load here, and starts
+    // frames coming from `source`. Can be called again after it failed.
+    pub fn start(&mut self, source: FrameSource) -> Result<()> {
+        info!(target: "vision", "Initializing Vision System...");
+        let labels = load_label_map(&self.config)?;
+        let rules = load_rules(&self.config).map_err(|errors| anyhow::anyhow!("Invalid rules: {}", describe_rule_errors(&errors)))?;
+        self.distance = DistanceEstimator::new(&self.config).with_labels(&labels);
+        self.policy = ActionPolicy::new(&self.config).with_labels(&labels);
+        self.labels = labels;
+        self.rules = rules;
+
+        let frames = self.frame_sender.clone();
+        let health = self.bridge_health.clone();
+        match source {
+            FrameSource::Bridge => {
+                let control = spawn_control_writer();
+                if let Some(settings) = get_profile(&self.base_config, self.profile.as_deref()) {
+                    let _ = control.send(BridgeControl::Line("camera", profiles::camera_control(self.profile.as_deref(), &settings.camera)));
+                }
+                let process = Self::start_camera_bridge(frames, health, &self.config, self.bridge_restart.clone(), self.bridge_exposure.clone(), control.clone())?;
+                self.bridge_control = Some(control);
+                self.bridge_process = Some(process);
+                info!(target: "vision", "Vision bridge started");
+            }
+            #[cfg(feature = "sim")]
+            FrameSource::Scenario(scenario) => {
+                simulation::spawn_scenario(scenario, frames, health);
+                info!(target: "vision", "Simulated scenario started");
+            }
+            FrameSource::Recording { path, speed } => {
+                simulation::spawn_recording(path, speed, frames, health)?;
+                info!(target: "vision", "Recording playback started");
+            }
+            FrameSource::Manual => {}
+        }
+
+        if let Some(profile) = &self.profile {
+            info!(target: "vision", "Vision profile {} active", profile);
+        }
+        Ok(())
+    }
+
+    // A change of vision.profile switches to it; an active profile that
+    // is no longer configured falls back to the base settings.
+    pub fn apply_config(&mut self, config: VisionConfig) {
+        let previous = std::mem::replace(&mut self.base_config, config);
+        if self.base_config.profile != previous.profile {
+            let profile = self.base_config.profile.clone();
+            self.switch_profile(profile, ProfileCause::Config);
+        } else if self.profile.as_ref().is_some_and(|p| !self.base_config.profiles.contains_key(p)) {
+            warn!(target: "vision", "Vision profile {} is no longer configured", self.profile.as_deref().unwrap_or_default());
+            self.switch_profile(None, ProfileCause::Config);
+        } else {
+            let edited = self.profile.as_ref().is_some_and(|p| self.base_config.profiles.get(p) != previous.profiles.get(p));
+            self.rebuild_config();
+            if edited {
+                self.send_camera_settings();
+            }
+        }
+    }
+
+    fn rebuild_config(&mut self) {
+        let config = profiles::overlay(&self.base_config, get_profile(&self.base_config, self.profile.as_deref()));
+        self.apply_settings(config);
+    }
+
+    // A label map that fails to load leaves the previous one in place.
+    fn apply_settings(&mut self, config: VisionConfig) {
+        match load_label_map(&config) {
+            Ok(labels) => self.labels = labels,
+            Err(e) => warn!(target: "vision", "Keeping the current label map: {:#}", e),
+        }
+        match load_rules(&config) {
+            Ok(rules) => self.rules = rules,
+            Err(errors) => warn!(target: "vision", "Keeping the current rules: {}", describe_rule_errors(&errors)),
+        }
+        self.calibration = ConfidenceCurve::new(&config, config.detector.source_name());
+        self.distance = DistanceEstimator::new(&config).with_labels(&self.labels);
+        self.policy = ActionPolicy::new(&config).with_labels(&self.labels);
+        self.free_space = FreeSpaceEstimator::new(&config);
+        self.gray_frames.apply_config(&config);
+        self.self_mask = SelfMask::new(&config);
+        self.ground = GroundProjection::new(&config);
+        self.sweep.get_mut().apply_config(&config.sweep);
+        for filter in &mut self.filters {
+            filter.apply_config(&config);
+        }
+        self.config = config;
+    }
+
+    // Picks up newly activated assets: the label map now, the rest with
+    // the bridge restart. False when there is no bridge to restart.
+    pub fn apply_assets(&mut self) -> bool {
+        self.rebuild_config();
+        self.restart_bridge()
+    }
+
+    // Makes `name` the active profile, None being the base settings, and
+    // tells the bridge. False if it already was.
+    pub fn set_profile(&mut self, name: Option<&str>, cause: ProfileCause) -> Result<bool> {
+        if let Some(name) = name {
+            if !self.base_config.profiles.contains_key(name) {
+                anyhow::bail!("no vision profile named {}", name);
+            }
+        }
+        Ok(self.switch_profile(name.map(str::to_string), cause))
+    }
+
+    fn switch_profile(&mut self, name: Option<String>, cause: ProfileCause) -> bool {
+        if self.profile == name {
+            return false;
+        }
+        let previous = std::mem::replace(&mut self.profile, name);
+        self.profile_since_ms = Some(CLOCK.wall_ms());
+        self.rebuild_config();
+        self.send_camera_settings();
+
+        let shown = self.profile.as_deref().unwrap_or("base");
+        METRICS.vision_profile_switches.inc(shown);
+        let brightness = self.auto_switch.get_brightness();
+        let message = match (cause, brightness) {
+            (ProfileCause::Auto, Some(brightness)) => format!("Switched to vision profile {} (brightness {:.0})", shown, brightness),
+            _ => format!("Switched to vision profile {} ({})", shown, cause.as_str()),
+        };
+        info!(target: "vision", "{}", message);
+        EVENTS.publish(Severity::Info, "vision", message, serde_json::json!({
+            "profile": self.profile,
+            "previous": previous,
+            "cause": cause,
+            "brightness": brightness,
+        }));
+        true
+    }
+
+    // Some slows the bridge to a keepalive rate while the rover sleeps;
+    // None brings it back to full rate.
+    pub fn set_keepalive(&mut self, fps: Option<f32>) {
+        self.keepalive_fps = fps;
+        let Some(control) = &self.bridge_control else { return };
+        let line = serde_json::json!({
+            "type": "pause",
+            "paused": fps.is_some(),
+            "keepalive_fps": fps,
+        });
+        let _ = control.send(BridgeControl::Line("pause", line.to_string()));
+    }
+
+    fn send_camera_settings(&self) {
+        let Some(control) = &self.bridge_control else { return };
+        let camera = get_profile(&self.base_config, self.profile.as_deref()).map(|p| p.camera.clone()).unwrap_or_default();
+        let _ = control.send(BridgeControl::Line("camera", profiles::camera_control(self.profile.as_deref(), &camera)));
+    }
+
+    // Pausing keeps the current profile; resuming lets the next
+    // brightness sample decide again.
+    pub fn set_auto_profile_paused(&mut self, paused: bool) {
+        if self.auto_paused != paused {
+            self.auto_paused = paused;
+            info!(target: "vision", "Automatic vision profile switching {}", if paused { "paused" } else { "resumed" });
+        }
+    }
+
+    pub fn get_profile_status(&self) -> ProfileStatus {
+        ProfileStatus {
+            active: self.profile.clone(),
+            auto: self.base_config.auto_profile.enabled && !self.auto_paused,
+            brightness: self.auto_switch.get_brightness(),
+            since_ms: self.profile_since_ms,
+        }
+    }
+
+    pub fn get_profiles(&self) -> std::collections::BTreeMap<String, VisionProfile> {
+        self.base_config.profiles.clone()
+    }
+
+    // Replayed frames go back onto the profile they were recorded on, so
+    // their detections are judged as they were then. Otherwise the
+    // brightness is sampled for auto switching.
+    fn update_profile(&mut self, frame: &BridgeFrame, image: &FrameImage, gray: Option<&Mat>) {
+        if let Some(recorded) = &frame.recorded_profile {
+            let known = recorded.as_ref().map_or(true, |name| self.base_config.profiles.contains_key(name));
+            if known {
+                self.switch_profile(recorded.clone(), ProfileCause::Recording);
+            }
+            return;
+        }
+        let auto = &self.base_config.auto_profile;
+        if !auto.enabled || !self.auto_switch.is_due(auto, frame.capture_ms) {
+            return;
+        }
+        let measured = match (frame.brightness, gray) {
+            (Some(brightness), _) => Ok(Some(brightness)),
+            (None, Some(gray)) => mean_brightness(gray).map(Some),
+            (None, None) if image.is_empty() => Ok(None),
+            (None, None) => gray_frame(image).and_then(|gray| mean_brightness(&gray)).map(Some),
+        };
+        let brightness = match measured {
+            Ok(Some(brightness)) => brightness,
+            Ok(None) => return,
+            Err(e) => {
+                debug!(target: "vision", "No brightness sample: {:#}", e);
+                return;
+            }
+        };
+        METRICS.vision_brightness.set(brightness as f64);
+        let target = self.auto_switch.sample(auto, brightness, frame.capture_ms, self.profile.as_deref());
+        if let (Some(target), false) = (target, self.auto_paused) {
+            self.switch_profile(Some(target), ProfileCause::Auto);
+        }
+    }
+
+    pub fn get_exposure_status(&self) -> ExposureStatus {
+        self.exposure.get_status(self.bridge_exposure.load(Ordering::SeqCst), self.config.auto_exposure.enabled)
+    }
+
+    // Only live frames from a bridge that takes set_exposure, so the loop
+    // stays inert otherwise.
+    fn is_exposure_due(&self) -> bool {
+        let config = &self.config.auto_exposure;
+        config.enabled
+            && self.bridge_exposure.load(Ordering::SeqCst)
+            && self.last_recorded.is_none()
+            && self.frame_capture_ms.is_some_and(|now_ms| self.exposure.is_due(config, now_ms))
+    }
+
+    fn update_exposure(&mut self, frame: &BridgeFrame, gray: Option<&Mat>) {
+        if !self.is_exposure_due() {
+            // Switched off, or a bridge without the capability took over,
+            // which starts from its normal exposure anyway.
+            let active = self.config.auto_exposure.enabled && self.bridge_exposure.load(Ordering::SeqCst);
+            if !active && self.exposure.reset(CLOCK.wall_ms()) {
+                self.send_exposure(0.0);
+            }
+            return;
+        }
+        let Some(gray) = gray else { return };
+        let config = &self.config.auto_exposure;
+        let clipped = match exposure::luminance_histogram(gray, config.roi) {
+            Ok(histogram) => exposure::clipped_fraction(&histogram, config.clip_level),
+            Err(e) => {
+                debug!(target: "vision", "No exposure sample: {:#}", e);
+                return;
+            }
+        };
+        METRICS.vision_clipped_fraction.set(clipped as f64);
+        if let Some(ev) = self.exposure.sample(config, clipped, frame.capture_ms, CLOCK.wall_ms()) {
+            info!(target: "vision", "Exposure offset {:+.1} EV ({:.0}% clipped)", ev, clipped * 100.0);
+            self.send_exposure(ev);
+        }
+    }
+
+    fn send_exposure(&self, ev: f32) {
+        METRICS.vision_exposure_ev.set(ev as f64);
+        let Some(control) = &self.bridge_control else { return };
+        let _ = control.send(BridgeControl::Line("exposure", exposure::exposure_control(ev)));
+    }
+
+    pub fn restart_bridge(&self) -> bool {
+        let Some(process) = &self.bridge_process else { return false };
+        let mut guard = process.lock();
+        let Some(child) = guard.as_mut() else { return false };
+        self.bridge_restart.store(true, Ordering::SeqCst);
+        let _ = child.kill();
+        true
     }
     
-    fn start_camera_bridge() -> Result<(std::process::Child, Receiver<BridgeFrame>)> {
-        let mut child = Command::new("python3")
-            .arg("src/vision_bridge.py")
+    // Tags need OpenCV's aruco module on the bridge side, so looking for
+    // them is opt-in and fixed for the life of the bridge. So is whether
+    // it sends class indices, which it does when there is a label map to
+    // name them with. An uploaded bridge script takes the place of
+    // BRIDGE_SCRIPT in the command, and an uploaded model config is passed
+    // on in SCOUT_POST_PROCESS_FILE.
+    fn spawn_bridge(config: &VisionConfig) -> Result<std::process::Child> {
+        let flag = |on: bool| if on { "1" } else { "0" };
+        let versions: Vec<String> = protocol::SUPPORTED_VERSIONS.iter().map(|v| v.to_string()).collect();
+        let (program, args) = config.bridge_command.split_first().context("vision.bridge_command is empty")?;
+        let script = ASSETS.get_active_path(ArtifactKind::BridgeScript);
+        let args = args.iter().map(|arg| match &script {
+            Some(path) if arg == BRIDGE_SCRIPT => path.clone().into_os_string(),
+            _ => arg.into(),
+        });
+        let mut command = Command::new(program);
+        if let Some(model_config) = ASSETS.get_active_path(ArtifactKind::ModelConfig) {
+            command.env("SCOUT_POST_PROCESS_FILE", model_config);
+        }
+        command
+            .args(args)
+            .env("SCOUT_FIDUCIALS", flag(config.fiducials_enabled))
+            .env("SCOUT_CLASS_INDICES", flag(label_map_path(config, VisionDetector::Imx500.source_name()).is_some()))
+            .env("SCOUT_PROTOCOL_VERSIONS", versions.join(","))
+            .env("SCOUT_IR_CUT_PIN", config.ir_cut_pin.map(|pin| pin.to_string()).unwrap_or_default())
+            .env("SCOUT_FRAME_TRANSPORT", config.frame_transport.as_str())
+            .env("SCOUT_FRAME_SLOTS", config.frame_slots.to_string())
+            .env("SCOUT_FRAME_SLOT_BYTES", (config.frame_slot_kb * 1024).to_string())
+            .env("SCOUT_PIXEL_FORMAT", config.pixel_format.as_str())
+            .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::inherit())
-            .spawn()?;
-            
-        let stdout = child.stdout.take()
+            .spawn()
+            .with_context(|| format!("Failed to start the vision bridge ({})", program))
+    }
+
+    // Reads the bridge until its output stops. A bridge whose lines
+    // mostly fail to parse is restarted; one that speaks a protocol
+    // version the rover doesn't is stopped, since another start won't
+    // change that. A bridge killed by restart_bridge is started again.
+    fn start_camera_bridge(
+        frames: FrameSender,
+        health: HealthHandle,
+        config: &VisionConfig,
+        restart: Arc<AtomicBool>,
+        exposure: Arc<AtomicBool>,
+        control: Sender<BridgeControl>,
+    ) -> Result<BridgeProcess> {
+        let mut child = Self::spawn_bridge(config)?;
+        let mut stdout = child.stdout.take()
             .ok_or_else(|| anyhow::anyhow!("Failed to capture stdout"))?;
-            
-        let (tx, rx) = unbounded();  // crossbeam channel
-        
+        if let Some(stdin) = child.stdin.take() {
+            let _ = control.send(BridgeControl::Stdin(stdin));
+        }
+        let process: BridgeProcess = Arc::new(parking_lot::Mutex::new(Some(child)));
+        let config = config.clone();
+
+        let bridge = process.clone();
         thread::spawn(move || {
-            let reader = BufReader::new(stdout);
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    if let Ok(frame_data) = serde_json::from_str::<BridgeFrame>(&line) {
-                        let _ = tx.send(frame_data);
+            let mut monitor = ParseMonitor::new(Duration::from_secs_f32(config.bridge_parse_window_s));
+            loop {
+                let outcome = read_bridge(BufReader::new(stdout), &frames, &health, &mut monitor, &config, &exposure, &control);
+                let mut guard = bridge.lock();
+                let Some(mut child) = guard.take() else { return };
+                let _ = child.kill();
+                let _ = child.wait();
+                match outcome {
+                    BridgeOutcome::Closed if restart.swap(false, Ordering::SeqCst) => {
+                        METRICS.bridge_restarts.inc();
+                        info!(target: "vision", "Restarting the vision bridge");
+                    }
+                    BridgeOutcome::Closed => {
+                        health.fail("Vision bridge stdout closed");
+                        EVENTS.publish(Severity::Critical, "vision", "Vision bridge stopped producing output", serde_json::Value::Null);
+                        return;
+                    }
+                    BridgeOutcome::Unsupported(version) => {
+                        let message = format!("Vision bridge speaks protocol v{}, the rover supports {:?}", version, protocol::SUPPORTED_VERSIONS);
+                        health.fail(message.clone());
+                        EVENTS.publish(Severity::Critical, "vision", message, serde_json::json!({ "version": version }));
+                        return;
+                    }
+                    BridgeOutcome::Failing(rate) => {
+                        METRICS.bridge_restarts.inc();
+                        EVENTS.publish(
+                            Severity::Warning,
+                            "vision",
+                            format!("Restarting the vision bridge: {:.0}% of its lines failed to parse", rate * 100.0),
+                            serde_json::json!({ "failure_rate": rate }),
+                        );
+                    }
+                }
+                monitor.clear();
+                restart.store(false, Ordering::SeqCst);
+                METRICS.bridge_protocol_version.set(0);
+                let restarted = Self::spawn_bridge(&config)
+                    .and_then(|mut child| {
+                        let stdout = child.stdout.take().ok_or_else(|| anyhow::anyhow!("Failed to capture stdout"))?;
+                        if let Some(stdin) = child.stdin.take() {
+                            let _ = control.send(BridgeControl::Stdin(stdin));
+                        }
+                        Ok((child, stdout))
+                    });
+                match restarted {
+                    Ok((child, restarted_stdout)) => {
+                        *guard = Some(child);
+                        stdout = restarted_stdout;
+                    }
+                    Err(e) => {
+                        health.fail(format!("Failed to restart the vision bridge: {:#}", e));
+                        EVENTS.publish(Severity::Critical, "vision", format!("Failed to restart the vision bridge: {:#}", e), serde_json::Value::Null);
+                        return;
                     }
                 }
             }
         });
-        
-        Ok((child, rx))
+
+        Ok(process)
     }
     
     pub fn process_frame(&mut self) -> Result<Vec<Detection>> {
+        simulation::chaos_point("vision");
         self.frame_count += 1;
+        let _frame = debug_span!(target: "vision", "frame", n = self.frame_count).entered();
         
-        let frame_data = match self.frame_receiver.try_recv() {
+        let mut frame_data = match self.frame_receiver.try_recv() {
             Ok(data) => data,
-            Err(_) => return Ok(self.last_detections.read().clone()),  // read() not lock()
+            Err(_) => {
+                self.frame_capture_ms = None;
+                self.frame_stamps = None;
+                self.check_staleness();
+                return Ok(self.last_detections.read().clone());  // read() not lock()
+            }
         };
+        self.frame_capture_ms = Some(frame_data.capture_ms);
+        self.frame_stamps = Some(frame_data.stamps);
+        self.last_frame_id = Some(frame_data.frame_id);
+        self.last_recorded = frame_data.recorded.take().map(|r| *r);
+        self.newest_capture_ms = Some(self.newest_capture_ms.unwrap_or(0).max(frame_data.capture_ms));
+        self.check_staleness();
+
+        // The web and recordings still want base64, so a JPEG is copied
+        // out of its slot, encoded and kept to decode from. A raw frame is
+        // copied out and only converted for what wants it. A frame whose
+        // slot was rewritten meanwhile goes on without a picture.
+        let shared = frame_data.shared_frame.take();
+        let mut slot_jpeg = None;
+        let image = match &shared {
+            Some(shared) => match shared.read() {
+                Ok(bytes) => match picture_from_slot(shared.get_slot(), bytes) {
+                    Ok((image, jpeg)) => {
+                        slot_jpeg = jpeg;
+                        image
+                    }
+                    Err(e) => {
+                        warn!(target: "vision", "Dropped the picture of frame {}: {:#}", frame_data.frame_id, e);
+                        FrameImage::none()
+                    }
+                },
+                Err(e) => {
+                    METRICS.bridge_shm_torn.inc();
+                    warn!(target: "vision", "Dropped the picture of frame {}: {:#}", frame_data.frame_id, e);
+                    FrameImage::none()
+                }
+            },
+            None => FrameImage::from_jpeg_base64(std::mem::take(&mut frame_data.jpeg_base64)),
+        };
+        // Copied out, so the bridge can have the slot back.
+        drop(shared);
+
+        // Without the picture the blob detector has nothing to go on; the
+        // rest just skip this frame.
+        let colour = match self.decode_image(&image, slot_jpeg.as_deref()) {
+            Ok(colour) => colour,
+            Err(e) if self.config.detector == VisionDetector::Blob => return Err(e),
+            Err(e) => {
+                warn!(target: "vision", "{:#}", e);
+                None
+            }
+        };
+        let gray = self.gray_image(&image, colour.as_ref());
+        // Before any detection is judged, so a switch applies to this frame.
+        self.update_profile(&frame_data, &image, gray.as_ref());
+        self.update_exposure(&frame_data, gray.as_ref());
+        let raw_detections = match self.config.detector {
+            VisionDetector::Imx500 => frame_data.imx500_basic,
+            VisionDetector::Blob => colour.as_ref()
+                .map(|frame| SimpleBlobObstacleDetector::new(&self.config).detect(frame))
+                .transpose()?
+                .unwrap_or_default(),
+        };
+        let fiducials = self.detect_fiducials(frame_data.fiducials, gray.as_ref(), frame_data.capture_ms);
+        *self.last_fiducials.write() = fiducials;
+        *self.last_free_space.write() = self.estimate_free_space(colour.as_ref(), frame_data.capture_ms);
+        if let (true, Some(gray)) = (self.config.gray_cache_enabled, &gray) {
+            if let Err(e) = self.gray_frames.push(gray, frame_data.capture_ms) {
+                warn!(target: "vision", "Caching the gray frame failed: {:#}", e);
+            }
+        }
+        METRICS.frames_processed.inc();
         
-        *self.last_frame_base64.write() = frame_data.jpeg_base64;
-        
+        let use_masks = self.check_mask_budget(&raw_detections);
+        let sweep_floor = self.sweep.get_mut().get_floor(CLOCK.now_ms());
         let mut all_detections = Vec::new();
-        for imx_det in &frame_data.imx500_basic {
-            let distance = self.calculate_distance(imx_det.h as f32, &imx_det.class);
-            let action = self.determine_action(&imx_det.class, distance);
-            
-            let detection = Detection {
-                class_name: imx_det.class.clone(),
-                confidence: imx_det.conf,
-                bbox: (imx_det.x, imx_det.y, imx_det.w, imx_det.h),
-                distance_estimate: distance,
-                action,
-            };
-            
-            all_detections.push(detection);
+        let mut below_threshold = Vec::new();
+        for imx_det in &raw_detections {
+            let mut detection = Detection::new(
+                self.labels.resolve(&imx_det.class, imx_det.class_id),
+                imx_det.conf,
+                (imx_det.x, imx_det.y, imx_det.w, imx_det.h),
+                frame_data.capture_ms,
+                &self.calibration,
+                &self.distance,
+                &self.policy,
+            );
+            if detection.confidence < self.config.min_confidence {
+                if !sweep_floor.is_some_and(|floor| detection.confidence >= floor) {
+                    continue;
+                }
+                detection.below_threshold = true;
+            }
+            if let (true, Some(mask)) = (use_masks, &imx_det.mask) {
+                let used = detection.set_mask(mask.clone(), &self.distance, &self.policy);
+                METRICS.mask_distances.inc(if used { "mask" } else { "box" });
+            }
+            if let Some(ground) = &self.ground {
+                detection.set_ground(ground, &self.policy);
+            }
+            if detection.below_threshold {
+                below_threshold.push(detection);
+            } else {
+                all_detections.push(detection);
+            }
+        }
+        self.mask_proposer.observe(&all_detections);
+        let (all_detections, self_occluded) = self.self_mask.split(all_detections);
+        *self.last_self_occluded.write() = self_occluded;
+        let (below_threshold, _) = self.self_mask.split(below_threshold);
+        self.sweep.get_mut().record(frame_data.capture_ms, all_detections.iter().chain(&below_threshold));
+        *self.last_below_threshold.write() = below_threshold;
+        let all_detections = self.run_filters(frame_data.capture_ms, &image, all_detections);
+        *self.last_frame_image.write() = Arc::new(image);
+        for detection in &all_detections {
+            METRICS.detections.inc(&detection.class_name);
         }
         
         *self.last_detections.write() = all_detections.clone();  // write() not lock()
         
-        println!("🔬 Running detection on frame {}", self.frame_count);
+        debug!(target: "vision", "🔬 Running detection on frame {}", self.frame_count);
         if !all_detections.is_empty() {
-            println!("  Received {} detections from vision bridge", all_detections.len());
+            debug!(target: "vision", "Received {} detections from vision bridge", all_detections.len());
         } else {
-            println!("  Frame processed in 0ms with 0 detections");
+            debug!(target: "vision", "Frame processed with 0 detections");
         }
         
         Ok(all_detections)
     }
     
-    fn calculate_distance(&self, height: f32, class_name: &str) -> f32 {
-        let object_heights = [
-            ("person", 1.7), ("car", 1.5), ("truck", 3.0),
-            ("bicycle", 1.0), ("chair", 0.8), ("bottle", 0.25),
-        ];
-        
-        let real_height = object_heights.iter()
-            .find(|(name, _)| name == &class_name)
-            .map(|(_, h)| *h)
-            .unwrap_or(0.5);
-            
-        let focal_length = 500.0;
-        if height > 0.0 {
-            (real_height * focal_length) / height
-        } else {
-            10.0
+    fn run_filters(&mut self, capture_ms: u64, image: &FrameImage, raw: Vec<Detection>) -> Vec<Detection> {
+        let frame_ctx = FrameContext {
+            frame_count: self.frame_count,
+            capture_ms,
+            image,
+            policy: &self.policy,
+        };
+        let mut detections = raw.clone();
+        self.filter_timings.clear();
+        for filter in &mut self.filters {
+            let started = Instant::now();
+            detections = filter.process(&frame_ctx, detections);
+            self.filter_timings.push(FilterTiming {
+                name: filter.name().to_string(),
+                last_ms: started.elapsed().as_secs_f32() * 1000.0,
+            });
+        }
+        let (detections, restored) = filters::enforce_safety(&raw, detections, &self.filters);
+        if restored {
+            warn!(target: "vision", "Detection filters dropped an emergency stop; restored it");
         }
+        detections
     }
-    
-    fn determine_action(&self, class_name: &str, distance: f32) -> NavigationAction {
-        match (class_name, distance) {
-            ("person", d) if d < 1.0 => NavigationAction::EmergencyStop,
-            ("person", d) if d < 2.0 => NavigationAction::Stop,
-            ("person", d) if d < 3.0 => NavigationAction::SlowDown,
-            (_, d) if d < 1.5 => NavigationAction::Stop,
-            (_, d) if d < 3.0 => NavigationAction::SlowDown,
-            _ => NavigationAction::Continue,
+
+    // Runs at `index` in the pipeline, or last if that is past the end.
+    pub fn insert_filter(&mut self, index: usize, filter: Box<dyn DetectionFilter>) {
+        info!(target: "vision", "Detection filter '{}' added", filter.name());
+        self.filters.insert(index.min(self.filters.len()), filter);
+    }
+
+    pub fn push_filter(&mut self, filter: Box<dyn DetectionFilter>) {
+        self.insert_filter(self.filters.len(), filter);
+    }
+
+    // The first filter with that name, built-in ones included.
+    pub fn remove_filter(&mut self, name: &str) -> Option<Box<dyn DetectionFilter>> {
+        let index = self.filters.iter().position(|f| f.name() == name)?;
+        info!(target: "vision", "Detection filter '{}' removed", name);
+        Some(self.filters.remove(index))
+    }
+
+    // Of one class, or of all of them with None, faded to now.
+    pub fn get_heatmap(&self, class: Option<&str>) -> HeatmapSnapshot {
+        self.heatmap.lock().snapshot(class, CLOCK.now_ms())
+    }
+
+    pub fn clear_heatmap(&self) {
+        self.heatmap.lock().clear();
+        info!(target: "vision", "Detection heatmap cleared");
+    }
+
+    pub fn get_filter_names(&self) -> Vec<String> {
+        self.filters.iter().map(|f| f.name().to_string()).collect()
+    }
+
+    // The frame in colour, decoded once for whatever needs the picture.
+    // None when nothing does or the frame has no image. A raw frame's gray
+    // is its Y plane, so it is only converted for what works in colour.
+    fn decode_image(&self, image: &FrameImage, slot_jpeg: Option<&[u8]>) -> Result<Option<Mat>> {
+        let in_colour = self.config.detector == VisionDetector::Blob || self.config.free_space_enabled;
+        let wanted = match image.get_format() {
+            Some(PixelFormat::Jpeg) => in_colour || self.is_gray_wanted(),
+            Some(PixelFormat::Yuv420) => in_colour,
+            None => false,
+        };
+        if !wanted {
+            return Ok(None);
+        }
+        if let Some((width, height, bgr)) = image.get_bgr() {
+            return Mat::from_slice_2d(height as i32, width as i32, 3, &bgr).context("Failed to convert frame image").map(Some);
+        }
+        if let Some(jpeg) = slot_jpeg {
+            return imgcodecs::imdecode(jpeg).context("Failed to decode frame image").map(Some);
+        }
+        decode_frame(&image.get_jpeg_base64()?).map(Some)
+    }
+
+    fn is_gray_wanted(&self) -> bool {
+        self.config.gray_cache_enabled || self.fiducial_detector.is_some() || self.is_exposure_due()
+    }
+
+    // None when nothing wants it or there is no picture to take it from.
+    fn gray_image(&self, image: &FrameImage, colour: Option<&Mat>) -> Option<Mat> {
+        if !self.is_gray_wanted() {
+            return None;
+        }
+        let gray = match (image.get_luma(), colour) {
+            (Some((width, height, luma)), _) => luma_mat(width, height, luma),
+            (None, Some(colour)) => to_gray(colour),
+            (None, None) => return None,
+        };
+        gray.map(Some).unwrap_or_else(|e| {
+            warn!(target: "vision", "{:#}", e);
+            None
+        })
+    }
+
+    // On top of any tags the bridge sent. A failing plug-in detector only
+    // costs the tags from this frame.
+    fn detect_fiducials(&mut self, mut tags: Vec<TagCorners>, gray: Option<&Mat>, capture_ms: u64) -> Vec<FiducialDetection> {
+        if let (Some(detector), Some(gray)) = (self.fiducial_detector.as_mut(), gray) {
+            match detector.detect(gray) {
+                Ok(found) => tags.extend(found),
+                Err(e) => warn!(target: "vision", "Fiducial detection failed: {:#}", e),
+            }
         }
+        tags.into_iter()
+            .map(|tag| FiducialDetection {
+                pose_estimate: TagPose::from_corners(&tag.corners, self.config.fiducial_tag_size_m, self.config.focal_length_px, FRAME_WIDTH_PX),
+                id: tag.id,
+                corners: tag.corners,
+                capture_ms,
+            })
+            .collect()
+    }
+
+    // Nothing when it is switched off, the frame has no image, or the
+    // estimate fails; the planner then goes by detections alone.
+    fn estimate_free_space(&self, image: Option<&Mat>, capture_ms: u64) -> Option<FreeSpace> {
+        if !self.config.free_space_enabled {
+            return None;
+        }
+        match self.free_space.estimate(image?, capture_ms) {
+            Ok(free_space) => Some(free_space),
+            Err(e) => {
+                warn!(target: "vision", "Free-space estimation failed: {:#}", e);
+                None
+            }
+        }
+    }
+
+    pub fn set_fiducial_detector(&mut self, detector: Box<dyn FiducialDetector>) {
+        self.fiducial_detector = Some(detector);
+    }
+
+    // Drops cached results after a panic; the frame source keeps running.
+    pub fn reset(&mut self) {
+        self.last_detections.write().clear();
+        self.last_self_occluded.write().clear();
+        self.last_below_threshold.write().clear();
+        self.last_fiducials.write().clear();
+        *self.last_free_space.write() = None;
+        *self.last_frame_image.write() = Arc::new(FrameImage::none());
+        self.gray_frames.clear();
+    }
+
+    // Scaled-down gray copies of the last vision.gray_cache_frames frames,
+    // oldest first. Empty with the cache switched off.
+    pub fn get_recent_gray_frames(&self) -> Vec<Arc<GrayFrame>> {
+        self.gray_frames.get_frames()
+    }
+
+    // Shift of the picture between the last two frames as (dx, dy,
+    // confidence); see motion::estimate_translation.
+    pub fn estimate_ego_motion(&self) -> Option<(f32, f32, f32)> {
+        self.gray_frames.estimate_ego_motion()
+    }
+
+    // How far the rover went between the last two frames by the camera.
+    // The shift of the whole picture is taken as the floor's halfway
+    // between the horizon and the bottom of the frame, where most of it is.
+    pub fn estimate_visual_travel(&self) -> Option<VisualTravel> {
+        let frames = self.gray_frames.get_frames();
+        let [.., prev, next] = frames.as_slice() else { return None };
+        let (_, dy, confidence) = self.gray_frames.estimate_ego_motion()?;
+        let camera = self.get_camera();
+        let shift_px = dy * FRAME_HEIGHT_PX / next.height as f32;
+        let y = (camera.horizon_row_px + FRAME_HEIGHT_PX) / 2.0;
+        Some(VisualTravel {
+            distance_m: slip::floor_travel_m(&camera, y, shift_px)?,
+            confidence,
+            from_ms: prev.capture_ms,
+            to_ms: next.capture_ms,
+        })
+    }
+
+    // Woken whenever a frame source delivers a frame.
+    pub fn get_frame_notify(&self) -> Arc<Notify> {
+        self.frame_notify.clone()
+    }
+
+    pub fn get_frame_sender(&self) -> FrameSender {
+        self.frame_sender.clone()
+    }
+
+    pub fn get_frame_capture_ms(&self) -> Option<u64> {
+        self.frame_capture_ms
+    }
+
+    // Set, like get_frame_capture_ms, only on a tick that took a new frame.
+    pub fn get_frame_stamps(&self) -> Option<FrameStamps> {
+        self.frame_stamps
+    }
+
+    pub fn get_last_frame_id(&self) -> Option<u32> {
+        self.last_frame_id
+    }
+
+    // Some while the frames are being replayed from a recording.
+    pub fn get_last_recorded(&self) -> Option<RecordingRef> {
+        self.last_recorded.clone()
+    }
+
+    pub fn has_pending_frame(&self) -> bool {
+        !self.frame_receiver.is_empty()
     }
     
+    #[deprecated(note = "read `detections` from the world state (StateHandle::get_world) instead")]
     pub fn get_last_detections(&self) -> Vec<Detection> {
         self.last_detections.read().clone()  // read() not lock()
     }
+
+    pub fn get_last_self_occluded(&self) -> Vec<Detection> {
+        self.last_self_occluded.read().clone()
+    }
+
+    pub fn get_last_below_threshold(&self) -> Vec<Detection> {
+        self.last_below_threshold.read().clone()
+    }
+
+    // A threshold sweep of `duration_s` down to `floor`, each defaulting to
+    // vision.sweep's.
+    pub fn start_sweep(&self, duration_s: Option<f32>, floor: Option<f32>) -> Result<SweepStatus> {
+        self.sweep.lock().start(duration_s, floor, CLOCK.now_ms())
+    }
+
+    pub fn stop_sweep(&self) -> bool {
+        self.sweep.lock().stop(CLOCK.now_ms())
+    }
+
+    pub fn get_sweep_status(&self) -> SweepStatus {
+        self.sweep.lock().get_status(CLOCK.now_ms())
+    }
+
+    pub fn get_sweep_report(&self, minutes: Option<f32>, thresholds: Option<Vec<f32>>) -> Result<SweepReport> {
+        self.sweep.lock().report(CLOCK.now_ms(), minutes, thresholds, self.config.min_confidence)
+    }
+
+    // Floor metres, right of the lens and ahead of it, of captured-frame
+    // pixel (px, py); None without a ground calibration or above the
+    // horizon.
+    pub fn project_to_ground(&self, px: f32, py: f32) -> Option<(f32, f32)> {
+        self.ground.as_ref()?.project_to_ground(px, py)
+    }
+
+    // The ground calibration detections are being placed with.
+    pub fn get_ground_calibration_version(&self) -> Option<u32> {
+        self.ground.as_ref().map(GroundProjection::get_version)
+    }
+
+    // Boxes that stayed put in at least `min_frames` frames, as polygons
+    // for vision.self_mask.
+    pub fn propose_self_mask(&self, min_frames: u64) -> MaskProposal {
+        self.mask_proposer.propose(min_frames)
+    }
+
+    pub fn clear_self_mask_proposal(&mut self) {
+        self.mask_proposer.clear();
+    }
     
+    pub fn get_last_fiducials(&self) -> Vec<FiducialDetection> {
+        self.last_fiducials.read().clone()
+    }
+
+    // The active backend's label map, keyed by class index.
+    pub fn get_labels(&self) -> std::collections::BTreeMap<u32, LabelInfo> {
+        self.labels.get_labels()
+    }
+
+    pub fn get_last_free_space(&self) -> Option<FreeSpace> {
+        self.last_free_space.read().clone()
+    }
+
+    pub fn get_camera(&self) -> CameraModel {
+        CameraModel::new(&self.config)
+    }
+
+    #[deprecated(note = "read `image` from the world state (StateHandle::get_world) instead")]
     pub fn get_last_frame_base64(&self) -> String {
-        self.last_frame_base64.read().clone()  // read() not lock()
+        self.get_last_frame_image().get_jpeg_base64().map(|jpeg| jpeg.to_string()).unwrap_or_default()
+    }
+
+    // The last frame's image, shared rather than copied.
+    pub fn get_last_frame_image(&self) -> Arc<FrameImage> {
+        self.last_frame_image.read().clone()  // read() not lock()
     }
     
-    pub fn get_navigation_command(&self) -> NavigationAction {
-        let detections = self.last_detections.read();  // read() not lock()
-        
-        if detections.is_empty() {
-            return NavigationAction::Continue;
+    // True when no frame newer than vision.max_frame_age_ms has arrived,
+    // including before the first one. What the rover last saw may no
+    // longer be in front of it, so it stops until frames come back.
+    pub fn is_stale(&self) -> bool {
+        // Parked at the keepalive rate, frames are further apart than that.
+        let keepalive_ms = self.keepalive_fps.map_or(0, |fps| (2000.0 / fps) as u64);
+        let max_age = match self.config.max_frame_age_ms {
+            0 => 0,
+            max_age => max_age.max(keepalive_ms),
+        };
+        max_age > 0 && self.newest_capture_ms.map_or(true, |at| CLOCK.now_ms().saturating_sub(at) > max_age)
+    }
+
+    // Whether this frame's masks are worth the time, logging when that
+    // changes. Counts any that are dropped.
+    fn check_mask_budget(&mut self, raw: &[IMX500Detection]) -> bool {
+        let masks = raw.iter().filter(|d| d.mask.is_some()).count();
+        if masks == 0 {
+            return true;
+        }
+        let loop_ms = METRICS.last_loop_time_ms.get();
+        let skip = loop_ms > self.config.mask_budget_ms as f64 || THERMAL.is_degraded();
+        if skip != self.skipping_masks {
+            self.skipping_masks = skip;
+            if skip {
+                info!(target: "vision", "Dropping segmentation masks (tick took {:.0} ms, hot: {})", loop_ms, THERMAL.is_degraded());
+            } else {
+                info!(target: "vision", "Using segmentation masks again");
+            }
+        }
+        if skip {
+            METRICS.masks_dropped.add(masks as u64);
+        }
+        !skip
+    }
+
+    fn check_staleness(&mut self) {
+        let stale = self.is_stale();
+        INTERLOCK.set_vision_stale(stale);
+        if stale == self.stale {
+            return;
+        }
+        self.stale = stale;
+        let age_ms = self.newest_capture_ms.map(|at| CLOCK.now_ms().saturating_sub(at));
+        if stale {
+            METRICS.vision_stale.inc();
+            warn!(target: "vision", "No frame for over {} ms, stopping until frames resume", self.config.max_frame_age_ms);
+            EVENTS.publish(Severity::Warning, "vision", "Frames stopped arriving", serde_json::json!({
+                "age_ms": age_ms,
+                "max_frame_age_ms": self.config.max_frame_age_ms,
+            }));
+        } else {
+            info!(target: "vision", "Frames resumed");
+            EVENTS.publish(Severity::Info, "vision", "Frames resumed", serde_json::Value::Null);
         }
+    }
+
+    // The action policy's verdict on the nearest detection, or Stop on
+    // stale frames, is the floor; rules from vision.rules_path can only
+    // make it more cautious.
+    pub fn get_navigation_command(&self, world: &RuleWorld) -> NavigationAction {
+        let detections = self.last_detections.read();  // read() not lock()
         
-        detections.iter()
-            .min_by(|a, b| a.distance_estimate.partial_cmp(&b.distance_estimate).unwrap())
+        let mut floor = detections.iter()
+            .min_by(|a, b| a.distance_estimate.total_cmp(&b.distance_estimate))
             .map(|d| d.action)
-            .unwrap_or(NavigationAction::Continue)
+            .unwrap_or(NavigationAction::Continue);
+        if self.is_stale() {
+            floor = floor.max(NavigationAction::Stop);
+        }
+        if self.rules.is_empty() {
+            return floor;
+        }
+        let (action, rule) = self.rules.apply(floor, &detections, world, &self.get_camera());
+        if let Some(rule) = rule {
+            METRICS.rule_matches.inc(&rule.name);
+        }
+        action
+    }
+
+    pub fn get_rules(&self) -> Vec<Rule> {
+        self.rules.get_rules().to_vec()
+    }
+
+    // Re-reads vision.rules_path. A file that doesn't load leaves the
+    // current rules in place.
+    pub fn reload_rules(&mut self) -> Result<usize, Vec<RuleError>> {
+        if self.config.rules_path.is_none() {
+            return Err(vec![RuleError { rule: String::new(), message: "vision.rules_path is not set".to_string() }]);
+        }
+        self.rules = load_rules(&self.config)?;
+        Ok(self.rules.get_rules().len())
     }
     
     pub fn get_telemetry(&self) -> VisionTelemetry {
@@ -196,15 +1217,314 @@ impl VisionSystem {
             fps: 30.0,
             inference_time: 15.0,
             processing: true,
+            filters: self.filter_timings.clone(),
+            profile: self.get_profile_status(),
+            ground_calibration: self.get_ground_calibration_version(),
+            exposure: self.get_exposure_status(),
         }
     }
 }
 
 impl Drop for VisionSystem {
     fn drop(&mut self) {
-        if let Some(mut child) = self.bridge_process.take() {
+        let child = self.bridge_process.take().and_then(|process| process.lock().take());
+        if let Some(mut child) = child {
             let _ = child.kill();
             let _ = child.wait();
         }
     }
 }
+
+enum BridgeOutcome {
+    Closed,
+    Unsupported(u32),
+    Failing(f32),
+}
+
+// Each bridge process sets up a frame ring of its own, if any.
+fn read_bridge(
+    reader: impl BufRead,
+    frames: &FrameSender,
+    health: &HealthHandle,
+    monitor: &mut ParseMonitor,
+    config: &VisionConfig,
+    exposure: &AtomicBool,
+    control: &Sender<BridgeControl>,
+) -> BridgeOutcome {
+    let mut ring: Option<Arc<ShmRing>> = None;
+    for line in reader.lines().map_while(|line| line.ok()) {
+        let parsed = protocol::parse_line(&line);
+        let parsed_ms = CLOCK.now_ms_f64();
+        monitor.record(parsed.is_ok());
+        BRIDGE_TAP.observe(&line, parsed.as_ref().err());
+        match parsed {
+            Ok(BridgeLine::Hello(hello)) => {
+                if !protocol::SUPPORTED_VERSIONS.contains(&hello.version) {
+                    return BridgeOutcome::Unsupported(hello.version);
+                }
+                METRICS.bridge_protocol_version.set(hello.version as i64);
+                exposure.store(hello.capabilities.iter().any(|c| c == exposure::CAPABILITY), Ordering::SeqCst);
+                info!(target: "vision", "Vision bridge speaks protocol v{} ({})", hello.version, hello.capabilities.join(", "));
+                if let Some(monotonic) = hello.monotonic {
+                    CLOCK.sync_bridge(monotonic);
+                    info!(target: "vision", "Bridge clock offset {:.1} ms", CLOCK.get_bridge_offset_ms().unwrap_or_default());
+                }
+                ring = hello.shm.and_then(|info| open_ring(&info, config, control));
+                // A bridge that doesn't know the format goes on sending
+                // JPEGs, each frame saying which it is.
+                let raw = config.pixel_format.as_str();
+                if config.pixel_format != PixelFormat::Jpeg && (ring.is_none() || !hello.capabilities.iter().any(|c| c == raw)) {
+                    info!(target: "vision", "The bridge sends JPEG frames rather than {}", raw);
+                }
+            }
+            Ok(BridgeLine::Frame(mut frame_data)) => {
+                // The emit time goes first, so that if it corrects the
+                // offset the capture time is mapped with the correction.
+                let emit_ms = CLOCK.map_bridge_ms(frame_data.emit_monotonic);
+                let capture_ms = CLOCK.map_bridge_ms(frame_data.capture_monotonic);
+                frame_data.capture_ms = capture_ms.map_or_else(|| CLOCK.now_ms(), |ms| ms as u64);
+                frame_data.stamps = FrameStamps { capture_ms: capture_ms.unwrap_or(parsed_ms), emit_ms, parsed_ms };
+                if let Some(slot) = frame_data.slot.take().map(|slot| *slot) {
+                    METRICS.bridge_shm_frames.inc();
+                    if slot.format == PixelFormat::Yuv420 {
+                        METRICS.bridge_raw_frames.inc();
+                    }
+                    // Without a ring the frame goes on without its picture
+                    // and the slot is only handed back.
+                    match &ring {
+                        Some(ring) => frame_data.shared_frame = Some(SharedFrame::new(ring.clone(), slot, control.clone())),
+                        None => shm::ack(control, &slot),
+                    }
+                }
+                health.beat_with(serde_json::json!({
+                    "frame_id": frame_data.frame_id,
+                    "bridge_timestamp": frame_data.timestamp,
+                }));
+                frames.send(frame_data);
+            }
+            Err(e) => {
+                METRICS.bridge_parse_errors.inc();
+                health.error(format!("Unparseable bridge line: {:#}", e));
+                if let Some(suppressed) = monitor.take_warning() {
+                    let suppressed = if suppressed > 0 { format!(" ({} more since the last warning)", suppressed) } else { String::new() };
+                    warn!(target: "vision", "Unparseable bridge line{}: {:#}: {}", suppressed, e, protocol::truncate(&line));
+                }
+                if let Some(rate) = monitor.get_failure_rate().filter(|&rate| rate > config.bridge_max_parse_failure_rate) {
+                    return BridgeOutcome::Failing(rate);
+                }
+            }
+        }
+    }
+    BridgeOutcome::Closed
+}
+
+// A ring that can't be mapped, or wasn't asked for, is turned down, and
+// the bridge goes back to sending JPEGs inline.
+fn open_ring(info: &RingInfo, config: &VisionConfig, control: &Sender<BridgeControl>) -> Option<Arc<ShmRing>> {
+    let opened = match config.frame_transport {
+        FrameTransport::Shm => ShmRing::open(info),
+        FrameTransport::Base64 => Err(anyhow::anyhow!("vision.frame_transport is base64")),
+    };
+    match opened {
+        Ok(ring) => {
+            info!(target: "vision", "Frames come through shared memory: {} slots of {} KB at {}", info.slots, info.slot_bytes / 1024, info.path);
+            Some(Arc::new(ring))
+        }
+        Err(e) => {
+            warn!(target: "vision", "Falling back to base64 frames: {:#}", e);
+            EVENTS.publish(Severity::Warning, "vision", format!("Shared-memory frames unavailable, falling back to base64: {:#}", e), serde_json::Value::Null);
+            let _ = control.send(BridgeControl::Once(serde_json::json!({ "type": "transport", "shm": false }).to_string()));
+            None
+        }
+    }
+}
+
+fn get_profile<'a>(config: &'a VisionConfig, name: Option<&str>) -> Option<&'a VisionProfile> {
+    config.profiles.get(name?)
+}
+
+// Writes control lines to the bridge's stdin on a thread of its own, so a
+// bridge that doesn't read them can't hold up the vision system. Each new
+// bridge process is sent the latest line again.
+fn spawn_control_writer() -> Sender<BridgeControl> {
+    let (tx, rx) = unbounded();
+    thread::spawn(move || {
+        let mut stdin: Option<ChildStdin> = None;
+        let mut latest: std::collections::BTreeMap<&'static str, String> = std::collections::BTreeMap::new();
+        for message in rx {
+            let lines: Vec<String> = match message {
+                BridgeControl::Stdin(pipe) => {
+                    stdin = Some(pipe);
+                    latest.values().cloned().collect()
+                }
+                BridgeControl::Line(kind, line) => {
+                    latest.insert(kind, line.clone());
+                    vec![line]
+                }
+                BridgeControl::Once(line) => vec![line],
+            };
+            let Some(pipe) = stdin.as_mut() else { continue };
+            if let Err(e) = lines.iter().try_for_each(|line| writeln!(pipe, "{}", line)).and_then(|()| pipe.flush()) {
+                debug!(target: "vision", "Bridge stdin closed: {}", e);
+                stdin = None;
+            }
+        }
+    });
+    tx
+}
+
+fn mean_brightness(gray: &Mat) -> Result<f32> {
+    let (mut mean, mut stddev) = (Scalar::default(), Scalar::default());
+    cv_core::mean_std_dev(gray, &mut mean, &mut stddev)?;
+    Ok(mean.channel(0) as f32)
+}
+
+fn to_gray(frame: &Mat) -> Result<Mat> {
+    let mut gray = Mat::default();
+    imgproc::cvt_color(frame, &mut gray, imgproc::COLOR_BGR2GRAY)?;
+    Ok(gray)
+}
+
+fn luma_mat(width: u32, height: u32, luma: &[u8]) -> Result<Mat> {
+    Mat::from_slice_2d(height as i32, width as i32, 1, luma).context("Failed to copy the frame's gray")
+}
+
+// For the odd brightness sample on a frame nothing else looked at.
+fn gray_frame(image: &FrameImage) -> Result<Mat> {
+    match image.get_luma() {
+        Some((width, height, luma)) => luma_mat(width, height, luma),
+        None => to_gray(&decode_frame(&image.get_jpeg_base64()?)?),
+    }
+}
+
+// A JPEG is encoded to base64 on the spot, since the web and recordings
+// want it that way; a raw frame is copied out as it is.
+// A JPEG's bytes come back as well, to decode from.
+fn picture_from_slot(slot: &SlotRef, bytes: Vec<u8>) -> Result<(FrameImage, Option<Vec<u8>>)> {
+    match slot.format {
+        PixelFormat::Jpeg => Ok((FrameImage::from_jpeg_base64(base64::engine::general_purpose::STANDARD.encode(&bytes)), Some(bytes))),
+        PixelFormat::Yuv420 => Ok((FrameImage::from_yuv420(slot.width, slot.height, bytes)?, None)),
+    }
+}
+
+// Nothing configured for the active backend means names are used as sent.
+fn load_label_map(config: &VisionConfig) -> Result<LabelMap> {
+    match label_map_path(config, config.detector.source_name()) {
+        Some(path) => LabelMap::load(&path),
+        None => Ok(LabelMap::default()),
+    }
+}
+
+// Uploaded labels go with the IMX500 model and stand in for its map.
+fn label_map_path(config: &VisionConfig, source: &str) -> Option<std::path::PathBuf> {
+    if source == VisionDetector::Imx500.source_name() {
+        if let Some(path) = ASSETS.get_active_path(ArtifactKind::Labels) {
+            return Some(path);
+        }
+    }
+    config.label_maps.get(source).cloned()
+}
+
+fn load_rules(config: &VisionConfig) -> Result<RuleSet, Vec<RuleError>> {
+    match &config.rules_path {
+        Some(path) => RuleSet::load(path),
+        None => Ok(RuleSet::default()),
+    }
+}
+
+fn describe_rule_errors(errors: &[RuleError]) -> String {
+    errors.iter()
+        .map(|e| if e.rule.is_empty() { e.message.clone() } else { format!("{}: {}", e.rule, e.message) })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// The bridge's base64 JPEG as an 8UC3 BGR Mat.
+fn decode_frame(jpeg_base64: &str) -> Result<Mat> {
+    let jpeg = base64::engine::general_purpose::STANDARD.decode(jpeg_base64)
+        .context("Frame image is not valid base64")?;
+    imgcodecs::imdecode(&jpeg).context("Failed to decode frame image")
+}
+
+// Fallback obstacle detector for when no model is available: large dark
+// blobs reaching into the bottom of the frame, where the ground just ahead
+// of the rover is. Blobs come out in the bridge's detection format with
+// class "obstacle" and the blob's fill of its box as the confidence.
+pub struct SimpleBlobObstacleDetector {
+    dark_threshold: f64,
+    min_area_fraction: f32,
+    bottom_fraction: f32,
+    scale_width: i32,
+}
+
+impl SimpleBlobObstacleDetector {
+    pub fn new(config: &VisionConfig) -> Self {
+        Self {
+            dark_threshold: config.blob_dark_threshold as f64,
+            min_area_fraction: config.blob_min_area_fraction,
+            bottom_fraction: config.blob_bottom_fraction,
+            scale_width: config.blob_scale_width as i32,
+        }
+    }
+
+    // Frames without an image (e.g. from a scenario) have no obstacles.
+    pub fn detect_jpeg(&self, jpeg_base64: &str) -> Result<Vec<IMX500Detection>> {
+        if jpeg_base64.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.detect(&decode_frame(jpeg_base64)?)
+    }
+
+    // Boxes are in the coordinates of `frame`, which is 8UC3 BGR or 8UC1.
+    pub fn detect(&self, frame: &Mat) -> Result<Vec<IMX500Detection>> {
+        let gray = if frame.channels() == 1 {
+            frame.clone_owned()?
+        } else {
+            let mut gray = Mat::default();
+            imgproc::cvt_color(frame, &mut gray, imgproc::COLOR_BGR2GRAY)?;
+            gray
+        };
+
+        // Only ever scale down; the blobs worth finding are large anyway.
+        let small = if gray.cols() > self.scale_width {
+            let rows = (gray.rows() * self.scale_width / gray.cols()).max(1);
+            let mut small = Mat::default();
+            imgproc::resize(&gray, &mut small, Size::new(self.scale_width, rows), imgproc::INTER_LINEAR)?;
+            small
+        } else {
+            gray
+        };
+        let (w, h) = (small.cols(), small.rows());
+        let scale_x = frame.cols() as f32 / w as f32;
+        let scale_y = frame.rows() as f32 / h as f32;
+
+        let mut dark = Mat::default();
+        imgproc::threshold(&small, &mut dark, self.dark_threshold, 255.0, imgproc::THRESH_BINARY_INV)?;
+        let (mut labels, mut stats, mut centroids) = (Mat::default(), Mat::default(), Mat::default());
+        let count = imgproc::connected_components_with_stats(&dark, &mut labels, &mut stats, &mut centroids, 8)?;
+
+        let min_area = self.min_area_fraction * (w * h) as f32;
+        let bottom_start = h as f32 * (1.0 - self.bottom_fraction);
+        let mut detections = Vec::new();
+        for label in 1..count {
+            let stat = |column: usize| stats.get::<i32>(label, column as i32);
+            let (x, y) = (stat(imgproc::CC_STAT_LEFT)?, stat(imgproc::CC_STAT_TOP)?);
+            let (bw, bh) = (stat(imgproc::CC_STAT_WIDTH)?, stat(imgproc::CC_STAT_HEIGHT)?);
+            let area = stat(imgproc::CC_STAT_AREA)?;
+            if (area as f32) < min_area || ((y + bh) as f32) <= bottom_start {
+                continue;
+            }
+            detections.push(IMX500Detection {
+                class: "obstacle".to_string(),
+                class_id: None,
+                conf: area as f32 / (bw * bh) as f32,
+                x: (x as f32 * scale_x) as i32,
+                y: (y as f32 * scale_y) as i32,
+                w: (bw as f32 * scale_x).round() as i32,
+                h: (bh as f32 * scale_y).round() as i32,
+                mask: None,
+            });
+        }
+        Ok(detections)
+    }
+}
@@ -0,0 +1,59 @@
+// src/vision/camera.rs
+use crate::config::VisionConfig;
+
+use super::FRAME_WIDTH_PX;
+
+// Nearer than this a floor point is under the lens and has no sensible
+// place in the frame.
+const MIN_FORWARD_M: f32 = 0.05;
+
+// Pinhole camera looking level along the rover's heading from
+// vision.camera_height_m above the floor, centred on the frame
+// horizontally with the horizon on vision.horizon_row_px. The lens is
+// taken to be at the rover's position, as everywhere else on the map.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraModel {
+    pub focal_length_px: f32,
+    pub center_x_px: f32,
+    pub horizon_row_px: f32,
+    pub height_m: f32,
+}
+
+impl CameraModel {
+    pub fn new(config: &VisionConfig) -> Self {
+        Self {
+            focal_length_px: config.focal_length_px,
+            center_x_px: FRAME_WIDTH_PX / 2.0,
+            horizon_row_px: config.horizon_row_px,
+            height_m: config.camera_height_m,
+        }
+    }
+
+    // Degrees right of straight ahead of captured-frame column `x`.
+    pub fn bearing_deg(&self, x: f32) -> f32 {
+        ((x - self.center_x_px) / self.focal_length_px).atan().to_degrees()
+    }
+
+    // Pixel (x, y) of the point on the floor `forward_m` ahead of the lens
+    // and `right_m` to its right, in captured-frame pixels. Points may fall
+    // outside the frame; those behind the lens have no pixel.
+    pub fn project_floor(&self, forward_m: f32, right_m: f32) -> Option<(f32, f32)> {
+        if forward_m < MIN_FORWARD_M {
+            return None;
+        }
+        Some((
+            self.center_x_px + self.focal_length_px * right_m / forward_m,
+            self.horizon_row_px + self.focal_length_px * self.height_m / forward_m,
+        ))
+    }
+
+    // How far ahead of the lens the floor seen on captured-frame row `y`
+    // is. Rows at or above the horizon show no floor.
+    pub fn floor_distance_m(&self, y: f32) -> Option<f32> {
+        let below_horizon = y - self.horizon_row_px;
+        if below_horizon <= 0.0 {
+            return None;
+        }
+        Some(self.focal_length_px * self.height_m / below_horizon)
+    }
+}
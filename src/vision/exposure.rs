@@ -0,0 +1,124 @@
+// src/vision/exposure.rs
+//
+// Exposure feedback for backlit scenes, where the bright part of the frame
+// blows out and nothing in it is detected: the share of clipped pixels is
+// measured from a luminance histogram, and the bridge is asked to expose
+// less while too much is clipped and back toward its normal exposure once
+// that clears.
+use anyhow::{bail, Result};
+use opencv_embedded::core::Mat;
+use serde::Serialize;
+
+use crate::config::AutoExposureConfig;
+
+// Offered in the bridge's hello by bridges that take set_exposure lines.
+pub const CAPABILITY: &str = "set_exposure";
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExposureStatus {
+    // The bridge offered set_exposure in its hello.
+    pub supported: bool,
+    pub enabled: bool,
+    // What the bridge has been asked for, in EV on top of the profile's
+    // camera.ev.
+    pub ev: f32,
+    // Of the last sample, 0-1.
+    pub clipped_fraction: Option<f32>,
+    // Wall-clock ms of the last step.
+    pub since_ms: Option<u64>,
+}
+
+// Steps the offset down while more than clip_fraction is clipped, and
+// back up toward 0 while less than clear_fraction is; the gap between the
+// two keeps it from hunting.
+#[derive(Debug, Default)]
+pub struct ExposureControl {
+    ev: f32,
+    clipped_fraction: Option<f32>,
+    last_sample_ms: Option<u64>,
+    last_step_ms: Option<u64>,
+    since_ms: Option<u64>,
+}
+
+impl ExposureControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_due(&self, config: &AutoExposureConfig, now_ms: u64) -> bool {
+        self.last_sample_ms.map_or(true, |at| now_ms.saturating_sub(at) >= config.sample_interval_ms)
+    }
+
+    pub fn get_status(&self, supported: bool, enabled: bool) -> ExposureStatus {
+        ExposureStatus { supported, enabled, ev: self.ev, clipped_fraction: self.clipped_fraction, since_ms: self.since_ms }
+    }
+
+    // Returns the new offset when it steps.
+    pub fn sample(&mut self, config: &AutoExposureConfig, clipped: f32, now_ms: u64, wall_ms: u64) -> Option<f32> {
+        self.last_sample_ms = Some(now_ms);
+        self.clipped_fraction = Some(clipped);
+        if self.last_step_ms.is_some_and(|at| now_ms.saturating_sub(at) < config.min_step_interval_ms) {
+            return None;
+        }
+        let target = if clipped > config.clip_fraction {
+            (self.ev - config.step_ev).max(config.min_ev)
+        } else if clipped < config.clear_fraction {
+            (self.ev + config.step_ev).min(0.0)
+        } else {
+            self.ev
+        };
+        if target == self.ev {
+            return None;
+        }
+        self.ev = target;
+        self.last_step_ms = Some(now_ms);
+        self.since_ms = Some(wall_ms);
+        Some(target)
+    }
+
+    // Back to no offset, as when the loop is switched off or a bridge
+    // without the capability takes over. Returns whether there was one.
+    pub fn reset(&mut self, wall_ms: u64) -> bool {
+        self.clipped_fraction = None;
+        self.last_sample_ms = None;
+        self.last_step_ms = None;
+        if self.ev == 0.0 {
+            return false;
+        }
+        self.ev = 0.0;
+        self.since_ms = Some(wall_ms);
+        true
+    }
+}
+
+// The line the bridge is sent on its stdin for a new offset.
+pub fn exposure_control(ev: f32) -> String {
+    serde_json::json!({ "type": "set_exposure", "ev": ev }).to_string()
+}
+
+// Of an 8-bit gray frame, or of `roi` (x, y, w, h in its pixels) cut down
+// to it.
+pub fn luminance_histogram(gray: &Mat, roi: Option<(i32, i32, i32, i32)>) -> Result<[u32; 256]> {
+    let (width, height) = (gray.cols(), gray.rows());
+    let (x, y, w, h) = roi.unwrap_or((0, 0, width, height));
+    let (x0, y0) = (x.clamp(0, width), y.clamp(0, height));
+    let (x1, y1) = (x.saturating_add(w).clamp(0, width), y.saturating_add(h).clamp(0, height));
+    if x1 <= x0 || y1 <= y0 {
+        bail!("the exposure region is outside the {}x{} frame", width, height);
+    }
+    let pixels = gray.to_vec::<u8>()?;
+    let mut histogram = [0u32; 256];
+    for row in pixels.chunks_exact(width as usize).take(y1 as usize).skip(y0 as usize) {
+        for &value in &row[x0 as usize..x1 as usize] {
+            histogram[value as usize] += 1;
+        }
+    }
+    Ok(histogram)
+}
+
+// The share of the histogram at or above `level`.
+pub fn clipped_fraction(histogram: &[u32; 256], level: u8) -> f32 {
+    let total: u64 = histogram.iter().map(|&n| n as u64).sum();
+    let clipped: u64 = histogram[level as usize..].iter().map(|&n| n as u64).sum();
+    if total == 0 { 0.0 } else { clipped as f32 / total as f32 }
+}
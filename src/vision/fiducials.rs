@@ -0,0 +1,58 @@
+// src/vision/fiducials.rs
+use anyhow::Result;
+use opencv_embedded::core::Mat;
+use serde::{Deserialize, Serialize};
+
+// A decoded tag as a detector reports it: image-pixel corners in the tag's
+// own order (top-left, top-right, bottom-right, bottom-left for both
+// AprilTag and ArUco).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagCorners {
+    pub id: u32,
+    pub corners: [(f32, f32); 4],
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TagPose {
+    pub distance_m: f32,
+    // Clockwise from the camera axis, like headings on the map.
+    pub bearing_deg: f32,
+}
+
+impl TagPose {
+    // Pinhole estimate from the tag's apparent size. The longest edge is
+    // the least foreshortened one, so it stands in for the tag's width.
+    pub fn from_corners(corners: &[(f32, f32); 4], tag_size_m: f32, focal_length_px: f32, frame_width_px: f32) -> Option<Self> {
+        let edge = (0..4)
+            .map(|i| {
+                let (a, b) = (corners[i], corners[(i + 1) % 4]);
+                (b.0 - a.0).hypot(b.1 - a.1)
+            })
+            .fold(0.0, f32::max);
+        if edge <= 0.0 || focal_length_px <= 0.0 {
+            return None;
+        }
+        let centre_x = corners.iter().map(|c| c.0).sum::<f32>() / 4.0;
+        Some(Self {
+            distance_m: tag_size_m * focal_length_px / edge,
+            bearing_deg: ((centre_x - frame_width_px / 2.0) / focal_length_px).atan().to_degrees(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FiducialDetection {
+    pub id: u32,
+    pub corners: [(f32, f32); 4],
+    pub pose_estimate: Option<TagPose>,
+    // Rover-clock ms at which the frame was captured.
+    #[serde(default)]
+    pub capture_ms: u64,
+}
+
+// Where a decoder running on the rover plugs in, e.g. a binding to the
+// apriltag C library. It gets every frame as 8UC1 grayscale. Tags found
+// by the bridge arrive with the frame and don't go through this.
+pub trait FiducialDetector: Send + Sync {
+    fn detect(&mut self, gray: &Mat) -> Result<Vec<TagCorners>>;
+}
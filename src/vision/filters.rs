@@ -0,0 +1,219 @@
+// src/vision/filters.rs
+use opencv_embedded::core::Rect;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::VisionConfig;
+use super::heatmap::{Heatmap, HeatmapFilter};
+use super::types::{ActionPolicy, Detection, NavigationAction};
+
+// What a filter gets to know about the frame its detections came from.
+pub struct FrameContext<'a> {
+    pub frame_count: u64,
+    // Rover-clock ms at which the frame was captured.
+    pub capture_ms: u64,
+    // Empty for frames without an image, e.g. from a scenario.
+    pub image: &'a super::pixels::FrameImage,
+    // What turns a distance into an action for this frame's backend.
+    pub policy: &'a ActionPolicy,
+}
+
+// One step of the detection pipeline. Filters run in order on every new
+// frame, each getting what the previous one returned. Whatever they do,
+// an emergency stop among the detections going in survives to the end
+// unless some filter in the pipeline claims overrides_safety.
+pub trait DetectionFilter: Send + Sync {
+    // Shown in telemetry and used to remove the filter again.
+    fn name(&self) -> &str;
+
+    fn process(&mut self, frame_ctx: &FrameContext, detections: Vec<Detection>) -> Vec<Detection>;
+
+    fn overrides_safety(&self) -> bool {
+        false
+    }
+
+    // Called on every vision config change.
+    fn apply_config(&mut self, _config: &VisionConfig) {}
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterTiming {
+    pub name: String,
+    pub last_ms: f32,
+}
+
+// The built-in filters, in the order they run. The heatmap counts what
+// the others leave.
+pub fn builtin_filters(config: &VisionConfig, heatmap: &Arc<Mutex<Heatmap>>) -> Vec<Box<dyn DetectionFilter>> {
+    vec![
+        Box::new(ClassFilter::new(config)),
+        Box::new(RoiFilter::new(config)),
+        Box::new(Tracker::new(config)),
+        Box::new(DistanceSmoother::new(config)),
+        Box::new(HeatmapFilter::new(heatmap.clone())),
+    ]
+}
+
+// Puts back the emergency stops the pipeline lost, unless a filter was
+// allowed to drop them.
+pub(crate) fn enforce_safety(
+    raw: &[Detection],
+    mut filtered: Vec<Detection>,
+    filters: &[Box<dyn DetectionFilter>],
+) -> (Vec<Detection>, bool) {
+    let is_emergency = |d: &Detection| matches!(d.action, NavigationAction::EmergencyStop);
+    if filtered.iter().any(is_emergency) || filters.iter().any(|f| f.overrides_safety()) {
+        return (filtered, false);
+    }
+    let before = filtered.len();
+    filtered.extend(raw.iter().filter(|d| is_emergency(d)).cloned());
+    let restored = filtered.len() > before;
+    (filtered, restored)
+}
+
+// Drops vision.ignored_classes.
+pub struct ClassFilter {
+    ignored: Vec<String>,
+}
+
+impl ClassFilter {
+    pub fn new(config: &VisionConfig) -> Self {
+        Self { ignored: config.ignored_classes.clone() }
+    }
+}
+
+impl DetectionFilter for ClassFilter {
+    fn name(&self) -> &str {
+        "class"
+    }
+
+    fn process(&mut self, _frame_ctx: &FrameContext, mut detections: Vec<Detection>) -> Vec<Detection> {
+        detections.retain(|d| !self.ignored.contains(&d.class_name));
+        detections
+    }
+
+    fn apply_config(&mut self, config: &VisionConfig) {
+        *self = Self::new(config);
+    }
+}
+
+// Keeps detections centred inside vision.detection_roi, when it is set.
+pub struct RoiFilter {
+    roi: Option<(i32, i32, i32, i32)>,
+}
+
+impl RoiFilter {
+    pub fn new(config: &VisionConfig) -> Self {
+        Self { roi: config.detection_roi }
+    }
+}
+
+impl DetectionFilter for RoiFilter {
+    fn name(&self) -> &str {
+        "roi"
+    }
+
+    fn process(&mut self, _frame_ctx: &FrameContext, mut detections: Vec<Detection>) -> Vec<Detection> {
+        if let Some((rx, ry, rw, rh)) = self.roi {
+            detections.retain(|d| {
+                let (x, y, w, h) = d.bbox;
+                let (cx, cy) = (x + w / 2, y + h / 2);
+                cx >= rx && cx < rx + rw && cy >= ry && cy < ry + rh
+            });
+        }
+        detections
+    }
+
+    fn apply_config(&mut self, config: &VisionConfig) {
+        *self = Self::new(config);
+    }
+}
+
+// Gives each detection a track id, carried over from the previous frame's
+// box of the same class it overlaps most, if that is at least
+// vision.track_iou.
+pub struct Tracker {
+    min_iou: f32,
+    next_id: u32,
+    previous: Vec<(u32, String, Rect)>,
+}
+
+impl Tracker {
+    pub fn new(config: &VisionConfig) -> Self {
+        Self { min_iou: config.track_iou, next_id: 0, previous: Vec::new() }
+    }
+}
+
+impl DetectionFilter for Tracker {
+    fn name(&self) -> &str {
+        "tracker"
+    }
+
+    fn process(&mut self, _frame_ctx: &FrameContext, mut detections: Vec<Detection>) -> Vec<Detection> {
+        let mut unmatched = std::mem::take(&mut self.previous);
+        for detection in &mut detections {
+            let (x, y, w, h) = detection.bbox;
+            let rect = Rect::new(x, y, w, h);
+            let best = unmatched.iter()
+                .enumerate()
+                .filter(|(_, (_, class, _))| *class == detection.class_name)
+                .map(|(i, (_, _, prev))| (i, rect.iou(prev)))
+                .filter(|&(_, iou)| iou >= self.min_iou)
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            let id = match best {
+                Some((i, _)) => unmatched.swap_remove(i).0,
+                None => {
+                    self.next_id = self.next_id.wrapping_add(1);
+                    self.next_id
+                }
+            };
+            detection.track_id = Some(id);
+            self.previous.push((id, detection.class_name.clone(), rect));
+        }
+        detections
+    }
+
+    fn apply_config(&mut self, config: &VisionConfig) {
+        self.min_iou = config.track_iou;
+    }
+}
+
+// Blends each track's distance with its previous one by
+// vision.distance_smoothing and re-derives the action from the result.
+// Untracked detections pass through untouched.
+pub struct DistanceSmoother {
+    weight: f32,
+    distances: HashMap<u32, f32>,
+}
+
+impl DistanceSmoother {
+    pub fn new(config: &VisionConfig) -> Self {
+        Self { weight: config.distance_smoothing, distances: HashMap::new() }
+    }
+}
+
+impl DetectionFilter for DistanceSmoother {
+    fn name(&self) -> &str {
+        "smoothing"
+    }
+
+    fn process(&mut self, frame_ctx: &FrameContext, mut detections: Vec<Detection>) -> Vec<Detection> {
+        let mut distances = HashMap::new();
+        for detection in &mut detections {
+            let Some(id) = detection.track_id else { continue };
+            if let Some(previous) = self.distances.get(&id) {
+                detection.distance_estimate = self.weight * previous + (1.0 - self.weight) * detection.distance_estimate;
+                detection.action = frame_ctx.policy.action(&detection.class_name, detection.distance_estimate);
+            }
+            distances.insert(id, detection.distance_estimate);
+        }
+        self.distances = distances;
+        detections
+    }
+
+    fn apply_config(&mut self, config: &VisionConfig) {
+        self.weight = config.distance_smoothing;
+    }
+}
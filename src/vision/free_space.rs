@@ -0,0 +1,170 @@
+// src/vision/free_space.rs
+use anyhow::{bail, Result};
+use opencv_embedded::core::{Mat, Size};
+use opencv_embedded::imgproc;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+use crate::config::VisionConfig;
+
+// The seed patch: the bottom tenth of the frame across its middle fifth,
+// which is floor whenever the rover could have driven there.
+const SEED_HEIGHT_FRACTION: f32 = 0.1;
+const SEED_WIDTH_FRACTION: f32 = 0.2;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Clearance {
+    // Clockwise from the camera axis, like detections and tags.
+    pub bearing_deg: f32,
+    // Floor visible in that direction before something that isn't floor,
+    // capped at vision.free_space_max_range_m.
+    pub distance_m: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FreeSpace {
+    // One per column of the scaled-down frame, left to right.
+    pub columns: Vec<Clearance>,
+    // Share of the seed patch that looks like its own average. A patterned
+    // or cluttered floor brings it down, and so does anything parked right
+    // in front of the rover.
+    pub confidence: f32,
+    // Share of the floor expected between the seed patch and
+    // vision.dropoff_lookahead_m ahead that is floor. Low means the floor
+    // stops short, as at the top of a step.
+    pub near_floor: f32,
+    // Rover-clock ms at which the frame was captured.
+    pub capture_ms: u64,
+}
+
+pub struct FreeSpaceEstimator {
+    scale_width: i32,
+    color_tolerance: f32,
+    texture_tolerance: f32,
+    max_range_m: f32,
+    dropoff_lookahead_m: f32,
+    camera_height_m: f32,
+    horizon_row_px: f32,
+    focal_length_px: f32,
+}
+
+impl FreeSpaceEstimator {
+    pub fn new(config: &VisionConfig) -> Self {
+        Self {
+            scale_width: config.free_space_scale_width as i32,
+            color_tolerance: config.free_space_color_tolerance,
+            texture_tolerance: config.free_space_texture_tolerance,
+            max_range_m: config.free_space_max_range_m,
+            dropoff_lookahead_m: config.dropoff_lookahead_m,
+            camera_height_m: config.camera_height_m,
+            horizon_row_px: config.horizon_row_px,
+            focal_length_px: config.focal_length_px,
+        }
+    }
+
+    // `frame` is 8UC3 BGR at the camera's resolution.
+    pub fn estimate(&self, frame: &Mat, capture_ms: u64) -> Result<FreeSpace> {
+        if frame.channels() != 3 {
+            bail!("Free-space estimation needs a BGR frame, got {} channels", frame.channels());
+        }
+        let rows = (frame.rows() * self.scale_width / frame.cols()).max(1);
+        let mut small = Mat::default();
+        imgproc::resize(frame, &mut small, Size::new(self.scale_width, rows), imgproc::INTER_LINEAR)?;
+        let mut smooth = Mat::default();
+        imgproc::gaussian_blur(&small, &mut smooth, Size::new(3, 3), 0.0, 0.0)?;
+
+        let (w, h) = (smooth.cols() as usize, smooth.rows() as usize);
+        let scale_x = frame.cols() as f32 / w as f32;
+        let scale_y = frame.rows() as f32 / h as f32;
+        let bgr = smooth.to_vec::<u8>()?;
+        let pixel = |i: usize| [bgr[3 * i] as f32, bgr[3 * i + 1] as f32, bgr[3 * i + 2] as f32];
+        let gray: Vec<f32> = (0..w * h).map(|i| pixel(i).iter().sum::<f32>() / 3.0).collect();
+        let gradient: Vec<f32> = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                let gx = gray[y * w + (x + 1).min(w - 1)] - gray[y * w + x.saturating_sub(1)];
+                let gy = gray[(y + 1).min(h - 1) * w + x] - gray[y.saturating_sub(1) * w + x];
+                (gx.abs() + gy.abs()) / 2.0
+            })
+            .collect();
+
+        let seed_rows = ((h as f32 * SEED_HEIGHT_FRACTION).ceil() as usize).max(1);
+        let seed_half_width = ((w as f32 * SEED_WIDTH_FRACTION / 2.0).ceil() as usize).max(1);
+        let seed: Vec<usize> = (h - seed_rows..h)
+            .flat_map(|y| ((w / 2).saturating_sub(seed_half_width)..(w / 2 + seed_half_width).min(w)).map(move |x| y * w + x))
+            .collect();
+        let n = seed.len() as f32;
+        let mut mean = [0.0f32; 3];
+        for &i in &seed {
+            for (m, v) in mean.iter_mut().zip(pixel(i)) {
+                *m += v / n;
+            }
+        }
+        let mean_gradient = seed.iter().map(|&i| gradient[i]).sum::<f32>() / n;
+        let is_floor = |i: usize| {
+            let p = pixel(i);
+            let distance = ((p[0] - mean[0]).powi(2) + (p[1] - mean[1]).powi(2) + (p[2] - mean[2]).powi(2)).sqrt();
+            distance <= self.color_tolerance && gradient[i] <= mean_gradient + self.texture_tolerance
+        };
+
+        // Grown 4-connected from the seed pixels that pass, and never above
+        // the horizon, where there can't be floor.
+        let first_row = ((self.horizon_row_px / scale_y).ceil().max(0.0) as usize).min(h - 1);
+        let mut floor = vec![false; w * h];
+        let mut queue: VecDeque<usize> = seed.iter().copied().filter(|&i| is_floor(i)).collect();
+        let confidence = queue.len() as f32 / n;
+        for &i in &queue {
+            floor[i] = true;
+        }
+        while let Some(i) = queue.pop_front() {
+            let (x, y) = (i % w, i / w);
+            let neighbours = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < w).then(|| i + 1),
+                (y > first_row).then(|| i - w),
+                (y + 1 < h).then(|| i + w),
+            ];
+            for j in neighbours.into_iter().flatten() {
+                if !floor[j] && is_floor(j) {
+                    floor[j] = true;
+                    queue.push_back(j);
+                }
+            }
+        }
+
+        // The band above the seed patch, up to the row the floor
+        // dropoff_lookahead_m away falls on, across the patch's columns.
+        let seed_columns = (w / 2).saturating_sub(seed_half_width)..(w / 2 + seed_half_width).min(w);
+        let lookahead_row = self.horizon_row_px + self.camera_height_m * self.focal_length_px / self.dropoff_lookahead_m;
+        let band_top = ((lookahead_row / scale_y).floor().max(first_row as f32) as usize).min(h - seed_rows);
+        let band: Vec<usize> = (band_top..h - seed_rows)
+            .flat_map(|y| seed_columns.clone().map(move |x| y * w + x))
+            .collect();
+        let near_floor = if band.is_empty() {
+            1.0
+        } else {
+            band.iter().filter(|&&i| floor[i]).count() as f32 / band.len() as f32
+        };
+
+        let columns = (0..w)
+            .map(|x| {
+                let top = (0..h).rev().take_while(|&y| floor[y * w + x]).last();
+                let centre_x = (x as f32 + 0.5) * scale_x;
+                Clearance {
+                    bearing_deg: ((centre_x - frame.cols() as f32 / 2.0) / self.focal_length_px).atan().to_degrees(),
+                    distance_m: top.map_or(0.0, |y| self.ground_distance(y as f32 * scale_y)),
+                }
+            })
+            .collect();
+        Ok(FreeSpace { columns, confidence, near_floor, capture_ms })
+    }
+
+    // Flat-floor pinhole model: how far away the floor seen at `row` is.
+    fn ground_distance(&self, row: f32) -> f32 {
+        let below_horizon = row - self.horizon_row_px;
+        if below_horizon <= 0.0 {
+            return self.max_range_m;
+        }
+        (self.camera_height_m * self.focal_length_px / below_horizon).min(self.max_range_m)
+    }
+}
@@ -0,0 +1,226 @@
+// src/vision/ground.rs
+//
+// Image pixels to the floor through a homography fitted to points an
+// operator measured: each is a captured-frame pixel and where that pixel
+// lies on the floor, in metres to the right of the lens and ahead of it.
+// Four points fix a homography; more are fitted in the least-squares sense
+// by the normalised direct linear transform.
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::clock::CLOCK;
+use crate::config::{GroundCalibration, GroundPoint, VisionConfig};
+
+pub const MIN_POINTS: usize = 4;
+// Below this the projective scale is taken as zero: the pixel is on the
+// horizon, or above it.
+const MIN_SCALE: f64 = 1e-6;
+// A fit whose second-smallest singular value is this small next to the
+// largest has more than one solution, as when three of four points are in
+// a line.
+const MIN_CONDITION: f64 = 1e-10;
+// A normalised fit this close to singular squashes the floor onto a line.
+const MIN_DETERMINANT: f64 = 1e-9;
+const JACOBI_SWEEPS: usize = 64;
+
+// A similarity as a row-major 3x3, and the points it has moved.
+type Normalised = ([f64; 9], Vec<(f64, f64)>);
+
+// vision.ground_calibration, ready to project with.
+#[derive(Debug, Clone)]
+pub struct GroundProjection {
+    matrix: [f64; 9],
+    version: u32,
+}
+
+impl GroundProjection {
+    // None until a calibration has been made.
+    pub fn new(config: &VisionConfig) -> Option<Self> {
+        config.ground_calibration.as_ref().map(|c| Self { matrix: c.homography, version: c.version })
+    }
+
+    pub fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    // Metres to the right of the lens and ahead of it of the floor seen at
+    // captured-frame pixel (px, py). None on and above the horizon, where
+    // there is no floor.
+    pub fn project_to_ground(&self, px: f32, py: f32) -> Option<(f32, f32)> {
+        let (x, y) = apply(&self.matrix, px as f64, py as f64)?;
+        (y > 0.0).then_some((x as f32, y as f32))
+    }
+}
+
+// How far one measured point ended up from where the fit puts it.
+#[derive(Debug, Clone, Serialize)]
+pub struct PointResidual {
+    pub point: GroundPoint,
+    pub projected: Option<(f32, f32)>,
+    pub error_m: Option<f32>,
+}
+
+// Fits the points and numbers the calibration one past `previous`.
+pub fn calibrate(points: &[GroundPoint], previous: Option<&GroundCalibration>) -> Result<GroundCalibration> {
+    let homography = solve_homography(points)?;
+    let squared: f32 = get_residuals(&homography, points).iter().filter_map(|r| r.error_m).map(|e| e * e).sum();
+    Ok(GroundCalibration {
+        version: previous.map_or(1, |p| p.version + 1),
+        homography,
+        points: points.to_vec(),
+        rms_error_m: (squared / points.len() as f32).sqrt(),
+        calibrated_at_ms: CLOCK.wall_ms(),
+    })
+}
+
+pub fn get_residuals(homography: &[f64; 9], points: &[GroundPoint]) -> Vec<PointResidual> {
+    points.iter()
+        .map(|point| {
+            let projected = apply(homography, point.px as f64, point.py as f64).map(|(x, y)| (x as f32, y as f32));
+            let error_m = projected.map(|(x, y)| (x - point.x_m).hypot(y - point.y_m));
+            PointResidual { point: point.clone(), projected, error_m }
+        })
+        .collect()
+}
+
+// Row-major, scaled by the last element where it isn't near zero, and
+// signed so the measured points have a positive projective scale; the
+// horizon is where that scale reaches zero.
+pub fn solve_homography(points: &[GroundPoint]) -> Result<[f64; 9]> {
+    if points.len() < MIN_POINTS {
+        bail!("need at least {} points, got {}", MIN_POINTS, points.len());
+    }
+    if let Some(p) = points.iter().find(|p| ![p.px, p.py, p.x_m, p.y_m].iter().all(|v| v.is_finite())) {
+        bail!("point at ({}, {}) is not finite", p.px, p.py);
+    }
+    let image: Vec<(f64, f64)> = points.iter().map(|p| (p.px as f64, p.py as f64)).collect();
+    let ground: Vec<(f64, f64)> = points.iter().map(|p| (p.x_m as f64, p.y_m as f64)).collect();
+    let (image_norm, image) = normalise(&image)?;
+    let (ground_norm, ground) = normalise(&ground)?;
+
+    // A h = 0, two rows a point, through the eigenvectors of A^T A.
+    let mut ata = [[0.0f64; 9]; 9];
+    for (&(u, v), &(x, y)) in image.iter().zip(&ground) {
+        for row in [[-u, -v, -1.0, 0.0, 0.0, 0.0, x * u, x * v, x], [0.0, 0.0, 0.0, -u, -v, -1.0, y * u, y * v, y]] {
+            for i in 0..9 {
+                for j in 0..9 {
+                    ata[i][j] += row[i] * row[j];
+                }
+            }
+        }
+    }
+    let (values, vectors) = symmetric_eigen(ata);
+    let mut order: Vec<usize> = (0..9).collect();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+    let largest = values[order[8]].max(f64::MIN_POSITIVE);
+    if values[order[1]] / largest < MIN_CONDITION {
+        bail!("the points don't fix a homography; are three of them in a line?");
+    }
+    // A unit vector, so this is on the same footing whatever the units.
+    let solved: [f64; 9] = std::array::from_fn(|i| vectors[i][order[0]]);
+    if determinant(&solved).abs() < MIN_DETERMINANT {
+        bail!("the points don't fix a homography; are they all in a line?");
+    }
+
+    // Back out of the normalised coordinates.
+    let mut h = multiply(&invert_similarity(&ground_norm), &multiply(&solved, &image_norm));
+    let positive = points.iter().filter(|p| scale(&h, p.px as f64, p.py as f64) > 0.0).count();
+    if positive != 0 && positive != points.len() {
+        bail!("the points are on both sides of the horizon");
+    }
+    let sign = if positive == 0 { -1.0 } else { 1.0 };
+    let divisor = if h[8].abs() > MIN_SCALE { h[8].abs() } else { norm(&h) };
+    for value in &mut h {
+        *value *= sign / divisor;
+    }
+    Ok(h)
+}
+
+fn scale(h: &[f64; 9], px: f64, py: f64) -> f64 {
+    h[6] * px + h[7] * py + h[8]
+}
+
+fn apply(h: &[f64; 9], px: f64, py: f64) -> Option<(f64, f64)> {
+    let w = scale(h, px, py);
+    if w <= MIN_SCALE {
+        return None;
+    }
+    Some(((h[0] * px + h[1] * py + h[2]) / w, (h[3] * px + h[4] * py + h[5]) / w))
+}
+
+// Moves the points' centroid to the origin and scales them to an average
+// distance of √2 from it, which keeps A^T A well conditioned; returns the
+// similarity that does it and the moved points.
+fn normalise(points: &[(f64, f64)]) -> Result<Normalised> {
+    let n = points.len() as f64;
+    let (cx, cy) = points.iter().fold((0.0, 0.0), |(x, y), p| (x + p.0 / n, y + p.1 / n));
+    let spread = points.iter().map(|p| (p.0 - cx).hypot(p.1 - cy)).sum::<f64>() / n;
+    if spread <= f64::EPSILON {
+        bail!("the points are all in one place");
+    }
+    let s = std::f64::consts::SQRT_2 / spread;
+    let t = [s, 0.0, -s * cx, 0.0, s, -s * cy, 0.0, 0.0, 1.0];
+    Ok((t, points.iter().map(|p| (s * (p.0 - cx), s * (p.1 - cy))).collect()))
+}
+
+fn invert_similarity(t: &[f64; 9]) -> [f64; 9] {
+    let s = t[0];
+    [1.0 / s, 0.0, -t[2] / s, 0.0, 1.0 / s, -t[5] / s, 0.0, 0.0, 1.0]
+}
+
+fn multiply(a: &[f64; 9], b: &[f64; 9]) -> [f64; 9] {
+    std::array::from_fn(|k| {
+        let (i, j) = (k / 3, k % 3);
+        (0..3).map(|m| a[i * 3 + m] * b[m * 3 + j]).sum()
+    })
+}
+
+fn determinant(h: &[f64; 9]) -> f64 {
+    h[0] * (h[4] * h[8] - h[5] * h[7]) - h[1] * (h[3] * h[8] - h[5] * h[6]) + h[2] * (h[3] * h[7] - h[4] * h[6])
+}
+
+fn norm(h: &[f64; 9]) -> f64 {
+    h.iter().map(|v| v * v).sum::<f64>().sqrt()
+}
+
+// Cyclic Jacobi: eigenvalues, and the matching eigenvectors as columns.
+fn symmetric_eigen(mut a: [[f64; 9]; 9]) -> ([f64; 9], [[f64; 9]; 9]) {
+    let mut v = [[0.0f64; 9]; 9];
+    for (i, row) in v.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    for _ in 0..JACOBI_SWEEPS {
+        let off: f64 = (0..9).flat_map(|i| (0..9).filter(move |&j| j != i).map(move |j| (i, j))).map(|(i, j)| a[i][j] * a[i][j]).sum();
+        if off < 1e-30 {
+            break;
+        }
+        for p in 0..9 {
+            for q in p + 1..9 {
+                if a[p][q].abs() < 1e-300 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut() {
+                    let (akp, akq) = (row[p], row[q]);
+                    row[p] = c * akp - s * akq;
+                    row[q] = s * akp + c * akq;
+                }
+                let (head, tail) = a.split_at_mut(q);
+                for (apk, aqk) in head[p].iter_mut().zip(tail[0].iter_mut()) {
+                    let (x, y) = (*apk, *aqk);
+                    *apk = c * x - s * y;
+                    *aqk = s * x + c * y;
+                }
+                for row in v.iter_mut() {
+                    let (vkp, vkq) = (row[p], row[q]);
+                    row[p] = c * vkp - s * vkq;
+                    row[q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    (std::array::from_fn(|i| a[i][i]), v)
+}
@@ -0,0 +1,211 @@
+// src/vision/heatmap.rs
+//
+// Where in the frame detections turn up, for placing the camera: the
+// centres of the boxes that come out of the filter pipeline, or the whole
+// boxes, are counted onto a coarse grid over the frame that fades with a
+// half-life, so what it shows is mostly the last hour or so.
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::clock::CLOCK;
+use crate::config::{HeatmapConfig, VisionConfig};
+use super::filters::{DetectionFilter, FrameContext};
+use super::types::Detection;
+use super::{FRAME_HEIGHT_PX, FRAME_WIDTH_PX};
+
+// Past this many classes new ones only count toward the overall grid.
+const MAX_CLASSES: usize = 64;
+
+// How much a count has faded after `elapsed_ms`: a half for every
+// half_life_s that went by.
+pub fn decay_factor(half_life_s: f32, elapsed_ms: u64) -> f32 {
+    (-(elapsed_ms as f64 / 1000.0) / half_life_s as f64).exp2() as f32
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HeatmapSnapshot {
+    // None for all classes together.
+    pub class: Option<String>,
+    pub columns: usize,
+    pub rows: usize,
+    pub frame_width: f32,
+    pub frame_height: f32,
+    pub half_life_s: f32,
+    pub boxes: bool,
+    // rows x columns, top row first; 1 is one detection seen just now.
+    pub cells: Vec<Vec<f32>>,
+    pub min: f32,
+    pub max: f32,
+    pub total: f32,
+    // Detections counted since the last reset, without fading.
+    pub detections: u64,
+    // The classes there are grids for.
+    pub classes: Vec<String>,
+    // Wall-clock ms of the last reset.
+    pub since_ms: u64,
+}
+
+pub struct Heatmap {
+    config: HeatmapConfig,
+    all: Vec<f32>,
+    classes: BTreeMap<String, (Vec<f32>, u64)>,
+    detections: u64,
+    // Rover-clock ms the cells have been faded to.
+    updated_ms: Option<u64>,
+    since_ms: u64,
+}
+
+impl Heatmap {
+    pub fn new(config: &HeatmapConfig) -> Self {
+        Self {
+            config: config.clone(),
+            all: vec![0.0; config.columns * config.rows],
+            classes: BTreeMap::new(),
+            detections: 0,
+            updated_ms: None,
+            since_ms: CLOCK.wall_ms(),
+        }
+    }
+
+    // A new grid size or mode starts over; a new half-life applies to what
+    // is there.
+    pub fn apply_config(&mut self, config: &HeatmapConfig) {
+        let reshaped = (config.columns, config.rows, config.boxes) != (self.config.columns, self.config.rows, self.config.boxes);
+        self.config = config.clone();
+        if reshaped {
+            self.clear();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new(&self.config);
+    }
+
+    // Fades what is there to `now_ms`, then counts each detection once.
+    pub fn add(&mut self, detections: &[Detection], now_ms: u64) {
+        let elapsed = self.updated_ms.map_or(0, |at| now_ms.saturating_sub(at));
+        if elapsed > 0 {
+            let factor = decay_factor(self.config.half_life_s, elapsed);
+            self.all.iter_mut().for_each(|v| *v *= factor);
+            for (cells, _) in self.classes.values_mut() {
+                cells.iter_mut().for_each(|v| *v *= factor);
+            }
+        }
+        // A replay from further back doesn't turn the clock back.
+        self.updated_ms = Some(self.updated_ms.map_or(now_ms, |at| at.max(now_ms)));
+        for detection in detections {
+            let weights = self.weights(detection.bbox);
+            for &(i, weight) in &weights {
+                self.all[i] += weight;
+            }
+            if self.classes.len() < MAX_CLASSES || self.classes.contains_key(&detection.class_name) {
+                let size = self.all.len();
+                let (cells, count) = self.classes.entry(detection.class_name.clone()).or_insert_with(|| (vec![0.0; size], 0));
+                for &(i, weight) in &weights {
+                    cells[i] += weight;
+                }
+                *count += 1;
+            }
+            self.detections += 1;
+        }
+    }
+
+    // The cells a box counts toward and how much, adding up to 1: the one
+    // its centre is in, or with `boxes` each cell it covers by how much of
+    // the box lies in it.
+    fn weights(&self, bbox: (i32, i32, i32, i32)) -> Vec<(usize, f32)> {
+        let (columns, rows) = (self.config.columns, self.config.rows);
+        let (cell_w, cell_h) = (FRAME_WIDTH_PX / columns as f32, FRAME_HEIGHT_PX / rows as f32);
+        let cell = |x: f32, y: f32| {
+            let column = ((x / cell_w).floor().max(0.0) as usize).min(columns - 1);
+            let row = ((y / cell_h).floor().max(0.0) as usize).min(rows - 1);
+            (column, row)
+        };
+        let (x, y, w, h) = bbox;
+        let (x0, y0) = ((x as f32).clamp(0.0, FRAME_WIDTH_PX), (y as f32).clamp(0.0, FRAME_HEIGHT_PX));
+        let (x1, y1) = (((x + w) as f32).clamp(0.0, FRAME_WIDTH_PX), ((y + h) as f32).clamp(0.0, FRAME_HEIGHT_PX));
+        let area = (x1 - x0) * (y1 - y0);
+        if !self.config.boxes || area <= 0.0 {
+            let (column, row) = cell(x as f32 + w as f32 / 2.0, y as f32 + h as f32 / 2.0);
+            return vec![(row * columns + column, 1.0)];
+        }
+        let ((c0, r0), (c1, r1)) = (cell(x0, y0), cell(x1, y1));
+        let mut weights = Vec::new();
+        for row in r0..=r1 {
+            let overlap_h = (y1.min((row + 1) as f32 * cell_h) - y0.max(row as f32 * cell_h)).max(0.0);
+            for column in c0..=c1 {
+                let overlap_w = (x1.min((column + 1) as f32 * cell_w) - x0.max(column as f32 * cell_w)).max(0.0);
+                if overlap_w * overlap_h > 0.0 {
+                    weights.push((row * columns + column, overlap_w * overlap_h / area));
+                }
+            }
+        }
+        weights
+    }
+
+    // Faded to `now_ms`, of one class or of them all. A class not seen yet
+    // is all zeros.
+    pub fn snapshot(&self, class: Option<&str>, now_ms: u64) -> HeatmapSnapshot {
+        let factor = self.updated_ms.map_or(1.0, |at| decay_factor(self.config.half_life_s, now_ms.saturating_sub(at)));
+        let (source, detections) = match class {
+            None => (Some(&self.all), self.detections),
+            Some(class) => self.classes.get(class).map_or((None, 0), |(cells, count)| (Some(cells), *count)),
+        };
+        let cells: Vec<f32> = match source {
+            Some(cells) => cells.iter().map(|v| v * factor).collect(),
+            None => vec![0.0; self.all.len()],
+        };
+        HeatmapSnapshot {
+            class: class.map(str::to_string),
+            columns: self.config.columns,
+            rows: self.config.rows,
+            frame_width: FRAME_WIDTH_PX,
+            frame_height: FRAME_HEIGHT_PX,
+            half_life_s: self.config.half_life_s,
+            boxes: self.config.boxes,
+            min: cells.iter().copied().fold(f32::INFINITY, f32::min),
+            max: cells.iter().copied().fold(0.0, f32::max),
+            total: cells.iter().sum(),
+            cells: cells.chunks(self.config.columns).map(<[f32]>::to_vec).collect(),
+            detections,
+            classes: self.classes.keys().cloned().collect(),
+            since_ms: self.since_ms,
+        }
+    }
+}
+
+// Runs last in the pipeline, so it counts what the rover actually acts
+// on, and passes everything through.
+pub struct HeatmapFilter {
+    heatmap: Arc<Mutex<Heatmap>>,
+}
+
+impl HeatmapFilter {
+    pub fn new(heatmap: Arc<Mutex<Heatmap>>) -> Self {
+        Self { heatmap }
+    }
+}
+
+impl DetectionFilter for HeatmapFilter {
+    fn name(&self) -> &str {
+        "heatmap"
+    }
+
+    fn process(&mut self, frame_ctx: &FrameContext, detections: Vec<Detection>) -> Vec<Detection> {
+        let mut heatmap = self.heatmap.lock();
+        if heatmap.is_enabled() {
+            heatmap.add(&detections, frame_ctx.capture_ms);
+        }
+        detections
+    }
+
+    fn apply_config(&mut self, config: &VisionConfig) {
+        self.heatmap.lock().apply_config(&config.heatmap);
+    }
+}
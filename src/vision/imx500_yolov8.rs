@@ -1,45 +1,140 @@
 // src/vision/imx500_yolov8.rs - COMPLETE FILE with Python service integration
-use anyhow::Result;
-use std::process::Command;
+//
+// Detection runs in one long-lived src/imx500_fast_detector.py child that
+// keeps the camera and the NPU model loaded; each call is a line of JSON
+// each way over its stdin and stdout, and waits for the camera's next
+// finished frame, so the rate is bounded by the 30 FPS stream and the NPU
+// rather than by process startup (see tests/detector_fps.rs and the README
+// for the measured difference).
+use anyhow::{bail, Context, Result};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use parking_lot::RwLock;
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Detection {
-    pub class_name: String,
-    pub confidence: f32,
-    pub bbox: (i32, i32, i32, i32),
-    pub distance_estimate: f32,
-}
+use std::thread;
+use std::time::Duration;
+use parking_lot::{Mutex, RwLock};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::clock::CLOCK;
+use crate::config::VisionConfig;
+use crate::vision::labels::LabelMap;
+use crate::vision::types::{ActionPolicy, ConfidenceCurve, Detection, DistanceEstimator};
+
+pub const DETECTOR_SCRIPT: &str = "src/imx500_fast_detector.py";
+
+// This backend's key in vision.confidence_calibration and vision.label_maps.
+const SOURCE: &str = "imx500_yolov8";
+
+// The service itself gives up on a frame after 2s.
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(2500);
 
 #[derive(Deserialize)]
 struct PythonDetectionResult {
+    id: u64,
     status: String,
     detections: Vec<PythonDetection>,
     detection_count: usize,
-    inference_time: Option<f32>,
 }
 
 #[derive(Deserialize)]
 struct PythonDetection {
+    #[serde(default)]
     class_name: String,
+    #[serde(default)]
+    class_id: Option<u32>,
     confidence: f32,
     bbox: Vec<i32>,
-    distance: f32,
+}
+
+// The running service. Responses are read on their own thread so a
+// request can time out without blocking on the pipe.
+struct DetectorProcess {
+    child: Child,
+    stdin: ChildStdin,
+    responses: Receiver<String>,
+    next_id: u64,
+}
+
+impl DetectorProcess {
+    // With class_indices the service sends indices for `labels` to name.
+    fn spawn(class_indices: bool) -> Result<Self> {
+        let mut child = Command::new("python3")
+            .arg(DETECTOR_SCRIPT)
+            .env("SCOUT_CLASS_INDICES", if class_indices { "1" } else { "0" })
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .with_context(|| format!("Failed to start {}", DETECTOR_SCRIPT))?;
+        let stdin = child.stdin.take().context("Detector stdin unavailable")?;
+        let stdout = child.stdout.take().context("Detector stdout unavailable")?;
+
+        let (tx, responses) = unbounded();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self { child, stdin, responses, next_id: 0 })
+    }
+
+    fn request(&mut self, timeout: Duration) -> Result<PythonDetectionResult> {
+        self.next_id += 1;
+        let id = self.next_id;
+        writeln!(self.stdin, "{}", serde_json::json!({ "id": id }))
+            .and_then(|_| self.stdin.flush())
+            .context("Detector service is not accepting requests")?;
+
+        // Answers to requests that timed out earlier may still be queued.
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            let line = match self.responses.recv_timeout(remaining) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => bail!("Detector service did not answer within {:?}", timeout),
+                Err(RecvTimeoutError::Disconnected) => bail!("Detector service exited"),
+            };
+            match serde_json::from_str::<PythonDetectionResult>(&line) {
+                Ok(result) if result.id == id => return Ok(result),
+                Ok(_) => continue,
+                Err(e) => warn!(target: "vision", "⚠️ Failed to parse detection JSON ({}): {}", e, line),
+            }
+        }
+    }
+}
+
+impl Drop for DetectorProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
 }
 
 pub struct IMX500YoloV8 {
     detections: Arc<RwLock<Vec<Detection>>>,
+    calibration: ConfidenceCurve,
+    distance: DistanceEstimator,
+    policy: ActionPolicy,
+    labels: LabelMap,
+    service: Mutex<Option<DetectorProcess>>,
+    // Set once the service has answered a request, cleared when it has to
+    // be restarted.
+    warm: AtomicBool,
 }
 
 impl IMX500YoloV8 {
-    pub fn new() -> Result<Self> {
-        println!("🎥 Initializing IMX500 with YOLOv8...");
+    pub fn new(config: &VisionConfig) -> Result<Self> {
+        info!(target: "vision", "🎥 Initializing IMX500 with YOLOv8...");
 
         // Test that the model loads (keep this check)
         let test = Command::new("rpicam-still")
-            .args(&[
+            .args([
                 "--width", "640",
                 "--height", "480",
                 "--rotation", "180",
@@ -52,146 +147,89 @@ impl IMX500YoloV8 {
             .output()?;
 
         if !test.status.success() {
-            println!("⚠️ Warning: {}", String::from_utf8_lossy(&test.stderr));
+            warn!(target: "vision", "⚠️ Warning: {}", String::from_utf8_lossy(&test.stderr));
         }
 
+        // Start the service now so the model is loading while the rest of
+        // the rover boots; a failure here is retried on the first call.
+        let labels = match config.label_maps.get(SOURCE) {
+            Some(path) => LabelMap::load(path)?,
+            None => LabelMap::default(),
+        };
+        let service = DetectorProcess::spawn(!labels.is_empty())
+            .map_err(|e| warn!(target: "vision", "⚠️ {:#}", e))
+            .ok();
+
         Ok(Self {
             detections: Arc::new(RwLock::new(Vec::new())),
+            calibration: ConfidenceCurve::new(config, SOURCE),
+            distance: DistanceEstimator::new(config).with_labels(&labels),
+            policy: ActionPolicy::new(config).with_labels(&labels),
+            labels,
+            service: Mutex::new(service),
+            warm: AtomicBool::new(false),
         })
     }
 
-    pub fn capture_and_detect(&self) -> Result<Vec<Detection>> {
-        println!("📸 Running YOLOv8 detection...");
-
-        // Use the Python service that actually works!
-        let output = Command::new("python3")
-            .arg("src/imx500_fast_detector.py")
-            .output()?;
-
-        if output.status.success() {
-            let json_str = String::from_utf8_lossy(&output.stdout);
-            
-            // Try to parse the JSON response
-            if let Ok(result) = serde_json::from_str::<PythonDetectionResult>(&json_str) {
-                if result.status == "success" && !result.detections.is_empty() {
-                    let detections: Vec<Detection> = result.detections.iter().map(|d| {
-                        Detection {
-                            class_name: d.class_name.clone(),
-                            confidence: d.confidence,
-                            bbox: (d.bbox[0], d.bbox[1], d.bbox[2], d.bbox[3]),
-                            distance_estimate: d.distance,
-                        }
-                    }).collect();
-                    
-                    println!("  ✅ Detected {} objects", detections.len());
-                    for det in &detections {
-                        println!("    - {} ({}%) at distance ~{:.1}m", 
-                                det.class_name, 
-                                (det.confidence * 100.0) as i32,
-                                det.distance_estimate);
-                    }
-                    
-                    *self.detections.write() = detections.clone();
-                    return Ok(detections);
-                }
-            } else {
-                // If JSON parsing failed, log the error for debugging
-                println!("  ⚠️ Failed to parse detection JSON: {}", json_str);
-            }
-        } else {
-            println!("  ⚠️ Python detection service failed: {}", 
-                    String::from_utf8_lossy(&output.stderr));
-        }
-
-        println!("  ℹ️ No objects detected in frame");
-        *self.detections.write() = Vec::new();
-        Ok(Vec::new())
+    // Whether the detector service is up and has answered, i.e. the next
+    // call won't pay for starting python3 and loading the model.
+    pub fn is_warm(&self) -> bool {
+        self.warm.load(Ordering::Relaxed)
     }
 
-    // Keep all the helper functions even though they're not used right now
-    fn parse_real_detection(line: &str) -> Option<Detection> {
-        // Parse ACTUAL detection output - adjust based on real rpicam output format
-
-        // Example parsing for format: "Object detected: person confidence: 0.85 bbox: [100,200,50,100]"
-        if let Some(class_start) = line.find("Object detected:") {
-            let rest = &line[class_start + 16..];
-            let parts: Vec<&str> = rest.split_whitespace().collect();
-
-            if parts.len() >= 5 {
-                let class_name = parts[0].to_string();
-
-                // Parse confidence
-                let confidence = if let Some(conf_idx) = parts.iter().position(|&x| x == "confidence:") {
-                    parts.get(conf_idx + 1)
-                        .and_then(|s| s.parse::<f32>().ok())
-                        .unwrap_or(0.0)
-                } else {
-                    0.0
-                };
-
-                // Parse bbox [x,y,w,h]
-                let bbox = if let Some(bbox_idx) = parts.iter().position(|&x| x == "bbox:") {
-                    if let Some(bbox_str) = parts.get(bbox_idx + 1) {
-                        Self::parse_bbox(bbox_str).unwrap_or((0, 0, 0, 0))
-                    } else {
-                        (0, 0, 0, 0)
-                    }
-                } else {
-                    (0, 0, 0, 0)
-                };
-
-                // Calculate REAL distance based on object height
-                let distance = Self::calculate_real_distance(bbox.3 as f32, &class_name);
-
-                return Some(Detection {
-                    class_name,
-                    confidence,
-                    bbox,
-                    distance_estimate: distance,
-                });
-            }
-        }
-
-        None
+    pub fn get_last_detections(&self) -> Vec<Detection> {
+        self.detections.read().clone()
     }
 
-    fn parse_bbox(bbox_str: &str) -> Option<(i32, i32, i32, i32)> {
-        // Parse [x,y,w,h] format
-        let cleaned = bbox_str.trim_matches(|c| c == '[' || c == ']');
-        let parts: Vec<&str> = cleaned.split(',').collect();
-
-        if parts.len() == 4 {
-            let x = parts[0].parse().ok()?;
-            let y = parts[1].parse().ok()?;
-            let w = parts[2].parse().ok()?;
-            let h = parts[3].parse().ok()?;
-            Some((x, y, w, h))
-        } else {
-            None
-        }
+    pub fn capture_and_detect(&self) -> Result<Vec<Detection>> {
+        self.capture_and_detect_timeout(DEFAULT_TIMEOUT)
     }
 
-    fn calculate_real_distance(bbox_height: f32, class_name: &str) -> f32 {
-        // REAL distance calculation based on known object sizes
-        // Camera FOV and focal length for IMX500
-        let focal_length_pixels = 500.0; // Calibrate this for your camera
-
-        // Real-world heights in meters
-        let real_height = match class_name {
-            "person" => 1.7,
-            "car" => 1.5,
-            "truck" | "bus" => 3.0,
-            "bicycle" => 1.0,
-            "chair" => 0.8,
-            "dog" => 0.5,
-            "cat" => 0.25,
-            _ => 0.5, // Default assumption
+    // On a timeout or a broken pipe the service is dropped and started
+    // again on the next call.
+    pub fn capture_and_detect_timeout(&self, timeout: Duration) -> Result<Vec<Detection>> {
+        debug!(target: "vision", "📸 Running YOLOv8 detection...");
+
+        let mut service = self.service.lock();
+        let mut process = match service.take() {
+            Some(process) => process,
+            None => DetectorProcess::spawn(!self.labels.is_empty())?,
         };
+        let result = process.request(timeout);
+        self.warm.store(result.is_ok(), Ordering::Relaxed);
+        let result = result?;
+        *service = Some(process);
+        // The service answers with the frame that finished after the request.
+        let capture_ms = CLOCK.now_ms();
+
+        if result.status != "success" {
+            warn!(target: "vision", "⚠️ Detection service reported {}", result.status);
+            *self.detections.write() = Vec::new();
+            return Ok(Vec::new());
+        }
 
-        if bbox_height > 0.0 {
-            (focal_length_pixels * real_height) / bbox_height
-        } else {
-            10.0 // Far away if no height
+        let detections: Vec<Detection> = result.detections.iter()
+            .filter(|d| d.bbox.len() == 4)
+            .map(|d| Detection::new(
+                self.labels.resolve(&d.class_name, d.class_id),
+                d.confidence,
+                (d.bbox[0], d.bbox[1], d.bbox[2], d.bbox[3]),
+                capture_ms,
+                &self.calibration,
+                &self.distance,
+                &self.policy,
+            ))
+            .collect();
+
+        debug!(target: "vision", "✅ Detected {} of {} objects", detections.len(), result.detection_count);
+        for det in &detections {
+            debug!(target: "vision", "- {} ({}%) at distance ~{:.1}m",
+                    det.class_name,
+                    (det.confidence * 100.0) as i32,
+                    det.distance_estimate);
         }
+
+        *self.detections.write() = detections.clone();
+        Ok(detections)
     }
 }
@@ -0,0 +1,124 @@
+// src/vision/labels.rs
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use tracing::warn;
+
+// Which of the action rules a class follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelRule {
+    // The person_* thresholds.
+    Person,
+    // The obstacle_* thresholds.
+    Obstacle,
+    // Reported but never slows the rover.
+    Ignore,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LabelInfo {
+    pub name: String,
+    // Overrides vision.object_heights_m for this class.
+    #[serde(default)]
+    pub height_m: Option<f32>,
+    #[serde(default)]
+    pub rule: Option<LabelRule>,
+    // CSS colour for the dashboard.
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LabelEntry {
+    Name(String),
+    Info(LabelInfo),
+}
+
+impl From<LabelEntry> for LabelInfo {
+    fn from(entry: LabelEntry) -> Self {
+        match entry {
+            LabelEntry::Name(name) => LabelInfo { name, height_m: None, rule: None, color: None },
+            LabelEntry::Info(info) => info,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LabelFile {
+    // Index is the position in the list.
+    List(Vec<LabelEntry>),
+    Indexed(BTreeMap<u32, LabelEntry>),
+}
+
+// Class indices to names for a model with its own label set. An empty map
+// leaves the names the backend sends alone.
+#[derive(Debug, Default)]
+pub struct LabelMap {
+    labels: BTreeMap<u32, LabelInfo>,
+    // Indices already warned about, so an unknown class doesn't log on
+    // every frame.
+    warned: Mutex<BTreeSet<u32>>,
+}
+
+impl LabelMap {
+    // A `.json` file holds a list of names or label objects, or an object
+    // keyed by index. Anything else is read as labels.txt: one name per
+    // non-empty line, in index order.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read label map {}", path.display()))?;
+        let labels = if path.extension().is_some_and(|e| e == "json") {
+            let file: LabelFile = serde_json::from_str(&text)
+                .with_context(|| format!("Invalid label map {}", path.display()))?;
+            match file {
+                LabelFile::List(entries) => entries.into_iter().enumerate().map(|(i, e)| (i as u32, e.into())).collect(),
+                LabelFile::Indexed(entries) => entries.into_iter().map(|(i, e)| (i, e.into())).collect(),
+            }
+        } else {
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .enumerate()
+                .map(|(i, name)| (i as u32, LabelEntry::Name(name.to_string()).into()))
+                .collect()
+        };
+        Ok(Self { labels, warned: Mutex::new(BTreeSet::new()) })
+    }
+
+    // The class name for what a backend reported. With a map loaded the
+    // index wins, since the backend's own names assume COCO; an index that
+    // isn't in the map comes out as "unknown_<index>".
+    pub fn resolve(&self, name: &str, index: Option<u32>) -> String {
+        match index {
+            Some(index) if !self.labels.is_empty() || name.is_empty() => match self.labels.get(&index) {
+                Some(label) => label.name.clone(),
+                None => {
+                    if self.warned.lock().insert(index) {
+                        warn!(target: "vision", "Class index {} is not in the label map", index);
+                    }
+                    format!("unknown_{}", index)
+                }
+            },
+            _ if name.is_empty() => "unknown".to_string(),
+            _ => name.to_string(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn get_labels(&self) -> BTreeMap<u32, LabelInfo> {
+        self.labels.clone()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &LabelInfo> {
+        self.labels.values()
+    }
+}
@@ -0,0 +1,168 @@
+// src/vision/motion.rs
+use anyhow::Result;
+use opencv_embedded::core::{Mat, Size};
+use opencv_embedded::imgproc;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::config::VisionConfig;
+
+// Mean absolute step between neighbouring pixels below which a frame is
+// too flat (dark, blank wall, lens covered) to match against.
+const MIN_TEXTURE: f32 = 2.0;
+// Only every SAMPLE_STEP-th pixel in each direction is compared.
+const SAMPLE_STEP: usize = 2;
+
+// One frame as the motion estimate sees it: 8-bit grayscale, row by row.
+#[derive(Debug, Clone)]
+pub struct GrayFrame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    // Rover-clock ms at which the frame was captured.
+    pub capture_ms: u64,
+}
+
+impl GrayFrame {
+    fn at(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+}
+
+// The last few frames, scaled down and in gray, so whatever needs them
+// doesn't decode the JPEG again.
+pub struct GrayFrameCache {
+    capacity: usize,
+    size: (u32, u32),
+    max_shift_px: usize,
+    frames: VecDeque<Arc<GrayFrame>>,
+}
+
+impl GrayFrameCache {
+    pub fn new(config: &VisionConfig) -> Self {
+        Self {
+            capacity: config.gray_cache_frames,
+            size: (config.gray_cache_width, config.gray_cache_height),
+            max_shift_px: config.ego_motion_max_shift_px as usize,
+            frames: VecDeque::new(),
+        }
+    }
+
+    // Frames of the old size are dropped rather than compared with new ones.
+    pub fn apply_config(&mut self, config: &VisionConfig) {
+        let frames = std::mem::take(&mut self.frames);
+        *self = Self::new(config);
+        if frames.front().is_some_and(|f| (f.width as u32, f.height as u32) == self.size) {
+            self.frames = frames;
+            self.trim();
+        }
+    }
+
+    // `gray` is 8UC1 at any size.
+    pub fn push(&mut self, gray: &Mat, capture_ms: u64) -> Result<()> {
+        let (width, height) = self.size;
+        let mut small = Mat::default();
+        imgproc::resize(gray, &mut small, Size::new(width as i32, height as i32), imgproc::INTER_LINEAR)?;
+        self.frames.push_back(Arc::new(GrayFrame {
+            width: width as usize,
+            height: height as usize,
+            pixels: small.to_vec::<u8>()?,
+            capture_ms,
+        }));
+        self.trim();
+        Ok(())
+    }
+
+    fn trim(&mut self) {
+        while self.frames.len() > self.capacity {
+            self.frames.pop_front();
+        }
+    }
+
+    // Oldest first.
+    pub fn get_frames(&self) -> Vec<Arc<GrayFrame>> {
+        self.frames.iter().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    // How far the picture moved between the last two frames, in cached
+    // pixels (positive is right and down), with a 0-1 confidence. None
+    // until there are two frames, or when either is too flat to match.
+    pub fn estimate_ego_motion(&self) -> Option<(f32, f32, f32)> {
+        let mut newest = self.frames.iter().rev();
+        let (next, prev) = (newest.next()?, newest.next()?);
+        estimate_translation(prev, next, self.max_shift_px)
+    }
+}
+
+// Block matching: the whole of `prev` inside a max_shift margin is
+// compared with `next` at every whole-pixel shift up to max_shift, and the
+// shift with the smallest mean absolute difference is refined to a
+// fraction of a pixel from its neighbours. Confidence is how much better
+// that shift matches than the average one, and 0 when it is at the edge
+// of the search, where the real motion may lie beyond it.
+pub fn estimate_translation(prev: &GrayFrame, next: &GrayFrame, max_shift: usize) -> Option<(f32, f32, f32)> {
+    if (prev.width, prev.height) != (next.width, next.height)
+        || prev.width <= 2 * max_shift + 1
+        || prev.height <= 2 * max_shift + 1
+    {
+        return None;
+    }
+    if texture(prev, max_shift) < MIN_TEXTURE || texture(next, max_shift) < MIN_TEXTURE {
+        return None;
+    }
+
+    let span = 2 * max_shift + 1;
+    let cost = |sx: usize, sy: usize| -> f32 {
+        let mut total = 0u64;
+        let mut count = 0u64;
+        for y in (max_shift..prev.height - max_shift).step_by(SAMPLE_STEP) {
+            for x in (max_shift..prev.width - max_shift).step_by(SAMPLE_STEP) {
+                let moved = next.at(x + sx - max_shift, y + sy - max_shift);
+                total += prev.at(x, y).abs_diff(moved) as u64;
+                count += 1;
+            }
+        }
+        total as f32 / count as f32
+    };
+    let costs: Vec<f32> = (0..span * span).map(|i| cost(i % span, i / span)).collect();
+    let (best, &best_cost) = costs.iter().enumerate().min_by(|a, b| a.1.total_cmp(b.1))?;
+    let (bx, by) = (best % span, best / span);
+
+    // Vertex of the parabola through the best cost and its two neighbours.
+    let refine = |before: Option<f32>, after: Option<f32>| match (before, after) {
+        (Some(before), Some(after)) => {
+            let curvature = before - 2.0 * best_cost + after;
+            if curvature > 0.0 { ((before - after) / (2.0 * curvature)).clamp(-0.5, 0.5) } else { 0.0 }
+        }
+        _ => 0.0,
+    };
+    let at = |x: usize, y: usize| costs[y * span + x];
+    let dx = bx as f32 - max_shift as f32
+        + refine(bx.checked_sub(1).map(|x| at(x, by)), (bx + 1 < span).then(|| at(bx + 1, by)));
+    let dy = by as f32 - max_shift as f32
+        + refine(by.checked_sub(1).map(|y| at(bx, y)), (by + 1 < span).then(|| at(bx, by + 1)));
+
+    let on_edge = max_shift > 0 && (bx == 0 || by == 0 || bx == span - 1 || by == span - 1);
+    let mean_cost = costs.iter().sum::<f32>() / costs.len() as f32;
+    let confidence = if on_edge || mean_cost <= 0.0 { 0.0 } else { (1.0 - best_cost / mean_cost).clamp(0.0, 1.0) };
+    Some((dx, dy, confidence))
+}
+
+// Mean absolute difference between horizontally and vertically
+// neighbouring pixels inside the margin.
+fn texture(frame: &GrayFrame, margin: usize) -> f32 {
+    let mut total = 0u64;
+    let mut count = 0u64;
+    for y in (margin..frame.height - margin - 1).step_by(SAMPLE_STEP) {
+        for x in (margin..frame.width - margin - 1).step_by(SAMPLE_STEP) {
+            let here = frame.at(x, y);
+            total += here.abs_diff(frame.at(x + 1, y)) as u64 + here.abs_diff(frame.at(x, y + 1)) as u64;
+            count += 2;
+        }
+    }
+    if count == 0 { 0.0 } else { total as f32 / count as f32 }
+}
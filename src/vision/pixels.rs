@@ -0,0 +1,209 @@
+// src/vision/pixels.rs
+//
+// A frame's picture as the bridge sent it, a JPEG or raw YUV420 through
+// the frame ring, and whatever it gets turned into. Nothing is converted
+// until something asks: RGB for overlays and exports, a JPEG for web
+// clients, BGR for the OpenCV detectors, while gray consumers take the Y
+// plane as it is. Each conversion is done at most once per frame.
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use image::RgbImage;
+use once_cell::sync::OnceCell;
+use std::sync::Arc;
+
+use crate::config::PixelFormat;
+use crate::metrics::METRICS;
+
+// What a raw frame is encoded at for clients taking it as captured.
+pub const FULL_JPEG_QUALITY: u8 = 90;
+
+// Bytes in a width x height I420 frame.
+pub fn yuv420_len(width: usize, height: usize) -> usize {
+    width * height + 2 * (width / 2) * (height / 2)
+}
+
+// BT.601 limited range, as the camera stack delivers it, in 8-bit fixed
+// point: 298 is 255/219 and 409, 100, 208 and 516 the chroma weights
+// scaled by 255/224, all times 256.
+#[inline]
+pub fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = 298 * (y as i32 - 16);
+    let (d, e) = (u as i32 - 128, v as i32 - 128);
+    [
+        clamp((c + 409 * e + 128) >> 8),
+        clamp((c - 100 * d - 208 * e + 128) >> 8),
+        clamp((c + 516 * d + 128) >> 8),
+    ]
+}
+
+#[inline]
+fn clamp(value: i32) -> u8 {
+    value.clamp(0, 255) as u8
+}
+
+pub fn yuv420_to_rgb(yuv: &[u8], width: usize, height: usize) -> Vec<u8> {
+    convert::<false>(yuv, width, height)
+}
+
+// In OpenCV's channel order.
+pub fn yuv420_to_bgr(yuv: &[u8], width: usize, height: usize) -> Vec<u8> {
+    convert::<true>(yuv, width, height)
+}
+
+// The chroma terms are worked out once for the four pixels sharing them.
+// `yuv` must be yuv420_len(width, height) long, with an even width and
+// height.
+fn convert<const BGR: bool>(yuv: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let (luma, chroma) = yuv.split_at(width * height);
+    let (u_plane, v_plane) = chroma.split_at((width / 2) * (height / 2));
+    let mut out = vec![0u8; width * height * 3];
+    let (r, b) = if BGR { (2, 0) } else { (0, 2) };
+    for (row, pixels) in out.chunks_exact_mut(width * 3).enumerate() {
+        let luma = &luma[row * width..][..width];
+        let chroma_at = (row / 2) * (width / 2);
+        let (u_row, v_row) = (&u_plane[chroma_at..][..width / 2], &v_plane[chroma_at..][..width / 2]);
+        for (i, (pair, ys)) in pixels.chunks_exact_mut(6).zip(luma.chunks_exact(2)).enumerate() {
+            let (d, e) = (u_row[i] as i32 - 128, v_row[i] as i32 - 128);
+            let (red, green, blue) = (409 * e + 128, -100 * d - 208 * e + 128, 516 * d + 128);
+            for (pixel, &y) in pair.chunks_exact_mut(3).zip(ys) {
+                let c = 298 * (y as i32 - 16);
+                pixel[r] = clamp((c + red) >> 8);
+                pixel[1] = clamp((c + green) >> 8);
+                pixel[b] = clamp((c + blue) >> 8);
+            }
+        }
+    }
+    out
+}
+
+pub fn encode_jpeg(rgb: &RgbImage, quality: u8) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, quality)
+        .encode(rgb.as_raw(), rgb.width(), rgb.height(), image::ColorType::Rgb8)
+        .context("Failed to encode frame image")?;
+    Ok(out)
+}
+
+enum Picture {
+    None,
+    // Held as base64 in `jpeg_base64`, which is what everything wants.
+    Jpeg,
+    Yuv420 { width: u32, height: u32, data: Vec<u8> },
+}
+
+pub struct FrameImage {
+    picture: Picture,
+    jpeg_base64: OnceCell<Arc<str>>,
+    rgb: OnceCell<Arc<RgbImage>>,
+}
+
+impl FrameImage {
+    // For frames without a picture, e.g. from a scenario.
+    pub fn none() -> Self {
+        Self { picture: Picture::None, jpeg_base64: OnceCell::new(), rgb: OnceCell::new() }
+    }
+
+    pub fn from_jpeg_base64(jpeg_base64: String) -> Self {
+        if jpeg_base64.is_empty() {
+            return Self::none();
+        }
+        Self { picture: Picture::Jpeg, jpeg_base64: OnceCell::with_value(Arc::from(jpeg_base64)), rgb: OnceCell::new() }
+    }
+
+    pub fn from_yuv420(width: u32, height: u32, data: Vec<u8>) -> Result<Self> {
+        if width == 0 || height == 0 || width % 2 != 0 || height % 2 != 0 {
+            bail!("a {}x{} yuv420 frame needs an even width and height", width, height);
+        }
+        let expected = yuv420_len(width as usize, height as usize);
+        if data.len() != expected {
+            bail!("a {}x{} yuv420 frame is {} bytes, not {}", width, height, expected, data.len());
+        }
+        Ok(Self { picture: Picture::Yuv420 { width, height, data }, jpeg_base64: OnceCell::new(), rgb: OnceCell::new() })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self.picture, Picture::None)
+    }
+
+    pub fn get_format(&self) -> Option<PixelFormat> {
+        match self.picture {
+            Picture::None => None,
+            Picture::Jpeg => Some(PixelFormat::Jpeg),
+            Picture::Yuv420 { .. } => Some(PixelFormat::Yuv420),
+        }
+    }
+
+    // The Y plane of a raw frame with its width and height; a JPEG has to
+    // be decoded for its gray.
+    pub fn get_luma(&self) -> Option<(u32, u32, &[u8])> {
+        match &self.picture {
+            Picture::Yuv420 { width, height, data } => Some((*width, *height, &data[..(*width * *height) as usize])),
+            _ => None,
+        }
+    }
+
+    // A raw frame in OpenCV's channel order, with its width and height.
+    // Not kept, since the detectors only look once.
+    pub fn get_bgr(&self) -> Option<(u32, u32, Vec<u8>)> {
+        match &self.picture {
+            Picture::Yuv420 { width, height, data } => {
+                METRICS.frame_conversions.inc("bgr");
+                Some((*width, *height, yuv420_to_bgr(data, *width as usize, *height as usize)))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_rgb(&self) -> Result<Arc<RgbImage>> {
+        self.rgb.get_or_try_init(|| self.to_rgb().map(Arc::new)).cloned()
+    }
+
+    fn to_rgb(&self) -> Result<RgbImage> {
+        let rgb = match &self.picture {
+            Picture::None => bail!("the frame has no picture"),
+            Picture::Jpeg => {
+                let jpeg_base64 = self.jpeg_base64.get().map_or("", |jpeg| &**jpeg);
+                let jpeg = base64::engine::general_purpose::STANDARD.decode(jpeg_base64).context("Frame image is not valid base64")?;
+                image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg)
+                    .context("Failed to decode frame image")?
+                    .to_rgb8()
+            }
+            Picture::Yuv420 { width, height, data } => {
+                let rgb = yuv420_to_rgb(data, *width as usize, *height as usize);
+                RgbImage::from_raw(*width, *height, rgb).context("yuv420 frame doesn't fit its size")?
+            }
+        };
+        METRICS.frame_conversions.inc("rgb");
+        Ok(rgb)
+    }
+
+    // A JPEG as sent, or a raw frame encoded at FULL_JPEG_QUALITY the
+    // first time anyone asks.
+    pub fn get_jpeg_base64(&self) -> Result<Arc<str>> {
+        self.jpeg_base64
+            .get_or_try_init(|| {
+                let jpeg = encode_jpeg(&*self.get_rgb()?, FULL_JPEG_QUALITY)?;
+                METRICS.frame_conversions.inc("jpeg");
+                Ok::<_, anyhow::Error>(Arc::from(base64::engine::general_purpose::STANDARD.encode(jpeg)))
+            })
+            .cloned()
+    }
+}
+
+// Without the pixels, which would be most of any log line.
+impl std::fmt::Debug for FrameImage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut out = f.debug_struct("FrameImage");
+        out.field("format", &self.get_format());
+        if let Picture::Yuv420 { width, height, .. } = &self.picture {
+            out.field("width", width).field("height", height);
+        }
+        out.field("rgb", &self.rgb.get().is_some()).finish()
+    }
+}
+
+impl Default for FrameImage {
+    fn default() -> Self {
+        Self::none()
+    }
+}
@@ -0,0 +1,116 @@
+// src/vision/profiles.rs
+use serde::Serialize;
+
+use crate::config::{AutoProfileConfig, CameraSettings, VisionConfig, VisionProfile};
+
+// What made the active profile change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileCause {
+    Config,
+    Manual,
+    Auto,
+    // A replayed session was recorded on it.
+    Recording,
+}
+
+impl ProfileCause {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProfileCause::Config => "config",
+            ProfileCause::Manual => "manual",
+            ProfileCause::Auto => "auto",
+            ProfileCause::Recording => "recording",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProfileStatus {
+    // None while the base vision settings are used.
+    pub active: Option<String>,
+    // Automatic switching is on and hasn't been paused by a manual choice.
+    pub auto: bool,
+    // The last sampled mean brightness, 0-255.
+    pub brightness: Option<f32>,
+    // Wall-clock ms of the last switch.
+    pub since_ms: Option<u64>,
+}
+
+// The base vision settings with a profile's thresholds laid over them.
+pub fn overlay(base: &VisionConfig, profile: Option<&VisionProfile>) -> VisionConfig {
+    let mut config = base.clone();
+    let Some(profile) = profile else { return config };
+    if let Some(min_confidence) = profile.min_confidence {
+        config.min_confidence = min_confidence;
+    }
+    if let Some(focal_length_px) = profile.focal_length_px {
+        config.focal_length_px = focal_length_px;
+    }
+    config.confidence_calibration.extend(profile.confidence_calibration.clone());
+    config
+}
+
+// The line the bridge is sent on its stdin when the profile changes.
+// Switching back to the base settings sends no settings, so the camera
+// goes back to its defaults.
+pub fn camera_control(name: Option<&str>, camera: &CameraSettings) -> String {
+    serde_json::json!({
+        "type": "camera",
+        "profile": name,
+        "settings": camera,
+    })
+    .to_string()
+}
+
+// Picks between the dark and bright profiles from brightness samples.
+// Only a reading that has stayed beyond its threshold for the whole hold
+// counts, and readings between the thresholds change nothing.
+#[derive(Debug, Default)]
+pub struct AutoSwitch {
+    last_sample_ms: Option<u64>,
+    brightness: Option<f32>,
+    dark_since_ms: Option<u64>,
+    bright_since_ms: Option<u64>,
+}
+
+impl AutoSwitch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_due(&self, config: &AutoProfileConfig, now_ms: u64) -> bool {
+        self.last_sample_ms.map_or(true, |at| now_ms.saturating_sub(at) >= config.sample_interval_ms)
+    }
+
+    pub fn get_brightness(&self) -> Option<f32> {
+        self.brightness
+    }
+
+    // Returns the profile to switch to, if it isn't the active one.
+    pub fn sample(&mut self, config: &AutoProfileConfig, brightness: f32, now_ms: u64, active: Option<&str>) -> Option<String> {
+        self.last_sample_ms = Some(now_ms);
+        self.brightness = Some(brightness);
+        if brightness < config.dark_below {
+            self.dark_since_ms.get_or_insert(now_ms);
+        } else {
+            self.dark_since_ms = None;
+        }
+        if brightness > config.bright_above {
+            self.bright_since_ms.get_or_insert(now_ms);
+        } else {
+            self.bright_since_ms = None;
+        }
+
+        let hold_ms = (config.hold_s * 1000.0) as u64;
+        let held = |since: Option<u64>| since.is_some_and(|at| now_ms.saturating_sub(at) >= hold_ms);
+        let target = if held(self.dark_since_ms) {
+            &config.dark_profile
+        } else if held(self.bright_since_ms) {
+            &config.bright_profile
+        } else {
+            return None;
+        };
+        (active != Some(target.as_str())).then(|| target.clone())
+    }
+}
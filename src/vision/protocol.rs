@@ -0,0 +1,258 @@
+// src/vision/protocol.rs
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::latency::FrameStamps;
+use super::fiducials::TagCorners;
+use super::shm::{RingInfo, SlotRef};
+use super::{BridgeFrame, IMX500Detection};
+
+// Offered to the bridge in SCOUT_PROTOCOL_VERSIONS; it answers with the
+// one it speaks in its hello.
+pub const SUPPORTED_VERSIONS: [u32; 2] = [1, 2];
+// v2 frames say how big they are, but everything on the rover still works
+// in the bridge's 640x480.
+const V2_FRAME_SIZE: (u32, u32) = (640, 480);
+// Unparseable lines are logged at most this often, cut to this length.
+const WARN_INTERVAL: Duration = Duration::from_secs(5);
+const WARN_LINE_CHARS: usize = 200;
+// Fewer lines than this in the window say nothing about the failure rate.
+const MIN_WINDOW_LINES: usize = 10;
+
+// Every line the bridge writes, tagged by `type`. v1 frames carry no type
+// and v1 bridges send a bare {"handshake": ...} instead of a hello; see
+// parse_line.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BridgeRecord {
+    Hello(BridgeHello),
+    Frame(BridgeFrame),
+    FrameV2(BridgeFrameV2),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BridgeHello {
+    pub version: u32,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    // Bridge-side time.monotonic(), so its clock can be related to ours.
+    #[serde(default)]
+    pub monotonic: Option<f64>,
+    // The frame ring, from a bridge asked for the shm transport that
+    // could set one up.
+    #[serde(default)]
+    pub shm: Option<RingInfo>,
+}
+
+// v2 adds the frame size, requires class indices and may carry a mask
+// with each detection.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BridgeFrameV2 {
+    pub frame_id: u32,
+    pub width: u32,
+    pub height: u32,
+    pub jpeg_base64: String,
+    pub timestamp: f64,
+    pub capture_monotonic: f64,
+    #[serde(default)]
+    pub emit_monotonic: Option<f64>,
+    pub detections: Vec<DetectionV2>,
+    #[serde(default)]
+    pub fiducials: Vec<TagCorners>,
+    #[serde(default)]
+    pub brightness: Option<f32>,
+    #[serde(default)]
+    pub shm: Option<Box<SlotRef>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DetectionV2 {
+    pub class_id: u32,
+    // Left out when the rover names classes from its label map.
+    #[serde(default)]
+    pub class: Option<String>,
+    pub conf: f32,
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+    #[serde(default)]
+    pub mask: Option<DetectionMask>,
+}
+
+// Which of a width x height grid laid over the box is the object, as run
+// lengths over its cells row by row, starting with a run of background.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectionMask {
+    pub width: u32,
+    pub height: u32,
+    pub counts: Vec<u32>,
+}
+
+impl DetectionMask {
+    // `cells` is width x height, row by row.
+    pub fn encode(width: u32, height: u32, cells: &[bool]) -> Self {
+        let mut counts = vec![0];
+        let mut current = false;
+        for &cell in cells {
+            if cell != current {
+                counts.push(0);
+                current = cell;
+            }
+            *counts.last_mut().unwrap() += 1;
+        }
+        Self { width, height, counts }
+    }
+
+    pub fn check(&self) -> Result<()> {
+        let cells: u64 = self.counts.iter().map(|&c| c as u64).sum();
+        if cells != self.width as u64 * self.height as u64 {
+            bail!("mask runs cover {} cells, not {}x{}", cells, self.width, self.height);
+        }
+        Ok(())
+    }
+
+    // Only the annotator needs the cells; everything else works from the
+    // runs.
+    pub fn decode(&self) -> Vec<bool> {
+        let mut cells = Vec::with_capacity((self.width * self.height) as usize);
+        for (i, &count) in self.counts.iter().enumerate() {
+            cells.extend(std::iter::repeat(i % 2 == 1).take(count as usize));
+        }
+        cells
+    }
+
+    // How much of the box is the object, 0-1.
+    pub fn get_fill(&self) -> f32 {
+        let total = self.width as u64 * self.height as u64;
+        if total == 0 {
+            return 0.0;
+        }
+        let object: u64 = self.counts.iter().skip(1).step_by(2).map(|&c| c as u64).sum();
+        object as f32 / total as f32
+    }
+}
+
+impl TryFrom<BridgeFrameV2> for BridgeFrame {
+    type Error = anyhow::Error;
+
+    fn try_from(frame: BridgeFrameV2) -> Result<Self> {
+        if (frame.width, frame.height) != V2_FRAME_SIZE {
+            bail!("unsupported frame size {}x{}", frame.width, frame.height);
+        }
+        let detections = frame.detections.into_iter()
+            .map(|d| {
+                if let Some(mask) = &d.mask {
+                    mask.check()?;
+                }
+                Ok(IMX500Detection {
+                    class: d.class.unwrap_or_default(),
+                    class_id: Some(d.class_id),
+                    conf: d.conf,
+                    x: d.x,
+                    y: d.y,
+                    w: d.w,
+                    h: d.h,
+                    mask: d.mask,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(BridgeFrame {
+            frame_id: frame.frame_id,
+            jpeg_base64: frame.jpeg_base64,
+            timestamp: frame.timestamp,
+            imx500_basic: detections,
+            fiducials: frame.fiducials,
+            capture_monotonic: Some(frame.capture_monotonic),
+            emit_monotonic: frame.emit_monotonic,
+            brightness: frame.brightness,
+            slot: frame.shm,
+            shared_frame: None,
+            recorded_profile: None,
+            recorded: None,
+            capture_ms: 0,
+            stamps: FrameStamps::default(),
+        })
+    }
+}
+
+// A line from the bridge, whichever version wrote it.
+pub enum BridgeLine {
+    Hello(BridgeHello),
+    Frame(BridgeFrame),
+}
+
+pub fn parse_line(line: &str) -> Result<BridgeLine> {
+    let mut value: serde_json::Value = serde_json::from_str(line).context("not JSON")?;
+    let Some(record) = value.as_object_mut() else { bail!("not a JSON object") };
+    if let Some(handshake) = record.remove("handshake") {
+        let monotonic = handshake.get("monotonic").and_then(|m| m.as_f64()).context("handshake without monotonic")?;
+        return Ok(BridgeLine::Hello(BridgeHello { version: 1, capabilities: Vec::new(), monotonic: Some(monotonic), shm: None }));
+    }
+    record.entry("type").or_insert_with(|| "frame".into());
+    Ok(match serde_json::from_value(value)? {
+        BridgeRecord::Hello(hello) => BridgeLine::Hello(hello),
+        BridgeRecord::Frame(frame) => BridgeLine::Frame(frame),
+        BridgeRecord::FrameV2(frame) => BridgeLine::Frame(frame.try_into()?),
+    })
+}
+
+// The start of a line, for logs.
+pub fn truncate(line: &str) -> String {
+    match line.char_indices().nth(WARN_LINE_CHARS) {
+        Some((end, _)) => format!("{}... ({} bytes)", &line[..end], line.len()),
+        None => line.to_string(),
+    }
+}
+
+// Keeps the outcome of every line over the last `window`, so a bridge
+// that has drifted off the protocol shows up as a failure rate rather
+// than one error per line.
+pub struct ParseMonitor {
+    window: Duration,
+    lines: VecDeque<(Instant, bool)>,
+    last_warned: Option<Instant>,
+    suppressed: u64,
+}
+
+impl ParseMonitor {
+    pub fn new(window: Duration) -> Self {
+        Self { window, lines: VecDeque::new(), last_warned: None, suppressed: 0 }
+    }
+
+    pub fn record(&mut self, ok: bool) {
+        let now = Instant::now();
+        self.lines.push_back((now, ok));
+        while self.lines.front().is_some_and(|(at, _)| now.duration_since(*at) > self.window) {
+            self.lines.pop_front();
+        }
+    }
+
+    // None until the window holds enough lines to judge.
+    pub fn get_failure_rate(&self) -> Option<f32> {
+        if self.lines.len() < MIN_WINDOW_LINES {
+            return None;
+        }
+        let failed = self.lines.iter().filter(|(_, ok)| !ok).count();
+        Some(failed as f32 / self.lines.len() as f32)
+    }
+
+    // Whether a failure may be logged now, and how many weren't since the
+    // last one that was.
+    pub fn take_warning(&mut self) -> Option<u64> {
+        if self.last_warned.is_some_and(|at| at.elapsed() < WARN_INTERVAL) {
+            self.suppressed += 1;
+            return None;
+        }
+        self.last_warned = Some(Instant::now());
+        Some(std::mem::take(&mut self.suppressed))
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
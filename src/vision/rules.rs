@@ -0,0 +1,464 @@
+// src/vision/rules.rs
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use super::{CameraModel, Detection, NavigationAction};
+
+// Priorities run from 0 (the default) to this.
+pub const MAX_PRIORITY: u32 = 1000;
+
+// What rules can see besides the detections.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RuleWorld {
+    // Wheel speed, m/s.
+    pub speed: f32,
+    // As RoverState::as_str names it.
+    pub state: &'static str,
+}
+
+// A problem with one rule, named by `rule`, or with the whole file when
+// `rule` is empty.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleError {
+    pub rule: String,
+    pub message: String,
+}
+
+impl RuleError {
+    fn new(rule: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { rule: rule.into(), message: message.into() }
+    }
+}
+
+// A rules file, in TOML or (with a .json extension) JSON:
+//
+//   [[rules]]
+//   name = "people ahead"
+//   rule = 'when class == "person" and distance < 1.5 and bearing in [-20, 20] then action Stop priority 90'
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<RuleEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleEntry {
+    #[serde(default)]
+    name: Option<String>,
+    rule: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Rule {
+    pub name: String,
+    // As written in the file.
+    pub rule: String,
+    pub action: NavigationAction,
+    pub priority: u32,
+    #[serde(skip)]
+    conditions: Vec<Condition>,
+}
+
+impl Rule {
+    pub fn parse(name: impl Into<String>, text: &str) -> Result<Self, String> {
+        let mut parser = Parser { tokens: tokenize(text)?.into_iter().peekable() };
+        let (conditions, action, priority) = parser.rule()?;
+        Ok(Self { name: name.into(), rule: text.to_string(), action, priority, conditions })
+    }
+
+    // Conditions on a detection must all hold for the same detection;
+    // conditions on the world hold or not for all of them.
+    pub fn matches(&self, detections: &[Detection], world: &RuleWorld, camera: &CameraModel) -> bool {
+        let holds = |detection: Option<&Detection>| self.conditions.iter().all(|c| c.holds(detection, world, camera));
+        if self.conditions.iter().any(|c| c.field.is_detection()) {
+            detections.iter().any(|d| holds(Some(d)))
+        } else {
+            holds(None)
+        }
+    }
+}
+
+// Rules in evaluation order: highest priority first, then as listed.
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    // Every rule is checked, so all the errors are reported at once.
+    pub fn load(path: &Path) -> Result<Self, Vec<RuleError>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| vec![RuleError::new("", format!("Failed to read {}: {}", path.display(), e))])?;
+        let json = path.extension().is_some_and(|e| e == "json");
+        Self::parse(&text, json)
+    }
+
+    pub fn parse(text: &str, json: bool) -> Result<Self, Vec<RuleError>> {
+        let file: RulesFile = if json {
+            serde_json::from_str(text).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(text).map_err(|e| e.to_string())
+        }
+        .map_err(|e| vec![RuleError::new("", e)])?;
+
+        let mut rules = Vec::new();
+        let mut errors = Vec::new();
+        let mut names = BTreeSet::new();
+        for (i, entry) in file.rules.into_iter().enumerate() {
+            let name = entry.name.unwrap_or_else(|| format!("rules[{}]", i));
+            if !names.insert(name.clone()) {
+                errors.push(RuleError::new(&name, "another rule has this name"));
+                continue;
+            }
+            match Rule::parse(&name, &entry.rule) {
+                Ok(rule) => rules.push(rule),
+                Err(message) => errors.push(RuleError::new(name, message)),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        // Stable, so rules of one priority keep the file's order.
+        rules.sort_by_key(|rule: &Rule| std::cmp::Reverse(rule.priority));
+        Ok(Self { rules })
+    }
+
+    pub fn get_rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // The highest-priority rule that matches. When rules of that priority
+    // disagree, the most cautious wins, then the first listed.
+    pub fn evaluate(&self, detections: &[Detection], world: &RuleWorld, camera: &CameraModel) -> Option<&Rule> {
+        let mut matching = self.rules.iter().filter(|rule| rule.matches(detections, world, camera));
+        let first = matching.next()?;
+        Some(matching
+            .take_while(|rule| rule.priority == first.priority)
+            .fold(first, |best, rule| if rule.action > best.action { rule } else { best }))
+    }
+
+    // Rules only ever add caution: the built-in action is a floor a rule
+    // can raise to Stop but never lower to Continue.
+    pub fn apply(
+        &self,
+        floor: NavigationAction,
+        detections: &[Detection],
+        world: &RuleWorld,
+        camera: &CameraModel,
+    ) -> (NavigationAction, Option<&Rule>) {
+        match self.evaluate(detections, world, camera) {
+            Some(rule) => (floor.max(rule.action), Some(rule)),
+            None => (floor, None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Class,
+    Distance,
+    Bearing,
+    Confidence,
+    Speed,
+    State,
+}
+
+impl Field {
+    const NAMES: [(&'static str, Field); 6] = [
+        ("class", Field::Class),
+        ("distance", Field::Distance),
+        ("bearing", Field::Bearing),
+        ("confidence", Field::Confidence),
+        ("speed", Field::Speed),
+        ("state", Field::State),
+    ];
+
+    fn parse(word: &str) -> Result<Self, String> {
+        Self::NAMES.iter().find(|(name, _)| *name == word).map(|(_, field)| *field).ok_or_else(|| {
+            let names: Vec<&str> = Self::NAMES.iter().map(|(name, _)| *name).collect();
+            format!("unknown field `{}`, expected one of {}", word, names.join(", "))
+        })
+    }
+
+    fn name(self) -> &'static str {
+        Self::NAMES.iter().find(|(_, field)| *field == self).map(|(name, _)| *name).unwrap_or_default()
+    }
+
+    fn is_text(self) -> bool {
+        matches!(self, Field::Class | Field::State)
+    }
+
+    fn is_detection(self) -> bool {
+        !matches!(self, Field::Speed | Field::State)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Test {
+    Number(Op, f32),
+    Text(Op, String),
+    OneOf(Vec<String>),
+    // Inclusive at both ends.
+    Between(f32, f32),
+}
+
+#[derive(Debug, Clone)]
+struct Condition {
+    field: Field,
+    test: Test,
+}
+
+enum Sample<'a> {
+    Number(f32),
+    Text(&'a str),
+}
+
+impl Condition {
+    fn holds(&self, detection: Option<&Detection>, world: &RuleWorld, camera: &CameraModel) -> bool {
+        let sample = match (self.field, detection) {
+            (Field::Speed, _) => Sample::Number(world.speed),
+            (Field::State, _) => Sample::Text(world.state),
+            (_, None) => return false,
+            (Field::Class, Some(d)) => Sample::Text(&d.class_name),
+            (Field::Distance, Some(d)) => Sample::Number(d.distance_estimate),
+            (Field::Confidence, Some(d)) => Sample::Number(d.confidence),
+            (Field::Bearing, Some(d)) => Sample::Number(camera.bearing_deg(d.bbox.0 as f32 + d.bbox.2 as f32 / 2.0)),
+        };
+        match (&self.test, sample) {
+            (Test::Number(op, limit), Sample::Number(value)) => match op {
+                Op::Eq => value == *limit,
+                Op::Ne => value != *limit,
+                Op::Lt => value < *limit,
+                Op::Le => value <= *limit,
+                Op::Gt => value > *limit,
+                Op::Ge => value >= *limit,
+            },
+            (Test::Text(op, text), Sample::Text(value)) => (value == text) == (*op == Op::Eq),
+            (Test::OneOf(texts), Sample::Text(value)) => texts.iter().any(|t| t == value),
+            (Test::Between(low, high), Sample::Number(value)) => (*low..=*high).contains(&value),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number(f32),
+    Text(String),
+    Op(Op),
+    Open,
+    Close,
+    Comma,
+}
+
+impl Token {
+    fn describe(token: Option<&Token>) -> String {
+        match token {
+            None => "the end of the rule".to_string(),
+            Some(Token::Word(word)) => format!("`{}`", word),
+            Some(Token::Number(n)) => format!("`{}`", n),
+            Some(Token::Text(text)) => format!("\"{}\"", text),
+            Some(Token::Op(_)) => "a comparison".to_string(),
+            Some(Token::Open) => "`[`".to_string(),
+            Some(Token::Close) => "`]`".to_string(),
+            Some(Token::Comma) => "`,`".to_string(),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, c)| c);
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '[' => Token::Open,
+            ']' => Token::Close,
+            ',' => Token::Comma,
+            '=' | '!' | '<' | '>' => {
+                let equals = next == Some('=');
+                if equals {
+                    chars.next();
+                }
+                Token::Op(match (c, equals) {
+                    ('=', true) => Op::Eq,
+                    ('!', true) => Op::Ne,
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    ('>', false) => Op::Gt,
+                    ('>', true) => Op::Ge,
+                    _ => return Err(format!("`{}` must be followed by `=`", c)),
+                })
+            }
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, c)) => value.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                Token::Text(value)
+            }
+            c if c.is_ascii_digit() || ((c == '-' || c == '.') && next.is_some_and(|n| n.is_ascii_digit() || n == '.')) => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                let number = &text[start..end];
+                Token::Number(number.parse().map_err(|_| format!("`{}` is not a number", number))?)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                Token::Word(text[start..end].to_string())
+            }
+            c => return Err(format!("unexpected `{}`", c)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+// rule      = "when" condition ("and" condition)* "then" "action" ACTION ["priority" N]
+// condition = FIELD OP value | FIELD "in" "[" value ("," value)* "]"
+struct Parser {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    fn rule(&mut self) -> Result<(Vec<Condition>, NavigationAction, u32), String> {
+        self.keyword("when")?;
+        let mut conditions = vec![self.condition()?];
+        loop {
+            match self.tokens.next() {
+                Some(Token::Word(word)) if word == "and" => conditions.push(self.condition()?),
+                Some(Token::Word(word)) if word == "then" => break,
+                other => return Err(format!("expected `and` or `then`, found {}", Token::describe(other.as_ref()))),
+            }
+        }
+        self.keyword("action")?;
+        let action = match self.tokens.next() {
+            Some(Token::Word(word)) => parse_action(&word)?,
+            other => return Err(format!("expected an action, found {}", Token::describe(other.as_ref()))),
+        };
+        let priority = match self.tokens.next() {
+            None => 0,
+            Some(Token::Word(word)) if word == "priority" => match self.tokens.next() {
+                Some(Token::Number(n)) if n.fract() == 0.0 && (0.0..=MAX_PRIORITY as f32).contains(&n) => n as u32,
+                other => return Err(format!(
+                    "priority must be a whole number from 0 to {}, found {}",
+                    MAX_PRIORITY,
+                    Token::describe(other.as_ref()),
+                )),
+            },
+            other => return Err(format!("expected `priority` or the end of the rule, found {}", Token::describe(other.as_ref()))),
+        };
+        if let Some(extra) = self.tokens.next() {
+            return Err(format!("unexpected {} after the priority", Token::describe(Some(&extra))));
+        }
+        Ok((conditions, action, priority))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), String> {
+        match self.tokens.next() {
+            Some(Token::Word(word)) if word == keyword => Ok(()),
+            other => Err(format!("expected `{}`, found {}", keyword, Token::describe(other.as_ref()))),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let field = match self.tokens.next() {
+            Some(Token::Word(word)) => Field::parse(&word)?,
+            other => return Err(format!("expected a field, found {}", Token::describe(other.as_ref()))),
+        };
+        let test = match self.tokens.next() {
+            Some(Token::Op(op)) => match (self.tokens.next(), field.is_text()) {
+                (Some(Token::Number(n)), false) => Test::Number(op, n),
+                (Some(Token::Text(text)), true) if matches!(op, Op::Eq | Op::Ne) => Test::Text(op, text),
+                (Some(Token::Text(_)), true) => return Err(format!("{} can only be compared with == or !=", field.name())),
+                (other, true) => return Err(format!("{} is compared with text in quotes, found {}", field.name(), Token::describe(other.as_ref()))),
+                (other, false) => return Err(format!("{} is compared with a number, found {}", field.name(), Token::describe(other.as_ref()))),
+            },
+            Some(Token::Word(word)) if word == "in" => self.list(field)?,
+            other => return Err(format!("expected a comparison or `in` after {}, found {}", field.name(), Token::describe(other.as_ref()))),
+        };
+        Ok(Condition { field, test })
+    }
+
+    // A list of texts for text fields, and a [low, high] range for numbers.
+    fn list(&mut self, field: Field) -> Result<Test, String> {
+        match self.tokens.next() {
+            Some(Token::Open) => {}
+            other => return Err(format!("expected `[` after `in`, found {}", Token::describe(other.as_ref()))),
+        }
+        let mut values = Vec::new();
+        loop {
+            values.push(self.tokens.next());
+            match self.tokens.next() {
+                Some(Token::Comma) => continue,
+                Some(Token::Close) => break,
+                other => return Err(format!("expected `,` or `]`, found {}", Token::describe(other.as_ref()))),
+            }
+        }
+        if field.is_text() {
+            let texts = values.into_iter()
+                .map(|value| match value {
+                    Some(Token::Text(text)) => Ok(text),
+                    other => Err(format!("{} is compared with text in quotes, found {}", field.name(), Token::describe(other.as_ref()))),
+                })
+                .collect::<Result<_, _>>()?;
+            return Ok(Test::OneOf(texts));
+        }
+        match values.as_slice() {
+            [Some(Token::Number(low)), Some(Token::Number(high))] if low <= high => Ok(Test::Between(*low, *high)),
+            [Some(Token::Number(_)), Some(Token::Number(_))] => Err(format!("{} range must be [low, high] with low <= high", field.name())),
+            _ => Err(format!("{} in takes a [low, high] range of numbers", field.name())),
+        }
+    }
+}
+
+// Accepts the names the API uses (Stop, SlowDown) as well as the
+// snake_case ones from metrics and the blackbox.
+fn parse_action(word: &str) -> Result<NavigationAction, String> {
+    let actions = [
+        NavigationAction::Continue,
+        NavigationAction::SlowDown,
+        NavigationAction::Stop,
+        NavigationAction::EmergencyStop,
+    ];
+    let wanted = word.replace('_', "").to_ascii_lowercase();
+    actions.into_iter()
+        .find(|action| action.as_str().replace('_', "") == wanted)
+        .ok_or_else(|| format!("unknown action `{}`, expected Continue, SlowDown, Stop or EmergencyStop", word))
+}
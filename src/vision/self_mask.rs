@@ -0,0 +1,158 @@
+// src/vision/self_mask.rs
+use opencv_embedded::core::Rect;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+use crate::config::VisionConfig;
+use crate::zones;
+use super::types::{Detection, NavigationAction};
+
+// A box is sampled on a grid this many points a side to see how much of
+// it the mask covers.
+const SAMPLES: i32 = 8;
+// Boxes overlapping this much are the same thing seen again.
+const SAME_BOX_IOU: f32 = 0.9;
+// At most this many boxes are watched for a proposal; the least seen
+// make room for new ones.
+const MAX_CANDIDATES: usize = 128;
+// Proposed polygons are this much bigger than the box all round.
+const PROPOSAL_MARGIN_PX: i32 = 4;
+
+// vision.self_mask: where the rover's own chassis or antenna is in the
+// frame.
+pub struct SelfMask {
+    polygons: Vec<Vec<(f32, f32)>>,
+    min_overlap: f32,
+}
+
+impl SelfMask {
+    pub fn new(config: &VisionConfig) -> Self {
+        Self { polygons: config.self_mask.clone(), min_overlap: config.self_mask_min_overlap }
+    }
+
+    // How much of the box, 0-1, lies in any of the polygons.
+    pub fn coverage(&self, bbox: (i32, i32, i32, i32)) -> f32 {
+        let (x, y, w, h) = bbox;
+        if self.polygons.is_empty() || w <= 0 || h <= 0 {
+            return 0.0;
+        }
+        let mut inside = 0;
+        for i in 0..SAMPLES {
+            for j in 0..SAMPLES {
+                let px = x as f32 + (i as f32 + 0.5) * w as f32 / SAMPLES as f32;
+                let py = y as f32 + (j as f32 + 0.5) * h as f32 / SAMPLES as f32;
+                if self.polygons.iter().any(|p| zones::contains(p, px, py)) {
+                    inside += 1;
+                }
+            }
+        }
+        inside as f32 / (SAMPLES * SAMPLES) as f32
+    }
+
+    // Tags the detections mostly inside the mask and takes them out. An
+    // emergency stop is never taken out.
+    pub fn split(&self, detections: Vec<Detection>) -> (Vec<Detection>, Vec<Detection>) {
+        if self.polygons.is_empty() {
+            return (detections, Vec::new());
+        }
+        detections.into_iter()
+            .map(|mut d| {
+                d.self_occlusion = d.action != NavigationAction::EmergencyStop && self.coverage(d.bbox) >= self.min_overlap;
+                d
+            })
+            .partition(|d| !d.self_occlusion)
+    }
+}
+
+struct Candidate {
+    bbox: Rect,
+    classes: BTreeSet<String>,
+    // Frames it was seen in, and the frame count when it was last seen.
+    seen: u64,
+    last_frame: u64,
+}
+
+// A box seen in the same place over and over, and the polygon that would
+// mask it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProposedRegion {
+    pub bbox: (i32, i32, i32, i32),
+    pub classes: Vec<String>,
+    pub frames_seen: u64,
+    pub polygon: Vec<(f32, f32)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MaskProposal {
+    pub frames_observed: u64,
+    pub min_frames: u64,
+    pub regions: Vec<ProposedRegion>,
+}
+
+// Watches detections, before the mask, for boxes that stay put. Nothing
+// drives the rover's own antenna across the frame, so a box found in the
+// same place in hundreds of frames, whatever it was called, is most
+// likely part of the rover.
+#[derive(Default)]
+pub struct MaskProposer {
+    frames: u64,
+    candidates: Vec<Candidate>,
+}
+
+impl MaskProposer {
+    pub fn observe(&mut self, detections: &[Detection]) {
+        self.frames += 1;
+        for detection in detections {
+            let (x, y, w, h) = detection.bbox;
+            let rect = Rect::new(x, y, w, h);
+            let matched = self.candidates.iter_mut()
+                .filter(|c| c.last_frame != self.frames)
+                .map(|c| (rect.iou(&c.bbox), c))
+                .filter(|(iou, _)| *iou >= SAME_BOX_IOU)
+                .max_by(|a, b| a.0.total_cmp(&b.0));
+            if let Some((_, candidate)) = matched {
+                candidate.seen += 1;
+                candidate.last_frame = self.frames;
+                candidate.classes.insert(detection.class_name.clone());
+                continue;
+            }
+            if self.candidates.len() == MAX_CANDIDATES {
+                let Some(weakest) = self.candidates.iter().enumerate()
+                    .min_by_key(|(_, c)| (c.seen, c.last_frame))
+                    .map(|(i, _)| i) else { continue };
+                self.candidates.swap_remove(weakest);
+            }
+            self.candidates.push(Candidate {
+                bbox: rect,
+                classes: BTreeSet::from([detection.class_name.clone()]),
+                seen: 1,
+                last_frame: self.frames,
+            });
+        }
+    }
+
+    // Boxes seen in at least `min_frames` frames, most seen first.
+    pub fn propose(&self, min_frames: u64) -> MaskProposal {
+        let mut found: Vec<&Candidate> = self.candidates.iter().filter(|c| c.seen >= min_frames).collect();
+        found.sort_by_key(|c| std::cmp::Reverse(c.seen));
+        MaskProposal {
+            frames_observed: self.frames,
+            min_frames,
+            regions: found.into_iter().map(|c| {
+                let Rect { x, y, width, height } = c.bbox;
+                let (x0, y0) = ((x - PROPOSAL_MARGIN_PX) as f32, (y - PROPOSAL_MARGIN_PX) as f32);
+                let (x1, y1) = ((x + width + PROPOSAL_MARGIN_PX) as f32, (y + height + PROPOSAL_MARGIN_PX) as f32);
+                ProposedRegion {
+                    bbox: (x, y, width, height),
+                    classes: c.classes.iter().cloned().collect(),
+                    frames_seen: c.seen,
+                    polygon: vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)],
+                }
+            }).collect(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
@@ -0,0 +1,197 @@
+// src/vision/shm.rs
+//
+// The rover's side of the shared-memory frame transport. The bridge
+// creates a ring of slots, names it in its hello and writes each JPEG, or
+// raw YUV420 frame when asked for and able to, into a free slot; the frame
+// line only says which slot and what is in it, and the rover acks the slot
+// on the bridge's stdin once it is done with the frame.
+//
+// Each slot starts with a 16-byte header: the sequence number of the
+// frame in it (u64 little-endian, 0 while it is being written), the
+// frame's length in bytes (u32) and 4 unused bytes. The bridge only rewrites a slot
+// that has been acked, or that it gave up waiting on, and the header is
+// checked before and after every read, so a frame rewritten meanwhile is
+// dropped rather than used. Frames are copied out of the slot, never
+// borrowed from it, since the bridge may be writing there at any time.
+//
+// The ring must stay at least as long as it was when the rover mapped
+// it: reading a page past the end of a shrunk memfd is a SIGBUS. The
+// bridges seal the memfd so it can't shrink; a ring without that seal
+// has its size checked before every read.
+use anyhow::{bail, Context, Result};
+use crossbeam_channel::Sender;
+use serde::Deserialize;
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{fence, Ordering};
+use std::sync::Arc;
+
+use crate::config::PixelFormat;
+use super::BridgeControl;
+
+pub const SLOT_HEADER_BYTES: usize = 16;
+
+// Under "shm" in the hello of a bridge that set a ring up.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RingInfo {
+    // Anything the rover can open and map, such as /proc/<pid>/fd/<n> of
+    // a memfd.
+    pub path: String,
+    pub slots: usize,
+    pub slot_bytes: usize,
+}
+
+// Under "shm" in a frame line, in place of the base64 JPEG. Bridges from
+// before raw frames only ever put JPEGs in the ring and leave out the
+// format and size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct SlotRef {
+    pub slot: usize,
+    pub seq: u64,
+    pub length: usize,
+    #[serde(default)]
+    pub format: PixelFormat,
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+}
+
+pub struct ShmRing {
+    base: *const u8,
+    mapped: usize,
+    slots: usize,
+    slot_bytes: usize,
+    file: File,
+    // Whether the bridge sealed the memfd against shrinking.
+    sealed: bool,
+}
+
+// SAFETY: `base` is a read-only mapping this ring owns and only unmaps
+// on drop. The only accesses through it are volatile header reads and
+// the copy in read(), which leave no reference into the mapping behind
+// and take &self, so any number of threads can make them at once.
+unsafe impl Send for ShmRing {}
+unsafe impl Sync for ShmRing {}
+
+impl ShmRing {
+    pub fn open(info: &RingInfo) -> Result<Self> {
+        if info.slots == 0 || info.slot_bytes <= SLOT_HEADER_BYTES {
+            bail!("a ring of {} slots of {} bytes holds no frames", info.slots, info.slot_bytes);
+        }
+        let mapped = info.slots.checked_mul(info.slot_bytes).context("frame ring is too large")?;
+        let file = File::open(&info.path).with_context(|| format!("Failed to open {}", info.path))?;
+        let size = file.metadata()?.len();
+        if size < mapped as u64 {
+            bail!("{} is {} bytes, the ring needs {}", info.path, size, mapped);
+        }
+        // SAFETY: a new read-only shared mapping of the whole ring, which
+        // outlives the file it was made from.
+        let base = unsafe {
+            libc::mmap(std::ptr::null_mut(), mapped, libc::PROT_READ, libc::MAP_SHARED, file.as_raw_fd(), 0)
+        };
+        if base == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error()).context("Failed to map the frame ring");
+        }
+        // SAFETY: F_GET_SEALS only reads the file's seals; anything but a
+        // memfd answers with an error, which counts as unsealed.
+        let seals = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GET_SEALS) };
+        let sealed = seals >= 0 && seals & libc::F_SEAL_SHRINK != 0;
+        Ok(Self { base: base as *const u8, mapped, slots: info.slots, slot_bytes: info.slot_bytes, file, sealed })
+    }
+
+    fn check_size(&self) -> Result<()> {
+        if self.sealed {
+            return Ok(());
+        }
+        let size = self.file.metadata()?.len();
+        if size < self.mapped as u64 {
+            bail!("the frame ring shrank to {} bytes from {}", size, self.mapped);
+        }
+        Ok(())
+    }
+
+    fn header(&self, slot: usize) -> (u64, usize) {
+        // SAFETY: callers check slot < self.slots, so the header is inside
+        // the mapping.
+        unsafe {
+            let at = self.base.add(slot * self.slot_bytes);
+            let seq = std::ptr::read_volatile(at as *const [u8; 8]);
+            let length = std::ptr::read_volatile(at.add(8) as *const [u8; 4]);
+            (u64::from_le_bytes(seq), u32::from_le_bytes(length) as usize)
+        }
+    }
+
+    fn check(&self, slot: &SlotRef) -> Result<()> {
+        fence(Ordering::Acquire);
+        let (seq, length) = self.header(slot.slot);
+        if seq != slot.seq || length != slot.length {
+            bail!("slot {} holds frame {} now, not {}", slot.slot, seq, slot.seq);
+        }
+        Ok(())
+    }
+
+    // Copies the frame out of its slot. Fails if the slot didn't hold that
+    // frame both before and after the copy.
+    pub fn read(&self, slot: &SlotRef) -> Result<Vec<u8>> {
+        if slot.slot >= self.slots || slot.length > self.slot_bytes - SLOT_HEADER_BYTES {
+            bail!("slot {} of {} bytes is outside the ring", slot.slot, slot.length);
+        }
+        self.check_size()?;
+        self.check(slot)?;
+        let mut frame = vec![0u8; slot.length];
+        // SAFETY: inside the mapping, as checked above, and the file still
+        // covers all of it. The bridge leaves a slot alone until it has it
+        // back; one that doesn't tears the copy, which the check afterwards
+        // catches before anything sees it.
+        unsafe {
+            let at = self.base.add(slot.slot * self.slot_bytes + SLOT_HEADER_BYTES);
+            std::ptr::copy_nonoverlapping(at, frame.as_mut_ptr(), slot.length);
+        }
+        self.check(slot).context("rewritten while it was read")?;
+        Ok(frame)
+    }
+}
+
+impl Drop for ShmRing {
+    fn drop(&mut self) {
+        // SAFETY: the mapping made in open(); nothing borrows from it once
+        // the last SharedFrame holding the ring is gone.
+        unsafe {
+            libc::munmap(self.base as *mut libc::c_void, self.mapped);
+        }
+    }
+}
+
+// A frame's picture, still in its slot. Dropping it hands the slot back.
+pub struct SharedFrame {
+    ring: Arc<ShmRing>,
+    slot: SlotRef,
+    acks: Sender<BridgeControl>,
+}
+
+impl SharedFrame {
+    pub(super) fn new(ring: Arc<ShmRing>, slot: SlotRef, acks: Sender<BridgeControl>) -> Self {
+        Self { ring, slot, acks }
+    }
+
+    pub fn get_slot(&self) -> &SlotRef {
+        &self.slot
+    }
+
+    pub fn read(&self) -> Result<Vec<u8>> {
+        self.ring.read(&self.slot)
+    }
+}
+
+impl Drop for SharedFrame {
+    fn drop(&mut self) {
+        ack(&self.acks, &self.slot);
+    }
+}
+
+// Tells the bridge it can have the slot back.
+pub(super) fn ack(acks: &Sender<BridgeControl>, slot: &SlotRef) {
+    let ack = serde_json::json!({ "type": "ack", "slot": slot.slot, "seq": slot.seq });
+    let _ = acks.send(BridgeControl::Once(ack.to_string()));
+}
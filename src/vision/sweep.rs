@@ -0,0 +1,250 @@
+// src/vision/sweep.rs
+//
+// Threshold sweeps, for choosing vision.min_confidence from what the camera
+// sees rather than by redeploying. While one runs, the vision system keeps
+// detections down to the sweep's floor, marked below_threshold and never
+// steered by, and notes each new frame's detections before the filters as
+// confidence and action. A report counts, at each candidate threshold, the
+// detections there would have been and the frames on which one would have
+// slowed or stopped the rover. A sweep ends by itself, and the notes are
+// capped in frames and per frame.
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::VecDeque;
+use tracing::info;
+
+use crate::config::SweepConfig;
+use crate::events::{Severity, EVENTS};
+use super::types::{Detection, NavigationAction};
+
+// More candidate thresholds than this in one report are refused.
+pub const MAX_THRESHOLDS: usize = 64;
+
+struct FrameNote {
+    capture_ms: u64,
+    // Surest first.
+    detections: Vec<(f32, NavigationAction)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SweepStatus {
+    pub active: bool,
+    pub floor: f32,
+    // Rover-clock ms of the last sweep; None before the first.
+    pub started_ms: Option<u64>,
+    pub ends_ms: Option<u64>,
+    pub remaining_s: f32,
+    // Frames noted since it started, at most vision.sweep.max_frames.
+    pub frames: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ThresholdCounts {
+    pub threshold: f32,
+    pub detections: u64,
+    // Frames whose most cautious detection at this threshold would have
+    // slowed or stopped the rover, and which of those it would have been.
+    pub decisions: u64,
+    pub slow_down: u64,
+    pub stop: u64,
+    pub emergency_stop: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SweepReport {
+    #[serde(flatten)]
+    pub status: SweepStatus,
+    // The threshold in use, to compare against.
+    pub min_confidence: f32,
+    // The counts are over the last `minutes`, or every frame noted without.
+    pub minutes: Option<f32>,
+    pub window_frames: usize,
+    pub thresholds: Vec<ThresholdCounts>,
+}
+
+pub struct ThresholdSweep {
+    config: SweepConfig,
+    active: bool,
+    floor: f32,
+    started_ms: Option<u64>,
+    ends_ms: Option<u64>,
+    notes: VecDeque<FrameNote>,
+}
+
+impl ThresholdSweep {
+    pub fn new(config: &SweepConfig) -> Self {
+        Self {
+            config: config.clone(),
+            active: false,
+            floor: config.floor,
+            started_ms: None,
+            ends_ms: None,
+            notes: VecDeque::new(),
+        }
+    }
+
+    // New caps apply to the notes there are; a sweep under way keeps the
+    // floor and end it started with.
+    pub fn apply_config(&mut self, config: &SweepConfig) {
+        self.config = config.clone();
+        self.trim();
+    }
+
+    // Starts afresh, dropping the last sweep's notes. The duration and floor
+    // default to vision.sweep's.
+    pub fn start(&mut self, duration_s: Option<f32>, floor: Option<f32>, now_ms: u64) -> Result<SweepStatus> {
+        let duration_s = duration_s.unwrap_or(self.config.duration_s);
+        if !(1.0..=self.config.max_duration_s).contains(&duration_s) {
+            anyhow::bail!("duration_s must be between 1 and {}", self.config.max_duration_s);
+        }
+        let floor = floor.unwrap_or(self.config.floor);
+        if !(0.01..=1.0).contains(&floor) {
+            anyhow::bail!("floor must be between 0.01 and 1");
+        }
+        self.active = true;
+        self.floor = floor;
+        self.started_ms = Some(now_ms);
+        self.ends_ms = Some(now_ms + (duration_s * 1000.0) as u64);
+        self.notes.clear();
+        info!(target: "vision", "Threshold sweep started: detections down to {} for {}s", floor, duration_s);
+        EVENTS.publish(
+            Severity::Info,
+            "vision",
+            format!("Threshold sweep started, down to {} for {}s", floor, duration_s),
+            serde_json::json!({ "floor": floor, "duration_s": duration_s }),
+        );
+        Ok(self.get_status(now_ms))
+    }
+
+    // Ends the sweep under way; its notes stay for reports until the next
+    // one starts. False if there was none.
+    pub fn stop(&mut self, now_ms: u64) -> bool {
+        if !self.active {
+            return false;
+        }
+        self.finish(now_ms, "stopped");
+        true
+    }
+
+    // The floor while a sweep runs. One whose time is up ends here, so
+    // nothing below min_confidence outlives it by a frame.
+    pub fn get_floor(&mut self, now_ms: u64) -> Option<f32> {
+        self.expire(now_ms);
+        self.active.then_some(self.floor)
+    }
+
+    // A new frame's detections, kept and below threshold alike, before the
+    // filters.
+    pub fn record<'a>(&mut self, capture_ms: u64, detections: impl Iterator<Item = &'a Detection>) {
+        if !self.active {
+            return;
+        }
+        let mut noted: Vec<(f32, NavigationAction)> = detections.map(|d| (d.confidence, d.action)).collect();
+        noted.sort_by(|a, b| b.0.total_cmp(&a.0));
+        noted.truncate(self.config.max_per_frame);
+        self.notes.push_back(FrameNote { capture_ms, detections: noted });
+        self.trim();
+    }
+
+    pub fn get_status(&mut self, now_ms: u64) -> SweepStatus {
+        self.expire(now_ms);
+        let remaining_ms = match (self.active, self.ends_ms) {
+            (true, Some(ends_ms)) => ends_ms.saturating_sub(now_ms),
+            _ => 0,
+        };
+        SweepStatus {
+            active: self.active,
+            floor: self.floor,
+            started_ms: self.started_ms,
+            ends_ms: self.ends_ms,
+            remaining_s: remaining_ms as f32 / 1000.0,
+            frames: self.notes.len(),
+        }
+    }
+
+    // Counts at each of `thresholds`, by default every 0.05 from the floor
+    // up and min_confidence, over the last `minutes` of notes.
+    pub fn report(&mut self, now_ms: u64, minutes: Option<f32>, thresholds: Option<Vec<f32>>, min_confidence: f32) -> Result<SweepReport> {
+        if minutes.is_some_and(|m| !(m.is_finite() && m > 0.0)) {
+            anyhow::bail!("minutes must be a positive number");
+        }
+        let mut thresholds = match thresholds {
+            Some(thresholds) => {
+                if thresholds.is_empty() || thresholds.len() > MAX_THRESHOLDS {
+                    anyhow::bail!("give between 1 and {} thresholds", MAX_THRESHOLDS);
+                }
+                if thresholds.iter().any(|t| !(self.floor..=1.0).contains(t)) {
+                    anyhow::bail!("thresholds must be between the sweep floor ({}) and 1", self.floor);
+                }
+                thresholds
+            }
+            None => default_thresholds(self.floor, min_confidence),
+        };
+        thresholds.sort_by(f32::total_cmp);
+        thresholds.dedup();
+
+        let status = self.get_status(now_ms);
+        let since_ms = minutes.map(|m| now_ms.saturating_sub((m * 60_000.0) as u64));
+        let window: Vec<&FrameNote> = self.notes.iter().filter(|n| since_ms.map_or(true, |since| n.capture_ms >= since)).collect();
+        let thresholds = thresholds.into_iter().map(|threshold| count(&window, threshold)).collect();
+        Ok(SweepReport {
+            status,
+            min_confidence,
+            minutes,
+            window_frames: window.len(),
+            thresholds,
+        })
+    }
+
+    fn expire(&mut self, now_ms: u64) {
+        if self.active && self.ends_ms.is_some_and(|ends_ms| now_ms >= ends_ms) {
+            self.finish(now_ms, "ended");
+        }
+    }
+
+    fn finish(&mut self, now_ms: u64, how: &str) {
+        self.active = false;
+        self.ends_ms = self.ends_ms.map(|ends_ms| ends_ms.min(now_ms));
+        info!(target: "vision", "Threshold sweep {} after {} frames", how, self.notes.len());
+        EVENTS.publish(
+            Severity::Info,
+            "vision",
+            format!("Threshold sweep {}", how),
+            serde_json::json!({ "frames": self.notes.len() }),
+        );
+    }
+
+    fn trim(&mut self) {
+        while self.notes.len() > self.config.max_frames {
+            self.notes.pop_front();
+        }
+    }
+}
+
+fn count(window: &[&FrameNote], threshold: f32) -> ThresholdCounts {
+    let mut counts = ThresholdCounts { threshold, ..Default::default() };
+    for note in window {
+        let kept = note.detections.iter().take_while(|(confidence, _)| *confidence >= threshold);
+        let mut decision = NavigationAction::Continue;
+        for (_, action) in kept {
+            counts.detections += 1;
+            decision = decision.max(*action);
+        }
+        match decision {
+            NavigationAction::Continue => continue,
+            NavigationAction::SlowDown => counts.slow_down += 1,
+            NavigationAction::Stop => counts.stop += 1,
+            NavigationAction::EmergencyStop => counts.emergency_stop += 1,
+        }
+        counts.decisions += 1;
+    }
+    counts
+}
+
+fn default_thresholds(floor: f32, min_confidence: f32) -> Vec<f32> {
+    let mut thresholds: Vec<f32> = (1..20).map(|i| i as f32 / 20.0).filter(|t| *t >= floor).collect();
+    if min_confidence >= floor {
+        thresholds.push(min_confidence);
+    }
+    thresholds
+}
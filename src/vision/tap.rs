@@ -0,0 +1,313 @@
+// src/vision/tap.rs
+//
+// Bridge diagnostics: while switched on over the API, every line the bridge
+// writes on stdout goes through here with how it parsed, and is teed to
+// rotating files at a chosen level of detail. They switch themselves off
+// after a while so a forgotten session can't fill the SD card, and with
+// them off a line costs one atomic load.
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::clock::CLOCK;
+use crate::config::VisionConfig;
+use crate::vision::protocol;
+
+pub static BRIDGE_TAP: Lazy<BridgeTap> = Lazy::new(BridgeTap::new);
+
+pub const FILE_NAME: &str = "bridge-lines.jsonl";
+pub const MAX_MINUTES: f32 = 240.0;
+// Lines per second are counted over this much of the latest lines.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TapLevel {
+    // Only the lines that fail to parse, whole.
+    Failures,
+    // Every line's time, outcome and size; the failures whole.
+    Outcomes,
+    // Every line whole.
+    Full,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TapError {
+    pub at_ms: u64,
+    pub error: String,
+    pub bytes: usize,
+    pub excerpt: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TapSummary {
+    pub enabled: bool,
+    // Of the session running, or else of the last one; None before any.
+    pub level: Option<TapLevel>,
+    pub started_ms: Option<u64>,
+    pub expires_in_s: Option<f32>,
+    pub lines: u64,
+    pub lines_per_s: f32,
+    pub failures: u64,
+    pub failure_ratio: Option<f32>,
+    pub bytes: u64,
+    pub largest_line_bytes: usize,
+    pub last_error: Option<TapError>,
+    // Where the lines go, and how much of the disk they may take.
+    pub path: Option<PathBuf>,
+    pub written_bytes: u64,
+    pub max_disk_bytes: u64,
+    // Why the files stopped being written to, if they did.
+    pub write_error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TapLine<'a> {
+    t_ms: u64,
+    ok: bool,
+    bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<&'a str>,
+}
+
+struct Session {
+    level: TapLevel,
+    started: Instant,
+    started_ms: u64,
+    until: Instant,
+    lines: u64,
+    failures: u64,
+    bytes: u64,
+    largest_line_bytes: usize,
+    recent: VecDeque<Instant>,
+    last_error: Option<TapError>,
+    files: Option<RotatingFile>,
+    path: PathBuf,
+    max_disk_bytes: u64,
+    write_error: Option<String>,
+}
+
+pub struct BridgeTap {
+    // Checked on every line; the session is only locked while it is set.
+    enabled: AtomicBool,
+    session: Mutex<Option<Session>>,
+}
+
+impl BridgeTap {
+    fn new() -> Self {
+        Self { enabled: AtomicBool::new(false), session: Mutex::new(None) }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    // Starts a session afresh, replacing one that is running. `minutes`
+    // defaults to vision.bridge_tap_minutes.
+    pub fn enable(&self, level: TapLevel, minutes: Option<f32>, config: &VisionConfig) -> Result<TapSummary> {
+        let minutes = minutes.unwrap_or(config.bridge_tap_minutes);
+        if !(minutes > 0.0 && minutes <= MAX_MINUTES) {
+            anyhow::bail!("minutes must be over 0 and at most {}", MAX_MINUTES);
+        }
+        let dir = PathBuf::from(&config.bridge_tap_dir);
+        let file_bytes = config.bridge_tap_file_mb * 1024 * 1024;
+        let files = RotatingFile::open(&dir, file_bytes, config.bridge_tap_files)?;
+        let now = Instant::now();
+        *self.session.lock() = Some(Session {
+            level,
+            started: now,
+            started_ms: CLOCK.wall_ms(),
+            until: now + Duration::from_secs_f32(minutes * 60.0),
+            lines: 0,
+            failures: 0,
+            bytes: 0,
+            largest_line_bytes: 0,
+            recent: VecDeque::new(),
+            last_error: None,
+            path: files.path.clone(),
+            files: Some(files),
+            max_disk_bytes: file_bytes * config.bridge_tap_files as u64,
+            write_error: None,
+        });
+        self.enabled.store(true, Ordering::Relaxed);
+        info!(target: "vision", "Bridge diagnostics on at {:?} level for {:.0} min, to {}", level, minutes, dir.display());
+        Ok(self.get_summary())
+    }
+
+    // The session's figures stay for the summary.
+    pub fn disable(&self) -> TapSummary {
+        if self.enabled.swap(false, Ordering::Relaxed) {
+            if let Some(session) = self.session.lock().as_mut() {
+                session.close();
+            }
+            info!(target: "vision", "Bridge diagnostics off");
+        }
+        self.get_summary()
+    }
+
+    // For each line off the bridge's stdout, with the error it failed to
+    // parse with.
+    pub fn observe(&self, line: &str, error: Option<&anyhow::Error>) {
+        if !self.is_enabled() {
+            return;
+        }
+        let now = Instant::now();
+        let mut guard = self.session.lock();
+        let Some(session) = guard.as_mut() else { return };
+        if now >= session.until {
+            session.close();
+            self.enabled.store(false, Ordering::Relaxed);
+            info!(target: "vision", "Bridge diagnostics expired after {} lines", session.lines);
+            return;
+        }
+        session.observe(now, line, error.map(|e| format!("{:#}", e)).as_deref());
+    }
+
+    pub fn get_summary(&self) -> TapSummary {
+        let now = Instant::now();
+        let guard = self.session.lock();
+        let enabled = self.is_enabled() && guard.as_ref().is_some_and(|s| now < s.until);
+        let Some(session) = guard.as_ref() else {
+            return TapSummary {
+                enabled,
+                level: None,
+                started_ms: None,
+                expires_in_s: None,
+                lines: 0,
+                lines_per_s: 0.0,
+                failures: 0,
+                failure_ratio: None,
+                bytes: 0,
+                largest_line_bytes: 0,
+                last_error: None,
+                path: None,
+                written_bytes: 0,
+                max_disk_bytes: 0,
+                write_error: None,
+            };
+        };
+        // Over the window, or however much of it the session has run.
+        let window = now.duration_since(session.started).min(RATE_WINDOW).as_secs_f32();
+        let recent = session.recent.iter().filter(|&&at| now.duration_since(at) <= RATE_WINDOW).count();
+        TapSummary {
+            enabled,
+            level: Some(session.level),
+            started_ms: Some(session.started_ms),
+            expires_in_s: enabled.then(|| session.until.duration_since(now).as_secs_f32()),
+            lines: session.lines,
+            lines_per_s: if enabled && window > 0.0 { recent as f32 / window } else { 0.0 },
+            failures: session.failures,
+            failure_ratio: (session.lines > 0).then(|| session.failures as f32 / session.lines as f32),
+            bytes: session.bytes,
+            largest_line_bytes: session.largest_line_bytes,
+            last_error: session.last_error.clone(),
+            path: Some(session.path.clone()),
+            written_bytes: session.files.as_ref().map_or(0, RotatingFile::get_written_bytes),
+            max_disk_bytes: session.max_disk_bytes,
+            write_error: session.write_error.clone(),
+        }
+    }
+}
+
+impl Session {
+    fn observe(&mut self, now: Instant, line: &str, error: Option<&str>) {
+        self.lines += 1;
+        self.bytes += line.len() as u64;
+        self.largest_line_bytes = self.largest_line_bytes.max(line.len());
+        self.recent.push_back(now);
+        while self.recent.front().is_some_and(|&at| now.duration_since(at) > RATE_WINDOW) {
+            self.recent.pop_front();
+        }
+        let t_ms = CLOCK.wall_ms();
+        if let Some(error) = error {
+            self.failures += 1;
+            self.last_error = Some(TapError { at_ms: t_ms, error: error.to_string(), bytes: line.len(), excerpt: protocol::truncate(line) });
+        }
+
+        let record = match (self.level, error) {
+            (TapLevel::Failures, None) => return,
+            (TapLevel::Outcomes, None) => TapLine { t_ms, ok: true, bytes: line.len(), error: None, line: None },
+            _ => TapLine { t_ms, ok: error.is_none(), bytes: line.len(), error, line: Some(line) },
+        };
+        let Some(files) = self.files.as_mut() else { return };
+        if let Err(e) = files.write(&record) {
+            warn!(target: "vision", "Bridge diagnostics stopped writing: {:#}", e);
+            self.write_error = Some(format!("{:#}", e));
+            self.files = None;
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(files) = self.files.as_mut() {
+            let _ = files.file.flush();
+        }
+        self.until = Instant::now();
+    }
+}
+
+// FILE_NAME, moved to FILE_NAME.1 when it reaches max_bytes, that to .2
+// and so on, the oldest past `keep` files deleted.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    keep: usize,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn open(dir: &Path, max_bytes: u64, keep: usize) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let path = dir.join(FILE_NAME);
+        let file = OpenOptions::new().create(true).append(true).open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        let mut files = Self { path, file, size, max_bytes, keep: keep.max(1), written: 0 };
+        if files.size >= max_bytes {
+            files.rotate()?;
+        }
+        Ok(files)
+    }
+
+    fn get_written_bytes(&self) -> u64 {
+        self.written
+    }
+
+    fn write(&mut self, record: &TapLine) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(&line).with_context(|| format!("Failed to write {}", self.path.display()))?;
+        self.size += line.len() as u64;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        let numbered = |n: usize| self.path.with_file_name(format!("{}.{}", FILE_NAME, n));
+        let _ = fs::remove_file(numbered(self.keep - 1));
+        for n in (1..self.keep - 1).rev() {
+            let _ = fs::rename(numbered(n), numbered(n + 1));
+        }
+        if self.keep > 1 {
+            fs::rename(&self.path, numbered(1)).with_context(|| format!("Failed to rotate {}", self.path.display()))?;
+        }
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        self.size = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+// src/vision/types.rs
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::config::VisionConfig;
+use super::ground::GroundProjection;
+use super::labels::{LabelMap, LabelRule};
+use super::protocol::DetectionMask;
+use super::FRAME_HEIGHT_PX;
+
+// A box bottom this close to the frame's is taken to be cut off by it.
+const FRAME_EDGE_PX: f32 = 2.0;
+
+// What every detection backend hands the rest of the rover. The field
+// names are the JSON dashboards receive, so renaming any of them is a
+// breaking change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Detection {
+    pub class_name: String,
+    // Calibrated onto the scale shared by all backends; thresholds use it.
+    pub confidence: f32,
+    // As the backend reported it.
+    #[serde(default)]
+    pub raw_confidence: f32,
+    pub bbox: (i32, i32, i32, i32),
+    pub distance_estimate: f32,
+    pub action: NavigationAction,
+    // Rover-clock ms at which the frame was captured.
+    #[serde(default)]
+    pub capture_ms: u64,
+    // The same object across frames, once the tracker filter has run.
+    #[serde(default)]
+    pub track_id: Option<u32>,
+    // From v2 bridges that segment what they detect.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask: Option<DetectionMask>,
+    // Mostly inside vision.self_mask, so the rover's own body; shown but
+    // never steered by.
+    #[serde(default)]
+    pub self_occlusion: bool,
+    // Under vision.min_confidence, kept only while a threshold sweep runs
+    // and never steered by either.
+    #[serde(default)]
+    pub below_threshold: bool,
+    // Where the centre of the box's bottom edge meets the floor, in metres
+    // to the right of the lens and ahead of it, with a ground calibration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ground_position: Option<(f32, f32)>,
+}
+
+impl Detection {
+    // Distance and action both follow from the box and the class, so
+    // backends only say what they saw.
+    pub fn new(
+        class_name: String,
+        raw_confidence: f32,
+        bbox: (i32, i32, i32, i32),
+        capture_ms: u64,
+        calibration: &ConfidenceCurve,
+        distance: &DistanceEstimator,
+        policy: &ActionPolicy,
+    ) -> Self {
+        let distance_estimate = distance.estimate(bbox.3 as f32, &class_name);
+        let action = policy.action(&class_name, distance_estimate);
+        Self {
+            class_name,
+            confidence: calibration.apply(raw_confidence),
+            raw_confidence,
+            bbox,
+            distance_estimate,
+            action,
+            capture_ms,
+            track_id: None,
+            mask: None,
+            self_occlusion: false,
+            below_threshold: false,
+            ground_position: None,
+        }
+    }
+
+    // Takes the distance from the mask where it is close enough to the
+    // box's, and the action with it. Returns whether it was.
+    pub fn set_mask(&mut self, mask: DetectionMask, distance: &DistanceEstimator, policy: &ActionPolicy) -> bool {
+        let from_mask = distance.estimate_with_mask(self.bbox.3 as f32, &mask, &self.class_name);
+        if let Some(distance_estimate) = from_mask {
+            self.distance_estimate = distance_estimate;
+            self.action = policy.action(&self.class_name, distance_estimate);
+        }
+        self.mask = Some(mask);
+        from_mask.is_some()
+    }
+
+    // Places the box where its bottom edge meets the floor, and takes the
+    // distance and action from there, for a box whose bottom edge is in
+    // the frame and below the horizon. A box cut off by the bottom of the
+    // frame stands on floor the camera can't see, nearer than any it can.
+    pub fn set_ground(&mut self, ground: &GroundProjection, policy: &ActionPolicy) -> bool {
+        let (x, y, w, h) = self.bbox;
+        let bottom = (y + h) as f32;
+        if h <= 0 || bottom >= FRAME_HEIGHT_PX - FRAME_EDGE_PX {
+            return false;
+        }
+        let Some((right_m, ahead_m)) = ground.project_to_ground(x as f32 + w as f32 / 2.0, bottom) else { return false };
+        self.ground_position = Some((right_m, ahead_m));
+        self.distance_estimate = right_m.hypot(ahead_m);
+        self.action = policy.action(&self.class_name, self.distance_estimate);
+        true
+    }
+}
+
+// Ordered from least to most cautious.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum NavigationAction {
+    Continue,
+    SlowDown,
+    Stop,
+    EmergencyStop,
+}
+
+impl NavigationAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NavigationAction::Continue => "continue",
+            NavigationAction::SlowDown => "slow_down",
+            NavigationAction::Stop => "stop",
+            NavigationAction::EmergencyStop => "emergency_stop",
+        }
+    }
+}
+
+// Backends report confidence on their own scales; this maps one onto the
+// shared scale through (raw, calibrated) points, linearly in between and
+// flat beyond the ends. Without points it leaves confidences alone.
+#[derive(Debug, Clone, Default)]
+pub struct ConfidenceCurve {
+    points: Vec<(f32, f32)>,
+}
+
+impl ConfidenceCurve {
+    // `source` is one of config::DETECTION_SOURCES.
+    pub fn new(config: &VisionConfig, source: &str) -> Self {
+        Self::from_points(config.confidence_calibration.get(source).cloned().unwrap_or_default())
+    }
+
+    pub fn from_points(mut points: Vec<(f32, f32)>) -> Self {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { points }
+    }
+
+    pub fn apply(&self, raw: f32) -> f32 {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else { return raw };
+        if raw <= first.0 {
+            return first.1;
+        }
+        if raw >= last.0 {
+            return last.1;
+        }
+        let i = self.points.partition_point(|p| p.0 <= raw);
+        let ((x0, y0), (x1, y1)) = (self.points[i - 1], self.points[i]);
+        y0 + (y1 - y0) * (raw - x0) / (x1 - x0)
+    }
+}
+
+// Pinhole estimate from a box's pixel height and the class's real height.
+#[derive(Debug, Clone)]
+pub struct DistanceEstimator {
+    focal_length_px: f32,
+    default_object_height_m: f32,
+    object_heights_m: BTreeMap<String, f32>,
+    mask_expected_fill: f32,
+    mask_max_disagreement: f32,
+}
+
+impl DistanceEstimator {
+    pub fn new(config: &VisionConfig) -> Self {
+        Self {
+            focal_length_px: config.focal_length_px,
+            default_object_height_m: config.default_object_height_m,
+            object_heights_m: config.object_heights_m.clone(),
+            mask_expected_fill: config.mask_expected_fill,
+            mask_max_disagreement: config.mask_max_disagreement,
+        }
+    }
+
+    // Heights from the label map take precedence over the config's.
+    pub fn with_labels(mut self, labels: &LabelMap) -> Self {
+        for label in labels.iter() {
+            if let Some(height) = label.height_m {
+                self.object_heights_m.insert(label.name.clone(), height);
+            }
+        }
+        self
+    }
+
+    // A box with no height is treated as far away.
+    pub fn estimate(&self, height_px: f32, class_name: &str) -> f32 {
+        let real_height = self.object_heights_m.get(class_name)
+            .copied()
+            .unwrap_or(self.default_object_height_m);
+        if height_px > 0.0 {
+            (real_height * self.focal_length_px) / height_px
+        } else {
+            10.0
+        }
+    }
+
+    // A box is loose around most objects, so the mask's area says more
+    // about how big the object looks than the box's height: the height
+    // used is the box's scaled by how far the mask's fill is from the
+    // expected one. None when that puts it more than mask_max_disagreement
+    // times nearer or further than the box does, which is more likely a bad
+    // mask than a bad box.
+    pub fn estimate_with_mask(&self, height_px: f32, mask: &DetectionMask, class_name: &str) -> Option<f32> {
+        let fill = mask.get_fill();
+        if fill <= 0.0 || height_px <= 0.0 {
+            return None;
+        }
+        let ratio = (self.mask_expected_fill / fill).sqrt();
+        if ratio > self.mask_max_disagreement || ratio < 1.0 / self.mask_max_disagreement {
+            return None;
+        }
+        Some(self.estimate(height_px, class_name) * ratio)
+    }
+}
+
+// People get their own, more cautious thresholds; everything else is an
+// obstacle, unless a label map gives its class another rule.
+#[derive(Debug, Clone)]
+pub struct ActionPolicy {
+    person_emergency_stop_m: f32,
+    person_stop_m: f32,
+    person_slow_down_m: f32,
+    obstacle_stop_m: f32,
+    obstacle_slow_down_m: f32,
+    rules: BTreeMap<String, LabelRule>,
+}
+
+impl ActionPolicy {
+    pub fn new(config: &VisionConfig) -> Self {
+        Self {
+            person_emergency_stop_m: config.person_emergency_stop_m,
+            person_stop_m: config.person_stop_m,
+            person_slow_down_m: config.person_slow_down_m,
+            obstacle_stop_m: config.obstacle_stop_m,
+            obstacle_slow_down_m: config.obstacle_slow_down_m,
+            rules: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_labels(mut self, labels: &LabelMap) -> Self {
+        for label in labels.iter() {
+            if let Some(rule) = label.rule {
+                self.rules.insert(label.name.clone(), rule);
+            }
+        }
+        self
+    }
+
+    pub fn action(&self, class_name: &str, distance: f32) -> NavigationAction {
+        let rule = self.rules.get(class_name).copied().unwrap_or(match class_name {
+            "person" => LabelRule::Person,
+            _ => LabelRule::Obstacle,
+        });
+        match (rule, distance) {
+            (LabelRule::Person, d) if d < self.person_emergency_stop_m => NavigationAction::EmergencyStop,
+            (LabelRule::Person, d) if d < self.person_stop_m => NavigationAction::Stop,
+            (LabelRule::Person, d) if d < self.person_slow_down_m => NavigationAction::SlowDown,
+            (LabelRule::Obstacle, d) if d < self.obstacle_stop_m => NavigationAction::Stop,
+            (LabelRule::Obstacle, d) if d < self.obstacle_slow_down_m => NavigationAction::SlowDown,
+            _ => NavigationAction::Continue,
+        }
+    }
+}
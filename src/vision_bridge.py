@@ -9,6 +9,88 @@ import signal
 import time
 import re
 import threading
+import mmap
+import fcntl
+import struct
+
+# Protocol versions this bridge can write; the rover offers its own in
+# SCOUT_PROTOCOL_VERSIONS and the highest both know is used.
+PROTOCOL_VERSIONS = (1, 2)
+
+# The rover points this at an uploaded model config once one is activated.
+POST_PROCESS_FILE = os.environ.get('SCOUT_POST_PROCESS_FILE', '/usr/share/rpi-camera-assets/imx500_yolov8.json')
+
+# rpicam-vid options for the camera settings the rover sends with a vision
+# profile; settings left out keep the camera's defaults.
+CAMERA_OPTIONS = {
+    'shutter_us': '--shutter',
+    'gain': '--gain',
+    'ev': '--ev',
+    'saturation': '--saturation',
+}
+
+# The IR-cut filter's GPIO, when the rover has one configured.
+IR_CUT_PIN = os.environ.get('SCOUT_IR_CUT_PIN', '')
+
+# Each slot of the shared-memory frame ring starts with the sequence number
+# of the frame in it (0 while it is being written), the frame's length and
+# four spare bytes; the JPEG or raw frame follows.
+SLOT_HEADER = struct.Struct('<QI4x')
+# A slot the rover hasn't acked in this long is taken back.
+SLOT_RECLAIM_S = 2.0
+# A 640x480 I420 frame, as rpicam-vid writes them with --codec yuv420.
+RAW_FRAME_BYTES = 640 * 480 * 3 // 2
+
+class FrameRing:
+    """Hands frames to the rover in a memfd it maps, instead of base64 in every line"""
+    def __init__(self, slots, slot_bytes):
+        self.slots = slots
+        self.slot_bytes = slot_bytes
+        self.fd = os.memfd_create('scout-frames', os.MFD_ALLOW_SEALING)
+        os.ftruncate(self.fd, slots * slot_bytes)
+        # The rover maps the whole ring; sealed, it can't shrink under it
+        fcntl.fcntl(self.fd, fcntl.F_ADD_SEALS, fcntl.F_SEAL_SHRINK | fcntl.F_SEAL_GROW | fcntl.F_SEAL_SEAL)
+        self.map = mmap.mmap(self.fd, slots * slot_bytes)
+        self.path = f"/proc/{os.getpid()}/fd/{self.fd}"
+        # (seq, when written) of each slot the rover hasn't acked
+        self.pending = [None] * slots
+        self.next_seq = 1
+        self.lock = threading.Lock()
+
+    def info(self):
+        return {"path": self.path, "slots": self.slots, "slot_bytes": self.slot_bytes}
+
+    def write(self, frame):
+        """Puts the frame in a free slot and returns the frame's "shm" field, or None to send it inline"""
+        if len(frame) > self.slot_bytes - SLOT_HEADER.size:
+            return None
+        now = time.monotonic()
+        with self.lock:
+            for i, pending in enumerate(self.pending):
+                if pending is not None and now - pending[1] > SLOT_RECLAIM_S:
+                    self.pending[i] = None
+            slot = next((i for i, pending in enumerate(self.pending) if pending is None), None)
+            if slot is None:
+                return None
+            seq = self.next_seq
+            self.next_seq += 1
+            self.pending[slot] = (seq, now)
+        at = slot * self.slot_bytes
+        SLOT_HEADER.pack_into(self.map, at, 0, 0)
+        self.map[at + SLOT_HEADER.size:at + SLOT_HEADER.size + len(frame)] = frame
+        struct.pack_into('<I', self.map, at + 8, len(frame))
+        struct.pack_into('<Q', self.map, at, seq)
+        return {"slot": slot, "seq": seq, "length": len(frame)}
+
+    def ack(self, slot, seq):
+        with self.lock:
+            if 0 <= slot < self.slots and self.pending[slot] is not None and self.pending[slot][0] == seq:
+                self.pending[slot] = None
+
+def negotiate_version():
+    offered = os.environ.get('SCOUT_PROTOCOL_VERSIONS', '1')
+    common = [v for v in PROTOCOL_VERSIONS if str(v) in offered.split(',')]
+    return max(common) if common else max(PROTOCOL_VERSIONS)
 
 class VisionBridge:
     def __init__(self):
@@ -33,35 +115,296 @@ class VisionBridge:
             "toothbrush"
         ]
         
-        self.min_x_raw = float('inf')
-        self.max_x_raw = 0
-        self.min_y_raw = float('inf')
-        self.max_y_raw = 0
-        
+        # With a custom model the rover names classes from its label map,
+        # so only the index is sent.
+        self.send_indices = os.environ.get('SCOUT_CLASS_INDICES') == '1'
+        self.version = negotiate_version()
+        self.tag_detector = None
+        if os.environ.get('SCOUT_FIDUCIALS') == '1':
+            self.tag_detector = self.make_tag_detector()
+
+        self.ring = None
+        if os.environ.get('SCOUT_FRAME_TRANSPORT') == 'shm':
+            self.ring = self.make_ring()
+        self.raw = self.wants_raw()
+
+        self.camera_settings = {}
+        # The rover's exposure feedback, on top of the profile's ev.
+        self.exposure_ev = 0.0
+        self.keepalive_fps = None
+        self.process = None
+        self.restart_requested = False
+        self.process_lock = threading.Lock()
+
     def signal_handler(self, sig, frame):
         self.running = False
+
+    def make_ring(self):
+        """The shared-memory frame ring the rover asked for, or None to send base64"""
+        try:
+            return FrameRing(int(os.environ.get('SCOUT_FRAME_SLOTS', '4')), int(os.environ.get('SCOUT_FRAME_SLOT_BYTES', '262144')))
+        except (AttributeError, OSError, ValueError) as e:
+            sys.stderr.write(f"Shared-memory frames unavailable ({e}); sending base64\n")
+            sys.stderr.flush()
+            return None
+
+    def wants_raw(self):
+        """Whether to send raw YUV420 frames: asked for, with a ring to put them in and cv2 to encode the odd one that doesn't fit"""
+        if self.ring is None or os.environ.get('SCOUT_PIXEL_FORMAT') != 'yuv420':
+            return False
+        try:
+            import cv2
+            import numpy
+        except ImportError:
+            sys.stderr.write("SCOUT_PIXEL_FORMAT=yuv420 but cv2 is not installed; sending JPEGs\n")
+            sys.stderr.flush()
+            return False
+        self.cv2 = cv2
+        self.numpy = numpy
+        return True
+
+    def encode_jpeg(self, raw):
+        """A raw frame as a JPEG, for when it can't go through the ring"""
+        yuv = self.numpy.frombuffer(raw, dtype=self.numpy.uint8).reshape(480 * 3 // 2, 640)
+        ok, jpeg = self.cv2.imencode('.jpg', self.cv2.cvtColor(yuv, self.cv2.COLOR_YUV2BGR_I420))
+        return jpeg.tobytes() if ok else b''
+
+    def make_tag_detector(self):
+        """AprilTag 36h11 detector from OpenCV's aruco module, or None without it"""
+        try:
+            import cv2
+            import numpy
+        except ImportError:
+            sys.stderr.write("SCOUT_FIDUCIALS set but cv2 is not installed; not detecting tags\n")
+            sys.stderr.flush()
+            return None
+        self.cv2 = cv2
+        self.numpy = numpy
+        dictionary = cv2.aruco.getPredefinedDictionary(cv2.aruco.DICT_APRILTAG_36h11)
+        return cv2.aruco.ArucoDetector(dictionary, cv2.aruco.DetectorParameters())
+
+    def detect_tags(self, picture, raw=False):
+        """Tags in one JPEG or raw frame as {"id", "corners"} dicts"""
+        if self.tag_detector is None:
+            return []
+        if raw:
+            gray = self.numpy.frombuffer(picture, dtype=self.numpy.uint8)[:640 * 480].reshape(480, 640)
+        else:
+            gray = self.cv2.imdecode(self.numpy.frombuffer(picture, dtype=self.numpy.uint8), self.cv2.IMREAD_GRAYSCALE)
+        if gray is None:
+            return []
+        corners, ids, _ = self.tag_detector.detectMarkers(gray)
+        if ids is None:
+            return []
+        return [
+            {"id": int(tag_id), "corners": [[float(x), float(y)] for x, y in quad.reshape(4, 2)]}
+            for tag_id, quad in zip(ids.flatten(), corners)
+        ]
     
+    def parse_detection(self, line):
+        """One detection from an rpicam post-processing line, or None"""
+        det_pattern = r'\[(\d+)\]\s*:\s*(\w+)\[?(\d*)\]?\s*\(([0-9.]+)\)\s*@\s*(\d+),(\d+)\s*(\d+)x(\d+)'
+        match = re.search(det_pattern, line)
+        if not match:
+            return None
+        class_str = match.group(2)
+        
+        if class_str.isdigit():
+            class_id = int(class_str)
+            class_name = self.coco_classes[class_id] if class_id < len(self.coco_classes) else "object"
+        else:
+            class_name = class_str
+            try:
+                class_id = self.coco_classes.index(class_name)
+            except ValueError:
+                class_id = 0
+        
+        center_x_raw = int(match.group(5))
+        center_y_raw = int(match.group(6))
+        
+        # The IMX500 reports centres in 1/16ths of its 12288x9216 space.
+        center_x = int((center_x_raw / 16) * 640 / 12288)
+        center_y = int((center_y_raw / 16) * 480 / 9216)
+        
+        center_x = max(0, min(center_x, 639))
+        center_y = max(0, min(center_y, 479))
+        
+        conf = float(match.group(4))
+        
+        if class_name == "person":
+            base_h = int(100 + conf * 60) 
+            base_w = int(base_h * 0.4)  
+        elif class_name in ["car", "truck", "bus"]:
+            base_h = int(60 + conf * 40)
+            base_w = int(base_h * 1.5)    
+        else:
+            base_h = int(50 + conf * 50)
+            base_w = base_h
+        
+        x = center_x - base_w // 2
+        y = center_y - base_h // 2
+        
+        x = max(0, min(x, 640 - base_w))
+        y = max(0, min(y, 480 - base_h))
+        
+        det = {
+            "class": class_name,
+            "class_id": class_id,
+            "conf": conf,
+            "x": x,
+            "y": y,
+            "w": base_w,
+            "h": base_h,
+            "center_x": center_x,
+            "center_y": center_y
+        }
+        # v2 always carries the index.
+        if self.send_indices and class_str.isdigit():
+            del det["class"]
+        elif self.send_indices and self.version < 2:
+            del det["class_id"]
+        return det
+
+    def detection_v2(self, det):
+        """A detection as v2 sends it, without the centre"""
+        return {key: value for key, value in det.items() if key not in ("center_x", "center_y")}
+
+    def hello(self):
+        """The first line: the protocol version chosen and the bridge's clock"""
+        capabilities = ["class_indices"] if self.send_indices else []
+        capabilities.append("set_exposure")
+        if self.tag_detector is not None:
+            capabilities.append("fiducials")
+        hello = {"type": "hello", "version": self.version, "capabilities": capabilities, "monotonic": time.monotonic()}
+        if self.ring is not None:
+            capabilities.append("shm")
+            hello["shm"] = self.ring.info()
+        if self.raw:
+            capabilities.append("yuv420")
+        return hello
+
+    def read_control(self):
+        """Control lines from the rover on stdin; a camera message restarts rpicam-vid with its settings, a pause one with its frame rate"""
+        for line in sys.stdin:
+            try:
+                message = json.loads(line)
+            except ValueError:
+                sys.stderr.write(f"Ignoring control line: {line.strip()}\n")
+                continue
+            if message.get("type") == "ack":
+                ring = self.ring
+                if ring is not None:
+                    ring.ack(message.get("slot", -1), message.get("seq"))
+                continue
+            if message.get("type") == "transport" and message.get("shm") is False:
+                sys.stderr.write("The rover couldn't map the frame ring; sending base64\n")
+                sys.stderr.flush()
+                self.ring = None
+                # Raw frames only go through the ring, so rpicam-vid goes
+                # back to writing JPEGs.
+                with self.process_lock:
+                    if self.raw and self.process is not None:
+                        self.restart_requested = True
+                        self.process.terminate()
+                    self.raw = False
+                continue
+            if message.get("type") == "pause":
+                self.set_keepalive(message.get("keepalive_fps") if message.get("paused") else None)
+                continue
+            if message.get("type") == "set_exposure":
+                self.set_exposure(float(message.get("ev") or 0.0))
+                continue
+            if message.get("type") != "camera":
+                continue
+            settings = message.get("settings") or {}
+            sys.stderr.write(f"Camera settings for profile {message.get('profile')}: {settings}\n")
+            sys.stderr.flush()
+            self.set_ir_cut(settings.get("ir_cut"))
+            with self.process_lock:
+                changed = any(settings.get(key) != self.camera_settings.get(key) for key in CAMERA_OPTIONS)
+                self.camera_settings = settings
+                if changed and self.process is not None:
+                    self.restart_requested = True
+                    self.process.terminate()
+
+    def set_keepalive(self, fps):
+        """While the rover is asleep, rpicam-vid runs at the keepalive rate; None is the full rate"""
+        sys.stderr.write(f"Keepalive at {fps} FPS\n" if fps else "Resuming full frame rate\n")
+        sys.stderr.flush()
+        with self.process_lock:
+            changed = fps != self.keepalive_fps
+            self.keepalive_fps = fps
+            if changed and self.process is not None:
+                self.restart_requested = True
+                self.process.terminate()
+
+    def set_exposure(self, ev):
+        """The rover's exposure offset; rpicam-vid can't change --ev while running, so it is restarted"""
+        sys.stderr.write(f"Exposure offset {ev:+.1f} EV\n")
+        sys.stderr.flush()
+        with self.process_lock:
+            changed = ev != self.exposure_ev
+            self.exposure_ev = ev
+            if changed and self.process is not None:
+                self.restart_requested = True
+                self.process.terminate()
+
+    def set_ir_cut(self, ir_cut):
+        """Drives the IR-cut filter's GPIO, high putting the filter in front of the sensor"""
+        if ir_cut is None or not IR_CUT_PIN:
+            return
+        level = 'dh' if ir_cut else 'dl'
+        try:
+            subprocess.run(['pinctrl', 'set', IR_CUT_PIN, 'op', level], check=True)
+        except (OSError, subprocess.CalledProcessError) as e:
+            sys.stderr.write(f"Failed to set IR-cut pin {IR_CUT_PIN}: {e}\n")
+
+    def camera_args(self):
+        args = []
+        for key, option in CAMERA_OPTIONS.items():
+            value = self.camera_settings.get(key)
+            if key == 'ev' and self.exposure_ev:
+                value = (value or 0.0) + self.exposure_ev
+            if value is not None:
+                args += [option, str(value)]
+        return args
+
     def run(self):
+        """Keeps rpicam-vid running, restarting it whenever the camera settings change"""
+        threading.Thread(target=self.read_control, daemon=True).start()
+        frame_id = 0
+        while self.running:
+            frame_id = self.stream(frame_id)
+            with self.process_lock:
+                restart = self.restart_requested
+                self.restart_requested = False
+            if not restart:
+                break
+
+    def stream(self, frame_id):
         """Stream camera with IMX500 NPU detections"""
+        raw = self.raw
         cmd = [
             'rpicam-vid',
             '--width', '640',
             '--height', '480',
-            '--framerate', '30',
+            '--framerate', str(self.keepalive_fps or 30),
             '--timeout', '0',
             '--rotation', '180',
-            '--codec', 'mjpeg',
-            '--post-process-file', '/usr/share/rpi-camera-assets/imx500_yolov8.json',
+            '--codec', 'yuv420' if raw else 'mjpeg',
+            '--post-process-file', POST_PROCESS_FILE,
             '--verbose', '2',
             '-o', '-'
         ]
-        
-        process = subprocess.Popen(
-            cmd,
-            stdout=subprocess.PIPE,
-            stderr=subprocess.PIPE,
-            bufsize=65536
-        )
+        with self.process_lock:
+            cmd[-2:-2] = self.camera_args()
+            process = subprocess.Popen(
+                cmd,
+                stdout=subprocess.PIPE,
+                stderr=subprocess.PIPE,
+                bufsize=65536
+            )
+            self.process = process
         
         sys.stderr.write("Started rpicam-vid with IMX500 NPU YOLOv8\n")
         sys.stderr.flush()
@@ -80,133 +423,89 @@ class VisionBridge:
                         self.current_frame_detections = []
                     continue
                 
-                det_pattern = r'\[(\d+)\]\s*:\s*(\w+)\[?(\d*)\]?\s*\(([0-9.]+)\)\s*@\s*(\d+),(\d+)\s*(\d+)x(\d+)'
-                match = re.search(det_pattern, line)
-                if match:
-                    class_str = match.group(2)
-                    
-                    if class_str.isdigit():
-                        class_id = int(class_str)
-                        class_name = self.coco_classes[class_id] if class_id < len(self.coco_classes) else "object"
-                    else:
-                        class_name = class_str
-                        try:
-                            class_id = self.coco_classes.index(class_name)
-                        except ValueError:
-                            class_id = 0
-                    
-                    center_x_raw = int(match.group(5))
-                    center_y_raw = int(match.group(6))
-                    
-                    self.min_x_raw = min(self.min_x_raw, center_x_raw)
-                    self.max_x_raw = max(self.max_x_raw, center_x_raw)
-                    self.min_y_raw = min(self.min_y_raw, center_y_raw)
-                    self.max_y_raw = max(self.max_y_raw, center_y_raw)
-                    
-                    # The IMX500 seems to use a much larger coordinate space
-                    # Based on the data, it looks like it might be using 12288x9216 (12K x 9K)
-                    # or some other high resolution coordinate system
-                    
-                    # Let's try mapping from the observed range to 640x480
-                    # Assuming the IMX500 uses a coordinate space of approximately 12288x9216
-                    # (based on observed values like 174208 / 16 ≈ 10888)
-                    
-                    # Method 1: Direct scaling from assumed IMX500 resolution
-                    IMX500_WIDTH = 12288  # Estimated based on max observed values
-                    IMX500_HEIGHT = 9216  # Estimated based on max observed values
-                    
-                    center_x = int((center_x_raw / 16) * 640 / IMX500_WIDTH)
-                    center_y = int((center_y_raw / 16) * 480 / IMX500_HEIGHT)
-                    
-                    # Alternative Method 2: Dynamic scaling based on observed range
-                    # Uncomment to try this approach instead
-                    # if self.max_x_raw > 0 and self.max_y_raw > 0:
-                    #     center_x = int((center_x_raw - self.min_x_raw) * 640 / (self.max_x_raw - self.min_x_raw))
-                    #     center_y = int((center_y_raw - self.min_y_raw) * 480 / (self.max_y_raw - self.min_y_raw))
-                    # else:
-                    #     continue
-                    
-                    center_x = max(0, min(center_x, 639))
-                    center_y = max(0, min(center_y, 479))
-                    
-                    conf = float(match.group(4))
-                    
-                    if class_name == "person":
-                        base_h = int(100 + conf * 60) 
-                        base_w = int(base_h * 0.4)  
-                    elif class_name in ["car", "truck", "bus"]:
-                        base_h = int(60 + conf * 40)
-                        base_w = int(base_h * 1.5)    
-                    else:
-                        base_h = int(50 + conf * 50)
-                        base_w = base_h
-                    
-                    x = center_x - base_w // 2
-                    y = center_y - base_h // 2
-                    
-                    x = max(0, min(x, 640 - base_w))
-                    y = max(0, min(y, 480 - base_h))
-                    
-                    det = {
-                        "class": class_name,
-                        "class_id": class_id,
-                        "conf": conf,
-                        "x": x,
-                        "y": y,
-                        "w": base_w,
-                        "h": base_h,
-                        "center_x": center_x,
-                        "center_y": center_y
-                    }
-                    
+                det = self.parse_detection(line)
+                if det:
                     with self.detection_lock:
                         self.current_frame_detections.append(det)
-                    
-                    sys.stderr.write(f"✓ Detected: {class_name} ({conf:.2f}) at ({center_x},{center_y}) - Box: {base_w}x{base_h} at ({x},{y})\n")
-                    sys.stderr.write(f"  Raw range: X[{self.min_x_raw}-{self.max_x_raw}] Y[{self.min_y_raw}-{self.max_y_raw}]\n")
-                    sys.stderr.flush()
         
         stderr_thread = threading.Thread(target=parse_stderr, daemon=True)
         stderr_thread.start()
         
-        buffer = b''
-        frame_id = 0
+        pictures = self.read_raw(process.stdout) if raw else self.read_jpegs(process.stdout)
+        for picture in pictures:
+            capture_monotonic = time.monotonic()
+            frame_id += 1
+            
+            with self.detection_lock:
+                detections_copy = self.current_frame_detections.copy()
+            
+            ring = self.ring
+            slot = ring.write(picture) if ring is not None else None
+            if slot and raw:
+                slot.update({"format": "yuv420", "width": 640, "height": 480})
+            jpeg = self.encode_jpeg(picture) if raw and not slot else picture
+            output = {
+                "frame_id": frame_id,
+                "jpeg_base64": "" if slot else base64.b64encode(jpeg).decode('utf-8'),
+                "timestamp": time.time(),
+                "capture_monotonic": capture_monotonic,
+                "fiducials": self.detect_tags(picture, raw)
+            }
+            if self.version >= 2:
+                output.update({
+                    "type": "frame_v2",
+                    "width": 640,
+                    "height": 480,
+                    "detections": [self.detection_v2(det) for det in detections_copy],
+                })
+            else:
+                output["imx500_basic"] = detections_copy
+            if slot:
+                output["shm"] = slot
+            
+            # Stamped last, so the rover can tell time spent here from
+            # time spent in the pipe.
+            output["emit_monotonic"] = time.monotonic()
+            print(json.dumps(output))
+            sys.stdout.flush()
         
+        with self.process_lock:
+            process.terminate()
+            process.wait()
+            self.process = None
+        return frame_id
+
+    def read_jpegs(self, stdout):
+        """JPEGs as rpicam-vid writes them with --codec mjpeg"""
+        buffer = b''
         while self.running:
-            chunk = process.stdout.read(4096)
+            chunk = stdout.read(4096)
             if not chunk:
-                break
-                
+                return
             buffer += chunk
-            
             while True:
                 start = buffer.find(b'\xff\xd8')
                 if start == -1:
                     break
-                    
                 end = buffer.find(b'\xff\xd9', start + 2)
                 if end == -1:
                     break
-                    
                 jpeg = buffer[start:end + 2]
                 buffer = buffer[end + 2:]
-                frame_id += 1
-                
-                with self.detection_lock:
-                    detections_copy = self.current_frame_detections.copy()
-                
-                output = {
-                    "frame_id": frame_id,
-                    "jpeg_base64": base64.b64encode(jpeg).decode('utf-8'),
-                    "timestamp": time.time(),
-                    "imx500_basic": detections_copy
-                }
-                
-                print(json.dumps(output))
-                sys.stdout.flush()
-        
-        process.terminate()
+                yield jpeg
+
+    def read_raw(self, stdout):
+        """Whole I420 frames as rpicam-vid writes them with --codec yuv420"""
+        while self.running:
+            frame = stdout.read(RAW_FRAME_BYTES)
+            if len(frame) < RAW_FRAME_BYTES:
+                return
+            yield frame
 
 if __name__ == "__main__":
     bridge = VisionBridge()
+    # Tells the rover which protocol follows and lets it relate
+    # time.monotonic() here to its own clock.
+    print(json.dumps(bridge.hello()))
+    sys.stdout.flush()
     bridge.run()
@@ -0,0 +1,141 @@
+// src/watchdog.rs
+use parking_lot::{Mutex, RwLock};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::{ControlConfig, WatchdogAction};
+use crate::events::{Severity, EVENTS};
+use crate::interlock::INTERLOCK;
+use crate::metrics::METRICS;
+use crate::motor_control::MotorController;
+use crate::state_machine::{RoverState, StateHandle, Trigger};
+
+// Fed by the control loop once per tick. The watchdog runs on its own OS
+// thread rather than a tokio task, so a tick blocked inside the runtime
+// can't starve it. It is also the only thing that drives the motor power
+// interlock, for the same reason.
+#[derive(Clone)]
+pub struct Watchdog {
+    last_feed: Arc<Mutex<Instant>>,
+}
+
+impl Watchdog {
+    pub fn feed(&self) {
+        *self.last_feed.lock() = Instant::now();
+    }
+}
+
+pub fn spawn(config: &ControlConfig, state: StateHandle, motor_controller: Arc<RwLock<MotorController>>) -> Watchdog {
+    let watchdog = Watchdog {
+        last_feed: Arc::new(Mutex::new(Instant::now())),
+    };
+    let deadline = Duration::from_millis(config.watchdog_deadline_ms);
+    let action = config.watchdog_action;
+    let mut hardware = open_hardware_watchdog(config.hardware_watchdog.as_deref());
+
+    let last_feed = watchdog.last_feed.clone();
+    thread::spawn(move || {
+        info!(target: "control", "Watchdog armed with a {:?} deadline", deadline);
+        let mut tripped = false;
+        loop {
+            // Woken early when the interlock's conditions change, so the
+            // output drops as soon as an emergency stop latches.
+            INTERLOCK.wait(deadline / 4);
+
+            if state.get_state() == RoverState::ShuttingDown {
+                INTERLOCK.release();
+                if let Some(hardware) = hardware.as_mut() {
+                    hardware.disarm();
+                }
+                return;
+            }
+            // The hardware watchdog only needs this thread to be alive; it
+            // is there to catch the whole process hanging.
+            if let Some(hardware) = hardware.as_mut() {
+                hardware.feed();
+            }
+
+            let stalled = last_feed.lock().elapsed();
+            INTERLOCK.update(stalled <= deadline);
+            if stalled <= deadline {
+                tripped = false;
+                continue;
+            }
+            if tripped {
+                continue;
+            }
+            tripped = true;
+
+            METRICS.watchdog_trips.inc();
+            match action {
+                WatchdogAction::EmergencyStop => motor_controller.write().emergency_stop(),
+                WatchdogAction::Stop => motor_controller.write().stop(),
+            }
+            let message = format!("Control loop missed its watchdog deadline ({} ms without a tick)", stalled.as_millis());
+            EVENTS.publish(Severity::Critical, "watchdog", message.clone(), serde_json::json!({
+                "stalled_ms": stalled.as_millis() as u64,
+                "deadline_ms": deadline.as_millis() as u64,
+            }));
+            // Fault only clears through an operator acknowledgment, so the
+            // rover stays put even once the loop recovers.
+            state.request(Trigger::Fault(message));
+        }
+    });
+
+    watchdog
+}
+
+struct HardwareWatchdog {
+    #[cfg(feature = "hw-watchdog")]
+    device: std::fs::File,
+}
+
+#[cfg(feature = "hw-watchdog")]
+impl HardwareWatchdog {
+    fn feed(&mut self) {
+        use std::io::Write;
+        if let Err(e) = self.device.write_all(b"\0") {
+            warn!(target: "control", "Failed to feed hardware watchdog: {}", e);
+        }
+    }
+
+    // Writing 'V' before closing tells the driver this is a clean stop, so
+    // the board is not reset on the way out.
+    fn disarm(&mut self) {
+        use std::io::Write;
+        let _ = self.device.write_all(b"V");
+        info!(target: "control", "Hardware watchdog disarmed");
+    }
+}
+
+#[cfg(not(feature = "hw-watchdog"))]
+impl HardwareWatchdog {
+    fn feed(&mut self) {}
+
+    fn disarm(&mut self) {}
+}
+
+#[cfg(feature = "hw-watchdog")]
+fn open_hardware_watchdog(path: Option<&str>) -> Option<HardwareWatchdog> {
+    let path = path?;
+    match std::fs::OpenOptions::new().write(true).open(path) {
+        Ok(device) => {
+            info!(target: "control", "Feeding hardware watchdog {}", path);
+            Some(HardwareWatchdog { device })
+        }
+        Err(e) => {
+            EVENTS.publish(Severity::Warning, "watchdog", format!("Failed to open hardware watchdog {}: {}", path, e), serde_json::Value::Null);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "hw-watchdog"))]
+fn open_hardware_watchdog(path: Option<&str>) -> Option<HardwareWatchdog> {
+    if let Some(path) = path {
+        warn!(target: "control", "Hardware watchdog {} configured but the binary was built without the `hw-watchdog` feature", path);
+    }
+    None
+}
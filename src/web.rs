@@ -1,22 +1,58 @@
 // src/web.rs
-use warp::Filter;
+use warp::{Filter, Reply};
+use warp::filters::BoxedFilter;
+use warp::reply::Response;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use futures_util::{StreamExt, SinkExt};
+use warp::http::StatusCode;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use parking_lot::RwLock;
-use image::{RgbImage, Rgb, DynamicImage};
-use imageproc::drawing::draw_hollow_rect_mut;
-use imageproc::rect::Rect;
-use base64;
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH, Duration};
-use std::os::unix::process::ExitStatusExt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Instant, Duration};
+use tracing::{error, info, warn};
 
-use crate::vision::{VisionSystem, Detection, NavigationAction, VisionTelemetry};
-use crate::pathfinding::PathPlanner;
+use crate::vision::{ground, tap, VisionSystem, Detection, FrameImage, NavigationAction, ProfileCause, TapLevel, VisionTelemetry, BRIDGE_TAP, FRAME_HEIGHT_PX, FRAME_WIDTH_PX};
+use crate::pathfinding::{PathPlanner, PathPoint};
 use crate::motor_control::MotorController;
+use crate::capabilities;
+use crate::clock::CLOCK;
+use crate::config::{ClassDisplay, ConfigStore, DisplayConfig, GroundPoint, IdentityConfig, LogLevel, RoverConfig, SnapshotFormat, WebConfig};
+use crate::events::{Severity, EVENTS};
+use crate::health::{HealthHandle, HealthRegistry};
+use crate::metrics::METRICS;
+use crate::session::{self, SessionRecorder};
+use crate::discovery;
+use crate::assets;
+use crate::telemetry::{self, TelemetryHistory, TelemetrySample};
+use crate::state_machine::{RoverState, StateHandle, Trigger};
+use crate::world::WorldState;
+use crate::supervisor;
+use crate::simulation;
+use crate::scheduler::Scheduler;
+use crate::config::{ScheduleEntry, Zone, ZonePolicy};
+use crate::stream_quality::{self, LinkQuality, QualityMode};
+use crate::detection_delta::DetectionSync;
+use crate::annotate;
+use crate::snapshot::{self, EncoderPool};
+use crate::blackbox::{self, RecordKind, BLACKBOX};
+use crate::annotations::{self, Annotation};
+use crate::recording::RecordedFrame;
+use crate::display::DisplayPreferences;
+use crate::updates::{ArtifactKind, AssetError, ArtifactManifest, ASSETS};
+use crate::power::POWER;
+use crate::checkpoint;
+use crate::logging::{LogQuery, LOGS};
+use crate::teleop::{AxisCommand, AxisResult, TeleopSession};
+use crate::hazards::{HazardError, HazardReport, ReportOutcome, HAZARDS};
+
+const API_VERSION: u32 = 1;
+// A client this far behind gets no more log lines until it catches up.
+const LOG_TAIL_MAX_BACKLOG: usize = 64;
 
 #[derive(Serialize)]
 struct FrameData {
@@ -28,142 +64,2401 @@ struct FrameData {
     timestamp: u64,
 }
 
-#[derive(Serialize)]
-struct PathStatus {
-    status: String,  
-    color: String, 
-    obstacles: usize,
-}
+#[derive(Serialize)]
+struct PathStatus {
+    status: String,  
+    color: String, 
+    obstacles: usize,
+}
+
+#[derive(Serialize)]
+struct NavigationInfo {
+    action: String,
+    speed: f32,
+    heading: f32,
+    distance_to_goal: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum ClientCommand {
+    Subscribe {
+        frames: bool,
+        // None means every rover this server relays.
+        #[serde(default)]
+        rovers: Option<Vec<String>>,
+    },
+    EmergencyStop,
+    SetMode {
+        mode: ModeRequest,
+    },
+    AcknowledgeFault,
+    SetShadow {
+        enabled: bool,
+    },
+    Dock,
+    // A track id to follow, or null to stop following.
+    Follow {
+        track_id: Option<u32>,
+    },
+    // Arm despite a failed self-test.
+    OverrideSelftest,
+    // Only while idle.
+    RerunSelftest,
+    // The same as POST /api/annotate.
+    Annotate {
+        label: String,
+        #[serde(default)]
+        note: Option<String>,
+    },
+    // The client's answer to the server's hello.
+    Hello {
+        #[serde(default)]
+        quality: QualityMode,
+        // Detection masks are left out of frames unless asked for.
+        #[serde(default)]
+        masks: bool,
+        // Frames then carry only what changed in the detections, keyed by
+        // track id, plus a full sync now and then.
+        #[serde(default)]
+        detection_deltas: bool,
+    },
+    // Log lines at `level` or above, logs.level by default, as they are
+    // logged.
+    SubscribeLogs {
+        #[serde(default)]
+        level: Option<LogLevel>,
+    },
+    UnsubscribeLogs,
+    // A gamepad's sticks, in manual mode. Each is acknowledged with what
+    // it came to.
+    Axis(AxisCommand),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ModeRequest {
+    Autonomous,
+    Manual,
+    Idle,
+}
+
+struct Client {
+    tx: mpsc::UnboundedSender<warp::ws::Message>,
+    frames: bool,
+    masks: bool,
+    rovers: Option<Vec<String>>,
+    peer: Option<SocketAddr>,
+    forwarded_for: Option<String>,
+    connected_at: Instant,
+    messages_sent: AtomicU64,
+    // Handed to the socket writer but not yet written out.
+    queued: Arc<AtomicUsize>,
+    link: parking_lot::Mutex<LinkQuality>,
+    // Only for clients whose hello asked for detection deltas.
+    detection_sync: parking_lot::Mutex<Option<DetectionSync>>,
+    // The level the client tails logs from, if it does.
+    logs: Option<LogLevel>,
+    teleop: parking_lot::Mutex<TeleopSession>,
+}
+
+impl Client {
+    fn wants(&self, rover_id: &str, frames_only: bool) -> bool {
+        (self.frames || !frames_only)
+            && self.rovers.as_ref().map_or(true, |r| r.iter().any(|id| id == rover_id))
+    }
+
+    fn send(&self, msg: &warp::ws::Message) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        if self.tx.send(msg.clone()).is_ok() {
+            self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// A zone as PUT to /api/zones/{name}, which gives the name.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ZoneEdit {
+    polygon: Vec<(f32, f32)>,
+    #[serde(default)]
+    policy: ZonePolicy,
+    #[serde(default)]
+    direction_deg: Option<f32>,
+}
+
+// vision.self_mask as PUT to /api/vision/self_mask.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SelfMaskEdit {
+    polygons: Vec<Vec<(f32, f32)>>,
+    min_overlap: Option<f32>,
+}
+
+// Measured points as POST to /api/vision/ground_calibration.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GroundCalibrationEdit {
+    points: Vec<GroundPoint>,
+}
+
+// An operator's mark on the frame being shown, as POST to /api/annotate.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnnotateRequest {
+    label: String,
+    #[serde(default)]
+    note: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AnnotationsQuery {
+    session: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BridgeDiagnosticsRequest {
+    // Null switches them off.
+    level: Option<TapLevel>,
+    #[serde(default)]
+    minutes: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct GroundQuery {
+    px: f32,
+    py: f32,
+}
+
+#[derive(Deserialize)]
+struct SelfMaskProposalQuery {
+    #[serde(default = "default_self_mask_min_frames")]
+    min_frames: u64,
+}
+
+fn default_self_mask_min_frames() -> u64 {
+    200
+}
+
+#[derive(Deserialize)]
+struct HeatmapQuery {
+    class: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SweepQuery {
+    minutes: Option<f32>,
+    // Comma-separated, e.g. 0.2,0.35,0.5.
+    thresholds: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SweepStart {
+    #[serde(default)]
+    duration_s: Option<f32>,
+    #[serde(default)]
+    floor: Option<f32>,
+}
+
+// A goal in map metres or, once the map is tied to GPS, in degrees.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GoalRequest {
+    Map { x: f32, y: f32 },
+    Geodetic { lat: f64, lon: f64 },
+}
+
+#[derive(Deserialize)]
+struct ReplayQuery {
+    file: String,
+    #[serde(default = "default_replay_speed")]
+    speed: f32,
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    metric: String,
+    #[serde(default = "default_history_window")]
+    window: String,
+}
+
+fn default_history_window() -> String {
+    "1m".to_string()
+}
+
+#[derive(Deserialize)]
+struct BlackboxQuery {
+    from: Option<String>,
+    to: Option<String>,
+    kind: Option<String>,
+    #[serde(default = "default_blackbox_limit")]
+    limit: usize,
+}
+
+fn default_blackbox_limit() -> usize {
+    1000
+}
+
+#[derive(Deserialize)]
+struct LogsQuery {
+    level: Option<String>,
+    since: Option<String>,
+    after: Option<u64>,
+    target: Option<String>,
+    #[serde(default = "default_logs_limit")]
+    limit: usize,
+}
+
+fn default_logs_limit() -> usize {
+    500
+}
+
+#[derive(Deserialize)]
+struct MapQuery {
+    since: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct FrameQuery {
+    // Default true.
+    masks: Option<bool>,
+    // Default snapshot.format and snapshot.quality.
+    format: Option<SnapshotFormat>,
+    quality: Option<u8>,
+    // Stream only; default snapshot.stream_fps.
+    fps: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct AssetUploadQuery {
+    kind: String,
+    filename: String,
+    sha256: String,
+}
+
+// A missing or null profile goes back to the base vision settings.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VisionProfileRequest {
+    #[serde(default)]
+    profile: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AssetActivateRequest {
+    version: String,
+}
+
+fn default_replay_speed() -> f32 {
+    1.0
+}
+
+struct Replay {
+    session: String,
+    stop: Arc<AtomicBool>,
+    // The frame last sent out, which is what an annotation is about.
+    frame: Arc<parking_lot::Mutex<Option<RecordedFrame>>>,
+}
+
+#[derive(Serialize)]
+struct ClientStats {
+    id: usize,
+    // First X-Forwarded-For hop when behind a proxy, otherwise the peer.
+    addr: Option<String>,
+    peer: Option<String>,
+    frames: bool,
+    masks: bool,
+    connected_s: f32,
+    messages_sent: u64,
+    backlog: usize,
+    rtt_ms: Option<f32>,
+    quality_mode: QualityMode,
+    // 0 for frames as captured, n for web.quality_steps[n - 1].
+    quality_level: usize,
+}
+
+pub struct WebServer {
+    vision: Arc<RwLock<VisionSystem>>,
+    path_planner: Arc<RwLock<PathPlanner>>,
+    motor_controller: Arc<RwLock<MotorController>>,
+    state: StateHandle,
+    scheduler: Arc<Scheduler>,
+    clients: Arc<RwLock<HashMap<usize, Client>>>,
+    next_client_id: Arc<RwLock<usize>>,
+    health: Arc<HealthRegistry>,
+    // Registered up front, so the self-test can tell a server that hasn't
+    // bound yet from one that was never started.
+    web_health: HealthHandle,
+    config: Arc<ConfigStore>,
+    identity: IdentityConfig,
+    web: WebConfig,
+    display: RwLock<DisplayConfig>,
+    telemetry: TelemetryHistory,
+    recorder: Option<SessionRecorder>,
+    sessions_dir: PathBuf,
+    replay: parking_lot::Mutex<Option<Replay>>,
+    // Read from updates.token_env at startup; None refuses every asset
+    // change.
+    update_token: Option<String>,
+    // Read from hazards.token_env at startup; None refuses every hazard
+    // report.
+    hazard_token: Option<String>,
+    encoders: EncoderPool,
+}
+
+impl WebServer {
+    pub fn new(
+        vision: Arc<RwLock<VisionSystem>>,
+        path_planner: Arc<RwLock<PathPlanner>>,
+        motor_controller: Arc<RwLock<MotorController>>,
+        state: StateHandle,
+        scheduler: Arc<Scheduler>,
+        health: Arc<HealthRegistry>,
+        config: Arc<ConfigStore>,
+    ) -> Self {
+        let session_config = config.get().sessions;
+        let recorder = if session_config.enabled {
+            match SessionRecorder::start(&session_config) {
+                Ok(recorder) => Some(recorder),
+                Err(e) => {
+                    EVENTS.publish(Severity::Warning, "sessions", format!("Session recording disabled: {:#}", e), serde_json::Value::Null);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let read_token = |name: Option<&str>| name
+            .and_then(|name| std::env::var(name).ok())
+            .filter(|token| !token.is_empty());
+        let update_token = read_token(config.get().updates.token_env.as_deref());
+        let hazard_token = read_token(config.get().hazards.token_env.as_deref());
+        let max_encodes = config.get().snapshot.max_concurrent;
+
+        Self {
+            vision,
+            path_planner,
+            motor_controller,
+            state,
+            scheduler,
+            clients: Arc::new(RwLock::new(HashMap::new())),
+            next_client_id: Arc::new(RwLock::new(0)),
+            web_health: health.register("web_server", None),
+            health,
+            identity: config.get().identity,
+            web: config.get().web,
+            display: RwLock::new(config.get().display),
+            telemetry: TelemetryHistory::new(),
+            config,
+            recorder,
+            sessions_dir: PathBuf::from(session_config.directory),
+            replay: parking_lot::Mutex::new(None),
+            update_token,
+            hazard_token,
+            encoders: EncoderPool::new(max_encodes),
+        }
+    }
+
+    // Everything the server answers, for run() to serve or a test to
+    // send requests to without a socket.
+    pub fn routes(self: &Arc<Self>) -> BoxedFilter<(Response,)> {
+        let web_config = self.config.get().web;
+        let cors_origins = Arc::new(web_config.cors_allowed_origins.clone());
+
+        let rover_routes = with_cors(self.api_routes(), cors_origins)
+            .or(self.ws_route())
+            .unify()
+            .or(static_routes(web_config.static_dir.as_deref()))
+            .unify()
+            .boxed();
+
+        // Everything is reachable both directly and under /rovers/<id>/, so
+        // a proxy or aggregating dashboard can address rovers uniformly.
+        base_path_filter(&web_config.base_path)
+            .and(warp::path("rovers")
+                .and(warp::path(self.identity.rover_id.clone()))
+                .and(rover_routes.clone())
+                .or(rover_routes)
+                .unify())
+            .boxed()
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let web_config = self.config.get().web;
+        let routes = self.routes();
+
+        let web_health = self.web_health.clone();
+
+        // Broadcaster panics fault the rover like any other subsystem, then
+        // the task is restarted.
+        let server = self.clone();
+        supervisor::supervise("frame_broadcaster", self.state.clone(), move || server.clone().run_frame_broadcaster());
+        let server = self.clone();
+        supervisor::supervise("event_forwarder", self.state.clone(), move || server.clone().run_event_forwarder());
+        let server = self.clone();
+        supervisor::supervise("log_forwarder", self.state.clone(), move || server.clone().run_log_forwarder());
+        let server = self.clone();
+        supervisor::supervise("map_broadcaster", self.state.clone(), move || server.clone().run_map_broadcaster());
+
+        let ip = web_config.bind_address.parse().unwrap_or(std::net::Ipv4Addr::UNSPECIFIED.into());
+        let addr = SocketAddr::new(ip, web_config.port);
+
+        if let (Some(cert), Some(key)) = (&web_config.tls_cert_path, &web_config.tls_key_path) {
+            #[cfg(feature = "tls")]
+            {
+                let (addr, server) = warp::serve(routes)
+                    .tls()
+                    .cert_path(cert)
+                    .key_path(key)
+                    .bind_ephemeral(addr);
+                web_health.beat();
+                info!(target: "web", "Web server ready at https://{}{}", addr, web_config.base_path);
+                let _announcement = self.announce(addr.port(), &web_config.base_path);
+                server.await;
+            }
+            #[cfg(not(feature = "tls"))]
+            {
+                let message = format!("TLS configured ({}, {}) but the binary was built without the `tls` feature", cert, key);
+                web_health.fail(message.clone());
+                EVENTS.publish(Severity::Critical, "web", message.clone(), serde_json::Value::Null);
+                error!(target: "web", "Web server failed to start: {}", message);
+            }
+            return;
+        }
+
+        match warp::serve(routes).try_bind_ephemeral(addr) {
+            Ok((addr, server)) => {
+                web_health.beat();
+                info!(target: "web", "Web server ready at http://{}{}", addr, web_config.base_path);
+                let _announcement = self.announce(addr.port(), &web_config.base_path);
+                server.await;
+            }
+            Err(e) => {
+                web_health.fail(format!("Failed to bind: {}", e));
+                EVENTS.publish(Severity::Critical, "web", format!("Web server failed to bind: {}", e), serde_json::Value::Null);
+                error!(target: "web", "Web server failed to start: {}", e);
+            }
+        }
+    }
+
+    fn announce(&self, port: u16, base_path: &str) -> Option<discovery::Announcement> {
+        match discovery::announce(&self.identity, port, base_path) {
+            Ok(announcement) => Some(announcement),
+            Err(e) => {
+                EVENTS.publish(Severity::Warning, "discovery", format!("mDNS announcement failed: {}", e), serde_json::Value::Null);
+                None
+            }
+        }
+    }
+
+    fn api_routes(self: &Arc<Self>) -> BoxedFilter<(Response,)> {
+        // Health routes only read the HealthRegistry, never the subsystem
+        // locks, so they keep answering while a component is wedged.
+        let healthz_route = warp::path("healthz")
+            .and(warp::get())
+            .map({
+                let health = self.health.clone();
+                move || {
+                    let report = health.report();
+                    let status = if report.healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+                    warp::reply::with_status(warp::reply::json(&report), status)
+                }
+            });
+
+        let readyz_route = warp::path("readyz")
+            .and(warp::get())
+            .map({
+                let health = self.health.clone();
+                move || {
+                    let report = health.report();
+                    let status = if report.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+                    warp::reply::with_status(warp::reply::json(&report), status)
+                }
+            });
+
+        let metrics_route = warp::path("metrics")
+            .and(warp::get())
+            .map(|| {
+                warp::reply::with_header(
+                    METRICS.render(),
+                    "content-type",
+                    "text/plain; version=0.0.4",
+                )
+            });
+
+        let config_get_route = warp::path!("api" / "config")
+            .and(warp::get())
+            .map({
+                let config = self.config.clone();
+                move || warp::reply::json(&config.get())
+            });
+
+        let config_patch_route = warp::path!("api" / "config")
+            .and(warp::patch())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(warp::body::content_length_limit(16 * 1024))
+            .and(warp::body::json())
+            .map({
+                let server = self.clone();
+                move |query: HashMap<String, String>, patch: serde_json::Value| {
+                    let persist = query.get("persist").map(|v| v == "true" || v == "1").unwrap_or(false);
+                    server.handle_config_patch(&patch, persist)
+                }
+            });
+
+        let events_route = warp::path!("api" / "events")
+            .and(warp::get())
+            .map(|| warp::reply::json(&EVENTS.recent()));
+
+        let clients_route = warp::path!("api" / "clients")
+            .and(warp::get())
+            .map({
+                let server = self.clone();
+                move || warp::reply::json(&server.get_client_stats())
+            });
+
+        let history_route = warp::path!("api" / "telemetry" / "history")
+            .and(warp::get())
+            .and(warp::query::<HistoryQuery>())
+            .map({
+                let server = self.clone();
+                move |query: HistoryQuery| {
+                    let Some(window) = telemetry::parse_window(&query.window) else {
+                        return error_reply(StatusCode::BAD_REQUEST, "window must look like 30s, 5m or 1h");
+                    };
+                    match server.telemetry.query(&query.metric, window) {
+                        Some(history) => warp::reply::with_status(warp::reply::json(&history), StatusCode::OK),
+                        None => error_reply(
+                            StatusCode::BAD_REQUEST,
+                            &format!("unknown metric, expected one of {}", telemetry::METRIC_NAMES.join(", ")),
+                        ),
+                    }
+                }
+            });
+
+        let selftest_route = warp::path!("api" / "selftest")
+            .and(warp::get())
+            .map({
+                let server = self.clone();
+                move || warp::reply::json(&server.state.get_selftest())
+            });
+
+        let blackbox_route = warp::path!("api" / "blackbox")
+            .and(warp::get())
+            .and(warp::query::<BlackboxQuery>())
+            .map({
+                let server = self.clone();
+                move |query: BlackboxQuery| server.handle_blackbox_query(query)
+            });
+
+        let motor_audit_route = warp::path!("api" / "motors" / "audit")
+            .and(warp::get())
+            .map({
+                let server = self.clone();
+                move || warp::reply::json(&server.motor_controller.read().get_audit_log())
+            });
+
+        let schedule_get_route = warp::path!("api" / "schedule")
+            .and(warp::get())
+            .map({
+                let server = self.clone();
+                move || warp::reply::json(&server.scheduler.get_schedule())
+            });
+
+        // Replaces the whole list and always persists, so schedules survive
+        // a restart.
+        let schedule_put_route = warp::path!("api" / "schedule")
+            .and(warp::put())
+            .and(warp::body::content_length_limit(16 * 1024))
+            .and(warp::body::json())
+            .map({
+                let server = self.clone();
+                move |entries: Vec<ScheduleEntry>| {
+                    server.handle_config_patch(&serde_json::json!({ "schedule": { "entries": entries } }), true)
+                }
+            });
+
+        let zones_get_route = warp::path!("api" / "zones")
+            .and(warp::get())
+            .map({
+                let server = self.clone();
+                move || warp::reply::json(&server.path_planner.read().get_zones())
+            });
+
+        // Zone edits persist like schedules do.
+        let zones_put_route = warp::path!("api" / "zones")
+            .and(warp::put())
+            .and(warp::body::content_length_limit(64 * 1024))
+            .and(warp::body::json())
+            .map({
+                let server = self.clone();
+                move |zones: Vec<Zone>| server.save_zones(zones)
+            });
+
+        let zone_put_route = warp::path!("api" / "zones" / String)
+            .and(warp::put())
+            .and(warp::body::content_length_limit(16 * 1024))
+            .and(warp::body::json())
+            .map({
+                let server = self.clone();
+                move |name: String, edit: ZoneEdit| {
+                    let zone = Zone { name, polygon: edit.polygon, policy: edit.policy, direction_deg: edit.direction_deg };
+                    let mut zones = server.config.get().planner.zones;
+                    match zones.iter_mut().find(|z| z.name == zone.name) {
+                        Some(existing) => *existing = zone,
+                        None => zones.push(zone),
+                    }
+                    server.save_zones(zones)
+                }
+            });
+
+        let zone_delete_route = warp::path!("api" / "zones" / String)
+            .and(warp::delete())
+            .map({
+                let server = self.clone();
+                move |name: String| {
+                    let mut zones = server.config.get().planner.zones;
+                    let before = zones.len();
+                    zones.retain(|z| z.name != name);
+                    if zones.len() == before {
+                        return error_reply(StatusCode::NOT_FOUND, "no such zone");
+                    }
+                    server.save_zones(zones)
+                }
+            });
+
+        let gps_route = warp::path!("api" / "gps")
+            .and(warp::get())
+            .map({
+                let server = self.clone();
+                move || warp::reply::json(&server.path_planner.read().get_gps_status())
+            });
+
+        let localization_route = warp::path!("api" / "localization")
+            .and(warp::get())
+            .map({
+                let server = self.clone();
+                move || warp::reply::json(&server.path_planner.read().get_localization_status())
+            });
+
+        let goal_put_route = warp::path!("api" / "goal")
+            .and(warp::put())
+            .and(warp::body::content_length_limit(1024))
+            .and(warp::body::json())
+            .map({
+                let server = self.clone();
+                move |goal: GoalRequest| {
+                    let mut planner = server.path_planner.write();
+                    let set = match goal {
+                        GoalRequest::Map { x, y } => {
+                            planner.set_goal(Some(PathPoint { x, y }));
+                            Ok(())
+                        }
+                        GoalRequest::Geodetic { lat, lon } => planner.set_goal_geodetic(lat, lon),
+                    };
+                    match set {
+                        Ok(()) => warp::reply::with_status(warp::reply::json(&planner.get_map_state().goal), StatusCode::OK),
+                        Err(e) => error_reply(StatusCode::CONFLICT, &e.to_string()),
+                    }
+                }
+            });
+
+        let goal_delete_route = warp::path!("api" / "goal")
+            .and(warp::delete())
+            .map({
+                let server = self.clone();
+                move || {
+                    server.path_planner.write().set_goal(None);
+                    warp::reply::json(&serde_json::Value::Null)
+                }
+            });
+
+        let labels_route = warp::path!("api" / "labels")
+            .and(warp::get())
+            .map({
+                let server = self.clone();
+                move || warp::reply::json(&server.vision.read().get_labels())
+            });
+
+        let rules_route = warp::path!("api" / "rules")
+            .and(warp::get())
+            .map({
+                let server = self.clone();
+                move || warp::reply::json(&serde_json::json!({
+                    "path": server.config.get().vision.rules_path,
+                    "rules": server.vision.read().get_rules(),
+                }))
+            });
+
+        let rules_reload_route = warp::path!("api" / "rules" / "reload")
+            .and(warp::post())
+            .map({
+                let server = self.clone();
+                move || server.handle_rules_reload()
+            });
+
+        let assets_route = warp::path!("api" / "assets")
+            .and(warp::get())
+            .map(|| match ASSETS.get_manifests() {
+                Some(manifests) => warp::reply::with_status(warp::reply::json(&manifests), StatusCode::OK),
+                None => error_reply(StatusCode::SERVICE_UNAVAILABLE, &AssetError::Disabled.to_string()),
+            });
+
+        let asset_upload_route = warp::path!("api" / "assets" / "upload")
+            .and(warp::post())
+            .and(warp::header::optional::<String>("authorization"))
+            .and(warp::query::<AssetUploadQuery>())
+            .and(warp::body::content_length_limit(self.config.get().updates.max_upload_mb * 1024 * 1024))
+            .and(warp::body::bytes())
+            .map({
+                let server = self.clone();
+                move |authorization: Option<String>, query: AssetUploadQuery, body: bytes::Bytes| {
+                    server.handle_asset_upload(authorization.as_deref(), query, &body)
+                }
+            });
+
+        let asset_activate_route = warp::path!("api" / "assets" / String / "activate")
+            .and(warp::post())
+            .and(warp::header::optional::<String>("authorization"))
+            .and(warp::body::content_length_limit(1024))
+            .and(warp::body::json())
+            .map({
+                let server = self.clone();
+                move |kind: String, authorization: Option<String>, request: AssetActivateRequest| {
+                    server.handle_asset_change(authorization.as_deref(), &kind, Some(&request.version))
+                }
+            });
+
+        let asset_rollback_route = warp::path!("api" / "assets" / String / "rollback")
+            .and(warp::post())
+            .and(warp::header::optional::<String>("authorization"))
+            .map({
+                let server = self.clone();
+                move |kind: String, authorization: Option<String>| server.handle_asset_change(authorization.as_deref(), &kind, None)
+            });
+
+        // Boxed as a group, which keeps the type of the whole route table
+        // within the compiler's query depth limit.
+        let asset_routes = assets_route
+            .or(asset_upload_route)
+            .or(asset_activate_route)
+            .or(asset_rollback_route)
+            .map(Reply::into_response)
+            .boxed();
+
+        let hazard_route = warp::path!("api" / "hazard")
+            .and(warp::post())
+            .and(warp::header::optional::<String>("authorization"))
+            .and(warp::body::content_length_limit(4 * 1024))
+            .and(warp::body::json())
+            .map({
+                let server = self.clone();
+                move |authorization: Option<String>, report: HazardReport| server.handle_hazard(authorization.as_deref(), report)
+            });
+
+        let hazards_route = warp::path!("api" / "hazards")
+            .and(warp::get())
+            .map(|| warp::reply::json(&serde_json::json!({ "hazards": HAZARDS.get_active() })));
+
+        let hazard_routes = hazard_route
+            .or(hazards_route)
+            .map(Reply::into_response)
+            .boxed();
+
+        let vision_profiles_route = warp::path!("api" / "vision" / "profiles")
+            .and(warp::get())
+            .map({
+                let vision = self.vision.clone();
+                move || {
+                    let vision = vision.read();
+                    warp::reply::json(&serde_json::json!({
+                        "status": vision.get_profile_status(),
+                        "profiles": vision.get_profiles(),
+                    }))
+                }
+            });
+
+        let vision_profile_route = warp::path!("api" / "vision" / "profile")
+            .and(warp::post())
+            .and(warp::body::content_length_limit(1024))
+            .and(warp::body::json())
+            .map({
+                let server = self.clone();
+                move |request: VisionProfileRequest| server.handle_vision_profile(request.profile.as_deref())
+            });
+
+        let vision_profile_auto_route = warp::path!("api" / "vision" / "profile" / "auto")
+            .and(warp::post())
+            .map({
+                let vision = self.vision.clone();
+                move || {
+                    let mut vision = vision.write();
+                    vision.set_auto_profile_paused(false);
+                    warp::reply::json(&vision.get_profile_status())
+                }
+            });
+
+        let vision_profile_routes = vision_profiles_route
+            .or(vision_profile_route)
+            .or(vision_profile_auto_route)
+            .map(Reply::into_response)
+            .boxed();
+
+        // What a mask editor needs: the polygons, the frame they are drawn
+        // on and what they took out of the last one.
+        let self_mask_get_route = warp::path!("api" / "vision" / "self_mask")
+            .and(warp::get())
+            .map({
+                let server = self.clone();
+                move || {
+                    let vision = server.config.get().vision;
+                    warp::reply::json(&serde_json::json!({
+                        "polygons": vision.self_mask,
+                        "min_overlap": vision.self_mask_min_overlap,
+                        "width": FRAME_WIDTH_PX,
+                        "height": FRAME_HEIGHT_PX,
+                        "image": "/api/frame.jpg",
+                        "self_occluded": server.state.get_world().self_occluded,
+                    }))
+                }
+            });
+
+        let self_mask_put_route = warp::path!("api" / "vision" / "self_mask")
+            .and(warp::put())
+            .and(warp::body::content_length_limit(64 * 1024))
+            .and(warp::body::json())
+            .map({
+                let server = self.clone();
+                move |edit: SelfMaskEdit| {
+                    let mut vision = serde_json::json!({ "self_mask": edit.polygons });
+                    if let Some(min_overlap) = edit.min_overlap {
+                        vision["self_mask_min_overlap"] = min_overlap.into();
+                    }
+                    server.handle_config_patch(&serde_json::json!({ "vision": vision }), true)
+                }
+            });
+
+        let self_mask_proposal_route = warp::path!("api" / "vision" / "self_mask" / "proposal")
+            .and(warp::get())
+            .and(warp::query::<SelfMaskProposalQuery>())
+            .map({
+                let vision = self.vision.clone();
+                move |query: SelfMaskProposalQuery| warp::reply::json(&vision.read().propose_self_mask(query.min_frames.max(1)))
+            });
+
+        let self_mask_proposal_reset_route = warp::path!("api" / "vision" / "self_mask" / "proposal")
+            .and(warp::delete())
+            .map({
+                let vision = self.vision.clone();
+                move || {
+                    vision.write().clear_self_mask_proposal();
+                    warp::reply::json(&serde_json::json!({ "cleared": true }))
+                }
+            });
+
+        let heatmap_route = warp::path!("api" / "vision" / "heatmap")
+            .and(warp::get())
+            .and(warp::query::<HeatmapQuery>())
+            .map({
+                let vision = self.vision.clone();
+                move |query: HeatmapQuery| warp::reply::json(&vision.read().get_heatmap(query.class.as_deref()))
+            });
+
+        let heatmap_reset_route = warp::path!("api" / "vision" / "heatmap")
+            .and(warp::delete())
+            .map({
+                let vision = self.vision.clone();
+                move || {
+                    vision.read().clear_heatmap();
+                    warp::reply::json(&serde_json::json!({ "cleared": true }))
+                }
+            });
+
+        let sweep_route = warp::path!("api" / "vision" / "sweep")
+            .and(warp::get())
+            .and(warp::query::<SweepQuery>())
+            .map({
+                let vision = self.vision.clone();
+                move |query: SweepQuery| {
+                    let thresholds = match query.thresholds.as_deref().map(parse_thresholds).transpose() {
+                        Ok(thresholds) => thresholds,
+                        Err(e) => return error_reply(StatusCode::BAD_REQUEST, &e),
+                    };
+                    match vision.read().get_sweep_report(query.minutes, thresholds) {
+                        Ok(report) => warp::reply::with_status(warp::reply::json(&report), StatusCode::OK),
+                        Err(e) => error_reply(StatusCode::BAD_REQUEST, &format!("{:#}", e)),
+                    }
+                }
+            });
+
+        let sweep_start_route = warp::path!("api" / "vision" / "sweep")
+            .and(warp::post())
+            .and(warp::body::content_length_limit(1024))
+            .and(warp::body::json())
+            .map({
+                let vision = self.vision.clone();
+                move |start: SweepStart| match vision.read().start_sweep(start.duration_s, start.floor) {
+                    Ok(status) => warp::reply::with_status(warp::reply::json(&status), StatusCode::CREATED),
+                    Err(e) => error_reply(StatusCode::BAD_REQUEST, &format!("{:#}", e)),
+                }
+            });
+
+        let sweep_stop_route = warp::path!("api" / "vision" / "sweep")
+            .and(warp::delete())
+            .map({
+                let vision = self.vision.clone();
+                move || warp::reply::json(&serde_json::json!({ "stopped": vision.read().stop_sweep() }))
+            });
+
+        let sweep_routes = sweep_route
+            .or(sweep_start_route)
+            .or(sweep_stop_route)
+            .map(Reply::into_response)
+            .boxed();
+
+        let self_mask_routes = self_mask_get_route
+            .or(self_mask_put_route)
+            .or(self_mask_proposal_route)
+            .or(self_mask_proposal_reset_route)
+            .or(heatmap_route)
+            .or(heatmap_reset_route)
+            .or(sweep_routes)
+            .map(Reply::into_response)
+            .boxed();
+
+        // What a calibration tool needs: the current calibration and how
+        // well it fits its points, and the frame to click them on.
+        let ground_calibration_get_route = warp::path!("api" / "vision" / "ground_calibration")
+            .and(warp::get())
+            .map({
+                let config = self.config.clone();
+                move || {
+                    let calibration = config.get().vision.ground_calibration;
+                    let residuals = calibration.as_ref().map(|c| ground::get_residuals(&c.homography, &c.points));
+                    warp::reply::json(&serde_json::json!({
+                        "calibration": calibration,
+                        "residuals": residuals,
+                        "min_points": ground::MIN_POINTS,
+                        "width": FRAME_WIDTH_PX,
+                        "height": FRAME_HEIGHT_PX,
+                        "image": "/api/frame.jpg",
+                    }))
+                }
+            });
+
+        let ground_calibration_post_route = warp::path!("api" / "vision" / "ground_calibration")
+            .and(warp::post())
+            .and(warp::body::content_length_limit(64 * 1024))
+            .and(warp::body::json())
+            .map({
+                let server = self.clone();
+                move |edit: GroundCalibrationEdit| {
+                    let previous = server.config.get().vision.ground_calibration;
+                    match ground::calibrate(&edit.points, previous.as_ref()) {
+                        Ok(calibration) => {
+                            info!(target: "vision", "Ground calibration {} fitted to {} points, {:.3} m RMS", calibration.version, calibration.points.len(), calibration.rms_error_m);
+                            server.handle_config_patch(&serde_json::json!({ "vision": { "ground_calibration": calibration } }), true)
+                        }
+                        Err(e) => error_reply(StatusCode::UNPROCESSABLE_ENTITY, &format!("{:#}", e)),
+                    }
+                }
+            });
+
+        let ground_route = warp::path!("api" / "vision" / "ground")
+            .and(warp::get())
+            .and(warp::query::<GroundQuery>())
+            .map({
+                let vision = self.vision.clone();
+                move |query: GroundQuery| {
+                    let vision = vision.read();
+                    let Some(version) = vision.get_ground_calibration_version() else {
+                        return error_reply(StatusCode::CONFLICT, "no ground calibration yet");
+                    };
+                    let ground = vision.project_to_ground(query.px, query.py);
+                    warp::reply::with_status(warp::reply::json(&serde_json::json!({
+                        "px": query.px,
+                        "py": query.py,
+                        "ground": ground.map(|(x_m, y_m)| serde_json::json!({ "x_m": x_m, "y_m": y_m })),
+                        "version": version,
+                    })), StatusCode::OK)
+                }
+            });
+
+        let ground_routes = ground_calibration_get_route
+            .or(ground_calibration_post_route)
+            .or(ground_route)
+            .map(Reply::into_response)
+            .boxed();
+
+        let bridge_diagnostics_get_route = warp::path!("api" / "bridge" / "diagnostics")
+            .and(warp::get())
+            .map(|| warp::reply::json(&BRIDGE_TAP.get_summary()));
+
+        let bridge_diagnostics_post_route = warp::path!("api" / "bridge" / "diagnostics")
+            .and(warp::post())
+            .and(warp::body::content_length_limit(1024))
+            .and(warp::body::json())
+            .map({
+                let config = self.config.clone();
+                move |request: BridgeDiagnosticsRequest| {
+                    let Some(level) = request.level else {
+                        return warp::reply::with_status(warp::reply::json(&BRIDGE_TAP.disable()), StatusCode::OK);
+                    };
+                    if request.minutes.is_some_and(|m| !(m > 0.0 && m <= tap::MAX_MINUTES)) {
+                        return error_reply(StatusCode::BAD_REQUEST, &format!("minutes must be over 0 and at most {}", tap::MAX_MINUTES));
+                    }
+                    match BRIDGE_TAP.enable(level, request.minutes, &config.get().vision) {
+                        Ok(summary) => warp::reply::with_status(warp::reply::json(&summary), StatusCode::OK),
+                        Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &format!("{:#}", e)),
+                    }
+                }
+            });
+
+        let bridge_diagnostics_routes = bridge_diagnostics_get_route
+            .or(bridge_diagnostics_post_route)
+            .map(Reply::into_response)
+            .boxed();
+
+        let logs_route = warp::path!("api" / "logs")
+            .and(warp::get())
+            .and(warp::query::<LogsQuery>())
+            .map(|query: LogsQuery| {
+                let level = match query.level.as_deref().map(LogLevel::parse) {
+                    Some(None) => return error_reply(StatusCode::BAD_REQUEST, "level must be trace, debug, info, warn or error"),
+                    Some(level) => level,
+                    None => None,
+                };
+                let since_ms = match query.since.as_deref().map(blackbox::parse_time) {
+                    Some(None) => return error_reply(StatusCode::BAD_REQUEST, "since must look like 10m, epoch milliseconds or an RFC 3339 time"),
+                    Some(since) => since,
+                    None => None,
+                };
+                let logs = LOGS.query(&LogQuery { level, since_ms, after: query.after, target: query.target, limit: Some(query.limit) });
+                warp::reply::with_status(warp::reply::json(&serde_json::json!({
+                    "buffer": LOGS.get_summary(),
+                    "logs": logs,
+                })), StatusCode::OK)
+            })
+            .map(Reply::into_response)
+            .boxed();
+
+        let power_route = warp::path!("api" / "power")
+            .and(warp::get())
+            .map(|| warp::reply::json(&POWER.get_status()));
+
+        let power_wake_route = warp::path!("api" / "power" / "wake")
+            .and(warp::post())
+            .map(|| {
+                POWER.request_wake("api");
+                warp::reply::json(&POWER.get_status())
+            });
+
+        let power_routes = power_route
+            .or(power_wake_route)
+            .map(Reply::into_response)
+            .boxed();
+
+        let display_get_route = warp::path!("api" / "display")
+            .and(warp::get())
+            .map({
+                let server = self.clone();
+                move || warp::reply::json(&server.get_display_preferences())
+            });
+
+        let display_patch_route = warp::path!("api" / "display")
+            .and(warp::patch())
+            .and(warp::body::content_length_limit(64 * 1024))
+            .and(warp::body::json())
+            .map({
+                let server = self.clone();
+                move |patch: serde_json::Value| server.handle_display_patch(patch)
+            });
+
+        let state_route = warp::path!("api" / "state")
+            .and(warp::get())
+            .map({
+                let state = self.state.clone();
+                move || warp::reply::json(&state.get_status())
+            });
+
+        let status_route = warp::path!("api" / "status")
+            .and(warp::get())
+            .map({
+                let state = self.state.clone();
+                move || warp::reply::json(&serde_json::json!({
+                    "state": state.get_status(),
+                    "subsystems": state.get_subsystems(),
+                }))
+            });
+
+        let subsystem_retry_route = warp::path!("api" / "subsystems" / String / "retry")
+            .and(warp::post())
+            .map({
+                let state = self.state.clone();
+                move |name: String| match state.retry_subsystem(&name) {
+                    Ok(()) => warp::reply::with_status(warp::reply::json(&state.get_subsystems()), StatusCode::ACCEPTED),
+                    Err(e) if e.starts_with("no subsystem") => error_reply(StatusCode::NOT_FOUND, &e),
+                    Err(e) => error_reply(StatusCode::CONFLICT, &e),
+                }
+            });
+
+        let resume_get_route = warp::path!("api" / "resume")
+            .and(warp::get())
+            .map({
+                let state = self.state.clone();
+                move || match state.get_resume() {
+                    Some(offer) => warp::reply::with_status(warp::reply::json(&offer), StatusCode::OK),
+                    None => error_reply(StatusCode::NOT_FOUND, "no interrupted mission to resume"),
+                }
+            });
+
+        let resume_post_route = warp::path!("api" / "resume")
+            .and(warp::post())
+            .map({
+                let (state, planner) = (self.state.clone(), self.path_planner.clone());
+                move || match checkpoint::confirm(&state, &planner, "api") {
+                    Some(offer) => warp::reply::with_status(warp::reply::json(&offer), StatusCode::ACCEPTED),
+                    None => error_reply(StatusCode::NOT_FOUND, "no interrupted mission to resume"),
+                }
+            });
+
+        let resume_delete_route = warp::path!("api" / "resume")
+            .and(warp::delete())
+            .map({
+                let state = self.state.clone();
+                move || match checkpoint::decline(&state, "api") {
+                    Some(offer) => warp::reply::with_status(warp::reply::json(&offer), StatusCode::OK),
+                    None => error_reply(StatusCode::NOT_FOUND, "no interrupted mission to resume"),
+                }
+            });
+
+        let startup_routes = status_route
+            .or(subsystem_retry_route)
+            .or(resume_get_route)
+            .or(resume_post_route)
+            .or(resume_delete_route)
+            .map(Reply::into_response)
+            .boxed();
+
+        let world_route = warp::path!("api" / "world")
+            .and(warp::get())
+            .map({
+                let state = self.state.clone();
+                move || warp::reply::json(&*state.get_world())
+            });
+
+        let identity_route = warp::path!("api" / "identity")
+            .and(warp::get())
+            .map({
+                let identity = self.identity.clone();
+                move || warp::reply::json(&identity_payload(&identity))
+            });
+
+        let api_index_route = warp::path("api")
+            .and(warp::path::end())
+            .and(warp::get())
+            .map(|| warp::reply::json(&api_index()));
+
+        let frame_route = warp::path!("api" / "frame.jpg")
+            .and(warp::get())
+            .and(warp::query::<FrameQuery>())
+            .then({
+                let server = self.clone();
+                move |query: FrameQuery| {
+                    let server = server.clone();
+                    async move { server.handle_annotated_frame(query).await }
+                }
+            });
+
+        let stream_route = warp::path!("api" / "stream.mjpeg")
+            .and(warp::get())
+            .and(warp::query::<FrameQuery>())
+            .map({
+                let server = self.clone();
+                move |query: FrameQuery| server.handle_frame_stream(query)
+            });
+
+        let frame_routes = frame_route
+            .or(stream_route)
+            .map(Reply::into_response)
+            .boxed();
+
+        let map_route = warp::path!("api" / "map")
+            .and(warp::get())
+            .and(warp::query::<MapQuery>())
+            .map({
+                let server = self.clone();
+                move |query: MapQuery| {
+                    let planner = server.path_planner.read();
+                    let grid = match query.since {
+                        Some(since) => serde_json::to_value(planner.get_map_diff(since)),
+                        None => serde_json::to_value(planner.get_map_snapshot()),
+                    }
+                    .unwrap_or_default();
+                    warp::reply::json(&serde_json::json!({
+                        "grid": grid,
+                        "state": planner.get_map_state(),
+                    }))
+                }
+            });
+
+        let sessions_route = warp::path!("api" / "sessions")
+            .and(warp::get())
+            .map({
+                let server = self.clone();
+                move || match session::list_sessions(&server.sessions_dir, server.get_recording_name()) {
+                    Ok(sessions) => warp::reply::with_status(warp::reply::json(&sessions), StatusCode::OK),
+                    Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+                }
+            });
+
+        let session_delete_route = warp::path!("api" / "sessions" / String)
+            .and(warp::delete())
+            .map({
+                let server = self.clone();
+                move |name: String| server.handle_session_delete(&name)
+            });
+
+        let replay_start_route = warp::path!("api" / "replay" / "start")
+            .and(warp::get())
+            .and(warp::query::<ReplayQuery>())
+            .map({
+                let server = self.clone();
+                move |query: ReplayQuery| server.clone().start_replay(query)
+            });
+
+        let replay_stop_route = warp::path!("api" / "replay" / "stop")
+            .and(warp::get())
+            .map({
+                let server = self.clone();
+                move || warp::reply::json(&serde_json::json!({ "stopped": server.stop_replay() }))
+            });
+
+        let annotate_route = warp::path!("api" / "annotate")
+            .and(warp::post())
+            .and(warp::body::content_length_limit(16 * 1024))
+            .and(warp::body::json())
+            .map({
+                let server = self.clone();
+                move |request: AnnotateRequest| match server.annotate(request.label, request.note, "api".to_string()) {
+                    Ok(annotation) => warp::reply::with_status(warp::reply::json(&annotation), StatusCode::CREATED),
+                    Err((status, message)) => error_reply(status, &message),
+                }
+            });
+
+        let annotations_route = warp::path!("api" / "annotations")
+            .and(warp::get())
+            .and(warp::query::<AnnotationsQuery>())
+            .map({
+                let server = self.clone();
+                move |query: AnnotationsQuery| {
+                    let replaying = server.replay.lock().as_ref().map(|r| r.session.clone());
+                    let Some(name) = query.session.or(replaying).or_else(|| server.get_recording_name().map(str::to_string)) else {
+                        return error_reply(StatusCode::NOT_FOUND, "no session given, and none is being replayed or recorded");
+                    };
+                    if !session::is_session_name(&name) {
+                        return error_reply(StatusCode::BAD_REQUEST, "invalid session name");
+                    }
+                    match annotations::load(&server.sessions_dir.join(&name)) {
+                        Ok(annotations) => warp::reply::with_status(warp::reply::json(&serde_json::json!({
+                            "session": name,
+                            "annotations": annotations,
+                        })), StatusCode::OK),
+                        Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &format!("{:#}", e)),
+                    }
+                }
+            });
+
+        let annotation_routes = annotate_route
+            .or(annotations_route)
+            .map(Reply::into_response)
+            .boxed();
+
+        let planner_routes = schedule_get_route
+            .or(schedule_put_route)
+            .or(zones_get_route)
+            .or(zones_put_route)
+            .or(zone_put_route)
+            .or(zone_delete_route)
+            .or(gps_route)
+            .or(localization_route)
+            .or(goal_put_route)
+            .or(goal_delete_route)
+            .map(Reply::into_response)
+            .boxed();
+
+        healthz_route
+            .or(readyz_route)
+            .or(metrics_route)
+            .or(config_get_route)
+            .or(config_patch_route)
+            .or(events_route)
+            .or(clients_route)
+            .or(sessions_route)
+            .or(session_delete_route)
+            .or(replay_start_route)
+            .or(replay_stop_route)
+            .or(annotation_routes)
+            .or(api_index_route)
+            .or(map_route)
+            .or(frame_routes)
+            .or(identity_route)
+            .or(history_route)
+            .or(blackbox_route)
+            .or(selftest_route)
+            .or(state_route)
+            .or(startup_routes)
+            .or(world_route)
+            .or(labels_route)
+            .or(rules_route)
+            .or(rules_reload_route)
+            .or(asset_routes)
+            .or(hazard_routes)
+            .or(vision_profile_routes)
+            .or(self_mask_routes)
+            .or(ground_routes)
+            .or(bridge_diagnostics_routes)
+            .or(logs_route)
+            .or(power_routes)
+            .or(display_get_route)
+            .or(display_patch_route)
+            .or(motor_audit_route)
+            .or(planner_routes)
+            .map(Reply::into_response)
+            .map({
+                let rover_id = warp::http::HeaderValue::from_str(&self.identity.rover_id)
+                    .unwrap_or(warp::http::HeaderValue::from_static("unknown"));
+                move |mut response: Response| {
+                    response.headers_mut().insert("x-rover-id", rover_id.clone());
+                    response
+                }
+            })
+            .boxed()
+    }
+
+    fn ws_route(self: &Arc<Self>) -> BoxedFilter<(Response,)> {
+        warp::path("ws")
+            .and(warp::ws())
+            .and(warp::addr::remote())
+            .and(warp::header::optional::<String>("x-forwarded-for"))
+            .map({
+                let server = self.clone();
+                move |ws: warp::ws::Ws, peer: Option<SocketAddr>, forwarded_for: Option<String>| {
+                    let server = server.clone();
+                    ws.on_upgrade(move |websocket| {
+                        let server = server.clone();
+                        async move {
+                            server.handle_websocket(websocket, peer, forwarded_for).await
+                        }
+                    })
+                    .into_response()
+                }
+            })
+            .boxed()
+    }
+
+    async fn handle_websocket(&self, ws: warp::ws::WebSocket, peer: Option<SocketAddr>, forwarded_for: Option<String>) {
+        let (mut ws_tx, mut ws_rx) = ws.split();
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let client_id = {
+            let mut next_id = self.next_client_id.write();
+            let id = *next_id;
+            *next_id += 1;
+            id
+        };
+
+        let ping_tx = tx.clone();
+        let queued = Arc::new(AtomicUsize::new(1));
+        let hello = serde_json::json!({
+            "type": "hello",
+            "client_id": client_id,
+            "rover_id": self.identity.rover_id,
+            "rover_name": self.identity.rover_name,
+            "api_version": API_VERSION,
+        });
+        let _ = tx.send(warp::ws::Message::text(hello.to_string()));
+
+        self.clients.write().insert(client_id, Client {
+            tx,
+            frames: true,
+            masks: false,
+            rovers: None,
+            peer,
+            forwarded_for,
+            connected_at: Instant::now(),
+            messages_sent: AtomicU64::new(0),
+            queued: queued.clone(),
+            link: parking_lot::Mutex::new(LinkQuality::new()),
+            detection_sync: parking_lot::Mutex::new(None),
+            logs: None,
+            teleop: parking_lot::Mutex::new(TeleopSession::new()),
+        });
+        METRICS.websocket_clients.inc();
+        METRICS.websocket_connects.inc();
+        POWER.request_wake("web_client");
+        self.update_video_clients();
+
+        let mut rx = UnboundedReceiverStream::new(rx);
+        let written = queued.clone();
+        tokio::spawn(async move {
+            while let Some(msg) = rx.next().await {
+                if ws_tx.send(msg).await.is_err() {
+                    break;
+                }
+                written.fetch_sub(1, Ordering::Relaxed);
+            }
+        });
+
+        // A client that loses WiFi never closes its socket, so it is pinged
+        // and dropped after missing too many pongs. Anything it sends counts
+        // as a sign of life.
+        let web_config = self.config.get().web;
+        let mut ping_timer = tokio::time::interval(Duration::from_secs_f32(web_config.ping_interval_s));
+        ping_timer.tick().await;
+        let mut missed_pongs = 0u32;
+        let mut ping_sent = None;
+
+        let reason = loop {
+            tokio::select! {
+                _ = ping_timer.tick() => {
+                    if missed_pongs >= web_config.max_missed_pongs {
+                        break "timeout";
+                    }
+                    missed_pongs += 1;
+                    queued.fetch_add(1, Ordering::Relaxed);
+                    if ping_tx.send(warp::ws::Message::ping(Vec::new())).is_err() {
+                        break "error";
+                    }
+                    ping_sent = Some(Instant::now());
+                }
+                msg = ws_rx.next() => {
+                    let msg = match msg {
+                        Some(Ok(msg)) => msg,
+                        Some(Err(_)) => break "error",
+                        None => break "closed",
+                    };
+                    missed_pongs = 0;
+                    if msg.is_close() {
+                        break "closed";
+                    }
+                    if msg.is_pong() {
+                        if let (Some(sent), Some(client)) = (ping_sent.take(), self.clients.read().get(&client_id)) {
+                            client.link.lock().set_rtt(sent.elapsed());
+                        }
+                        continue;
+                    }
+                    let Ok(text) = msg.to_str() else { continue };
+                    self.handle_client_command(client_id, text);
+                }
+            }
+        };
+
+        self.remove_client(client_id, reason);
+    }
+
+    fn handle_client_command(&self, client_id: usize, text: &str) {
+        let Ok(command) = serde_json::from_str::<ClientCommand>(text) else { return };
+        match command {
+            ClientCommand::Subscribe { frames, rovers } => {
+                if let Some(client) = self.clients.write().get_mut(&client_id) {
+                    client.frames = frames;
+                    client.rovers = rovers;
+                    if let Some(sync) = client.detection_sync.get_mut() {
+                        sync.resync();
+                    }
+                }
+                self.update_video_clients();
+            }
+            ClientCommand::EmergencyStop => {
+                self.state.emergency_stop(format!("Emergency stop from WebSocket client {}", client_id));
+            }
+            ClientCommand::SetMode { mode } => self.state.request(match mode {
+                ModeRequest::Autonomous => Trigger::StartAutonomous,
+                ModeRequest::Manual => Trigger::TakeManual,
+                ModeRequest::Idle => Trigger::Pause,
+            }),
+            ClientCommand::AcknowledgeFault => self.state.request(Trigger::AcknowledgeFault),
+            ClientCommand::SetShadow { enabled } => self.state.request(Trigger::SetShadow(enabled)),
+            ClientCommand::Dock => {
+                let mut planner = self.path_planner.write();
+                planner.set_docking(true);
+                if planner.is_docking() {
+                    self.state.request(Trigger::StartAutonomous);
+                }
+            }
+            ClientCommand::Follow { track_id } => match self.path_planner.write().set_follow(track_id) {
+                Ok(()) if track_id.is_some() => self.state.request(Trigger::StartAutonomous),
+                Ok(()) => {}
+                Err(e) => warn!(target: "web", "Follow request from client {} refused: {:#}", client_id, e),
+            },
+            ClientCommand::OverrideSelftest => {
+                if !self.state.override_selftest(&format!("WebSocket client {}", client_id)) {
+                    warn!(target: "web", "Self-test override from client {} ignored: nothing to override", client_id);
+                }
+            }
+            ClientCommand::RerunSelftest => {
+                self.state.rerun_selftest();
+            }
+            ClientCommand::Annotate { label, note } => {
+                if let Err(e) = self.annotate(label, note, format!("ws:{}", client_id)) {
+                    warn!(target: "web", "Annotation from client {} refused: {}", client_id, e.1);
+                }
+            }
+            ClientCommand::Hello { quality, masks, detection_deltas } => {
+                let full_sync_ms = (self.config.get().web.detection_full_sync_s * 1000.0) as u64;
+                if let Some(client) = self.clients.write().get_mut(&client_id) {
+                    client.link.lock().set_mode(quality);
+                    client.masks = masks;
+                    *client.detection_sync.get_mut() = detection_deltas.then(|| DetectionSync::new(full_sync_ms));
+                }
+            }
+            ClientCommand::SubscribeLogs { level } => {
+                if let Some(client) = self.clients.write().get_mut(&client_id) {
+                    client.logs = Some(level.unwrap_or_else(|| LOGS.get_level()));
+                }
+                self.update_log_subscribers();
+            }
+            ClientCommand::UnsubscribeLogs => {
+                if let Some(client) = self.clients.write().get_mut(&client_id) {
+                    client.logs = None;
+                }
+                self.update_log_subscribers();
+            }
+            ClientCommand::Axis(command) => self.handle_axis(client_id, &command),
+        }
+    }
+
+    fn handle_axis(&self, client_id: usize, command: &AxisCommand) {
+        let config = self.config.get().teleop;
+        let clients = self.clients.read();
+        let Some(client) = clients.get(&client_id) else { return };
+        let outcome = client.teleop.lock().drive(command, &config, &self.state);
+        if outcome.result == AxisResult::Gap {
+            warn!(target: "web", "Stopped for client {}: {} axis commands missing before {}", client_id, outcome.missed, outcome.seq);
+        }
+        let mut ack = serde_json::to_value(outcome).unwrap_or_default();
+        ack["type"] = serde_json::json!("teleop");
+        client.send(&warp::ws::Message::text(ack.to_string()));
+    }
+
+    // Clients taking frames keep the rover from going to sleep.
+    fn update_video_clients(&self) {
+        POWER.set_video_clients(self.clients.read().values().filter(|c| c.frames).count());
+    }
+
+    fn update_log_subscribers(&self) {
+        METRICS.log_subscribers.set(self.clients.read().values().filter(|c| c.logs.is_some()).count() as i64);
+    }
+
+    // Dropping the Client drops its sender, which ends the writer task and
+    // frees anything still queued for it.
+    fn remove_client(&self, client_id: usize, reason: &'static str) {
+        let Some(client) = self.clients.write().remove(&client_id) else { return };
+        if client.teleop.lock().is_driving() && self.state.stop_manual() {
+            info!(target: "web", "Stopped: client {} went while driving", client_id);
+        }
+        METRICS.websocket_clients.dec();
+        self.update_video_clients();
+        self.update_log_subscribers();
+        METRICS.websocket_disconnects.inc(reason);
+
+        if reason == "timeout" {
+            EVENTS.publish(Severity::Warning, "web", format!("WebSocket client {} timed out", client_id), serde_json::json!({
+                "client_id": client_id,
+                "peer": client.peer.map(|p| p.to_string()),
+                "connected_s": client.connected_at.elapsed().as_secs_f32(),
+            }));
+        } else {
+            info!(target: "web", "WebSocket client {} disconnected ({})", client_id, reason);
+        }
+    }
+
+    fn handle_config_patch(&self, patch: &serde_json::Value, persist: bool) -> warp::reply::WithStatus<warp::reply::Json> {
+        let config = match self.config.update(patch) {
+            Ok(config) => config,
+            Err(errors) => {
+                return warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({ "errors": errors })),
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                );
+            }
+        };
+
+        self.apply_config(&config);
+
+        let persist_error = if persist {
+            self.config.persist().err().map(|e| e.to_string())
+        } else {
+            None
+        };
+        if let Some(e) = &persist_error {
+            EVENTS.publish(Severity::Warning, "config", format!("Failed to persist config: {}", e), serde_json::Value::Null);
+        }
+
+        self.broadcast(&serde_json::json!({
+            "type": "config_changed",
+            "config": config,
+        }));
+        EVENTS.publish(Severity::Info, "config", "Runtime configuration updated", patch.clone());
+
+        warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({
+                "config": config,
+                "persisted": persist && persist_error.is_none(),
+                "persist_error": persist_error,
+            })),
+            StatusCode::OK,
+        )
+    }
+
+    fn handle_rules_reload(&self) -> warp::reply::WithStatus<warp::reply::Json> {
+        let mut vision = self.vision.write();
+        match vision.reload_rules() {
+            Ok(count) => {
+                EVENTS.publish(Severity::Info, "vision", format!("Loaded {} detection rules", count), serde_json::Value::Null);
+                warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({ "loaded": count, "rules": vision.get_rules() })),
+                    StatusCode::OK,
+                )
+            }
+            Err(errors) => {
+                EVENTS.publish(Severity::Warning, "vision", "Detection rules not reloaded", serde_json::json!({ "errors": errors }));
+                warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({ "errors": errors })),
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                )
+            }
+        }
+    }
+
+    // A profile chosen here holds until automatic switching is resumed.
+    fn handle_vision_profile(&self, profile: Option<&str>) -> warp::reply::WithStatus<warp::reply::Json> {
+        let mut vision = self.vision.write();
+        match vision.set_profile(profile, ProfileCause::Manual) {
+            Ok(_) => {
+                vision.set_auto_profile_paused(true);
+                warp::reply::with_status(warp::reply::json(&vision.get_profile_status()), StatusCode::OK)
+            }
+            Err(e) => error_reply(StatusCode::NOT_FOUND, &e.to_string()),
+        }
+    }
+
+    // Every asset change needs the bearer token; without one configured
+    // nothing can be changed at all.
+    fn check_update_token(&self, authorization: Option<&str>) -> Result<(), warp::reply::WithStatus<warp::reply::Json>> {
+        check_bearer(self.update_token.as_deref(), authorization, "update", "updates.token_env")
+    }
+
+    // Out-of-band detectors report hazards that stop or slow the rover.
+    fn handle_hazard(&self, authorization: Option<&str>, report: HazardReport) -> warp::reply::WithStatus<warp::reply::Json> {
+        if let Err(reply) = check_bearer(self.hazard_token.as_deref(), authorization, "hazard", "hazards.token_env") {
+            METRICS.hazard_reports.inc("unauthorized");
+            return reply;
+        }
+        let source = report.source.clone();
+        match HAZARDS.report(report) {
+            Ok(outcome) => {
+                let status = if outcome == ReportOutcome::Accepted { StatusCode::CREATED } else { StatusCode::OK };
+                warp::reply::with_status(warp::reply::json(&serde_json::json!({
+                    "result": outcome,
+                    "hazards": HAZARDS.get_active(),
+                })), status)
+            }
+            Err(HazardError::RateLimited(retry_s)) => {
+                warn!(target: "web", "Hazard report from {} rate limited", source);
+                warp::reply::with_status(warp::reply::json(&serde_json::json!({
+                    "error": HazardError::RateLimited(retry_s).to_string(),
+                    "retry_after_s": retry_s,
+                })), StatusCode::TOO_MANY_REQUESTS)
+            }
+            Err(e) => {
+                let status = match e {
+                    HazardError::Disabled => StatusCode::SERVICE_UNAVAILABLE,
+                    HazardError::Full => StatusCode::TOO_MANY_REQUESTS,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                error_reply(status, &e.to_string())
+            }
+        }
+    }
+
+    fn handle_asset_upload(&self, authorization: Option<&str>, query: AssetUploadQuery, body: &[u8]) -> warp::reply::WithStatus<warp::reply::Json> {
+        if let Err(reply) = self.check_update_token(authorization) {
+            return reply;
+        }
+        let Some(kind) = ArtifactKind::parse(&query.kind) else {
+            return error_reply(StatusCode::BAD_REQUEST, "kind must be bridge_script, model_config or labels");
+        };
+        match ASSETS.stage(kind, &query.filename, &query.sha256, body) {
+            Ok(version) => warp::reply::with_status(warp::reply::json(&version), StatusCode::CREATED),
+            Err(e) => asset_error_reply(&e),
+        }
+    }
 
-#[derive(Serialize)]
-struct NavigationInfo {
-    action: String,
-    speed: f32,
-    heading: f32,
-    distance_to_goal: Option<f32>,
-}
+    // Activates a version, or rolls back with none. Only while idle, since
+    // the bridge is restarted and vision goes away meanwhile.
+    fn handle_asset_change(&self, authorization: Option<&str>, kind: &str, version: Option<&str>) -> warp::reply::WithStatus<warp::reply::Json> {
+        if let Err(reply) = self.check_update_token(authorization) {
+            return reply;
+        }
+        let Some(kind) = ArtifactKind::parse(kind) else {
+            return error_reply(StatusCode::NOT_FOUND, "no such asset kind");
+        };
+        let state = self.state.get_state();
+        if state != RoverState::Idle {
+            return error_reply(StatusCode::CONFLICT, &format!("assets can only be changed while idle, not {}", state.as_str()));
+        }
+        let changed = match version {
+            Some(version) => ASSETS.activate(kind, version),
+            None => ASSETS.rollback(kind),
+        };
+        match changed {
+            Ok(manifest) => {
+                let restarted = self.vision.write().apply_assets();
+                warp::reply::with_status(warp::reply::json(&asset_change_payload(kind, &manifest, restarted)), StatusCode::OK)
+            }
+            Err(e) => asset_error_reply(&e),
+        }
+    }
 
-pub struct WebServer {
-    vision: Arc<RwLock<VisionSystem>>,
-    path_planner: Arc<RwLock<PathPlanner>>,
-    motor_controller: Arc<RwLock<MotorController>>,
-    clients: Arc<RwLock<HashMap<usize, mpsc::UnboundedSender<warp::ws::Message>>>>,
-    next_client_id: Arc<RwLock<usize>>,
-}
+    fn save_zones(&self, zones: Vec<Zone>) -> warp::reply::WithStatus<warp::reply::Json> {
+        self.handle_config_patch(&serde_json::json!({ "planner": { "zones": zones } }), true)
+    }
 
-impl WebServer {
-    pub fn new(
-        vision: Arc<RwLock<VisionSystem>>,
-        path_planner: Arc<RwLock<PathPlanner>>,
-        motor_controller: Arc<RwLock<MotorController>>,
-    ) -> Self {
-        Self {
-            vision,
-            path_planner,
-            motor_controller,
-            clients: Arc::new(RwLock::new(HashMap::new())),
-            next_client_id: Arc::new(RwLock::new(0)),
+    // Saved like any other config change. A class set to null goes back
+    // to its defaults, since a merge patch can't remove it.
+    fn handle_display_patch(&self, mut patch: serde_json::Value) -> warp::reply::WithStatus<warp::reply::Json> {
+        if let Some(classes) = patch.get_mut("classes").and_then(serde_json::Value::as_object_mut) {
+            for display in classes.values_mut().filter(|d| d.is_null()) {
+                *display = serde_json::to_value(ClassDisplay::default()).unwrap_or_default();
+            }
         }
+        self.handle_config_patch(&serde_json::json!({ "display": patch }), true)
     }
 
-    pub async fn run(self: Arc<Self>) {
-        let static_files = warp::fs::dir("static");
+    // Every class there is a reason to style: those configured, those in
+    // the label map and those in view.
+    fn get_display_preferences(&self) -> DisplayPreferences {
+        let labels = self.vision.read().get_labels();
+        let world = self.state.get_world();
+        let detections = &world.detections;
+        let display = self.display.read();
+        let classes: std::collections::BTreeSet<&str> = display.classes.keys().map(String::as_str)
+            .chain(labels.values().map(|l| l.name.as_str()))
+            .chain(detections.iter().map(|d| d.class_name.as_str()))
+            .collect();
+        DisplayPreferences::resolve(&display, &labels, classes)
+    }
 
-        let ws_route = warp::path("ws")
-            .and(warp::ws())
-            .map({
-                let server = self.clone();
-                move |ws: warp::ws::Ws| {
-                    let server = server.clone();
-                    ws.on_upgrade(move |websocket| {
-                        let server = server.clone();
-                        async move {
-                            server.handle_websocket(websocket).await
-                        }
-                    })
-                }
-            });
+    fn apply_config(&self, config: &RoverConfig) {
+        *self.display.write() = config.display.clone();
+        LOGS.apply_config(config);
+        self.motor_controller.write().apply_config(config.motors.clone());
+        self.vision.write().apply_config(config.vision.clone());
+        self.path_planner.write().apply_config(config.planner.clone());
+        info!(target: "web", "Applied runtime config update");
+    }
 
-        let routes = static_files.or(ws_route);
+    fn broadcast(&self, payload: &serde_json::Value) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(payload);
+        }
+        self.send_to_clients(payload, false);
+    }
 
-        self.clone().start_frame_broadcaster();
+    // Live frames are held back while a replay runs so the two streams
+    // don't interleave on the dashboard. They are still recorded.
+    fn broadcast_frame(&self, payload: &serde_json::Value) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(payload);
+        }
+        if self.replay.lock().is_none() {
+            self.send_to_clients(payload, true);
+        }
+    }
 
-        println!(" Web server ready at http://0.0.0.0:8080");
-        warp::serve(routes).run(([0, 0, 0, 0], 8080)).await;
+    // A live frame with its picture, which is only encoded for the
+    // recording and the quality levels clients actually take.
+    fn broadcast_picture(&self, payload: &serde_json::Value, image: &FrameImage) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record_frame(payload, image);
+        }
+        if self.replay.lock().is_none() {
+            self.send_with_image(payload, true, Some(image));
+        }
     }
 
-    async fn handle_websocket(&self, ws: warp::ws::WebSocket) {
-        let (mut ws_tx, mut ws_rx) = ws.split();
-        let (tx, rx) = mpsc::unbounded_channel();
+    fn send_to_clients(&self, payload: &serde_json::Value, frames_only: bool) {
+        self.send_with_image(payload, frames_only, None);
+    }
 
-        let client_id = {
-            let mut next_id = self.next_client_id.write();
-            let id = *next_id;
-            *next_id += 1;
-            id
+    // Every payload is stamped with the rover it came from. Payloads that
+    // already carry a rover_id (relayed from another rover) keep theirs.
+    // Without `image` the picture is whatever the payload carries.
+    fn send_with_image(&self, payload: &serde_json::Value, frames_only: bool, image: Option<&FrameImage>) {
+        let mut payload = payload.clone();
+        let rover_id = match payload.as_object_mut() {
+            Some(obj) => obj.entry("rover_id")
+                .or_insert_with(|| serde_json::Value::String(self.identity.rover_id.clone()))
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            None => self.identity.rover_id.clone(),
+        };
+
+        let inline = match image {
+            Some(_) => None,
+            None => payload.get("image").and_then(|i| i.as_str()).map(|i| FrameImage::from_jpeg_base64(i.to_string())),
         };
+        let image = image.or(inline.as_ref()).filter(|i| frames_only && !i.is_empty());
+        let masked = has_masks(&payload);
+        let mut targets: Vec<(usize, usize, bool)> = Vec::new();
+        for (id, client) in self.clients.read().iter().filter(|(_, c)| c.wants(&rover_id, frames_only)) {
+            let masks = client.masks || !masked;
+            let level = match image {
+                Some(_) => client.link.lock().update(client.queued.load(Ordering::Relaxed), &self.web),
+                None => 0,
+            };
+            targets.push((*id, level, masks));
+        }
+        // A picture not yet in the payload goes in as captured only if
+        // some client takes it that way.
+        if let (Some(image), None, true) = (image, &inline, targets.iter().any(|(_, level, _)| *level == 0)) {
+            match image.get_jpeg_base64() {
+                Ok(jpeg_base64) => payload["image"] = serde_json::Value::String(jpeg_base64.to_string()),
+                Err(e) => warn!(target: "web", "Failed to encode frame: {:#}", e),
+            }
+        }
 
-        self.clients.write().insert(client_id, tx);
+        // Masks are large, so only clients that asked for them get them;
+        // the rest get `bare`.
+        let bare = strip_masks(&payload);
+        let variant = |masks: bool| if masks { &payload } else { bare.as_ref().unwrap_or(&payload) };
 
-        let mut rx = UnboundedReceiverStream::new(rx);
-        tokio::spawn(async move {
-            while let Some(msg) = rx.next().await {
-                if ws_tx.send(msg).await.is_err() {
-                    break;
+        // Frames go out at each client's quality level, encoded once per
+        // level and without holding the client list while encoding.
+        let mut encoded: HashMap<(usize, bool), Option<serde_json::Value>> = HashMap::new();
+        if let Some(image) = image {
+            for &(_, level, masks) in targets.iter().filter(|(_, level, _)| *level > 0) {
+                encoded.entry((level, masks)).or_insert_with(|| self.frame_at_level(variant(masks), image, level));
+            }
+        }
+
+        // Each variant is serialized once, except for clients taking
+        // detection deltas, whose detections are their own.
+        let keyed = payload.get("detections").is_some_and(|d| d.is_array());
+        let now_ms = CLOCK.now_ms();
+        let mut messages: HashMap<(usize, bool), warp::ws::Message> = HashMap::new();
+        let clients = self.clients.read();
+        for (id, level, masks) in targets {
+            let Some(client) = clients.get(&id) else { continue };
+            let frame = encoded.get(&(level, masks)).and_then(Option::as_ref).unwrap_or_else(|| variant(masks));
+            match client.detection_sync.lock().as_mut().filter(|_| keyed) {
+                Some(sync) => {
+                    let mut frame = frame.clone();
+                    sync.apply(&mut frame, now_ms);
+                    client.send(&warp::ws::Message::text(frame.to_string()));
                 }
+                None => client.send(messages.entry((level, masks)).or_insert_with(|| warp::ws::Message::text(frame.to_string()))),
             }
-        });
+        }
+    }
 
-        while let Some(msg) = ws_rx.next().await {
-            if let Ok(msg) = msg {
-            } else {
-                break;
+    // None if the frame can't be re-encoded, in which case it goes out
+    // as it is.
+    fn frame_at_level(&self, payload: &serde_json::Value, image: &FrameImage, level: usize) -> Option<serde_json::Value> {
+        let step = self.web.quality_steps.get(level - 1)?;
+        match stream_quality::reencode(image, step) {
+            Ok(jpeg_base64) => {
+                let mut payload = payload.clone();
+                payload["image"] = serde_json::Value::String(jpeg_base64);
+                payload["quality"] = serde_json::json!({
+                    "level": level,
+                    "jpeg_quality": step.jpeg_quality,
+                    "scale": step.scale,
+                });
+                Some(payload)
+            }
+            Err(e) => {
+                warn!(target: "web", "Failed to re-encode frame: {:#}", e);
+                None
             }
         }
+    }
 
-        self.clients.write().remove(&client_id);
+    // The latest frame with its detections drawn on, from the same tick.
+    async fn handle_annotated_frame(&self, query: FrameQuery) -> Response {
+        let (format, quality) = match self.get_snapshot_settings(&query) {
+            Ok(settings) => settings,
+            Err(reply) => return reply.into_response(),
+        };
+        let world = self.state.get_world();
+        if world.image.is_empty() {
+            return error_reply(StatusCode::NOT_FOUND, "no frame yet").into_response();
+        }
+        let draw = self.annotator(world, query.masks.unwrap_or(true));
+        match self.encoders.encode(draw, format, quality).await {
+            Ok(bytes) => warp::reply::with_header(bytes, "content-type", snapshot::content_type(format)).into_response(),
+            Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &format!("{:#}", e)).into_response(),
+        }
     }
 
-    fn start_frame_broadcaster(self: Arc<Self>) {
-        tokio::spawn(async move {
+    // multipart/x-mixed-replace, a part for each new frame at up to `fps`.
+    // A frame that comes while every encoder is busy is skipped, not queued,
+    // so a slow client sees a lower rate rather than an older picture.
+    fn handle_frame_stream(self: &Arc<Self>, query: FrameQuery) -> Response {
+        let (format, quality) = match self.get_snapshot_settings(&query) {
+            Ok(settings) => settings,
+            Err(reply) => return reply.into_response(),
+        };
+        let fps = query.fps.unwrap_or(self.config.get().snapshot.stream_fps);
+        if !(0.1..=30.0).contains(&fps) {
+            return error_reply(StatusCode::BAD_REQUEST, "fps must be between 0.1 and 30").into_response();
+        }
+        let masks = query.masks.unwrap_or(true);
+        let mut interval = tokio::time::interval(Duration::from_secs_f32(1.0 / fps));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        let parts = futures_util::stream::unfold((self.clone(), interval, None), move |(server, mut interval, mut last)| async move {
             loop {
-                let frame_data = {
-                    let vision = self.vision.read();
-                    let detections = vision.get_last_detections();
-                    let image_base64 = vision.get_last_frame_base64();
-                    
-                    serde_json::json!({
-                        "image": image_base64,
-                        "detections": detections,
-                        "timestamp": SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis(),
-                        "status": "success",
-                        "detection_count": detections.len(),
-                    })
-                };
-                
-                if let Ok(json) = serde_json::to_string(&frame_data) {
-                    let msg = warp::ws::Message::text(json);
-                    let clients = self.clients.read();
-                    for tx in clients.values() {
-                        let _ = tx.send(msg.clone());
+                interval.tick().await;
+                let world = server.state.get_world();
+                if world.image.is_empty() || last == Some(world.frame_id) {
+                    continue;
+                }
+                let frame_id = world.frame_id;
+                let draw = server.annotator(world, masks);
+                let bytes = match server.encoders.try_encode(draw, format, quality).await {
+                    Some(Ok(bytes)) => bytes,
+                    Some(Err(e)) => {
+                        warn!(target: "web", "Ending a frame stream: {:#}", e);
+                        return None;
+                    }
+                    None => {
+                        METRICS.snapshot_frames_skipped.inc();
+                        continue;
                     }
+                };
+                last = Some(frame_id);
+                let mut part = format!(
+                    "--frame\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+                    snapshot::content_type(format),
+                    bytes.len(),
+                )
+                .into_bytes();
+                part.extend_from_slice(&bytes);
+                part.extend_from_slice(b"\r\n");
+                return Some((Ok::<_, std::convert::Infallible>(part), (server, interval, last)));
+            }
+        });
+
+        let mut response = Response::new(warp::hyper::Body::wrap_stream(parts));
+        let headers = response.headers_mut();
+        headers.insert("content-type", warp::http::HeaderValue::from_static("multipart/x-mixed-replace; boundary=frame"));
+        headers.insert("cache-control", warp::http::HeaderValue::from_static("no-cache"));
+        response
+    }
+
+    // The format and quality a frame request asks for, or the snapshot
+    // section's.
+    fn get_snapshot_settings(&self, query: &FrameQuery) -> Result<(SnapshotFormat, u8), warp::reply::WithStatus<warp::reply::Json>> {
+        let config = self.config.get().snapshot;
+        let format = query.format.unwrap_or(config.format);
+        if format == SnapshotFormat::Webp && !cfg!(feature = "webp") {
+            return Err(error_reply(StatusCode::BAD_REQUEST, "this build has no WebP encoder (the webp feature)"));
+        }
+        let quality = query.quality.unwrap_or(config.quality);
+        if !(1..=100).contains(&quality) {
+            return Err(error_reply(StatusCode::BAD_REQUEST, "quality must be between 1 and 100"));
+        }
+        Ok((format, quality))
+    }
+
+    // Draws `world` with its detections, from the same tick, on an encoder.
+    fn annotator(&self, world: Arc<WorldState>, masks: bool) -> impl FnOnce() -> anyhow::Result<image::RgbImage> + Send + 'static {
+        let display = DisplayPreferences::resolve(
+            &self.display.read(),
+            &self.vision.read().get_labels(),
+            world.detections.iter().map(|d| d.class_name.as_str()),
+        );
+        move || {
+            let detections: Vec<Detection> = world.detections.iter().chain(&world.self_occluded).cloned().collect();
+            annotate::annotate(&world.image, &detections, &display, masks)
+        }
+    }
+
+    fn get_recording_name(&self) -> Option<&str> {
+        self.recorder.as_ref().map(|r| r.get_name())
+    }
+
+    fn handle_blackbox_query(&self, query: BlackboxQuery) -> warp::reply::WithStatus<warp::reply::Json> {
+        let config = self.config.get().blackbox;
+        if !config.enabled {
+            return error_reply(StatusCode::NOT_FOUND, "the black box is not enabled");
+        }
+        let mut times = [None, None];
+        for (time, value) in times.iter_mut().zip([&query.from, &query.to]) {
+            if let Some(value) = value {
+                let Some(parsed) = blackbox::parse_time(value) else {
+                    return error_reply(StatusCode::BAD_REQUEST, "from and to must look like 10m, epoch milliseconds or an RFC 3339 time");
+                };
+                *time = Some(parsed);
+            }
+        }
+        if query.kind.as_deref().is_some_and(|kind| !blackbox::KINDS.contains(&kind)) {
+            return error_reply(
+                StatusCode::BAD_REQUEST,
+                &format!("unknown kind, expected one of {}", blackbox::KINDS.join(", ")),
+            );
+        }
+        let query = blackbox::Query {
+            from_ms: times[0],
+            to_ms: times[1],
+            kind: query.kind,
+            limit: Some(query.limit.min(blackbox::MAX_QUERY_LIMIT)),
+        };
+        match blackbox::query(&config, &query) {
+            Ok(records) => warp::reply::with_status(warp::reply::json(&records), StatusCode::OK),
+            Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &format!("{:#}", e)),
+        }
+    }
+
+    fn handle_session_delete(&self, name: &str) -> warp::reply::WithStatus<warp::reply::Json> {
+        if !session::is_session_name(name) {
+            return error_reply(StatusCode::BAD_REQUEST, "invalid session name");
+        }
+        if self.get_recording_name() == Some(name) {
+            return error_reply(StatusCode::CONFLICT, "session is currently being recorded");
+        }
+        match std::fs::remove_file(self.sessions_dir.join(name)) {
+            Ok(()) => {
+                annotations::remove(&self.sessions_dir.join(name));
+                info!(target: "web", "Deleted session {}", name);
+                warp::reply::with_status(warp::reply::json(&serde_json::json!({ "deleted": name })), StatusCode::OK)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => error_reply(StatusCode::NOT_FOUND, "no such session"),
+            Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+        }
+    }
+
+    // Replayed messages carry `"replayed": true` so clients never mistake
+    // them for live telemetry and act on them.
+    fn start_replay(self: Arc<Self>, query: ReplayQuery) -> warp::reply::WithStatus<warp::reply::Json> {
+        if !(query.speed.is_finite() && query.speed > 0.0 && query.speed <= 20.0) {
+            return error_reply(StatusCode::BAD_REQUEST, "speed must be between 0 and 20");
+        }
+        let entries = match session::open_session(&self.sessions_dir, &query.file) {
+            Ok(entries) => entries,
+            Err(e) => return error_reply(StatusCode::NOT_FOUND, &format!("{:#}", e)),
+        };
+
+        self.stop_replay();
+        let stop = Arc::new(AtomicBool::new(false));
+        let shown = Arc::new(parking_lot::Mutex::new(None));
+        *self.replay.lock() = Some(Replay {
+            session: query.file.clone(),
+            stop: stop.clone(),
+            frame: shown.clone(),
+        });
+
+        let session_name = query.file.clone();
+        let speed = query.speed;
+        let server = self.clone();
+        std::thread::spawn(move || {
+            server.send_replay_status(true, &session_name);
+            let mut last_t = None;
+            for entry in entries {
+                if let Some(last) = last_t {
+                    let gap = entry.t_ms.saturating_sub(last) as f32 / speed;
+                    std::thread::sleep(Duration::from_millis(gap as u64));
+                }
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                last_t = Some(entry.t_ms);
+                if let Some(frame) = RecordedFrame::from_entry(&entry) {
+                    *shown.lock() = Some(frame);
                 }
-                
-                tokio::time::sleep(Duration::from_millis(33)).await;
+
+                let mut payload = entry.payload;
+                if let Some(obj) = payload.as_object_mut() {
+                    obj.insert("replayed".to_string(), serde_json::Value::Bool(true));
+                    obj.insert("replay".to_string(), serde_json::json!({
+                        "session": session_name,
+                        "t_ms": entry.t_ms,
+                    }));
+                }
+                let frames_only = payload.get("type").and_then(|t| t.as_str()) == Some("frame");
+                server.send_to_clients(&payload, frames_only);
+            }
+
+            // Only clear our own slot; a newer replay may have replaced it.
+            let mut replay = server.replay.lock();
+            if replay.as_ref().is_some_and(|r| Arc::ptr_eq(&r.stop, &stop)) {
+                *replay = None;
             }
+            drop(replay);
+            server.send_replay_status(false, &session_name);
+            info!(target: "web", "Replay of {} finished", session_name);
         });
+
+        info!(target: "web", "Replaying {} at {}x", query.file, query.speed);
+        warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({
+                "replaying": query.file,
+                "speed": query.speed,
+            })),
+            StatusCode::OK,
+        )
     }
 
-    async fn create_frame_data(&self) -> FrameData {
-        let (image_base64, detections, telemetry, nav_action) = {
-            let vision = self.vision.read();
-            (
-                vision.get_last_frame_base64(),
-                vision.get_last_detections(),
-                vision.get_telemetry(),
-                vision.get_navigation_command()
-            )
+    // About the frame being shown: the replayed one while a replay runs,
+    // else the one the last tick consumed. It is kept with the recording
+    // that frame is in, which is the one being replayed, fed back through
+    // vision or, for a live frame, being recorded; with none it only goes
+    // to the black box.
+    fn annotate(&self, label: String, note: Option<String>, source: String) -> Result<Annotation, (StatusCode, String)> {
+        annotations::check_label(&label, note.as_deref()).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let replayed = self.replay.lock().as_ref().map(|r| (r.session.clone(), r.frame.lock().clone()));
+        let world = self.state.get_world();
+        let (recording, annotation) = match (replayed, &world.recorded) {
+            (Some((session, frame)), _) => {
+                let frame = frame.ok_or((StatusCode::CONFLICT, "the replay hasn't shown a frame yet".to_string()))?;
+                (Some(self.sessions_dir.join(&session)), Annotation {
+                    at_ms: CLOCK.wall_ms(),
+                    label,
+                    note,
+                    frame_id: frame.frame_id,
+                    tick: frame.tick,
+                    detections: frame.detections,
+                    session: Some(session),
+                    t_ms: Some(frame.t_ms),
+                    replayed: true,
+                    source,
+                })
+            }
+            (None, Some(recorded)) => (Some(recorded.path.clone()), Annotation {
+                at_ms: CLOCK.wall_ms(),
+                label,
+                note,
+                frame_id: recorded.frame_id,
+                tick: recorded.tick,
+                detections: world.detections.clone(),
+                session: recorded.path.file_name().map(|n| n.to_string_lossy().to_string()),
+                t_ms: Some(recorded.t_ms),
+                replayed: true,
+                source,
+            }),
+            (None, None) => (self.get_recording_name().map(|name| self.sessions_dir.join(name)), Annotation {
+                at_ms: CLOCK.wall_ms(),
+                label,
+                note,
+                frame_id: world.frame_id,
+                tick: Some(world.tick),
+                detections: world.detections.clone(),
+                session: self.get_recording_name().map(str::to_string),
+                t_ms: self.recorder.as_ref().map(SessionRecorder::get_elapsed_ms),
+                replayed: false,
+                source,
+            }),
         };
+        if let Some(recording) = &recording {
+            annotations::append(recording, &annotation).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))?;
+        }
+
+        let payload = serde_json::to_value(&annotation).unwrap_or_default();
+        BLACKBOX.record(RecordKind::Annotation, payload.clone());
+        info!(target: "web", "Annotated frame {:?} as {} ({})", annotation.frame_id, annotation.label, annotation.session.as_deref().unwrap_or("no recording"));
+        // Not recorded: it is already with the recording it belongs to.
+        self.send_to_clients(&serde_json::json!({ "type": "annotation", "annotation": payload }), false);
+        Ok(annotation)
+    }
+
+    fn stop_replay(&self) -> bool {
+        match self.replay.lock().take() {
+            Some(replay) => {
+                replay.stop.store(true, Ordering::Relaxed);
+                info!(target: "web", "Stopping replay of {}", replay.session);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn send_replay_status(&self, active: bool, session: &str) {
+        self.send_to_clients(&serde_json::json!({
+            "type": "replay_status",
+            "active": active,
+            "session": session,
+        }), false);
+    }
+
+    fn get_client_stats(&self) -> Vec<ClientStats> {
+        let mut stats: Vec<ClientStats> = self.clients.read().iter()
+            .map(|(id, client)| {
+                let link = client.link.lock();
+                let forwarded = client.forwarded_for.as_deref()
+                    .and_then(|v| v.split(',').next())
+                    .map(|v| v.trim().to_string());
+                ClientStats {
+                    id: *id,
+                    addr: forwarded.or_else(|| client.peer.map(|p| p.to_string())),
+                    peer: client.peer.map(|p| p.to_string()),
+                    frames: client.frames,
+                    masks: client.masks,
+                    connected_s: client.connected_at.elapsed().as_secs_f32(),
+                    messages_sent: client.messages_sent.load(Ordering::Relaxed),
+                    backlog: client.queued.load(Ordering::Relaxed),
+                    rtt_ms: link.get_rtt().map(|rtt| rtt.as_secs_f32() * 1000.0),
+                    quality_mode: link.get_mode(),
+                    quality_level: link.get_level(),
+                }
+            })
+            .collect();
+        stats.sort_by_key(|c| c.id);
+        stats
+    }
+
+    // Events go to every client, including ones that unsubscribed from frames.
+    async fn run_event_forwarder(self: Arc<Self>) {
+        let mut events = EVENTS.subscribe();
+        loop {
+            match events.recv().await {
+                Ok(event) => self.broadcast(&serde_json::json!({
+                    "type": "event",
+                    "event": event,
+                })),
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+
+    // Log lines go only to clients tailing them, straight out rather than
+    // through broadcast(), so they are never recorded into sessions. A
+    // client with too much still queued misses them.
+    async fn run_log_forwarder(self: Arc<Self>) {
+        let mut logs = LOGS.subscribe();
+        loop {
+            let record = match logs.recv().await {
+                Ok(record) => record,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(missed)) => {
+                    METRICS.logs_dropped.add(missed);
+                    continue;
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            };
+            let clients = self.clients.read();
+            let mut message = None;
+            for client in clients.values().filter(|c| c.logs.is_some_and(|level| record.level >= level)) {
+                if client.queued.load(Ordering::Relaxed) >= LOG_TAIL_MAX_BACKLOG {
+                    METRICS.logs_dropped.inc();
+                    continue;
+                }
+                client.send(message.get_or_insert_with(|| warp::ws::Message::text(serde_json::json!({
+                    "type": "log",
+                    "rover_id": self.identity.rover_id,
+                    "log": record,
+                }).to_string())));
+            }
+        }
+    }
+
+    // Map updates go out at 2Hz and only carry tiles changed since the
+    // previous update. Clients that fall behind refetch /api/map.
+    async fn run_map_broadcaster(self: Arc<Self>) {
+        let mut sent_version = 0u64;
+        loop {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+
+            let payload = {
+                let planner = self.path_planner.read();
+                let diff = planner.get_map_diff(sent_version);
+                sent_version = planner.get_map_version();
+                serde_json::json!({
+                    "type": "map",
+                    "grid": diff,
+                    "state": planner.get_map_state(),
+                })
+            };
+
+            self.broadcast_frame(&payload);
+        }
+    }
+
+    async fn run_frame_broadcaster(self: Arc<Self>) {
+        loop {
+            simulation::chaos_point("frame_broadcaster");
+            // Image, detections and state all come from one tick; the
+            // rest has no frame to match.
+            let world = self.state.get_world();
+            let degraded = world.thermal.degraded;
+            let frame_data = {
+                let detections = &world.detections;
+                let vision = self.vision.read();
+
+                serde_json::json!({
+                    "type": "frame",
+                    "tick": world.tick,
+                    "frame_id": world.frame_id,
+                    "detections": detections,
+                    "self_occluded": world.self_occluded,
+                    "below_threshold": world.below_threshold,
+                    "sweep": Some(vision.get_sweep_status()).filter(|sweep| sweep.active),
+                    "fiducials": vision.get_last_fiducials(),
+                    "free_space": vision.get_last_free_space(),
+                    "gps": self.path_planner.read().get_gps_status(),
+                    "ranges": self.path_planner.read().get_ranges(),
+                    "follow": self.path_planner.read().get_follow_status(),
+                    "drop_off": self.path_planner.read().get_drop_off_status(),
+                    "hazards": world.hazards,
+                    "trajectory": (!degraded).then(|| self.path_planner.read().get_trajectory(&vision.get_camera())),
+                    "display": DisplayPreferences::resolve(
+                        &self.display.read(),
+                        &vision.get_labels(),
+                        detections.iter().map(|d| d.class_name.as_str()),
+                    ),
+                    "timestamp": CLOCK.wall_ms(),
+                    "status": "success",
+                    "detection_count": detections.len(),
+                    "state": world.state,
+                    "thermal": world.thermal,
+                    "power": world.power,
+                    "vision_profile": world.telemetry.profile,
+                    "ground_calibration": world.telemetry.ground_calibration,
+                    "latency": world.latency,
+                })
+            };
+
+            self.broadcast_picture(&frame_data, &world.image);
+            self.telemetry.record(TelemetrySample::from_metrics());
+
+            let interval = if POWER.is_asleep() {
+                self.config.get().power.asleep_frame_interval_ms
+            } else if degraded {
+                self.config.get().thermal.degraded_frame_interval_ms
+            } else {
+                33
+            };
+            tokio::time::sleep(Duration::from_millis(interval)).await;
+        }
+    }
+
+    #[allow(dead_code)]
+    async fn create_frame_data(&self) -> FrameData {
+        let world = self.state.get_world();
+        let (image_base64, detections, telemetry, nav_action) = (
+            world.image.get_jpeg_base64().map(|jpeg| jpeg.to_string()).unwrap_or_default(),
+            world.detections.clone(),
+            world.telemetry.clone(),
+            world.nav_action,
+        );
 
         let path_status = if detections.is_empty() {
             PathStatus {
@@ -172,7 +2467,6 @@ impl WebServer {
                 obstacles: 0,
             }
         } else {
-            let closest = &detections[0];
             let (status, color) = match nav_action {
                 NavigationAction::EmergencyStop => ("BLOCKED", "red"),
                 NavigationAction::Stop => ("BLOCKED", "red"),
@@ -195,8 +2489,8 @@ impl WebServer {
                 NavigationAction::SlowDown => 0.3,
                 NavigationAction::Continue => 0.5,
             },
-            heading: self.motor_controller.read().get_heading(),
-            distance_to_goal: self.path_planner.read().get_distance_to_goal(),
+            heading: world.motors.heading,
+            distance_to_goal: world.plan.distance_to_goal,
         };
 
         FrameData {
@@ -205,9 +2499,338 @@ impl WebServer {
             telemetry,
             path_status,
             navigation,
-            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64,
+            timestamp: CLOCK.wall_ms(),
+        }
+    }
+}
+
+
+fn identity_payload(identity: &IdentityConfig) -> serde_json::Value {
+    serde_json::json!({
+        "rover_id": identity.rover_id,
+        "rover_name": identity.rover_name,
+        "api_version": API_VERSION,
+        "version": env!("CARGO_PKG_VERSION"),
+        "service_type": discovery::SERVICE_TYPE,
+        "capabilities": capabilities::report(),
+    })
+}
+
+fn api_index() -> serde_json::Value {
+    let endpoints = [
+        ("GET", "/api/identity", "Rover id, name, API version and what the build has (capabilities)"),
+        ("GET", "/api", "This index"),
+        ("GET", "/healthz", "Liveness with per-component detail"),
+        ("GET", "/readyz", "Readiness"),
+        ("GET", "/metrics", "Prometheus metrics"),
+        ("GET", "/api/config", "Current runtime config"),
+        ("PATCH", "/api/config", "Merge-patch the runtime config (?persist=true to save)"),
+        ("GET", "/api/events", "Recent events"),
+        ("GET", "/api/clients", "Connected WebSocket clients"),
+        ("GET", "/api/telemetry/history", "Downsampled min/max/avg history (?metric=fps|loop_time_ms|obstacle_count|speed|latency_ms|cpu_temp_c|pose_sigma_m|wheel_slip&window=1h)"),
+        ("GET", "/api/selftest", "Startup self-test: each check's pass/warn/fail and detail, and whether it blocks arming"),
+        ("GET", "/api/blackbox", "Flight recorder records (?from=10m|<epoch ms>|<RFC 3339>&to=...&kind=decision|command|event|telemetry|annotation&limit=1000)"),
+        ("GET", "/api/state", "Rover state machine state, time in state and last transition reason"),
+        ("GET", "/api/status", "The state machine's status and each subsystem started behind the web server: initializing, ready or failed with its error"),
+        ("POST", "/api/subsystems/{name}/retry", "Start a subsystem that failed to start (vision or motors) again; the fault still needs acknowledging once it is ready"),
+        ("GET", "/api/resume", "The mission a restart interrupted, picked up from the checkpoint and waiting to be resumed; 404 when there is none"),
+        ("POST", "/api/resume", "Resume the interrupted mission: puts its goal or docking back and arms"),
+        ("DELETE", "/api/resume", "Decline the interrupted mission; the restored pose and map stay"),
+        ("GET", "/api/world", "Detections, decision, plan, pose, motors and state from the last control tick"),
+        ("GET", "/api/labels", "The active vision backend's label map by class index: name, height_m, rule and color"),
+        ("GET", "/api/rules", "Detection rules from vision.rules_path, in evaluation order"),
+        ("POST", "/api/rules/reload", "Re-read vision.rules_path; on errors the current rules stay and the errors are returned"),
+        ("GET", "/api/vision/profiles", "Configured vision profiles and the active one, with the last brightness sample"),
+        ("POST", "/api/vision/profile", "Switch vision profile ({\"profile\": name, or null for the base settings}); pauses automatic switching"),
+        ("POST", "/api/vision/profile/auto", "Resume automatic vision profile switching"),
+        ("GET", "/api/vision/self_mask", "The self-occlusion mask for an editor: polygons in frame pixels, frame size, the frame to draw on and what the mask took out of it"),
+        ("PUT", "/api/vision/self_mask", "Replace the self-occlusion mask ({\"polygons\": [[[x, y], ...], ...], \"min_overlap\": 0-1}); applies immediately and persists"),
+        ("GET", "/api/vision/self_mask/proposal", "Boxes that stayed put in at least ?min_frames= frames (default 200), as polygons to mask"),
+        ("DELETE", "/api/vision/self_mask/proposal", "Start watching for a self-mask proposal afresh"),
+        ("GET", "/api/vision/sweep", "The threshold sweep and, at each candidate threshold, how many detections and slow or stop decisions there would have been over the last ?minutes= (?thresholds=0.2,0.3 to choose them)"),
+        ("POST", "/api/vision/sweep", "Keep detections down to a floor under min_confidence for a while, marked below_threshold: {\"duration_s\": 300, \"floor\": 0.1}, both optional"),
+        ("DELETE", "/api/vision/sweep", "End the threshold sweep early"),
+        ("GET", "/api/vision/heatmap", "Where in the frame detections have turned up, faded by vision.heatmap.half_life_s: a rows x columns grid with min and max; ?class= for one class"),
+        ("DELETE", "/api/vision/heatmap", "Start the detection heatmap afresh"),
+        ("GET", "/api/vision/ground_calibration", "The ground calibration with how far off each of its points it is, the frame size and the frame to click points on"),
+        ("POST", "/api/vision/ground_calibration", "Fit a new ground calibration to at least 4 points ({\"points\": [{\"px\", \"py\", \"x_m\", \"y_m\"}, ...]}, metres right of and ahead of the lens); applies immediately and persists"),
+        ("GET", "/api/vision/ground", "Floor position of a frame pixel (?px=&py=) by the ground calibration; null above the horizon"),
+        ("GET", "/api/bridge/diagnostics", "Bridge line diagnostics: lines/s, parse failure ratio, largest line, last error with an excerpt, and where the lines are written"),
+        ("POST", "/api/bridge/diagnostics", "Tee the bridge's raw lines to vision.bridge_tap_dir ({\"level\": failures|outcomes|full, or null to stop, \"minutes\": how long, default vision.bridge_tap_minutes})"),
+        ("GET", "/api/logs", "Recent log lines from memory, oldest first (?level=warn, ?since=10m|epoch ms|RFC 3339, ?after=<id>, ?target=planner, ?limit=500); tail them over /ws with subscribe_logs"),
+        ("GET", "/api/power", "Idle power state, video clients and how the last wake went"),
+        ("POST", "/api/power/wake", "Wake the rover from idle sleep"),
+        ("GET", "/api/assets", "Uploaded bridge scripts, model configs and label files: each kind's versions, and the active and previous one"),
+        ("POST", "/api/hazard", "Report an external hazard: {\"source\", \"type\", \"action\": caution|stop, \"bearing_deg\" (optional, anticlockwise from ahead), \"ttl_s\"} (bearer token; rate limited per source)"),
+        ("GET", "/api/hazards", "External hazards still active, with their effect and seconds left"),
+        ("POST", "/api/assets/upload", "Stage a new version (?kind=bridge_script|model_config|labels&filename=<name>&sha256=<hex>, file as the body; bearer token)"),
+        ("POST", "/api/assets/{kind}/activate", "Make {\"version\"} active and restart the bridge on it, only while idle (bearer token)"),
+        ("POST", "/api/assets/{kind}/rollback", "Go back to the previous version, or the built-in asset, and restart the bridge, only while idle (bearer token)"),
+        ("GET", "/api/display", "Overlay preferences: color and visibility per class, label_detail (minimal|standard|full) and units (metric|imperial)"),
+        ("PATCH", "/api/display", "Merge-patch the display preferences (saved to the config file; a class set to null is reset)"),
+        ("GET", "/api/motors/audit", "Recent motor commands with the capture time and latency of the frame behind each"),
+        ("GET", "/api/schedule", "Scheduled actions with when each last fired"),
+        ("PUT", "/api/schedule", "Replace the scheduled actions (saved to the config file)"),
+        ("GET", "/api/zones", "Zones with their policy and who is inside each"),
+        ("PUT", "/api/zones", "Replace the zones (saved to the config file)"),
+        ("PUT", "/api/zones/{name}", "Add or replace one zone: {\"polygon\": [[x, y], ...], \"policy\": none|slow|stop|forbid_entry}"),
+        ("DELETE", "/api/zones/{name}", "Remove a zone"),
+        ("GET", "/api/gps", "GPS fix, whether it is being fused, and the rover's lat/lon"),
+        ("GET", "/api/localization", "Position covariance and the last landmark fix, applied or rejected"),
+        ("PUT", "/api/goal", "Set the goal as {\"x\", \"y\"} in map metres or {\"lat\", \"lon\"} once GPS has placed the map"),
+        ("DELETE", "/api/goal", "Clear the goal"),
+        ("GET", "/api/map", "Full occupancy grid and map state (?since=<version> for changed tiles only)"),
+        ("GET", "/api/frame.jpg", "Latest frame with its detections drawn on (?masks=false to leave masks out, ?format=baseline|progressive|webp, ?quality=1-100)"),
+        ("GET", "/api/stream.mjpeg", "The same as a multipart stream of new frames (?fps= as well)"),
+        ("GET", "/api/sessions", "Recorded sessions"),
+        ("DELETE", "/api/sessions/{name}", "Delete a recorded session"),
+        ("GET", "/api/replay/start", "Replay a session (?file=<name>&speed=<factor>)"),
+        ("GET", "/api/replay/stop", "Stop the running replay"),
+        ("POST", "/api/annotate", "Mark the frame being shown, the replayed one during a replay: {\"label\": e.g. false_positive|missed_obstacle, \"note\"}"),
+        ("GET", "/api/annotations", "A recording's annotations (?session=<name>; default the one being replayed, else the one being recorded)"),
+        ("GET", "/ws", "WebSocket telemetry stream; accepts {\"cmd\": subscribe, emergency_stop, set_mode (mode: autonomous|manual|idle), acknowledge_fault, set_shadow (enabled, only while idle), dock, follow (track_id, or null to stop), override_selftest, rerun_selftest (only while idle), annotate (label, note), subscribe_logs (level), unsubscribe_logs, axis (seq, deadman, linear and angular from -1 to 1; manual only, acknowledged with a teleop message) or hello (quality: auto|low|full, masks, detection_deltas)}"),
+        ("GET", "/rovers/{rover_id}/...", "Any of the above, namespaced by rover id"),
+    ];
+
+    let tile = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "tx": {"type": "integer", "description": "Tile column"},
+            "ty": {"type": "integer", "description": "Tile row"},
+            "data": {"type": "string", "description": "rle8 cells of the tile, row-major, clipped at the grid edge"}
+        }
+    });
+    let point = serde_json::json!({
+        "type": "object",
+        "properties": {"x": {"type": "number"}, "y": {"type": "number"}}
+    });
+    let state = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "pose": {
+                "type": "object",
+                "properties": {
+                    "x": {"type": "number", "description": "Metres right of the start position"},
+                    "y": {"type": "number", "description": "Metres ahead of the start position"},
+                    "heading_deg": {"type": "number", "description": "Clockwise from the start heading"}
+                }
+            },
+            "goal": {"oneOf": [point, {"type": "null"}]},
+            "path": {"type": "array", "items": point},
+            "obstacles": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "class_name": {"type": "string"},
+                        "x": {"type": "number"},
+                        "y": {"type": "number"},
+                        "age_s": {"type": "number"}
+                    }
+                }
+            },
+            "zones": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": "string"},
+                        "policy": {"enum": ["none", "slow", "stop", "forbid_entry"]},
+                        "polygon": {"type": "array", "items": {"type": "array", "items": {"type": "number"}}},
+                        "occupants": {"type": "array", "items": {"type": "string"}, "description": "\"rover\" or \"<class>#<track id>\""}
+                    }
+                }
+            }
+        }
+    });
+
+    serde_json::json!({
+        "rover_id_header": "x-rover-id",
+        "endpoints": endpoints.iter()
+            .map(|(method, path, description)| serde_json::json!({
+                "method": method,
+                "path": path,
+                "description": description,
+            }))
+            .collect::<Vec<_>>(),
+        "encodings": {
+            "rle8": "base64 of (value, count) byte pairs; values 0 = unknown, 1 = free, 2 = occupied; cell (col, row) is at index row * width + col, row 0 is the most negative y"
+        },
+        "schemas": {
+            "map_snapshot": {
+                "type": "object",
+                "properties": {
+                    "grid": {
+                        "type": "object",
+                        "properties": {
+                            "width": {"type": "integer"},
+                            "height": {"type": "integer"},
+                            "resolution_m": {"type": "number"},
+                            "tile_size": {"type": "integer"},
+                            "version": {"type": "integer"},
+                            "encoding": {"const": "rle8"},
+                            "data": {"type": "string"}
+                        }
+                    },
+                    "state": state
+                }
+            },
+            "map_update": {
+                "description": "WebSocket message with type \"map\", sent at 2Hz. Apply tiles when the version you hold is >= base_version and width/height match, otherwise refetch /api/map.",
+                "type": "object",
+                "properties": {
+                    "type": {"const": "map"},
+                    "grid": {
+                        "type": "object",
+                        "properties": {
+                            "width": {"type": "integer"},
+                            "height": {"type": "integer"},
+                            "base_version": {"type": "integer"},
+                            "version": {"type": "integer"},
+                            "tiles": {"type": "array", "items": tile}
+                        }
+                    },
+                    "state": state
+                }
+            }
+        }
+    })
+}
+
+fn has_masks(payload: &serde_json::Value) -> bool {
+    payload.get("detections")
+        .and_then(|d| d.as_array())
+        .is_some_and(|d| d.iter().any(|d| d.get("mask").is_some()))
+}
+
+// The payload without its detections' masks, or None if it has none.
+fn strip_masks(payload: &serde_json::Value) -> Option<serde_json::Value> {
+    if !has_masks(payload) {
+        return None;
+    }
+    let mut bare = payload.clone();
+    for detection in bare["detections"].as_array_mut().into_iter().flatten() {
+        if let Some(detection) = detection.as_object_mut() {
+            detection.remove("mask");
+        }
+    }
+    Some(bare)
+}
+
+// "0.2,0.35" to thresholds; the sweep checks they are in range.
+fn parse_thresholds(list: &str) -> Result<Vec<f32>, String> {
+    list.split(',')
+        .map(|t| t.trim().parse::<f32>().map_err(|_| format!("{:?} is not a threshold", t)))
+        .collect()
+}
+
+fn error_reply(status: StatusCode, message: &str) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&serde_json::json!({ "error": message })), status)
+}
+
+fn asset_error_reply(e: &AssetError) -> warp::reply::WithStatus<warp::reply::Json> {
+    let status = match e {
+        AssetError::Disabled => StatusCode::SERVICE_UNAVAILABLE,
+        AssetError::Invalid(_) => StatusCode::BAD_REQUEST,
+        AssetError::NotFound(_) => StatusCode::NOT_FOUND,
+        AssetError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    error_reply(status, &e.to_string())
+}
+
+fn asset_change_payload(kind: ArtifactKind, manifest: &ArtifactManifest, bridge_restarted: bool) -> serde_json::Value {
+    serde_json::json!({
+        "kind": kind,
+        "active": manifest.active,
+        "previous": manifest.previous,
+        "bridge_restarted": bridge_restarted,
+    })
+}
+
+// `kind` names the token in replies and logs, `setting` the config field
+// naming its environment variable.
+fn check_bearer(token: Option<&str>, authorization: Option<&str>, kind: &str, setting: &str) -> Result<(), warp::reply::WithStatus<warp::reply::Json>> {
+    let Some(token) = token else {
+        return Err(error_reply(StatusCode::SERVICE_UNAVAILABLE, &format!("no {} token is set ({})", kind, setting)));
+    };
+    let presented = authorization.and_then(|value| value.strip_prefix("Bearer ")).unwrap_or_default();
+    if !constant_time_eq(presented.as_bytes(), token.as_bytes()) {
+        warn!(target: "web", "Rejected a {} request with a missing or wrong token", kind);
+        return Err(error_reply(StatusCode::UNAUTHORIZED, "missing or wrong bearer token"));
+    }
+    Ok(())
+}
+
+// Takes as long for a wrong token as for a right one of the same length.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn static_routes(static_dir: Option<&str>) -> BoxedFilter<(Response,)> {
+    let embedded = warp::get()
+        .and(warp::path::tail())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and_then(|tail: warp::path::Tail, if_none_match: Option<String>| async move {
+            assets::serve(tail.as_str(), if_none_match.as_deref()).ok_or_else(warp::reject::not_found)
+        })
+        .boxed();
+
+    match static_dir {
+        Some(dir) => {
+            info!(target: "web", "Serving dashboard from {} (embedded copy as fallback)", dir);
+            warp::fs::dir(dir.to_string())
+                .map(Reply::into_response)
+                .or(embedded)
+                .unify()
+                .boxed()
         }
+        None => embedded,
     }
 }
 
-use serde_json::json;
+fn base_path_filter(base_path: &str) -> BoxedFilter<()> {
+    base_path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .fold(warp::any().boxed(), |filter, segment| {
+            filter.and(warp::path(segment.to_string())).boxed()
+        })
+}
+
+// Hand-rolled instead of warp::cors(): warp rejects any request whose Origin
+// is not listed, and browsers send Origin on same-origin PATCH/POST too, which
+// would lock the rover's own dashboard out as soon as a list is configured.
+fn with_cors(routes: BoxedFilter<(Response,)>, allowed: Arc<Vec<String>>) -> BoxedFilter<(Response,)> {
+    let preflight = warp::options()
+        .and(warp::header::optional::<String>("origin"))
+        .map({
+            let allowed = allowed.clone();
+            move |origin: Option<String>| {
+                let mut response = warp::reply::with_status(warp::reply(), StatusCode::NO_CONTENT).into_response();
+                add_cors_headers(&mut response, origin.as_deref(), &allowed);
+                response
+            }
+        });
+
+    let actual = routes
+        .and(warp::header::optional::<String>("origin"))
+        .map(move |mut response: Response, origin: Option<String>| {
+            add_cors_headers(&mut response, origin.as_deref(), &allowed);
+            response
+        });
+
+    preflight.or(actual).unify().boxed()
+}
+
+fn add_cors_headers(response: &mut Response, origin: Option<&str>, allowed: &[String]) {
+    let Some(origin) = origin else { return };
+    if !allowed.iter().any(|o| o == "*" || o == origin) {
+        return;
+    }
+    let headers = response.headers_mut();
+    if let Ok(value) = warp::http::HeaderValue::from_str(origin) {
+        headers.insert("access-control-allow-origin", value);
+    }
+    headers.insert("vary", warp::http::HeaderValue::from_static("Origin"));
+    headers.insert("access-control-allow-methods", warp::http::HeaderValue::from_static("GET, POST, PATCH, DELETE, OPTIONS"));
+    headers.insert("access-control-allow-headers", warp::http::HeaderValue::from_static("Content-Type, Authorization"));
+}
@@ -0,0 +1,142 @@
+// src/world.rs
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+use crate::annotations::RecordingRef;
+use crate::clock::CLOCK;
+use crate::deliberation::Plan;
+use crate::hazards::ActiveHazard;
+use crate::latency::LatencyBreakdown;
+use crate::localization::LocalizationStatus;
+use crate::mapping::RoverPose;
+use crate::motor_control::{MotorController, MotorStatus};
+use crate::pathfinding::PathStatus;
+use crate::power::{PowerStatus, POWER};
+use crate::range::RangeReading;
+use crate::slip::{TractionStatus, VisualTravel};
+use crate::state_machine::StateStatus;
+use crate::thermal::{ThermalStatus, THERMAL};
+use crate::vision::{Detection, FiducialDetection, FrameImage, FreeSpace, NavigationAction, VisionSystem, VisionTelemetry};
+
+// Always holds the latest snapshot; readers never wait on the control loop.
+pub type WorldWatch = watch::Receiver<Arc<WorldState>>;
+
+// What the rover saw and did on one control tick, taken together at the
+// end of the tick. Everything that reports on the rover reads this rather
+// than the subsystems, so detections, decision and motor state always
+// come from the same tick.
+#[derive(Debug, Clone, Serialize)]
+pub struct WorldState {
+    pub tick: u64,
+    // Rover-clock ms at the end of the tick.
+    pub at_ms: u64,
+    // Capture time of the frame the tick consumed; None if none arrived.
+    pub capture_ms: Option<u64>,
+    // The bridge's id for the frame the detections came from, and where it
+    // was in its recording when replayed from one.
+    pub frame_id: Option<u32>,
+    pub recorded: Option<RecordingRef>,
+    pub detections: Vec<Detection>,
+    // Detections vision.self_mask kept out of the above.
+    pub self_occluded: Vec<Detection>,
+    // Detections under vision.min_confidence, while a threshold sweep runs.
+    pub below_threshold: Vec<Detection>,
+    pub nav_action: NavigationAction,
+    // The tick the planner last planned from, behind `tick` while a
+    // planning step is under way.
+    pub plan_tick: u64,
+    pub plan: PathStatus,
+    pub pose: RoverPose,
+    pub localization: LocalizationStatus,
+    // Wheel slip, with the last interval it was measured over.
+    pub traction: TractionStatus,
+    pub motors: MotorStatus,
+    pub state: StateStatus,
+    pub telemetry: VisionTelemetry,
+    pub thermal: ThermalStatus,
+    pub power: PowerStatus,
+    // External hazards still active, as weighed on the tick.
+    pub hazards: Vec<ActiveHazard>,
+    // How long the consumed frame took to reach the motors, stage by
+    // stage; set by the control loop once the motors have their command.
+    pub latency: Option<LatencyBreakdown>,
+    // The frame the detections were found on, as the bridge sent it. Left
+    // out of the JSON; it goes out on its own in frame messages.
+    #[serde(skip)]
+    pub image: Arc<FrameImage>,
+    // What the planner takes from the tick besides the detections; left
+    // out of the JSON like the image.
+    #[serde(skip)]
+    pub findings: Arc<FrameFindings>,
+    #[serde(skip)]
+    pub ranges: Vec<RangeReading>,
+}
+
+// What the last new frame had for the planner besides its detections.
+// Every world state carries the latest, so a planner that takes several
+// ticks over a step still gets the frame that came in meanwhile.
+#[derive(Debug, Clone, Default)]
+pub struct FrameFindings {
+    // The tick the frame arrived on; 0 before the first.
+    pub tick: u64,
+    pub fiducials: Vec<FiducialDetection>,
+    pub free_space: Option<FreeSpace>,
+    pub travel: Option<VisualTravel>,
+}
+
+impl WorldState {
+    // Each subsystem is locked in turn rather than all at once, so this
+    // never holds one lock while waiting for another. The planner's side
+    // comes from its last plan rather than its lock, which a planning step
+    // may be holding.
+    pub fn capture(
+        tick: u64,
+        detections: Vec<Detection>,
+        nav_action: NavigationAction,
+        vision: &RwLock<VisionSystem>,
+        plan: &Plan,
+        motors: &RwLock<MotorController>,
+        state: StateStatus,
+    ) -> Self {
+        let (capture_ms, frame_id, recorded, telemetry, image, self_occluded, below_threshold) = {
+            let vision = vision.read();
+            (
+                vision.get_frame_capture_ms(),
+                vision.get_last_frame_id(),
+                vision.get_last_recorded(),
+                vision.get_telemetry(),
+                vision.get_last_frame_image(),
+                vision.get_last_self_occluded(),
+                vision.get_last_below_threshold(),
+            )
+        };
+        Self {
+            tick,
+            at_ms: CLOCK.now_ms(),
+            capture_ms,
+            frame_id,
+            recorded,
+            detections,
+            self_occluded,
+            below_threshold,
+            nav_action,
+            plan_tick: plan.tick,
+            plan: plan.status.clone(),
+            pose: plan.pose,
+            localization: plan.localization.clone(),
+            traction: plan.traction.clone(),
+            motors: motors.read().get_status(),
+            state,
+            telemetry,
+            thermal: THERMAL.get_status(),
+            power: POWER.get_status(),
+            hazards: Vec::new(),
+            latency: None,
+            image,
+            findings: Arc::default(),
+            ranges: Vec::new(),
+        }
+    }
+}
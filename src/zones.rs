@@ -0,0 +1,166 @@
+// src/zones.rs
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::config::{Zone, ZonePolicy};
+use crate::events::{Severity, EVENTS};
+
+// A track not seen for this long is taken to have left every zone.
+const TRACK_TTL: Duration = Duration::from_secs(2);
+
+// Even-odd rule. Points on an edge may land either side, and a polygon
+// whose corners all lie on one line contains nothing.
+pub fn contains(polygon: &[(f32, f32)], x: f32, y: f32) -> bool {
+    if polygon.len() < 3 || is_flat(polygon) {
+        return false;
+    }
+    let mut inside = false;
+    let mut previous = polygon[polygon.len() - 1];
+    for &(xi, yi) in polygon {
+        let (xj, yj) = previous;
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        previous = (xi, yi);
+    }
+    inside
+}
+
+// Not by the area, which is zero for a bow tie whose two lobes wind
+// opposite ways.
+fn is_flat(polygon: &[(f32, f32)]) -> bool {
+    let (x0, y0) = polygon[0];
+    let Some(&(x1, y1)) = polygon.iter().find(|&&(x, y)| (x - x0).abs() > f32::EPSILON || (y - y0).abs() > f32::EPSILON) else {
+        return true;
+    };
+    polygon.iter().all(|&(x, y)| ((x1 - x0) * (y - y0) - (y1 - y0) * (x - x0)).abs() <= f32::EPSILON)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Occupant {
+    Rover,
+    Track(u32),
+}
+
+struct Presence {
+    class_name: String,
+    zones: BTreeSet<String>,
+    seen: Instant,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ZoneStatus {
+    pub name: String,
+    pub policy: ZonePolicy,
+    pub direction_deg: Option<f32>,
+    pub polygon: Vec<(f32, f32)>,
+    // "rover", or "<class>#<track id>" for tracked detections.
+    pub occupants: Vec<String>,
+}
+
+// Which zones the rover and each tracked detection are in. Every change
+// is published as a "zones" event.
+#[derive(Default)]
+pub struct ZoneTracker {
+    presence: HashMap<Occupant, Presence>,
+}
+
+impl ZoneTracker {
+    pub fn update(&mut self, zones: &[Zone], occupant: Occupant, class_name: &str, x: f32, y: f32) {
+        let now_in: BTreeSet<String> = zones.iter()
+            .filter(|z| contains(&z.polygon, x, y))
+            .map(|z| z.name.clone())
+            .collect();
+        let presence = self.presence.entry(occupant).or_insert_with(|| Presence {
+            class_name: class_name.to_string(),
+            zones: BTreeSet::new(),
+            seen: Instant::now(),
+        });
+        presence.seen = Instant::now();
+        for name in now_in.difference(&presence.zones) {
+            publish(zones, name, occupant, &presence.class_name, true, Some((x, y)));
+        }
+        for name in presence.zones.difference(&now_in) {
+            publish(zones, name, occupant, &presence.class_name, false, Some((x, y)));
+        }
+        presence.zones = now_in;
+    }
+
+    // Tracks that stopped being seen leave their zones without a position.
+    pub fn expire(&mut self, zones: &[Zone]) {
+        self.presence.retain(|&occupant, presence| {
+            if occupant == Occupant::Rover || presence.seen.elapsed() < TRACK_TTL {
+                return true;
+            }
+            for name in &presence.zones {
+                publish(zones, name, occupant, &presence.class_name, false, None);
+            }
+            false
+        });
+    }
+
+    // Zones removed from the config are forgotten quietly.
+    pub fn retain_zones(&mut self, zones: &[Zone]) {
+        for presence in self.presence.values_mut() {
+            presence.zones.retain(|name| zones.iter().any(|z| &z.name == name));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.presence.clear();
+    }
+
+    pub fn is_inside(&self, occupant: Occupant, policy: ZonePolicy, zones: &[Zone]) -> bool {
+        self.presence.get(&occupant).is_some_and(|presence| {
+            zones.iter().any(|z| z.policy == policy && presence.zones.contains(&z.name))
+        })
+    }
+
+    pub fn get_status(&self, zones: &[Zone]) -> Vec<ZoneStatus> {
+        zones.iter()
+            .map(|zone| {
+                let mut occupants: Vec<_> = self.presence.iter()
+                    .filter(|(_, p)| p.zones.contains(&zone.name))
+                    .collect();
+                occupants.sort_by_key(|(occupant, _)| **occupant);
+                ZoneStatus {
+                    name: zone.name.clone(),
+                    policy: zone.policy,
+                    direction_deg: zone.direction_deg,
+                    polygon: zone.polygon.clone(),
+                    occupants: occupants.into_iter().map(|(o, p)| label(*o, &p.class_name)).collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+fn label(occupant: Occupant, class_name: &str) -> String {
+    match occupant {
+        Occupant::Rover => "rover".to_string(),
+        Occupant::Track(id) => format!("{}#{}", class_name, id),
+    }
+}
+
+fn publish(zones: &[Zone], name: &str, occupant: Occupant, class_name: &str, entered: bool, position: Option<(f32, f32)>) {
+    let policy = zones.iter().find(|z| z.name == name).map(|z| z.policy).unwrap_or_default();
+    let restricted = matches!(policy, ZonePolicy::Stop | ZonePolicy::ForbidEntry);
+    let severity = if entered && restricted { Severity::Warning } else { Severity::Info };
+    let object = label(occupant, class_name);
+    EVENTS.publish(
+        severity,
+        "zones",
+        format!("{} {} {}", object, if entered { "entered" } else { "left" }, name),
+        serde_json::json!({
+            "zone": name,
+            "policy": policy,
+            "object": object,
+            "class_name": class_name,
+            "track_id": match occupant { Occupant::Track(id) => Some(id), Occupant::Rover => None },
+            "entered": entered,
+            "x": position.map(|p| p.0),
+            "y": position.map(|p| p.1),
+        }),
+    );
+}
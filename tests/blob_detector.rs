@@ -0,0 +1,100 @@
+// tests/blob_detector.rs
+//
+// The fallback obstacle detector on synthetic frames: a dark blob reaching
+// into the bottom of the frame is an obstacle, boxed in the frame's own
+// coordinates, while blobs too small or too high up are not. With
+// vision.detector = "blob" the vision system finds the same in a bridge
+// frame's JPEG.
+use anyhow::Result;
+use base64::Engine;
+use opencv_embedded::core::Mat;
+
+use scout_vision::config::{VisionConfig, VisionDetector};
+use scout_vision::health::HealthRegistry;
+use scout_vision::simulation::{self, FrameSource};
+use scout_vision::vision::{SimpleBlobObstacleDetector, VisionSystem};
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+
+// A light grey frame with black boxes of (x, y, w, h).
+fn frame(boxes: &[(u32, u32, u32, u32)]) -> image::GrayImage {
+    image::GrayImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let inside = boxes.iter().any(|&(bx, by, bw, bh)| (bx..bx + bw).contains(&x) && (by..by + bh).contains(&y));
+        image::Luma([if inside { 0 } else { 200 }])
+    })
+}
+
+fn bgr(gray: &image::GrayImage) -> Result<Mat> {
+    let data: Vec<u8> = gray.as_raw().iter().flat_map(|&v| [v, v, v]).collect();
+    Ok(Mat::from_slice_2d(HEIGHT as i32, WIDTH as i32, 3, &data)?)
+}
+
+fn jpeg(gray: &image::GrayImage) -> Result<String> {
+    let mut out = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, 90).encode(gray.as_raw(), WIDTH, HEIGHT, image::ColorType::L8)?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(out))
+}
+
+#[test]
+fn a_dark_blob_at_the_bottom_is_an_obstacle_in_frame_coordinates() -> Result<()> {
+    let detector = SimpleBlobObstacleDetector::new(&VisionConfig::default());
+    // Scaled down 2x to blob_scale_width, on even edges, so the box comes
+    // back exactly.
+    let detections = detector.detect(&bgr(&frame(&[(100, 160, 100, 80)]))?)?;
+    assert_eq!(detections.len(), 1);
+    let obstacle = &detections[0];
+    assert_eq!(obstacle.class, "obstacle");
+    assert_eq!((obstacle.x, obstacle.y, obstacle.w, obstacle.h), (100, 160, 100, 80));
+    // A solid box fills its bounds.
+    assert_eq!(obstacle.conf, 1.0);
+    assert!(obstacle.mask.is_none());
+    Ok(())
+}
+
+#[test]
+fn small_or_distant_blobs_are_not_obstacles() -> Result<()> {
+    let detector = SimpleBlobObstacleDetector::new(&VisionConfig::default());
+    // Under 2% of the frame, and wholly in its top half.
+    assert!(detector.detect(&bgr(&frame(&[(150, 220, 10, 10)]))?)?.is_empty());
+    assert!(detector.detect(&bgr(&frame(&[(0, 0, 200, 100)]))?)?.is_empty());
+    // One reaching down from the top half counts.
+    assert_eq!(detector.detect(&bgr(&frame(&[(0, 60, 200, 100)]))?)?.len(), 1);
+    // Two apart are two.
+    assert_eq!(detector.detect(&bgr(&frame(&[(0, 180, 80, 60), (200, 180, 80, 60)]))?)?.len(), 2);
+    assert!(detector.detect(&bgr(&frame(&[]))?)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn gray_frames_work_and_frames_without_an_image_have_none() -> Result<()> {
+    let detector = SimpleBlobObstacleDetector::new(&VisionConfig::default());
+    let gray = frame(&[(100, 160, 100, 80)]);
+    let mat = Mat::from_slice_2d(HEIGHT as i32, WIDTH as i32, 1, gray.as_raw())?;
+    let detections = detector.detect(&mat)?;
+    assert_eq!(detections.len(), 1);
+    assert_eq!((detections[0].x, detections[0].y, detections[0].w, detections[0].h), (100, 160, 100, 80));
+    assert!(detector.detect_jpeg("")?.is_empty());
+    assert!(detector.detect_jpeg("not base64!").is_err());
+    Ok(())
+}
+
+#[test]
+fn the_blob_backend_finds_obstacles_in_bridge_frames() -> Result<()> {
+    let health = HealthRegistry::new();
+    let config = VisionConfig { detector: VisionDetector::Blob, ..VisionConfig::default() };
+    let mut vision = VisionSystem::new(&health, config, FrameSource::Manual)?;
+    // The bridge's own detections are ignored in favour of the blobs.
+    let mut frame_data = simulation::synthetic_frame(1, Vec::new());
+    frame_data.jpeg_base64 = jpeg(&frame(&[(100, 160, 100, 80)]))?;
+    vision.get_frame_sender().send(frame_data);
+    let detections = vision.process_frame()?;
+    assert_eq!(detections.len(), 1);
+    assert_eq!(detections[0].class_name, "obstacle");
+    let (x, y, w, h) = detections[0].bbox;
+    // Within a pixel or two of the box after JPEG.
+    for (got, want) in [(x, 100), (y, 160), (w, 100), (h, 80)] {
+        assert!((got - want).abs() <= 2, "{:?}", detections[0].bbox);
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+// tests/bridge_protocol.rs
+//
+// Bridge lines as each protocol version writes them, checked in below:
+// the v1 handshake and untyped frame, the v2 hello and frame_v2 with its
+// class indices and masks, and the lines each must turn away. Parse
+// failures are watched as a rate over a window and warned about at most
+// every few seconds, with the rest counted.
+use std::time::Duration;
+
+use scout_vision::vision::protocol::{self, BridgeLine, DetectionMask, ParseMonitor, SUPPORTED_VERSIONS};
+use scout_vision::vision::{BridgeFrame, BridgeHello};
+
+// What vision_bridge.py wrote before it had versions.
+const V1_HANDSHAKE: &str = r#"{"handshake": {"monotonic": 1234.5}}"#;
+const V1_FRAME: &str = r#"{"frame_id": 41, "jpeg_base64": "", "timestamp": 1700000000.25, "capture_monotonic": 1234.75, "imx500_basic": [{"class": "person", "conf": 0.82, "x": 100, "y": 50, "w": 80, "h": 300}, {"class": "dog", "class_id": 16, "conf": 0.61, "x": 400, "y": 300, "w": 120, "h": 90}]}"#;
+
+const V2_HELLO: &str = r#"{"type": "hello", "version": 2, "capabilities": ["fiducials", "set_exposure"], "monotonic": 88.0}"#;
+const V2_FRAME: &str = r#"{"type": "frame_v2", "frame_id": 7, "width": 640, "height": 480, "jpeg_base64": "", "timestamp": 1700000001.5, "capture_monotonic": 90.125, "emit_monotonic": 90.14, "brightness": 112.0, "detections": [{"class_id": 0, "class": "person", "conf": 0.9, "x": 280, "y": 100, "w": 80, "h": 300, "mask": {"width": 4, "height": 2, "counts": [1, 2, 2, 3]}}, {"class_id": 56, "conf": 0.7, "x": 10, "y": 20, "w": 30, "h": 40}]}"#;
+
+fn hello(line: &str) -> BridgeHello {
+    match protocol::parse_line(line) {
+        Ok(BridgeLine::Hello(hello)) => hello,
+        Ok(BridgeLine::Frame(_)) => panic!("a frame, not a hello: {}", line),
+        Err(e) => panic!("{:#}: {}", e, line),
+    }
+}
+
+fn frame(line: &str) -> BridgeFrame {
+    match protocol::parse_line(line) {
+        Ok(BridgeLine::Frame(frame)) => frame,
+        Ok(BridgeLine::Hello(_)) => panic!("a hello, not a frame: {}", line),
+        Err(e) => panic!("{:#}: {}", e, line),
+    }
+}
+
+fn error(line: &str) -> String {
+    match protocol::parse_line(line) {
+        Ok(_) => panic!("parsed: {}", line),
+        Err(e) => format!("{:#}", e),
+    }
+}
+
+// The v2 frame fixture with one field changed.
+fn v2_with(edit: impl FnOnce(&mut serde_json::Value)) -> String {
+    let mut value: serde_json::Value = serde_json::from_str(V2_FRAME).unwrap();
+    edit(&mut value);
+    value.to_string()
+}
+
+#[test]
+fn v1_lines_parse() {
+    let hello = hello(V1_HANDSHAKE);
+    assert_eq!((hello.version, hello.monotonic), (1, Some(1234.5)));
+    assert!(hello.capabilities.is_empty() && hello.shm.is_none());
+
+    let v1 = frame(V1_FRAME);
+    assert_eq!((v1.frame_id, v1.timestamp, v1.capture_monotonic), (41, 1700000000.25, Some(1234.75)));
+    assert_eq!(v1.emit_monotonic, None);
+    assert_eq!(v1.imx500_basic.len(), 2);
+    let person = &v1.imx500_basic[0];
+    assert_eq!((person.class.as_str(), person.class_id, person.conf), ("person", None, 0.82));
+    assert_eq!((person.x, person.y, person.w, person.h), (100, 50, 80, 300));
+    assert_eq!(v1.imx500_basic[1].class_id, Some(16));
+    assert!(v1.imx500_basic.iter().all(|d| d.mask.is_none()));
+    assert!(v1.fiducials.is_empty() && v1.slot.is_none());
+
+    // Written out explicitly, the type changes nothing.
+    let typed = V1_FRAME.replacen('{', r#"{"type": "frame", "#, 1);
+    assert_eq!(frame(&typed).frame_id, 41);
+    assert!(error(r#"{"handshake": {}}"#).contains("handshake without monotonic"));
+}
+
+#[test]
+fn v2_lines_parse() {
+    let hello = hello(V2_HELLO);
+    assert_eq!((hello.version, hello.monotonic), (2, Some(88.0)));
+    assert_eq!(hello.capabilities, ["fiducials", "set_exposure"]);
+    assert!(SUPPORTED_VERSIONS.contains(&hello.version));
+
+    let v2 = frame(V2_FRAME);
+    assert_eq!((v2.frame_id, v2.capture_monotonic, v2.emit_monotonic), (7, Some(90.125), Some(90.14)));
+    assert_eq!(v2.brightness, Some(112.0));
+    let person = &v2.imx500_basic[0];
+    assert_eq!((person.class.as_str(), person.class_id), ("person", Some(0)));
+    let mask = person.mask.as_ref().expect("no mask");
+    assert_eq!(mask, &DetectionMask { width: 4, height: 2, counts: vec![1, 2, 2, 3] });
+    assert_eq!(mask.decode(), [false, true, true, false, false, true, true, true]);
+    // A v2 bridge may leave the name to the rover's label map.
+    let cup = &v2.imx500_basic[1];
+    assert_eq!((cup.class.as_str(), cup.class_id), ("", Some(56)));
+    assert!(cup.mask.is_none());
+}
+
+#[test]
+fn bad_v2_frames_are_turned_away() {
+    let resized = v2_with(|v| v["width"] = 1280.into());
+    assert!(error(&resized).contains("unsupported frame size 1280x480"));
+    let short_mask = v2_with(|v| v["detections"][0]["mask"]["counts"] = serde_json::json!([1, 2, 2]));
+    assert!(error(&short_mask).contains("mask runs cover 5 cells, not 4x2"));
+    // v2 requires class indices, and takes no fields it doesn't know.
+    let unindexed = v2_with(|v| {
+        v["detections"][1].as_object_mut().unwrap().remove("class_id");
+    });
+    assert!(error(&unindexed).contains("class_id"));
+    let extra = v2_with(|v| v["exposure_us"] = 8000.into());
+    assert!(error(&extra).contains("exposure_us"));
+    // v1 fields under a v2 tag.
+    let mixed = V1_FRAME.replacen('{', r#"{"type": "frame_v2", "#, 1);
+    error(&mixed);
+}
+
+#[test]
+fn lines_of_no_known_kind_are_errors() {
+    assert!(error("").contains("not JSON"));
+    assert!(error(r#"{"frame_id": 1, "jpeg_base64": "#).contains("not JSON"));
+    assert!(error("[1, 2, 3]").contains("not a JSON object"));
+    assert!(error(r#"{"type": "frame_v9", "frame_id": 1}"#).contains("frame_v9"));
+    // A frame with a field of the wrong type.
+    error(r#"{"frame_id": "one", "jpeg_base64": "", "timestamp": 0, "imx500_basic": []}"#);
+
+    // A hello on a version the rover doesn't speak still parses; it is the
+    // reader that turns the bridge away.
+    let future = hello(r#"{"type": "hello", "version": 3}"#);
+    assert_eq!(future.version, 3);
+    assert!(!SUPPORTED_VERSIONS.contains(&future.version));
+}
+
+#[test]
+fn long_lines_are_cut_for_the_log() {
+    assert_eq!(protocol::truncate("short"), "short");
+    let long = "x".repeat(1000);
+    let cut = protocol::truncate(&long);
+    assert_eq!(cut, format!("{}... (1000 bytes)", "x".repeat(200)));
+    // Cut on a character boundary, with the length still in bytes.
+    let wide = "é".repeat(300);
+    assert_eq!(protocol::truncate(&wide), format!("{}... (600 bytes)", "é".repeat(200)));
+}
+
+#[test]
+fn parse_failures_are_a_rate_over_the_window() {
+    let mut monitor = ParseMonitor::new(Duration::from_millis(200));
+    for _ in 0..9 {
+        monitor.record(false);
+    }
+    assert_eq!(monitor.get_failure_rate(), None, "nine lines are too few to judge");
+    monitor.record(true);
+    assert_eq!(monitor.get_failure_rate(), Some(0.9));
+    for _ in 0..10 {
+        monitor.record(true);
+    }
+    assert!((monitor.get_failure_rate().unwrap() - 9.0 / 20.0).abs() < 1e-6);
+
+    // Lines older than the window drop out.
+    std::thread::sleep(Duration::from_millis(250));
+    for _ in 0..10 {
+        monitor.record(true);
+    }
+    assert_eq!(monitor.get_failure_rate(), Some(0.0));
+    monitor.clear();
+    assert_eq!(monitor.get_failure_rate(), None);
+}
+
+#[test]
+fn failures_are_warned_about_now_and_then_and_the_rest_counted() {
+    let mut monitor = ParseMonitor::new(Duration::from_secs(10));
+    assert_eq!(monitor.take_warning(), Some(0));
+    for _ in 0..5 {
+        assert_eq!(monitor.take_warning(), None);
+    }
+    // Clearing the window doesn't forget what went unlogged.
+    monitor.clear();
+    assert_eq!(monitor.take_warning(), None);
+    std::thread::sleep(Duration::from_millis(5100));
+    assert_eq!(monitor.take_warning(), Some(6));
+    assert_eq!(monitor.take_warning(), None);
+}
@@ -0,0 +1,180 @@
+// tests/chaos.rs
+//
+// A panic injected into each supervised subsystem (vision, the planner and
+// the frame broadcaster) while the rover drives: the motors end up
+// stopped, a Critical event names the subsystem, the rover faults, and
+// once the fault is acknowledged the subsystem runs again. The chaos
+// points are only built with `sim`.
+#![cfg(feature = "sim")]
+use anyhow::Result;
+use std::time::{Duration, Instant};
+
+use scout_vision::clock::CLOCK;
+use scout_vision::events::{Severity, EVENTS};
+use scout_vision::metrics::METRICS;
+use scout_vision::simulation::CHAOS;
+use scout_vision::state_machine::{RoverState, Trigger};
+
+mod common;
+use common::{Sim, SERIAL};
+
+const TICK: Duration = Duration::from_millis(20);
+
+impl Sim {
+    fn new(web_port: u16) -> Result<Self> {
+        CLOCK.set_manual(CLOCK.now_ms());
+        CHAOS.clear();
+        Sim::boot("chaos", &[serde_json::json!({
+            "control": { "start_autonomous": true },
+            "web": { "bind_address": "127.0.0.1", "port": web_port },
+        })])
+    }
+
+    // A tick with a clear view.
+    fn step(&mut self) -> Result<()> {
+        self.send(&[]);
+        CLOCK.advance(TICK);
+        self.rover.tick(TICK)
+    }
+
+    fn get_state(&self) -> RoverState {
+        self.rover.state.get_state()
+    }
+
+    // Steps until the planner has the rover cruising.
+    fn drive_off(&mut self) -> Result<()> {
+        for _ in 0..200 {
+            if self.get_state() == RoverState::Autonomous && self.get_target_speed() > 0.0 {
+                return Ok(());
+            }
+            self.step()?;
+        }
+        anyhow::bail!("never moved off: {:?}", self.rover.state.get_status())
+    }
+
+    // Acknowledges the fault and arms again, which is when a panicked
+    // subsystem is reset, then drives off as before.
+    fn recover(&mut self) -> Result<()> {
+        self.rover.state.request(Trigger::AcknowledgeFault);
+        self.step()?;
+        assert_eq!(self.get_state(), RoverState::Idle);
+        self.rover.state.request(Trigger::StartAutonomous);
+        self.drive_off()
+    }
+
+    // The motors are stopped, the rover is in Fault for `subsystem`, and
+    // an event since `since` said so.
+    fn assert_faulted(&self, subsystem: &str, since: u64) {
+        let motors = self.rover.motor_controller.read().get_status();
+        assert_eq!(motors.target_speed, 0.0, "{} left the motors driving", subsystem);
+        assert_eq!(self.get_state(), RoverState::Fault);
+        let status = self.rover.state.get_status();
+        assert_eq!(status.reason.as_deref(), Some(format!("{} panicked", subsystem).as_str()));
+        let reported = EVENTS.recent().into_iter().any(|event| {
+            event.id > since
+                && event.severity == Severity::Critical
+                && event.source == subsystem
+                && event.message.contains("panic injected")
+        });
+        assert!(reported, "no event for the {} panic", subsystem);
+    }
+}
+
+fn last_event_id() -> u64 {
+    EVENTS.recent().last().map_or(0, |event| event.id)
+}
+
+fn wait_until(within: Duration, mut done: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + within;
+    while Instant::now() < deadline {
+        if done() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    done()
+}
+
+fn free_port() -> Result<u16> {
+    Ok(std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+#[test]
+fn a_vision_panic_stops_the_motors_and_vision_runs_again() -> Result<()> {
+    let _serial = SERIAL.lock();
+    let mut sim = Sim::new(free_port()?)?;
+    sim.drive_off()?;
+
+    let since = last_event_id();
+    let panics = METRICS.panics.get("vision");
+    let restarts = METRICS.subsystem_restarts.get("vision");
+    CHAOS.inject("vision");
+    sim.step()?;
+    sim.assert_faulted("vision", since);
+    assert_eq!(METRICS.panics.get("vision"), panics + 1);
+
+    // Held stopped for as long as the fault stands.
+    for _ in 0..10 {
+        sim.step()?;
+        assert_eq!(sim.get_target_speed(), 0.0);
+    }
+    sim.recover()?;
+    assert_eq!(METRICS.subsystem_restarts.get("vision"), restarts + 1);
+    let frame_id = sim.rover.state.get_world().frame_id;
+    sim.step()?;
+    assert!(sim.rover.state.get_world().frame_id > frame_id, "vision took no frame after its restart");
+    Ok(())
+}
+
+#[test]
+fn a_planner_panic_stops_the_motors_and_planning_runs_again() -> Result<()> {
+    let _serial = SERIAL.lock();
+    let mut sim = Sim::new(free_port()?)?;
+    sim.drive_off()?;
+
+    let since = last_event_id();
+    let panics = METRICS.panics.get("planner");
+    let restarts = METRICS.subsystem_restarts.get("planner");
+    CHAOS.inject("planner");
+    sim.step()?;
+    // The planner's panic reaches the state machine as a request, taken on
+    // the next tick.
+    sim.step()?;
+    sim.assert_faulted("planner", since);
+    assert_eq!(METRICS.panics.get("planner"), panics + 1);
+
+    sim.recover()?;
+    assert_eq!(METRICS.subsystem_restarts.get("planner"), restarts + 1);
+    let plan_tick = sim.rover.state.get_world().plan_tick;
+    sim.step()?;
+    assert!(sim.rover.state.get_world().plan_tick > plan_tick, "no plan after the planner's restart");
+    Ok(())
+}
+
+#[test]
+fn a_broadcaster_panic_stops_the_motors_and_the_broadcaster_restarts() -> Result<()> {
+    let _serial = SERIAL.lock();
+    let mut sim = Sim::new(free_port()?)?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.spawn(sim.web().run());
+    sim.drive_off()?;
+
+    let since = last_event_id();
+    let panics = METRICS.panics.get("frame_broadcaster");
+    let restarts = METRICS.subsystem_restarts.get("frame_broadcaster");
+    CHAOS.inject("frame_broadcaster");
+    assert!(wait_until(Duration::from_secs(5), || METRICS.panics.get("frame_broadcaster") > panics), "the broadcaster never panicked");
+    // The motors stop from the broadcaster's task, before any tick.
+    assert_eq!(sim.get_target_speed(), 0.0);
+    sim.step()?;
+    sim.assert_faulted("frame_broadcaster", since);
+
+    // Respawned after its backoff, without waiting on the operator; a
+    // second panic shows the new task is the one broadcasting.
+    assert!(wait_until(Duration::from_secs(5), || METRICS.subsystem_restarts.get("frame_broadcaster") > restarts), "the broadcaster was never restarted");
+    CHAOS.inject("frame_broadcaster");
+    assert!(wait_until(Duration::from_secs(5), || METRICS.panics.get("frame_broadcaster") > panics + 1), "the restarted broadcaster isn't running");
+    assert!(wait_until(Duration::from_secs(5), || METRICS.subsystem_restarts.get("frame_broadcaster") > restarts + 1));
+    sim.recover()?;
+    Ok(())
+}
@@ -0,0 +1,250 @@
+// tests/checkpoint.rs
+//
+// Checkpoints as an unexpected restart finds them: a writer killed over and
+// over partway through writing, half-written and stale files, and a rover
+// booting from one taken mid-mission, which should know where it is
+// straight away but not drive off until the mission is confirmed.
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use scout_vision::checkpoint::{self, Checkpoint, MapTiles, Mission, FORMAT};
+use scout_vision::clock::CLOCK;
+use scout_vision::config::{CheckpointConfig, ConfigStore, PlannerConfig};
+use scout_vision::events::EVENTS;
+use scout_vision::health::HealthRegistry;
+use scout_vision::mapping::{Cell, GridDiff, GridTile, OccupancyGrid, RoverPose};
+use scout_vision::pathfinding::{HazardType, ObstacleMemory, ObstacleMotion, PathPlanner, PathPoint, PlannerMemory};
+use scout_vision::rover::Rover;
+use scout_vision::simulation::FrameSource;
+use scout_vision::state_machine::RoverState;
+
+const TICK: Duration = Duration::from_millis(20);
+// Set for the copy of this binary that writes until it is killed.
+const CHILD_PATH: &str = "SCOUT_CHECKPOINT_CHILD_PATH";
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("checkpoint").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn config(path: &Path) -> CheckpointConfig {
+    CheckpointConfig { enabled: true, path: path.display().to_string(), ..CheckpointConfig::default() }
+}
+
+// Big enough that writing it takes a while, so kills land mid-write.
+fn checkpoint(n: u32, tiles: usize) -> Checkpoint {
+    let tiles = (0..tiles)
+        .map(|i| GridTile { tx: i % 50, ty: i / 50, data: format!("{:08}", n.wrapping_mul(31).wrapping_add(i as u32)).repeat(100) })
+        .collect();
+    Checkpoint {
+        format: FORMAT,
+        written_ms: CLOCK.wall_ms(),
+        mission: Some(Mission { state: RoverState::Autonomous, goal: Some(PathPoint { x: 1.0, y: n as f32 }), docking: false }),
+        pose: Some(RoverPose { x: n as f32, y: 0.0, heading_deg: 0.0 }),
+        map: Some(MapTiles { resolution_m: 0.1, diff: GridDiff { width: 1000, height: 1000, base_version: 0, version: n as u64, tiles } }),
+        memory: None,
+    }
+}
+
+fn files_in(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir).unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+fn published(fragment: &str) -> bool {
+    EVENTS.recent().iter().any(|e| e.source == "checkpoint" && e.message.contains(fragment))
+}
+
+// Not a test on its own: the child half of the one below, doing nothing
+// unless it was started as the child.
+#[test]
+fn child_writes_until_killed() {
+    let Ok(path) = std::env::var(CHILD_PATH) else { return };
+    for n in 1.. {
+        checkpoint::write(Path::new(&path), &checkpoint(n, 2000)).unwrap();
+    }
+}
+
+#[test]
+fn killed_mid_write_leaves_the_last_checkpoint_whole() {
+    let dir = scratch("killed");
+    let path = dir.join("rover.json");
+    let mut mid_write = 0;
+    for round in 0..12u64 {
+        let mut child = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "child_writes_until_killed", "--nocapture"])
+            .env(CHILD_PATH, &path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(20);
+        while !path.exists() {
+            assert!(Instant::now() < deadline, "the child never wrote a checkpoint");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        std::thread::sleep(Duration::from_millis(5 + round * 7));
+        child.kill().unwrap();
+        child.wait().unwrap();
+        if dir.join("rover.json.tmp").exists() {
+            mid_write += 1;
+        }
+
+        let loaded = checkpoint::load(&config(&path)).expect("a whole checkpoint is left");
+        let n = loaded.pose.unwrap().x;
+        assert!(n >= 1.0 && loaded.map.unwrap().diff.tiles.len() == 2000, "round {}: {}", round, n);
+        assert_eq!(files_in(&dir), ["rover.json"], "round {}", round);
+    }
+    eprintln!("{} of 12 kills landed mid-write", mid_write);
+}
+
+#[test]
+fn half_written_temporary_file_is_ignored() {
+    let dir = scratch("half_written");
+    let path = dir.join("rover.json");
+    checkpoint::write(&path, &checkpoint(7, 10)).unwrap();
+    // What a write killed partway leaves, at every length it could stop at.
+    let next = serde_json::to_vec(&checkpoint(8, 10)).unwrap();
+    for len in (0..next.len()).step_by(97) {
+        std::fs::write(dir.join("rover.json.tmp"), &next[..len]).unwrap();
+        let loaded = checkpoint::load(&config(&path)).unwrap();
+        assert_eq!(loaded.pose.unwrap().x, 7.0);
+        assert_eq!(files_in(&dir), ["rover.json"]);
+    }
+}
+
+#[test]
+fn corrupt_and_stale_checkpoints_are_moved_aside() {
+    let dir = scratch("quarantine");
+    let path = dir.join("rover.json");
+
+    // Cut short, as on a filesystem that lost the end of it.
+    let text = serde_json::to_vec(&checkpoint(1, 10)).unwrap();
+    std::fs::write(&path, &text[..text.len() / 2]).unwrap();
+    assert!(checkpoint::load(&config(&path)).is_none());
+    let names = files_in(&dir);
+    assert!(names.len() == 1 && names[0].starts_with("rover.json.corrupt-"), "{:?}", names);
+    assert!(published("was corrupt"));
+
+    let old = Checkpoint { written_ms: CLOCK.wall_ms() - 3_600_000, ..checkpoint(2, 10) };
+    checkpoint::write(&path, &old).unwrap();
+    assert!(checkpoint::load(&config(&path)).is_none());
+    assert!(files_in(&dir).iter().any(|n| n.starts_with("rover.json.stale-")), "{:?}", files_in(&dir));
+    assert!(!path.exists());
+    assert!(published("was stale"));
+
+    // Within checkpoint.max_age_s it is used.
+    let recent = Checkpoint { written_ms: CLOCK.wall_ms() - 60_000, ..checkpoint(3, 10) };
+    checkpoint::write(&path, &recent).unwrap();
+    assert!(checkpoint::load(&config(&path)).is_some());
+}
+
+fn boot(dir: &Path) -> Result<Rover> {
+    let config = ConfigStore::load(dir.join("rover.toml"))?;
+    config.update(&serde_json::json!({
+        "selftest": { "enabled": false },
+        "control": { "start_autonomous": true },
+        "checkpoint": { "enabled": true, "path": dir.join("rover.json") },
+    })).map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+    let rover = Rover::new(Arc::new(config), FrameSource::Manual)?;
+    Ok(rover)
+}
+
+fn tick(rover: &Rover, ticks: u32) -> Result<RoverState> {
+    for _ in 0..ticks {
+        rover.tick(TICK)?;
+    }
+    Ok(rover.state.get_state())
+}
+
+#[test]
+fn restart_mid_mission_waits_to_be_resumed() -> Result<()> {
+    let dir = scratch("resume");
+
+    // What the rover knew when it was killed: a wall it had mapped and a
+    // box it remembered, out on its way to a goal.
+    let mut before = PathPlanner::new(&HealthRegistry::new(), PlannerConfig::default());
+    before.restore_memory(PlannerMemory {
+        obstacles: vec![ObstacleMemory { class_name: "box".into(), hazard: HazardType::Obstacle, motion: ObstacleMotion::Static, x: 0.5, y: 2.0 }],
+        drop_offs: Vec::new(),
+    });
+    let resolution = PlannerConfig::default().map_resolution_m;
+    let mut grid = OccupancyGrid::new(PlannerConfig::default().map_size_cells, resolution);
+    for i in 0..10 {
+        grid.set_world(-1.0 + i as f32 * resolution, 3.0, Cell::Occupied);
+    }
+    let written = Checkpoint {
+        format: FORMAT,
+        written_ms: CLOCK.wall_ms(),
+        mission: Some(Mission { state: RoverState::Autonomous, goal: Some(PathPoint { x: 2.0, y: 5.0 }), docking: false }),
+        pose: Some(RoverPose { x: 1.5, y: 2.5, heading_deg: 30.0 }),
+        map: Some(MapTiles { resolution_m: resolution, diff: grid.diff_since(0) }),
+        memory: Some(before.get_memory()),
+    };
+    checkpoint::write(&dir.join("rover.json"), &written)?;
+
+    let rover = boot(&dir)?;
+    let offer = rover.load_checkpoint().expect("the mission was underway");
+    assert_eq!(offer.mission.goal.as_ref().map(|g| (g.x, g.y)), Some((2.0, 5.0)));
+    {
+        let planner = rover.path_planner.read();
+        let pose = planner.get_pose();
+        assert_eq!((pose.x, pose.y, pose.heading_deg), (1.5, 2.5, 30.0));
+        let snapshot = planner.get_map_snapshot();
+        assert_eq!(snapshot.data, grid.snapshot().data);
+        let obstacles = planner.get_map_state().obstacles;
+        assert!(obstacles.len() == 1 && obstacles[0].motion == ObstacleMotion::Static, "{:?}", obstacles);
+        // The goal waits for the mission to be confirmed.
+        assert!(planner.get_goal().is_none());
+    }
+    assert!(published("Restarted mid-mission"));
+
+    // Booted, with control.start_autonomous set, it still waits.
+    rover.start_subsystems();
+    rover.selftest.start();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while tick(&rover, 1)? != RoverState::Idle {
+        assert!(Instant::now() < deadline, "still {:?}", rover.state.get_state());
+    }
+    assert_eq!(tick(&rover, 10)?, RoverState::Idle);
+    assert!(rover.state.get_status().resume.is_some());
+
+    // A checkpoint taken while it waits still offers the mission.
+    let again = checkpoint::capture(&rover.path_planner.read(), &rover.state, &CheckpointConfig::default().include);
+    assert!(again.mission.is_some_and(|m| m.is_underway()));
+
+    checkpoint::confirm(&rover.state, &rover.path_planner, "test").expect("on offer");
+    assert_eq!(tick(&rover, 2)?, RoverState::Autonomous);
+    assert!(rover.state.get_resume().is_none());
+    assert_eq!(rover.path_planner.read().get_goal().map(|g| (g.x, g.y)), Some((2.0, 5.0)));
+    Ok(())
+}
+
+#[test]
+fn declined_mission_stays_put() -> Result<()> {
+    let dir = scratch("decline");
+    checkpoint::write(&dir.join("rover.json"), &Checkpoint { map: None, ..checkpoint(4, 0) })?;
+
+    let rover = boot(&dir)?;
+    assert!(rover.load_checkpoint().is_some());
+    assert!(checkpoint::decline(&rover.state, "test").is_some());
+    assert!(checkpoint::confirm(&rover.state, &rover.path_planner, "test").is_none());
+    // The pose is kept either way.
+    assert_eq!(rover.path_planner.read().get_pose().x, 4.0);
+
+    // With nothing underway there is nothing to offer.
+    let idle = Checkpoint { mission: Some(Mission { state: RoverState::Idle, goal: None, docking: false }), ..checkpoint(5, 0) };
+    checkpoint::write(&dir.join("rover.json"), &Checkpoint { map: None, ..idle })?;
+    let rover = boot(&dir)?;
+    assert!(rover.load_checkpoint().is_none());
+    assert_eq!(rover.path_planner.read().get_pose().x, 5.0);
+    Ok(())
+}
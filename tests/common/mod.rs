@@ -0,0 +1,69 @@
+// tests/common/mod.rs
+//
+// The whole-rover fixture the simulated drives share: a rover booted on
+// frames fed by hand with the self-test out of the way, and a lock for
+// runs that touch process-wide state - the rover clock, the metrics, the
+// hazard board, the chaos points - so they take turns. Each test file
+// adds the stepping it needs in its own `impl Sim`.
+#![allow(dead_code)]
+use anyhow::Result;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::Duration;
+
+use scout_vision::config::ConfigStore;
+use scout_vision::rover::Rover;
+use scout_vision::simulation::{self, FrameSource};
+use scout_vision::vision::{FrameSender, IMX500Detection};
+use scout_vision::web::WebServer;
+
+pub static SERIAL: Mutex<()> = Mutex::new(());
+
+pub struct Sim {
+    pub rover: Rover,
+    pub frames: FrameSender,
+    pub frame_id: u32,
+    pub ticks: u32,
+}
+
+impl Sim {
+    // Boots with `updates` applied in turn over the self-test being off.
+    // `name` keeps each file's unused config path apart.
+    pub fn boot(name: &str, updates: &[serde_json::Value]) -> Result<Self> {
+        let config = ConfigStore::load(std::env::temp_dir().join(format!("scout-{}-unused.toml", name)))?;
+        config.update(&serde_json::json!({ "selftest": { "enabled": false } })).map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+        for update in updates {
+            config.update(update).map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+        }
+        let rover = Rover::new(Arc::new(config), FrameSource::Manual)?;
+        rover.start_subsystems();
+        while rover.startup.is_initializing() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        rover.selftest.start();
+        let frames = rover.vision.read().get_frame_sender();
+        Ok(Self { rover, frames, frame_id: 0, ticks: 0 })
+    }
+
+    // The next frame, seeing `detections`.
+    pub fn send(&mut self, detections: &[IMX500Detection]) {
+        self.frame_id += 1;
+        self.frames.send(simulation::synthetic_frame(self.frame_id, detections.to_vec()));
+    }
+
+    pub fn web(&self) -> Arc<WebServer> {
+        Arc::new(WebServer::new(
+            self.rover.vision.clone(),
+            self.rover.path_planner.clone(),
+            self.rover.motor_controller.clone(),
+            self.rover.state.clone(),
+            self.rover.scheduler.clone(),
+            self.rover.health.clone(),
+            self.rover.config.clone(),
+        ))
+    }
+
+    pub fn get_target_speed(&self) -> f32 {
+        self.rover.motor_controller.read().get_status().target_speed
+    }
+}
@@ -0,0 +1,265 @@
+// tests/detection_delta.rs
+//
+// Keyed detection updates replayed against a simulated client: a scripted
+// scene of tracks appearing, moving, losing fields and leaving, with a few
+// untracked detections and a repeated id, goes through DetectionSync and
+// a DetectionMirror fed what it sends must hold exactly what each frame
+// held. A lost delta leaves the mirror unsynced until the next full sync.
+// Over the WebSocket, a client that asks for deltas ends up with the same
+// detections as the rover while one that doesn't still gets whole frames.
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+use scout_vision::config::ConfigStore;
+use scout_vision::detection_delta::{DetectionMirror, DetectionSync};
+use scout_vision::rover::Rover;
+use scout_vision::simulation::{self, FrameSource};
+use scout_vision::vision::IMX500Detection;
+use scout_vision::web::WebServer;
+
+fn tracked(track_id: u64, class: &str, x: i64, distance: f64) -> Value {
+    json!({ "track_id": track_id, "class_name": class, "bbox": [x, 100, 60, 200], "distance_estimate": distance, "confidence": 0.9 })
+}
+
+fn untracked(class: &str, x: i64) -> Value {
+    json!({ "track_id": null, "class_name": class, "bbox": [x, 40, 20, 20] })
+}
+
+// A short scene, one frame a line.
+fn scene() -> Vec<Vec<Value>> {
+    let mut sofa_gone = tracked(3, "sofa", 500, 3.0);
+    sofa_gone.as_object_mut().unwrap().remove("distance_estimate");
+    vec![
+        vec![tracked(1, "person", 100, 2.0), tracked(2, "chair", 300, 1.5)],
+        // Nothing changes.
+        vec![tracked(1, "person", 100, 2.0), tracked(2, "chair", 300, 1.5)],
+        // One moves; one arrives.
+        vec![tracked(1, "person", 120, 1.9), tracked(2, "chair", 300, 1.5), tracked(3, "sofa", 500, 3.0)],
+        // One leaves, and something untracked turns up.
+        vec![tracked(1, "person", 140, 1.8), tracked(3, "sofa", 500, 3.0), untracked("cup", 10)],
+        // A field goes missing, and an id turns up twice in one frame.
+        vec![tracked(1, "person", 140, 1.8), sofa_gone.clone(), tracked(1, "person", 400, 4.0), untracked("cup", 10)],
+        // Back in track id order, as the mirror keeps them.
+        vec![tracked(1, "person", 160, 1.7), tracked(2, "chair", 310, 1.5), sofa_gone],
+        vec![],
+        vec![tracked(4, "dog", 200, 2.5)],
+    ]
+}
+
+fn frame(detections: &[Value]) -> Value {
+    json!({ "type": "frame", "detections": detections, "image": "" })
+}
+
+// What the mirror should hold: tracked detections by id, then the rest.
+fn expected(detections: &[Value]) -> Vec<Value> {
+    let mut tracked: Vec<&Value> = Vec::new();
+    let mut rest = Vec::new();
+    for detection in detections {
+        match detection["track_id"].as_u64() {
+            Some(id) if !tracked.iter().any(|t| t["track_id"] == id) => tracked.push(detection),
+            _ => rest.push(detection.clone()),
+        }
+    }
+    tracked.sort_by_key(|t| t["track_id"].as_u64());
+    tracked.into_iter().cloned().chain(rest).collect()
+}
+
+#[test]
+fn a_replayed_scene_is_rebuilt_exactly() {
+    let mut sync = DetectionSync::new(60_000);
+    let mut mirror = DetectionMirror::new();
+    for (i, detections) in scene().iter().enumerate() {
+        let mut message = frame(detections);
+        sync.apply(&mut message, 1000 + i as u64 * 33);
+        // Only the first is a full sync; the rest carry deltas.
+        assert_eq!(message.get("detections").is_some(), i == 0, "frame {}: {}", i, message);
+        assert_eq!(message.get("detections_delta").is_some(), i > 0);
+        // The rest of the frame is left alone.
+        assert_eq!((&message["type"], &message["image"]), (&json!("frame"), &json!("")));
+
+        // And it survives being sent as text.
+        let message: Value = serde_json::from_str(&message.to_string()).unwrap();
+        assert!(mirror.apply(&message), "frame {} didn't apply", i);
+        assert!(mirror.is_synced());
+        assert_eq!(mirror.get_detections(), expected(detections), "frame {}", i);
+    }
+}
+
+#[test]
+fn deltas_carry_only_what_changed() {
+    let scene = scene();
+    let mut sync = DetectionSync::new(60_000);
+    let mut messages = Vec::new();
+    for (i, detections) in scene.iter().enumerate() {
+        let mut message = frame(detections);
+        sync.apply(&mut message, i as u64);
+        messages.push(message);
+    }
+
+    let delta = |i: usize| &messages[i]["detections_delta"];
+    assert_eq!(delta(1), &json!({ "seq": 2, "added": [], "updated": [], "removed": [] }));
+    assert_eq!(delta(2)["updated"], json!([{ "track_id": 1, "bbox": [120, 100, 60, 200], "distance_estimate": 1.9 }]));
+    assert_eq!(delta(2)["added"], json!([tracked(3, "sofa", 500, 3.0)]));
+    assert_eq!((&delta(3)["removed"], &delta(3)["untracked"]), (&json!([2]), &json!([untracked("cup", 10)])));
+    // A detection that lost a field is sent whole, and a repeated id
+    // joins the untracked ones.
+    assert_eq!(delta(4)["added"][0]["track_id"], 3);
+    assert!(delta(4)["added"][0].get("distance_estimate").is_none());
+    assert_eq!(delta(4)["untracked"], json!([tracked(1, "person", 400, 4.0), untracked("cup", 10)]));
+    assert_eq!(delta(6)["removed"], json!([1, 2, 3]));
+    assert_eq!(delta(5)["untracked"], json!([]));
+    // Untracked detections unchanged since the last frame aren't resent.
+    assert!(delta(6).get("untracked").is_none() && delta(7).get("untracked").is_none());
+}
+
+#[test]
+fn a_lost_delta_leaves_the_mirror_unsynced_until_the_next_full_sync() {
+    let scene = scene();
+    let mut sync = DetectionSync::new(1000);
+    let mut mirror = DetectionMirror::new();
+    // A delta before any full sync has nothing to apply to.
+    let mut other = DetectionSync::new(1000);
+    other.apply(&mut frame(&scene[0]), 0);
+    let mut orphan = frame(&scene[1]);
+    other.apply(&mut orphan, 1);
+    assert!(!mirror.apply(&orphan));
+    assert!(!mirror.is_synced());
+
+    let mut send = |i: usize, now_ms: u64, mirror: &mut DetectionMirror, delivered: bool| {
+        let mut message = frame(&scene[i]);
+        sync.apply(&mut message, now_ms);
+        !delivered || mirror.apply(&message)
+    };
+    assert!(send(0, 0, &mut mirror, true));
+    assert!(send(1, 100, &mut mirror, true));
+    // Frame 2's delta never arrives.
+    send(2, 200, &mut mirror, false);
+    assert!(!send(3, 300, &mut mirror, true));
+    assert!(!mirror.is_synced());
+    assert!(!send(4, 400, &mut mirror, true), "still out of sequence");
+
+    // The next full sync, a second after the last, puts it right.
+    assert!(send(5, 1000, &mut mirror, true));
+    assert!(mirror.is_synced());
+    assert_eq!(mirror.get_detections(), expected(&scene[5]));
+    assert!(send(6, 1100, &mut mirror, true));
+    assert_eq!(mirror.get_detections(), expected(&scene[6]));
+
+    // A resync, as when the client resubscribes, sends the next frame whole.
+    sync.resync();
+    let mut message = frame(&scene[7]);
+    sync.apply(&mut message, 1200);
+    assert_eq!(message["detections_sync"]["full"], true);
+    assert!(mirror.apply(&message));
+}
+
+#[test]
+fn messages_without_detections_are_left_alone() {
+    let mut sync = DetectionSync::new(1000);
+    let mut mirror = DetectionMirror::new();
+    for message in [json!({ "type": "map", "grid": [] }), json!({ "type": "frame", "detections": null }), json!(7)] {
+        let mut sent = message.clone();
+        sync.apply(&mut sent, 0);
+        assert_eq!(sent, message);
+        assert!(mirror.apply(&sent));
+    }
+    // Nor do they count toward the sequence.
+    let mut first = frame(&[]);
+    sync.apply(&mut first, 0);
+    assert_eq!(first["detections_sync"]["seq"], 1);
+}
+
+#[test]
+fn websocket_clients_that_ask_for_deltas_rebuild_the_rovers_detections() -> Result<()> {
+    let config = ConfigStore::load(std::env::temp_dir().join("scout-detection-delta-unused.toml"))?;
+    let port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+    config.update(&json!({
+        "selftest": { "enabled": false },
+        "control": { "start_autonomous": false },
+        "web": { "bind_address": "127.0.0.1", "port": port, "detection_full_sync_s": 60.0 },
+    })).map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+    let rover = Rover::new(Arc::new(config), FrameSource::Manual)?;
+    rover.start_subsystems();
+    while rover.startup.is_initializing() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    rover.selftest.start();
+
+    let web = Arc::new(WebServer::new(
+        rover.vision.clone(),
+        rover.path_planner.clone(),
+        rover.motor_controller.clone(),
+        rover.state.clone(),
+        rover.scheduler.clone(),
+        rover.health.clone(),
+        rover.config.clone(),
+    ));
+    let routes = web.routes();
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.spawn(web.clone().run());
+
+    let person = |x: i32| IMX500Detection { class: "person".into(), class_id: None, conf: 0.9, x, y: 120, w: 60, h: 200, mask: None };
+    let chair = |x: i32| IMX500Detection { class: "chair".into(), class_id: None, conf: 0.8, x, y: 300, w: 100, h: 120, mask: None };
+    let scene = [
+        vec![person(100), chair(400)],
+        vec![person(110), chair(400)],
+        vec![person(120), chair(400)],
+        vec![person(130)],
+        vec![person(140), chair(420)],
+        vec![],
+    ];
+
+    runtime.block_on(async {
+        let mut keyed = warp::test::ws().path("/ws").handshake(routes.clone()).await?;
+        keyed.send_text(json!({ "cmd": "hello", "detection_deltas": true }).to_string()).await;
+        let mut plain = warp::test::ws().path("/ws").handshake(routes).await?;
+        plain.send_text(json!({ "cmd": "hello" }).to_string()).await;
+
+        let mut mirror = DetectionMirror::new();
+        let (mut deltas, mut tracks) = (0, 0);
+        for (i, detections) in scene.into_iter().enumerate() {
+            let frame_id = 100 + i as u32;
+            rover.vision.read().get_frame_sender().send(simulation::synthetic_frame(frame_id, detections));
+            rover.tick(Duration::from_millis(20))?;
+            let world: Vec<Value> = rover.state.get_world().detections.iter().map(serde_json::to_value).collect::<Result<_, _>>()?;
+            tracks += world.iter().filter(|d| d["track_id"].is_u64()).count();
+
+            // Everything the keyed client is sent goes into its mirror,
+            // until a frame of this tick arrives.
+            loop {
+                let message = next_frame(&mut keyed).await?;
+                deltas += message.get("detections_delta").is_some() as usize;
+                assert!(mirror.apply(&message), "out of sequence: {}", message);
+                if message["frame_id"] == frame_id {
+                    break;
+                }
+            }
+            assert_eq!(mirror.get_detections(), expected(&world), "frame {}", frame_id);
+
+            let message = loop {
+                let message = next_frame(&mut plain).await?;
+                if message["frame_id"] == frame_id {
+                    break message;
+                }
+            };
+            assert!(message.get("detections_delta").is_none());
+            assert_eq!(message["detections"], Value::Array(world));
+        }
+        assert!(deltas > 0 && tracks > 0, "{} deltas of {} tracks", deltas, tracks);
+        anyhow::Ok(())
+    })
+}
+
+// The next frame message, skipping events, maps and the like.
+async fn next_frame(client: &mut warp::test::WsClient) -> Result<Value> {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), client.recv()).await??;
+        let Ok(text) = message.to_str() else { continue };
+        let message: Value = serde_json::from_str(text)?;
+        if message["type"] == "frame" {
+            return Ok(message);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// tests/detection_json.rs
+//
+// The JSON a Detection goes out as, against strings checked in here.
+// Dashboards read these field names and shapes, so a change that breaks
+// either fails here before it reaches one.
+use scout_vision::vision::{Detection, DetectionMask, NavigationAction};
+
+// Every field, with the optional ones absent, as /api/world and the
+// websocket send it.
+const PLAIN: &str = r#"{"class_name":"person","confidence":0.75,"raw_confidence":0.5,"bbox":[280,40,80,400],"distance_estimate":1.5,"action":"Stop","capture_ms":1234,"track_id":7,"self_occlusion":false,"below_threshold":false}"#;
+
+// With a mask and a ground position, which are left out when None.
+const FULL: &str = r#"{"class_name":"chair","confidence":0.25,"raw_confidence":0.25,"bbox":[0,0,16,8],"distance_estimate":3.0,"action":"SlowDown","capture_ms":0,"track_id":null,"mask":{"width":4,"height":2,"counts":[1,3,4]},"self_occlusion":true,"below_threshold":true,"ground_position":[-0.5,2.5]}"#;
+
+fn plain() -> Detection {
+    Detection {
+        class_name: "person".into(),
+        confidence: 0.75,
+        raw_confidence: 0.5,
+        bbox: (280, 40, 80, 400),
+        distance_estimate: 1.5,
+        action: NavigationAction::Stop,
+        capture_ms: 1234,
+        track_id: Some(7),
+        mask: None,
+        self_occlusion: false,
+        below_threshold: false,
+        ground_position: None,
+    }
+}
+
+fn full() -> Detection {
+    Detection {
+        class_name: "chair".into(),
+        confidence: 0.25,
+        raw_confidence: 0.25,
+        bbox: (0, 0, 16, 8),
+        distance_estimate: 3.0,
+        action: NavigationAction::SlowDown,
+        capture_ms: 0,
+        track_id: None,
+        mask: Some(DetectionMask { width: 4, height: 2, counts: vec![1, 3, 4] }),
+        self_occlusion: true,
+        below_threshold: true,
+        ground_position: Some((-0.5, 2.5)),
+    }
+}
+
+#[test]
+fn a_detection_serializes_to_the_json_dashboards_read() {
+    assert_eq!(serde_json::to_string(&plain()).unwrap(), PLAIN);
+    assert_eq!(serde_json::to_string(&full()).unwrap(), FULL);
+}
+
+#[test]
+fn the_snapshots_read_back_as_the_same_detections() {
+    for (json, expected) in [(PLAIN, plain()), (FULL, full())] {
+        let detection: Detection = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_value(&detection).unwrap(), serde_json::to_value(&expected).unwrap());
+    }
+}
+
+#[test]
+fn json_from_before_the_newer_fields_still_reads() {
+    // As recordings and older bridges wrote it.
+    let old = r#"{"class_name":"person","confidence":0.9,"bbox":[1,2,3,4],"distance_estimate":2.0,"action":"Continue"}"#;
+    let detection: Detection = serde_json::from_str(old).unwrap();
+    assert_eq!(detection.raw_confidence, 0.0);
+    assert_eq!(detection.capture_ms, 0);
+    assert_eq!(detection.track_id, None);
+    assert!(detection.mask.is_none() && detection.ground_position.is_none());
+    assert!(!detection.self_occlusion && !detection.below_threshold);
+}
+
+#[test]
+fn actions_serialize_by_variant_name() {
+    let names: Vec<String> = [NavigationAction::Continue, NavigationAction::SlowDown, NavigationAction::Stop, NavigationAction::EmergencyStop]
+        .iter()
+        .map(|action| serde_json::to_string(action).unwrap())
+        .collect();
+    assert_eq!(names, [r#""Continue""#, r#""SlowDown""#, r#""Stop""#, r#""EmergencyStop""#]);
+}
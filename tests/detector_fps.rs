@@ -0,0 +1,93 @@
+// tests/detector_fps.rs
+//
+// The imx500_yolov8 backend against a stand-in rpicam-vid that reports one
+// person every 33ms: detections come through the persistent service, and it
+// answers faster than starting src/imx500_fast_detector.py for every call,
+// as the backend used to. Both rates are printed
+// (`cargo test --test detector_fps -- --nocapture`). The stand-in starts at
+// once, where the real camera spends a while loading the model onto the
+// IMX500, so on a Pi the per-call figure is lower still.
+use anyhow::{ensure, Result};
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use scout_vision::config::VisionConfig;
+use scout_vision::vision::imx500_yolov8::{IMX500YoloV8, DETECTOR_SCRIPT};
+
+const CALLS: u32 = 20;
+
+const RPICAM_VID: &str = "#!/bin/sh
+while :; do
+  printf 'Number of objects detected: 1\\n[0] : person (0.87) @ 98304,73728 200x300\\n' >&2
+  sleep 0.033
+done
+";
+
+// Puts the stand-in rpicam-vid, and an rpicam-still that succeeds, ahead
+// of anything real on PATH.
+fn stand_in_camera() -> Result<()> {
+    let dir: PathBuf = std::env::temp_dir().join(format!("scout-detector-fps-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    for (name, script) in [("rpicam-vid", RPICAM_VID), ("rpicam-still", "#!/bin/sh\nexit 0\n")] {
+        let path = dir.join(name);
+        std::fs::write(&path, script)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    let path = std::env::var("PATH").unwrap_or_default();
+    std::env::set_var("PATH", format!("{}:{}", dir.display(), path));
+    Ok(())
+}
+
+// One call the old way: a fresh python3 and camera for a single request.
+fn call_per_process() -> Result<()> {
+    let mut child = Command::new("python3")
+        .arg(DETECTOR_SCRIPT)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child.stdin.take().expect("piped stdin").write_all(b"{\"id\": 1}\n")?;
+    let output = child.wait_with_output()?;
+    ensure!(String::from_utf8_lossy(&output.stdout).contains("\"person\""), "no detection from {}", DETECTOR_SCRIPT);
+    Ok(())
+}
+
+fn rate(elapsed: Duration) -> f64 {
+    CALLS as f64 / elapsed.as_secs_f64()
+}
+
+#[test]
+fn the_service_outpaces_a_process_per_call() -> Result<()> {
+    stand_in_camera()?;
+
+    let started = Instant::now();
+    for _ in 0..CALLS {
+        call_per_process()?;
+    }
+    let per_process = started.elapsed();
+
+    let detector = IMX500YoloV8::new(&VisionConfig::default())?;
+    let first = detector.capture_and_detect()?;
+    assert_eq!(first.len(), 1);
+    assert_eq!(first[0].class_name, "person");
+    assert!(detector.is_warm());
+
+    let started = Instant::now();
+    for _ in 0..CALLS {
+        assert_eq!(detector.capture_and_detect()?.len(), 1);
+    }
+    let persistent = started.elapsed();
+
+    println!(
+        "process per call: {:.1} ms/call, {:.1} FPS; persistent service: {:.1} ms/call, {:.1} FPS",
+        per_process.as_secs_f64() * 1000.0 / CALLS as f64,
+        rate(per_process),
+        persistent.as_secs_f64() * 1000.0 / CALLS as f64,
+        rate(persistent),
+    );
+    assert!(persistent < per_process, "{:?} persistent vs {:?} per process", persistent, per_process);
+    Ok(())
+}
@@ -0,0 +1,322 @@
+// tests/fake_bridge.rs
+//
+// Starts the vision system on the fake_bridge binary through the same
+// bridge command, stdout reading and supervision as the real camera
+// bridge, and checks what reaches the pipeline when the bridge writes
+// clean frames, torn and malformed lines, stops writing, floods garbage
+// or dies mid-line, and when it hands pictures over in shared memory,
+// JPEGs or raw frames. The binary is only built with `sim`.
+#![cfg(feature = "sim")]
+use anyhow::Result;
+use base64::Engine;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use scout_vision::config::{FrameTransport, PixelFormat, VisionConfig};
+use scout_vision::events::{Severity, EVENTS};
+use scout_vision::health::{ComponentHealth, ComponentState, HealthRegistry};
+use scout_vision::metrics::METRICS;
+use scout_vision::simulation::FrameSource;
+use scout_vision::vision::pixels;
+use scout_vision::vision::{Detection, NavigationAction, RuleWorld, VisionSystem};
+
+const SCENARIO: &str = r#"{
+  "frame_interval_ms": 20,
+  "loop": true,
+  "steps": [
+    {"repeat": 5, "detections": [{"class": "person", "class_id": 0, "conf": 0.9, "x": 280, "y": 100, "w": 80, "h": 300}]}
+  ]
+}"#;
+
+const WORLD: RuleWorld = RuleWorld { speed: 0.0, state: "autonomous" };
+
+// A scratch directory per test, since they run side by side.
+fn scratch(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("fake_bridge").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("scenario.json"), SCENARIO).unwrap();
+    dir
+}
+
+fn start(dir: &std::path::Path, flags: &[&str], config: VisionConfig) -> Result<(HealthRegistry, VisionSystem)> {
+    let mut command = vec![
+        env!("CARGO_BIN_EXE_fake_bridge").to_string(),
+        dir.join("scenario.json").display().to_string(),
+    ];
+    command.extend(flags.iter().map(|f| f.to_string()));
+    let health = HealthRegistry::new();
+    let vision = VisionSystem::new(&health, VisionConfig { bridge_command: command, ..config }, FrameSource::Bridge)?;
+    Ok((health, vision))
+}
+
+fn bridge_health(health: &HealthRegistry) -> ComponentHealth {
+    health.report().components.into_iter().find(|c| c.name == "vision_bridge").unwrap()
+}
+
+// Processes frames until one has detections.
+fn wait_for_detections(vision: &mut VisionSystem, within: Duration) -> Result<Vec<Detection>> {
+    let deadline = Instant::now() + within;
+    while Instant::now() < deadline {
+        let detections = vision.process_frame()?;
+        if vision.get_frame_capture_ms().is_some() && !detections.is_empty() {
+            return Ok(detections);
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    anyhow::bail!("no detections within {:?}", within)
+}
+
+fn wait_until(within: Duration, mut done: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + within;
+    while Instant::now() < deadline {
+        if done() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
+
+#[test]
+fn frames_survive_torn_and_malformed_lines() -> Result<()> {
+    for protocol in ["1", "2"] {
+        let dir = scratch(&format!("frames_v{}", protocol));
+        let (health, mut vision) = start(&dir, &["--protocol", protocol, "--partial-writes", "--malformed-every", "4"], VisionConfig::default())?;
+
+        let detections = wait_for_detections(&mut vision, Duration::from_secs(5))?;
+        assert_eq!(detections.len(), 1, "v{}", protocol);
+        assert_eq!(detections[0].class_name, "person", "v{}", protocol);
+        assert!(vision.get_frame_stamps().is_some_and(|s| s.emit_ms.is_some()), "v{}: frame has no emit stamp", protocol);
+
+        // One line in four is garbage, too few to restart the bridge.
+        let parse_errors = METRICS.bridge_parse_errors.get();
+        assert!(wait_until(Duration::from_secs(2), || bridge_health(&health).errors > 0), "v{}: malformed lines not counted", protocol);
+        assert!(wait_until(Duration::from_secs(2), || METRICS.bridge_parse_errors.get() > parse_errors), "v{}: no parse errors counted", protocol);
+        let bridge = bridge_health(&health);
+        assert_eq!(bridge.state, ComponentState::Healthy, "v{}: {:?}", protocol, bridge);
+    }
+    Ok(())
+}
+
+#[test]
+fn a_stalled_bridge_goes_stale_and_stops() -> Result<()> {
+    let dir = scratch("stall");
+    let config = VisionConfig { max_frame_age_ms: 200, ..VisionConfig::default() };
+    let (_health, mut vision) = start(&dir, &["--stall-after", "3"], config)?;
+
+    wait_for_detections(&mut vision, Duration::from_secs(5))?;
+    let stale = wait_until(Duration::from_secs(3), || {
+        vision.process_frame().is_ok() && vision.is_stale()
+    });
+    assert!(stale, "never went stale");
+    assert!(vision.get_navigation_command(&WORLD) >= NavigationAction::Stop);
+    Ok(())
+}
+
+#[test]
+fn a_bridge_flooding_garbage_is_restarted() -> Result<()> {
+    let dir = scratch("restart");
+    let state_file = dir.join("starts");
+    let config = VisionConfig { bridge_parse_window_s: 1.0, ..VisionConfig::default() };
+    let flags = ["--garbage-starts", "1", "--state-file", state_file.to_str().unwrap()];
+    let restarts_before = METRICS.bridge_restarts.get();
+    let (_health, mut vision) = start(&dir, &flags, config)?;
+
+    // The first start only ever writes garbage, so frames mean a restart.
+    let detections = wait_for_detections(&mut vision, Duration::from_secs(10))?;
+    assert_eq!(detections[0].class_name, "person");
+    assert_eq!(std::fs::read_to_string(&state_file)?.trim(), "2");
+    assert!(METRICS.bridge_restarts.get() > restarts_before);
+    Ok(())
+}
+
+#[test]
+fn a_bridge_dying_mid_line_fails_vision() -> Result<()> {
+    let dir = scratch("exit");
+    let (health, mut vision) = start(&dir, &["--exit-after", "3"], VisionConfig::default())?;
+
+    wait_for_detections(&mut vision, Duration::from_secs(5))?;
+    let failed = wait_until(Duration::from_secs(3), || bridge_health(&health).state == ComponentState::Failed);
+    let bridge = bridge_health(&health);
+    assert!(failed, "{:?}", bridge);
+    assert!(bridge.last_error.is_some_and(|e| e.contains("stdout closed")));
+    // The torn last line never made it through as a frame.
+    let frames = std::iter::from_fn(|| {
+        vision.process_frame().ok()?;
+        vision.get_frame_capture_ms()
+    }).count();
+    assert!(frames <= 3, "{} frames after the first", frames);
+    Ok(())
+}
+
+#[test]
+fn a_bridge_on_an_unsupported_version_is_turned_away() -> Result<()> {
+    let dir = scratch("unsupported");
+    let (health, mut vision) = start(&dir, &["--protocol", "3"], VisionConfig::default())?;
+
+    let failed = wait_until(Duration::from_secs(5), || bridge_health(&health).state == ComponentState::Failed);
+    let bridge = bridge_health(&health);
+    assert!(failed, "{:?}", bridge);
+    assert!(bridge.last_error.as_deref().is_some_and(|e| e.contains("protocol v3")), "{:?}", bridge);
+    let reported = EVENTS.recent().into_iter().any(|e| e.severity == Severity::Critical && e.source == "vision" && e.data["version"] == 3);
+    assert!(reported, "no event for the unsupported bridge");
+    // Nothing it went on to write was taken as a frame.
+    vision.process_frame()?;
+    assert_eq!(vision.get_frame_capture_ms(), None);
+    Ok(())
+}
+
+// Shared memory with nothing decoding the made-up pictures.
+fn shm_config(slots: usize) -> VisionConfig {
+    VisionConfig {
+        frame_transport: FrameTransport::Shm,
+        frame_slots: slots,
+        frame_slot_kb: 256,
+        gray_cache_enabled: false,
+        free_space_enabled: false,
+        ..VisionConfig::default()
+    }
+}
+
+// fake_bridge's pictures are one line naming the frame, over and over, so
+// one put together from two frames shows.
+fn check_picture(image_base64: &str, len: usize) -> Result<()> {
+    let image = base64::engine::general_purpose::STANDARD.decode(image_base64)?;
+    let line = image.split_inclusive(|&b| b == b'\n').next().unwrap_or_default();
+    let whole = image.len() == len && !line.is_empty() && image.iter().zip(line.iter().cycle()).all(|(a, b)| a == b);
+    anyhow::ensure!(whole, "picture of {} bytes starting {:?} is torn", image.len(), String::from_utf8_lossy(line));
+    Ok(())
+}
+
+// Processes frames for `run`, checking every picture, and returns how many
+// came whole and how many came without one.
+fn check_pictures(vision: &mut VisionSystem, run: Duration, len: usize) -> Result<(usize, usize)> {
+    let (mut whole, mut missing) = (0, 0);
+    let deadline = Instant::now() + run;
+    while Instant::now() < deadline {
+        vision.process_frame()?;
+        if vision.get_frame_capture_ms().is_none() {
+            std::thread::sleep(Duration::from_millis(1));
+            continue;
+        }
+        let image = vision.get_last_frame_image();
+        if image.is_empty() {
+            missing += 1;
+        } else {
+            check_picture(&image.get_jpeg_base64()?, len)?;
+            whole += 1;
+        }
+    }
+    Ok((whole, missing))
+}
+
+#[test]
+fn shm_frames_arrive_whole_from_a_fast_producer() -> Result<()> {
+    let dir = scratch("shm_stress");
+    let len = 150_000;
+    let before = METRICS.bridge_shm_frames.get();
+    let flags = ["--interval-ms", "1", "--image-bytes", "150000"];
+    let (health, mut vision) = start(&dir, &flags, shm_config(4))?;
+
+    let (whole, missing) = check_pictures(&mut vision, Duration::from_secs(3), len)?;
+    assert!(whole > 200, "only {} whole pictures", whole);
+    assert_eq!(missing, 0, "pictures lost from acked slots");
+    assert!(METRICS.bridge_shm_frames.get() > before, "nothing came through shared memory");
+    assert_eq!(bridge_health(&health).state, ComponentState::Healthy);
+    Ok(())
+}
+
+#[test]
+fn slots_rewritten_before_the_ack_are_never_read_torn() -> Result<()> {
+    let dir = scratch("shm_ignore_acks");
+    let len = 200_000;
+    let torn_before = METRICS.bridge_shm_torn.get();
+    let flags = ["--interval-ms", "0", "--image-bytes", "200000", "--shm-ignore-acks"];
+    let (_health, mut vision) = start(&dir, &flags, shm_config(2))?;
+
+    // The bridge outruns the rover, so most slots are overwritten before
+    // they are read. Those are dropped; whatever got through is one
+    // frame's.
+    let (whole, missing) = check_pictures(&mut vision, Duration::from_secs(3), len)?;
+    assert!(missing > 0, "no slot was overwritten ({} whole)", whole);
+    assert!(METRICS.bridge_shm_torn.get() > torn_before);
+    Ok(())
+}
+
+#[test]
+fn a_ring_that_cant_be_mapped_falls_back_to_base64() -> Result<()> {
+    let dir = scratch("shm_fallback");
+    let flags = ["--image-bytes", "20000", "--shm-bad-path"];
+    let (_health, mut vision) = start(&dir, &flags, shm_config(4))?;
+
+    // The first few frames may be sent before the bridge hears; after
+    // that every picture comes inline.
+    std::thread::sleep(Duration::from_millis(300));
+    let (whole, _) = check_pictures(&mut vision, Duration::from_secs(1), 20_000)?;
+    assert!(whole > 10, "only {} pictures after falling back", whole);
+    Ok(())
+}
+
+// The same frames as fake_bridge's yuv_for.
+fn yuv_for(frame_id: u32) -> Vec<u8> {
+    let mut frame = Vec::new();
+    for y in 0..480 {
+        frame.extend((0..640).map(|x| (x + y + frame_id as usize) as u8));
+    }
+    for y in 0..240 {
+        frame.extend((0..320).map(|x| (2 * x + y + frame_id as usize) as u8));
+    }
+    for y in 0..240 {
+        frame.extend((0..320).map(|x| (x + 3 * y + frame_id as usize) as u8));
+    }
+    frame
+}
+
+fn conversions() -> [u64; 3] {
+    ["rgb", "bgr", "jpeg"].map(|to| METRICS.frame_conversions.get(to))
+}
+
+#[test]
+fn raw_frames_are_only_converted_when_asked() -> Result<()> {
+    let dir = scratch("raw");
+    let raw_before = METRICS.bridge_raw_frames.get();
+    let config = VisionConfig { pixel_format: PixelFormat::Yuv420, frame_slot_kb: 512, gray_cache_enabled: true, ..shm_config(4) };
+    let (_health, mut vision) = start(&dir, &["--yuv420", "--image-bytes", "1000"], config)?;
+    let before = conversions();
+
+    let mut raw = 0;
+    let got = wait_until(Duration::from_secs(5), || {
+        let processed = vision.process_frame().is_ok() && vision.get_frame_capture_ms().is_some();
+        if processed && vision.get_last_frame_image().get_format() == Some(PixelFormat::Yuv420) {
+            raw += 1;
+        }
+        raw >= 10
+    });
+    assert!(got, "only {} raw frames", raw);
+    assert!(METRICS.bridge_raw_frames.get() > raw_before);
+    // The gray cache takes the Y plane as it is.
+    assert!(!vision.get_recent_gray_frames().is_empty());
+    assert_eq!(conversions(), before, "converted without being asked");
+
+    let image = vision.get_last_frame_image();
+    let frame_id = vision.get_last_frame_id().unwrap();
+    let rgb = image.get_rgb()?;
+    assert_eq!(rgb.as_raw(), &pixels::yuv420_to_rgb(&yuv_for(frame_id), 640, 480));
+    let jpeg = base64::engine::general_purpose::STANDARD.decode(&*image.get_jpeg_base64()?)?;
+    assert_eq!(&jpeg[..2], [0xff, 0xd8]);
+    assert_eq!(conversions(), [before[0] + 1, before[1], before[2] + 1]);
+    Ok(())
+}
+
+#[test]
+fn a_bridge_without_raw_frames_goes_on_sending_jpegs() -> Result<()> {
+    let dir = scratch("raw_unsupported");
+    let config = VisionConfig { pixel_format: PixelFormat::Yuv420, frame_slot_kb: 512, ..shm_config(4) };
+    let (_health, mut vision) = start(&dir, &["--image-bytes", "20000"], config)?;
+
+    let (whole, missing) = check_pictures(&mut vision, Duration::from_secs(1), 20_000)?;
+    assert!(whole > 10 && missing == 0, "{} whole, {} missing", whole, missing);
+    assert_eq!(vision.get_last_frame_image().get_format(), Some(PixelFormat::Jpeg));
+    Ok(())
+}
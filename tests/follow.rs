@@ -0,0 +1,269 @@
+// tests/follow.rs
+//
+// Follow-me against a scripted target walking ahead of the rover: the
+// planner closes to follow_distance_m and holds it there while the target
+// walks and turns, never comes within vision.person_stop_m, waits while
+// the track is briefly lost and picks it up again under a new id, and
+// gives up after follow_timeout_s. The WebSocket follow command designates
+// and clears the target on a whole rover.
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+
+use scout_vision::config::{ConfigStore, PlannerConfig, VisionConfig};
+use scout_vision::events::EVENTS;
+use scout_vision::health::HealthRegistry;
+use scout_vision::motor_control::MotorStatus;
+use scout_vision::pathfinding::{FollowState, NavigationCommand, PathPlanner};
+use scout_vision::rover::Rover;
+use scout_vision::simulation::{self, FrameSource};
+use scout_vision::state_machine::RoverState;
+use scout_vision::vision::{Detection, IMX500Detection, NavigationAction, FRAME_WIDTH_PX};
+use scout_vision::web::WebServer;
+
+const TICK: Duration = Duration::from_millis(100);
+
+// The motor loop faked as in tests/passages.rs, with a person walking
+// about on the same map: each tick the planner is shown where the person
+// is from where the rover now stands.
+struct Walk {
+    planner: PathPlanner,
+    config: PlannerConfig,
+    speed: f32,
+    heading: f32,
+    target: (f32, f32),
+}
+
+impl Walk {
+    fn new(config: PlannerConfig, target: (f32, f32)) -> Self {
+        let planner = PathPlanner::new(&HealthRegistry::new(), config.clone());
+        let mut walk = Self { planner, config, speed: 0.0, heading: 0.0, target };
+        walk.planner.update_pose(&walk.motors(), Duration::ZERO);
+        walk
+    }
+
+    fn motors(&self) -> MotorStatus {
+        MotorStatus { speed: self.speed, target_speed: self.speed, heading: self.heading, enabled: true, speed_cap: None, interlock: None, heading_hold: Default::default() }
+    }
+
+    fn distance(&self) -> f32 {
+        let pose = self.planner.get_pose();
+        (self.target.0 - pose.x).hypot(self.target.1 - pose.y)
+    }
+
+    // The person as the camera would see them, boxed where their bearing
+    // puts them in the frame.
+    fn sighting(&self, track_id: u32) -> Detection {
+        let pose = self.planner.get_pose();
+        let bearing = (self.target.0 - pose.x).atan2(self.target.1 - pose.y).to_degrees() - pose.heading_deg;
+        let center_x = (bearing / self.config.camera_hfov_deg + 0.5) * FRAME_WIDTH_PX;
+        Detection {
+            class_name: "person".into(),
+            confidence: 0.9,
+            raw_confidence: 0.9,
+            bbox: (center_x as i32 - 40, 100, 80, 300),
+            distance_estimate: self.distance(),
+            action: NavigationAction::Continue,
+            capture_ms: 0,
+            track_id: Some(track_id),
+            mask: None,
+            self_occlusion: false,
+            below_threshold: false,
+            ground_position: None,
+        }
+    }
+
+    fn step(&mut self, seen: &[Detection]) -> NavigationCommand {
+        self.planner.update_pose(&self.motors(), TICK);
+        self.planner.update_obstacles(seen);
+        let command = self.planner.get_navigation_command();
+        self.speed = 0.0;
+        match command {
+            NavigationCommand::Forward(speed) => self.speed = speed,
+            NavigationCommand::Backward(speed) => self.speed = -speed,
+            NavigationCommand::TurnRight(deg) => self.heading += deg.min(9.0),
+            NavigationCommand::TurnLeft(deg) => self.heading -= deg.min(9.0),
+            NavigationCommand::Stop => {}
+        }
+        command
+    }
+}
+
+fn config() -> PlannerConfig {
+    PlannerConfig { envelope_unknown_curve: Vec::new(), ..PlannerConfig::default() }
+}
+
+#[test]
+fn the_rover_closes_to_and_holds_the_following_distance() -> Result<()> {
+    let stop_m = VisionConfig::default().person_stop_m;
+    let config = config();
+    let (follow_m, slack_m) = (config.follow_distance_m, 1.0);
+    let mut walk = Walk::new(config, (0.0, 5.0));
+    let seen = walk.sighting(7);
+    walk.planner.update_obstacles(&[seen]);
+    walk.planner.set_follow(Some(7))?;
+
+    // Closes in from 5 m while the person stands still.
+    for _ in 0..100 {
+        let seen = walk.sighting(7);
+        walk.step(&[seen]);
+    }
+    let settled = walk.distance();
+    assert!(settled >= follow_m && settled <= follow_m + 0.3, "settled {:.2} m off", settled);
+
+    // Then walks ahead at half the follow speed and veers off to the right:
+    // the rover keeps up and turns with them.
+    let mut turned = false;
+    for tick in 0..300 {
+        let (dx, dy) = if tick < 150 { (0.0, 0.03) } else { (0.02, 0.02) };
+        walk.target = (walk.target.0 + dx, walk.target.1 + dy);
+        let seen = walk.sighting(7);
+        turned |= matches!(walk.step(&[seen]), NavigationCommand::TurnRight(_));
+        let distance = walk.distance();
+        assert!(distance > stop_m, "within {:.2} m of the person on tick {}", distance, tick);
+        if tick > 50 {
+            assert!(distance <= follow_m + slack_m, "fell {:.2} m behind on tick {}", distance, tick);
+        }
+    }
+    assert!(turned, "never turned after the person");
+    let status = walk.planner.get_follow_status().expect("stopped following");
+    assert_eq!((status.state, status.track_id), (FollowState::Locked, 7));
+    assert_eq!(status.target_distance_m, follow_m);
+
+    // Walking back toward the rover only ever stops it; it never drives on.
+    for _ in 0..30 {
+        walk.target.1 -= 0.05;
+        let seen = walk.sighting(7);
+        let command = walk.step(&[seen]);
+        if walk.distance() < follow_m {
+            assert!(!matches!(command, NavigationCommand::Forward(_)), "{:?} at {:.2} m", command, walk.distance());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn a_briefly_lost_target_is_waited_for_and_picked_up_again() -> Result<()> {
+    let mut walk = Walk::new(config(), (0.0, 3.0));
+    let seen = walk.sighting(3);
+    walk.planner.update_obstacles(&[seen]);
+    walk.planner.set_follow(Some(3))?;
+
+    // Out of sight for a few ticks: held still, and shown as lost.
+    for _ in 0..5 {
+        walk.target.1 += 0.02;
+        assert!(matches!(walk.step(&[]), NavigationCommand::Stop));
+    }
+    let status = walk.planner.get_follow_status().expect("gave up too soon");
+    assert_eq!(status.state, FollowState::Lost);
+    assert!(status.lost_s.is_some());
+
+    // Back under the new id the tracker gave them, near where they were.
+    let seen = walk.sighting(4);
+    walk.step(&[seen]);
+    let status = walk.planner.get_follow_status().expect("not following");
+    assert_eq!((status.state, status.track_id, status.lost_s), (FollowState::Locked, 4, None));
+
+    // Someone else of the same class well away from there isn't them.
+    walk.step(&[]);
+    walk.target = (1.5, 6.0);
+    let stranger = walk.sighting(9);
+    walk.step(&[stranger]);
+    assert_eq!(walk.planner.get_follow_status().map(|s| (s.state, s.track_id)), Some((FollowState::Lost, 4)));
+    assert!(!walk.planner.take_follow_ended());
+    Ok(())
+}
+
+#[test]
+fn following_gives_up_once_the_target_is_lost_for_too_long() -> Result<()> {
+    let mut walk = Walk::new(PlannerConfig { follow_timeout_s: 0.2, ..config() }, (0.0, 4.0));
+    let seen = walk.sighting(11);
+    walk.planner.update_obstacles(&[seen]);
+    walk.planner.set_follow(Some(11))?;
+    walk.step(&[]);
+    assert!(walk.planner.get_follow_status().is_some());
+
+    std::thread::sleep(Duration::from_millis(250));
+    walk.step(&[]);
+    assert!(walk.planner.get_follow_status().is_none());
+    assert!(walk.planner.take_follow_ended());
+    assert!(!walk.planner.take_follow_ended(), "ended twice");
+    let reported = EVENTS.recent().into_iter().any(|e| e.source == "planner" && e.data["track_id"] == 11);
+    assert!(reported, "no event for the lost target");
+
+    // A track that isn't in view can't be followed.
+    assert!(walk.planner.set_follow(Some(12)).is_err());
+    assert!(walk.planner.get_follow_status().is_none());
+    Ok(())
+}
+
+#[test]
+fn the_websocket_follow_command_designates_and_clears_the_target() -> Result<()> {
+    let config = ConfigStore::load(std::env::temp_dir().join("scout-follow-unused.toml"))?;
+    config.update(&serde_json::json!({
+        "selftest": { "enabled": false },
+        "control": { "start_autonomous": false },
+    })).map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+    let rover = Rover::new(Arc::new(config), FrameSource::Manual)?;
+    rover.start_subsystems();
+    while rover.startup.is_initializing() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    rover.selftest.start();
+    // A person well beyond the following distance.
+    let person = IMX500Detection { class: "person".into(), class_id: None, conf: 0.9, x: 290, y: 120, w: 60, h: 200, mask: None };
+    rover.vision.read().get_frame_sender().send(simulation::synthetic_frame(1, vec![person]));
+    let tick = || rover.tick(Duration::from_millis(20)).unwrap();
+    tick();
+    let track_id = rover.state.get_world().detections[0].track_id.expect("untracked");
+    assert_eq!(rover.state.get_state(), RoverState::Idle);
+
+    let web = Arc::new(WebServer::new(
+        rover.vision.clone(),
+        rover.path_planner.clone(),
+        rover.motor_controller.clone(),
+        rover.state.clone(),
+        rover.scheduler.clone(),
+        rover.health.clone(),
+        rover.config.clone(),
+    ));
+    let routes = web.routes();
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let mut client = warp::test::ws().path("/ws").handshake(routes).await?;
+        // Designating a target arms the rover to follow it.
+        client.send_text(serde_json::json!({ "cmd": "follow", "track_id": track_id }).to_string()).await;
+        wait_for(|| rover.path_planner.read().get_follow_status().is_some()).await;
+        let status = rover.path_planner.read().get_follow_status().unwrap();
+        assert_eq!((status.state, status.track_id), (FollowState::Locked, track_id));
+        wait_for(|| {
+            tick();
+            rover.state.get_state() == RoverState::Autonomous
+        }).await;
+
+        // Clearing it leaves the rover waiting rather than roaming off.
+        client.send_text(r#"{"cmd": "follow", "track_id": null}"#).await;
+        wait_for(|| rover.path_planner.read().get_follow_status().is_none()).await;
+        wait_for(|| {
+            tick();
+            rover.state.get_state() == RoverState::Idle
+        }).await;
+
+        // An id that isn't in view is refused and changes nothing.
+        client.send_text(r#"{"cmd": "follow", "track_id": 999}"#).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(rover.path_planner.read().get_follow_status().is_none());
+        tick();
+        assert_eq!(rover.state.get_state(), RoverState::Idle);
+        anyhow::Ok(())
+    })
+}
+
+async fn wait_for(mut done: impl FnMut() -> bool) {
+    for _ in 0..200 {
+        if done() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("timed out");
+}
@@ -0,0 +1,190 @@
+// tests/hazards.rs
+//
+// External hazards: the bearer token and rate limit on POST /api/hazard,
+// reports checked and expiring on the rover clock, and how a hazard weighs
+// against a clear view in autonomous mode: a stop ahead stops the rover,
+// one behind or any caution holds it to the caution speed, and it drives
+// on again once they expire.
+use anyhow::Result;
+use std::sync::mpsc;
+use std::time::Duration;
+use warp::http::StatusCode;
+
+use scout_vision::clock::CLOCK;
+use scout_vision::hazards::{HazardAction, HazardError, HazardReport, ReportOutcome, HAZARDS};
+
+mod common;
+use common::{Sim, SERIAL};
+
+const TICK: Duration = Duration::from_millis(20);
+const TOKEN_ENV: &str = "SCOUT_TEST_HAZARD_TOKEN";
+const TOKEN: &str = "bark-bark-bark";
+const CRUISE_M_S: f32 = 0.5;
+const CAUTION_M_S: f32 = 0.15;
+
+impl Sim {
+    fn new(hazards: serde_json::Value) -> Result<Self> {
+        CLOCK.set_manual(CLOCK.now_ms());
+        std::env::set_var(TOKEN_ENV, TOKEN);
+        Sim::boot("hazards", &[
+            serde_json::json!({
+                "control": { "start_autonomous": true },
+                "planner": { "cruise_speed": CRUISE_M_S },
+                "hazards": { "token_env": TOKEN_ENV, "caution_speed_m_s": CAUTION_M_S },
+            }),
+            serde_json::json!({ "hazards": hazards }),
+        ])
+    }
+
+    // A tick with a clear view, `dt` on from the last.
+    fn step(&mut self, dt: Duration) -> Result<()> {
+        self.send(&[]);
+        CLOCK.advance(dt);
+        self.rover.tick(dt)
+    }
+
+    // Ticks until the target speed settles, and returns it.
+    fn settle(&mut self) -> Result<f32> {
+        for _ in 0..10 {
+            self.step(TICK)?;
+        }
+        Ok(self.get_target_speed())
+    }
+}
+
+fn report(source: &str, kind: &str, action: HazardAction, bearing_deg: Option<f32>, ttl_s: f32) -> HazardReport {
+    HazardReport { source: source.to_string(), kind: kind.to_string(), action, bearing_deg, ttl_s }
+}
+
+#[test]
+fn stop_ahead_stops_caution_and_behind_slow_then_they_expire() -> Result<()> {
+    let _serial = SERIAL.lock();
+    let mut sim = Sim::new(serde_json::json!({}))?;
+    for _ in 0..100 {
+        sim.step(TICK)?;
+    }
+    assert!((sim.get_target_speed() - CRUISE_M_S).abs() < 1e-3, "cruising at {}", sim.get_target_speed());
+
+    // A bark behind: a stop hazard, but not in the way.
+    HAZARDS.report(report("mic", "dog_bark", HazardAction::Stop, Some(175.0), 1.0)).unwrap();
+    assert!(sim.settle()? <= CAUTION_M_S + 1e-3, "{} m/s with a hazard behind", sim.get_target_speed());
+    let world = sim.rover.state.get_world();
+    assert_eq!(world.hazards.len(), 1);
+    assert_eq!((world.hazards[0].action, world.hazards[0].effect), (HazardAction::Stop, HazardAction::Caution));
+
+    // One ahead, and one that can't say where it is, each stop the rover.
+    for bearing in [Some(-30.0), None] {
+        HAZARDS.report(report("lidar", "unknown", HazardAction::Stop, bearing, 0.5)).unwrap();
+        assert_eq!(sim.settle()?, 0.0, "still driving with a stop at {:?}", bearing);
+        sim.step(Duration::from_millis(500))?;
+    }
+
+    // The bark outlasts the stops; once it expires too the rover cruises.
+    HAZARDS.report(report("mic", "dog_bark", HazardAction::Caution, None, 0.3)).unwrap();
+    assert!(sim.settle()? <= CAUTION_M_S + 1e-3);
+    sim.step(Duration::from_millis(300))?;
+    assert!(sim.rover.state.get_world().hazards.is_empty());
+    assert!((sim.settle()? - CRUISE_M_S).abs() < 1e-3, "{} m/s after every hazard expired", sim.get_target_speed());
+    Ok(())
+}
+
+#[test]
+fn reports_are_checked_refreshed_and_capped() -> Result<()> {
+    let _serial = SERIAL.lock();
+    let _sim = Sim::new(serde_json::json!({ "max_active": 2, "max_ttl_s": 10.0, "burst": 20 }))?;
+    for bad in [
+        report("", "bark", HazardAction::Caution, None, 1.0),
+        report("mic", "dog bark", HazardAction::Caution, None, 1.0),
+        report("mic", "bark", HazardAction::Caution, None, 0.0),
+        report("mic", "bark", HazardAction::Caution, None, 11.0),
+        report("mic", "bark", HazardAction::Caution, Some(f32::NAN), 1.0),
+    ] {
+        assert!(matches!(HAZARDS.report(bad.clone()), Err(HazardError::Invalid(_))), "{:?} accepted", bad);
+    }
+
+    assert_eq!(HAZARDS.report(report("mic", "bark", HazardAction::Caution, Some(540.0), 2.0)), Ok(ReportOutcome::Accepted));
+    assert_eq!(HAZARDS.get_active()[0].bearing_deg, Some(180.0));
+    CLOCK.advance(Duration::from_secs(1));
+    // The same source and type again is the same hazard, lasting anew.
+    assert_eq!(HAZARDS.report(report("mic", "bark", HazardAction::Stop, None, 5.0)), Ok(ReportOutcome::Refreshed));
+    let active = HAZARDS.get_active();
+    assert_eq!((active.len(), active[0].effect, active[0].remaining_s), (1, HazardAction::Stop, 5.0));
+
+    assert_eq!(HAZARDS.report(report("mic", "glass", HazardAction::Caution, None, 1.0)), Ok(ReportOutcome::Accepted));
+    assert_eq!(HAZARDS.report(report("radar", "wall", HazardAction::Caution, None, 1.0)), Err(HazardError::Full));
+    CLOCK.advance(Duration::from_millis(1001));
+    assert_eq!(HAZARDS.report(report("radar", "wall", HazardAction::Caution, None, 1.0)), Ok(ReportOutcome::Accepted));
+
+    let assessment = HAZARDS.assess();
+    assert_eq!(assessment.effect, Some(HazardAction::Stop));
+    assert_eq!(assessment.hazards.len(), 2);
+    CLOCK.advance(Duration::from_secs(5));
+    let assessment = HAZARDS.assess();
+    assert_eq!((assessment.effect, assessment.hazards.len()), (None, 0));
+    Ok(())
+}
+
+#[test]
+fn each_source_is_rate_limited() -> Result<()> {
+    let _serial = SERIAL.lock();
+    let _sim = Sim::new(serde_json::json!({ "rate_per_s": 2.0, "burst": 3 }))?;
+    let bark = |source: &str| HAZARDS.report(report(source, "bark", HazardAction::Caution, None, 1.0));
+    for _ in 0..3 {
+        assert!(bark("mic").is_ok());
+    }
+    let Err(HazardError::RateLimited(retry_s)) = bark("mic") else { panic!("a fourth report at once went through") };
+    assert!((retry_s - 0.5).abs() < 1e-3, "retry in {}", retry_s);
+    // Another source has a bucket of its own.
+    assert!(bark("radar").is_ok());
+    CLOCK.advance(Duration::from_millis(500));
+    assert!(bark("mic").is_ok());
+    assert!(matches!(bark("mic"), Err(HazardError::RateLimited(_))));
+
+    // Sources in the process are polled each tick and limited the same way.
+    let (sender, receiver) = mpsc::channel();
+    HAZARDS.add_source(Box::new(receiver));
+    for i in 0..5 {
+        sender.send(report("classifier", &format!("sound{}", i), HazardAction::Caution, None, 1.0))?;
+    }
+    let kinds: Vec<_> = HAZARDS.assess().hazards.into_iter().filter(|h| h.source == "classifier").map(|h| h.kind).collect();
+    assert_eq!(kinds, ["sound0", "sound1", "sound2"]);
+    Ok(())
+}
+
+#[test]
+fn posting_needs_the_token() -> Result<()> {
+    let _serial = SERIAL.lock();
+    let sim = Sim::new(serde_json::json!({ "rate_per_s": 0.5, "burst": 1 }))?;
+    let routes = sim.web().routes();
+    let body = serde_json::json!({ "source": "mic", "type": "dog_bark", "bearing_deg": 180, "ttl_s": 5 });
+    let post = |authorization: Option<&str>| {
+        let request = warp::test::request().method("POST").path("/api/hazard").json(&body);
+        match authorization {
+            Some(authorization) => request.header("authorization", authorization),
+            None => request,
+        }
+    };
+    let bearer = format!("Bearer {}", TOKEN);
+
+    tokio::runtime::Runtime::new()?.block_on(async {
+        assert_eq!(post(None).reply(&routes).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(post(Some("Bearer wrong")).reply(&routes).await.status(), StatusCode::UNAUTHORIZED);
+        assert!(HAZARDS.get_active().is_empty());
+
+        let accepted = post(Some(&bearer)).reply(&routes).await;
+        assert_eq!(accepted.status(), StatusCode::CREATED);
+        let accepted: serde_json::Value = serde_json::from_slice(accepted.body())?;
+        assert_eq!(accepted["result"], "accepted");
+        assert_eq!(accepted["hazards"][0]["effect"], "caution");
+
+        let limited = post(Some(&bearer)).reply(&routes).await;
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        let limited: serde_json::Value = serde_json::from_slice(limited.body())?;
+        assert!(limited["retry_after_s"].as_f64().is_some_and(|s| s > 1.9), "{}", limited);
+
+        let listed = warp::test::request().path("/api/hazards").reply(&routes).await;
+        let listed: serde_json::Value = serde_json::from_slice(listed.body())?;
+        assert_eq!(listed["hazards"][0]["source"], "mic");
+        Ok(())
+    })
+}
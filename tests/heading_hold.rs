@@ -0,0 +1,135 @@
+// tests/heading_hold.rs
+//
+// Drives the motor controller's wheel speeds into a differential-drive
+// plant whose right wheel gives a little less than asked and which gets
+// knocked off line by bumps, with and without heading hold, and checks
+// that holding the heading keeps the rover much closer to the line it
+// started on.
+use anyhow::Result;
+use std::time::Duration;
+
+use scout_vision::config::MotorConfig;
+use scout_vision::health::HealthRegistry;
+use scout_vision::motor_control::MotorController;
+
+const TICK: Duration = Duration::from_millis(20);
+const TRACK_M: f32 = 0.3;
+const SPEED_M_S: f32 = 0.5;
+const DRIVE_S: f32 = 20.0;
+// What the right wheel gives of what it is asked for.
+const RIGHT_WHEEL_GAIN: f32 = 0.98;
+// A bump every this many ticks turns the rover up to BUMP_DEG either way.
+const BUMP_EVERY: u32 = 12;
+const BUMP_DEG: f32 = 3.0;
+
+// Where the rover is, +y along the line it started on and +x to its
+// right, and which way it faces, clockwise from +y.
+#[derive(Debug, Default)]
+struct Plant {
+    x: f32,
+    y: f32,
+    heading_deg: f32,
+    rate_dps: f32,
+    ticks: u32,
+    seed: u32,
+}
+
+impl Plant {
+    fn new() -> Self {
+        Self { seed: 12345, ..Default::default() }
+    }
+
+    // -1 to 1, the same sequence every run.
+    fn noise(&mut self) -> f32 {
+        self.seed = self.seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (self.seed >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+    }
+
+    fn step(&mut self, (left, right): (f32, f32)) {
+        let dt = TICK.as_secs_f32();
+        let right = right * RIGHT_WHEEL_GAIN;
+        let before = self.heading_deg;
+        self.heading_deg += ((left - right) / TRACK_M).to_degrees() * dt;
+        self.ticks += 1;
+        if self.ticks % BUMP_EVERY == 0 {
+            self.heading_deg += BUMP_DEG * self.noise();
+        }
+        self.rate_dps = (self.heading_deg - before) / dt;
+        let speed = (left + right) / 2.0;
+        self.x += speed * self.heading_deg.to_radians().sin() * dt;
+        self.y += speed * self.heading_deg.to_radians().cos() * dt;
+    }
+}
+
+// How far off the line the rover ends up, and the largest correction
+// heading hold asked for on the way.
+fn drive(heading_hold: bool) -> Result<(f32, f32)> {
+    let limits = MotorConfig { heading_hold, ..Default::default() };
+    let max_correction = limits.heading_max_correction;
+    let mut motors = MotorController::new(&HealthRegistry::new(), limits)?;
+    let mut plant = Plant::new();
+    let mut largest = 0.0f32;
+    for _ in 0..(DRIVE_S / TICK.as_secs_f32()) as u32 {
+        motors.move_forward(SPEED_M_S);
+        // An IMU a little noisier than the plant.
+        let measured = (plant.heading_deg + 0.1 * plant.noise(), plant.rate_dps);
+        motors.set_measured_heading(Some(measured));
+        motors.advance(TICK);
+        let correction = motors.get_status().heading_hold.correction;
+        assert!(correction.abs() <= max_correction + 1e-6, "correction {} over the cap", correction);
+        largest = largest.max(correction.abs());
+        plant.step(motors.get_wheel_speeds());
+    }
+    assert!(plant.y > 0.8 * SPEED_M_S * DRIVE_S, "only got {:.2} m along", plant.y);
+    Ok((plant.x.abs(), largest))
+}
+
+#[test]
+fn heading_hold_cuts_lateral_drift() -> Result<()> {
+    let (open_loop, none) = drive(false)?;
+    let (held, largest) = drive(true)?;
+    eprintln!("Lateral drift over {} m: {:.2} m open loop, {:.2} m holding heading", SPEED_M_S * DRIVE_S, open_loop, held);
+    assert_eq!(none, 0.0);
+    assert!(largest > 0.0);
+    assert!(open_loop > 1.0, "the plant hardly drifted ({:.2} m)", open_loop);
+    assert!(held < open_loop / 5.0 && held < 0.3, "held {:.2} m against {:.2} m open loop", held, open_loop);
+    Ok(())
+}
+
+#[test]
+fn turns_and_reversing_let_go_of_the_heading() -> Result<()> {
+    let mut motors = MotorController::new(&HealthRegistry::new(), MotorConfig::default())?;
+    let tick = |motors: &mut MotorController, heading: f32| {
+        motors.set_measured_heading(Some((heading, 0.0)));
+        motors.advance(TICK);
+        motors.get_status().heading_hold
+    };
+
+    motors.move_forward(SPEED_M_S);
+    assert_eq!(tick(&mut motors, 10.0).setpoint_deg, Some(10.0));
+    let hold = tick(&mut motors, 8.0);
+    assert_eq!(hold.setpoint_deg, Some(10.0));
+    assert!(hold.error_deg > 1.9 && hold.correction > 0.0, "{:?}", hold);
+
+    motors.turn_right(30.0);
+    let hold = tick(&mut motors, 20.0);
+    assert_eq!((hold.setpoint_deg, hold.correction), (None, 0.0));
+    assert!(!motors.is_still());
+
+    // A new setpoint once driving forward again.
+    motors.move_forward(SPEED_M_S);
+    assert_eq!(tick(&mut motors, 40.0).setpoint_deg, Some(40.0));
+
+    motors.move_backward(SPEED_M_S);
+    let hold = tick(&mut motors, 30.0);
+    assert_eq!((hold.setpoint_deg, hold.correction), (None, 0.0));
+
+    // Without a heading there is nothing to hold.
+    motors.move_forward(SPEED_M_S);
+    motors.set_measured_heading(None);
+    motors.advance(TICK);
+    let status = motors.get_status();
+    assert_eq!((status.heading_hold.setpoint_deg, status.heading_hold.correction), (None, 0.0));
+    assert_eq!(motors.get_wheel_speeds(), (status.speed, status.speed));
+    Ok(())
+}
@@ -0,0 +1,145 @@
+// tests/heatmap.rs
+//
+// The detection heatmap's arithmetic: where a box is counted, how counts
+// fade with the half-life, and that what reaches it through the vision
+// system is what the filters left.
+use scout_vision::config::{HeatmapConfig, VisionConfig};
+use scout_vision::health::HealthRegistry;
+use scout_vision::simulation::{self, FrameSource};
+use scout_vision::vision::heatmap::{decay_factor, Heatmap};
+use scout_vision::vision::{Detection, IMX500Detection, NavigationAction, VisionSystem};
+
+const HALF_LIFE_S: f32 = 60.0;
+
+fn config(boxes: bool) -> HeatmapConfig {
+    HeatmapConfig { half_life_s: HALF_LIFE_S, boxes, ..HeatmapConfig::default() }
+}
+
+fn detection(class: &str, bbox: (i32, i32, i32, i32)) -> Detection {
+    Detection {
+        class_name: class.to_string(),
+        confidence: 0.9,
+        raw_confidence: 0.9,
+        bbox,
+        distance_estimate: 3.0,
+        action: NavigationAction::Continue,
+        capture_ms: 0,
+        track_id: None,
+        mask: None,
+        self_occlusion: false,
+        below_threshold: false,
+        ground_position: None,
+    }
+}
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4
+}
+
+#[test]
+fn decay_halves_every_half_life() {
+    assert_eq!(decay_factor(HALF_LIFE_S, 0), 1.0);
+    assert!(close(decay_factor(HALF_LIFE_S, 60_000), 0.5));
+    assert!(close(decay_factor(HALF_LIFE_S, 120_000), 0.25));
+    assert!(close(decay_factor(HALF_LIFE_S, 30_000), 0.5f32.sqrt()));
+    // Fading in steps comes to the same as all at once.
+    let stepped: f32 = (0..60).map(|_| decay_factor(HALF_LIFE_S, 1000)).product();
+    assert!(close(stepped, 0.5), "{}", stepped);
+}
+
+#[test]
+fn centres_land_in_their_cell() {
+    let mut heatmap = Heatmap::new(&config(false));
+    // 640x480 over 32x24 makes 20 px cells; this centre is at (330, 250).
+    heatmap.add(&[detection("person", (300, 200, 60, 100))], 1000);
+    let map = heatmap.snapshot(None, 1000);
+    assert_eq!((map.columns, map.rows, map.cells.len(), map.cells[0].len()), (32, 24, 24, 32));
+    assert_eq!(map.cells[12][16], 1.0);
+    assert_eq!((map.min, map.max, map.total, map.detections), (0.0, 1.0, 1.0, 1));
+
+    // Boxes hanging off the frame count at its edge.
+    heatmap.add(&[detection("person", (-100, -100, 40, 40)), detection("person", (630, 470, 40, 40))], 1000);
+    let map = heatmap.snapshot(None, 1000);
+    assert_eq!((map.cells[0][0], map.cells[23][31]), (1.0, 1.0));
+}
+
+#[test]
+fn counts_fade_between_and_after_detections() {
+    let mut heatmap = Heatmap::new(&config(false));
+    let at = |x| detection("box", (x, 0, 10, 10));
+    heatmap.add(&[at(0)], 0);
+    heatmap.add(&[at(0)], 60_000);
+    // One half-life on, the first has faded to a half and the second not.
+    assert!(close(heatmap.snapshot(None, 60_000).cells[0][0], 1.5));
+    // Reading fades to the time asked for without changing what is kept.
+    assert!(close(heatmap.snapshot(None, 120_000).cells[0][0], 0.75));
+    assert!(close(heatmap.snapshot(None, 60_000).cells[0][0], 1.5));
+    // An hour of nothing leaves next to nothing.
+    assert!(heatmap.snapshot(None, 60_000 + 3_600_000).max < 1e-15);
+    // A frame from earlier doesn't undo the fading.
+    heatmap.add(&[at(40)], 120_000);
+    heatmap.add(&[at(40)], 90_000);
+    let map = heatmap.snapshot(None, 120_000);
+    assert!(close(map.cells[0][0], 0.75) && close(map.cells[0][2], 2.0), "{:?}", &map.cells[0][..3]);
+}
+
+#[test]
+fn boxes_spread_one_detection_over_what_they_cover() {
+    let mut heatmap = Heatmap::new(&config(true));
+    // Two cells wide and one and a half high, from (10, 10).
+    heatmap.add(&[detection("box", (10, 10, 40, 30))], 0);
+    let map = heatmap.snapshot(None, 0);
+    assert!(close(map.total, 1.0), "{}", map.total);
+    let area = 40.0 * 30.0;
+    assert!(close(map.cells[0][0], 100.0 / area));
+    assert!(close(map.cells[0][1], 200.0 / area));
+    assert!(close(map.cells[0][2], 100.0 / area));
+    assert!(close(map.cells[1][1], 400.0 / area));
+    assert!(close(map.cells[1][2], 200.0 / area));
+    assert_eq!(map.cells[2][0], 0.0);
+}
+
+#[test]
+fn classes_have_grids_of_their_own() {
+    let mut heatmap = Heatmap::new(&config(false));
+    heatmap.add(&[detection("person", (0, 0, 10, 10)), detection("dog", (620, 460, 10, 10))], 0);
+    heatmap.add(&[detection("person", (0, 0, 10, 10))], 0);
+    let person = heatmap.snapshot(Some("person"), 0);
+    assert_eq!((person.cells[0][0], person.cells[23][31], person.detections), (2.0, 0.0, 2));
+    let all = heatmap.snapshot(None, 0);
+    assert_eq!((all.cells[0][0], all.cells[23][31], all.detections), (2.0, 1.0, 3));
+    assert_eq!(all.classes, ["dog", "person"]);
+    let cat = heatmap.snapshot(Some("cat"), 0);
+    assert_eq!((cat.max, cat.detections, cat.class.as_deref()), (0.0, 0, Some("cat")));
+
+    heatmap.clear();
+    let all = heatmap.snapshot(None, 0);
+    assert_eq!((all.total, all.detections, all.classes.len()), (0.0, 0, 0));
+}
+
+#[test]
+fn new_grid_size_starts_over() {
+    let mut heatmap = Heatmap::new(&config(false));
+    heatmap.add(&[detection("box", (0, 0, 10, 10))], 0);
+    heatmap.apply_config(&HeatmapConfig { half_life_s: 120.0, ..config(false) });
+    assert_eq!(heatmap.snapshot(None, 0).total, 1.0);
+    heatmap.apply_config(&HeatmapConfig { columns: 8, rows: 6, ..config(false) });
+    let map = heatmap.snapshot(None, 0);
+    assert_eq!((map.cells.len(), map.cells[0].len(), map.total), (6, 8, 0.0));
+}
+
+#[test]
+fn only_detections_the_filters_leave_are_counted() {
+    let config = VisionConfig { ignored_classes: vec!["cat".to_string()], ..VisionConfig::default() };
+    let mut vision = VisionSystem::new(&HealthRegistry::new(), config, FrameSource::Manual).unwrap();
+    let seen = |class: &str, x| IMX500Detection { class: class.to_string(), class_id: None, conf: 0.9, x, y: 0, w: 10, h: 10, mask: None };
+    vision.get_frame_sender().send(simulation::synthetic_frame(1, vec![seen("person", 0), seen("cat", 620)]));
+    vision.process_frame().unwrap();
+
+    let map = vision.get_heatmap(None);
+    assert_eq!(map.detections, 1);
+    assert!(map.cells[0][0] > 0.99 && map.cells[0][31] == 0.0, "{:?}", map.cells[0]);
+    assert!(vision.get_heatmap(Some("cat")).classes == ["person"]);
+    vision.clear_heatmap();
+    assert_eq!(vision.get_heatmap(None).detections, 0);
+}
@@ -0,0 +1,106 @@
+// tests/library.rs
+//
+// Each subsystem built from the library on its own, as another tool would
+// embed it, with no camera bridge: vision on a manual frame source, the
+// planner and motors fed its detections, and the web routes over a rover
+// assembled from the same parts.
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use warp::http::StatusCode;
+
+use scout_vision::config::{ConfigStore, MotorConfig, PlannerConfig, VisionConfig};
+use scout_vision::health::HealthRegistry;
+use scout_vision::motor_control::MotorController;
+use scout_vision::pathfinding::PathPlanner;
+use scout_vision::rover::Rover;
+use scout_vision::simulation::{self, FrameSource};
+use scout_vision::vision::{BridgeFrame, IMX500Detection, NavigationAction, RuleWorld, VisionSystem};
+use scout_vision::web::WebServer;
+
+// A person straight ahead, 1.8 m off: close enough to stop for.
+fn person() -> IMX500Detection {
+    IMX500Detection { class: "person".into(), class_id: None, conf: 0.9, x: 280, y: 4, w: 80, h: 472, mask: None }
+}
+
+#[test]
+fn vision_planner_and_motors_run_without_a_bridge() -> Result<()> {
+    let health = HealthRegistry::new();
+    let mut vision = VisionSystem::new(&health, VisionConfig::default(), FrameSource::Manual)?;
+    let mut planner = PathPlanner::new(&health, PlannerConfig::default());
+    let mut motors = MotorController::new(&health, MotorConfig::default())?;
+
+    // No frame yet: nothing seen, nothing new.
+    assert!(vision.process_frame()?.is_empty());
+    assert_eq!(vision.get_frame_capture_ms(), None);
+
+    vision.get_frame_sender().send(simulation::synthetic_frame(1, vec![person()]));
+    let detections = vision.process_frame()?;
+    assert_eq!(detections.len(), 1);
+    assert_eq!(detections[0].class_name, "person");
+    assert!(vision.get_frame_capture_ms().is_some());
+    let world = RuleWorld { speed: 0.0, state: "autonomous" };
+    assert!(vision.get_navigation_command(&world) >= NavigationAction::Stop);
+
+    planner.update_obstacles(&detections);
+    assert_eq!(planner.get_status().obstacles_count, 1);
+
+    motors.move_forward(0.2);
+    for _ in 0..20 {
+        motors.advance(Duration::from_millis(50));
+    }
+    assert!(motors.get_speed() > 0.0);
+    motors.emergency_stop();
+    assert_eq!(motors.get_status().target_speed, 0.0);
+    Ok(())
+}
+
+#[test]
+fn bridge_frames_parse_from_the_bridges_json() -> Result<()> {
+    let line = serde_json::json!({
+        "frame_id": 7,
+        "jpeg_base64": "",
+        "timestamp": 1.5,
+        "imx500_basic": [{ "class": "person", "conf": 0.8, "x": 1, "y": 2, "w": 3, "h": 4 }],
+    });
+    let frame: BridgeFrame = serde_json::from_value(line)?;
+    assert_eq!(frame.frame_id, 7);
+    assert_eq!(frame.imx500_basic[0].class, "person");
+    assert_eq!(frame.imx500_basic[0].class_id, None);
+    assert!(frame.imx500_basic[0].mask.is_none());
+    Ok(())
+}
+
+#[test]
+fn the_web_routes_serve_a_rover_built_from_the_library() -> Result<()> {
+    let config = ConfigStore::load(std::env::temp_dir().join("scout-library-unused.toml"))?;
+    config.update(&serde_json::json!({ "selftest": { "enabled": false } })).map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+    let rover = Rover::new(Arc::new(config), FrameSource::Manual)?;
+    rover.start_subsystems();
+    while rover.startup.is_initializing() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    rover.vision.read().get_frame_sender().send(simulation::synthetic_frame(1, vec![person()]));
+    rover.tick(Duration::from_millis(20))?;
+
+    let web = Arc::new(WebServer::new(
+        rover.vision.clone(),
+        rover.path_planner.clone(),
+        rover.motor_controller.clone(),
+        rover.state.clone(),
+        rover.scheduler.clone(),
+        rover.health.clone(),
+        rover.config.clone(),
+    ));
+    let routes = web.routes();
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let world = warp::test::request().path("/api/world").reply(&routes).await;
+        assert_eq!(world.status(), StatusCode::OK);
+        let world: serde_json::Value = serde_json::from_slice(world.body())?;
+        assert_eq!(world["detections"][0]["class_name"], "person");
+
+        let status = warp::test::request().path("/api/status").reply(&routes).await;
+        assert_eq!(status.status(), StatusCode::OK);
+        Ok(())
+    })
+}
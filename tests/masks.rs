@@ -0,0 +1,161 @@
+// tests/masks.rs
+//
+// Run-length masks: cells encoded and decoded back unchanged, on random
+// grids and at the edges (empty, all object, all background), runs that
+// don't cover the grid turned away, and the distance taken from a mask's
+// fill only while it roughly agrees with the box, falling back to the box
+// when the two disagree wildly, both in the estimator and through vision.
+use anyhow::Result;
+
+use scout_vision::config::VisionConfig;
+use scout_vision::health::HealthRegistry;
+use scout_vision::metrics::METRICS;
+use scout_vision::simulation::{self, FrameSource};
+use scout_vision::vision::{ActionPolicy, ConfidenceCurve, Detection, DetectionMask, DistanceEstimator, IMX500Detection, VisionSystem};
+
+// xorshift, so the grids are the same every run.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+// A width x height mask with the first `object` cells, row by row, the
+// object: its fill is object / (width x height).
+fn filled(width: u32, height: u32, object: usize) -> DetectionMask {
+    let cells: Vec<bool> = (0..(width * height) as usize).map(|i| i < object).collect();
+    DetectionMask::encode(width, height, &cells)
+}
+
+#[test]
+fn random_masks_survive_a_round_trip() {
+    let mut rng = Rng(0x5eed_1234_abcd);
+    for _ in 0..500 {
+        let (width, height) = (1 + rng.below(40) as u32, 1 + rng.below(40) as u32);
+        // From speckled to a few long runs.
+        let flip = 1 + rng.below(20);
+        let mut cell = rng.below(2) == 0;
+        let cells: Vec<bool> = (0..width * height)
+            .map(|_| {
+                if rng.below(flip) == 0 {
+                    cell = !cell;
+                }
+                cell
+            })
+            .collect();
+        let mask = DetectionMask::encode(width, height, &cells);
+        mask.check().unwrap();
+        assert_eq!(mask.decode(), cells, "{}x{}", width, height);
+        // Runs alternate, so only the leading background run can be empty.
+        assert!(mask.counts.iter().skip(1).all(|&c| c > 0), "{:?}", mask.counts);
+        let object = cells.iter().filter(|&&c| c).count();
+        assert!((mask.get_fill() - object as f32 / cells.len() as f32).abs() < 1e-6);
+
+        // And through the JSON the bridge sends.
+        let json = serde_json::to_string(&mask).unwrap();
+        assert_eq!(serde_json::from_str::<DetectionMask>(&json).unwrap(), mask);
+    }
+}
+
+#[test]
+fn edge_masks_encode_as_expected() {
+    let all_object = DetectionMask::encode(3, 2, &[true; 6]);
+    assert_eq!(all_object.counts, [0, 6]);
+    assert_eq!(all_object.get_fill(), 1.0);
+    let all_background = DetectionMask::encode(3, 2, &[false; 6]);
+    assert_eq!(all_background.counts, [6]);
+    assert_eq!(all_background.get_fill(), 0.0);
+    let checker = DetectionMask::encode(2, 2, &[true, false, false, true]);
+    assert_eq!(checker.counts, [0, 1, 2, 1]);
+    assert_eq!(checker.decode(), [true, false, false, true]);
+
+    let empty = DetectionMask::encode(0, 0, &[]);
+    assert_eq!(empty.counts, [0]);
+    assert!(empty.check().is_ok() && empty.decode().is_empty());
+    assert_eq!(empty.get_fill(), 0.0);
+}
+
+#[test]
+fn runs_that_dont_cover_the_grid_are_refused() {
+    for counts in [vec![1, 2], vec![4, 4, 4], vec![]] {
+        let mask = DetectionMask { width: 3, height: 3, counts: counts.clone() };
+        let error = mask.check().unwrap_err().to_string();
+        let cells: u32 = counts.iter().sum();
+        assert_eq!(error, format!("mask runs cover {} cells, not 3x3", cells));
+    }
+    // Huge sizes are summed without overflowing.
+    let mask = DetectionMask { width: u32::MAX, height: u32::MAX, counts: vec![u32::MAX, u32::MAX] };
+    assert!(mask.check().is_err());
+}
+
+#[test]
+fn a_mask_moves_the_distance_only_while_it_agrees_with_the_box() {
+    let config = VisionConfig::default();
+    let estimator = DistanceEstimator::new(&config);
+    let from_box = estimator.estimate(200.0, "box");
+
+    // At the expected fill the mask and the box agree.
+    let expected = filled(10, 10, (config.mask_expected_fill * 100.0) as usize);
+    assert!((estimator.estimate_with_mask(200.0, &expected, "box").unwrap() - from_box).abs() < 1e-4);
+    // Less of the box filled is a smaller object, so further off, and more
+    // filled nearer: by the square root of the fill.
+    let sparse = estimator.estimate_with_mask(200.0, &filled(10, 10, 30), "box").unwrap();
+    assert!((sparse - from_box * 2f32.sqrt()).abs() < 1e-3, "{} vs {}", sparse, from_box);
+    let full = estimator.estimate_with_mask(200.0, &filled(10, 10, 100), "box").unwrap();
+    assert!((full - from_box * 0.6f32.sqrt()).abs() < 1e-3);
+
+    // Up to mask_max_disagreement times apart is believed; past it is not.
+    assert!(estimator.estimate_with_mask(200.0, &filled(10, 10, 15), "box").is_some());
+    assert!(estimator.estimate_with_mask(200.0, &filled(10, 10, 14), "box").is_none());
+    assert!(estimator.estimate_with_mask(200.0, &filled(10, 10, 0), "box").is_none());
+    assert!(estimator.estimate_with_mask(0.0, &expected, "box").is_none());
+}
+
+#[test]
+fn a_wildly_wrong_mask_falls_back_to_the_box() {
+    let config = VisionConfig::default();
+    let (estimator, policy) = (DistanceEstimator::new(&config), ActionPolicy::new(&config));
+    let mut detection = Detection::new("person".into(), 0.9, (280, 4, 80, 472), 0, &ConfidenceCurve::from_points(Vec::new()), &estimator, &policy);
+    let (distance, action) = (detection.distance_estimate, detection.action);
+
+    // A sliver of a person in a full box: taken at its word, far off.
+    assert!(!detection.set_mask(filled(8, 8, 1), &estimator, &policy));
+    assert_eq!((detection.distance_estimate, detection.action), (distance, action));
+    assert!(detection.mask.is_some(), "the mask is kept for the overlay");
+
+    assert!(detection.set_mask(filled(8, 8, 64), &estimator, &policy));
+    assert!(detection.distance_estimate < distance);
+    assert!(detection.action >= action);
+}
+
+#[test]
+fn vision_uses_masks_that_agree_and_falls_back_on_those_that_dont() -> Result<()> {
+    let health = HealthRegistry::new();
+    let mut vision = VisionSystem::new(&health, VisionConfig::default(), FrameSource::Manual)?;
+    let person = |mask: Option<DetectionMask>| IMX500Detection { class: "person".into(), class_id: Some(0), conf: 0.9, x: 280, y: 4, w: 80, h: 472, mask };
+
+    vision.get_frame_sender().send(simulation::synthetic_frame(1, vec![person(None)]));
+    let from_box = vision.process_frame()?[0].distance_estimate;
+
+    let (used, fell_back) = (METRICS.mask_distances.get("mask"), METRICS.mask_distances.get("box"));
+    vision.get_frame_sender().send(simulation::synthetic_frame(2, vec![person(Some(filled(10, 10, 30)))]));
+    let detection = vision.process_frame()?.remove(0);
+    assert!(detection.distance_estimate > from_box, "{} vs {}", detection.distance_estimate, from_box);
+    assert_eq!(detection.mask.as_ref().map(DetectionMask::get_fill), Some(0.3));
+    assert_eq!(METRICS.mask_distances.get("mask"), used + 1);
+
+    vision.get_frame_sender().send(simulation::synthetic_frame(3, vec![person(Some(filled(10, 10, 2)))]));
+    let detection = vision.process_frame()?.remove(0);
+    assert!((detection.distance_estimate - from_box).abs() < 0.3, "{} vs {}", detection.distance_estimate, from_box);
+    assert_eq!(METRICS.mask_distances.get("box"), fell_back + 1);
+    Ok(())
+}
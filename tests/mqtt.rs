@@ -0,0 +1,327 @@
+// tests/mqtt.rs
+//
+// MQTT publishing and commands. The queue, the debouncing and the topics
+// are checked on their own; with the `mqtt` feature a rover also talks to
+// a minimal MQTT 3.1.1 broker run by the test: it publishes its state,
+// motors and detections there, stops on <prefix>/cmd/stop, and keeps
+// ticking on time once the broker goes away.
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+
+use scout_vision::config::{ConfigStore, MqttConfig};
+use scout_vision::events::{RoverEvent, Severity};
+use scout_vision::metrics::METRICS;
+use scout_vision::mqtt::{self, MqttCommand, MqttMessage, Outbox, Publisher};
+use scout_vision::rover::Rover;
+use scout_vision::simulation::{self, FrameSource};
+use scout_vision::state_machine::RoverState;
+use scout_vision::vision::IMX500Detection;
+
+fn message(topic: &str) -> MqttMessage {
+    MqttMessage { topic: topic.to_string(), payload: String::new(), retain: false }
+}
+
+// A person straight ahead, 1.8 m off.
+fn person() -> IMX500Detection {
+    IMX500Detection { class: "person".into(), class_id: None, conf: 0.9, x: 280, y: 4, w: 80, h: 472, mask: None }
+}
+
+fn rover(mqtt: serde_json::Value) -> Result<Rover> {
+    let config = ConfigStore::load(std::env::temp_dir().join("scout-mqtt-unused.toml"))?;
+    config.update(&serde_json::json!({ "selftest": { "enabled": false }, "mqtt": mqtt }))
+        .map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+    let rover = Rover::new(Arc::new(config), FrameSource::Manual)?;
+    rover.start_subsystems();
+    while rover.startup.is_initializing() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    rover.selftest.start();
+    Ok(rover)
+}
+
+#[cfg(feature = "mqtt")]
+fn wait_until(within: Duration, mut done: impl FnMut() -> bool) -> bool {
+    let deadline = std::time::Instant::now() + within;
+    while std::time::Instant::now() < deadline {
+        if done() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    done()
+}
+
+fn drain(outbox: &Outbox) -> Vec<MqttMessage> {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let mut messages = Vec::new();
+    while !outbox.is_empty() {
+        messages.push(runtime.block_on(outbox.pop()));
+    }
+    messages
+}
+
+#[test]
+fn a_full_outbox_drops_its_oldest_messages() {
+    let outbox = Outbox::new(3);
+    let dropped = METRICS.mqtt_dropped.get();
+    for i in 0..5 {
+        outbox.push(message(&format!("t/{}", i)));
+    }
+    assert_eq!(outbox.len(), 3);
+    assert!(METRICS.mqtt_dropped.get() >= dropped + 2);
+    let topics: Vec<String> = drain(&outbox).into_iter().map(|m| m.topic).collect();
+    assert_eq!(topics, ["t/2", "t/3", "t/4"]);
+}
+
+#[test]
+fn detections_are_debounced_per_class_and_state_sent_on_change() -> Result<()> {
+    let rover = rover(serde_json::json!({}))?;
+    let frames = rover.vision.read().get_frame_sender();
+    frames.send(simulation::synthetic_frame(1, vec![person()]));
+    rover.tick(Duration::from_millis(20))?;
+    let world = rover.state.get_world();
+    assert_eq!(world.detections.len(), 1);
+
+    let config = MqttConfig { topic_prefix: "scout".into(), detection_debounce_s: 60.0, status_interval_s: 60.0, ..MqttConfig::default() };
+    let mut publisher = Publisher::new(&config);
+    let outbox = Outbox::new(64);
+    publisher.sample(&world.detections, &world.state, &world.motors, &outbox);
+    let first = drain(&outbox);
+    let topics: Vec<&str> = first.iter().map(|m| m.topic.as_str()).collect();
+    assert_eq!(topics, ["scout/detection/person", "scout/state", "scout/motors"]);
+    assert!(first.iter().all(|m| m.retain));
+    let event: serde_json::Value = serde_json::from_str(&first[0].payload)?;
+    assert_eq!(event["class"], "person");
+    assert_eq!(event["action"], world.detections[0].action.as_str());
+    let state: serde_json::Value = serde_json::from_str(&first[1].payload)?;
+    assert_eq!(state["state"], world.state.state.as_str());
+
+    // Within the debounce, with the state unchanged: nothing new.
+    publisher.sample(&world.detections, &world.state, &world.motors, &outbox);
+    assert!(outbox.is_empty());
+
+    // Another class isn't held back by the first, and a new state goes out.
+    let mut chair = world.detections[0].clone();
+    chair.class_name = "office chair/desk".into();
+    let mut state = world.state.clone();
+    state.state = RoverState::Fault;
+    publisher.sample(&[world.detections[0].clone(), chair], &state, &world.motors, &outbox);
+    let topics: Vec<String> = drain(&outbox).into_iter().map(|m| m.topic).collect();
+    assert_eq!(topics, ["scout/detection/office_chair_desk", "scout/state"]);
+    Ok(())
+}
+
+#[test]
+fn zone_events_go_out_unretained_on_their_zones_topic() {
+    let event = |source: &'static str, data: serde_json::Value| RoverEvent {
+        id: 1,
+        severity: Severity::Info,
+        source,
+        message: "entered".into(),
+        timestamp: 0,
+        data,
+    };
+    let crossing = mqtt::zone_message("scout", &event("zones", serde_json::json!({ "zone": "front yard", "object": "rover" }))).unwrap();
+    assert_eq!(crossing.topic, "scout/zone/front_yard");
+    assert!(!crossing.retain);
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&crossing.payload).unwrap()["object"], "rover");
+    assert!(mqtt::zone_message("scout", &event("vision", serde_json::json!({ "zone": "x" }))).is_none());
+    assert!(mqtt::zone_message("scout", &event("zones", serde_json::json!({}))).is_none());
+}
+
+#[test]
+fn command_topics_map_to_commands_under_the_prefix_only() {
+    assert_eq!(MqttCommand::from_topic("scout", "scout/cmd/stop"), Some(MqttCommand::Stop));
+    assert_eq!(MqttCommand::from_topic("scout", "scout/cmd/start"), Some(MqttCommand::Start));
+    assert_eq!(MqttCommand::from_topic("scout", "scout/cmd/acknowledge_fault"), Some(MqttCommand::AcknowledgeFault));
+    assert_eq!(MqttCommand::from_topic("scout", "scout/cmd/dock"), Some(MqttCommand::Dock));
+    assert_eq!(MqttCommand::from_topic("scout", "scout/cmd/explode"), None);
+    assert_eq!(MqttCommand::from_topic("scout", "other/cmd/stop"), None);
+    assert_eq!(MqttCommand::from_topic("scout", "scout/stop"), None);
+}
+
+#[test]
+fn a_stop_command_faults_the_rover_and_stops_the_motors() -> Result<()> {
+    let rover = rover(serde_json::json!({}))?;
+    MqttCommand::Stop.apply(&rover.state, &rover.path_planner);
+    rover.tick(Duration::from_millis(20))?;
+    assert_eq!(rover.state.get_state(), RoverState::Fault);
+    assert_eq!(rover.motor_controller.read().get_status().target_speed, 0.0);
+    Ok(())
+}
+
+#[cfg(feature = "mqtt")]
+mod broker {
+    use parking_lot::Mutex;
+    use std::io::{Read, Write};
+    use std::net::{Shutdown, TcpListener, TcpStream};
+    use std::sync::Arc;
+
+    // What the rover published: topic, payload and whether it was retained.
+    pub type Published = Arc<Mutex<Vec<(String, String, bool)>>>;
+
+    // Just enough of MQTT 3.1.1 for one client: CONNECT, SUBSCRIBE,
+    // PUBLISH at QoS 0 or 1 and PINGREQ, each answered as a broker would,
+    // and PUBLISH at QoS 0 back to the client.
+    pub struct Broker {
+        pub port: u16,
+        pub published: Published,
+        client: Arc<Mutex<Option<TcpStream>>>,
+    }
+
+    impl Broker {
+        pub fn start() -> std::io::Result<Self> {
+            let listener = TcpListener::bind("127.0.0.1:0")?;
+            let port = listener.local_addr()?.port();
+            let published = Published::default();
+            let client = Arc::new(Mutex::new(None));
+            let (record, current) = (published.clone(), client.clone());
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else { return };
+                    *current.lock() = stream.try_clone().ok();
+                    let record = record.clone();
+                    std::thread::spawn(move || serve(stream, record));
+                }
+            });
+            Ok(Self { port, published, client })
+        }
+
+        pub fn publish(&self, topic: &str, payload: &str) {
+            let mut body = Vec::new();
+            body.extend((topic.len() as u16).to_be_bytes());
+            body.extend(topic.as_bytes());
+            body.extend(payload.as_bytes());
+            if let Some(client) = self.client.lock().as_mut() {
+                let _ = client.write_all(&packet(0x30, &body));
+            }
+        }
+
+        // Drops the client, as a broker going away would.
+        pub fn disconnect(&self) {
+            if let Some(client) = self.client.lock().take() {
+                let _ = client.shutdown(Shutdown::Both);
+            }
+        }
+
+        pub fn find(&self, topic: &str) -> Option<(String, bool)> {
+            self.published.lock().iter().rev().find(|(t, _, _)| t == topic).map(|(_, payload, retain)| (payload.clone(), *retain))
+        }
+    }
+
+    fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+        let mut out = vec![header];
+        let mut len = body.len();
+        loop {
+            let byte = (len % 128) as u8;
+            len /= 128;
+            out.push(if len > 0 { byte | 0x80 } else { byte });
+            if len == 0 {
+                break;
+            }
+        }
+        out.extend(body);
+        out
+    }
+
+    fn read_packet(stream: &mut TcpStream) -> std::io::Result<(u8, Vec<u8>)> {
+        let mut byte = [0u8];
+        stream.read_exact(&mut byte)?;
+        let header = byte[0];
+        let (mut len, mut shift) = (0usize, 0);
+        loop {
+            stream.read_exact(&mut byte)?;
+            len |= ((byte[0] & 0x7f) as usize) << shift;
+            shift += 7;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; len];
+        stream.read_exact(&mut body)?;
+        Ok((header, body))
+    }
+
+    fn serve(mut stream: TcpStream, published: Published) {
+        while let Ok((header, body)) = read_packet(&mut stream) {
+            let reply = match header >> 4 {
+                1 => packet(0x20, &[0, 0]),
+                8 => packet(0x90, &[body[0], body[1], 1]),
+                3 => {
+                    let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                    let topic = String::from_utf8_lossy(&body[2..2 + topic_len]).into_owned();
+                    let qos = (header >> 1) & 3;
+                    let start = 2 + topic_len + if qos > 0 { 2 } else { 0 };
+                    let payload = String::from_utf8_lossy(&body[start..]).into_owned();
+                    published.lock().push((topic, payload, header & 1 == 1));
+                    if qos == 0 {
+                        continue;
+                    }
+                    packet(0x40, &body[2 + topic_len..2 + topic_len + 2])
+                }
+                12 => packet(0xd0, &[]),
+                _ => continue,
+            };
+            if stream.write_all(&reply).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "mqtt")]
+#[test]
+fn a_rover_publishes_to_the_broker_takes_its_commands_and_outlives_it() -> Result<()> {
+    let broker = broker::Broker::start()?;
+    let rover = rover(serde_json::json!({
+        "enabled": true,
+        "broker_host": "127.0.0.1",
+        "broker_port": broker.port,
+        "topic_prefix": "scout-test",
+        "status_interval_s": 0.2,
+    }))?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    let _context = runtime.enter();
+    rover.start_mqtt();
+    let frames = rover.vision.read().get_frame_sender();
+    let mut frame_id = 0;
+    let mut tick = |detections: Vec<IMX500Detection>| -> Result<Duration> {
+        frame_id += 1;
+        frames.send(simulation::synthetic_frame(frame_id, detections));
+        let started = std::time::Instant::now();
+        rover.tick(Duration::from_millis(20))?;
+        Ok(started.elapsed())
+    };
+
+    assert!(wait_until(Duration::from_secs(10), || broker.find("scout-test/online").is_some()), "never came online");
+    assert_eq!(broker.find("scout-test/online"), Some(("true".to_string(), true)));
+    assert!(wait_until(Duration::from_secs(5), || {
+        tick(vec![person()]).unwrap();
+        broker.find("scout-test/detection/person").is_some()
+    }), "no detection published");
+    let (event, retained) = broker.find("scout-test/detection/person").unwrap();
+    assert!(retained);
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&event)?["class"], "person");
+    let (state, _) = broker.find("scout-test/state").expect("no state published");
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&state)?["state"], rover.state.get_state().as_str());
+    assert!(broker.find("scout-test/motors").is_some());
+    assert_eq!(METRICS.mqtt_connected.get(), 1);
+
+    // The subscription is made after the CONNACK, so give it a moment.
+    assert!(wait_until(Duration::from_secs(5), || {
+        broker.publish("scout-test/cmd/stop", "");
+        tick(Vec::new()).unwrap();
+        rover.state.get_state() == RoverState::Fault
+    }), "the stop command never arrived");
+    assert!(wait_until(Duration::from_secs(5), || broker.find("scout-test/state").is_some_and(|(state, _)| state.contains("\"fault\""))));
+
+    // With the broker gone, ticks take no longer than before.
+    broker.disconnect();
+    assert!(wait_until(Duration::from_secs(5), || METRICS.mqtt_connected.get() == 0), "the lost connection went unnoticed");
+    for _ in 0..50 {
+        let took = tick(vec![person()])?;
+        assert!(took < Duration::from_millis(250), "a tick took {:?} without a broker", took);
+    }
+    Ok(())
+}
@@ -0,0 +1,155 @@
+// tests/obstacle_motion.rs
+//
+// Remembered obstacles sorted into static and dynamic from synthetic
+// tracks: a "person" that stands still long enough ends up static
+// whatever its class, a "chair" being pushed about stays dynamic, a static
+// one that starts moving is dynamic again, the thresholds come from the
+// planner config, and dynamic ones are forgotten well before static ones.
+use std::time::Duration;
+
+use scout_vision::config::PlannerConfig;
+use scout_vision::health::HealthRegistry;
+use scout_vision::motor_control::MotorStatus;
+use scout_vision::pathfinding::{ObstacleMotion, PathPlanner};
+use scout_vision::vision::{Detection, NavigationAction, FRAME_WIDTH_PX};
+
+// Frames 100 ms apart, as the camera would stamp them.
+const FRAME_MS: u64 = 100;
+
+fn parked(config: PlannerConfig) -> PathPlanner {
+    let mut planner = PathPlanner::new(&HealthRegistry::new(), config);
+    let still = MotorStatus { speed: 0.0, target_speed: 0.0, heading: 0.0, enabled: true, speed_cap: None, interlock: None, heading_hold: Default::default() };
+    planner.update_pose(&still, Duration::ZERO);
+    planner
+}
+
+// One of a track's sightings, `distance_m` off at `bearing_deg` from the
+// rover, in frame `frame`.
+fn sighting(class_name: &str, track_id: u32, bearing_deg: f32, distance_m: f32, frame: u64) -> Detection {
+    let center_x = (bearing_deg / PlannerConfig::default().camera_hfov_deg + 0.5) * FRAME_WIDTH_PX;
+    Detection {
+        class_name: class_name.into(),
+        confidence: 0.9,
+        raw_confidence: 0.9,
+        bbox: (center_x as i32 - 30, 100, 60, 200),
+        distance_estimate: distance_m,
+        action: NavigationAction::Continue,
+        capture_ms: frame * FRAME_MS,
+        track_id: Some(track_id),
+        mask: None,
+        self_occlusion: false,
+        below_threshold: false,
+        ground_position: None,
+    }
+}
+
+fn motion_of(planner: &PathPlanner, class_name: &str) -> Option<ObstacleMotion> {
+    let map = planner.get_map_state();
+    map.obstacles.iter().find(|o| o.class_name == class_name).map(|o| o.motion)
+}
+
+fn counts(planner: &PathPlanner) -> (usize, usize) {
+    let status = planner.get_status();
+    (status.static_obstacles, status.dynamic_obstacles)
+}
+
+#[test]
+fn a_parked_person_turns_static_and_a_moving_chair_stays_dynamic() {
+    let config = PlannerConfig::default();
+    let needed = config.static_min_observations as u64;
+    let mut planner = parked(config);
+    for frame in 0..needed * 2 {
+        // The person's distance jitters by a centimetre either way, the
+        // chair is pushed away at 0.5 m/s.
+        let jitter = if frame % 2 == 0 { 0.005 } else { -0.005 };
+        let person = sighting("person", 1, -15.0, 2.0 + jitter, frame);
+        let chair = sighting("chair", 2, 15.0, 1.5 + 0.05 * frame as f32, frame);
+        planner.update_obstacles(&[person, chair]);
+
+        let person = motion_of(&planner, "person");
+        if frame + 1 < needed {
+            assert_eq!(person, Some(ObstacleMotion::Dynamic), "static after {} frames", frame + 1);
+        } else {
+            assert_eq!(person, Some(ObstacleMotion::Static), "still dynamic after {} frames", frame + 1);
+        }
+        assert_eq!(motion_of(&planner, "chair"), Some(ObstacleMotion::Dynamic), "frame {}", frame);
+    }
+    assert_eq!(counts(&planner), (1, 1));
+    assert_eq!(planner.get_status().obstacles_count, 2);
+}
+
+#[test]
+fn a_static_obstacle_that_moves_is_dynamic_again() {
+    let config = PlannerConfig::default();
+    let needed = config.static_min_observations as u64;
+    let mut planner = parked(config);
+    for frame in 0..needed {
+        planner.update_obstacles(&[sighting("person", 4, 0.0, 3.0, frame)]);
+    }
+    assert_eq!(counts(&planner), (1, 0));
+
+    // Walking off at 0.5 m/s undoes it on the first frame that shows it.
+    planner.update_obstacles(&[sighting("person", 4, 0.0, 3.05, needed)]);
+    assert_eq!(counts(&planner), (0, 1));
+    // And standing still again has to earn it over from the start.
+    for frame in needed + 1..needed * 2 {
+        planner.update_obstacles(&[sighting("person", 4, 0.0, 3.05, frame)]);
+        assert_eq!(motion_of(&planner, "person"), Some(ObstacleMotion::Dynamic), "frame {}", frame);
+    }
+    for frame in needed * 2..needed * 3 {
+        planner.update_obstacles(&[sighting("person", 4, 0.0, 3.05, frame)]);
+    }
+    assert_eq!(motion_of(&planner, "person"), Some(ObstacleMotion::Static));
+}
+
+#[test]
+fn repeated_frames_dont_count_as_observations() {
+    let config = PlannerConfig::default();
+    let needed = config.static_min_observations;
+    let mut planner = parked(config);
+    // The same frame handed over again, as vision does between captures.
+    for _ in 0..needed * 2 {
+        planner.update_obstacles(&[sighting("box", 5, 0.0, 2.0, 1)]);
+    }
+    assert_eq!(motion_of(&planner, "box"), Some(ObstacleMotion::Dynamic));
+}
+
+#[test]
+fn the_thresholds_come_from_the_config() {
+    // Three frames is enough, and walking pace is slow enough.
+    let config = PlannerConfig { static_min_observations: 3, static_max_speed_m_s: 1.0, ..PlannerConfig::default() };
+    let mut planner = parked(config);
+    for frame in 0..3 {
+        planner.update_obstacles(&[sighting("chair", 6, 0.0, 1.5 + 0.05 * frame as f32, frame)]);
+        let expected = if frame < 2 { ObstacleMotion::Dynamic } else { ObstacleMotion::Static };
+        assert_eq!(motion_of(&planner, "chair"), Some(expected), "frame {}", frame);
+    }
+
+    // Strict enough, even a parked person never qualifies.
+    let config = PlannerConfig { static_min_observations: 1000, ..PlannerConfig::default() };
+    let mut planner = parked(config);
+    for frame in 0..100 {
+        planner.update_obstacles(&[sighting("person", 7, 0.0, 2.0, frame)]);
+    }
+    assert_eq!(counts(&planner), (0, 1));
+}
+
+#[test]
+fn dynamic_obstacles_are_forgotten_long_before_static_ones() {
+    let config = PlannerConfig { static_min_observations: 3, dynamic_memory_s: 0.1, ..PlannerConfig::default() };
+    let mut planner = parked(config);
+    for frame in 0..3 {
+        let chair = sighting("chair", 8, -15.0, 2.0, frame);
+        let person = sighting("person", 9, 15.0, 1.5 + 0.05 * frame as f32, frame);
+        planner.update_obstacles(&[chair, person]);
+    }
+    assert_eq!(counts(&planner), (1, 1));
+
+    // Both out of sight: the person is gone after dynamic_memory_s, the
+    // chair is still on the map.
+    std::thread::sleep(Duration::from_millis(150));
+    planner.update_obstacles(&[]);
+    assert_eq!(counts(&planner), (1, 0));
+    assert_eq!(motion_of(&planner, "chair"), Some(ObstacleMotion::Static));
+    assert!(motion_of(&planner, "person").is_none());
+}
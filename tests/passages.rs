@@ -0,0 +1,220 @@
+// tests/passages.rs
+//
+// Drives the planner through corridors laid out as zones, feeding each
+// command it gives back in as motor status the way the motor loop would,
+// and checks it reverses out of passages too narrow to turn in and keeps
+// to the way through one-way ones.
+use std::time::Duration;
+
+use scout_vision::config::{PlannerConfig, Zone, ZonePolicy};
+use scout_vision::events::EVENTS;
+use scout_vision::health::HealthRegistry;
+use scout_vision::motor_control::MotorStatus;
+use scout_vision::pathfinding::{NavigationCommand, PathPlanner};
+use scout_vision::range::RangeReading;
+use scout_vision::zones;
+
+const TICK: Duration = Duration::from_millis(100);
+const NEAR_FIELD_M: f32 = 0.5;
+// The most the rover turns in a tick.
+const TURN_STEP_DEG: f32 = 9.0;
+
+fn zone(name: &str, policy: ZonePolicy, direction_deg: Option<f32>, x: (f32, f32), y: (f32, f32)) -> Zone {
+    Zone {
+        name: name.to_string(),
+        polygon: vec![(x.0, y.0), (x.1, y.0), (x.1, y.1), (x.0, y.1)],
+        policy,
+        direction_deg,
+    }
+}
+
+fn reading(sensor: &str, bearing_deg: f32, distance_m: f32) -> RangeReading {
+    RangeReading {
+        sensor: sensor.to_string(),
+        bearing_deg,
+        fov_deg: 25.0,
+        distance_m: Some(distance_m),
+        age_ms: Some(0),
+        ok: true,
+        error: None,
+        floor_m: None,
+        drop_off: false,
+    }
+}
+
+struct Rover {
+    planner: PathPlanner,
+    speed: f32,
+    heading: f32,
+}
+
+impl Rover {
+    fn new(zones: Vec<Zone>, heading: f32) -> Self {
+        let config = PlannerConfig {
+            zones,
+            cruise_speed: 0.3,
+            envelope_unknown_curve: Vec::new(),
+            ..PlannerConfig::default()
+        };
+        let mut rover = Self { planner: PathPlanner::new(&HealthRegistry::new(), config), speed: 0.0, heading };
+        rover.planner.update_pose(&rover.motors(), Duration::ZERO);
+        rover
+    }
+
+    fn motors(&self) -> MotorStatus {
+        MotorStatus {
+            speed: self.speed,
+            target_speed: self.speed,
+            heading: self.heading,
+            enabled: true,
+            speed_cap: None,
+            interlock: None,
+            heading_hold: Default::default(),
+        }
+    }
+
+    // One tick: moves as last commanded, then asks for the next command.
+    fn step(&mut self, ranges: Vec<RangeReading>) -> NavigationCommand {
+        self.planner.update_pose(&self.motors(), TICK);
+        self.planner.update_ranges(ranges, NEAR_FIELD_M);
+        let command = self.planner.get_navigation_command();
+        self.speed = 0.0;
+        match command {
+            NavigationCommand::Forward(speed) => self.speed = speed,
+            NavigationCommand::Backward(speed) => self.speed = -speed,
+            NavigationCommand::TurnRight(deg) => self.heading += deg.min(TURN_STEP_DEG),
+            NavigationCommand::TurnLeft(deg) => self.heading -= deg.min(TURN_STEP_DEG),
+            NavigationCommand::Stop => {}
+        }
+        command
+    }
+
+    fn y(&self) -> f32 {
+        self.planner.get_pose().y
+    }
+
+    fn is_in(&self, zone: &Zone) -> bool {
+        let pose = self.planner.get_pose();
+        zones::contains(&zone.polygon, pose.x, pose.y)
+    }
+}
+
+fn is_turn(command: &NavigationCommand) -> bool {
+    matches!(command, NavigationCommand::TurnLeft(_) | NavigationCommand::TurnRight(_))
+}
+
+fn published(zone: &str) -> bool {
+    EVENTS.recent().iter().any(|e| e.source == "planner" && e.message.contains(&format!("Zone {} ", zone)))
+}
+
+#[test]
+fn dead_end_corridor_is_backed_out_of() {
+    // Too narrow to turn in, with a wall across its far end.
+    let corridor = zone("dead_end", ZonePolicy::NoRotate, None, (-0.4, 0.4), (0.5, 3.0));
+    let mut rover = Rover::new(vec![corridor.clone()], 0.0);
+    // Open to the right, so out in the open the rover would turn that way.
+    let sense = |y: f32| vec![reading("front", 0.0, 3.0 - y), reading("right", 60.0, 2.0)];
+
+    let mut reversing = false;
+    for _ in 0..300 {
+        let command = rover.step(sense(rover.y()));
+        if rover.is_in(&corridor) {
+            assert!(!is_turn(&command), "turned in the corridor at y {:.2}: {:?}", rover.y(), command);
+        }
+        match command {
+            NavigationCommand::Backward(speed) => {
+                assert_eq!(speed, PlannerConfig::default().passage_reverse_speed);
+                reversing = true;
+            }
+            // Once it has started backing out it keeps on until it is out,
+            // even after the wall is far enough away again.
+            NavigationCommand::Forward(_) => assert!(!reversing || !rover.is_in(&corridor), "drove back in at y {:.2}", rover.y()),
+            _ => {}
+        }
+        if reversing && !rover.is_in(&corridor) {
+            break;
+        }
+    }
+    assert!(reversing, "never reached the wall");
+    assert!(rover.y() < 0.5 && (rover.heading - 0.0).abs() < 0.01, "at y {:.2} heading {}", rover.y(), rover.heading);
+    assert!(published("dead_end"));
+}
+
+#[test]
+fn turns_once_out_of_a_no_rotate_zone() {
+    let corridor = zone("narrows", ZonePolicy::NoRotate, None, (-0.4, 0.4), (-2.0, 0.5));
+    let mut rover = Rover::new(vec![corridor.clone()], 0.0);
+    // Something stays right in front the whole time.
+    let sense = || vec![reading("front", 0.0, 0.3), reading("right", 60.0, 2.0)];
+
+    let command = rover.step(sense());
+    assert!(matches!(command, NavigationCommand::Backward(_)), "{:?}", command);
+    let violation = rover.planner.get_status().passage_violation.expect("the turn was ruled out");
+    assert_eq!((violation.zone.as_str(), violation.policy), ("narrows", ZonePolicy::NoRotate));
+    assert_eq!((violation.planned.as_str(), violation.instead.as_str()), ("turn_right", "backward"));
+
+    let mut turned = false;
+    for _ in 0..200 {
+        let command = rover.step(sense());
+        if is_turn(&command) {
+            assert!(!rover.is_in(&corridor), "turned at y {:.2}", rover.y());
+            turned = true;
+            break;
+        }
+    }
+    assert!(turned, "still at y {:.2}", rover.y());
+    assert!(rover.planner.get_passage_violation().is_none());
+}
+
+#[test]
+fn one_way_corridor_is_driven_with_its_direction() {
+    let corridor = zone("with", ZonePolicy::OneWay, Some(0.0), (-0.4, 0.4), (1.0, 3.0));
+    let mut rover = Rover::new(vec![corridor.clone()], 0.0);
+    for _ in 0..200 {
+        let command = rover.step(Vec::new());
+        assert!(matches!(command, NavigationCommand::Forward(_)), "at y {:.2}: {:?}", rover.y(), command);
+        if rover.y() > 3.5 {
+            break;
+        }
+    }
+    assert!(rover.y() > 3.5, "only got to y {:.2}", rover.y());
+    assert!(rover.planner.get_passage_violation().is_none());
+}
+
+#[test]
+fn one_way_corridor_is_not_entered_against_its_direction() {
+    // Heading south toward a corridor only to be driven north through.
+    let corridor = zone("against", ZonePolicy::OneWay, Some(0.0), (-0.4, 0.4), (-3.0, -1.0));
+    let mut rover = Rover::new(vec![corridor.clone()], 180.0);
+    let mut avoided = false;
+    for _ in 0..150 {
+        let command = rover.step(Vec::new());
+        assert!(!rover.is_in(&corridor), "entered it at y {:.2}", rover.y());
+        avoided |= is_turn(&command);
+    }
+    assert!(avoided, "never turned aside");
+    assert!(published("against"));
+}
+
+#[test]
+fn facing_the_wrong_way_in_a_one_way_corridor() {
+    // Out in the open the rover turns around.
+    let corridor = zone("wrong_way", ZonePolicy::OneWay, Some(180.0), (-0.4, 0.4), (-1.0, 3.0));
+    let mut rover = Rover::new(vec![corridor.clone()], 0.0);
+    let command = rover.step(Vec::new());
+    assert!(matches!(command, NavigationCommand::TurnLeft(deg) if (deg - 180.0).abs() < 0.01), "{:?}", command);
+    for _ in 0..30 {
+        rover.step(Vec::new());
+    }
+    assert!(matches!(rover.step(Vec::new()), NavigationCommand::Forward(_)), "heading {}", rover.heading);
+
+    // Where it can't turn, it backs along the way through instead.
+    let narrow = zone("wrong_way_narrow", ZonePolicy::NoRotate, None, (-0.4, 0.4), (-1.0, 3.0));
+    let mut rover = Rover::new(vec![corridor, narrow], 0.0);
+    let command = rover.step(Vec::new());
+    assert!(matches!(command, NavigationCommand::Backward(_)), "{:?}", command);
+    for _ in 0..20 {
+        assert!(matches!(rover.step(Vec::new()), NavigationCommand::Backward(_)));
+    }
+    assert!(rover.y() < -0.25, "at y {:.2}", rover.y());
+}
@@ -0,0 +1,93 @@
+// tests/pixels.rs
+//
+// The integer YUV420 to RGB conversion against BT.601 worked out in
+// floating point, and a raw frame's picture being converted once, only
+// when asked for.
+use std::sync::Arc;
+
+use scout_vision::vision::pixels::{yuv420_len, yuv420_to_bgr, yuv420_to_rgb, yuv_to_rgb};
+use scout_vision::vision::FrameImage;
+
+// Limited-range BT.601 from its definition: Kr 0.299, Kb 0.114, luma over
+// 16-235 and chroma over 16-240.
+fn reference(y: u8, u: u8, v: u8) -> [f64; 3] {
+    let (kr, kb) = (0.299, 0.114);
+    let kg = 1.0 - kr - kb;
+    let luma = (y as f64 - 16.0) * 255.0 / 219.0;
+    let (cb, cr) = ((u as f64 - 128.0) * 255.0 / 224.0, (v as f64 - 128.0) * 255.0 / 224.0);
+    let r = luma + 2.0 * (1.0 - kr) * cr;
+    let b = luma + 2.0 * (1.0 - kb) * cb;
+    let g = luma - (2.0 * kb * (1.0 - kb) * cb + 2.0 * kr * (1.0 - kr) * cr) / kg;
+    [r, g, b].map(|c| c.round().clamp(0.0, 255.0))
+}
+
+#[test]
+fn every_yuv_value_is_within_one_of_the_reference() {
+    let mut off = 0usize;
+    for y in 0..=255u8 {
+        for u in 0..=255u8 {
+            for v in 0..=255u8 {
+                let got = yuv_to_rgb(y, u, v);
+                let expected = reference(y, u, v);
+                for (g, e) in got.iter().zip(expected) {
+                    let diff = (*g as f64 - e).abs();
+                    assert!(diff <= 1.0, "yuv ({}, {}, {}) gave {:?}, not {:?}", y, u, v, got, expected);
+                    off += (diff > 0.0) as usize;
+                }
+            }
+        }
+    }
+    // Off by one now and then from rounding, not as a rule.
+    assert!(off < 256 * 256 * 256 * 3 / 20, "{} channels off by one", off);
+}
+
+// Luma ramps with chroma that differs between the four pixels of each
+// block's neighbours, so a block taking the wrong chroma shows.
+fn frame(width: usize, height: usize) -> Vec<u8> {
+    let mut yuv = Vec::with_capacity(yuv420_len(width, height));
+    yuv.extend((0..width * height).map(|i| (i * 7 % 251) as u8));
+    yuv.extend((0..width * height / 4).map(|i| (i * 13 % 256) as u8));
+    yuv.extend((0..width * height / 4).map(|i| (255 - i * 5 % 256) as u8));
+    yuv
+}
+
+#[test]
+fn whole_frames_convert_pixel_by_pixel() {
+    let (width, height) = (64, 48);
+    let yuv = frame(width, height);
+    let (luma, chroma) = yuv.split_at(width * height);
+    let (u, v) = chroma.split_at(width * height / 4);
+    let rgb = yuv420_to_rgb(&yuv, width, height);
+    let bgr = yuv420_to_bgr(&yuv, width, height);
+    assert_eq!(rgb.len(), width * height * 3);
+    for row in 0..height {
+        for column in 0..width {
+            let chroma_at = (row / 2) * (width / 2) + column / 2;
+            let expected = yuv_to_rgb(luma[row * width + column], u[chroma_at], v[chroma_at]);
+            let at = (row * width + column) * 3;
+            assert_eq!(rgb[at..at + 3], expected, "rgb at ({}, {})", column, row);
+            assert_eq!([bgr[at + 2], bgr[at + 1], bgr[at]], expected, "bgr at ({}, {})", column, row);
+        }
+    }
+}
+
+#[test]
+fn raw_frames_convert_once_when_asked() -> anyhow::Result<()> {
+    let (width, height) = (64, 48);
+    let image = FrameImage::from_yuv420(width, height, frame(width as usize, height as usize))?;
+    assert_eq!(image.get_luma().map(|(w, h, luma)| (w, h, luma.len())), Some((64, 48, 64 * 48)));
+
+    let rgb = image.get_rgb()?;
+    assert_eq!(rgb.as_raw(), &yuv420_to_rgb(&frame(64, 48), 64, 48));
+    assert!(Arc::ptr_eq(&rgb, &image.get_rgb()?), "converted twice");
+
+    let jpeg = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &*image.get_jpeg_base64()?)?;
+    let decoded = image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg)?;
+    assert_eq!((decoded.width(), decoded.height()), (64, 48));
+    assert!(Arc::ptr_eq(&image.get_jpeg_base64()?, &image.get_jpeg_base64()?), "encoded twice");
+
+    assert!(FrameImage::from_yuv420(64, 48, vec![0; 100]).is_err());
+    assert!(FrameImage::from_yuv420(63, 48, vec![0; yuv420_len(63, 48)]).is_err());
+    assert!(FrameImage::none().get_rgb().is_err() && FrameImage::none().get_jpeg_base64().is_err());
+    Ok(())
+}
@@ -0,0 +1,207 @@
+// tests/recording.rs
+//
+// Reads hand-written session recordings back through the reader replay
+// and dataset export share, then exports one and checks the images,
+// labels and annotations that come out.
+use anyhow::Result;
+use base64::Engine;
+use std::path::{Path, PathBuf};
+
+use scout_vision::annotations::{self, Annotation};
+use scout_vision::dataset::{self, ExportOptions};
+use scout_vision::recording::{self, RecordedFrame};
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("recording").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// A 640x480 JPEG of one grey level, base64 as frames carry it.
+fn jpeg(level: u8) -> String {
+    let frame = image::GrayImage::from_pixel(640, 480, image::Luma([level]));
+    let mut out = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, 90)
+        .encode(frame.as_raw(), 640, 480, image::ColorType::L8)
+        .unwrap();
+    base64::engine::general_purpose::STANDARD.encode(out)
+}
+
+fn detection(class: &str, confidence: f32, bbox: (i32, i32, i32, i32)) -> serde_json::Value {
+    serde_json::json!({
+        "class_name": class,
+        "confidence": confidence,
+        "raw_confidence": confidence,
+        "bbox": [bbox.0, bbox.1, bbox.2, bbox.3],
+        "distance_estimate": 2.0,
+        "action": "Continue",
+    })
+}
+
+fn frame(t_ms: u64, image: Option<&str>, detections: Vec<serde_json::Value>) -> String {
+    let mut payload = serde_json::json!({
+        "type": "frame",
+        "tick": t_ms / 33,
+        "detections": detections,
+        "timestamp": 0,
+    });
+    if let Some(image) = image {
+        payload["image"] = image.into();
+    }
+    serde_json::json!({ "t_ms": t_ms, "payload": payload }).to_string()
+}
+
+fn write_recording(dir: &Path, lines: &[String]) -> PathBuf {
+    let path = dir.join("session-20260101-120000.jsonl");
+    std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+    path
+}
+
+#[test]
+fn reader_keeps_only_frames_in_order() -> Result<()> {
+    let dir = scratch("reader");
+    let mut old = detection("person", 0.7, (10, 20, 30, 40));
+    old.as_object_mut().unwrap().remove("raw_confidence");
+    let path = write_recording(&dir, &[
+        frame(0, None, vec![detection("person", 0.9, (10, 20, 30, 40))]),
+        serde_json::json!({ "t_ms": 10, "payload": { "type": "event", "message": "hello" } }).to_string(),
+        "{\"t_ms\": 20, \"payload\": garbage".to_string(),
+        serde_json::json!({ "t_ms": 30, "payload": {
+            "type": "frame",
+            "detections": [old],
+            "image": jpeg(100),
+            "vision_profile": { "active": null, "brightness": 42.0 },
+        } }).to_string(),
+        frame(66, None, Vec::new()),
+    ]);
+
+    let frames: Vec<RecordedFrame> = recording::open_frames(&path)?.collect();
+    assert_eq!(frames.iter().map(|f| f.t_ms).collect::<Vec<_>>(), vec![0, 30, 66]);
+    assert_eq!(frames[0].tick, Some(0));
+    assert!(frames[0].profile.is_none());
+    assert!(frames[0].decode_image()?.is_none());
+
+    // Recorded on the base settings, before raw confidences were kept.
+    assert_eq!(frames[1].profile, Some(None));
+    assert_eq!(frames[1].brightness, Some(42.0));
+    assert_eq!(RecordedFrame::raw_confidence(&frames[1].detections[0]), 0.7);
+    assert!(frames[1].decode_image()?.is_some_and(|jpeg| jpeg.starts_with(&[0xff, 0xd8])));
+    Ok(())
+}
+
+#[test]
+fn export_writes_yolo_and_coco_with_clamped_boxes() -> Result<()> {
+    let dir = scratch("export");
+    let (dark, light) = (jpeg(20), jpeg(220));
+    let path = write_recording(&dir, &[
+        frame(0, Some(&dark), vec![
+            detection("person", 0.9, (-20, 40, 100, 200)),
+            detection("dog", 0.3, (300, 300, 50, 50)),
+        ]),
+        frame(33, None, vec![detection("person", 0.9, (0, 0, 10, 10))]),
+        // Near-identical to the first, so dropped.
+        frame(66, Some(&dark), vec![detection("person", 0.9, (0, 0, 10, 10))]),
+        frame(100, Some(&light), vec![
+            detection("dog", 0.8, (600, 400, 100, 100)),
+            detection("person", 0.95, (700, 10, 20, 20)),
+        ]),
+    ]);
+    let output = dir.join("dataset");
+    let summary = dataset::export(&path, &output, &ExportOptions { min_confidence: 0.5, ..Default::default() })?;
+
+    assert_eq!((summary.frames, summary.images, summary.without_image, summary.duplicates), (4, 2, 1, 1));
+    assert_eq!(summary.instances, 2);
+    assert_eq!(summary.instances_per_class.get("person"), Some(&1));
+    assert_eq!(summary.instances_per_class.get("dog"), Some(&1));
+    assert_eq!((summary.boxes_clamped, summary.boxes_outside), (2, 1));
+
+    assert_eq!(std::fs::read_to_string(output.join("classes.txt"))?, "dog\nperson\n");
+    let first = std::fs::read_to_string(output.join("labels/session-20260101-120000-000001.txt"))?;
+    assert_eq!(first, "1 0.062500 0.291667 0.125000 0.416667 0.9000\n");
+    let second = std::fs::read_to_string(output.join("labels/session-20260101-120000-000002.txt"))?;
+    assert!(second.starts_with("0 0.968750 0.916667 0.062500 0.166667"), "{}", second);
+    assert!(output.join("images/session-20260101-120000-000002.jpg").exists());
+
+    let coco: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(output.join("annotations.json"))?)?;
+    assert_eq!(coco["images"].as_array().map(Vec::len), Some(2));
+    assert_eq!(coco["annotations"][0]["bbox"], serde_json::json!([0, 40, 80, 200]));
+    assert_eq!(coco["annotations"][0]["category_id"], 2);
+    assert_eq!(coco["categories"][0]["name"], "dog");
+
+    let manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(output.join("manifest.json"))?)?;
+    assert_eq!(manifest["images"][1]["t_ms"], 100);
+    assert!(dataset::export(&path, &output, &ExportOptions::default()).is_err(), "a second export overwrote the first");
+    Ok(())
+}
+
+#[test]
+fn export_filters_by_class_time_and_stride() -> Result<()> {
+    let dir = scratch("filters");
+    let lines: Vec<String> = (0..6u8)
+        .map(|i| frame(i as u64 * 1000, Some(&jpeg(i * 40)), vec![
+            detection("person", 0.9, (10, 10, 50, 50)),
+            detection("cat", 0.9, (100, 100, 50, 50)),
+        ]))
+        .collect();
+    let path = write_recording(&dir, &lines);
+    let options = ExportOptions {
+        classes: vec!["person".to_string()],
+        from_ms: Some(1000),
+        to_ms: Some(5000),
+        every: 2,
+        ..Default::default()
+    };
+    let summary = dataset::export(&path, &dir.join("dataset"), &options)?;
+
+    // 1, 3 and 5 s, of the five frames in range.
+    assert_eq!((summary.frames, summary.images), (5, 3));
+    assert_eq!(summary.instances_per_class.keys().collect::<Vec<_>>(), vec!["person"]);
+    let manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(dir.join("dataset/manifest.json"))?)?;
+    let times: Vec<u64> = manifest["images"].as_array().unwrap().iter().map(|i| i["t_ms"].as_u64().unwrap()).collect();
+    assert_eq!(times, vec![1000, 3000, 5000]);
+    Ok(())
+}
+
+#[test]
+fn export_keeps_annotated_frames_and_lists_the_annotations() -> Result<()> {
+    let dir = scratch("annotated");
+    let mut lines: Vec<String> = (0..5u8)
+        .map(|i| frame(i as u64 * 1000, Some(&jpeg(i * 40)), vec![detection("person", 0.9, (10, 10, 50, 50))]))
+        .collect();
+    lines.push(frame(4500, None, Vec::new()));
+    let path = write_recording(&dir, &lines);
+
+    let mark = |label: &str, tick: Option<u64>, t_ms: u64| Annotation {
+        at_ms: 0,
+        label: label.to_string(),
+        note: Some("by hand".to_string()),
+        frame_id: None,
+        tick,
+        detections: Vec::new(),
+        session: Some("session-20260101-120000.jsonl".to_string()),
+        t_ms: Some(t_ms),
+        replayed: true,
+        source: "api".to_string(),
+    };
+    // By tick on the frame at 1 s, which the stride would skip, and by
+    // time on one with no image.
+    annotations::append(&path, &mark("false_positive", Some(1000 / 33), 1010))?;
+    annotations::append(&path, &mark("missed_obstacle", None, 4500))?;
+    assert_eq!(annotations::load(&path)?.len(), 2);
+
+    let summary = dataset::export(&path, &dir.join("dataset"), &ExportOptions { every: 2, ..Default::default() })?;
+    assert_eq!((summary.images, summary.annotations, summary.annotations_with_image), (4, 2, 1));
+    let manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(dir.join("dataset/manifest.json"))?)?;
+    let images = manifest["images"].as_array().unwrap();
+    assert_eq!(images.iter().map(|i| i["t_ms"].as_u64().unwrap()).collect::<Vec<_>>(), vec![0, 1000, 2000, 4000]);
+    assert_eq!(images[1]["annotations"], serde_json::json!(["false_positive"]));
+    let listed = manifest["operator_annotations"].as_array().unwrap();
+    assert_eq!(listed[0]["image"], "images/session-20260101-120000-000002.jpg");
+    assert_eq!((listed[1]["label"].as_str(), listed[1]["image"].is_null()), (Some("missed_obstacle"), true));
+
+    let options = ExportOptions { annotated_only: true, ..Default::default() };
+    let summary = dataset::export(&path, &dir.join("annotated-only"), &options)?;
+    assert_eq!((summary.frames, summary.images, summary.without_image), (2, 1, 1));
+    Ok(())
+}
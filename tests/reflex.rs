@@ -0,0 +1,140 @@
+// tests/reflex.rs
+//
+// The reflex loop against a planner that takes its time. With the
+// planning loop on its own thread and a planning step holding the
+// planner for a second, as a slow search over a big map might, a person
+// stepping in front of the rover still stops it on the next reflex tick,
+// as soon as with a planner that keeps up. A planning loop that stays
+// stuck leaves a stale plan, which stops the rover too.
+use anyhow::Result;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use scout_vision::metrics::METRICS;
+use scout_vision::vision::IMX500Detection;
+
+mod common;
+use common::{Sim, SERIAL};
+
+const TICK: Duration = Duration::from_millis(10);
+const SLOW_STEP: Duration = Duration::from_secs(1);
+
+impl Sim {
+    fn new(plan_stale_ms: u64) -> Result<Self> {
+        let sim = Sim::boot("reflex", &[serde_json::json!({
+            "control": { "start_autonomous": true, "plan_interval_ms": 20, "plan_stale_ms": plan_stale_ms },
+        })])?;
+        sim.rover.start_planning();
+        Ok(sim)
+    }
+
+    // One reflex tick in real time, with a frame every other one.
+    fn step(&mut self, detections: &[IMX500Detection]) -> Result<()> {
+        if self.ticks % 2 == 0 {
+            self.send(detections);
+        }
+        self.ticks += 1;
+        thread::sleep(TICK);
+        self.rover.reflex_tick(TICK)
+    }
+
+    // The reflex tick last published, and the one the plan it drove on was
+    // made from.
+    fn get_ticks(&self) -> (u64, u64) {
+        let world = self.rover.state.get_world();
+        (world.tick, world.plan_tick)
+    }
+
+    // Drives with a clear view until the planner has the rover moving.
+    fn drive_off(&mut self) -> Result<()> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while self.get_target_speed() <= 0.0 {
+            anyhow::ensure!(Instant::now() < deadline, "never moved off: {:?}", self.rover.state.get_status());
+            self.step(&[])?;
+        }
+        Ok(())
+    }
+
+    // Holds the planner lock on another thread for SLOW_STEP, as a slow
+    // planning step would, so the planning loop's next step waits as long.
+    fn slow_down_planner(&self) -> thread::JoinHandle<()> {
+        let (held, holding) = mpsc::channel();
+        let planner = self.rover.path_planner.clone();
+        let handle = thread::spawn(move || {
+            let _step = planner.write();
+            held.send(()).unwrap();
+            thread::sleep(SLOW_STEP);
+        });
+        holding.recv().unwrap();
+        handle
+    }
+
+    // How many reflex ticks, and how long, from a frame with a person in
+    // it to the motors being told to stop.
+    fn stop_latency(&mut self) -> Result<(u32, Duration)> {
+        let person = person(1.8);
+        self.send(std::slice::from_ref(&person));
+        let sent = Instant::now();
+        for ticks in 1..=50 {
+            self.rover.reflex_tick(TICK)?;
+            if self.get_target_speed() == 0.0 {
+                return Ok((ticks, sent.elapsed()));
+            }
+            thread::sleep(TICK);
+        }
+        anyhow::bail!("still driving at {} m/s", self.get_target_speed())
+    }
+}
+
+// A person straight ahead, as tall as one looks from `distance`: inside
+// vision.person_stop_m, outside vision.person_emergency_stop_m.
+fn person(distance: f32) -> IMX500Detection {
+    let h = (1.7 * 500.0 / distance).round() as i32;
+    IMX500Detection { class: "person".to_string(), class_id: None, conf: 0.9, x: 280, y: 240 - h / 2, w: 80, h, mask: None }
+}
+
+#[test]
+fn a_slow_planner_doesnt_hold_up_a_stop() -> Result<()> {
+    let _serial = SERIAL.lock();
+    let mut sim = Sim::new(5000)?;
+    sim.drive_off()?;
+    let (ticks, latency) = sim.stop_latency()?;
+    assert_eq!(ticks, 1, "took {} ticks with the planner keeping up", ticks);
+
+    let mut sim = Sim::new(5000)?;
+    sim.drive_off()?;
+    let slow = sim.slow_down_planner();
+    let (_, planned) = sim.get_ticks();
+    let (slow_ticks, slow_latency) = sim.stop_latency()?;
+    assert!(!slow.is_finished(), "the planner let go before the rover stopped");
+    let (tick, still_planned) = sim.get_ticks();
+    assert!(tick > planned && still_planned == planned, "planned on tick {} then {}, at tick {}", planned, still_planned, tick);
+    assert_eq!(slow_ticks, 1, "took {} ticks with the planner held up", slow_ticks);
+    // A few ticks' leeway for a busy machine, and still nothing like the
+    // second the planner is taking.
+    assert!(slow_latency <= latency + TICK * 3, "{:?} to stop with the planner held up, {:?} without", slow_latency, latency);
+    slow.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn a_stalled_planner_stops_the_rover() -> Result<()> {
+    let _serial = SERIAL.lock();
+    let mut sim = Sim::new(200)?;
+    sim.drive_off()?;
+    let stale_stops = METRICS.plan_stale_stops.get();
+    let (tick, _) = sim.get_ticks();
+    let slow = sim.slow_down_planner();
+    let deadline = Instant::now() + SLOW_STEP;
+    while sim.get_target_speed() > 0.0 {
+        assert!(Instant::now() < deadline, "still driving on a plan {} ms old", METRICS.plan_age_ms.get());
+        sim.step(&[])?;
+    }
+    assert!(METRICS.plan_stale_stops.get() > stale_stops);
+    assert!(sim.get_ticks().0 > tick, "the reflex loop stopped ticking");
+    slow.join().unwrap();
+
+    // Going again once the planner catches up.
+    sim.drive_off()
+}
@@ -0,0 +1,318 @@
+// tests/rules.rs
+//
+// The detection rules DSL: what the parser takes and the message for
+// each thing it turns down, rules files in TOML and JSON with every bad
+// rule reported at once, evaluation from the highest priority down with
+// the most cautious rule winning a tie, and the built-in action staying a
+// floor that no rule can relax, both in RuleSet::apply and in vision's
+// navigation command with a rules file loaded and reloaded.
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use scout_vision::config::{ConfigStore, VisionConfig};
+use scout_vision::health::HealthRegistry;
+use scout_vision::rover::Rover;
+use scout_vision::simulation::{self, FrameSource};
+use scout_vision::vision::{CameraModel, Detection, IMX500Detection, NavigationAction, Rule, RuleSet, RuleWorld, VisionSystem};
+use scout_vision::web::WebServer;
+
+const IDLE: RuleWorld = RuleWorld { speed: 0.0, state: "idle" };
+const DRIVING: RuleWorld = RuleWorld { speed: 0.4, state: "autonomous" };
+
+fn camera() -> CameraModel {
+    CameraModel::new(&VisionConfig::default())
+}
+
+// A detection `distance` m off, boxed so its bearing is `bearing` degrees.
+fn seen(class_name: &str, distance: f32, bearing: f32) -> Detection {
+    let center_x = 320.0 + 500.0 * bearing.to_radians().tan();
+    Detection {
+        class_name: class_name.into(),
+        confidence: 0.8,
+        raw_confidence: 0.8,
+        bbox: (center_x as i32 - 20, 100, 40, 200),
+        distance_estimate: distance,
+        action: NavigationAction::Continue,
+        capture_ms: 0,
+        track_id: None,
+        mask: None,
+        self_occlusion: false,
+        below_threshold: false,
+        ground_position: None,
+    }
+}
+
+fn rule(text: &str) -> Rule {
+    Rule::parse("test", text).unwrap_or_else(|e| panic!("{}: {}", e, text))
+}
+
+fn parse_error(text: &str) -> String {
+    match Rule::parse("test", text) {
+        Ok(rule) => panic!("parsed as {:?}", rule),
+        Err(message) => message,
+    }
+}
+
+fn rules(lines: &[(&str, &str)]) -> RuleSet {
+    let toml: String = lines.iter()
+        .map(|(name, text)| format!("[[rules]]\nname = \"{}\"\nrule = '{}'\n", name, text))
+        .collect();
+    RuleSet::parse(&toml, false).unwrap_or_else(|errors| panic!("{:?}", errors))
+}
+
+fn names(set: &RuleSet) -> Vec<&str> {
+    set.get_rules().iter().map(|r| r.name.as_str()).collect()
+}
+
+#[test]
+fn the_parser_takes_every_form_of_condition() {
+    let people = rule(r#"when class == "person" and distance < 1.5 and bearing in [-20, 20] then action Stop priority 90"#);
+    assert_eq!((people.action, people.priority), (NavigationAction::Stop, 90));
+    assert_eq!(people.rule, r#"when class == "person" and distance < 1.5 and bearing in [-20, 20] then action Stop priority 90"#);
+
+    // The priority is optional, and action names go either way.
+    let default = rule("when speed > 0.5 then action slow_down");
+    assert_eq!((default.action, default.priority), (NavigationAction::SlowDown, 0));
+    assert_eq!(rule("when speed > 0.5 then action SlowDown").action, NavigationAction::SlowDown);
+    assert_eq!(rule("when speed > 0.5 then action EMERGENCY_STOP").action, NavigationAction::EmergencyStop);
+    for op in ["==", "!=", "<", "<=", ">", ">="] {
+        rule(&format!("when confidence {} .5 then action Continue priority 1000", op));
+    }
+    rule(r#"when class in ["cat", "dog"] and state != "manual" and distance >= -1 then action Stop"#);
+    rule(r#"when state=="autonomous" and distance<=2 then action Stop"#);
+}
+
+#[test]
+fn the_parser_says_what_is_wrong() {
+    let cases = [
+        ("", "expected `when`, found the end of the rule"),
+        ("if class == \"person\" then action Stop", "expected `when`, found `if`"),
+        ("when colour == \"red\" then action Stop", "unknown field `colour`, expected one of class, distance, bearing, confidence, speed, state"),
+        ("when distance < 1 action Stop", "expected `and` or `then`, found `action`"),
+        ("when distance < 1 then Stop", "expected `action`, found `Stop`"),
+        ("when distance < 1 then action Halt", "unknown action `Halt`, expected Continue, SlowDown, Stop or EmergencyStop"),
+        ("when distance < 1 then action Stop priority 1001", "priority must be a whole number from 0 to 1000, found `1001`"),
+        ("when distance < 1 then action Stop priority 1.5", "priority must be a whole number from 0 to 1000, found `1.5`"),
+        ("when distance < 1 then action Stop urgently", "expected `priority` or the end of the rule, found `urgently`"),
+        ("when distance < 1 then action Stop priority 5 now", "unexpected `now` after the priority"),
+        ("when distance < \"far\" then action Stop", "distance is compared with a number, found \"far\""),
+        ("when class == person then action Stop", "class is compared with text in quotes, found `person`"),
+        ("when class < \"person\" then action Stop", "class can only be compared with == or !="),
+        ("when class == \"person then action Stop", "unterminated string"),
+        ("when distance = 1 then action Stop", "`=` must be followed by `=`"),
+        ("when distance ~ 1 then action Stop", "unexpected `~`"),
+        ("when distance in 1, 2 then action Stop", "expected `[` after `in`, found `1`"),
+        ("when distance in [2, 1] then action Stop", "distance range must be [low, high] with low <= high"),
+        ("when distance in [1, 2, 3] then action Stop", "distance in takes a [low, high] range of numbers"),
+        ("when class in [\"cat\" \"dog\"] then action Stop", "expected `,` or `]`, found \"dog\""),
+        ("when class in [\"cat\", 2] then action Stop", "class is compared with text in quotes, found `2`"),
+        ("when distance then action Stop", "expected a comparison or `in` after distance, found `then`"),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(parse_error(text), expected, "for {:?}", text);
+    }
+}
+
+#[test]
+fn rules_files_report_every_bad_rule_at_once() {
+    let toml = r#"
+[[rules]]
+name = "ok"
+rule = 'when distance < 1 then action Stop'
+
+[[rules]]
+name = "typo"
+rule = 'when distnce < 1 then action Stop'
+
+[[rules]]
+rule = 'when speed > 1 then action Danger'
+
+[[rules]]
+name = "ok"
+rule = 'when distance < 2 then action SlowDown'
+"#;
+    let errors = RuleSet::parse(toml, false).unwrap_err();
+    let errors: Vec<(&str, &str)> = errors.iter().map(|e| (e.rule.as_str(), e.message.as_str())).collect();
+    assert_eq!(errors.len(), 3, "{:?}", errors);
+    assert_eq!(errors[0].0, "typo");
+    assert!(errors[0].1.starts_with("unknown field `distnce`"));
+    assert_eq!(errors[1].0, "rules[2]");
+    assert!(errors[1].1.starts_with("unknown action `Danger`"));
+    assert_eq!(errors[2], ("ok", "another rule has this name"));
+
+    // The same file in JSON, unnamed rules named by their place.
+    let json = r#"{"rules": [{"rule": "when distance < 1 then action Stop"}, {"name": "slow", "rule": "when speed > 1 then action SlowDown"}]}"#;
+    let set = RuleSet::parse(json, true).unwrap_or_else(|errors| panic!("{:?}", errors));
+    assert_eq!(names(&set), ["rules[0]", "slow"]);
+
+    // A file the format itself turns down is one error about the file.
+    for (text, json) in [("rules = 3", false), (r#"{"rules": [], "extra": 1}"#, true), ("[[rules]]\nname = \"x\"\n", false)] {
+        let errors = RuleSet::parse(text, json).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].rule, "");
+    }
+    assert!(RuleSet::parse("", false).unwrap().is_empty());
+}
+
+#[test]
+fn higher_priorities_are_evaluated_first_then_the_files_order() {
+    let set = rules(&[
+        ("low", "when distance < 5 then action SlowDown priority 10"),
+        ("first", "when distance < 3 then action Stop priority 50"),
+        ("high", "when class == \"person\" then action Continue priority 90"),
+        ("second", "when distance < 3 then action Stop priority 50"),
+    ]);
+    assert_eq!(names(&set), ["high", "first", "second", "low"]);
+
+    let camera = camera();
+    let pick = |detections: &[Detection]| set.evaluate(detections, &DRIVING, &camera).map(|r| r.name.clone());
+    // The highest priority that matches decides, wherever it is listed.
+    assert_eq!(pick(&[seen("person", 2.0, 0.0)]).as_deref(), Some("high"));
+    assert_eq!(pick(&[seen("box", 2.0, 0.0)]).as_deref(), Some("first"));
+    assert_eq!(pick(&[seen("box", 4.0, 0.0)]).as_deref(), Some("low"));
+    assert_eq!(pick(&[seen("box", 6.0, 0.0)]), None);
+    assert_eq!(pick(&[]), None);
+}
+
+#[test]
+fn a_tie_goes_to_the_most_cautious_rule_then_the_first_listed() {
+    let set = rules(&[
+        ("slow", "when distance < 3 then action SlowDown priority 50"),
+        ("stop", "when distance < 3 then action Stop priority 50"),
+        ("also stop", "when distance < 3 then action Stop priority 50"),
+        ("lower e-stop", "when distance < 3 then action EmergencyStop priority 49"),
+    ]);
+    let camera = camera();
+    let winner = set.evaluate(&[seen("box", 1.0, 0.0)], &DRIVING, &camera).unwrap();
+    assert_eq!(winner.name, "stop");
+    // A lower priority never wins, however cautious.
+    assert_eq!(winner.action, NavigationAction::Stop);
+}
+
+#[test]
+fn conditions_on_a_detection_must_hold_for_the_same_one() {
+    let camera = camera();
+    let people_ahead = rules(&[("ahead", r#"when class == "person" and bearing in [-20, 20] and distance < 1.5 then action Stop"#)]);
+    let matches = |detections: &[Detection], world: &RuleWorld| people_ahead.evaluate(detections, world, &camera).is_some();
+    assert!(matches(&[seen("person", 1.0, 10.0)], &IDLE));
+    assert!(matches(&[seen("person", 1.0, -19.0)], &IDLE));
+    assert!(!matches(&[seen("person", 1.0, 25.0)], &IDLE));
+    assert!(!matches(&[seen("person", 2.0, 0.0)], &IDLE));
+    // A near box and a person off to the side are not a near person ahead.
+    assert!(!matches(&[seen("box", 1.0, 0.0), seen("person", 1.0, 30.0)], &IDLE));
+    assert!(matches(&[seen("box", 1.0, 0.0), seen("person", 1.0, 30.0), seen("person", 1.2, 5.0)], &IDLE));
+
+    // Ranges take in both ends.
+    let dead_ahead = rules(&[("dead ahead", "when bearing in [0, 0] then action Stop")]);
+    assert!(dead_ahead.evaluate(&[seen("box", 1.0, 0.0)], &IDLE, &camera).is_some());
+
+    // World conditions hold or not whatever is in view, even nothing.
+    let fast = rules(&[("fast", r#"when speed > 0.3 and state == "autonomous" then action SlowDown"#)]);
+    assert!(fast.evaluate(&[], &DRIVING, &camera).is_some());
+    assert!(fast.evaluate(&[], &IDLE, &camera).is_none());
+    let fast_near = rules(&[("fast near", "when speed > 0.3 and distance < 2 then action Stop")]);
+    assert!(fast_near.evaluate(&[], &DRIVING, &camera).is_none());
+    assert!(fast_near.evaluate(&[seen("box", 1.0, 0.0)], &DRIVING, &camera).is_some());
+    assert!(fast_near.evaluate(&[seen("box", 1.0, 0.0)], &IDLE, &camera).is_none());
+}
+
+#[test]
+fn no_rule_can_relax_the_built_in_floor() {
+    let camera = camera();
+    let relaxing = rules(&[
+        ("ignore people", r#"when class == "person" then action Continue priority 1000"#),
+        ("ease off", "when distance < 10 then action SlowDown priority 500"),
+    ]);
+    let person = [seen("person", 0.5, 0.0)];
+    for floor in [NavigationAction::SlowDown, NavigationAction::Stop, NavigationAction::EmergencyStop] {
+        let (action, rule) = relaxing.apply(floor, &person, &IDLE, &camera);
+        assert_eq!(action, floor);
+        assert_eq!(rule.map(|r| r.name.as_str()), Some("ignore people"));
+    }
+    // Raising it is what rules are for.
+    let (action, _) = relaxing.apply(NavigationAction::Continue, &[seen("box", 3.0, 0.0)], &IDLE, &camera);
+    assert_eq!(action, NavigationAction::SlowDown);
+    let none = RuleSet::default();
+    let (action, rule) = none.apply(NavigationAction::Stop, &person, &IDLE, &camera);
+    assert_eq!((action, rule.is_none()), (NavigationAction::Stop, true));
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("rules").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// A person straight ahead, 1.8 m off: close enough to stop for.
+fn near_person() -> IMX500Detection {
+    IMX500Detection { class: "person".into(), class_id: None, conf: 0.9, x: 280, y: 4, w: 80, h: 472, mask: None }
+}
+
+#[test]
+fn vision_keeps_stopping_for_people_whatever_the_rules_file_says() -> Result<()> {
+    let path = scratch("floor").join("rules.toml");
+    std::fs::write(&path, "[[rules]]\nname = \"ignore people\"\nrule = 'when class == \"person\" then action Continue priority 1000'\n")?;
+    let health = HealthRegistry::new();
+    let config = VisionConfig { rules_path: Some(path), ..VisionConfig::default() };
+    let mut vision = VisionSystem::new(&health, config, FrameSource::Manual)?;
+    assert_eq!(vision.get_rules().len(), 1);
+
+    vision.get_frame_sender().send(simulation::synthetic_frame(1, vec![near_person()]));
+    let detections = vision.process_frame()?;
+    assert!(detections[0].action >= NavigationAction::Stop);
+    assert!(vision.get_navigation_command(&DRIVING) >= NavigationAction::Stop);
+    Ok(())
+}
+
+#[test]
+fn a_bad_reload_over_the_api_keeps_the_old_rules() -> Result<()> {
+    let path = scratch("reload").join("rules.toml");
+    std::fs::write(&path, "[[rules]]\nname = \"fast\"\nrule = 'when speed > 0.3 then action SlowDown'\n")?;
+    let config = ConfigStore::load(std::env::temp_dir().join("scout-rules-unused.toml"))?;
+    config.update(&serde_json::json!({
+        "selftest": { "enabled": false },
+        "vision": { "rules_path": path },
+    })).map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+    let rover = Rover::new(Arc::new(config), FrameSource::Manual)?;
+    rover.start_subsystems();
+    while rover.startup.is_initializing() {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    let web = Arc::new(WebServer::new(
+        rover.vision.clone(),
+        rover.path_planner.clone(),
+        rover.motor_controller.clone(),
+        rover.state.clone(),
+        rover.scheduler.clone(),
+        rover.health.clone(),
+        rover.config.clone(),
+    ));
+    let routes = web.routes();
+    let rule_names = || rover.vision.read().get_rules().into_iter().map(|r| r.name).collect::<Vec<_>>();
+    assert_eq!(rule_names(), ["fast"]);
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let reload = || warp::test::request().method("POST").path("/api/rules/reload").reply(&routes);
+
+        std::fs::write(&path, "[[rules]]\nname = \"near\"\nrule = 'when distance < 1 then action Stop'\n[[rules]]\nname = \"broken\"\nrule = 'when distance < 1 then action'\n")?;
+        let reply = reload().await;
+        assert_eq!(reply.status(), warp::http::StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = serde_json::from_slice(reply.body())?;
+        assert_eq!(body["errors"][0]["rule"], "broken");
+        assert_eq!(body["errors"][0]["message"], "expected an action, found the end of the rule");
+        assert_eq!(rule_names(), ["fast"]);
+
+        std::fs::write(&path, "[[rules]]\nname = \"near\"\nrule = 'when distance < 1 then action Stop'\n")?;
+        let reply = reload().await;
+        assert_eq!(reply.status(), warp::http::StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(reply.body())?;
+        assert_eq!(body["loaded"], 1);
+        assert_eq!(rule_names(), ["near"]);
+
+        let listed = warp::test::request().path("/api/rules").reply(&routes).await;
+        let listed: serde_json::Value = serde_json::from_slice(listed.body())?;
+        assert_eq!(listed["rules"][0]["rule"], "when distance < 1 then action Stop");
+        anyhow::Ok(())
+    })
+}
@@ -0,0 +1,91 @@
+// tests/shm_ring.rs
+//
+// The rover's side of the frame ring over memfds made here: a frame is
+// copied out whole, a slot rewritten since the frame line is refused, and
+// a ring the bridge cut short is an error rather than a SIGBUS.
+use anyhow::Result;
+use std::fs::File;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::fs::FileExt;
+
+use scout_vision::config::PixelFormat;
+use scout_vision::vision::shm::SLOT_HEADER_BYTES;
+use scout_vision::vision::{RingInfo, ShmRing, SlotRef};
+
+const SLOTS: usize = 2;
+const SLOT_BYTES: usize = 4096;
+
+fn memfd(sealed: bool) -> Result<(File, RingInfo)> {
+    let name = std::ffi::CString::new("scout-frames-test")?;
+    // SAFETY: a new memfd, owned by the File from here on.
+    let file = unsafe {
+        let fd = libc::memfd_create(name.as_ptr(), libc::MFD_ALLOW_SEALING);
+        assert!(fd >= 0, "{}", std::io::Error::last_os_error());
+        File::from_raw_fd(fd)
+    };
+    file.set_len((SLOTS * SLOT_BYTES) as u64)?;
+    if sealed {
+        // SAFETY: adds seals to the memfd made above.
+        let ret = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, libc::F_SEAL_SHRINK | libc::F_SEAL_GROW) };
+        assert_eq!(ret, 0, "{}", std::io::Error::last_os_error());
+    }
+    let path = format!("/proc/self/fd/{}", file.as_raw_fd());
+    Ok((file, RingInfo { path, slots: SLOTS, slot_bytes: SLOT_BYTES }))
+}
+
+// Writes `frame` into `slot` as the bridge does, and the frame line's
+// reference to it.
+fn put(file: &File, slot: usize, seq: u64, frame: &[u8]) -> Result<SlotRef> {
+    let at = (slot * SLOT_BYTES) as u64;
+    file.write_all_at(&frame.len().to_le_bytes()[..4], at + 8)?;
+    file.write_all_at(frame, at + SLOT_HEADER_BYTES as u64)?;
+    file.write_all_at(&seq.to_le_bytes(), at)?;
+    Ok(SlotRef { slot, seq, length: frame.len(), format: PixelFormat::Jpeg, width: 0, height: 0 })
+}
+
+#[test]
+fn frames_are_copied_out_whole() -> Result<()> {
+    let (file, info) = memfd(true)?;
+    let ring = ShmRing::open(&info)?;
+    let frame: Vec<u8> = (0..3000u32).map(|i| (i * 7) as u8).collect();
+    let slot = put(&file, 1, 5, &frame)?;
+    let copy = ring.read(&slot)?;
+    assert_eq!(copy, frame);
+
+    // The copy is the rover's own: rewriting the slot doesn't change it.
+    put(&file, 1, 6, &[0; 100])?;
+    assert_eq!(copy, frame);
+    Ok(())
+}
+
+#[test]
+fn a_slot_holding_another_frame_is_refused() -> Result<()> {
+    let (file, info) = memfd(true)?;
+    let ring = ShmRing::open(&info)?;
+    let slot = put(&file, 0, 5, &[1; 64])?;
+    put(&file, 0, 6, &[2; 64])?;
+    assert!(ring.read(&slot).is_err());
+    assert!(ring.read(&SlotRef { slot: 2, ..slot }).is_err());
+    assert!(ring.read(&SlotRef { length: SLOT_BYTES, ..slot }).is_err());
+    Ok(())
+}
+
+#[test]
+fn a_ring_cut_short_is_an_error_not_a_fault() -> Result<()> {
+    let (file, info) = memfd(false)?;
+    let ring = ShmRing::open(&info)?;
+    let slot = put(&file, 1, 5, &[3; 2000])?;
+    assert!(ring.read(&slot).is_ok());
+    file.set_len(SLOT_BYTES as u64)?;
+    let error = ring.read(&slot).unwrap_err();
+    assert!(error.to_string().contains("shrank"), "{:#}", error);
+    Ok(())
+}
+
+#[test]
+fn a_sealed_ring_cant_be_cut_short() -> Result<()> {
+    let (file, info) = memfd(true)?;
+    let _ring = ShmRing::open(&info)?;
+    assert!(file.set_len(SLOT_BYTES as u64).is_err());
+    Ok(())
+}
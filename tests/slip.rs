@@ -0,0 +1,238 @@
+// tests/slip.rs
+//
+// Wheel slip from synthetic encoder and camera sequences: wheels that get
+// the travel they turn for show none, part of it is partial slip, and
+// spinning in place is all slip. The camera's travel is worked out from
+// the floor shifting down the frame, intervals that can't be judged are
+// left out, slip kept up long enough is warned about and cleared again,
+// and the planner both counts less of the wheels' travel and slows down.
+use std::time::Duration;
+
+use scout_vision::clock::CLOCK;
+use scout_vision::config::PlannerConfig;
+use scout_vision::events::{RoverEvent, Severity, EVENTS};
+use scout_vision::health::HealthRegistry;
+use scout_vision::motor_control::MotorStatus;
+use scout_vision::pathfinding::{NavigationCommand, PathPlanner};
+use scout_vision::slip::{self, SlipEstimator, VisualTravel};
+use scout_vision::vision::CameraModel;
+
+// As in tests/trajectory.rs: a floor point d ahead is 250 / d rows below
+// the horizon at row 240.
+const CAMERA: CameraModel = CameraModel { focal_length_px: 500.0, center_x_px: 320.0, horizon_row_px: 240.0, height_m: 0.5 };
+
+// Frames and wheel ticks 100 ms apart.
+const TICK_MS: u64 = 100;
+// Where the rover clock is set for the planner, well after any interval
+// the estimator is fed directly.
+const PLANNER_START_MS: u64 = 1_000_000;
+
+fn seen(distance_m: f32, from_ms: u64, to_ms: u64) -> VisualTravel {
+    VisualTravel { distance_m, confidence: 0.9, from_ms, to_ms }
+}
+
+// Drives `frames` frames at `speed` m/s, the camera seeing `grip` of the
+// wheels' travel over each, from `start_ms`. The slip of each interval.
+fn drive(estimator: &mut SlipEstimator, config: &PlannerConfig, speed: f32, grip: f32, start_ms: u64, frames: u64) -> Vec<f32> {
+    let step_m = speed * TICK_MS as f32 / 1000.0;
+    estimator.record_wheel(0.0, start_ms);
+    (1..=frames)
+        .map(|i| {
+            let to_ms = start_ms + i * TICK_MS;
+            estimator.record_wheel(step_m, to_ms);
+            estimator.update(config, seen(step_m * grip, to_ms - TICK_MS, to_ms)).expect("not judged").slip
+        })
+        .collect()
+}
+
+fn motors(speed: f32) -> MotorStatus {
+    MotorStatus { speed, target_speed: speed, heading: 0.0, enabled: true, speed_cap: None, interlock: None, heading_hold: Default::default() }
+}
+
+#[test]
+fn the_floor_shift_is_worked_out_in_metres() {
+    // Row 365 is 2 m out and row 490 1 m: a 125 px shift there is 1 m.
+    assert!((slip::floor_travel_m(&CAMERA, 365.0, 125.0).unwrap() - 1.0).abs() < 1e-4);
+    // The same distance is fewer pixels further out.
+    let far = slip::floor_travel_m(&CAMERA, 290.0, 10.0).unwrap();
+    assert!((far - (5.0 - 250.0 / 60.0)).abs() < 1e-4, "{}", far);
+    // The floor moving up the frame is driving backwards.
+    assert!((slip::floor_travel_m(&CAMERA, 490.0, -125.0).unwrap() + 1.0).abs() < 1e-4);
+    // Above the horizon, there's no floor to go by.
+    assert!(slip::floor_travel_m(&CAMERA, 200.0, 10.0).is_none());
+    assert!(slip::floor_travel_m(&CAMERA, 250.0, -20.0).is_none());
+}
+
+#[test]
+fn wheels_that_grip_show_no_slip() {
+    let config = PlannerConfig::default();
+    let mut estimator = SlipEstimator::default();
+    assert!(drive(&mut estimator, &config, 0.5, 1.0, 0, 10).iter().all(|&s| s == 0.0));
+    // Up to slip_disagreement short is put down to the camera.
+    assert!(drive(&mut estimator, &config, 0.5, 0.9, 1000, 10).iter().all(|&s| s == 0.0));
+    // As is the camera seeing further than the wheels turned.
+    assert!(drive(&mut estimator, &config, 0.5, 1.3, 2000, 10).iter().all(|&s| s == 0.0));
+    let status = estimator.get_status();
+    assert_eq!((status.slip, status.sustained, status.intervals), (0.0, false, 30));
+    let last = status.last_interval.unwrap();
+    assert_eq!((last.from_ms, last.to_ms), (2900, 3000));
+    assert!((last.wheel_m - 0.05).abs() < 1e-6 && (last.visual_m - 0.065).abs() < 1e-6);
+}
+
+#[test]
+fn partial_slip_is_the_shortfall() {
+    let config = PlannerConfig::default();
+    let mut estimator = SlipEstimator::default();
+    // Getting 60% of the travel is 40% slip, each interval and averaged.
+    let slips = drive(&mut estimator, &config, 0.5, 0.6, 0, 10);
+    assert!(slips.iter().all(|&s| (s - 0.4).abs() < 1e-4), "{:?}", slips);
+    assert!((estimator.get_slip() - 0.4).abs() < 1e-4);
+
+    // Backwards is the same: the ratio is of the signed travel.
+    let mut reversing = SlipEstimator::default();
+    let slips = drive(&mut reversing, &config, -0.5, 0.6, 0, 3);
+    assert!(slips.iter().all(|&s| (s - 0.4).abs() < 1e-4), "{:?}", slips);
+}
+
+#[test]
+fn wheels_spinning_in_place_are_all_slip() {
+    let config = PlannerConfig::default();
+    let mut estimator = SlipEstimator::default();
+    assert!(drive(&mut estimator, &config, 0.8, 0.0, 0, 5).iter().all(|&s| s == 1.0));
+    // Or the rover dragged back against them.
+    assert!(drive(&mut estimator, &config, 0.8, -0.2, 500, 5).iter().all(|&s| s == 1.0));
+    assert_eq!(estimator.get_slip(), 1.0);
+}
+
+#[test]
+fn slip_is_averaged_over_the_window() {
+    let config = PlannerConfig { slip_window: 4, ..PlannerConfig::default() };
+    let mut estimator = SlipEstimator::default();
+    drive(&mut estimator, &config, 0.5, 1.0, 0, 4);
+    drive(&mut estimator, &config, 0.5, 0.0, 400, 1);
+    assert!((estimator.get_slip() - 0.25).abs() < 1e-6);
+    drive(&mut estimator, &config, 0.5, 0.0, 500, 2);
+    assert!((estimator.get_slip() - 0.75).abs() < 1e-6);
+    // The gripping intervals have all dropped out.
+    drive(&mut estimator, &config, 0.5, 0.0, 700, 1);
+    assert_eq!(estimator.get_slip(), 1.0);
+}
+
+#[test]
+fn intervals_that_cant_be_judged_change_nothing() {
+    let config = PlannerConfig::default();
+    let mut estimator = SlipEstimator::default();
+    drive(&mut estimator, &config, 0.5, 0.5, 0, 5);
+    let before = estimator.get_status();
+
+    // The camera unsure of itself.
+    assert!(estimator.update(&config, VisualTravel { confidence: 0.1, ..seen(0.0, 400, 500) }).is_none());
+    // An interval running backwards, or of no length.
+    assert!(estimator.update(&config, seen(0.0, 500, 400)).is_none());
+    assert!(estimator.update(&config, seen(0.0, 500, 500)).is_none());
+    // From before the wheel history.
+    estimator.record_wheel(0.05, 8000);
+    assert!(estimator.update(&config, seen(0.0, 2000, 8000)).is_none());
+    // The wheels hardly turning: less than slip_min_travel_m.
+    estimator.record_wheel(0.01, 8100);
+    assert!(estimator.update(&config, seen(0.0, 8000, 8100)).is_none());
+
+    let after = estimator.get_status();
+    assert_eq!((after.slip, after.intervals), (before.slip, before.intervals));
+    assert_eq!(after.last_interval, before.last_interval);
+}
+
+#[test]
+fn wheel_travel_is_matched_to_frames_between_ticks() {
+    let config = PlannerConfig::default();
+    let mut estimator = SlipEstimator::default();
+    estimator.record_wheel(0.0, 0);
+    estimator.record_wheel(0.1, 100);
+    estimator.record_wheel(0.1, 200);
+    // Frames captured halfway between ticks: 0.1 m of wheel travel from
+    // 50 to 150 ms, of which the camera saw half.
+    let interval = estimator.update(&config, seen(0.05, 50, 150)).unwrap();
+    assert!((interval.wheel_m - 0.1).abs() < 1e-6);
+    assert!((interval.slip - 0.5).abs() < 1e-6);
+    // After the last tick the wheels are where that left them.
+    assert!(estimator.update(&config, seen(0.05, 150, 400)).is_some_and(|i| (i.wheel_m - 0.05).abs() < 1e-6));
+}
+
+#[test]
+fn sustained_slip_is_warned_about_and_cleared() {
+    let config = PlannerConfig { slip_window: 2, slip_sustain_s: 1.0, ..PlannerConfig::default() };
+    let mut estimator = SlipEstimator::default();
+    // Leaving out the planner test's, stamped later on the rover clock.
+    let ours = |e: &RoverEvent| e.data["interval"]["to_ms"].as_u64().map_or(true, |ms| ms < PLANNER_START_MS);
+    let warned = |since: u64| EVENTS.recent().into_iter().filter(|e| e.id > since && e.source == "traction" && ours(e)).collect::<Vec<_>>();
+    let since = EVENTS.recent().last().map_or(0, |e| e.id);
+
+    // Spinning for under slip_sustain_s isn't yet sustained.
+    drive(&mut estimator, &config, 0.5, 0.2, 0, 10);
+    assert!(!estimator.is_sustained());
+    assert!(warned(since).is_empty());
+    drive(&mut estimator, &config, 0.5, 0.2, 1000, 1);
+    assert!(estimator.is_sustained());
+    let events = warned(since);
+    assert_eq!(events.len(), 1, "{:?}", events.iter().map(|e| &e.message).collect::<Vec<_>>());
+    assert_eq!(events[0].severity, Severity::Warning);
+    assert!((events[0].data["slip"].as_f64().unwrap() - 0.8).abs() < 1e-4);
+    assert_eq!(events[0].data["interval"]["to_ms"], 1100);
+
+    // Staying sustained doesn't warn again; regaining grip says so once.
+    drive(&mut estimator, &config, 0.5, 0.2, 1100, 5);
+    drive(&mut estimator, &config, 0.5, 1.0, 1600, 5);
+    assert!(!estimator.is_sustained());
+    let events = warned(since);
+    assert_eq!(events.iter().map(|e| e.severity).collect::<Vec<_>>(), [Severity::Warning, Severity::Info]);
+    assert_eq!(events[1].message, "Traction regained");
+
+    // Dipping under slip_warn_ratio restarts the clock.
+    drive(&mut estimator, &config, 0.5, 0.2, 2100, 8);
+    drive(&mut estimator, &config, 0.5, 1.0, 2900, 2);
+    drive(&mut estimator, &config, 0.5, 0.2, 3100, 8);
+    assert!(!estimator.is_sustained());
+    estimator.reset();
+    assert_eq!((estimator.get_slip(), estimator.get_status().intervals), (0.0, 0));
+}
+
+#[test]
+fn the_planner_counts_less_of_slipping_wheels_and_slows_down() {
+    let config = PlannerConfig { envelope_unknown_curve: Vec::new(), slip_window: 1, slip_sustain_s: 0.0, ..PlannerConfig::default() };
+    let mut planner = PathPlanner::new(&HealthRegistry::new(), config.clone());
+    CLOCK.set_manual(PLANNER_START_MS);
+    let tick = Duration::from_millis(TICK_MS);
+    planner.update_pose(&motors(0.5), Duration::ZERO);
+    let cruising = match planner.get_navigation_command() {
+        NavigationCommand::Forward(speed) => speed,
+        command => panic!("{:?} on an empty map", command),
+    };
+
+    // A frame's worth of driving with the camera seeing a quarter of it.
+    let from_ms = CLOCK.now_ms();
+    CLOCK.advance(tick);
+    planner.update_pose(&motors(0.5), tick);
+    assert!((planner.get_pose().y - 0.05).abs() < 1e-4, "without slip all of it counts");
+    planner.update_slip(seen(0.0125, from_ms, CLOCK.now_ms()));
+    let traction = planner.get_traction_status();
+    assert!((traction.slip - 0.75).abs() < 1e-4);
+    assert!(traction.sustained);
+
+    // The next 0.05 m the wheels turn for is taken as a quarter of that.
+    CLOCK.advance(tick);
+    planner.update_pose(&motors(0.5), tick);
+    assert!((planner.get_pose().y - 0.0625).abs() < 1e-4, "{}", planner.get_pose().y);
+    match planner.get_navigation_command() {
+        NavigationCommand::Forward(speed) => assert!((speed - cruising * config.slip_speed_factor).abs() < 1e-4, "{} vs {}", speed, cruising),
+        command => panic!("{:?} while slipping", command),
+    }
+
+    // Turned off, slip is forgotten and changes nothing.
+    let mut planner = PathPlanner::new(&HealthRegistry::new(), PlannerConfig { slip_enabled: false, ..config });
+    planner.update_pose(&motors(0.5), Duration::ZERO);
+    let from_ms = CLOCK.now_ms();
+    CLOCK.advance(tick);
+    planner.update_pose(&motors(0.5), tick);
+    planner.update_slip(seen(0.0, from_ms, CLOCK.now_ms()));
+    assert_eq!(planner.get_traction_status().intervals, 0);
+}
@@ -0,0 +1,186 @@
+// tests/snapshot.rs
+//
+// Snapshot encoders: a reference frame through baseline, progressive and
+// WebP, decoded again and compared, with the sizes and times printed
+// (`cargo test --test snapshot -- --nocapture`); the cap on encodes at once;
+// and the format, quality and Content-Type on GET /api/frame.jpg and
+// /api/stream.mjpeg.
+use anyhow::Result;
+use base64::Engine;
+use image::RgbImage;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use warp::http::StatusCode;
+use warp::hyper::body::HttpBody;
+
+use scout_vision::clock::CLOCK;
+use scout_vision::config::SnapshotFormat;
+use scout_vision::simulation;
+use scout_vision::snapshot::{self, EncoderPool};
+
+mod common;
+use common::Sim;
+
+// 640x480 like the camera: a graded sky, hard-edged blocks and some sensor
+// noise, so neither entropy coder gets an easy ride.
+fn reference_frame() -> RgbImage {
+    let mut seed = 0x2545_f491_u32;
+    RgbImage::from_fn(640, 480, |x, y| {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        let noise = (seed % 9) as i32 - 4;
+        let base = if (x / 80 + y / 60) % 3 == 0 && y > 240 {
+            [200, 60 + (x % 80) as i32, 40]
+        } else {
+            [(x * 255 / 640) as i32, (y * 255 / 480) as i32, 160]
+        };
+        image::Rgb(base.map(|c| (c + noise).clamp(0, 255) as u8))
+    })
+}
+
+// Peak signal to noise in dB against the reference; infinite when exact.
+fn psnr(reference: &RgbImage, decoded: &RgbImage) -> f64 {
+    let squared: f64 = reference
+        .as_raw()
+        .iter()
+        .zip(decoded.as_raw())
+        .map(|(a, b)| (f64::from(*a) - f64::from(*b)).powi(2))
+        .sum();
+    let mse = squared / reference.as_raw().len() as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+#[test]
+fn each_format_decodes_close_to_the_reference() -> Result<()> {
+    let reference = reference_frame();
+    let mut cases = vec![
+        (SnapshotFormat::Baseline, 85, 33.0),
+        (SnapshotFormat::Progressive, 85, 33.0),
+        (SnapshotFormat::Progressive, 50, 30.0),
+    ];
+    if cfg!(feature = "webp") {
+        cases.extend([(SnapshotFormat::Webp, 100, f64::INFINITY), (SnapshotFormat::Webp, 85, 40.0)]);
+    }
+
+    println!("{:<12} {:>7} {:>9} {:>8} {:>8}", "format", "quality", "bytes", "ms", "psnr");
+    let mut sizes = Vec::new();
+    for (format, quality, min_psnr) in cases {
+        let started = Instant::now();
+        let bytes = snapshot::encode(&reference, format, quality)?;
+        let elapsed = started.elapsed();
+        let decoded = image::load_from_memory(&bytes)?.to_rgb8();
+        assert_eq!(decoded.dimensions(), (640, 480));
+        let psnr = psnr(&reference, &decoded);
+        println!(
+            "{:<12} {:>7} {:>9} {:>8.1} {:>8.1}",
+            format.as_str(),
+            quality,
+            bytes.len(),
+            elapsed.as_secs_f64() * 1000.0,
+            psnr,
+        );
+        assert!(psnr >= min_psnr, "{} at {}: {:.1} dB", format.as_str(), quality, psnr);
+        sizes.push((format, quality, bytes));
+    }
+
+    let find = |format, quality| sizes.iter().find(|s| s.0 == format && s.1 == quality).map(|s| &s.2).unwrap();
+    let baseline = find(SnapshotFormat::Baseline, 85);
+    let progressive = find(SnapshotFormat::Progressive, 85);
+    // SOF2, not SOF0, and with its tuned tables about baseline's size; the
+    // two encoders scale quality a little differently.
+    assert!(progressive.windows(2).any(|w| w == [0xff, 0xc2]));
+    assert!(!progressive.windows(2).any(|w| w == [0xff, 0xc0]));
+    assert!(progressive.len() * 10 <= baseline.len() * 11, "{} progressive, {} baseline", progressive.len(), baseline.len());
+    assert!(find(SnapshotFormat::Progressive, 50).len() < progressive.len());
+    if cfg!(feature = "webp") {
+        let lossless = find(SnapshotFormat::Webp, 100);
+        assert_eq!((&lossless[..4], &lossless[8..16]), (&b"RIFF"[..], &b"WEBPVP8L"[..]));
+        assert_eq!(image::load_from_memory(lossless)?.to_rgb8(), reference);
+        assert!(find(SnapshotFormat::Webp, 85).len() < lossless.len());
+    }
+    Ok(())
+}
+
+#[test]
+fn encodes_past_the_cap_wait_or_are_skipped() -> Result<()> {
+    let pool = EncoderPool::new(1);
+    let (release, held) = mpsc::channel::<()>();
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let first = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                let draw = move || {
+                    held.recv()?;
+                    Ok(reference_frame())
+                };
+                pool.encode(draw, SnapshotFormat::Baseline, 80).await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let skipped = pool.try_encode(|| Ok(reference_frame()), SnapshotFormat::Baseline, 80).await;
+        assert!(skipped.is_none(), "encoded past the cap");
+
+        release.send(())?;
+        assert!(!first.await??.is_empty());
+        let second = pool.try_encode(|| Ok(reference_frame()), SnapshotFormat::Baseline, 80).await;
+        assert!(second.is_some_and(|encoded| encoded.is_ok()));
+        Ok(())
+    })
+}
+
+#[test]
+fn endpoints_take_the_format_and_quality() -> Result<()> {
+    CLOCK.set_manual(CLOCK.now_ms());
+    let sim = Sim::boot("snapshot", &[serde_json::json!({ "snapshot": { "format": "progressive", "quality": 70 } })])?;
+    let routes = sim.web().routes();
+
+    let mut frame = simulation::synthetic_frame(1, Vec::new());
+    let jpeg = snapshot::encode(&reference_frame(), SnapshotFormat::Baseline, 90)?;
+    frame.jpeg_base64 = base64::engine::general_purpose::STANDARD.encode(jpeg);
+    sim.frames.send(frame);
+    CLOCK.advance(Duration::from_millis(20));
+    sim.rover.tick(Duration::from_millis(20))?;
+
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let get = |path: &str| warp::test::request().path(path).reply(&routes);
+
+        // The snapshot section's format when the query has none.
+        let default = get("/api/frame.jpg").await;
+        assert_eq!(default.status(), StatusCode::OK);
+        assert_eq!(default.headers()["content-type"], "image/jpeg");
+        assert!(default.body().windows(2).any(|w| w == [0xff, 0xc2]));
+
+        let baseline = get("/api/frame.jpg?format=baseline&quality=95").await;
+        assert!(baseline.body().windows(2).any(|w| w == [0xff, 0xc0]));
+        let small = get("/api/frame.jpg?format=baseline&quality=20").await;
+        assert!(small.body().len() < baseline.body().len());
+
+        let webp = get("/api/frame.jpg?format=webp").await;
+        if cfg!(feature = "webp") {
+            assert_eq!(webp.headers()["content-type"], "image/webp");
+            assert_eq!(image::load_from_memory(webp.body())?.to_rgb8().dimensions(), (640, 480));
+        } else {
+            assert_eq!(webp.status(), StatusCode::BAD_REQUEST);
+        }
+        for bad in ["/api/frame.jpg?quality=0", "/api/frame.jpg?quality=101", "/api/stream.mjpeg?fps=0"] {
+            assert_eq!(get(bad).await.status(), StatusCode::BAD_REQUEST, "{}", bad);
+        }
+
+        // The stream goes on for as long as the client stays, so read only
+        // its first part.
+        let mut stream = warp::test::request()
+            .path("/api/stream.mjpeg?format=baseline&fps=10")
+            .filter(&routes)
+            .await
+            .map_err(|rejection| anyhow::anyhow!("{:?}", rejection))?;
+        assert_eq!(stream.headers()["content-type"], "multipart/x-mixed-replace; boundary=frame");
+        let part = stream.body_mut().data().await.unwrap()?;
+        let header_end = part.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let header = std::str::from_utf8(&part[..header_end])?;
+        assert!(header.starts_with("--frame\r\nContent-Type: image/jpeg\r\n"), "{}", header);
+        let length: usize = header.rsplit("Content-Length: ").next().unwrap().parse()?;
+        assert_eq!(part.len(), header_end + 4 + length + 2);
+        Ok(())
+    })
+}
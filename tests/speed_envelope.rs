@@ -0,0 +1,120 @@
+// tests/speed_envelope.rs
+//
+// Lays out occupancy grids by hand, a narrow corridor, an open field and
+// unmapped floor, and checks the speed the map allows the rover through
+// each. Then the whole planner: an obstacle seen and gone out of sight
+// still holds the rover down as it drives past where it was.
+use scout_vision::config::{PlannerConfig, VisionConfig};
+use scout_vision::envelope::{EnvelopeFactor, SpeedEnvelope};
+use scout_vision::health::HealthRegistry;
+use scout_vision::mapping::{Cell, OccupancyGrid, RoverPose};
+use scout_vision::pathfinding::{NavigationCommand, PathPlanner};
+use scout_vision::vision::{ActionPolicy, ConfidenceCurve, Detection, DistanceEstimator};
+
+const RESOLUTION_M: f32 = 0.1;
+const GRID_CELLS: usize = 100;
+
+fn config() -> PlannerConfig {
+    PlannerConfig {
+        envelope_lookahead_m: 2.0,
+        envelope_clearance_curve: vec![(0.2, 0.1), (0.5, 0.3), (1.5, 1.0)],
+        envelope_unknown_curve: vec![(0.25, 1.0), (1.0, 0.2)],
+        ..PlannerConfig::default()
+    }
+}
+
+// Everything within `half_m` of the origin has been driven over.
+fn mapped(half_m: f32) -> OccupancyGrid {
+    let mut grid = OccupancyGrid::new(GRID_CELLS, RESOLUTION_M);
+    let (cols, rows) = grid.cells_in((-half_m, -half_m), (half_m, half_m));
+    for row in rows {
+        for col in cols.clone() {
+            grid.set(col, row, Cell::Free);
+        }
+    }
+    grid
+}
+
+// Heading along +y from the origin.
+fn ahead() -> RoverPose {
+    RoverPose::default()
+}
+
+#[test]
+fn narrow_corridor_forces_slow() {
+    let mut grid = mapped(4.0);
+    // Walls either side, their cells' middles 0.35 m off the path.
+    for i in 0..40 {
+        let y = i as f32 * RESOLUTION_M;
+        grid.set_world(-0.35, y, Cell::Occupied);
+        grid.set_world(0.35, y, Cell::Occupied);
+    }
+
+    let envelope = SpeedEnvelope::evaluate(&grid, &ahead(), &config());
+    let clearance = envelope.clearance_m.expect("the walls are in range");
+    assert!((clearance - 0.35).abs() < 0.01, "{:?}", envelope);
+    assert_eq!(envelope.limited_by, Some(EnvelopeFactor::Clearance));
+    let max = envelope.max_speed.unwrap();
+    assert!((max - 0.2).abs() < 0.01, "{:?}", envelope);
+    assert_eq!(envelope.unknown_fraction, 0.0);
+}
+
+#[test]
+fn open_field_allows_max() {
+    let grid = mapped(4.0);
+    let envelope = SpeedEnvelope::evaluate(&grid, &ahead(), &config());
+    assert_eq!(envelope.clearance_m, None);
+    assert_eq!(envelope.max_speed, Some(1.0), "{:?}", envelope);
+
+    // A boulder well off to the side doesn't count.
+    let mut grid = mapped(4.0);
+    grid.set_world(1.8, 0.6, Cell::Occupied);
+    assert_eq!(SpeedEnvelope::evaluate(&grid, &ahead(), &config()).max_speed, Some(1.0));
+
+    // Turned toward it, it does.
+    let facing = RoverPose { heading_deg: 1.85f32.atan2(0.65).to_degrees(), ..ahead() };
+    let envelope = SpeedEnvelope::evaluate(&grid, &facing, &config());
+    assert!(envelope.max_speed.unwrap() < 0.2, "{:?}", envelope);
+}
+
+#[test]
+fn unmapped_floor_is_taken_slowly() {
+    // Only the first half metre has been driven over.
+    let grid = mapped(0.5);
+    let envelope = SpeedEnvelope::evaluate(&grid, &ahead(), &config());
+    assert!((envelope.unknown_fraction - 0.75).abs() < 0.06, "{:?}", envelope);
+    assert_eq!(envelope.limited_by, Some(EnvelopeFactor::Unknown));
+    assert!(envelope.max_speed.unwrap() < 0.5, "{:?}", envelope);
+
+    // Without curves nothing is limited.
+    let unlimited = PlannerConfig { envelope_clearance_curve: Vec::new(), envelope_unknown_curve: Vec::new(), ..config() };
+    let envelope = SpeedEnvelope::evaluate(&grid, &ahead(), &unlimited);
+    assert_eq!((envelope.max_speed, envelope.limited_by), (None, None));
+}
+
+#[test]
+fn remembered_obstacle_out_of_sight_still_slows_the_planner() {
+    let health = HealthRegistry::new();
+    let config = PlannerConfig { cruise_speed: 0.8, envelope_unknown_curve: Vec::new(), ..config() };
+    let mut planner = PathPlanner::new(&health, config);
+    let vision = VisionConfig::default();
+
+    planner.update_ranges(Vec::new(), 0.0);
+    assert!(matches!(planner.get_navigation_command(), NavigationCommand::Forward(speed) if speed == 0.8));
+
+    // Seen about 0.75 m ahead, then gone from the frame.
+    let bbox = (300, 100, 40, 333);
+    let detection = Detection::new("box".into(), 0.9, bbox, 1, &ConfidenceCurve::default(), &DistanceEstimator::new(&vision), &ActionPolicy::new(&vision));
+    assert!(detection.distance_estimate > 0.5 && detection.distance_estimate < 1.0, "{}", detection.distance_estimate);
+    planner.update_obstacles(&[detection]);
+    planner.update_obstacles(&[]);
+    planner.update_ranges(Vec::new(), 0.0);
+
+    let envelope = planner.get_status().speed_envelope;
+    assert_eq!(envelope.limited_by, Some(EnvelopeFactor::Clearance));
+    let max = envelope.max_speed.unwrap();
+    match planner.get_navigation_command() {
+        NavigationCommand::Forward(speed) => assert!(speed == max && speed < 0.2, "{} under {:?}", speed, envelope),
+        command => panic!("{:?}", command),
+    }
+}
@@ -0,0 +1,119 @@
+// tests/startup.rs
+//
+// Boots the rover with each subsystem started behind the web server
+// failing in turn: a rules file that isn't there yet for vision, and a
+// motor driver enable output that can't be opened for the motors. The
+// rover should come up anyway, hold itself in Fault with the failure
+// reported, and get going once the subsystem is retried.
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use scout_vision::config::ConfigStore;
+use scout_vision::rover::Rover;
+use scout_vision::simulation::FrameSource;
+use scout_vision::startup::{SubsystemState, SubsystemStatus};
+use scout_vision::state_machine::{RoverState, Trigger};
+
+const TICK: Duration = Duration::from_millis(20);
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("startup").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn boot(dir: &std::path::Path, patch: serde_json::Value) -> Result<Rover> {
+    let config = ConfigStore::load(dir.join("rover.toml"))?;
+    config.update(&serde_json::json!({ "selftest": { "enabled": false } })).map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+    config.update(&patch).map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+    let rover = Rover::new(Arc::new(config), FrameSource::Manual)?;
+    rover.start_subsystems();
+    rover.selftest.start();
+    Ok(rover)
+}
+
+// Once nothing is initializing any more.
+fn settle(rover: &Rover) -> Vec<SubsystemStatus> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while rover.startup.is_initializing() {
+        assert!(Instant::now() < deadline, "still starting: {:?}", rover.state.get_subsystems());
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    rover.state.get_subsystems()
+}
+
+fn status<'a>(statuses: &'a [SubsystemStatus], name: &str) -> &'a SubsystemStatus {
+    statuses.iter().find(|s| s.name == name).unwrap()
+}
+
+fn tick(rover: &Rover, ticks: u32) -> Result<RoverState> {
+    for _ in 0..ticks {
+        rover.tick(TICK)?;
+    }
+    Ok(rover.state.get_state())
+}
+
+#[test]
+fn vision_failing_at_boot_holds_fault_until_retried() -> Result<()> {
+    let dir = scratch("vision");
+    let rules = dir.join("rules.toml");
+    let rover = boot(&dir, serde_json::json!({ "vision": { "rules_path": rules } }))?;
+
+    let statuses = settle(&rover);
+    let vision = status(&statuses, "vision");
+    assert_eq!((vision.state, vision.attempts), (SubsystemState::Failed, 1));
+    assert!(vision.error.as_deref().is_some_and(|e| e.contains("rules.toml")), "{:?}", vision.error);
+    assert_eq!(status(&statuses, "motors").state, SubsystemState::Ready);
+
+    assert_eq!(tick(&rover, 3)?, RoverState::Fault);
+    let reason = rover.state.get_status().reason.unwrap_or_default();
+    assert!(reason.starts_with("vision failed to start"), "{}", reason);
+    // Acknowledging doesn't get the rover out while vision is down.
+    rover.state.request(Trigger::AcknowledgeFault);
+    assert_eq!(tick(&rover, 3)?, RoverState::Fault);
+
+    assert!(rover.state.retry_subsystem("motors").is_err(), "retried a subsystem that is ready");
+    assert!(rover.state.retry_subsystem("lidar").is_err());
+    std::fs::write(&rules, "[[rules]]\nname = \"people\"\nrule = 'when class == \"person\" and distance < 1.5 then action Stop priority 90'\n")?;
+    rover.state.retry_subsystem("vision").map_err(anyhow::Error::msg)?;
+    let statuses = settle(&rover);
+    let vision = status(&statuses, "vision");
+    assert_eq!((vision.state, vision.attempts, vision.error.as_deref()), (SubsystemState::Ready, 2, None));
+    assert_eq!(rover.vision.read().get_rules().len(), 1);
+
+    // Still faulted until acknowledged.
+    assert_eq!(tick(&rover, 3)?, RoverState::Fault);
+    rover.state.request(Trigger::AcknowledgeFault);
+    assert_eq!(tick(&rover, 3)?, RoverState::Idle);
+    Ok(())
+}
+
+#[test]
+fn motors_failing_at_boot_hold_fault_until_retried() -> Result<()> {
+    let dir = scratch("motors");
+    // Nothing here has a GPIO chip to open.
+    let rover = boot(&dir, serde_json::json!({ "power": { "motor_enable_pin": 17, "motor_enable_driver": "gpio" } }))?;
+
+    let statuses = settle(&rover);
+    let motors = status(&statuses, "motors");
+    assert_eq!(motors.state, SubsystemState::Failed);
+    assert!(motors.error.as_deref().is_some_and(|e| e.contains("motor driver enable")), "{:?}", motors.error);
+    assert_eq!(status(&statuses, "vision").state, SubsystemState::Ready);
+
+    assert_eq!(tick(&rover, 3)?, RoverState::Fault);
+    assert!(rover.state.get_status().reason.unwrap_or_default().starts_with("motors failed to start"));
+    rover.state.request(Trigger::StartAutonomous);
+    assert_eq!(tick(&rover, 3)?, RoverState::Fault);
+
+    // Retried on what the config says by then.
+    rover.config.update(&serde_json::json!({ "power": { "motor_enable_driver": "mock" } })).map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+    rover.state.retry_subsystem("motors").map_err(anyhow::Error::msg)?;
+    let statuses = settle(&rover);
+    assert_eq!(status(&statuses, "motors").state, SubsystemState::Ready);
+    rover.state.request(Trigger::AcknowledgeFault);
+    assert_eq!(tick(&rover, 3)?, RoverState::Idle);
+    Ok(())
+}
@@ -0,0 +1,169 @@
+// tests/stopping_distance.rs
+//
+// Drives the whole rover stack (vision, planner, state machine, motor
+// ramp) through simulated time at a range of speed caps, puts an obstacle
+// in its path at a range of distances, and checks it always comes to rest
+// short of it. The measured stopping distances are written as a table to
+// target/tmp/stopping_distances.md, and how far it goes on once frames
+// stop to target/tmp/stale_frame_stops.md.
+use anyhow::Result;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use scout_vision::clock::CLOCK;
+use scout_vision::vision::IMX500Detection;
+
+mod common;
+use common::{Sim, SERIAL};
+
+// Control loop period, and how many ticks pass between camera frames.
+const TICK: Duration = Duration::from_millis(20);
+const TICKS_PER_FRAME: u32 = 2;
+const DECEL_M_S2: f32 = 1.5;
+const MAX_FRAME_AGE_MS: u64 = 300;
+// Distance from the rover's reference point to its front bumper; the
+// obstacle must never come closer than this.
+const FOOTPRINT_M: f32 = 0.25;
+// Matches the defaults the detection's box height is worked out from.
+const FOCAL_LENGTH_PX: f32 = 500.0;
+const OBSTACLE_HEIGHT_M: f32 = 0.5;
+
+const SPEEDS_M_S: [f32; 4] = [0.25, 0.5, 0.75, 1.0];
+const APPEARANCE_M: [f32; 4] = [1.0, 1.5, 2.5, 4.0];
+
+impl Sim {
+    fn new(speed: f32) -> Result<Self> {
+        CLOCK.set_manual(CLOCK.now_ms());
+        Sim::boot("stopping-distance", &[serde_json::json!({
+            "control": { "start_autonomous": true },
+            "motors": { "decel_m_s2": DECEL_M_S2 },
+            "planner": { "cruise_speed": speed },
+            "vision": {
+                "max_frame_age_ms": MAX_FRAME_AGE_MS,
+                "focal_length_px": FOCAL_LENGTH_PX,
+                "default_object_height_m": OBSTACLE_HEIGHT_M,
+            },
+        })])
+    }
+
+    // One tick, with a frame first on every TICKS_PER_FRAME-th one.
+    // `obstacle_y` is where the obstacle is, if the camera sees it.
+    fn step(&mut self, camera: bool, obstacle_y: Option<f32>) -> Result<()> {
+        if camera && self.ticks % TICKS_PER_FRAME == 0 {
+            let detections: Vec<_> = obstacle_y.map(|y| obstacle_box(y - self.get_y())).into_iter().collect();
+            self.send(&detections);
+        }
+        self.ticks += 1;
+        CLOCK.advance(TICK);
+        self.rover.tick(TICK)
+    }
+
+    fn get_y(&self) -> f32 {
+        self.rover.path_planner.read().get_pose().y
+    }
+
+    fn get_speed(&self) -> f32 {
+        self.rover.motor_controller.read().get_speed()
+    }
+
+    // Drives with a clear view until the wheels reach `speed`.
+    fn reach(&mut self, speed: f32) -> Result<()> {
+        for _ in 0..500 {
+            if self.get_speed() >= speed {
+                return Ok(());
+            }
+            self.step(true, None)?;
+        }
+        anyhow::bail!("never reached {} m/s, at {} m/s", speed, self.get_speed())
+    }
+
+    // Steps until the rover has been at rest for a while, and returns how
+    // far it went.
+    fn stop(&mut self, camera: bool, obstacle_y: Option<f32>) -> Result<f32> {
+        let start = self.get_y();
+        let mut still = 0;
+        for _ in 0..1000 {
+            self.step(camera, obstacle_y)?;
+            still = if self.get_speed() == 0.0 { still + 1 } else { 0 };
+            if still >= 25 {
+                return Ok(self.get_y() - start);
+            }
+        }
+        anyhow::bail!("still moving at {} m/s", self.get_speed())
+    }
+}
+
+// A box straight ahead, as tall as the obstacle looks from `distance`.
+fn obstacle_box(distance: f32) -> IMX500Detection {
+    let h = (OBSTACLE_HEIGHT_M * FOCAL_LENGTH_PX / distance.max(0.01)).round() as i32;
+    IMX500Detection {
+        class: "box".to_string(),
+        class_id: None,
+        conf: 0.9,
+        x: 300,
+        y: 240 - h / 2,
+        w: 40,
+        h,
+        mask: None,
+    }
+}
+
+// Up to how far the rover can go from the moment something changes: a
+// frame interval until it is seen, a tick for the command and one for the
+// ramp to start, then braking at DECEL_M_S2.
+fn stopping_bound(speed: f32, delay: Duration) -> f32 {
+    let reaction = delay + TICK * (TICKS_PER_FRAME + 2);
+    speed * reaction.as_secs_f32() + speed * speed / (2.0 * DECEL_M_S2) + 0.01
+}
+
+fn write_artifact(name: &str, table: &str) {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::write(&path, table).unwrap();
+    println!("{}\nWritten to {}", table, path.display());
+}
+
+#[test]
+fn rover_stops_short_of_obstacles_at_every_speed() -> Result<()> {
+    let _serial = SERIAL.lock();
+    let mut table = String::from("| speed (m/s) | appears at (m) | stopping distance (m) | bound (m) | clearance (m) |\n|---|---|---|---|---|\n");
+    for speed in SPEEDS_M_S {
+        for appearance in APPEARANCE_M {
+            let mut sim = Sim::new(speed)?;
+            sim.reach(speed)?;
+            let obstacle_y = sim.get_y() + appearance;
+            let stopped = sim.stop(true, Some(obstacle_y))?;
+            let clearance = obstacle_y - sim.get_y();
+            let bound = stopping_bound(speed, Duration::ZERO);
+            writeln!(table, "| {:.2} | {:.1} | {:.3} | {:.3} | {:.3} |", speed, appearance, stopped, bound, clearance)?;
+            assert!(
+                clearance > FOOTPRINT_M,
+                "at {} m/s an obstacle appearing {} m ahead ended {:.3} m away",
+                speed, appearance, clearance,
+            );
+            assert!(stopped <= bound, "at {} m/s the rover took {:.3} m to stop, over {:.3} m", speed, stopped, bound);
+        }
+    }
+    write_artifact("stopping_distances.md", &table);
+    Ok(())
+}
+
+#[test]
+fn rover_stops_when_frames_stop() -> Result<()> {
+    let _serial = SERIAL.lock();
+    let mut table = String::from("| speed (m/s) | distance after last frame (m) | bound (m) |\n|---|---|---|\n");
+    for speed in SPEEDS_M_S {
+        let mut sim = Sim::new(speed)?;
+        sim.reach(speed)?;
+        // Align with the last frame, so the distance counts from its capture.
+        while sim.ticks % TICKS_PER_FRAME != 0 {
+            sim.step(true, None)?;
+        }
+        sim.step(true, None)?;
+        let stopped = sim.stop(false, None)?;
+        let bound = stopping_bound(speed, Duration::from_millis(MAX_FRAME_AGE_MS));
+        writeln!(table, "| {:.2} | {:.3} | {:.3} |", speed, stopped, bound)?;
+        assert!(stopped <= bound, "at {} m/s the rover went {:.3} m on without frames, over {:.3} m", speed, stopped, bound);
+    }
+    write_artifact("stale_frame_stops.md", &table);
+    Ok(())
+}
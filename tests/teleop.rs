@@ -0,0 +1,181 @@
+// tests/teleop.rs
+//
+// The gamepad protocol: axis commands shaped by the teleop config and
+// governed by the speed envelope, stopping the rover the moment the
+// dead-man is let go or too many commands go missing, and picking back up
+// once commands arrive in order again.
+use anyhow::Result;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use scout_vision::config::{ConfigStore, TeleopConfig};
+use scout_vision::rover::Rover;
+use scout_vision::simulation::FrameSource;
+use scout_vision::state_machine::{RoverState, Trigger};
+use scout_vision::teleop::{shape, AxisCommand, AxisOutcome, AxisResult, TeleopSession};
+
+const TICK: Duration = Duration::from_millis(10);
+
+fn axis(seq: u64, deadman: bool, linear: f32, angular: f32) -> AxisCommand {
+    AxisCommand { seq, deadman, linear, angular }
+}
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4
+}
+
+#[test]
+fn axes_follow_the_deadzone_curve_and_inversion() {
+    assert_eq!(shape(0.05, 0.1, 2.0), 0.0);
+    assert_eq!(shape(-0.1, 0.1, 2.0), 0.0);
+    assert!(close(shape(1.0, 0.1, 2.0), 1.0) && close(shape(-1.0, 0.1, 2.0), -1.0));
+    assert!(close(shape(1.7, 0.1, 2.0), 1.0), "past full deflection");
+    assert!(close(shape(0.55, 0.1, 0.0), 0.5), "expo 0 is linear past the deadzone");
+    let half = shape(0.55, 0.1, 2.0);
+    assert!(close(half, 1.0f32.exp_m1() / 2.0f32.exp_m1()), "{}", half);
+    assert!(close(shape(-0.55, 0.1, 2.0), -half));
+    assert!(shape(0.55, 0.1, 4.0) < half, "more expo is finer near the centre");
+
+    let config = TeleopConfig { deadzone: 0.0, expo: 0.0, invert_angular: true, ..TeleopConfig::default() };
+    let outcome = TeleopSession::new().handle(&axis(1, true, 0.5, 0.5), &config, None);
+    assert_eq!(outcome.result, AxisResult::Applied);
+    assert!(close(outcome.linear_m_s, 0.5 * config.max_linear_m_s), "{:?}", outcome);
+    assert!(close(outcome.angular_rad_s, -0.5 * config.max_angular_rad_s), "{:?}", outcome);
+}
+
+#[test]
+fn the_speed_envelope_caps_forward_not_reverse() {
+    let config = TeleopConfig::default();
+    let mut session = TeleopSession::new();
+    let forward = session.handle(&axis(1, true, 1.0, 0.0), &config, Some(0.2));
+    assert!(close(forward.linear_m_s, 0.2), "{:?}", forward);
+    let reverse = session.handle(&axis(2, true, -1.0, 0.0), &config, Some(0.2));
+    assert!(close(reverse.linear_m_s, -config.max_linear_m_s), "{:?}", reverse);
+    let unlimited = session.handle(&axis(3, true, 1.0, 0.0), &config, Some(5.0));
+    assert!(close(unlimited.linear_m_s, config.max_linear_m_s), "{:?}", unlimited);
+}
+
+#[test]
+fn lost_and_reordered_commands() {
+    let config = TeleopConfig { max_sequence_gap: 2, ..TeleopConfig::default() };
+    let mut session = TeleopSession::new();
+    let mut send = |seq| session.handle(&axis(seq, true, 0.8, 0.0), &config, None);
+    assert_eq!(send(100).result, AxisResult::Applied, "any first sequence number");
+    // Two lost is within what the link is allowed.
+    assert_eq!(send(103).result, AxisResult::Applied);
+    let gap = send(107);
+    assert_eq!((gap.result, gap.missed, gap.linear_m_s), (AxisResult::Gap, 3, 0.0));
+    // Late, duplicated or reordered commands are dropped, not driven on.
+    assert_eq!(send(105).result, AxisResult::Stale);
+    assert_eq!(send(107).result, AxisResult::Stale);
+    // The next one in order after a gap drives again.
+    assert_eq!(send(108).result, AxisResult::Applied);
+
+    let mut session = TeleopSession::new();
+    assert_eq!(session.handle(&axis(1, true, f32::NAN, 0.0), &config, None).result, AxisResult::Invalid);
+    assert!(!session.is_driving());
+    assert_eq!(session.handle(&axis(2, false, 1.0, 0.0), &config, None).result, AxisResult::Released);
+    assert!(!session.is_driving());
+}
+
+struct Sim {
+    rover: Rover,
+    session: TeleopSession,
+    config: TeleopConfig,
+}
+
+impl Sim {
+    fn new() -> Result<Self> {
+        let config = ConfigStore::load(std::env::temp_dir().join("scout-teleop-unused.toml"))?;
+        config.update(&serde_json::json!({
+            "selftest": { "enabled": false },
+            "control": { "start_autonomous": false },
+        })).map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+        let teleop = config.get().teleop;
+        let rover = Rover::new(Arc::new(config), FrameSource::Manual)?;
+        rover.start_subsystems();
+        while rover.startup.is_initializing() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        rover.selftest.start();
+        Ok(Self { rover, session: TeleopSession::new(), config: teleop })
+    }
+
+    // Once booted, since arming during boot is dropped.
+    fn take_manual(&self) -> Result<()> {
+        for want in [RoverState::Idle, RoverState::Manual] {
+            if want == RoverState::Manual {
+                self.rover.state.request(Trigger::TakeManual);
+            }
+            let mut ticks = 0;
+            while self.rover.state.get_state() != want {
+                anyhow::ensure!(ticks < 100, "never {}: {:?}", want.as_str(), self.rover.state.get_status());
+                self.rover.tick(TICK)?;
+                ticks += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, command: AxisCommand) -> AxisOutcome {
+        self.session.drive(&command, &self.config, &self.rover.state)
+    }
+
+    fn get_target_speed(&self) -> f32 {
+        self.rover.motor_controller.read().get_status().target_speed
+    }
+
+    // Drives forward on commands from `seq` on, a tick after each.
+    fn drive(&mut self, seq: std::ops::Range<u64>) -> Result<()> {
+        for seq in seq {
+            assert_eq!(self.send(axis(seq, true, 1.0, 0.0)).result, AxisResult::Applied);
+            self.rover.tick(TICK)?;
+        }
+        anyhow::ensure!(self.get_target_speed() > 0.0, "not driving: {:?}", self.rover.state.get_status());
+        Ok(())
+    }
+}
+
+#[test]
+fn only_manual_mode_takes_axis_commands() -> Result<()> {
+    let mut sim = Sim::new()?;
+    assert_eq!(sim.send(axis(1, true, 1.0, 0.0)).result, AxisResult::Ignored);
+    assert!(!sim.session.is_driving());
+    sim.rover.tick(TICK)?;
+    assert_eq!(sim.get_target_speed(), 0.0);
+    sim.take_manual()?;
+    sim.drive(2..5)
+}
+
+#[test]
+fn releasing_the_deadman_stops_at_once() -> Result<()> {
+    let mut sim = Sim::new()?;
+    sim.take_manual()?;
+    sim.drive(1..5)?;
+    // Still pushing the stick, but the button is let go: stopped before
+    // the next tick.
+    assert_eq!(sim.send(axis(5, false, 1.0, 0.0)).result, AxisResult::Released);
+    assert_eq!(sim.get_target_speed(), 0.0);
+    sim.rover.tick(TICK)?;
+    assert_eq!(sim.get_target_speed(), 0.0);
+    assert_eq!(sim.rover.state.get_state(), RoverState::Manual);
+    sim.drive(6..9)
+}
+
+#[test]
+fn packet_loss_stops_until_commands_resync() -> Result<()> {
+    let mut sim = Sim::new()?;
+    sim.take_manual()?;
+    sim.drive(1..5)?;
+    let gap = 5 + sim.config.max_sequence_gap + 1;
+    assert_eq!(sim.send(axis(gap, true, 1.0, 0.0)).result, AxisResult::Gap);
+    assert_eq!(sim.get_target_speed(), 0.0);
+    // The lost commands turning up late change nothing.
+    for seq in 5..gap {
+        assert_eq!(sim.send(axis(seq, true, 1.0, 0.0)).result, AxisResult::Stale);
+        sim.rover.tick(TICK)?;
+        assert_eq!(sim.get_target_speed(), 0.0);
+    }
+    sim.drive(gap + 1..gap + 4)
+}
@@ -0,0 +1,127 @@
+// tests/timing.rs
+//
+// Timing through the pipeline on the rover clock: a replayed recording
+// arrives with its recorded gaps, scaled by the replay speed, and a
+// frame's capture time is carried through vision and the state machine to
+// the motor command it causes, whose audit entry has the photon-to-motor
+// latency.
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use scout_vision::clock::CLOCK;
+use scout_vision::config::{ConfigStore, VisionConfig};
+use scout_vision::health::HealthRegistry;
+use scout_vision::metrics::METRICS;
+use scout_vision::rover::Rover;
+use scout_vision::simulation::{self, FrameSource};
+use scout_vision::vision::VisionSystem;
+
+// Recorded gaps, in ms. Uneven, so frames sent at a fixed rate would show.
+const GAPS_MS: [u64; 4] = [200, 400, 100, 300];
+// Replay is on real time, so arrivals are allowed some scheduling slop.
+const SLOP_MS: f64 = 60.0;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("timing").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_recording(name: &str) -> PathBuf {
+    let mut t_ms = 0;
+    let mut lines = Vec::new();
+    for gap in std::iter::once(0).chain(GAPS_MS) {
+        t_ms += gap;
+        let payload = serde_json::json!({ "type": "frame", "tick": t_ms / 33, "detections": [], "timestamp": 0 });
+        lines.push(serde_json::json!({ "t_ms": t_ms, "payload": payload }).to_string());
+    }
+    let path = scratch(name).join("session-20260101-120000.jsonl");
+    std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+    path
+}
+
+// When each frame of the recording reached process_frame, after the first,
+// and the recorded time it carried.
+fn replay(speed: f32) -> Result<Vec<(Duration, u64)>> {
+    let path = write_recording(&format!("speed-{}", speed));
+    let health = HealthRegistry::new();
+    let mut vision = VisionSystem::new(&health, VisionConfig::default(), FrameSource::Recording { path, speed })?;
+    let mut arrivals = Vec::new();
+    let mut first = None;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while arrivals.len() < GAPS_MS.len() + 1 {
+        anyhow::ensure!(Instant::now() < deadline, "only {} frames replayed", arrivals.len());
+        vision.process_frame()?;
+        if vision.get_frame_capture_ms().is_some() {
+            let now = Instant::now();
+            let started = *first.get_or_insert(now);
+            let t_ms = vision.get_last_recorded().map(|r| r.t_ms).unwrap_or_default();
+            arrivals.push((now - started, t_ms));
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    Ok(arrivals)
+}
+
+#[test]
+fn replay_keeps_the_recorded_gaps_at_each_speed() -> Result<()> {
+    for speed in [1.0, 2.0, 4.0] {
+        let arrivals = replay(speed)?;
+        let mut expected_ms = 0.0;
+        for (i, ((arrived, t_ms), gap)) in arrivals.iter().skip(1).zip(GAPS_MS).enumerate() {
+            expected_ms += gap as f64 / speed as f64;
+            assert_eq!(*t_ms, GAPS_MS[..=i].iter().sum::<u64>());
+            let arrived_ms = arrived.as_secs_f64() * 1000.0;
+            assert!(
+                (arrived_ms - expected_ms).abs() <= SLOP_MS,
+                "frame {} at {:.0} ms, expected {:.0} ms at {}x", i + 1, arrived_ms, expected_ms, speed
+            );
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn a_frames_capture_time_reaches_the_motor_audit_log() -> Result<()> {
+    let config = ConfigStore::load(std::env::temp_dir().join("scout-timing-unused.toml"))?;
+    config.update(&serde_json::json!({
+        "selftest": { "enabled": false },
+        "control": { "start_autonomous": true },
+    })).map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+    let rover = Rover::new(Arc::new(config), FrameSource::Manual)?;
+    rover.start_subsystems();
+    while rover.startup.is_initializing() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    rover.selftest.start();
+    let frames = rover.vision.read().get_frame_sender();
+    for frame_id in 1..=5 {
+        frames.send(simulation::synthetic_frame(frame_id, Vec::new()));
+        rover.tick(Duration::from_millis(20))?;
+    }
+
+    // Sent, then held back before the tick that acts on it.
+    let frame = simulation::synthetic_frame(6, Vec::new());
+    let capture_ms = frame.capture_ms;
+    frames.send(frame);
+    std::thread::sleep(Duration::from_millis(40));
+    rover.tick(Duration::from_millis(20))?;
+
+    let audit = rover.motor_controller.read().get_audit_log();
+    let entry = audit.iter().rev().find(|entry| entry.capture_ms.is_some()).expect("no command credited to a frame");
+    assert_eq!(entry.capture_ms, Some(capture_ms));
+    let latency_ms = entry.latency_ms.unwrap();
+    assert!(latency_ms >= 40, "{} ms from capture to motor", latency_ms);
+    assert_eq!(latency_ms, entry.t_ms - capture_ms);
+    assert!(entry.t_ms <= CLOCK.now_ms());
+    assert_eq!(METRICS.last_e2e_latency_ms.get(), latency_ms as f64);
+
+    // A tick with no new frame credits its command to none.
+    rover.tick(Duration::from_millis(20))?;
+    let audit = rover.motor_controller.read().get_audit_log();
+    assert_eq!(audit.last().unwrap().capture_ms, None);
+    Ok(())
+}
@@ -0,0 +1,173 @@
+// tests/trajectory.rs
+//
+// The trajectory rollout against worked camera geometry: floor points
+// projected through a pinhole camera of known focal length, height and
+// horizon land on the pixels the math says, straight and turning rollouts
+// lie on their line or arc whichever way the rover faces, and the first
+// point to come near a remembered obstacle is flagged.
+use std::time::Duration;
+
+use scout_vision::config::PlannerConfig;
+use scout_vision::health::HealthRegistry;
+use scout_vision::motor_control::MotorStatus;
+use scout_vision::pathfinding::{PathPlanner, PathPoint};
+use scout_vision::vision::{CameraModel, Detection, NavigationAction};
+
+// 500 px focal length, the horizon at row 240 of a 640 px wide frame, the
+// lens 0.5 m up: a floor point d ahead is 250 / d rows below the horizon.
+const CAMERA: CameraModel = CameraModel { focal_length_px: 500.0, center_x_px: 320.0, horizon_row_px: 240.0, height_m: 0.5 };
+
+fn assert_near(actual: (f32, f32), expected: (f32, f32)) {
+    let close = (actual.0 - expected.0).abs() < 1e-3 && (actual.1 - expected.1).abs() < 1e-3;
+    assert!(close, "{:?} is not {:?}", actual, expected);
+}
+
+fn motors(speed: f32, heading: f32) -> MotorStatus {
+    MotorStatus { speed, target_speed: speed, heading, enabled: true, speed_cap: None, interlock: None, heading_hold: Default::default() }
+}
+
+// A planner that has just driven one tick at `speed`, turning `rate_deg_s`
+// clockwise, to end up facing `heading`.
+fn moving(speed: f32, rate_deg_s: f32, heading: f32) -> PathPlanner {
+    let mut planner = PathPlanner::new(&HealthRegistry::new(), PlannerConfig::default());
+    let tick = Duration::from_millis(100);
+    planner.update_pose(&motors(0.0, heading - rate_deg_s * tick.as_secs_f32()), Duration::ZERO);
+    planner.update_pose(&motors(speed, heading), tick);
+    planner
+}
+
+fn distance(a: &PathPoint, b: &PathPoint) -> f32 {
+    (a.x - b.x).hypot(a.y - b.y)
+}
+
+// Something straight ahead, as vision reports it.
+fn seen_at(distance_m: f32) -> Detection {
+    Detection {
+        class_name: "box".into(),
+        confidence: 0.9,
+        raw_confidence: 0.9,
+        bbox: (300, 200, 40, 40),
+        distance_estimate: distance_m,
+        action: NavigationAction::Continue,
+        capture_ms: 0,
+        track_id: None,
+        mask: None,
+        self_occlusion: false,
+        below_threshold: false,
+        ground_position: None,
+    }
+}
+
+#[test]
+fn floor_points_project_onto_the_worked_pixels() {
+    assert_near(CAMERA.project_floor(2.0, 0.0).unwrap(), (320.0, 365.0));
+    assert_near(CAMERA.project_floor(1.0, 0.0).unwrap(), (320.0, 490.0));
+    assert_near(CAMERA.project_floor(2.0, 1.0).unwrap(), (570.0, 365.0));
+    assert_near(CAMERA.project_floor(5.0, -1.0).unwrap(), (220.0, 290.0));
+    // Far off, a point closes on the horizon.
+    let (_, row) = CAMERA.project_floor(1000.0, 0.0).unwrap();
+    assert!(row > 240.0 && row < 241.0);
+    // Under or behind the lens there is no pixel.
+    assert!(CAMERA.project_floor(0.0, 0.0).is_none());
+    assert!(CAMERA.project_floor(-1.0, 0.5).is_none());
+
+    assert!((CAMERA.bearing_deg(820.0) - 45.0).abs() < 1e-3);
+    assert!((CAMERA.bearing_deg(320.0)).abs() < 1e-6);
+    assert!((CAMERA.bearing_deg(-180.0) + 45.0).abs() < 1e-3);
+}
+
+#[test]
+fn a_projected_row_reads_back_as_its_floor_distance() {
+    for forward_m in [0.3, 0.8, 1.0, 2.5, 7.0] {
+        let (_, row) = CAMERA.project_floor(forward_m, 0.4).unwrap();
+        let back = CAMERA.floor_distance_m(row).unwrap();
+        assert!((back - forward_m).abs() < 1e-3, "{} m came back as {} m", forward_m, back);
+    }
+    // At or above the horizon there's no floor.
+    assert!(CAMERA.floor_distance_m(240.0).is_none());
+    assert!(CAMERA.floor_distance_m(100.0).is_none());
+}
+
+#[test]
+fn a_straight_rollout_is_a_line_down_the_middle_of_the_image() {
+    // The same whichever way the rover faces: the image is from its lens.
+    for heading in [0.0, 90.0, -135.0] {
+        let planner = moving(0.5, 0.0, heading);
+        let start = planner.get_pose();
+        let trajectory = planner.get_trajectory(&CAMERA);
+        assert_eq!((trajectory.speed_m_s, trajectory.horizon_s), (0.5, 2.0));
+        assert_eq!(trajectory.world.len(), 21);
+        assert_eq!(trajectory.image.len(), 21);
+        let heading_rad = heading.to_radians();
+        for (i, (point, pixel)) in trajectory.world.iter().zip(&trajectory.image).enumerate() {
+            let d = 0.5 * 0.1 * i as f32;
+            assert_near((point.x, point.y), (start.x + d * heading_rad.sin(), start.y + d * heading_rad.cos()));
+            match CAMERA.project_floor(d, 0.0) {
+                Some(expected) => assert_near(pixel.expect("in front of the lens"), expected),
+                None => assert!(pixel.is_none(), "the rover's own spot has no pixel"),
+            }
+        }
+        assert!(trajectory.image[0].is_none());
+        assert_near(trajectory.image[20].unwrap(), (320.0, 240.0 + 250.0));
+        assert!(trajectory.hit.is_none());
+    }
+}
+
+#[test]
+fn a_turning_rollout_follows_its_arc_and_bends_toward_the_turn() {
+    // 0.5 m/s at 45 °/s clockwise: a circle of radius 0.5 / (π/4) m.
+    let planner = moving(0.5, 45.0, 0.0);
+    let trajectory = planner.get_trajectory(&CAMERA);
+    assert!((trajectory.turn_rate_deg_s - 45.0).abs() < 1e-3);
+    let radius = 0.5 / 45f32.to_radians();
+    let start = planner.get_pose();
+    let center = PathPoint { x: start.x + radius, y: start.y };
+    for (i, point) in trajectory.world.iter().enumerate() {
+        assert!((distance(point, &center) - radius).abs() < 1e-3, "point {} is off the arc", i);
+    }
+    // After 2 s the rover has turned 90°, to face along x.
+    let end = trajectory.world.last().unwrap();
+    assert_near((end.x, end.y), (start.x + radius, start.y + radius));
+
+    // Turning right bends the line right on the image, more so further out.
+    let columns: Vec<f32> = trajectory.image.iter().flatten().map(|&(x, _)| x).take(8).collect();
+    assert!(columns.windows(2).all(|w| w[1] > w[0]), "{:?}", columns);
+    assert!(columns.iter().all(|&x| x > 320.0));
+
+    // And the same to the left.
+    let left = moving(0.5, -45.0, 0.0).get_trajectory(&CAMERA);
+    assert!(left.image.iter().flatten().take(8).all(|&(x, _)| x < 320.0));
+}
+
+#[test]
+fn standing_still_has_no_rollout() {
+    let trajectory = moving(0.0, 0.0, 30.0).get_trajectory(&CAMERA);
+    assert_eq!(trajectory.world.len(), 1);
+    // Turning on the spot goes nowhere either.
+    let trajectory = moving(0.0, 60.0, 30.0).get_trajectory(&CAMERA);
+    assert_eq!(trajectory.world.len(), 1);
+    assert!(trajectory.hit.is_none());
+}
+
+#[test]
+fn the_first_point_near_an_obstacle_is_flagged() {
+    let mut planner = moving(0.5, 0.0, 0.0);
+    let start = planner.get_pose();
+    // A box dead ahead, 0.62 m off.
+    planner.update_obstacles(&[seen_at(0.62)]);
+    let trajectory = planner.get_trajectory(&CAMERA);
+    let hit = trajectory.hit.expect("drove through the box");
+    assert_eq!(hit.class_name, "box");
+    // The first step within 0.15 m of it, at 0.05 m a step.
+    assert_eq!(hit.index, 10);
+    assert!((hit.t_s - 1.0).abs() < 1e-3);
+    assert_near((hit.point.x, hit.point.y), (start.x, start.y + 0.5));
+    assert_eq!(trajectory.world[hit.index].y, hit.point.y);
+
+    // Turned well away from it, the path is clear.
+    let mut planner = moving(0.5, 0.0, 0.0);
+    planner.update_obstacles(&[seen_at(0.62)]);
+    planner.update_pose(&motors(0.5, 90.0), Duration::from_millis(100));
+    planner.update_pose(&motors(0.5, 90.0), Duration::from_millis(100));
+    assert!(planner.get_trajectory(&CAMERA).hit.is_none());
+}
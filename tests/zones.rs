@@ -0,0 +1,308 @@
+// tests/zones.rs
+//
+// Zones: point-in-polygon on concave, self-crossing and degenerate
+// polygons; the rover and tracked detections crossing zone edges, each
+// crossing published once as a "zones" event; the planner holding the
+// rover to slow, stop and forbid_entry policies; and zones edited through
+// the REST API being saved to the config file.
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use warp::http::StatusCode;
+
+use scout_vision::config::{ConfigStore, PlannerConfig, Zone, ZonePolicy};
+use scout_vision::events::EVENTS;
+use scout_vision::health::HealthRegistry;
+use scout_vision::motor_control::MotorStatus;
+use scout_vision::pathfinding::{NavigationCommand, PathPlanner};
+use scout_vision::rover::Rover;
+use scout_vision::simulation::FrameSource;
+use scout_vision::vision::{Detection, NavigationAction};
+use scout_vision::web::WebServer;
+use scout_vision::zones::{self, Occupant, ZoneTracker};
+
+const TICK: Duration = Duration::from_millis(100);
+
+fn square(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<(f32, f32)> {
+    vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+}
+
+// A polygon, points inside it and points outside.
+type Case = (Vec<(f32, f32)>, &'static [(f32, f32)], &'static [(f32, f32)]);
+
+fn zone(name: &str, policy: ZonePolicy, polygon: Vec<(f32, f32)>) -> Zone {
+    Zone { name: name.to_string(), polygon, policy, direction_deg: None }
+}
+
+// The "zones" events for `zone`, oldest first, as (object, entered).
+fn crossings(zone: &str) -> Vec<(String, bool)> {
+    EVENTS.recent()
+        .into_iter()
+        .filter(|e| e.source == "zones" && e.data["zone"] == zone)
+        .map(|e| (e.data["object"].as_str().unwrap_or_default().to_string(), e.data["entered"] == true))
+        .collect()
+}
+
+#[test]
+fn points_inside_and_outside_plain_and_concave_polygons() {
+    let cases: &[Case] = &[
+        (square(0.0, 0.0, 2.0, 2.0), &[(1.0, 1.0), (0.1, 1.9)], &[(-0.1, 1.0), (2.1, 1.0), (1.0, 2.5), (1.0, -3.0)]),
+        // The same square wound the other way.
+        (vec![(0.0, 0.0), (0.0, 2.0), (2.0, 2.0), (2.0, 0.0)], &[(1.0, 1.0)], &[(3.0, 1.0)]),
+        (vec![(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)], &[(1.0, 1.0), (0.5, 3.0)], &[(3.0, 3.0), (2.1, 2.1)]),
+        // An L: the notch is outside.
+        (vec![(0.0, 0.0), (3.0, 0.0), (3.0, 1.0), (1.0, 1.0), (1.0, 3.0), (0.0, 3.0)], &[(2.5, 0.5), (0.5, 2.5), (0.5, 0.5)], &[(2.0, 2.0), (2.9, 1.1)]),
+    ];
+    for (polygon, inside, outside) in cases {
+        for &(x, y) in inside.iter() {
+            assert!(zones::contains(polygon, x, y), "({}, {}) outside {:?}", x, y, polygon);
+        }
+        for &(x, y) in outside.iter() {
+            assert!(!zones::contains(polygon, x, y), "({}, {}) inside {:?}", x, y, polygon);
+        }
+    }
+}
+
+#[test]
+fn degenerate_polygons_contain_nothing() {
+    let degenerate: [Vec<(f32, f32)>; 5] = [
+        Vec::new(),
+        vec![(0.0, 0.0)],
+        vec![(0.0, 0.0), (2.0, 2.0)],
+        // Collinear, with and without a repeated corner.
+        vec![(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)],
+        vec![(0.0, 0.0), (0.0, 0.0), (0.0, 0.0), (0.0, 0.0)],
+    ];
+    for polygon in &degenerate {
+        for (x, y) in [(0.0, 0.0), (1.0, 1.0), (0.5, 0.5), (1.0, 0.0)] {
+            assert!(!zones::contains(polygon, x, y), "({}, {}) inside {:?}", x, y, polygon);
+        }
+    }
+    // A bow tie crosses itself; by the even-odd rule each lobe is inside
+    // and the crossing point's surroundings above and below aren't.
+    let bow_tie = vec![(0.0, 0.0), (2.0, 2.0), (2.0, 0.0), (0.0, 2.0)];
+    assert!(zones::contains(&bow_tie, 0.2, 1.0));
+    assert!(zones::contains(&bow_tie, 1.8, 1.0));
+    assert!(!zones::contains(&bow_tie, 1.0, 0.2));
+    assert!(!zones::contains(&bow_tie, 1.0, 1.8));
+    // A repeated corner in an otherwise good polygon changes nothing.
+    let repeated = vec![(0.0, 0.0), (2.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)];
+    assert!(zones::contains(&repeated, 1.0, 1.0) && !zones::contains(&repeated, 3.0, 1.0));
+}
+
+#[test]
+fn each_crossing_is_one_event_in_order() {
+    let zones = vec![
+        zone("tracker_yard", ZonePolicy::None, square(0.0, 0.0, 2.0, 2.0)),
+        zone("tracker_line", ZonePolicy::None, vec![(3.0, 0.0), (3.0, 2.0), (3.0, 4.0)]),
+    ];
+    let mut tracker = ZoneTracker::default();
+    // Outside, in, staying in, out along the edge's far side, in again.
+    for (x, y) in [(-1.0, 1.0), (0.5, 1.0), (1.5, 1.0), (2.5, 1.0), (1.0, 1.9), (3.0, 1.0)] {
+        tracker.update(&zones, Occupant::Track(41), "person", x, y);
+    }
+    assert_eq!(crossings("tracker_yard"), [("person#41".to_string(), true), ("person#41".to_string(), false), ("person#41".to_string(), true), ("person#41".to_string(), false)]);
+    // The rover is counted on its own, under its own name.
+    tracker.update(&zones, Occupant::Rover, "rover", 1.0, 1.0);
+    let status = tracker.get_status(&zones);
+    assert_eq!(status[0].occupants, ["rover"]);
+    assert!(status[1].occupants.is_empty());
+    assert!(crossings("tracker_line").is_empty(), "a line is no zone");
+    assert!(tracker.is_inside(Occupant::Rover, ZonePolicy::None, &zones));
+    assert!(!tracker.is_inside(Occupant::Track(41), ZonePolicy::None, &zones));
+}
+
+#[test]
+fn a_removed_zone_is_forgotten_without_an_event() {
+    let zones = vec![zone("removed_yard", ZonePolicy::Slow, square(0.0, 0.0, 2.0, 2.0))];
+    let mut tracker = ZoneTracker::default();
+    tracker.update(&zones, Occupant::Rover, "rover", 1.0, 1.0);
+    tracker.retain_zones(&[]);
+    tracker.update(&[], Occupant::Rover, "rover", 1.0, 1.0);
+    assert_eq!(crossings("removed_yard"), [("rover".to_string(), true)]);
+    assert!(!tracker.is_inside(Occupant::Rover, ZonePolicy::Slow, &zones));
+}
+
+// The motor loop faked as in tests/passages.rs: each command is carried
+// out in full before the next tick.
+struct Drive {
+    planner: PathPlanner,
+    speed: f32,
+    heading: f32,
+}
+
+impl Drive {
+    fn new(zones: Vec<Zone>) -> Self {
+        let config = PlannerConfig { zones, cruise_speed: 0.3, envelope_unknown_curve: Vec::new(), ..PlannerConfig::default() };
+        let mut drive = Self { planner: PathPlanner::new(&HealthRegistry::new(), config), speed: 0.0, heading: 0.0 };
+        drive.planner.update_pose(&drive.motors(), Duration::ZERO);
+        drive
+    }
+
+    fn motors(&self) -> MotorStatus {
+        MotorStatus { speed: self.speed, target_speed: self.speed, heading: self.heading, enabled: true, speed_cap: None, interlock: None, heading_hold: Default::default() }
+    }
+
+    fn step(&mut self) -> NavigationCommand {
+        self.planner.update_pose(&self.motors(), TICK);
+        let command = self.planner.get_navigation_command();
+        self.speed = 0.0;
+        match command {
+            NavigationCommand::Forward(speed) => self.speed = speed,
+            NavigationCommand::Backward(speed) => self.speed = -speed,
+            NavigationCommand::TurnRight(deg) => self.heading += deg.min(9.0),
+            NavigationCommand::TurnLeft(deg) => self.heading -= deg.min(9.0),
+            NavigationCommand::Stop => {}
+        }
+        command
+    }
+
+    fn y(&self) -> f32 {
+        self.planner.get_pose().y
+    }
+}
+
+#[test]
+fn a_slow_zone_caps_the_speed_only_while_the_rover_is_inside() {
+    let slow = PlannerConfig::default().zone_slow_speed;
+    let mut drive = Drive::new(vec![zone("policy_slow", ZonePolicy::Slow, square(-1.0, 1.0, 1.0, 2.0))]);
+    let (mut capped, mut left) = (false, false);
+    for _ in 0..200 {
+        // The command is for the pose the step moved to.
+        let command = drive.step();
+        let y = drive.y();
+        let NavigationCommand::Forward(speed) = command else { panic!("{:?} at y {:.2}", command, y) };
+        if y > 1.0 && y < 2.0 {
+            assert!(speed <= slow, "{} m/s in the slow zone", speed);
+            capped = true;
+        }
+        if y > 2.2 {
+            assert!(speed > slow, "still slow at y {:.2}", y);
+            left = true;
+            break;
+        }
+    }
+    assert!(capped && left);
+    assert_eq!(crossings("policy_slow"), [("rover".to_string(), true), ("rover".to_string(), false)]);
+}
+
+#[test]
+fn a_stop_zone_holds_the_rover_once_it_is_in() {
+    let mut drive = Drive::new(vec![zone("policy_stop", ZonePolicy::Stop, square(-1.0, 0.5, 1.0, 3.0))]);
+    let mut stopped_at = None;
+    for _ in 0..200 {
+        if matches!(drive.step(), NavigationCommand::Stop) {
+            stopped_at = Some(drive.y());
+            break;
+        }
+    }
+    let stopped_at = stopped_at.expect("never stopped");
+    assert!(stopped_at > 0.5 && stopped_at < 1.0, "stopped at y {:.2}", stopped_at);
+    for _ in 0..20 {
+        assert!(!matches!(drive.step(), NavigationCommand::Forward(_)));
+    }
+    assert_eq!(drive.y(), stopped_at);
+}
+
+#[test]
+fn a_forbidden_zone_is_never_entered() {
+    let forbidden = square(-1.0, 2.0, 1.0, 3.0);
+    let mut drive = Drive::new(vec![zone("policy_forbid", ZonePolicy::ForbidEntry, forbidden.clone())]);
+    let mut avoided = false;
+    for _ in 0..300 {
+        avoided |= matches!(drive.step(), NavigationCommand::TurnLeft(_) | NavigationCommand::TurnRight(_));
+        let pose = drive.planner.get_pose();
+        assert!(!zones::contains(&forbidden, pose.x, pose.y), "entered at ({:.2}, {:.2})", pose.x, pose.y);
+    }
+    assert!(avoided, "never turned away");
+    assert!(crossings("policy_forbid").is_empty());
+}
+
+#[test]
+fn tracked_detections_crossing_a_zone_are_reported() {
+    let mut drive = Drive::new(vec![zone("policy_track", ZonePolicy::None, square(-1.0, 2.0, 1.0, 4.0))]);
+    // Straight ahead, so on the map at (0, distance).
+    let seen = |distance: f32| Detection {
+        class_name: "dog".into(),
+        confidence: 0.9,
+        raw_confidence: 0.9,
+        bbox: (300, 100, 40, 100),
+        distance_estimate: distance,
+        action: NavigationAction::Continue,
+        capture_ms: 0,
+        track_id: Some(5),
+        mask: None,
+        self_occlusion: false,
+        below_threshold: false,
+        ground_position: None,
+    };
+    for distance in [1.5, 2.5, 3.5, 4.5] {
+        drive.planner.update_obstacles(&[seen(distance)]);
+    }
+    // Untracked detections aren't followed.
+    drive.planner.update_obstacles(&[Detection { track_id: None, ..seen(3.0) }]);
+    assert_eq!(crossings("policy_track"), [("dog#5".to_string(), true), ("dog#5".to_string(), false)]);
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("zones").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn zones_edited_over_the_api_are_saved_and_take_effect() -> Result<()> {
+    let path = scratch("api").join("scout.toml");
+    let config = ConfigStore::load(&path)?;
+    config.update(&serde_json::json!({ "selftest": { "enabled": false } })).map_err(|errors| anyhow::anyhow!("{:?}", errors))?;
+    let rover = Rover::new(Arc::new(config), FrameSource::Manual)?;
+    let web = Arc::new(WebServer::new(
+        rover.vision.clone(),
+        rover.path_planner.clone(),
+        rover.motor_controller.clone(),
+        rover.state.clone(),
+        rover.scheduler.clone(),
+        rover.health.clone(),
+        rover.config.clone(),
+    ));
+    let routes = web.routes();
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let put = |name: &str, body: serde_json::Value| warp::test::request().method("PUT").path(&format!("/api/zones/{}", name)).json(&body).reply(&routes);
+        let reply = put("driveway", serde_json::json!({ "polygon": [[0, 0], [4, 0], [4, 6], [0, 6]], "policy": "slow" })).await;
+        assert_eq!(reply.status(), StatusCode::OK, "{:?}", reply.body());
+        let reply = put("stairs", serde_json::json!({ "polygon": [[-3, 1], [-2, 1], [-2, 2]], "policy": "forbid_entry" })).await;
+        assert_eq!(reply.status(), StatusCode::OK);
+        // Replaced in place, not added twice.
+        let reply = put("driveway", serde_json::json!({ "polygon": [[0, 0], [5, 0], [5, 6], [0, 6]], "policy": "stop" })).await;
+        assert_eq!(reply.status(), StatusCode::OK);
+
+        let listed = warp::test::request().path("/api/zones").reply(&routes).await;
+        let listed: serde_json::Value = serde_json::from_slice(listed.body())?;
+        assert_eq!(listed.as_array().map(Vec::len), Some(2));
+        assert_eq!(listed[0]["name"], "driveway");
+        assert_eq!(listed[0]["policy"], "stop");
+        assert_eq!(listed[0]["polygon"][1], serde_json::json!([5.0, 0.0]));
+
+        // Too few corners, and fields the API doesn't know.
+        let reply = put("bad", serde_json::json!({ "polygon": [[0, 0], [1, 1]] })).await;
+        assert_eq!(reply.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let reply = put("bad", serde_json::json!({ "polygon": [[0, 0], [1, 0], [1, 1]], "colour": "red" })).await;
+        assert!(reply.status().is_client_error());
+
+        let delete = || warp::test::request().method("DELETE").path("/api/zones/stairs").reply(&routes);
+        assert_eq!(delete().await.status(), StatusCode::OK);
+        assert_eq!(delete().await.status(), StatusCode::NOT_FOUND);
+        anyhow::Ok(())
+    })?;
+
+    // The planner has the edit, and it is in the file for the next start.
+    let zones = rover.path_planner.read().get_zones();
+    assert_eq!(zones.iter().map(|z| z.name.as_str()).collect::<Vec<_>>(), ["driveway"]);
+    let reloaded = ConfigStore::load(&path)?.get().planner.zones;
+    assert_eq!(reloaded.len(), 1);
+    assert_eq!((reloaded[0].name.as_str(), reloaded[0].policy), ("driveway", ZonePolicy::Stop));
+    assert_eq!(reloaded[0].polygon, square(0.0, 0.0, 5.0, 6.0));
+    Ok(())
+}